@@ -0,0 +1,75 @@
+//! Embeds the identity of the build in the binary, see `src/build_info.rs`.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+/// Malachite package whose version is reported
+const MALACHITE_PACKAGE: &str = "informalsystems-malachitebft-app-channel";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=EMERALD_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+    println!("cargo:rerun-if-changed=../Cargo.lock");
+
+    // The commit can be given explicitly when building outside of the repository
+    let git_commit = env::var("EMERALD_GIT_COMMIT")
+        .ok()
+        .or_else(|| command_output("git", &["rev-parse", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    let malachite_version = fs::read_to_string("../Cargo.lock")
+        .ok()
+        .and_then(|lock| locked_version(&lock, MALACHITE_PACKAGE))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=EMERALD_GIT_COMMIT={git_commit}");
+    println!("cargo:rustc-env=EMERALD_RUSTC_VERSION={rustc_version}");
+    println!("cargo:rustc-env=EMERALD_FEATURES={}", features.join(","));
+    println!("cargo:rustc-env=EMERALD_MALACHITE_VERSION={malachite_version}");
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+    Some(output.trim().to_string())
+}
+
+/// Version of `package` in the lock file, with the git revision it is built from if any
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let entry = lock
+        .split("[[package]]")
+        .find(|entry| entry.contains(&format!("name = \"{package}\"")))?;
+
+    let field = |name: &str| {
+        entry.lines().find_map(|line| {
+            line.strip_prefix(name)
+                .and_then(|value| value.trim().strip_prefix("= "))
+                .map(|value| value.trim_matches('"').to_string())
+        })
+    };
+
+    let version = field("version")?;
+    match field("source").and_then(|source| source.split_once('#').map(|(_, rev)| rev.to_string()))
+    {
+        Some(rev) => Some(format!("{version} ({rev})")),
+        None => Some(version),
+    }
+}
@@ -0,0 +1,327 @@
+//! Admin API, used by operators to inspect and adjust a running node.
+//!
+//! Routes:
+//! - `GET /retry_config`: current retry configuration of the Engine API calls
+//! - `PUT /retry_config`: replace the retry configuration, takes effect on the next call
+//! - `GET /vote_stats`: votes seen for the recent heights, with the delay of each validator
+//! - `GET /version`: build of the node and version of its execution client
+//! - `GET /peer_filter`: allow and deny lists of the peers, as set by the emerald config
+//! - `GET /peers`: peers which streamed proposals to the node, with the heights of their proposals
+//! - `GET /status`: height, round and proposer, participation of the validators, sync status
+//!   and head of the execution client, as printed by `emerald status --node`
+//! - `GET /schedule?from=A&to=B&rounds=N`: proposers of the heights `A` to `B` at the rounds
+//!   below `N`, selected from the current validator set, as printed by `emerald schedule`
+//! - `GET /sync_serving`: heights requested by syncing peers, with the decided values served
+//!   and those rebuilt from the execution client, since the start and over the last minute
+//! - `GET /ready`: state of the execution client, with status 200 when it is ready and 503
+//!   when it is syncing or unreachable, for the readiness probes of orchestrators
+//! - `POST /store/compact`: compact the store into a new file while the node keeps running,
+//!   as done by `emerald store compact --online`
+//! - `GET /certificates?from=A&to=B`: certificates and block headers of the decided heights
+//!   `A` to `B` still in the store, backfilled by the nodes starting from a snapshot of their
+//!   execution client
+//! - `GET /config?height=N`: effective configuration of the node at height `N`, i.e. the one
+//!   recorded by the last startup at or below it, or the current one without a height
+//! - `GET /config/history`: startups of the node, with the height each one started from and
+//!   the hash of its configuration
+//!
+//! It is served over TLS when `admin_tls` is set, and requires the `Authorization: Bearer`
+//! token loaded from `admin_auth_token` when set.
+
+use core::net::SocketAddr;
+use std::io;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use malachitebft_eth_cli::cmd::schedule::{ProposerSchedule, ScheduleQuery};
+use malachitebft_eth_cli::cmd::status::NodeStatus;
+use malachitebft_eth_cli::cmd::store::CompactionReport;
+use malachitebft_eth_cli::config::PeerFilterConfig;
+use malachitebft_eth_cli::http::{self, EndpointSecurity};
+use malachitebft_eth_types::{Height, RetryConfig, SharedRetryConfig};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::build_info::{BuildInfo, SharedBuildInfo};
+use crate::config_snapshot::{self, ConfigSnapshot, ConfigSnapshotSummary};
+use crate::el_health::{ElState, ElStatus, SharedElHealth};
+use crate::el_snapshot::{CertificateEntry, BACKFILL_BATCH};
+use crate::node_status::SharedNodeStatus;
+use crate::peer_filter::SharedPeerFilter;
+use crate::peer_registry::{PeerSummary, SharedPeerRegistry};
+use crate::store::{Store, StoreError};
+use crate::sync_stats::{ServingSummary, SharedSyncStats};
+use crate::vote_stats::{RoundSummary, SharedVoteStats};
+
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "admin", skip_all)]
+pub async fn serve(
+    listen_addr: SocketAddr,
+    retry_config: SharedRetryConfig,
+    vote_stats: SharedVoteStats,
+    build_info: SharedBuildInfo,
+    peer_filter: SharedPeerFilter,
+    peer_registry: SharedPeerRegistry,
+    node_status: SharedNodeStatus,
+    sync_stats: SharedSyncStats,
+    el_health: SharedElHealth,
+    store: Store,
+    security: EndpointSecurity,
+) {
+    if let Err(e) = inner(
+        listen_addr,
+        retry_config,
+        vote_stats,
+        build_info,
+        peer_filter,
+        peer_registry,
+        node_status,
+        sync_stats,
+        el_health,
+        store,
+        security,
+    )
+    .await
+    {
+        error!("Admin server failed: {e}");
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn inner(
+    listen_addr: SocketAddr,
+    retry_config: SharedRetryConfig,
+    vote_stats: SharedVoteStats,
+    build_info: SharedBuildInfo,
+    peer_filter: SharedPeerFilter,
+    peer_registry: SharedPeerRegistry,
+    node_status: SharedNodeStatus,
+    sync_stats: SharedSyncStats,
+    el_health: SharedElHealth,
+    store: Store,
+    security: EndpointSecurity,
+) -> io::Result<()> {
+    let app = Router::new()
+        .route("/retry_config", get(get_retry_config).put(put_retry_config))
+        .with_state(retry_config)
+        .merge(
+            Router::new()
+                .route("/vote_stats", get(get_vote_stats))
+                .with_state(vote_stats),
+        )
+        .merge(
+            Router::new()
+                .route("/version", get(get_version))
+                .with_state(build_info),
+        )
+        .merge(
+            Router::new()
+                .route("/peer_filter", get(get_peer_filter))
+                .with_state(peer_filter),
+        )
+        .merge(
+            Router::new()
+                .route("/peers", get(get_peers))
+                .with_state(peer_registry),
+        )
+        .merge(
+            Router::new()
+                .route("/status", get(get_status))
+                .route("/schedule", get(get_schedule))
+                .with_state(node_status),
+        )
+        .merge(
+            Router::new()
+                .route("/sync_serving", get(get_sync_serving))
+                .with_state(sync_stats),
+        )
+        .merge(
+            Router::new()
+                .route("/ready", get(get_ready))
+                .with_state(el_health),
+        )
+        .merge(
+            Router::new()
+                .route("/store/compact", post(compact_store))
+                .route("/certificates", get(get_certificates))
+                .route("/config", get(get_config))
+                .route("/config/history", get(get_config_history))
+                .with_state(store),
+        );
+
+    info!(
+        address = %listen_addr,
+        tls = security.is_tls(),
+        auth = security.is_authenticated(),
+        "Serving admin API"
+    );
+    http::serve(listen_addr, app, security).await
+}
+
+async fn get_retry_config(State(retry_config): State<SharedRetryConfig>) -> Json<RetryConfig> {
+    Json(retry_config.get())
+}
+
+async fn put_retry_config(
+    State(retry_config): State<SharedRetryConfig>,
+    Json(config): Json<RetryConfig>,
+) -> Result<Json<RetryConfig>, (StatusCode, String)> {
+    retry_config
+        .set(config)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let config = retry_config.get();
+    info!(?config, "Updated retry configuration");
+
+    Ok(Json(config))
+}
+
+async fn get_vote_stats(State(vote_stats): State<SharedVoteStats>) -> Json<Vec<RoundSummary>> {
+    Json(vote_stats.summary())
+}
+
+async fn get_version(State(build_info): State<SharedBuildInfo>) -> Json<BuildInfo> {
+    Json(build_info.get())
+}
+
+async fn get_peer_filter(State(peer_filter): State<SharedPeerFilter>) -> Json<PeerFilterConfig> {
+    Json(peer_filter.get())
+}
+
+async fn get_peers(State(peer_registry): State<SharedPeerRegistry>) -> Json<Vec<PeerSummary>> {
+    Json(peer_registry.summary())
+}
+
+async fn get_status(State(node_status): State<SharedNodeStatus>) -> Json<NodeStatus> {
+    Json(node_status.get())
+}
+
+async fn get_schedule(
+    State(node_status): State<SharedNodeStatus>,
+    Query(query): Query<ScheduleQuery>,
+) -> Result<Json<ProposerSchedule>, (StatusCode, String)> {
+    query.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    node_status.proposer_schedule(&query).map(Json).ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The validator set is not known yet".to_string(),
+    ))
+}
+
+async fn get_sync_serving(State(sync_stats): State<SharedSyncStats>) -> Json<ServingSummary> {
+    Json(sync_stats.summary())
+}
+
+async fn get_ready(State(el_health): State<SharedElHealth>) -> (StatusCode, Json<ElStatus>) {
+    let status = el_health.status();
+    let code = match status.state {
+        ElState::Ready => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (code, Json(status))
+}
+
+async fn compact_store(
+    State(store): State<Store>,
+) -> Result<Json<CompactionReport>, (StatusCode, String)> {
+    info!("Compacting the store");
+
+    let report = store.compact().await.map_err(|e| {
+        let status = match e {
+            StoreError::Compaction(_) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        error!(%e, "Failed to compact the store");
+        (status, e.to_string())
+    })?;
+
+    info!(
+        entries = %report.entries,
+        replayed_writes = %report.replayed_writes,
+        size_before = %report.size_before,
+        size_after = %report.size_after,
+        "Compacted the store"
+    );
+
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+struct CertificatesQuery {
+    from: u64,
+    to: u64,
+}
+
+async fn get_certificates(
+    State(store): State<Store>,
+    Query(query): Query<CertificatesQuery>,
+) -> Result<Json<Vec<CertificateEntry>>, (StatusCode, String)> {
+    if query.from > query.to || query.to - query.from >= BACKFILL_BATCH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "from must not be above to, and at most {BACKFILL_BATCH} heights can be requested"
+            ),
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for height in query.from..=query.to {
+        let stored = store
+            .get_certificate_and_header(Height::new(height))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if let Some((certificate, header)) = stored {
+            let validator_set = store
+                .get_validator_set(Height::new(height))
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let entry = CertificateEntry::new(&certificate, header, validator_set)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            entries.push(entry);
+        }
+    }
+
+    Ok(Json(entries))
+}
+
+#[derive(Deserialize)]
+struct ConfigQuery {
+    height: Option<u64>,
+}
+
+async fn get_config(
+    State(store): State<Store>,
+    Query(query): Query<ConfigQuery>,
+) -> Result<Json<ConfigSnapshot>, (StatusCode, String)> {
+    let snapshots = store
+        .get_config_snapshots()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let snapshot = match query.height {
+        Some(height) => config_snapshot::effective_at(&snapshots, Height::new(height)),
+        None => snapshots.last(),
+    };
+
+    snapshot.cloned().map(Json).ok_or((
+        StatusCode::NOT_FOUND,
+        "No configuration recorded for this height".to_string(),
+    ))
+}
+
+async fn get_config_history(
+    State(store): State<Store>,
+) -> Result<Json<Vec<ConfigSnapshotSummary>>, (StatusCode, String)> {
+    let snapshots = store
+        .get_config_snapshots()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(
+        snapshots.iter().map(ConfigSnapshot::summary).collect(),
+    ))
+}
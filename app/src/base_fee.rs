@@ -0,0 +1,141 @@
+//! Enforcement of the base fee floor set in the genesis, see [`BaseFeeFloor`].
+//!
+//! The Engine API does not let the proposer choose the base fee of the payloads built
+//! by the execution client, which derives it from the parent block (EIP-1559), and an
+//! idle chain lets it fall below any floor. The floor is therefore enforced on the
+//! transactions of the payloads: when the base fee of a payload is below the floor, its
+//! transactions must accept to pay the floor, i.e. their maximum fee per gas must not be
+//! below it. Payloads without such transactions, e.g. empty ones, are always accepted,
+//! so that the chain keeps producing blocks.
+//!
+//! The execution client applies the same rule to its transaction pool when started with
+//! `--txpool.minimal-protocol-fee` set to the floor, so that the payloads it builds meet
+//! it. Otherwise the proposer proposes a payload without the transactions of the pool.
+
+use core::time::Duration;
+
+use alloy_consensus::Transaction;
+use alloy_primitives::{B256, U256};
+use color_eyre::eyre;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::{Address, BaseFeeFloor, Block, BlockHash};
+use serde_json::json;
+use tracing::info;
+
+const GET_STORAGE_AT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Returns the minimum base fee per gas in force after the given block.
+pub async fn read_min_base_fee_per_gas(
+    engine: &Engine,
+    floor: &BaseFeeFloor,
+    block_hash: &BlockHash,
+) -> eyre::Result<u64> {
+    let Some(contract) = floor.override_contract else {
+        return Ok(floor.min_base_fee_per_gas);
+    };
+
+    let min_base_fee_per_gas = match read_override(engine, &contract, block_hash).await? {
+        Some(min_base_fee_per_gas) => {
+            info!(%contract, %min_base_fee_per_gas, "Base fee floor overridden on-chain");
+            min_base_fee_per_gas
+        }
+        None => floor.min_base_fee_per_gas,
+    };
+
+    Ok(min_base_fee_per_gas)
+}
+
+/// Returns the hashes of the transactions of the block which do not pay the floor: none if
+/// the base fee of the block is not below the floor, otherwise those whose maximum fee per
+/// gas is below it.
+pub fn txs_below_floor(block: &Block, min_base_fee_per_gas: u64) -> Vec<B256> {
+    let base_fee_per_gas = block.header.base_fee_per_gas.unwrap_or_default();
+    if base_fee_per_gas >= min_base_fee_per_gas {
+        return Vec::new();
+    }
+
+    block
+        .body
+        .transactions
+        .iter()
+        .filter(|tx| tx.max_fee_per_gas() < u128::from(min_base_fee_per_gas))
+        .map(|tx| *tx.tx_hash())
+        .collect()
+}
+
+/// Reads the override of the floor in storage slot 0 of the contract, zero meaning unset.
+async fn read_override(
+    engine: &Engine,
+    contract: &Address,
+    block_hash: &BlockHash,
+) -> eyre::Result<Option<u64>> {
+    let value: U256 = engine
+        .eth
+        .rpc_request(
+            "eth_getStorageAt",
+            json!([contract.to_alloy_address(), "0x0", { "blockHash": block_hash }]),
+            GET_STORAGE_AT_TIMEOUT,
+        )
+        .await?;
+
+    if value.is_zero() {
+        return Ok(None);
+    }
+
+    Ok(Some(u64::try_from(value).unwrap_or(u64::MAX)))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_consensus::{BlockBody, Header, Signed, TxEip1559, TxEnvelope};
+    use alloy_primitives::Signature;
+
+    use super::*;
+
+    fn tx(max_fee_per_gas: u128) -> TxEnvelope {
+        let tx = TxEip1559 {
+            max_fee_per_gas,
+            ..Default::default()
+        };
+        let signature = Signature::new(U256::from(1), U256::from(1), false);
+        let hash = B256::from(U256::from(max_fee_per_gas));
+        TxEnvelope::Eip1559(Signed::new_unchecked(tx, signature, hash))
+    }
+
+    fn block(base_fee_per_gas: u64, transactions: Vec<TxEnvelope>) -> Block {
+        Block {
+            header: Header {
+                base_fee_per_gas: Some(base_fee_per_gas),
+                ..Default::default()
+            },
+            body: BlockBody {
+                transactions,
+                ommers: vec![],
+                withdrawals: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_txs_below_floor() {
+        let floor = 100;
+
+        // Base fee at or above the floor: every transaction pays it
+        assert!(txs_below_floor(&block(100, vec![tx(100), tx(200)]), floor).is_empty());
+
+        // Base fee below the floor: only the transactions which accept to pay it are valid
+        let cheap = tx(99);
+        assert_eq!(
+            txs_below_floor(&block(7, vec![tx(100), cheap.clone(), tx(200)]), floor),
+            vec![*cheap.tx_hash()]
+        );
+        assert!(txs_below_floor(&block(7, vec![tx(100)]), floor).is_empty());
+    }
+
+    #[test]
+    fn test_empty_blocks_meet_floor() {
+        // An idle chain lets the base fee fall below the floor, its empty blocks stay valid
+        assert!(txs_below_floor(&block(7, vec![]), 100).is_empty());
+        assert!(txs_below_floor(&block(0, vec![]), u64::MAX).is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+//! Timings of the stages of the production of each block, from the start of its height to
+//! its commit to the execution client, to find where the time of a block goes.
+//!
+//! The handlers mark the stages of each height as they reach them, and the breakdown of
+//! each decided block is logged at the debug level. With `emerald start --profile-blocks N`,
+//! the breakdowns of the next N decided blocks are kept, and a summary of each phase over
+//! these blocks is printed before the node stops.
+//!
+//! The phases only timed on the proposer, building and streaming the value, are missing
+//! from the blocks proposed by other validators, whose proposal phase is the time taken to
+//! receive and validate their value.
+
+use core::fmt;
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use malachitebft_eth_types::Height;
+use tracing::debug;
+
+/// Number of heights above the last decided one for which the stages are recorded
+const MAX_HEIGHTS: usize = 16;
+
+/// Stage reached by the production of a block
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// The first round of the height started
+    Started,
+    /// This node started building the value it proposes
+    BuildStarted,
+    /// The payload was built: `forkchoiceUpdated` with attributes then `getPayload`
+    Built,
+    /// The value was streamed to the peers by this node, or received from the proposer
+    Proposed,
+    /// Consensus decided the value
+    Decided,
+    /// The execution client validated the payload with `newPayload`
+    Validated,
+    /// The execution client made the block its head with `forkchoiceUpdated`
+    ForkchoiceUpdated,
+    /// The decided value was stored and the next height is about to start
+    Committed,
+}
+
+/// Phases of the production of a block, between two stages
+const PHASES: [(&str, Stage, Stage); 8] = [
+    ("build", Stage::BuildStarted, Stage::Built),
+    ("stream", Stage::Built, Stage::Proposed),
+    ("proposal", Stage::Started, Stage::Proposed),
+    ("votes", Stage::Proposed, Stage::Decided),
+    ("new_payload", Stage::Decided, Stage::Validated),
+    ("forkchoice", Stage::Validated, Stage::ForkchoiceUpdated),
+    ("commit", Stage::ForkchoiceUpdated, Stage::Committed),
+    ("total", Stage::Started, Stage::Committed),
+];
+
+/// Stages reached by the production of the block of a height
+#[derive(Clone, Debug, Default)]
+struct BlockTimings {
+    stages: BTreeMap<Stage, Instant>,
+}
+
+impl BlockTimings {
+    fn phase(&self, from: Stage, to: Stage) -> Option<Duration> {
+        let from = self.stages.get(&from)?;
+        let to = self.stages.get(&to)?;
+        to.checked_duration_since(*from)
+    }
+
+    fn phases(&self) -> impl Iterator<Item = (&'static str, Option<Duration>)> + '_ {
+        PHASES
+            .iter()
+            .map(|(name, from, to)| (*name, self.phase(*from, *to)))
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockProfiler {
+    heights: BTreeMap<Height, BlockTimings>,
+    /// Number of blocks left to profile, if profiling
+    remaining: Option<u64>,
+    profiled: Vec<BlockTimings>,
+}
+
+impl BlockProfiler {
+    /// Profiles the next `blocks` decided blocks if set
+    pub fn new(blocks: Option<u64>) -> Self {
+        Self {
+            heights: BTreeMap::new(),
+            remaining: blocks,
+            profiled: Vec::new(),
+        }
+    }
+
+    /// Records that the block of `height` reached `stage` now
+    pub fn reached(&mut self, height: Height, stage: Stage) {
+        self.mark(height, stage, Instant::now());
+    }
+
+    /// Records that the block of `height` reached `stage` at `now`. A round starting
+    /// again does not restart the height, while the later stages are those of the last
+    /// round reaching them.
+    pub fn mark(&mut self, height: Height, stage: Stage, now: Instant) {
+        if !self.heights.contains_key(&height) && self.heights.len() >= MAX_HEIGHTS {
+            return;
+        }
+
+        let stages = &mut self.heights.entry(height).or_default().stages;
+        if stage == Stage::Started {
+            stages.entry(stage).or_insert(now);
+        } else {
+            stages.insert(stage, now);
+        }
+    }
+
+    /// Records that the block of `height` was committed at `now`, and logs its breakdown
+    pub fn committed(&mut self, height: Height, now: Instant) {
+        self.mark(height, Stage::Committed, now);
+
+        let timings = self.heights.remove(&height).unwrap_or_default();
+        self.heights.retain(|h, _| *h > height);
+
+        let breakdown = timings
+            .phases()
+            .filter_map(|(name, duration)| {
+                duration.map(|duration| format!("{name}={}ms", duration.as_millis()))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        debug!(%height, %breakdown, "Block timings");
+
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining > 0 {
+                *remaining -= 1;
+                self.profiled.push(timings);
+            }
+        }
+    }
+
+    /// Whether all the blocks to profile have been committed
+    pub fn is_done(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// Statistics of each phase over the profiled blocks
+    pub fn summary(&self) -> ProfileSummary {
+        let phases = PHASES
+            .iter()
+            .map(|(name, from, to)| {
+                let mut durations = self
+                    .profiled
+                    .iter()
+                    .filter_map(|timings| timings.phase(*from, *to))
+                    .collect::<Vec<_>>();
+                durations.sort();
+                PhaseSummary::new(name, &durations)
+            })
+            .collect();
+
+        ProfileSummary {
+            blocks: self.profiled.len(),
+            phases,
+        }
+    }
+}
+
+/// Statistics of a phase over the profiled blocks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhaseSummary {
+    pub name: &'static str,
+    /// Number of blocks for which the phase was timed
+    pub blocks: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub max: Duration,
+}
+
+impl PhaseSummary {
+    /// Statistics of the `durations` of a phase, sorted
+    fn new(name: &'static str, durations: &[Duration]) -> Self {
+        // Nearest-rank percentile
+        let percentile = |p: usize| {
+            durations
+                .get((durations.len() * p).div_ceil(100).saturating_sub(1))
+                .copied()
+                .unwrap_or_default()
+        };
+
+        Self {
+            name,
+            blocks: durations.len(),
+            mean: durations
+                .iter()
+                .sum::<Duration>()
+                .checked_div(durations.len() as u32)
+                .unwrap_or_default(),
+            p50: percentile(50),
+            p90: percentile(90),
+            max: durations.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileSummary {
+    pub blocks: usize,
+    pub phases: Vec<PhaseSummary>,
+}
+
+impl fmt::Display for ProfileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Timings of {} blocks, in milliseconds", self.blocks)?;
+        writeln!(
+            f,
+            "{:<12} {:>6} {:>8} {:>8} {:>8} {:>8}",
+            "phase", "blocks", "mean", "p50", "p90", "max"
+        )?;
+        for phase in &self.phases {
+            writeln!(
+                f,
+                "{:<12} {:>6} {:>8} {:>8} {:>8} {:>8}",
+                phase.name,
+                phase.blocks,
+                phase.mean.as_millis(),
+                phase.p50.as_millis(),
+                phase.p90.as_millis(),
+                phase.max.as_millis(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_block_profiler() {
+        let start = Instant::now();
+        let mut profiler = BlockProfiler::new(Some(2));
+
+        // Height 1 is proposed by this node, in its second round
+        let height = Height::new(1);
+        profiler.mark(height, Stage::Started, start);
+        profiler.mark(height, Stage::Started, start + 1000 * MS);
+        profiler.mark(height, Stage::BuildStarted, start + 1000 * MS);
+        profiler.mark(height, Stage::Built, start + 1100 * MS);
+        profiler.mark(height, Stage::Proposed, start + 1150 * MS);
+        profiler.mark(height, Stage::Decided, start + 1400 * MS);
+        profiler.mark(height, Stage::Validated, start + 1420 * MS);
+        profiler.mark(height, Stage::ForkchoiceUpdated, start + 1430 * MS);
+        profiler.committed(height, start + 1440 * MS);
+        assert!(!profiler.is_done());
+
+        // Height 2 is proposed by another validator
+        let height = Height::new(2);
+        profiler.mark(height, Stage::Started, start + 2000 * MS);
+        profiler.mark(height, Stage::Proposed, start + 2200 * MS);
+        profiler.mark(height, Stage::Decided, start + 2300 * MS);
+        profiler.mark(height, Stage::Validated, start + 2340 * MS);
+        profiler.mark(height, Stage::ForkchoiceUpdated, start + 2350 * MS);
+        profiler.committed(height, start + 2360 * MS);
+        assert!(profiler.is_done());
+
+        let summary = profiler.summary();
+        assert_eq!(summary.blocks, 2);
+        let phase = |name| {
+            summary
+                .phases
+                .iter()
+                .find(|phase| phase.name == name)
+                .unwrap()
+        };
+        assert_eq!(phase("build").blocks, 1);
+        assert_eq!(phase("build").max, 100 * MS);
+        assert_eq!(phase("proposal").p50, 200 * MS);
+        assert_eq!(phase("proposal").max, 1150 * MS);
+        assert_eq!(phase("new_payload").mean, 30 * MS);
+        assert_eq!(phase("total").max, 1440 * MS);
+
+        // The blocks after the profiled ones are not kept
+        profiler.committed(Height::new(3), start + 3000 * MS);
+        assert_eq!(profiler.summary().blocks, 2);
+    }
+
+    #[test]
+    fn test_phase_summary() {
+        let durations = (1..=10).map(|i| i * 10 * MS).collect::<Vec<_>>();
+        let summary = PhaseSummary::new("votes", &durations);
+        assert_eq!(summary.mean, 55 * MS);
+        assert_eq!(summary.p50, 50 * MS);
+        assert_eq!(summary.p90, 90 * MS);
+        assert_eq!(summary.max, 100 * MS);
+
+        let empty = PhaseSummary::new("votes", &[]);
+        assert_eq!(empty.blocks, 0);
+        assert_eq!(empty.mean, Duration::ZERO);
+    }
+}
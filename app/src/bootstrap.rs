@@ -3,17 +3,25 @@
 //! This module handles initializing node state from genesis or from
 //! previously decided blocks after a restart.
 
-use alloy_rpc_types_engine::{ExecutionPayloadV3, PayloadStatus, PayloadStatusEnum};
-use color_eyre::eyre::{self, eyre, OptionExt};
+use core::time::Duration;
+
+use alloy_rpc_types_engine::{
+    ExecutionPayloadV3, ForkchoiceState, PayloadStatus, PayloadStatusEnum,
+};
+use bytes::Bytes;
+use emerald_retry::{retry, RetryError};
 use malachitebft_eth_cli::config::EmeraldConfig;
 use malachitebft_eth_engine::engine::Engine;
-use malachitebft_eth_types::{Block, BlockHash, Height};
+use malachitebft_eth_engine::error::EngineError;
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_types::{Block, BlockHash, Height, PowerChangeLimit, ValidatorSet, B256};
 use ssz::Decode;
 use tracing::{debug, info, warn};
 
+use crate::forkchoice::{FinalizedBlock, Forkchoice};
 use crate::state::{decode_value, State};
-use crate::store::Store;
-use crate::validators::read_validators_from_contract;
+use crate::store::{Store, StoreError};
+use crate::validators::{limit_power_change, read_validators_from_contract};
 
 /// Represents the range of heights that need to be replayed to the execution client.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -58,6 +66,24 @@ pub fn determine_replay_range(
     }
 }
 
+/// Whether the execution client already has `expected` as forkchoice state, given the
+/// block of its `latest` tag and the hashes of its `safe` and `finalized` tags.
+///
+/// This is the case after a clean restart, where replaying blocks and updating the
+/// forkchoice would only make the execution client revalidate its own head.
+pub fn is_at_forkchoice(
+    el_head: Option<(u64, BlockHash)>,
+    el_safe: Option<BlockHash>,
+    el_finalized: Option<BlockHash>,
+    height: Height,
+    expected: &ForkchoiceState,
+) -> bool {
+    // A missing tag matches a zero hash, i.e. nothing finalized yet
+    el_head == Some((height.as_u64(), expected.head_block_hash))
+        && el_safe.unwrap_or(B256::ZERO) == expected.safe_block_hash
+        && el_finalized.unwrap_or(B256::ZERO) == expected.finalized_block_hash
+}
+
 /// Error returned when an execution client payload status is not `Valid`.
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum PayloadStatusError {
@@ -84,60 +110,238 @@ pub fn validate_payload_status(status: &PayloadStatus) -> Result<(), PayloadStat
     }
 }
 
-pub async fn initialize_state_from_genesis(state: &mut State, engine: &Engine) -> eyre::Result<()> {
+/// Error returned when initializing the node state at startup
+#[derive(Debug, thiserror::Error)]
+pub enum BootstrapError {
+    /// The execution client does not have the genesis block
+    #[error("Genesis block does not exist")]
+    MissingGenesisBlock,
+
+    /// The block decided at the latest stored height is missing from the store
+    #[error("Block decided at height {height} is not stored, database corrupted")]
+    MissingDecidedBlock { height: Height },
+
+    /// A decided value to replay to the execution client is missing from the store
+    #[error("Decided value not found at height {height}, data integrity error")]
+    MissingDecidedValue { height: Height },
+
+    /// A decided value to replay to the execution client is not a valid execution payload
+    #[error("Invalid execution payload at height {height}: {reason}")]
+    InvalidPayload { height: Height, reason: String },
+
+    /// The execution client rejected a block replayed to it
+    #[error("Block replay failed at height {height}: {source}")]
+    Replay {
+        height: Height,
+        #[source]
+        source: PayloadStatusError,
+    },
+
+    /// The execution client did not reconnect in time after losing the connection to it
+    /// during a replay
+    #[error("Execution client did not reconnect within {timeout:?} after losing the connection during the replay at height {height}")]
+    ElReconnectTimeout { height: Height, timeout: Duration },
+
+    /// The execution client rejected the forkchoice of the latest decided block
+    #[error("Forkchoice update failed: {0}")]
+    Forkchoice(#[source] PayloadStatusError),
+
+    /// The execution client does not hold the trusted block of its snapshot
+    #[error("Execution client does not have the trusted block {hash} at height {height}")]
+    MissingTrustedBlock { height: Height, hash: BlockHash },
+
+    /// The validator set could not be read from the validator manager contract
+    #[error("Failed to read the validator set: {0}")]
+    ValidatorSet(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+pub async fn initialize_state_from_genesis(
+    state: &mut State,
+    engine: &Engine,
+) -> Result<(), BootstrapError> {
     // Get the genesis block from the execution engine
     let genesis_block = engine
         .eth
         .get_block_by_number("earliest")
         .await?
-        .ok_or_eyre("Genesis block does not exist")?;
+        .ok_or(BootstrapError::MissingGenesisBlock)?;
     debug!("👉 genesis_block: {:?}", genesis_block);
     state.latest_block = Some(genesis_block);
-    let genesis_validator_set =
-        read_validators_from_contract(engine.eth.url().as_ref(), &genesis_block.block_hash).await?;
+    let genesis_validator_set = read_validators_from_contract(
+        engine.eth.url().as_ref(),
+        &state.validator_manager_address,
+        &genesis_block.block_hash,
+    )
+    .await
+    .map_err(|e| BootstrapError::ValidatorSet(e.into()))?;
     debug!("🌈 Got genesis validator set: {:?}", genesis_validator_set);
     // Set consensus_height to the next height where consensus will work (the tip)
     state.consensus_height = Height::new(genesis_block.block_number).increment();
+    // The validator sets of the next heights are limited from the one of the genesis
+    if state.power_change_limit.is_some() {
+        state
+            .store
+            .store_validator_set(state.consensus_height, genesis_validator_set.clone())
+            .await?;
+    }
     state.set_validator_set(state.consensus_height, genesis_validator_set);
     Ok(())
 }
 
+/// Initialize state from the trusted block of a snapshot of the execution client,
+/// without replaying or syncing the heights up to it.
+pub async fn initialize_state_from_el_snapshot(
+    state: &mut State,
+    engine: &Engine,
+    trusted: FinalizedBlock,
+    emerald_config: &EmeraldConfig,
+) -> Result<(), BootstrapError> {
+    let height = trusted.height;
+    let trusted_block = engine
+        .eth
+        .get_block_by_number(&format!("0x{:x}", height.as_u64()))
+        .await?
+        .filter(|block| block.block_hash == trusted.block_hash)
+        .ok_or(BootstrapError::MissingTrustedBlock {
+            height,
+            hash: trusted.block_hash,
+        })?;
+
+    // The trusted block is decided, and final, and the node starts from it again after a
+    // restart until it decides a height
+    state.store.store_snapshot_anchor(trusted).await?;
+    state.store.store_finalized_block(trusted).await?;
+    state.forkchoice.set_finalized(trusted);
+    let payload_status = engine
+        .send_forkchoice_updated(
+            state.forkchoice.state(height, trusted_block.block_hash),
+            &emerald_config.retry_config,
+        )
+        .await?;
+    validate_payload_status(&payload_status).map_err(BootstrapError::Forkchoice)?;
+
+    // Set consensus_height to the next height where consensus will work (the tip)
+    state.consensus_height = height.increment();
+    state.latest_block = Some(trusted_block);
+
+    // With a power change limit, the validator set must have been imported with the store
+    // of another node, as the blocks below the trusted one cannot be read
+    let validator_set = match &state.power_change_limit {
+        Some(limit) => limited_validator_set(state, engine, state.consensus_height, limit).await?,
+        None => read_validators_from_contract(
+            engine.eth.url().as_ref(),
+            &state.validator_manager_address,
+            &trusted_block.block_hash,
+        )
+        .await
+        .map_err(|e| BootstrapError::ValidatorSet(e.into()))?,
+    };
+    state.set_validator_set(state.consensus_height, validator_set);
+
+    Ok(())
+}
+
+/// Number of times a replay may be interrupted at the same height by a loss of the
+/// connection to the execution client, e.g. restarting on the block replayed to it,
+/// before the replay fails
+const MAX_INTERRUPTIONS_AT_HEIGHT: u32 = 3;
+
 /// Replay blocks from Emerald's store to the execution client (Reth).
-/// This is needed when Reth is behind Emerald's stored height after a crash.
-async fn replay_heights_to_engine(
+/// This is needed when Reth is behind Emerald's stored height after a crash, or when its
+/// chain diverged from the decided blocks.
+///
+/// When the connection to the execution client is lost, e.g. while it restarts, the
+/// replay waits for it to reconnect for up to `el_reconnect_timeout`, then resumes from
+/// its head, as it may have lost the latest blocks replayed to it.
+pub(crate) async fn replay_heights_to_engine(
     store: &Store,
     engine: &Engine,
+    forkchoice: &Forkchoice,
     start_height: Height,
     end_height: Height,
     emerald_config: &EmeraldConfig,
-) -> eyre::Result<()> {
+) -> Result<(), BootstrapError> {
     info!(
         "🔄 Replaying heights {} to {} to execution client",
         start_height, end_height
     );
 
-    for height in start_height.as_u64()..=end_height.as_u64() {
+    let mut next_height = start_height;
+    let mut interruptions: Option<(Height, u32)> = None;
+
+    loop {
+        let error = match replay_range(
+            store,
+            engine,
+            forkchoice,
+            &mut next_height,
+            end_height,
+            emerald_config,
+        )
+        .await
+        {
+            Ok(()) => break,
+            Err(BootstrapError::Engine(e)) if e.is_connection_loss() => e,
+            Err(e) => return Err(e),
+        };
+
+        let interrupted = next_height;
+        let count = match interruptions {
+            Some((height, count)) if height == interrupted => count + 1,
+            _ => 1,
+        };
+        if count > MAX_INTERRUPTIONS_AT_HEIGHT || emerald_config.el_reconnect_timeout.is_zero() {
+            return Err(error.into());
+        }
+        interruptions = Some((interrupted, count));
+
+        warn!(
+            height = %interrupted,
+            "Lost the connection to the execution client during the replay, waiting for it to reconnect: {error}"
+        );
+        let el_head = wait_for_el_head(engine, interrupted, emerald_config).await?;
+        next_height =
+            resume_height(store, engine, start_height, interrupted, el_head.as_ref()).await?;
+        info!(
+            from = %next_height,
+            el_head = ?el_head.map(|head| head.block_number),
+            "Execution client reconnected, resuming the replay"
+        );
+    }
+
+    info!("✅ Successfully replayed all heights to execution client");
+    Ok(())
+}
+
+/// Replays the heights from `next_height` to `end_height`, advancing `next_height` past
+/// each height replayed, so that it is the height of the failure on error.
+async fn replay_range(
+    store: &Store,
+    engine: &Engine,
+    forkchoice: &Forkchoice,
+    next_height: &mut Height,
+    end_height: Height,
+    emerald_config: &EmeraldConfig,
+) -> Result<(), BootstrapError> {
+    let mut decided_values = store.stream_raw_decided_values(*next_height..=end_height);
+
+    for height in next_height.as_u64()..=end_height.as_u64() {
         let height = Height::new(height);
 
         // Sending the whole block to the execution engine.
-        let value_bytes = store
-            .get_raw_decided_value(height)
-            .await?
-            .ok_or_else(|| {
-                eyre!("Decided value not found at height {height}, data integrity error")
-            })?
-            .value_bytes;
-
-        let value = decode_value(value_bytes);
-        let block_bytes = value.extensions.clone();
-        // Deserialize the execution payload
-        let execution_payload = ExecutionPayloadV3::from_ssz_bytes(&block_bytes).map_err(|e| {
-            eyre!(
-                "Failed to deserialize execution payload at height {}: {:?}",
-                height,
-                e
-            )
-        })?;
+        let value_bytes = match decided_values.recv().await.transpose()? {
+            Some(raw_decided_value) if raw_decided_value.certificate.height == height => {
+                raw_decided_value.value_bytes
+            }
+            _ => return Err(BootstrapError::MissingDecidedValue { height }),
+        };
+        let execution_payload = decode_execution_payload(height, value_bytes)?;
 
         debug!(
             "🔄 Replaying block at height {} with hash {:?}",
@@ -146,11 +350,10 @@ async fn replay_heights_to_engine(
 
         // Extract versioned hashes from blob transactions
         let block: Block = execution_payload.clone().try_into_block().map_err(|e| {
-            eyre!(
-                "Failed to convert execution payload to block at height {}: {}",
+            BootstrapError::InvalidPayload {
                 height,
-                e
-            )
+                reason: format!("failed to convert to a block: {e}"),
+            }
         })?;
         let versioned_hashes: Vec<BlockHash> =
             block.body.blob_versioned_hashes_iter().copied().collect();
@@ -166,24 +369,133 @@ async fn replay_heights_to_engine(
 
         // Verify the block was accepted
         validate_payload_status(&payload_status)
-            .map_err(|e| eyre::eyre!("Block replay failed at height {}: {}", height, e))?;
+            .map_err(|source| BootstrapError::Replay { height, source })?;
         debug!("✅ Block at height {} replayed successfully", height);
 
         // Update forkchoice to this block
         engine
             .set_latest_forkchoice_state(
-                execution_payload.payload_inner.payload_inner.block_hash,
+                forkchoice.state(
+                    height,
+                    execution_payload.payload_inner.payload_inner.block_hash,
+                ),
                 &emerald_config.retry_config,
             )
             .await?;
 
         debug!("🎯 Forkchoice updated to height {}", height);
+        *next_height = height.increment();
     }
 
-    info!("✅ Successfully replayed all heights to execution client");
     Ok(())
 }
 
+/// Decodes the execution payload of a decided value
+fn decode_execution_payload(
+    height: Height,
+    value_bytes: Bytes,
+) -> Result<ExecutionPayloadV3, BootstrapError> {
+    let value = decode_value(value_bytes).map_err(|e| BootstrapError::InvalidPayload {
+        height,
+        reason: format!("failed to decode the value: {e}"),
+    })?;
+    let block_bytes = value.extensions.clone();
+    // Deserialize the execution payload
+    ExecutionPayloadV3::from_ssz_bytes(&block_bytes).map_err(|e| BootstrapError::InvalidPayload {
+        height,
+        reason: format!("failed to deserialize: {e:?}"),
+    })
+}
+
+/// Waits for the execution client to answer again after a loss of the connection during
+/// the replay at `height`, and returns its head block.
+async fn wait_for_el_head(
+    engine: &Engine,
+    height: Height,
+    emerald_config: &EmeraldConfig,
+) -> Result<Option<ExecutionBlock>, BootstrapError> {
+    let timeout = emerald_config.el_reconnect_timeout;
+
+    retry(
+        &emerald_config.retry_config.backoff(),
+        timeout,
+        |outcome: &Result<_, EngineError>, delay| match outcome {
+            Err(e) if e.is_connection_loss() => {
+                debug!("Execution client still unreachable, retrying in {delay:?}");
+                true
+            }
+            _ => false,
+        },
+        || engine.eth.get_block_by_number("latest"),
+    )
+    .await
+    .map_err(|e| match e {
+        RetryError::Failed(e) => BootstrapError::Engine(e),
+        RetryError::TimedOut { .. } => BootstrapError::ElReconnectTimeout { height, timeout },
+    })
+}
+
+/// Height from which a replay interrupted at `interrupted` resumes, given the height of
+/// the head of the execution client once reconnected: it may have lost the latest blocks
+/// replayed to it which it did not persist before restarting. The replay never resumes
+/// below `floor`, the lowest height it can replay.
+fn resume_from_head(interrupted: Height, el_head: Option<u64>, floor: Height) -> Height {
+    match el_head {
+        Some(head) => Height::new(head + 1).min(interrupted).max(floor),
+        None => floor,
+    }
+}
+
+/// Height from which a replay from `start_height` interrupted at `interrupted` resumes.
+///
+/// The replay resumes from the head of the execution client if it is a replayed block,
+/// and from `start_height` otherwise, e.g. when the execution client went back to the
+/// chain which diverged from the decided blocks on restart. It never resumes below
+/// `start_height`, nor below the lowest height whose value was not pruned.
+async fn resume_height(
+    store: &Store,
+    engine: &Engine,
+    start_height: Height,
+    interrupted: Height,
+    el_head: Option<&ExecutionBlock>,
+) -> Result<Height, BootstrapError> {
+    let floor = store
+        .decided_heights()
+        .earliest_unpruned
+        .map_or(start_height, |earliest| earliest.max(start_height));
+    let resume = resume_from_head(interrupted, el_head.map(|head| head.block_number), floor);
+    if resume == floor {
+        return Ok(resume);
+    }
+
+    // The parent of the resumed height was replayed to the execution client
+    let parent = Height::new(resume.as_u64() - 1);
+    let el_parent = match el_head {
+        Some(head) if head.block_number == parent.as_u64() => Some(*head),
+        _ => {
+            engine
+                .eth
+                .get_block_by_number(&format!("0x{:x}", parent.as_u64()))
+                .await?
+        }
+    };
+    let decided_parent = match store.get_raw_decided_value(parent).await? {
+        Some(raw_decided_value) => decode_execution_payload(parent, raw_decided_value.value_bytes)?,
+        None => return Err(BootstrapError::MissingDecidedValue { height: parent }),
+    };
+
+    let decided_hash = decided_parent.payload_inner.payload_inner.block_hash;
+    if el_parent.map(|block| block.block_hash) == Some(decided_hash) {
+        Ok(resume)
+    } else {
+        warn!(
+            height = %parent,
+            "Execution client is not on the replayed blocks after reconnecting, replaying them again"
+        );
+        Ok(floor)
+    }
+}
+
 /// Initialize state from a previously decided block stored locally by catching the
 /// execution client up to that height, updating forkchoice, and loading the validator
 /// set for the next consensus height.
@@ -192,18 +504,165 @@ pub async fn initialize_state_from_existing_block(
     engine: &Engine,
     height: Height,
     emerald_config: &EmeraldConfig,
-) -> eyre::Result<()> {
+) -> Result<(), BootstrapError> {
     // If there was somethign stored in the store for height, we should be able to retrieve
     // block data as well.
 
     let latest_block_candidate_from_store = state
         .get_latest_block_candidate(height)
         .await
-        .ok_or_eyre("we have not atomically stored the last block, database corrupted")?;
+        .ok_or(BootstrapError::MissingDecidedBlock { height })?;
+
+    let forkchoice_state = state
+        .forkchoice
+        .state(height, latest_block_candidate_from_store.block_hash);
+
+    // The tags of the execution client are only read when its head is at the stored height
+    let el_head = engine.eth.get_block_by_number("latest").await?;
+    let at_forkchoice = match el_head {
+        Some(head) if head.block_number == height.as_u64() => {
+            let el_safe = engine.eth.get_block_by_number("safe").await?;
+            let el_finalized = engine.eth.get_block_by_number("finalized").await?;
+            is_at_forkchoice(
+                Some((head.block_number, head.block_hash)),
+                el_safe.map(|block| block.block_hash),
+                el_finalized.map(|block| block.block_hash),
+                height,
+                &forkchoice_state,
+            )
+        }
+        _ => false,
+    };
 
-    // Check if Reth is behind Emerald's stored height and replay if needed
-    let reth_latest_height = engine.get_latest_block_number().await?;
+    if at_forkchoice {
+        info!(
+            %height,
+            "Execution client is already at the stored head, skipping replay and forkchoice update"
+        );
+    } else {
+        sync_el_to_stored_head(
+            state,
+            engine,
+            height,
+            el_head.map(|block| block.block_number),
+            forkchoice_state,
+            emerald_config,
+        )
+        .await?;
+    }
+
+    // Set consensus_height to the next height where consensus will work (the tip)
+    state.consensus_height = height.increment();
+    state.latest_block = Some(latest_block_candidate_from_store);
+    debug!(latest_block = ?state.latest_block, "Payload is valid");
 
+    // Read the validator set at the stored block - this is the validator set
+    // that will be active for the NEXT height (where consensus will start)
+    let block_validator_set = match &state.power_change_limit {
+        Some(limit) => limited_validator_set(state, engine, state.consensus_height, limit).await?,
+        None => read_validators_from_contract(
+            engine.eth.url().as_ref(),
+            &state.validator_manager_address,
+            &latest_block_candidate_from_store.block_hash,
+        )
+        .await
+        .map_err(|e| BootstrapError::ValidatorSet(e.into()))?,
+    };
+
+    // Consensus will start at consensus_height, so we set the validator set for that height
+    debug!(
+        validator_set = ?block_validator_set,
+        height = %state.consensus_height,
+        "Got validator set"
+    );
+    state.set_validator_set(state.consensus_height, block_validator_set);
+
+    Ok(())
+}
+
+/// Returns the validator set of `height` under the power change limit of the genesis.
+///
+/// It is the one stored when the previous height was decided, or it is limited again from
+/// the latest validator set stored below it, or from the one of the genesis, reading the
+/// validator manager contract at each decided block in between, and the validator set of
+/// each height is stored. The validator set of the contract is never used as is, as the
+/// other validators limit it: a node that cannot limit it again fails to start.
+pub(crate) async fn limited_validator_set(
+    state: &State,
+    engine: &Engine,
+    height: Height,
+    limit: &PowerChangeLimit,
+) -> Result<ValidatorSet, BootstrapError> {
+    let stored = state
+        .store
+        .get_latest_validator_set_at_or_below(height)
+        .await?;
+    let (mut from, mut validator_set) = match stored {
+        Some(stored) => stored,
+        None => {
+            let genesis_block = engine
+                .eth
+                .get_block_by_number("earliest")
+                .await?
+                .ok_or(BootstrapError::MissingGenesisBlock)?;
+            let genesis_validator_set = read_validators_from_contract(
+                engine.eth.url().as_ref(),
+                &state.validator_manager_address,
+                &genesis_block.block_hash,
+            )
+            .await
+            .map_err(|e| BootstrapError::ValidatorSet(e.into()))?;
+            let from = Height::new(genesis_block.block_number).increment();
+            state
+                .store
+                .store_validator_set(from, genesis_validator_set.clone())
+                .await?;
+            (from, genesis_validator_set)
+        }
+    };
+
+    if from < height {
+        warn!(
+            %from,
+            to = %height,
+            "Validator set not stored for the height, limiting the power changes again"
+        );
+    }
+    while from < height {
+        let block_hash = state
+            .decided_block_hash(engine, from)
+            .await
+            .map_err(|e| BootstrapError::ValidatorSet(e.into()))?;
+        let target = read_validators_from_contract(
+            engine.eth.url().as_ref(),
+            &state.validator_manager_address,
+            &block_hash,
+        )
+        .await
+        .map_err(|e| BootstrapError::ValidatorSet(e.into()))?;
+
+        from = from.increment();
+        validator_set = limit_power_change(from, &validator_set, target, limit);
+        state
+            .store
+            .store_validator_set(from, validator_set.clone())
+            .await?;
+    }
+
+    Ok(validator_set)
+}
+
+/// Catches the execution client up to the stored head at `height`, replaying the
+/// missing blocks if it is behind, and updates its forkchoice.
+async fn sync_el_to_stored_head(
+    state: &State,
+    engine: &Engine,
+    height: Height,
+    reth_latest_height: Option<u64>,
+    forkchoice_state: ForkchoiceState,
+    emerald_config: &EmeraldConfig,
+) -> Result<(), BootstrapError> {
+    // Check if Reth is behind Emerald's stored height and replay if needed
     match determine_replay_range(reth_latest_height, height) {
         ReplayDecision::ReplayRange { start, end } => {
             if let Some(reth_height) = reth_latest_height {
@@ -214,7 +673,15 @@ pub async fn initialize_state_from_existing_block(
             } else {
                 warn!("⚠️  Execution client has no blocks, replaying from genesis");
             }
-            replay_heights_to_engine(&state.store, engine, start, end, emerald_config).await?;
+            replay_heights_to_engine(
+                &state.store,
+                engine,
+                &state.forkchoice,
+                start,
+                end,
+                emerald_config,
+            )
+            .await?;
             info!("✅ Height replay completed successfully");
         }
         ReplayDecision::NoReplay => {
@@ -226,36 +693,10 @@ pub async fn initialize_state_from_existing_block(
     }
 
     let payload_status = engine
-        .send_forkchoice_updated(
-            latest_block_candidate_from_store.block_hash,
-            &emerald_config.retry_config,
-        )
+        .send_forkchoice_updated(forkchoice_state, &emerald_config.retry_config)
         .await?;
 
-    validate_payload_status(&payload_status).map_err(|e| eyre::eyre!("{}", e))?;
-
-    // Set consensus_height to the next height where consensus will work (the tip)
-    state.consensus_height = height.increment();
-    state.latest_block = Some(latest_block_candidate_from_store);
-    debug!(latest_block = ?state.latest_block, "Payload is valid");
-
-    // Read the validator set at the stored block - this is the validator set
-    // that will be active for the NEXT height (where consensus will start)
-    let block_validator_set = read_validators_from_contract(
-        engine.eth.url().as_ref(),
-        &latest_block_candidate_from_store.block_hash,
-    )
-    .await?;
-
-    // Consensus will start at consensus_height, so we set the validator set for that height
-    debug!(
-        validator_set = ?block_validator_set,
-        height = %state.consensus_height,
-        "Got validator set"
-    );
-    state.set_validator_set(state.consensus_height, block_validator_set);
-
-    Ok(())
+    validate_payload_status(&payload_status).map_err(BootstrapError::Forkchoice)
 }
 
 #[cfg(test)]
@@ -264,6 +705,7 @@ mod tests {
     use alloy_rpc_types_engine::{PayloadStatus, PayloadStatusEnum};
 
     use super::*;
+    use crate::state::testing::TestNode;
 
     // ==================== determine_replay_range tests ====================
 
@@ -320,6 +762,161 @@ mod tests {
         );
     }
 
+    // ==================== resume_from_head tests ====================
+
+    #[test]
+    fn test_resume_from_head_lost_blocks() {
+        // The execution client lost the blocks replayed after 7 on restart
+        assert_eq!(
+            resume_from_head(Height::new(10), Some(7), Height::new(1)),
+            Height::new(8)
+        );
+    }
+
+    #[test]
+    fn test_resume_from_head_at_interrupted_height() {
+        // The block of the interrupted height may have been imported or not
+        assert_eq!(
+            resume_from_head(Height::new(10), Some(9), Height::new(1)),
+            Height::new(10)
+        );
+        assert_eq!(
+            resume_from_head(Height::new(10), Some(10), Height::new(1)),
+            Height::new(10)
+        );
+    }
+
+    #[test]
+    fn test_resume_from_head_no_blocks() {
+        assert_eq!(
+            resume_from_head(Height::new(10), None, Height::new(5)),
+            Height::new(5)
+        );
+    }
+
+    #[test]
+    fn test_resume_from_head_below_floor() {
+        // The execution client lost blocks below the ones the replay can replay
+        assert_eq!(
+            resume_from_head(Height::new(10), Some(2), Height::new(5)),
+            Height::new(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_height_without_head() {
+        let node = TestNode::new(1, Height::new(1), |_| {}).await;
+        for height in 5..=8 {
+            node.decide(Height::new(height), Bytes::from(vec![height as u8]))
+                .await;
+        }
+
+        // Resumes from the start of the replay rather than from the first height
+        let resume = resume_height(
+            &node.state.store,
+            &node.engine,
+            Height::new(6),
+            Height::new(8),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resume, Height::new(6));
+
+        // Nor from below the values still in the store
+        let resume = resume_height(
+            &node.state.store,
+            &node.engine,
+            Height::new(3),
+            Height::new(8),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resume, Height::new(5));
+    }
+
+    // ==================== is_at_forkchoice tests ====================
+
+    fn forkchoice_state(head: u8, finalized: u8) -> ForkchoiceState {
+        ForkchoiceState {
+            head_block_hash: B256::with_last_byte(head),
+            safe_block_hash: B256::with_last_byte(head),
+            finalized_block_hash: B256::with_last_byte(finalized),
+        }
+    }
+
+    #[test]
+    fn test_is_at_forkchoice_after_clean_restart() {
+        let expected = forkchoice_state(10, 8);
+        assert!(is_at_forkchoice(
+            Some((10, B256::with_last_byte(10))),
+            Some(B256::with_last_byte(10)),
+            Some(B256::with_last_byte(8)),
+            Height::new(10),
+            &expected
+        ));
+    }
+
+    #[test]
+    fn test_is_at_forkchoice_head_differs() {
+        let expected = forkchoice_state(10, 8);
+        let el_safe = Some(B256::with_last_byte(10));
+        let el_finalized = Some(B256::with_last_byte(8));
+
+        // Another block at the same height
+        assert!(!is_at_forkchoice(
+            Some((10, B256::with_last_byte(11))),
+            el_safe,
+            el_finalized,
+            Height::new(10),
+            &expected
+        ));
+        // Execution client without blocks
+        assert!(!is_at_forkchoice(
+            None,
+            el_safe,
+            el_finalized,
+            Height::new(10),
+            &expected
+        ));
+    }
+
+    #[test]
+    fn test_is_at_forkchoice_tags_differ() {
+        let expected = forkchoice_state(10, 8);
+        let el_head = Some((10, B256::with_last_byte(10)));
+
+        // The finalized block lags behind the stored one
+        assert!(!is_at_forkchoice(
+            el_head,
+            Some(B256::with_last_byte(10)),
+            Some(B256::with_last_byte(7)),
+            Height::new(10),
+            &expected
+        ));
+        // The safe tag was never set
+        assert!(!is_at_forkchoice(
+            el_head,
+            None,
+            Some(B256::with_last_byte(8)),
+            Height::new(10),
+            &expected
+        ));
+        // Nothing finalized yet on either side
+        let expected = ForkchoiceState {
+            finalized_block_hash: B256::ZERO,
+            ..expected
+        };
+        assert!(is_at_forkchoice(
+            el_head,
+            Some(B256::with_last_byte(10)),
+            None,
+            Height::new(10),
+            &expected
+        ));
+    }
+
     // ==================== validate_payload_status tests ====================
 
     fn make_payload_status(status: PayloadStatusEnum) -> PayloadStatus {
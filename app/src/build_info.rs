@@ -0,0 +1,58 @@
+//! Identity of the running node, so that operators can audit what runs on each node.
+//!
+//! The build information is embedded at compile time by `build.rs`, and exposed along
+//! with the version of the execution client as the `build_info` metric and on the
+//! `GET /version` route of the admin API.
+
+use std::sync::{Arc, RwLock};
+
+use malachitebft_eth_engine::json_structures::ClientVersionV1;
+use serde::Serialize;
+
+/// Version of the crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the binary was built from, `unknown` if built outside of the repository
+pub const GIT_COMMIT: &str = env!("EMERALD_GIT_COMMIT");
+
+/// Version of the compiler the binary was built with
+pub const RUSTC_VERSION: &str = env!("EMERALD_RUSTC_VERSION");
+
+/// Enabled cargo features, comma-separated
+pub const FEATURES: &str = env!("EMERALD_FEATURES");
+
+/// Version of Malachite, with the git revision it is built from
+pub const MALACHITE_VERSION: &str = env!("EMERALD_MALACHITE_VERSION");
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub rustc_version: &'static str,
+    pub features: Vec<&'static str>,
+    pub malachite_version: &'static str,
+    /// Version of the execution client, once connected to it
+    pub el_client_version: Option<ClientVersionV1>,
+}
+
+/// Build information shared with the admin API, completed with the version of the
+/// execution client when consensus is ready
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuildInfo(Arc<RwLock<Option<ClientVersionV1>>>);
+
+impl SharedBuildInfo {
+    pub fn get(&self) -> BuildInfo {
+        BuildInfo {
+            version: VERSION,
+            git_commit: GIT_COMMIT,
+            rustc_version: RUSTC_VERSION,
+            features: FEATURES.split(',').filter(|f| !f.is_empty()).collect(),
+            malachite_version: MALACHITE_VERSION,
+            el_client_version: self.0.read().expect("build info lock poisoned").clone(),
+        }
+    }
+
+    pub fn set_el_client_version(&self, client_version: ClientVersionV1) {
+        *self.0.write().expect("build info lock poisoned") = Some(client_version);
+    }
+}
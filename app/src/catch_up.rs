@@ -0,0 +1,325 @@
+//! Throttle of the catch-up, so that a node applying the values synced from its peers does
+//! not starve the RPC traffic of its execution client.
+//!
+//! A synced value is executed by the execution client when it is processed, and a node
+//! catching up processes them back to back. With `catch_up_throttle`, a synced value waits
+//! before being processed:
+//! - until `1 / blocks_per_second` after the previous one, if set,
+//! - while the execution client uses more than `max_el_cpu_percent` of a core since the
+//!   previous sample, if `el_pid_file` is set, for at most `max_el_cpu_wait` so that the
+//!   catch-up keeps progressing.
+//!
+//! The throttle is disabled by `emerald start --fast-sync`. The values decided by the node
+//! in consensus are never throttled.
+
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+
+use emerald_retry::SharedClock;
+use malachitebft_eth_cli::config::CatchUpThrottleConfig;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Interval at which the CPU usage of the execution client is sampled while a value waits
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Clock ticks per second of the CPU times in `/proc/<pid>/stat`, which the kernel reports
+/// in `USER_HZ`, 100 on the supported architectures
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// Why a synced value waited
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ThrottleReason {
+    /// Above `blocks_per_second`
+    Rate,
+    /// Execution client above `max_el_cpu_percent`
+    ElCpu,
+}
+
+impl ThrottleReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rate => "rate",
+            Self::ElCpu => "el_cpu",
+        }
+    }
+}
+
+/// Wait of a synced value
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Throttled {
+    pub delay: Duration,
+    /// Reason of the longest part of the wait
+    pub reason: ThrottleReason,
+}
+
+/// Sample of the CPU time of the execution client
+#[derive(Copy, Clone, Debug)]
+struct CpuSample {
+    pid: u32,
+    at: Instant,
+    ticks: u64,
+}
+
+#[derive(Debug)]
+struct ElCpu {
+    pid_file: PathBuf,
+    max_percent: f64,
+    max_wait: Duration,
+    last: Option<CpuSample>,
+}
+
+impl ElCpu {
+    /// CPU usage of the execution client since the previous sample, in percent of a core.
+    /// `None` on the first sample, after a restart of the client, or if it cannot be read.
+    fn usage(&mut self, now: Instant) -> Option<f64> {
+        let sample = match read_cpu_sample(&self.pid_file, now) {
+            Ok(sample) => sample,
+            Err(e) => {
+                debug!("Failed to read the CPU usage of the execution client: {e}");
+                self.last = None;
+                return None;
+            }
+        };
+
+        let previous = self.last.replace(sample)?;
+        cpu_percent(&previous, &sample)
+    }
+}
+
+fn read_cpu_sample(pid_file: &Path, now: Instant) -> Result<CpuSample, String> {
+    let pid = std::fs::read_to_string(pid_file)
+        .map_err(|e| format!("{}: {e}", pid_file.display()))?
+        .trim()
+        .parse::<u32>()
+        .map_err(|e| format!("invalid pid in {}: {e}", pid_file.display()))?;
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+        .map_err(|e| format!("/proc/{pid}/stat: {e}"))?;
+    let ticks = parse_cpu_ticks(&stat).ok_or_else(|| format!("invalid /proc/{pid}/stat"))?;
+
+    Ok(CpuSample {
+        pid,
+        at: now,
+        ticks,
+    })
+}
+
+/// User and system CPU time of a process in `/proc/<pid>/stat`, in clock ticks
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // The name of the process, in parentheses, may contain spaces
+    let fields: Vec<&str> = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .collect();
+    // `utime` and `stime` are the 14th and 15th fields, the first after the name being the 3rd
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// CPU usage between two samples of the same process, in percent of a core
+fn cpu_percent(previous: &CpuSample, sample: &CpuSample) -> Option<f64> {
+    let elapsed = sample
+        .at
+        .saturating_duration_since(previous.at)
+        .as_secs_f64();
+    if sample.pid != previous.pid || elapsed <= 0.0 {
+        return None;
+    }
+
+    let cpu_secs = sample.ticks.saturating_sub(previous.ticks) as f64 / CLOCK_TICKS_PER_SECOND;
+    Some(cpu_secs / elapsed * 100.0)
+}
+
+/// Throttle of the values synced from the peers
+#[derive(Debug, Default)]
+pub struct CatchUpThrottle {
+    /// Minimum time between two synced values
+    min_interval: Option<Duration>,
+    el_cpu: Option<ElCpu>,
+    /// Time the previous synced value was released
+    released: Option<Instant>,
+}
+
+impl CatchUpThrottle {
+    /// Throttle set by `config`, disabled when unset or with `fast_sync`
+    pub fn new(config: Option<&CatchUpThrottleConfig>, fast_sync: bool) -> Self {
+        let Some(config) = config.filter(|_| !fast_sync) else {
+            return Self::default();
+        };
+
+        Self {
+            min_interval: config
+                .blocks_per_second
+                .map(|rate| Duration::from_secs(1) / rate.max(1)),
+            el_cpu: config.el_pid_file.clone().map(|pid_file| ElCpu {
+                pid_file,
+                max_percent: f64::from(config.max_el_cpu_percent),
+                max_wait: config.max_el_cpu_wait,
+                last: None,
+            }),
+            released: None,
+        }
+    }
+
+    /// Description of the throttle set by `config`, as shown in the status of the node,
+    /// `None` when there is none
+    pub fn describe(config: Option<&CatchUpThrottleConfig>, fast_sync: bool) -> Option<String> {
+        let config = config?;
+        if fast_sync {
+            return Some("disabled by --fast-sync".to_string());
+        }
+
+        let mut limits = Vec::new();
+        if let Some(rate) = config.blocks_per_second {
+            limits.push(format!("{rate} blocks/s"));
+        }
+        if config.el_pid_file.is_some() {
+            limits.push(format!("{}% EL CPU", config.max_el_cpu_percent));
+        }
+        Some(limits.join(", "))
+    }
+
+    /// Delay before the next synced value, to stay below `blocks_per_second`
+    fn rate_delay(&self, now: Instant) -> Duration {
+        match (self.min_interval, self.released) {
+            (Some(min_interval), Some(released)) => {
+                min_interval.saturating_sub(now.saturating_duration_since(released))
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Waits until the next synced value may be applied to the execution client.
+    /// Returns how long it waited, if it did.
+    pub async fn wait(&mut self, clock: &SharedClock) -> Option<Throttled> {
+        let start = clock.now();
+
+        let rate_delay = self.rate_delay(start);
+        clock.sleep(rate_delay).await;
+
+        let mut cpu_delay = Duration::ZERO;
+        if let Some(el_cpu) = &mut self.el_cpu {
+            while cpu_delay < el_cpu.max_wait {
+                match el_cpu.usage(clock.now()) {
+                    Some(usage) if usage > el_cpu.max_percent => {
+                        let pause = CPU_SAMPLE_INTERVAL.min(el_cpu.max_wait - cpu_delay);
+                        clock.sleep(pause).await;
+                        cpu_delay += pause;
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        self.released = Some(clock.now());
+
+        let delay = rate_delay + cpu_delay;
+        (!delay.is_zero()).then_some(Throttled {
+            delay,
+            reason: if cpu_delay > rate_delay {
+                ThrottleReason::ElCpu
+            } else {
+                ThrottleReason::Rate
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use emerald_retry::MockClock;
+
+    use super::*;
+
+    fn config(blocks_per_second: Option<u32>) -> CatchUpThrottleConfig {
+        CatchUpThrottleConfig {
+            blocks_per_second,
+            el_pid_file: None,
+            max_el_cpu_percent: 80,
+            max_el_cpu_wait: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_parse_cpu_ticks() {
+        let stat = "4242 (reth node (1)) S 1 4242 4242 0 -1 4194560 1000 0 0 0 1234 567 0 0 20 0 32 0 100 0 0";
+        assert_eq!(parse_cpu_ticks(stat), Some(1234 + 567));
+        assert_eq!(parse_cpu_ticks("4242 (reth) S 1"), None);
+        assert_eq!(parse_cpu_ticks(""), None);
+    }
+
+    #[test]
+    fn test_cpu_percent() {
+        let at = Instant::now();
+        let previous = CpuSample {
+            pid: 1,
+            at,
+            ticks: 1000,
+        };
+        let sample = CpuSample {
+            pid: 1,
+            at: at + Duration::from_millis(500),
+            ticks: 1075,
+        };
+        // 0.75s of CPU time over 0.5s
+        assert_eq!(cpu_percent(&previous, &sample), Some(150.0));
+
+        let restarted = CpuSample { pid: 2, ..sample };
+        assert_eq!(cpu_percent(&previous, &restarted), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(CatchUpThrottle::describe(None, false), None);
+        assert_eq!(
+            CatchUpThrottle::describe(Some(&config(Some(20))), false),
+            Some("20 blocks/s".to_string())
+        );
+        assert_eq!(
+            CatchUpThrottle::describe(Some(&config(Some(20))), true),
+            Some("disabled by --fast-sync".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_throttle() {
+        let clock = MockClock::new();
+        let shared = clock.shared();
+        let mut throttle = CatchUpThrottle::new(Some(&config(Some(4))), false);
+
+        // The first value is not throttled
+        assert_eq!(throttle.wait(&shared).await, None);
+
+        // The next one waits for the rest of the 250ms interval
+        clock.advance(Duration::from_millis(100));
+        let wait = {
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                let throttled = throttle.wait(&shared).await;
+                (throttle, throttled)
+            })
+        };
+        while clock.sleepers() == 0 {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_millis(150));
+        let (mut throttle, throttled) = wait.await.unwrap();
+        assert_eq!(
+            throttled,
+            Some(Throttled {
+                delay: Duration::from_millis(150),
+                reason: ThrottleReason::Rate,
+            })
+        );
+
+        // A value arriving after the interval is not throttled
+        clock.advance(Duration::from_millis(300));
+        assert_eq!(throttle.wait(&shared).await, None);
+
+        // Fast sync disables the throttle
+        let mut throttle = CatchUpThrottle::new(Some(&config(Some(4))), true);
+        assert_eq!(throttle.wait(&shared).await, None);
+        assert_eq!(throttle.wait(&shared).await, None);
+    }
+}
@@ -0,0 +1,206 @@
+//! Verification of the commit certificates of the values synced from the peers.
+//!
+//! The consensus engine verifies the certificates of the values it syncs, but the store
+//! keeps whatever certificate comes with a decided value and serves it to other peers in
+//! turn. The certificate of a synced value is thus verified again against the validator
+//! set of its height before the value is committed, so that a faulty or malicious peer
+//! cannot make the node commit and store a value that was not decided.
+//!
+//! The verification only depends on the consensus types, and is available to light clients
+//! without the node with the `light-client` feature.
+
+use core::fmt;
+use std::collections::BTreeSet;
+
+use malachitebft_core_types::{CommitCertificate, NilOrVal};
+use malachitebft_eth_types::secp256k1::K256Provider;
+use malachitebft_eth_types::{Address, EmeraldContext, ValidatorSet, Vote};
+
+/// Reasons for which a commit certificate is rejected
+#[derive(Debug, PartialEq, Eq)]
+pub enum CertificateError {
+    /// The signer is not in the validator set of the height
+    UnknownValidator(Address),
+    /// The validator signed more than once
+    DuplicateSignature(Address),
+    /// The signature is not that of the precommit of the validator for the value
+    InvalidSignature(Address),
+    /// The signers do not hold more than 2/3 of the voting power
+    InsufficientVotingPower { signed: u64, total: u64 },
+}
+
+impl CertificateError {
+    /// Reason of the rejection, as reported by the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnknownValidator(_) => "unknown_validator",
+            Self::DuplicateSignature(_) => "duplicate_signature",
+            Self::InvalidSignature(_) => "invalid_signature",
+            Self::InsufficientVotingPower { .. } => "insufficient_voting_power",
+        }
+    }
+}
+
+impl fmt::Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownValidator(address) => {
+                write!(f, "signer {address} is not in the validator set")
+            }
+            Self::DuplicateSignature(address) => {
+                write!(f, "validator {address} signed more than once")
+            }
+            Self::InvalidSignature(address) => {
+                write!(f, "invalid precommit signature of validator {address}")
+            }
+            Self::InsufficientVotingPower { signed, total } => {
+                write!(
+                    f,
+                    "signers hold {signed} of {total} voting power, not more than 2/3"
+                )
+            }
+        }
+    }
+}
+
+/// Verifies that the precommits of `certificate` are signed by distinct validators of
+/// `validator_set`, the validator set of its height, holding more than 2/3 of its voting power
+pub fn verify_commit_certificate(
+    signing_provider: &K256Provider,
+    validator_set: &ValidatorSet,
+    certificate: &CommitCertificate<EmeraldContext>,
+) -> Result<(), CertificateError> {
+    let mut signers = BTreeSet::new();
+    let mut signed = 0;
+
+    for commit_signature in &certificate.commit_signatures {
+        let address = commit_signature.address;
+        let validator = validator_set
+            .get_by_address(&address)
+            .ok_or(CertificateError::UnknownValidator(address))?;
+
+        if !signers.insert(address) {
+            return Err(CertificateError::DuplicateSignature(address));
+        }
+
+        let precommit = Vote::new_precommit(
+            certificate.height,
+            certificate.round,
+            NilOrVal::Val(certificate.value_id),
+            address,
+        );
+        if !signing_provider.verify(
+            &precommit.to_sign_bytes(),
+            &commit_signature.signature,
+            &validator.public_key,
+        ) {
+            return Err(CertificateError::InvalidSignature(address));
+        }
+
+        signed += validator.voting_power;
+    }
+
+    let total = validator_set.total_voting_power();
+    if 3 * u128::from(signed) <= 2 * u128::from(total) {
+        return Err(CertificateError::InsufficientVotingPower { signed, total });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use malachitebft_core_types::{CommitSignature, Round};
+    use malachitebft_eth_types::{Height, PrivateKey, Validator, Value};
+
+    use super::*;
+
+    fn sign(
+        key: &PrivateKey,
+        certificate: &CommitCertificate<EmeraldContext>,
+    ) -> CommitSignature<EmeraldContext> {
+        let address = Validator::new(key.public_key(), 1).address;
+        let precommit = Vote::new_precommit(
+            certificate.height,
+            certificate.round,
+            NilOrVal::Val(certificate.value_id),
+            address,
+        );
+        CommitSignature {
+            address,
+            signature: K256Provider::new(key.clone()).sign(&precommit.to_sign_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_verify_commit_certificate() {
+        let keys = (1..=4)
+            .map(|i| PrivateKey::from_slice(&[i; 32]).unwrap())
+            .collect::<Vec<_>>();
+        let validator_set =
+            ValidatorSet::new(keys.iter().map(|key| Validator::new(key.public_key(), 1)));
+        let provider = K256Provider::new(keys[0].clone());
+        let verify = |certificate: &CommitCertificate<EmeraldContext>| {
+            verify_commit_certificate(&provider, &validator_set, certificate)
+        };
+
+        let mut certificate = CommitCertificate {
+            height: Height::new(10),
+            round: Round::new(1),
+            value_id: Value::new(Bytes::from_static(b"block")).id(),
+            commit_signatures: vec![],
+        };
+        let signatures = keys
+            .iter()
+            .map(|key| sign(key, &certificate))
+            .collect::<Vec<_>>();
+
+        // 3 of 4 validators are more than 2/3 of the voting power, 2 of 4 are not
+        certificate.commit_signatures = signatures[..3].to_vec();
+        assert_eq!(verify(&certificate), Ok(()));
+        certificate.commit_signatures = signatures[..2].to_vec();
+        assert_eq!(
+            verify(&certificate),
+            Err(CertificateError::InsufficientVotingPower {
+                signed: 2,
+                total: 4
+            })
+        );
+
+        // A signature cannot be counted twice
+        certificate.commit_signatures = vec![
+            signatures[0].clone(),
+            signatures[1].clone(),
+            signatures[1].clone(),
+        ];
+        assert_eq!(
+            verify(&certificate),
+            Err(CertificateError::DuplicateSignature(signatures[1].address))
+        );
+
+        // Signatures of another round do not commit the value
+        let mut other_round = certificate.clone();
+        other_round.round = Round::new(0);
+        let forged = sign(&keys[2], &other_round);
+        certificate.commit_signatures =
+            vec![signatures[0].clone(), signatures[1].clone(), forged.clone()];
+        assert_eq!(
+            verify(&certificate),
+            Err(CertificateError::InvalidSignature(forged.address))
+        );
+
+        // Nor do signatures of validators outside the validator set
+        let outsider = sign(&PrivateKey::from_slice(&[0xff; 32]).unwrap(), &certificate);
+        certificate.commit_signatures = vec![
+            signatures[0].clone(),
+            signatures[1].clone(),
+            signatures[2].clone(),
+            outsider.clone(),
+        ];
+        assert_eq!(
+            verify(&certificate),
+            Err(CertificateError::UnknownValidator(outsider.address))
+        );
+    }
+}
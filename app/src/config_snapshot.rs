@@ -0,0 +1,144 @@
+//! Provenance of the configuration of the node.
+//!
+//! At each startup, the effective configuration, i.e. the emerald config and the consensus
+//! config as loaded with their defaults, is recorded in the store along with the genesis
+//! hash and the height the node starts from. Postmortems can then tell which settings a
+//! node was running with at a given height, from the snapshots served on the
+//! `GET /config` and `GET /config/history` routes of the admin API.
+//!
+//! The secrets are not part of the configuration, which only points to where they are
+//! loaded from.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::{keccak256, B256};
+use malachitebft_eth_cli::config::{Config, EmeraldConfig};
+use malachitebft_eth_types::Height;
+use serde::{Deserialize, Serialize};
+
+use crate::build_info::VERSION;
+
+/// Effective configuration of the node at a startup
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    /// Height the node started from, the first one decided with this configuration
+    pub start_height: u64,
+    /// Unix time of the startup, in seconds
+    pub started_at: u64,
+    /// Version of the node
+    pub version: String,
+    pub genesis_hash: B256,
+    /// Hash of the configurations and the genesis hash, which only changes with the settings
+    pub hash: B256,
+    pub emerald: serde_json::Value,
+    pub consensus: serde_json::Value,
+}
+
+/// Snapshot of a startup without the configurations, as listed in the history
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigSnapshotSummary {
+    pub start_height: u64,
+    pub started_at: u64,
+    pub version: String,
+    pub hash: B256,
+}
+
+impl ConfigSnapshot {
+    pub fn new(
+        emerald: &EmeraldConfig,
+        consensus: &Config,
+        genesis_hash: B256,
+        start_height: Height,
+    ) -> Result<Self, serde_json::Error> {
+        Self::from_values(
+            serde_json::to_value(emerald)?,
+            serde_json::to_value(consensus)?,
+            genesis_hash,
+            start_height,
+        )
+    }
+
+    fn from_values(
+        emerald: serde_json::Value,
+        consensus: serde_json::Value,
+        genesis_hash: B256,
+        start_height: Height,
+    ) -> Result<Self, serde_json::Error> {
+        let hash = keccak256(serde_json::to_vec(&(&emerald, &consensus, genesis_hash))?);
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Ok(Self {
+            start_height: start_height.as_u64(),
+            started_at,
+            version: VERSION.to_string(),
+            genesis_hash,
+            hash,
+            emerald,
+            consensus,
+        })
+    }
+
+    pub fn summary(&self) -> ConfigSnapshotSummary {
+        ConfigSnapshotSummary {
+            start_height: self.start_height,
+            started_at: self.started_at,
+            version: self.version.clone(),
+            hash: self.hash,
+        }
+    }
+}
+
+/// Snapshot of the configuration the node was running with at `height`: the last one
+/// recorded by a startup at or below it, `snapshots` being in the order of the startups
+pub fn effective_at(snapshots: &[ConfigSnapshot], height: Height) -> Option<&ConfigSnapshot> {
+    snapshots
+        .iter()
+        .rev()
+        .find(|snapshot| snapshot.start_height <= height.as_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn snapshot(start_height: u64, min_block_time: &str) -> ConfigSnapshot {
+        ConfigSnapshot::from_values(
+            json!({ "min_block_time": min_block_time }),
+            json!({ "moniker": "node-0" }),
+            B256::ZERO,
+            Height::new(start_height),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hash_only_changes_with_the_settings() {
+        assert_eq!(snapshot(1, "1s").hash, snapshot(10, "1s").hash);
+        assert_ne!(snapshot(1, "1s").hash, snapshot(1, "2s").hash);
+    }
+
+    #[test]
+    fn test_effective_at() {
+        let snapshots = vec![snapshot(1, "1s"), snapshot(10, "2s"), snapshot(10, "3s")];
+
+        assert_eq!(effective_at(&snapshots, Height::new(0)), None);
+        assert_eq!(
+            effective_at(&snapshots, Height::new(9)),
+            Some(&snapshots[0])
+        );
+        // The last startup at a height runs it
+        assert_eq!(
+            effective_at(&snapshots, Height::new(10)),
+            Some(&snapshots[2])
+        );
+        assert_eq!(
+            effective_at(&snapshots, Height::new(100)),
+            Some(&snapshots[2])
+        );
+    }
+}
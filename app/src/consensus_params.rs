@@ -0,0 +1,128 @@
+//! Consensus parameters governed on-chain by the `ConsensusParams` contract.
+//!
+//! The parameters are read at each epoch boundary and take effect from the next height,
+//! so that all nodes apply a change at the same height. Parameters left unset in the
+//! contract fall back to the local configuration of the node.
+
+use core::time::Duration;
+
+use alloy_primitives::{address, Address, U256};
+use color_eyre::eyre;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::{BlockHash, Height};
+use serde_json::json;
+
+/// Address of the contract, predeployed by `emerald-utils genesis`
+const GENESIS_CONSENSUS_PARAMS_ACCOUNT: Address =
+    address!("0x0000000000000000000000000000000000002001");
+
+/// Storage slot of the parameters, whose fields are packed in a single slot
+/// (slot 0 holds the owner of the contract)
+const PARAMS_SLOT: &str = "0x1";
+
+const GET_STORAGE_AT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Parameters read from the contract, `None` when unset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChainParams {
+    pub min_block_time: Option<Duration>,
+    pub max_payload_bytes: Option<u64>,
+    pub epoch_length: u64,
+}
+
+impl ChainParams {
+    /// Decodes the storage slot of the parameters, where Solidity packs the fields of the
+    /// `Params` struct from the lowest-order bytes: `minBlockTimeMs`, `maxPayloadBytes`
+    /// and `epochLength`.
+    fn from_slot(slot: U256) -> Self {
+        let field = |index: usize| slot.as_limbs()[index];
+        let min_block_time_ms = field(0);
+        let max_payload_bytes = field(1);
+
+        Self {
+            min_block_time: (min_block_time_ms != 0)
+                .then(|| Duration::from_millis(min_block_time_ms)),
+            max_payload_bytes: (max_payload_bytes != 0).then_some(max_payload_bytes),
+            epoch_length: field(2),
+        }
+    }
+
+    /// Returns whether the parameters must be read again once `height` is decided.
+    pub fn is_epoch_boundary(&self, height: Height) -> bool {
+        height.as_u64() % self.epoch_length.max(1) == 0
+    }
+
+    /// Returns the last epoch boundary at or below `height`.
+    pub fn epoch_start(&self, height: Height) -> Height {
+        let height = height.as_u64();
+        Height::new(height - height % self.epoch_length.max(1))
+    }
+}
+
+/// Reads the parameters at the given block. Chains whose genesis predates the contract
+/// have no parameters set, their slot reading as zero.
+pub async fn read_consensus_params_from_contract(
+    engine: &Engine,
+    block_hash: &BlockHash,
+) -> eyre::Result<ChainParams> {
+    let slot: U256 = engine
+        .eth
+        .rpc_request(
+            "eth_getStorageAt",
+            json!([GENESIS_CONSENSUS_PARAMS_ACCOUNT, PARAMS_SLOT, { "blockHash": block_hash }]),
+            GET_STORAGE_AT_TIMEOUT,
+        )
+        .await?;
+
+    Ok(ChainParams::from_slot(slot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(min_block_time_ms: u64, max_payload_bytes: u64, epoch_length: u64) -> U256 {
+        U256::from(min_block_time_ms)
+            | U256::from(max_payload_bytes) << 64
+            | U256::from(epoch_length) << 128
+    }
+
+    #[test]
+    fn test_from_slot() {
+        assert_eq!(ChainParams::from_slot(U256::ZERO), ChainParams::default());
+        assert_eq!(
+            ChainParams::from_slot(slot(500, 1 << 20, 100)),
+            ChainParams {
+                min_block_time: Some(Duration::from_millis(500)),
+                max_payload_bytes: Some(1 << 20),
+                epoch_length: 100,
+            }
+        );
+        assert_eq!(
+            ChainParams::from_slot(slot(0, 4096, 0)),
+            ChainParams {
+                min_block_time: None,
+                max_payload_bytes: Some(4096),
+                epoch_length: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_epochs() {
+        let params = ChainParams {
+            epoch_length: 10,
+            ..Default::default()
+        };
+        assert!(params.is_epoch_boundary(Height::new(0)));
+        assert!(params.is_epoch_boundary(Height::new(20)));
+        assert!(!params.is_epoch_boundary(Height::new(21)));
+        assert_eq!(params.epoch_start(Height::new(29)), Height::new(20));
+        assert_eq!(params.epoch_start(Height::new(30)), Height::new(30));
+
+        // Without an epoch length, the parameters are read at every height
+        let params = ChainParams::default();
+        assert!(params.is_epoch_boundary(Height::new(7)));
+        assert_eq!(params.epoch_start(Height::new(7)), Height::new(7));
+    }
+}
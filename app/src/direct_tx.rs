@@ -0,0 +1,288 @@
+//! Direct submission of transactions to the proposer, for latency-sensitive clients.
+//!
+//! A transaction sent to any node reaches the pool of the proposer through the gossip of
+//! transactions between the execution clients, which delays its inclusion. When
+//! `direct_tx` is set, the node serves an authenticated JSON-RPC endpoint accepting
+//! `eth_sendRawTransaction` requests, single or batched, which it forwards right away to
+//! its own execution client while it proposes the current round or the next height.
+//!
+//! Other nodes do not submit the transactions, and answer with a `307 Temporary Redirect`
+//! to the endpoint of the proposer of the next height, as listed in `validator_endpoints`.
+//! The JSON-RPC error of the response tells the height, round and proposers as well, for
+//! the clients which do not follow redirects:
+//!
+//! ```json
+//! "error": {
+//!   "code": -32001,
+//!   "message": "Not the next proposer",
+//!   "data": { "height": 42, "round": 0, "proposer": "0x...", "nextProposer": "0x...", "url": "https://..." }
+//! }
+//! ```
+
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use malachitebft_eth_cli::http::{self, EndpointSecurity};
+use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use malachitebft_eth_types::Address;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, warn};
+
+use crate::metrics::ProposerMetrics;
+use crate::node_status::{SharedNodeStatus, UpcomingProposers};
+
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Standard JSON-RPC error code for internal errors
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Standard JSON-RPC error code for unsupported methods
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Error code of the requests made to a node which is not the next proposer
+const NOT_PROPOSER: i64 = -32001;
+
+#[derive(Clone)]
+struct Submitter {
+    eth: Arc<EthereumRPC>,
+    /// Address of this node
+    address: Address,
+    node_status: SharedNodeStatus,
+    validator_endpoints: Arc<BTreeMap<Address, String>>,
+    metrics: ProposerMetrics,
+}
+
+/// Where the transactions sent to this node go
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Route {
+    /// Submitted to the execution client of this node
+    Submit,
+    /// Redirected to the next proposer, at its endpoint if known
+    Redirect {
+        proposers: UpcomingProposers,
+        url: Option<String>,
+    },
+    /// The proposers are not known yet
+    Unavailable,
+}
+
+impl Route {
+    fn new(
+        address: Address,
+        proposers: Option<UpcomingProposers>,
+        validator_endpoints: &BTreeMap<Address, String>,
+    ) -> Self {
+        let Some(proposers) = proposers else {
+            return Self::Unavailable;
+        };
+
+        // The proposer of the current round may not have built its value yet
+        if proposers.current == address || proposers.next == address {
+            return Self::Submit;
+        }
+
+        Self::Redirect {
+            proposers,
+            url: validator_endpoints.get(&proposers.next).cloned(),
+        }
+    }
+}
+
+#[tracing::instrument(name = "direct_tx", skip_all)]
+pub async fn serve(
+    listen_addr: SocketAddr,
+    eth: EthereumRPC,
+    address: Address,
+    node_status: SharedNodeStatus,
+    validator_endpoints: BTreeMap<Address, String>,
+    metrics: ProposerMetrics,
+    security: EndpointSecurity,
+) {
+    let submitter = Submitter {
+        eth: Arc::new(eth),
+        address,
+        node_status,
+        validator_endpoints: Arc::new(validator_endpoints),
+        metrics,
+    };
+
+    let app = Router::new().route("/", post(handle)).with_state(submitter);
+
+    info!(
+        address = %listen_addr,
+        tls = security.is_tls(),
+        "Serving direct transaction endpoint"
+    );
+    if let Err(e) = http::serve(listen_addr, app, security).await {
+        error!("Direct transaction endpoint failed: {e}");
+    }
+}
+
+async fn handle(State(submitter): State<Submitter>, Json(request): Json<Value>) -> Response {
+    let route = Route::new(
+        submitter.address,
+        submitter.node_status.upcoming_proposers(),
+        &submitter.validator_endpoints,
+    );
+
+    let requests = match &request {
+        Value::Array(requests) => requests.as_slice(),
+        request => core::slice::from_ref(request),
+    };
+
+    let (status, url, responses) = match route {
+        Route::Submit => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(submitter.submit(request).await);
+            }
+            (StatusCode::OK, None, responses)
+        }
+        Route::Redirect { proposers, url } => {
+            submitter
+                .metrics
+                .inc_direct_txs("redirected", requests.len());
+            debug!(next_proposer = %proposers.next, ?url, "Redirecting transactions to the next proposer");
+
+            let data = json!({
+                "height": proposers.height.as_u64(),
+                "round": proposers.round.as_i64(),
+                "proposer": proposers.current.to_string(),
+                "nextProposer": proposers.next.to_string(),
+                "url": url,
+            });
+            let responses = requests
+                .iter()
+                .map(|request| {
+                    error_response(request, NOT_PROPOSER, "Not the next proposer", data.clone())
+                })
+                .collect();
+            let status = match url {
+                Some(_) => StatusCode::TEMPORARY_REDIRECT,
+                None => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            (status, url, responses)
+        }
+        Route::Unavailable => {
+            let responses = requests
+                .iter()
+                .map(|request| {
+                    error_response(
+                        request,
+                        NOT_PROPOSER,
+                        "The proposers are not known yet",
+                        Value::Null,
+                    )
+                })
+                .collect();
+            (StatusCode::SERVICE_UNAVAILABLE, None, responses)
+        }
+    };
+
+    let body = match request {
+        Value::Array(_) => Value::Array(responses),
+        _ => responses.into_iter().next().unwrap_or(Value::Null),
+    };
+
+    match url {
+        Some(url) => (status, [(header::LOCATION, url)], Json(body)).into_response(),
+        None => (status, Json(body)).into_response(),
+    }
+}
+
+impl Submitter {
+    /// Forwards a request to the execution client, if it submits a transaction
+    async fn submit(&self, request: &Value) -> Value {
+        let method = request.get("method").and_then(Value::as_str);
+        if method != Some("eth_sendRawTransaction") {
+            self.metrics.inc_direct_txs("rejected", 1);
+            return error_response(
+                request,
+                METHOD_NOT_FOUND,
+                "Only eth_sendRawTransaction is supported",
+                Value::Null,
+            );
+        }
+
+        match self.eth.forward(request, FORWARD_TIMEOUT).await {
+            Ok(response) => {
+                self.metrics.inc_direct_txs("submitted", 1);
+                response
+            }
+            Err(e) => {
+                warn!("Failed to submit a transaction to the execution client: {e}");
+                self.metrics.inc_direct_txs("failed", 1);
+                error_response(request, INTERNAL_ERROR, &e.to_string(), Value::Null)
+            }
+        }
+    }
+}
+
+fn error_response(request: &Value, code: i64, message: &str, data: Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let mut error = json!({ "code": code, "message": message });
+    if !data.is_null() {
+        error["data"] = data;
+    }
+
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_app_channel::app::types::core::Round;
+    use malachitebft_eth_types::Height;
+
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let address = |byte| Address::new([byte; 20]);
+        let proposers = |current, next| UpcomingProposers {
+            height: Height::new(10),
+            round: Round::new(1),
+            current: address(current),
+            next: address(next),
+        };
+        let endpoints = BTreeMap::from([(address(2), "https://validator-2:8547".to_string())]);
+        let route = |proposers| Route::new(address(1), proposers, &endpoints);
+
+        assert_eq!(route(None), Route::Unavailable);
+        assert_eq!(route(Some(proposers(1, 2))), Route::Submit);
+        assert_eq!(route(Some(proposers(3, 1))), Route::Submit);
+        assert_eq!(
+            route(Some(proposers(3, 2))),
+            Route::Redirect {
+                proposers: proposers(3, 2),
+                url: Some("https://validator-2:8547".to_string()),
+            }
+        );
+        assert_eq!(
+            route(Some(proposers(2, 3))),
+            Route::Redirect {
+                proposers: proposers(2, 3),
+                url: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_response() {
+        let request = json!({ "jsonrpc": "2.0", "id": 7, "method": "eth_call", "params": [] });
+        assert_eq!(
+            error_response(&request, METHOD_NOT_FOUND, "unsupported", Value::Null),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "error": { "code": METHOD_NOT_FOUND, "message": "unsupported" },
+            })
+        );
+    }
+}
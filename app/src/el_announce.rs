@@ -0,0 +1,336 @@
+//! Announcement of the decided blocks to other execution clients.
+//!
+//! An execution client learns the blocks from its consensus node only, through
+//! `engine_newPayload`, as the execution clients do not gossip blocks since the merge. An
+//! execution client whose consensus node missed a proposal, e.g. one synced from the peers,
+//! may thus report `SYNCING` right after the decision, until its consensus node hands it
+//! the block.
+//!
+//! With `el_announce`, each decided block is also sent with `engine_newPayload` to the
+//! execution clients of the configured endpoints, once the execution client of this node
+//! made it its head. The block is only imported, not made the head, which is left to the
+//! consensus node of each execution client. An execution client missing the parent block
+//! replies `SYNCING`, and downloads the missing blocks from its peers meanwhile.
+//!
+//! The announcements are best effort: they are sent in order by a task per endpoint, off
+//! the path of consensus, and are dropped when an endpoint lags behind.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use alloy_rpc_types_engine::{ExecutionPayloadV3, PayloadStatusEnum};
+use color_eyre::eyre::{self, Context};
+use malachitebft_eth_cli::config::ElAnnounceConfig;
+use malachitebft_eth_engine::engine_rpc::EngineRPC;
+use malachitebft_eth_types::{Block, BlockHash, EngineTimeouts};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::metrics::ElMetrics;
+
+/// Number of blocks queued for each endpoint
+const QUEUE_SIZE: usize = 16;
+
+/// Handle announcing the decided blocks to the execution clients of the endpoints
+pub struct ElAnnouncer {
+    endpoints: Vec<Endpoint>,
+}
+
+struct Endpoint {
+    url: String,
+    sender: mpsc::Sender<ExecutionPayloadV3>,
+}
+
+impl ElAnnouncer {
+    /// Spawns a task per endpoint of `config`, announcing the blocks to it
+    pub fn spawn(
+        config: &ElAnnounceConfig,
+        timeouts: &EngineTimeouts,
+        metrics: ElMetrics,
+    ) -> eyre::Result<Self> {
+        let mut endpoints = Vec::with_capacity(config.endpoints.len());
+        for endpoint in &config.endpoints {
+            let api = EngineRPC::new(
+                Url::parse(&endpoint.engine_url)?,
+                PathBuf::from_str(&endpoint.jwt_token_path)?.as_path(),
+                timeouts.clone(),
+            )
+            .wrap_err_with(|| format!("Invalid el_announce endpoint {}", endpoint.engine_url))?;
+
+            let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+            tokio::spawn(announce(
+                api,
+                endpoint.engine_url.clone(),
+                receiver,
+                metrics.clone(),
+            ));
+
+            endpoints.push(Endpoint {
+                url: endpoint.engine_url.clone(),
+                sender,
+            });
+        }
+
+        Ok(Self { endpoints })
+    }
+
+    /// Announcer without endpoints
+    pub fn disabled() -> Self {
+        Self {
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Queues a decided block for the endpoints, without waiting for its announcement
+    pub fn announce(&self, execution_payload: &ExecutionPayloadV3) {
+        for endpoint in &self.endpoints {
+            if let Err(e) = endpoint.sender.try_send(execution_payload.clone()) {
+                debug!(endpoint = %endpoint.url, "Dropped the announcement of a decided block: {e}");
+            }
+        }
+    }
+}
+
+/// Result of an announcement, in the metrics
+fn result(status: &PayloadStatusEnum) -> &'static str {
+    match status {
+        PayloadStatusEnum::Valid => "valid",
+        PayloadStatusEnum::Accepted => "accepted",
+        PayloadStatusEnum::Syncing => "syncing",
+        PayloadStatusEnum::Invalid { .. } => "invalid",
+    }
+}
+
+async fn announce(
+    api: EngineRPC,
+    url: String,
+    mut blocks: mpsc::Receiver<ExecutionPayloadV3>,
+    metrics: ElMetrics,
+) {
+    while let Some(execution_payload) = blocks.recv().await {
+        let block_hash = execution_payload.payload_inner.payload_inner.block_hash;
+        let parent_hash = execution_payload.payload_inner.payload_inner.parent_hash;
+        let block: Block = match execution_payload.clone().try_into_block() {
+            Ok(block) => block,
+            Err(e) => {
+                warn!(%block_hash, "Failed to convert the decided block to announce it: {e}");
+                continue;
+            }
+        };
+        let versioned_hashes: Vec<BlockHash> =
+            block.body.blob_versioned_hashes_iter().copied().collect();
+
+        let status = api
+            .new_payload(execution_payload, versioned_hashes, parent_hash, vec![])
+            .await;
+        match status {
+            Ok(status) => {
+                debug!(endpoint = %url, %block_hash, status = ?status.status, "Announced the decided block");
+                if status.status.is_invalid() {
+                    warn!(endpoint = %url, %block_hash, "Execution client rejected the decided block as invalid");
+                }
+                metrics.inc_block_announcements(&url, result(&status.status));
+            }
+            Err(e) => {
+                debug!(endpoint = %url, %block_hash, "Failed to announce the decided block: {e}");
+                metrics.inc_block_announcements(&url, "failed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::StatusCode;
+    use malachitebft_eth_cli::config::ElAnnounceEndpoint;
+    use malachitebft_eth_types::B256;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::state::testing::{spawn_execution_client, MockAnswer};
+
+    /// Execution client recording the hashes of the blocks it receives, which fails the
+    /// first `failures` calls and answers the next ones with `status`
+    #[derive(Default)]
+    struct ExecutionClient {
+        status: &'static str,
+        failures: Mutex<usize>,
+        received: Mutex<Vec<String>>,
+    }
+
+    impl ExecutionClient {
+        fn new_payload(&self, request: &Value) -> MockAnswer {
+            assert_eq!(request["method"], "engine_newPayloadV4");
+            {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            }
+
+            let block_hash = request["params"][0]["blockHash"].as_str().unwrap();
+            self.received.lock().unwrap().push(block_hash.to_string());
+
+            Ok(json!({
+                "result": { "status": self.status, "latestValidHash": null, "validationError": null },
+            }))
+        }
+    }
+
+    /// Spawns the execution client, and returns the URL of its engine API
+    async fn endpoint(client: Arc<ExecutionClient>) -> String {
+        spawn_execution_client(move |request| client.new_payload(request))
+            .await
+            .to_string()
+    }
+
+    fn config(dir: &tempfile::TempDir, urls: &[&str]) -> ElAnnounceConfig {
+        let jwt_path = dir.path().join("jwt.hex");
+        std::fs::write(&jwt_path, hex::encode([0x11; 32])).unwrap();
+
+        ElAnnounceConfig {
+            endpoints: urls
+                .iter()
+                .map(|url| ElAnnounceEndpoint {
+                    engine_url: url.to_string(),
+                    jwt_token_path: jwt_path.display().to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn block(byte: u8) -> ExecutionPayloadV3 {
+        let mut block = ExecutionPayloadV3::default();
+        block.payload_inner.payload_inner.block_hash = B256::repeat_byte(byte);
+        block
+    }
+
+    /// Waits until the execution client received `count` blocks, and returns their hashes
+    async fn received(client: &ExecutionClient, count: usize) -> Vec<String> {
+        for _ in 0..100 {
+            let received = client.received.lock().unwrap().clone();
+            if received.len() >= count {
+                return received;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("The execution client did not receive {count} blocks");
+    }
+
+    #[tokio::test]
+    async fn test_blocks_are_announced_to_every_endpoint_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let valid = Arc::new(ExecutionClient {
+            status: "VALID",
+            ..Default::default()
+        });
+        let syncing = Arc::new(ExecutionClient {
+            status: "SYNCING",
+            ..Default::default()
+        });
+        let urls = [
+            endpoint(valid.clone()).await,
+            endpoint(syncing.clone()).await,
+        ];
+
+        let announcer = ElAnnouncer::spawn(
+            &config(&dir, &[&urls[0], &urls[1]]),
+            &EngineTimeouts::default(),
+            ElMetrics::default(),
+        )
+        .unwrap();
+        announcer.announce(&block(1));
+        announcer.announce(&block(2));
+
+        let expected = vec![
+            B256::repeat_byte(1).to_string(),
+            B256::repeat_byte(2).to_string(),
+        ];
+        assert_eq!(received(&valid, 2).await, expected);
+        assert_eq!(received(&syncing, 2).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_failed_announcements_do_not_stop_the_endpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let flaky = Arc::new(ExecutionClient {
+            status: "VALID",
+            failures: Mutex::new(1),
+            ..Default::default()
+        });
+        let url = endpoint(flaky.clone()).await;
+
+        // An unreachable endpoint does not hold back the others
+        let announcer = ElAnnouncer::spawn(
+            &config(&dir, &["http://127.0.0.1:1", &url]),
+            &EngineTimeouts::default(),
+            ElMetrics::default(),
+        )
+        .unwrap();
+        announcer.announce(&block(1));
+        announcer.announce(&block(2));
+
+        // The first block is rejected by the execution client, and not sent again
+        assert_eq!(
+            received(&flaky, 1).await,
+            vec![B256::repeat_byte(2).to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_announcements_are_dropped_when_an_endpoint_lags() {
+        let (sender, mut receiver) = mpsc::channel(QUEUE_SIZE);
+        let announcer = ElAnnouncer {
+            endpoints: vec![Endpoint {
+                url: "http://127.0.0.1:1".to_string(),
+                sender,
+            }],
+        };
+
+        for byte in 0..QUEUE_SIZE as u8 + 2 {
+            announcer.announce(&block(byte));
+        }
+        for byte in 0..QUEUE_SIZE as u8 {
+            let block = receiver.try_recv().unwrap();
+            assert_eq!(
+                block.payload_inner.payload_inner.block_hash,
+                B256::repeat_byte(byte)
+            );
+        }
+        assert!(receiver.try_recv().is_err());
+
+        // Nor does a stopped endpoint fail the announcements
+        drop(receiver);
+        announcer.announce(&block(0));
+        ElAnnouncer::disabled().announce(&block(0));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_endpoint_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let spawned = ElAnnouncer::spawn(
+            &config(&dir, &["not a url"]),
+            &EngineTimeouts::default(),
+            ElMetrics::default(),
+        );
+        assert!(spawned.is_err());
+    }
+
+    #[test]
+    fn test_result_of_announcements() {
+        assert_eq!(result(&PayloadStatusEnum::Valid), "valid");
+        assert_eq!(result(&PayloadStatusEnum::Accepted), "accepted");
+        assert_eq!(result(&PayloadStatusEnum::Syncing), "syncing");
+        assert_eq!(
+            result(&PayloadStatusEnum::Invalid {
+                validation_error: "bad block".to_string()
+            }),
+            "invalid"
+        );
+    }
+}
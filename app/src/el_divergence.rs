@@ -0,0 +1,270 @@
+//! Recovery of an execution client whose chain diverged from the decided blocks.
+//!
+//! `newPayload` rejecting a payload as INVALID with a `latestValidHash` other than the
+//! parent of the payload means that the execution client no longer holds the decided
+//! blocks after `latestValidHash` as valid, e.g. after it lost them or was rolled back.
+//! Every later payload would then be rejected, and the node would stop on each decided
+//! block and again after each restart.
+//!
+//! Instead, the divergence is counted in the `el_divergences` metric, the head of the node
+//! is rolled back to the block of `latestValidHash`, and the blocks decided after it are
+//! replayed from the store to the execution client before the head is restored. If the
+//! recovery fails, e.g. as the execution client rejects the decided blocks themselves, the
+//! value is rejected and the recovery is attempted again at the next divergence detected.
+
+use alloy_rpc_types_engine::{ExecutionPayloadV3, PayloadStatus, PayloadStatusEnum};
+use color_eyre::eyre::{self, eyre, OptionExt};
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_types::{BlockHash, Height, B256};
+use ssz::Decode;
+use tracing::{error, info};
+
+use crate::bootstrap::replay_heights_to_engine;
+use crate::state::State;
+use crate::sync_handler::SyncError;
+
+/// Divergence of the execution client, reported when validating the payload of a height
+#[derive(Copy, Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "Execution client rejected the payload of height {height} with latest valid hash \
+     {latest_valid_hash} instead of its parent {parent_hash}"
+)]
+pub struct ElDivergence {
+    pub height: Height,
+    pub parent_hash: BlockHash,
+    pub latest_valid_hash: BlockHash,
+}
+
+impl ElDivergence {
+    /// Divergence reported by the `status` of the payload of `height` extending `parent_hash`
+    pub fn detect(status: &PayloadStatus, height: Height, parent_hash: BlockHash) -> Option<Self> {
+        if !matches!(status.status, PayloadStatusEnum::Invalid { .. }) {
+            return None;
+        }
+
+        // A zero hash is returned when the invalid block does not descend from a PoS block
+        let latest_valid_hash = status
+            .latest_valid_hash
+            .filter(|hash| *hash != B256::ZERO && *hash != parent_hash)?;
+
+        Some(Self {
+            height,
+            parent_hash,
+            latest_valid_hash,
+        })
+    }
+
+    /// Divergence which made the validation of a payload fail, if any
+    pub fn find(error: &eyre::Report) -> Option<Self> {
+        error.downcast_ref::<Self>().copied()
+    }
+
+    /// Divergence which made the validation of a synced value fail, if any
+    pub fn find_in_sync(error: &SyncError) -> Option<Self> {
+        match error {
+            SyncError::Validation(e) => e.downcast_ref::<Self>().copied(),
+            _ => None,
+        }
+    }
+}
+
+/// Rolls the head of the node back to the latest valid block of the execution client,
+/// and replays the blocks decided after it from the store before restoring the head.
+///
+/// A failure is not fatal to the node, which keeps rejecting the values built on the
+/// diverged blocks, and recovers again at the next divergence detected.
+pub async fn recover(
+    state: &mut State,
+    engine: &Engine,
+    divergence: &ElDivergence,
+) -> eyre::Result<()> {
+    state.metrics.validation.inc_el_divergences();
+
+    let head = state
+        .latest_block
+        .ok_or_eyre("missing latest block in state")?;
+    error!(
+        height = %divergence.height,
+        head = head.block_number,
+        latest_valid_hash = %divergence.latest_valid_hash,
+        "🚨 Execution client diverged from the decided blocks, replaying them from the store"
+    );
+
+    let valid_block = latest_valid_block(state, engine, head.block_number, divergence)
+        .await?
+        .ok_or_else(|| {
+            eyre!(
+                "Latest valid hash {} of the execution client is not a decided block in the store, \
+                 the execution client must be resynced",
+                divergence.latest_valid_hash
+            )
+        })?;
+
+    let valid_height = valid_block.block_number;
+    state.latest_block = Some(valid_block);
+
+    let replayed = replay_heights_to_engine(
+        &state.store,
+        engine,
+        &state.forkchoice,
+        Height::new(valid_height + 1),
+        Height::new(head.block_number),
+        &state.emerald_config,
+    )
+    .await;
+
+    // The head of the node is the latest decided block, even if the replay failed
+    state.latest_block = Some(head);
+    replayed?;
+
+    info!(
+        from = valid_height + 1,
+        to = head.block_number,
+        "Replayed the decided blocks to the execution client after its divergence"
+    );
+
+    Ok(())
+}
+
+/// Decided block with the latest valid hash of the `divergence`, searched from the `head`
+/// down to the earliest decided value which can be replayed
+async fn latest_valid_block(
+    state: &State,
+    engine: &Engine,
+    head: u64,
+    divergence: &ElDivergence,
+) -> eyre::Result<Option<ExecutionBlock>> {
+    let earliest = state
+        .decided_heights()
+        .earliest_unpruned
+        .map_or(1, |height| height.as_u64());
+
+    for height in (earliest.saturating_sub(1).max(1)..=head).rev() {
+        let Some((_, header)) = state
+            .store
+            .get_certificate_and_header(Height::new(height))
+            .await?
+        else {
+            continue;
+        };
+
+        let header = ExecutionPayloadV3::from_ssz_bytes(&header)
+            .map_err(|e| eyre!("Invalid block header at height {height}: {e:?}"))?;
+        let header = &header.payload_inner.payload_inner;
+        if header.block_hash == divergence.latest_valid_hash {
+            return Ok(Some(ExecutionBlock {
+                block_hash: header.block_hash,
+                block_number: header.block_number,
+                parent_hash: header.parent_hash,
+                timestamp: header.timestamp,
+                prev_randao: header.prev_randao,
+            }));
+        }
+    }
+
+    // The execution client may have rolled back to the genesis block
+    if earliest <= 1 {
+        let genesis = engine.eth.get_block_by_number("earliest").await?;
+        return Ok(genesis.filter(|block| block.block_hash == divergence.latest_valid_hash));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use ssz::Encode;
+
+    use super::*;
+    use crate::state::testing::TestNode;
+
+    fn status(status: PayloadStatusEnum, latest_valid_hash: Option<BlockHash>) -> PayloadStatus {
+        PayloadStatus {
+            status,
+            latest_valid_hash,
+        }
+    }
+
+    #[test]
+    fn test_detect_divergence() {
+        let height = Height::new(10);
+        let parent = B256::repeat_byte(9);
+        let older = B256::repeat_byte(5);
+        let invalid = || PayloadStatusEnum::Invalid {
+            validation_error: "invalid".to_string(),
+        };
+
+        assert_eq!(
+            ElDivergence::detect(&status(invalid(), Some(older)), height, parent),
+            Some(ElDivergence {
+                height,
+                parent_hash: parent,
+                latest_valid_hash: older,
+            })
+        );
+
+        // An invalid payload on top of the head of the execution client
+        assert_eq!(
+            ElDivergence::detect(&status(invalid(), Some(parent)), height, parent),
+            None
+        );
+        assert_eq!(
+            ElDivergence::detect(&status(invalid(), Some(B256::ZERO)), height, parent),
+            None
+        );
+        assert_eq!(
+            ElDivergence::detect(&status(invalid(), None), height, parent),
+            None
+        );
+        assert_eq!(
+            ElDivergence::detect(
+                &status(PayloadStatusEnum::Valid, Some(older)),
+                height,
+                parent
+            ),
+            None
+        );
+
+        let report = eyre::Report::from(
+            ElDivergence::detect(&status(invalid(), Some(older)), height, parent).unwrap(),
+        );
+        assert_eq!(
+            ElDivergence::find(&report).map(|d| d.latest_valid_hash),
+            Some(older)
+        );
+        assert_eq!(ElDivergence::find(&eyre!("other error")), None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_recovery_restores_the_head() {
+        let mut node = TestNode::new(1, Height::new(3), |_| {}).await;
+        let valid_hash = B256::repeat_byte(1);
+        let mut payload = ExecutionPayloadV3::default();
+        payload.payload_inner.payload_inner.block_number = 1;
+        payload.payload_inner.payload_inner.block_hash = valid_hash;
+        node.decide(Height::new(1), Bytes::from(payload.as_ssz_bytes()))
+            .await;
+
+        // The decided value of height 2 cannot be replayed, missing from the store
+        let head = ExecutionBlock {
+            block_hash: B256::repeat_byte(2),
+            block_number: 2,
+            parent_hash: valid_hash,
+            timestamp: 0,
+            prev_randao: B256::ZERO,
+        };
+        node.state.latest_block = Some(head);
+        let divergence = ElDivergence {
+            height: Height::new(3),
+            parent_hash: head.block_hash,
+            latest_valid_hash: valid_hash,
+        };
+
+        assert!(recover(&mut node.state, &node.engine, &divergence)
+            .await
+            .is_err());
+        assert_eq!(node.state.latest_block, Some(head));
+    }
+}
@@ -0,0 +1,324 @@
+//! Health of the execution client, polled in the background.
+//!
+//! Every `el_health_interval`, the sync status and the head of the execution client are
+//! polled, and the client is considered:
+//! - ready when it answers and is not syncing,
+//! - syncing when `eth_syncing` reports a sync in progress, up to its highest block,
+//! - unknown when it answers either call with an error, e.g. an RPC error, so that its sync
+//!   status cannot be told,
+//! - unreachable when it cannot be reached or does not answer, as well as until the first poll.
+//!
+//! Each change of state is logged, counted in the `el_state_transitions` metric and
+//! recorded in the event log. The state is consulted before building a proposal, before
+//! rebuilding pruned values from the execution client for syncing peers, and by the
+//! `GET /ready` route of the admin API.
+
+use core::time::Duration;
+use std::sync::{Arc, Mutex};
+
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::error::EngineError;
+use serde::Serialize;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::metrics::ElMetrics;
+
+/// Number of transitions kept until they are recorded in the event log
+const MAX_PENDING_TRANSITIONS: usize = 64;
+
+/// State of the execution client, as of its last poll
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ElState {
+    Ready,
+    Syncing { highest_block: u64 },
+    Unknown,
+    Unreachable,
+}
+
+impl ElState {
+    pub const ALL: [&'static str; 4] = ["ready", "syncing", "unknown", "unreachable"];
+
+    /// Name of the state, in the metrics and the event log
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ready => "ready",
+            Self::Syncing { .. } => "syncing",
+            Self::Unknown => "unknown",
+            Self::Unreachable => "unreachable",
+        }
+    }
+}
+
+/// Change of the state of the execution client
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ElTransition {
+    pub from: ElState,
+    pub to: ElState,
+    /// Head of the execution client when the state changed, if known
+    pub head: Option<u64>,
+}
+
+/// State of the execution client served by the admin API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ElStatus {
+    #[serde(flatten)]
+    pub state: ElState,
+    /// Latest block of the execution client, as of its last successful poll
+    pub head: Option<u64>,
+    /// Time since the state last changed, in seconds
+    pub for_secs: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: ElState,
+    head: Option<u64>,
+    changed_at: Instant,
+    /// Transitions not yet recorded in the event log
+    transitions: Vec<ElTransition>,
+}
+
+/// Health of the execution client shared between its poller, the handlers and the
+/// admin API
+#[derive(Clone, Debug)]
+pub struct SharedElHealth {
+    inner: Arc<Mutex<Inner>>,
+    metrics: ElMetrics,
+}
+
+impl SharedElHealth {
+    pub fn new(metrics: ElMetrics) -> Self {
+        metrics.set_state(ElState::Unreachable.as_str());
+
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                state: ElState::Unreachable,
+                head: None,
+                changed_at: Instant::now(),
+                transitions: Vec::new(),
+            })),
+            metrics,
+        }
+    }
+
+    pub fn state(&self) -> ElState {
+        self.inner.lock().expect("EL health lock poisoned").state
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state() == ElState::Ready
+    }
+
+    pub fn status(&self) -> ElStatus {
+        let inner = self.inner.lock().expect("EL health lock poisoned");
+        ElStatus {
+            state: inner.state,
+            head: inner.head,
+            for_secs: inner.changed_at.elapsed().as_secs(),
+        }
+    }
+
+    /// Records the state of a poll, and the head of the execution client if known.
+    /// Returns the transition if the state changed, a syncing client moving its highest
+    /// block not being one.
+    pub fn update(&self, state: ElState, head: Option<u64>, now: Instant) -> Option<ElTransition> {
+        let mut inner = self.inner.lock().expect("EL health lock poisoned");
+        let from = inner.state;
+        inner.state = state;
+        inner.head = head.or(inner.head);
+
+        if from.as_str() == state.as_str() {
+            return None;
+        }
+
+        let transition = ElTransition {
+            from,
+            to: state,
+            head: inner.head,
+        };
+        inner.changed_at = now;
+        if inner.transitions.len() < MAX_PENDING_TRANSITIONS {
+            inner.transitions.push(transition);
+        }
+
+        self.metrics.set_state(state.as_str());
+        self.metrics
+            .inc_state_transitions(from.as_str(), state.as_str());
+
+        Some(transition)
+    }
+
+    /// Transitions since the last call, to be recorded in the event log
+    pub fn take_transitions(&self) -> Vec<ElTransition> {
+        let mut inner = self.inner.lock().expect("EL health lock poisoned");
+        std::mem::take(&mut inner.transitions)
+    }
+
+    /// Polls the sync status and the head of the execution client, and returns its state
+    pub async fn poll(&self, engine: &Engine) -> ElState {
+        let polled = async {
+            let (syncing, highest_block) = engine.is_syncing().await?;
+            let head = engine.get_latest_block_number().await?;
+            let state = if syncing {
+                ElState::Syncing { highest_block }
+            } else {
+                ElState::Ready
+            };
+            Ok::<_, EngineError>((state, head))
+        };
+
+        let (state, head) = match polled.await {
+            Ok(polled) => polled,
+            Err(e) => {
+                debug!("Failed to poll the execution client: {e}");
+                let state = if e.is_connection_loss() {
+                    ElState::Unreachable
+                } else {
+                    ElState::Unknown
+                };
+                if self.state() != state {
+                    warn!(
+                        state = state.as_str(),
+                        "⚠️  Failed to get the sync status of the execution client: {e}"
+                    );
+                }
+                (state, None)
+            }
+        };
+
+        if let Some(transition) = self.update(state, head, Instant::now()) {
+            let from = transition.from.as_str();
+            match transition.to {
+                ElState::Ready => {
+                    info!(%from, head = ?transition.head, "Execution client is ready");
+                }
+                ElState::Syncing { highest_block } => {
+                    warn!(
+                        %from,
+                        head = ?transition.head,
+                        %highest_block,
+                        "⚠️  Execution client is syncing"
+                    );
+                }
+                ElState::Unknown | ElState::Unreachable => {}
+            }
+        }
+
+        state
+    }
+
+    /// State of the execution client, polled again unless it is ready, so that a client
+    /// which recovered since the last poll is not reported as unavailable
+    pub async fn refresh(&self, engine: &Engine) -> ElState {
+        match self.state() {
+            ElState::Ready => ElState::Ready,
+            _ => self.poll(engine).await,
+        }
+    }
+
+    /// Polls the execution client every `interval`
+    #[tracing::instrument(name = "el_health", skip_all)]
+    pub async fn run(self, engine: Engine, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            self.poll(&engine).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use url::Url;
+
+    use super::*;
+    use crate::state::testing::{engine, spawn_execution_client};
+
+    #[test]
+    fn test_transitions() {
+        let health = SharedElHealth::new(ElMetrics::default());
+        let now = Instant::now();
+        assert_eq!(health.state(), ElState::Unreachable);
+
+        let syncing = ElState::Syncing { highest_block: 10 };
+        assert_eq!(
+            health.update(syncing, Some(5), now),
+            Some(ElTransition {
+                from: ElState::Unreachable,
+                to: syncing,
+                head: Some(5),
+            })
+        );
+
+        // Moving the highest block of the sync is not a transition
+        let syncing_further = ElState::Syncing { highest_block: 20 };
+        assert_eq!(health.update(syncing_further, Some(8), now), None);
+        assert_eq!(health.state(), syncing_further);
+
+        // The last known head is kept while the client is unreachable
+        assert_eq!(
+            health.update(ElState::Unreachable, None, now),
+            Some(ElTransition {
+                from: syncing_further,
+                to: ElState::Unreachable,
+                head: Some(8),
+            })
+        );
+        assert!(!health.is_ready());
+
+        health.update(ElState::Ready, Some(20), now);
+        assert!(health.is_ready());
+        assert_eq!(health.status().head, Some(20));
+
+        assert_eq!(
+            health
+                .take_transitions()
+                .iter()
+                .map(|transition| transition.to.as_str())
+                .collect::<Vec<_>>(),
+            vec!["syncing", "unreachable", "ready"]
+        );
+        assert!(health.take_transitions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_poll_tells_failures_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let health = SharedElHealth::new(ElMetrics::default());
+
+        // Answering with an error: the sync status is unknown, not that it is not syncing
+        let url = spawn_execution_client(|_| {
+            Ok(json!({ "error": { "code": -32601, "message": "Method not found" } }))
+        })
+        .await;
+        assert_eq!(
+            health.poll(&engine(url, dir.path())).await,
+            ElState::Unknown
+        );
+        assert!(!health.is_ready());
+
+        // Not answering at all
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        assert_eq!(
+            health.poll(&engine(url, dir.path())).await,
+            ElState::Unreachable
+        );
+
+        assert_eq!(
+            health
+                .take_transitions()
+                .iter()
+                .map(|transition| transition.to.as_str())
+                .collect::<Vec<_>>(),
+            vec!["unknown", "unreachable"]
+        );
+    }
+}
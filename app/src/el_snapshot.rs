@@ -0,0 +1,494 @@
+//! Start of a node from a snapshot of its execution client.
+//!
+//! A new node whose execution client was restored from a snapshot, e.g. of Reth, would
+//! otherwise replay or sync every height from genesis. With `el_snapshot` set, and while the
+//! store holds no decided value, the block of the execution client at `trusted_height` is
+//! trusted as decided once its hash matches `trusted_hash`: consensus starts at the next
+//! height, and syncs from there.
+//!
+//! The certificates and block headers of the heights up to `trusted_height`, within
+//! `num_certificates_to_retain`, are then backfilled in the background from the admin API
+//! of the `backfill_peers` (`GET /certificates?from=A&to=B`), so that the node can serve
+//! them to syncing peers, their values being rebuilt from the execution client. Each header
+//! must be the parent of the one above it, starting from `trusted_hash`, and each
+//! certificate must be for the value rebuilt from the header and the execution client,
+//! signed by the validator set of the validator manager contract at the parent block.
+//!
+//! Under a power change limit, the validator set of a height differs from the one of the
+//! contract, and is served along with the certificate: it is trusted once limited towards
+//! the set of the contract at its block, it gives the set of the height above, starting
+//! from the set of `trusted_height + 1`, which the store of such a node must already hold
+//! for it to start from the snapshot.
+//!
+//! The trusted block is stored as the anchor of the node, which starts from it again after
+//! a restart, `el_snapshot` being set or not, until it decides a height above it.
+
+use core::ops::RangeInclusive;
+use core::time::Duration;
+
+use alloy_primitives::Bytes;
+use alloy_rpc_types_engine::ExecutionPayloadV3;
+use color_eyre::eyre::{self, bail, eyre, Context, OptionExt};
+use malachitebft_app_channel::app::types::core::CommitCertificate;
+use malachitebft_eth_cli::cmd::status::admin_url;
+use malachitebft_eth_cli::config::ElSnapshotConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::codec::proto as codec;
+use malachitebft_eth_types::secp256k1::K256Provider;
+use malachitebft_eth_types::{
+    proto, Address, BlockHash, EmeraldContext, Height, PowerChangeLimit, ValidatorSet, Value,
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use ssz::{Decode, Encode};
+use tracing::{info, warn};
+
+use crate::certificate::verify_commit_certificate;
+use crate::forkchoice::FinalizedBlock;
+use crate::payload::reconstruct_execution_payload;
+use crate::store::{DecidedHeights, Store};
+use crate::validators::{limit_power_change, read_validators_from_contract};
+
+/// Number of heights requested from a peer at once
+pub const BACKFILL_BATCH: u64 = 64;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Certificate and block header of a decided height, as served by the admin API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateEntry {
+    pub height: u64,
+    /// Protobuf encoding of the commit certificate
+    pub certificate: Bytes,
+    /// SSZ encoding of the block header
+    pub header: Bytes,
+    /// Validator set of the height, served when the voting power changes are limited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator_set: Option<ValidatorSet>,
+}
+
+impl CertificateEntry {
+    pub fn new(
+        certificate: &CommitCertificate<EmeraldContext>,
+        header: bytes::Bytes,
+        validator_set: Option<ValidatorSet>,
+    ) -> eyre::Result<Self> {
+        let certificate_bytes = codec::encode_certificate(certificate)?.encode_to_vec();
+        Ok(Self {
+            height: certificate.height.as_u64(),
+            certificate: certificate_bytes.into(),
+            header: header.into(),
+            validator_set,
+        })
+    }
+
+    fn decode(&self) -> eyre::Result<(CommitCertificate<EmeraldContext>, ExecutionPayloadV3)> {
+        let certificate = codec::decode_certificate(proto::CommitCertificate::decode(
+            self.certificate.as_ref(),
+        )?)?;
+        let header = ExecutionPayloadV3::from_ssz_bytes(&self.header)
+            .map_err(|e| eyre!("Invalid block header: {e:?}"))?;
+        Ok((certificate, header))
+    }
+}
+
+/// Trusted block of the execution client snapshot the node starts from, rather than from
+/// the latest height of its store: the one of `el_snapshot`, or the `anchor` the node
+/// started from before a restart, while the store holds no decided value, e.g. only the
+/// certificates and block headers backfilled up to the trusted height.
+///
+/// Fails if the store holds decided values below the trusted height, which the node would
+/// not be able to serve after skipping the heights in between.
+pub fn snapshot_to_start_from(
+    snapshot: Option<&ElSnapshotConfig>,
+    anchor: Option<FinalizedBlock>,
+    heights: &DecidedHeights,
+) -> eyre::Result<Option<FinalizedBlock>> {
+    let trusted = snapshot
+        .map(|snapshot| FinalizedBlock {
+            height: Height::new(snapshot.trusted_height),
+            block_hash: snapshot.trusted_hash,
+        })
+        .or(anchor);
+    let Some(trusted) = trusted else {
+        return Ok(None);
+    };
+
+    // The values are only stored once decided, never backfilled
+    let Some(latest) = heights.earliest_unpruned.and(heights.latest) else {
+        return Ok(Some(trusted));
+    };
+    if latest >= trusted.height {
+        return Ok(None);
+    }
+
+    bail!(
+        "The store holds decided values up to height {latest}, below the trusted height \
+         {} of the execution client snapshot: start from an empty store, or unset `el_snapshot`",
+        trusted.height
+    )
+}
+
+/// Heights whose certificate is backfilled, the latest `retain` heights up to the trusted
+/// height, unless the node already decided enough heights since
+pub fn backfill_range(
+    snapshot: &ElSnapshotConfig,
+    latest: Option<Height>,
+    retain: u64,
+) -> Option<RangeInclusive<Height>> {
+    let trusted_height = snapshot.trusted_height;
+    let top = latest.map_or(trusted_height, |latest| latest.as_u64().max(trusted_height));
+    let from = top.saturating_sub(retain.saturating_sub(1)).max(1);
+
+    (retain > 0 && from <= trusted_height).then(|| Height::new(from)..=Height::new(trusted_height))
+}
+
+/// Backfill of the certificates of the heights below the trusted height
+pub struct Backfill {
+    pub store: Store,
+    pub engine: Engine,
+    pub signing_provider: K256Provider,
+    pub snapshot: ElSnapshotConfig,
+    pub auth_token: Option<String>,
+    pub validator_manager_address: Address,
+    /// Power change limit of the genesis, under which the validator set of a height is
+    /// served by the peer along with its certificate
+    pub power_change_limit: Option<PowerChangeLimit>,
+}
+
+impl Backfill {
+    pub async fn run(self, range: RangeInclusive<Height>) {
+        let (from, to) = (*range.start(), *range.end());
+        info!(%from, %to, "Backfilling the certificates of the heights below the execution client snapshot");
+
+        match self.backfill(range).await {
+            Ok(backfilled) => info!(%from, %to, backfilled, "Backfilled the certificates"),
+            Err(e) => warn!(%from, %to, "Failed to backfill the certificates: {e:#}"),
+        }
+    }
+
+    async fn backfill(&self, range: RangeInclusive<Height>) -> eyre::Result<u64> {
+        let client = reqwest::Client::new();
+        let start = range.start().as_u64();
+
+        // Height to backfill next, and the hash its block must have
+        let mut next = range.end().as_u64();
+        let mut expected = self.snapshot.trusted_hash;
+        // Validator set of the height above, under a power change limit
+        let mut above = match self.power_change_limit {
+            Some(_) => Some(self.stored_validator_set(Height::new(next + 1)).await?),
+            None => None,
+        };
+        let mut backfilled = 0;
+
+        while next >= start {
+            // Backfilled before a restart
+            if let Some((_, header)) = self
+                .store
+                .get_certificate_and_header(Height::new(next))
+                .await?
+            {
+                let header = ExecutionPayloadV3::from_ssz_bytes(&header)
+                    .map_err(|e| eyre!("Invalid block header at height {next}: {e:?}"))?;
+                let header = &header.payload_inner.payload_inner;
+                if header.block_hash != expected {
+                    bail!("Stored block header at height {next} is not block {expected}");
+                }
+                expected = header.parent_hash;
+                if above.is_some() {
+                    above = Some(self.stored_validator_set(Height::new(next)).await?);
+                }
+                next -= 1;
+                continue;
+            }
+
+            let from = next.saturating_sub(BACKFILL_BATCH - 1).max(start);
+            let mut progressed = false;
+
+            for peer in &self.snapshot.backfill_peers {
+                let entries = match self.fetch(&client, peer, from, next).await {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        warn!(%peer, "Failed to fetch certificates: {e:#}");
+                        continue;
+                    }
+                };
+
+                for entry in entries.iter().rev().skip_while(|entry| entry.height > next) {
+                    if entry.height != next || next < start {
+                        break;
+                    }
+
+                    let verified = self.verify(entry, expected, above.as_ref()).await;
+                    let (certificate, parent_hash, validator_set) = match verified {
+                        Ok(verified) => verified,
+                        Err(e) => {
+                            warn!(%peer, height = next, "Rejecting a backfilled certificate: {e:#}");
+                            break;
+                        }
+                    };
+
+                    self.store
+                        .store_certificate_and_header(&certificate, entry.header.0.clone())
+                        .await?;
+                    if above.is_some() {
+                        self.store
+                            .store_validator_set(certificate.height, validator_set.clone())
+                            .await?;
+                        above = Some(validator_set);
+                    }
+                    backfilled += 1;
+                    progressed = true;
+
+                    expected = parent_hash;
+                    next -= 1;
+                }
+
+                if progressed {
+                    break;
+                }
+            }
+
+            if !progressed {
+                bail!("No peer served a valid certificate for height {next}");
+            }
+        }
+
+        Ok(backfilled)
+    }
+
+    async fn fetch(
+        &self,
+        client: &reqwest::Client,
+        peer: &str,
+        from: u64,
+        to: u64,
+    ) -> eyre::Result<Vec<CertificateEntry>> {
+        let mut request = client
+            .get(admin_url(peer, "certificates")?)
+            .query(&[("from", from), ("to", to)])
+            .timeout(FETCH_TIMEOUT);
+        if let Some(token) = &self.auth_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let reason = response.text().await.unwrap_or_default();
+            bail!("{status} {reason}");
+        }
+
+        response
+            .json()
+            .await
+            .wrap_err("Failed to parse the certificates")
+    }
+
+    /// Validator set of `height` stored by the node, which the validator sets served for
+    /// the heights below must lead to
+    async fn stored_validator_set(&self, height: Height) -> eyre::Result<ValidatorSet> {
+        self.store
+            .get_validator_set(height)
+            .await?
+            .ok_or_else(|| eyre!("The validator set of height {height} is not stored"))
+    }
+
+    /// Verifies the certificate and header of `entry` against the hash `expected` of its
+    /// block, returning the certificate, the hash of the parent block and the validator set
+    /// that signed the certificate.
+    ///
+    /// Under a power change limit, the validator set served for the height must lead to the
+    /// one of the height `above` once limited towards the set of the contract at its block.
+    async fn verify(
+        &self,
+        entry: &CertificateEntry,
+        expected: BlockHash,
+        above: Option<&ValidatorSet>,
+    ) -> eyre::Result<(CommitCertificate<EmeraldContext>, BlockHash, ValidatorSet)> {
+        let (certificate, header) = entry.decode()?;
+        let height = Height::new(entry.height);
+
+        let block = &header.payload_inner.payload_inner;
+        if certificate.height != height || block.block_number != entry.height {
+            bail!(
+                "Certificate of height {} and block {} served for height {height}",
+                certificate.height,
+                block.block_number
+            );
+        }
+        if block.block_hash != expected {
+            bail!("Block {} is not block {expected}", block.block_hash);
+        }
+        let parent_hash = block.parent_hash;
+
+        // The value is rebuilt as it is when served to syncing peers
+        let body = self
+            .engine
+            .get_payload_bodies_by_range(entry.height, 1)
+            .await?
+            .into_iter()
+            .next()
+            .flatten()
+            .ok_or_eyre("Block body not found in the execution client")?;
+        let payload = reconstruct_execution_payload(header, body);
+        let value = Value::new(payload.as_ssz_bytes().into());
+        if value.id() != certificate.value_id {
+            bail!("Certificate is not for the block of the execution client");
+        }
+
+        let validator_set = match (&self.power_change_limit, above) {
+            (Some(limit), Some(above)) => {
+                let validator_set = entry
+                    .validator_set
+                    .clone()
+                    .ok_or_eyre("No validator set served")?;
+                let target = read_validators_from_contract(
+                    self.engine.eth.url().as_ref(),
+                    &self.validator_manager_address,
+                    &block.block_hash,
+                )
+                .await?;
+                let limited = limit_power_change(height.increment(), &validator_set, target, limit);
+                if &limited != above {
+                    bail!(
+                        "Validator set does not lead to the one of height {}",
+                        height.increment()
+                    );
+                }
+                validator_set
+            }
+            _ => {
+                read_validators_from_contract(
+                    self.engine.eth.url().as_ref(),
+                    &self.validator_manager_address,
+                    &parent_hash,
+                )
+                .await?
+            }
+        };
+        verify_commit_certificate(&self.signing_provider, &validator_set, &certificate)
+            .map_err(|e| eyre!("Invalid certificate: {e}"))?;
+
+        Ok((certificate, parent_hash, validator_set))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_app_channel::app::types::core::Round;
+    use malachitebft_eth_types::B256;
+
+    use super::*;
+
+    fn snapshot(trusted_height: u64) -> ElSnapshotConfig {
+        ElSnapshotConfig {
+            trusted_height,
+            trusted_hash: B256::repeat_byte(1),
+            backfill_peers: vec![],
+            backfill_auth_token: None,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_to_start_from() {
+        let snapshot = snapshot(100);
+        let trusted = FinalizedBlock {
+            height: Height::new(100),
+            block_hash: snapshot.trusted_hash,
+        };
+        let heights =
+            |earliest: Option<u64>, earliest_unpruned: Option<u64>, latest: Option<u64>| {
+                DecidedHeights {
+                    earliest: earliest.map(Height::new),
+                    earliest_unpruned: earliest_unpruned.map(Height::new),
+                    latest: latest.map(Height::new),
+                }
+            };
+        let start = |anchor, heights| snapshot_to_start_from(Some(&snapshot), anchor, &heights);
+
+        assert_eq!(
+            start(None, heights(None, None, None)).unwrap(),
+            Some(trusted)
+        );
+        // Certificates backfilled up to the trusted height before a restart
+        assert_eq!(
+            start(Some(trusted), heights(Some(50), None, Some(99))).unwrap(),
+            Some(trusted)
+        );
+        assert_eq!(
+            start(Some(trusted), heights(Some(50), None, Some(100))).unwrap(),
+            Some(trusted)
+        );
+        // Heights decided since
+        assert_eq!(
+            start(Some(trusted), heights(Some(50), Some(101), Some(120))).unwrap(),
+            None
+        );
+        assert!(start(None, heights(Some(1), Some(1), Some(20))).is_err());
+
+        // Started again from the anchor once `el_snapshot` is unset
+        assert_eq!(
+            snapshot_to_start_from(None, Some(trusted), &heights(Some(50), None, Some(100)))
+                .unwrap(),
+            Some(trusted)
+        );
+        assert_eq!(
+            snapshot_to_start_from(
+                None,
+                Some(trusted),
+                &heights(Some(50), Some(101), Some(101))
+            )
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            snapshot_to_start_from(None, None, &heights(Some(1), Some(1), Some(20))).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_backfill_range() {
+        let snapshot = snapshot(100);
+        let range = |from, to| Some(Height::new(from)..=Height::new(to));
+
+        assert_eq!(backfill_range(&snapshot, None, 10), range(91, 100));
+        assert_eq!(backfill_range(&snapshot, None, 1000), range(1, 100));
+        assert_eq!(
+            backfill_range(&snapshot, Some(Height::new(95)), 10),
+            range(91, 100)
+        );
+        assert_eq!(
+            backfill_range(&snapshot, Some(Height::new(105)), 10),
+            range(96, 100)
+        );
+        assert_eq!(backfill_range(&snapshot, Some(Height::new(110)), 10), None);
+        assert_eq!(backfill_range(&snapshot, None, 0), None);
+    }
+
+    #[test]
+    fn test_certificate_entry() {
+        let value = Value::new(bytes::Bytes::from_static(b"block"));
+        let certificate = CommitCertificate {
+            height: Height::new(7),
+            round: Round::new(0),
+            value_id: value.id(),
+            commit_signatures: vec![],
+        };
+        let header = ExecutionPayloadV3::default();
+
+        let entry =
+            CertificateEntry::new(&certificate, header.as_ssz_bytes().into(), None).unwrap();
+        let json = serde_json::to_string(&entry).unwrap();
+        let entry: CertificateEntry = serde_json::from_str(&json).unwrap();
+
+        let (decoded, decoded_header) = entry.decode().unwrap();
+        assert_eq!(entry.height, 7);
+        assert_eq!(
+            (decoded.height, decoded.value_id),
+            (certificate.height, certificate.value_id)
+        );
+        assert_eq!(decoded_header, header);
+    }
+}
@@ -0,0 +1,261 @@
+//! Append-only log of consensus events, for postmortem analysis.
+//!
+//! Each event is written as one JSON object per line, independently of the tracing logs,
+//! so that the format stays stable and can be consumed by tools, e.g. to check the
+//! executions of a network against the Quint specification in `specs/`:
+//!
+//! ```json
+//! {"timestamp_ms":1718000000000,"node":"node-0","event":"decided","height":12,"round":0,"value_id":"...","block_hash":"0x..."}
+//! ```
+//!
+//! The log file is rotated once it grows above the configured size: `<path>` is renamed
+//! to `<path>.1`, `<path>.1` to `<path>.2`, and so on, and the oldest file is deleted.
+//! Failing to write an event is logged but never interrupts consensus.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::{self, Context};
+use malachitebft_eth_cli::config::EventLogConfig;
+use serde::Serialize;
+use tracing::warn;
+
+/// Consensus event, named after the corresponding action of the specification
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ConsensusReady {
+        height: u64,
+    },
+    StartedRound {
+        height: u64,
+        round: i64,
+        proposer: String,
+    },
+    /// A value was proposed by this node
+    GetValue {
+        height: u64,
+        round: i64,
+        value_id: String,
+    },
+    /// This node gave up on proposing a value, letting the round time out
+    ProposalAbandoned {
+        height: u64,
+        round: i64,
+        reason: String,
+        error: String,
+    },
+    /// A complete proposal was received from a peer
+    ProposalReceived {
+        height: u64,
+        round: i64,
+        proposer: String,
+        value_id: String,
+        valid: bool,
+    },
+    Decided {
+        height: u64,
+        round: i64,
+        value_id: String,
+        block_hash: String,
+    },
+    ForkchoiceUpdated {
+        height: u64,
+        head_block_hash: String,
+        latest_valid_hash: String,
+    },
+    ProcessSyncedValue {
+        height: u64,
+        round: i64,
+        proposer: String,
+        value_id: String,
+        valid: bool,
+    },
+    GetDecidedValue {
+        height: u64,
+        found: bool,
+    },
+    /// The execution client became ready, syncing or unreachable
+    ElStateChanged {
+        from: String,
+        to: String,
+        head: Option<u64>,
+    },
+    /// The block re-executed in shadow-fork mode diverged from the decided one
+    ShadowForkDiverged {
+        height: u64,
+        block_hash: String,
+        shadow_block_hash: String,
+        divergence: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp_ms: u64,
+    node: &'a str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+pub struct EventLog {
+    node: String,
+    writer: Option<Writer>,
+}
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_file_size: u64,
+    max_files: usize,
+}
+
+impl EventLog {
+    /// Opens the event log in append mode, relative paths being resolved against `home_dir`.
+    pub fn open(config: &EventLogConfig, home_dir: &Path, node: String) -> eyre::Result<Self> {
+        let path = home_dir.join(&config.path);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).wrap_err_with(|| {
+                format!("Failed to create event log directory {}", parent.display())
+            })?;
+        }
+
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            node,
+            writer: Some(Writer {
+                path,
+                file,
+                size,
+                max_file_size: config.max_file_size,
+                max_files: config.max_files,
+            }),
+        })
+    }
+
+    /// Event log which discards all events
+    pub fn disabled() -> Self {
+        Self {
+            node: String::new(),
+            writer: None,
+        }
+    }
+
+    pub fn record(&mut self, event: Event) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+
+        let entry = Entry {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            node: &self.node,
+            event: &event,
+        };
+
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(error = %e, "Failed to serialize event");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        if let Err(e) = writer.write_line(&line) {
+            warn!(path = %writer.path.display(), error = %e, "Failed to write to the event log");
+        }
+    }
+}
+
+impl Writer {
+    fn write_line(&mut self, line: &[u8]) -> eyre::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+
+        // Write the whole line at once so that a crash never leaves a partial event
+        // in the middle of the file, only possibly at its end.
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> eyre::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, i);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> eyre::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("Failed to open event log {}", path.display()))
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = EventLogConfig {
+            path: PathBuf::from("events.jsonl"),
+            max_file_size: 200,
+            max_files: 2,
+        };
+
+        let mut log = EventLog::open(&config, dir.path(), "node-0".to_string()).unwrap();
+        for height in 0..20 {
+            log.record(Event::ConsensusReady { height });
+        }
+
+        let path = dir.path().join("events.jsonl");
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.len() <= 200);
+
+        let last: serde_json::Value =
+            serde_json::from_str(content.lines().last().unwrap()).unwrap();
+        assert_eq!(last["event"], "consensus_ready");
+        assert_eq!(last["node"], "node-0");
+        assert_eq!(last["height"], 19);
+    }
+}
@@ -0,0 +1,700 @@
+//! Failover between a primary and a standby validator sharing the same key.
+//!
+//! Both nodes follow consensus, but only the holder of a lease, kept in a file shared by
+//! both nodes, signs with the validator key. The other node runs with a standby key which
+//! is not in the validator set, so that it follows the decided values without voting.
+//!
+//! The holder renews the lease every `renew_interval`. Once it has expired, the lease is
+//! taken over right away by the primary, and after another lease duration by the standby,
+//! so that the primary gets it first when both nodes are up. A node does not switch keys
+//! while running: it stops when it takes over or loses the lease, and must be restarted,
+//! e.g. by systemd, to run with the key of its new role.
+//!
+//! Double signing is prevented by:
+//! - the lease being read and written under an exclusive lock of the lock file next to it,
+//!   so that both nodes never take it at the same time
+//! - each node taking the lease incrementing its generation, which is checked against the
+//!   lease file before each signature with the validator key, refused once the lease was
+//!   taken by the other node or has expired, the vote or proposal being then skipped
+//! - the holder stopping before its lease expires if it fails to renew it
+//! - a node starting with the validator key only if it was the last holder of the lease,
+//!   the messages it signed before stopping being in its write-ahead log
+//! - a node taking over the lease only once it has decided, through consensus, all the
+//!   heights of the messages it saw signed with the validator key by the other node
+//!
+//! The lease expiry is compared to the clock of each node, which must be synchronized, and
+//! the storage shared by both nodes must support file locks, e.g. NFS.
+
+use core::time::Duration;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use malachitebft_app_channel::app::events::{Event as ConsensusEvent, RxEvent};
+use malachitebft_app_channel::app::types::core::{SignedExtension, SignedMessage};
+use malachitebft_app_channel::app::types::SignedConsensusMsg;
+use malachitebft_eth_cli::config::{FailoverConfig, FailoverRole};
+use malachitebft_eth_types::secp256k1::{K256Provider, PublicKey, Signature};
+use malachitebft_eth_types::{Address, EmeraldContext, Proposal, ProposalPart, Vote};
+use malachitebft_signing::{Error as SigningError, SigningProvider, VerificationResult};
+use rustix::fs::FlockOperation;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+use crate::node_status::SharedNodeStatus;
+use crate::store::Store;
+
+/// Holder of the signing lease, its generation and its expiry, in milliseconds since the
+/// Unix epoch
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    pub holder: String,
+    /// Incremented each time the lease is taken, and kept when it is renewed
+    #[serde(default)]
+    pub generation: u64,
+    pub expires_at: u64,
+}
+
+/// Whether `holder` may take or renew the `lease` at `now`, once it has been expired
+/// for `grace` if held by the other node
+fn may_take(lease: Option<&Lease>, holder: &str, now: u64, grace: Duration) -> bool {
+    match lease {
+        None => true,
+        Some(lease) if lease.holder == holder => true,
+        Some(lease) => now >= lease.expires_at.saturating_add(grace.as_millis() as u64),
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Signing lease shared by the nodes of a failover pair
+#[derive(Clone, Debug)]
+pub struct LeaseFile {
+    path: PathBuf,
+    holder: String,
+}
+
+impl LeaseFile {
+    pub fn new(path: PathBuf, holder: String) -> Self {
+        Self { path, holder }
+    }
+
+    pub async fn read(&self) -> io::Result<Option<Lease>> {
+        self.with_lock(FlockOperation::LockShared, |file| file.read_locked())
+            .await
+    }
+
+    /// Takes the lease for `duration` if this node may take it, `grace` after its expiry if
+    /// held by the other node, incrementing its generation, or renews the lease of the
+    /// `held` generation if this node still holds it. Returns the generation of the lease
+    /// held by this node, if any.
+    pub async fn take(
+        &self,
+        held: Option<u64>,
+        duration: Duration,
+        grace: Duration,
+    ) -> io::Result<Option<u64>> {
+        self.with_lock(FlockOperation::LockExclusive, move |file| {
+            let lease = file.read_locked()?;
+            let now = unix_millis();
+            let generation = match held {
+                Some(generation) => {
+                    let holds = lease.is_some_and(|lease| {
+                        lease.holder == file.holder && lease.generation == generation
+                    });
+                    if !holds {
+                        return Ok(None);
+                    }
+                    generation
+                }
+                None => {
+                    if !may_take(lease.as_ref(), &file.holder, now, grace) {
+                        return Ok(None);
+                    }
+                    lease.map_or(1, |lease| lease.generation + 1)
+                }
+            };
+
+            file.write_locked(&Lease {
+                holder: file.holder.clone(),
+                generation,
+                expires_at: now + duration.as_millis() as u64,
+            })?;
+            Ok(Some(generation))
+        })
+        .await
+    }
+
+    /// Whether this node holds the lease of the given generation, and it has not expired
+    pub async fn holds(&self, generation: u64) -> io::Result<bool> {
+        self.with_lock(FlockOperation::LockShared, move |file| {
+            Ok(file.read_locked()?.is_some_and(|lease| {
+                lease.holder == file.holder
+                    && lease.generation == generation
+                    && unix_millis() < lease.expires_at
+            }))
+        })
+        .await
+    }
+
+    /// Runs `f` holding a lock of the lock file next to the lease, which is not replaced
+    /// when the lease is written, unlike the lease file itself
+    async fn with_lock<T, F>(&self, operation: FlockOperation, f: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Self) -> io::Result<T> + Send + 'static,
+    {
+        let file = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let lock = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(file.path.with_extension("lock"))?;
+            rustix::fs::flock(&lock, operation)?;
+            // Released when the lock file is closed
+            f(&file)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    fn read_locked(&self) -> io::Result<Option<Lease>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the lease at once, so that a crash never leaves a partial write
+    fn write_locked(&self, lease: &Lease) -> io::Result<()> {
+        let tmp = self.path.with_extension(format!("{}.tmp", self.holder));
+        let mut file = File::create(&tmp)?;
+        io::Write::write_all(&mut file, &serde_json::to_vec(lease)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Generation of the signing lease held by this node, checked before each signature with
+/// the validator key
+#[derive(Clone, Debug)]
+pub struct SigningFence {
+    lease: LeaseFile,
+    generation: u64,
+}
+
+impl SigningFence {
+    /// Refuses to sign once the node does not hold the lease anymore, or if the lease
+    /// cannot be read, as the other node may then sign. The vote or proposal is then
+    /// skipped, the node stopping once it notices that it lost the lease.
+    async fn check(&self) -> Result<(), SigningError> {
+        match self.lease.holds(self.generation).await {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                error!(
+                    generation = self.generation,
+                    "Not holding the signing lease anymore, refusing to sign"
+                );
+                Err(SigningError::new())
+            }
+            Err(e) => {
+                error!(%e, "Failed to read the signing lease, refusing to sign");
+                Err(SigningError::new())
+            }
+        }
+    }
+}
+
+/// Signing provider of the node, which checks the signing lease before each signature
+/// with the validator key, with failover: the votes and proposals signed by consensus as
+/// well as the parts of the proposals streamed by the application
+#[derive(Debug)]
+pub struct FencedProvider {
+    inner: K256Provider,
+    fence: Option<SigningFence>,
+}
+
+impl FencedProvider {
+    pub fn new(inner: K256Provider, fence: Option<SigningFence>) -> Self {
+        Self { inner, fence }
+    }
+
+    /// Underlying provider, to verify the signatures of other nodes, which requires no lease
+    pub fn provider(&self) -> &K256Provider {
+        &self.inner
+    }
+
+    /// Signs `hash` with the key of the node, e.g. the parts of a proposal, unless the
+    /// fence refuses it
+    pub async fn sign(&self, hash: &[u8]) -> Result<Signature, SigningError> {
+        self.check().await?;
+        Ok(self.inner.sign(hash))
+    }
+
+    async fn check(&self) -> Result<(), SigningError> {
+        match &self.fence {
+            Some(fence) => fence.check().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl SigningProvider<EmeraldContext> for FencedProvider {
+    async fn sign_vote(
+        &self,
+        vote: Vote,
+    ) -> Result<SignedMessage<EmeraldContext, Vote>, SigningError> {
+        self.check().await?;
+        SigningProvider::<EmeraldContext>::sign_vote(&self.inner, vote).await
+    }
+
+    async fn verify_signed_vote(
+        &self,
+        vote: &Vote,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<VerificationResult, SigningError> {
+        SigningProvider::<EmeraldContext>::verify_signed_vote(
+            &self.inner,
+            vote,
+            signature,
+            public_key,
+        )
+        .await
+    }
+
+    async fn sign_proposal(
+        &self,
+        proposal: Proposal,
+    ) -> Result<SignedMessage<EmeraldContext, Proposal>, SigningError> {
+        self.check().await?;
+        SigningProvider::<EmeraldContext>::sign_proposal(&self.inner, proposal).await
+    }
+
+    async fn verify_signed_proposal(
+        &self,
+        proposal: &Proposal,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<VerificationResult, SigningError> {
+        SigningProvider::<EmeraldContext>::verify_signed_proposal(
+            &self.inner,
+            proposal,
+            signature,
+            public_key,
+        )
+        .await
+    }
+
+    async fn sign_proposal_part(
+        &self,
+        proposal_part: ProposalPart,
+    ) -> Result<SignedMessage<EmeraldContext, ProposalPart>, SigningError> {
+        self.check().await?;
+        SigningProvider::<EmeraldContext>::sign_proposal_part(&self.inner, proposal_part).await
+    }
+
+    async fn verify_signed_proposal_part(
+        &self,
+        proposal_part: &ProposalPart,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<VerificationResult, SigningError> {
+        SigningProvider::<EmeraldContext>::verify_signed_proposal_part(
+            &self.inner,
+            proposal_part,
+            signature,
+            public_key,
+        )
+        .await
+    }
+
+    async fn sign_vote_extension(
+        &self,
+        extension: Bytes,
+    ) -> Result<SignedExtension<EmeraldContext>, SigningError> {
+        self.check().await?;
+        SigningProvider::<EmeraldContext>::sign_vote_extension(&self.inner, extension).await
+    }
+
+    async fn verify_signed_vote_extension(
+        &self,
+        extension: &Bytes,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<VerificationResult, SigningError> {
+        SigningProvider::<EmeraldContext>::verify_signed_vote_extension(
+            &self.inner,
+            extension,
+            signature,
+            public_key,
+        )
+        .await
+    }
+}
+
+/// Failover of a node, from the start of the node until it stops to switch keys
+pub struct Failover {
+    config: FailoverConfig,
+    lease: LeaseFile,
+    /// Generation of the lease held by this node, if it signs with the validator key
+    generation: Option<u64>,
+}
+
+impl Failover {
+    /// Decides whether the node starts with the validator key: only if it was the last
+    /// holder of the lease, or if there is no lease yet and it is the primary.
+    pub async fn start(config: FailoverConfig, lease: LeaseFile) -> io::Result<Self> {
+        let may_sign = match lease.read().await? {
+            Some(current) => current.holder == lease.holder,
+            None => config.role == FailoverRole::Primary,
+        };
+
+        // Taken again right away, before consensus starts
+        let generation = if may_sign {
+            lease
+                .take(None, config.lease_duration, Duration::ZERO)
+                .await?
+        } else {
+            None
+        };
+        if let Some(generation) = generation {
+            info!(role = ?config.role, %generation, "Holding the signing lease, signing with the validator key");
+        } else {
+            info!(role = ?config.role, "Not holding the signing lease, following consensus with the standby key");
+        }
+
+        Ok(Self {
+            config,
+            lease,
+            generation,
+        })
+    }
+
+    /// Whether the node signs with the validator key
+    pub fn is_active(&self) -> bool {
+        self.generation.is_some()
+    }
+
+    /// Fence of the signatures with the validator key, if the node signs with it
+    pub fn fence(&self) -> Option<SigningFence> {
+        self.generation.map(|generation| SigningFence {
+            lease: self.lease.clone(),
+            generation,
+        })
+    }
+
+    /// Key of the node while it does not hold the lease
+    pub fn standby_key_file(&self) -> &Path {
+        &self.config.standby_key_file
+    }
+
+    /// Keeps the lease while the node signs with the validator key, or waits to take it over
+    /// otherwise. Returns why the node must stop to switch keys.
+    pub fn spawn(
+        self,
+        events: RxEvent<EmeraldContext>,
+        validator_address: Address,
+        store: Store,
+        node_status: SharedNodeStatus,
+    ) -> FailoverStop {
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let reason = if let Some(generation) = self.generation {
+                self.keep_lease(generation).await
+            } else {
+                let signed_height = Arc::new(AtomicU64::new(0));
+                tokio::spawn(track_signed_heights(
+                    events,
+                    validator_address,
+                    Arc::clone(&signed_height),
+                ));
+                self.take_over(&store, &node_status, &signed_height).await
+            };
+
+            let _ = tx.send(reason);
+        });
+
+        FailoverStop(Some(rx))
+    }
+
+    async fn keep_lease(&self, generation: u64) -> String {
+        let mut renewed_at = tokio::time::Instant::now();
+
+        loop {
+            tokio::time::sleep(self.config.renew_interval).await;
+
+            match self
+                .lease
+                .take(Some(generation), self.config.lease_duration, Duration::ZERO)
+                .await
+            {
+                Ok(Some(_)) => renewed_at = tokio::time::Instant::now(),
+                Ok(None) => {
+                    error!("The signing lease was taken by the other node, stopping");
+                    return "Lost the signing lease".to_string();
+                }
+                Err(e) => {
+                    warn!(%e, "Failed to renew the signing lease");
+
+                    // Stop before the next attempt could come after the expiry
+                    if renewed_at.elapsed() + self.config.renew_interval
+                        >= self.config.lease_duration
+                    {
+                        error!("Failed to renew the signing lease before its expiry, stopping");
+                        return "Failed to renew the signing lease".to_string();
+                    }
+                }
+            }
+        }
+    }
+
+    async fn take_over(
+        &self,
+        store: &Store,
+        node_status: &SharedNodeStatus,
+        signed_height: &AtomicU64,
+    ) -> String {
+        let grace = match self.config.role {
+            FailoverRole::Primary => Duration::ZERO,
+            FailoverRole::Standby => self.config.lease_duration,
+        };
+
+        loop {
+            tokio::time::sleep(self.config.renew_interval).await;
+
+            // Signing at a height where the other node may have signed is not safe, nor
+            // while the node is catching up on heights the other node may have signed
+            let decided = store
+                .max_decided_value_height()
+                .map_or(0, |height| height.as_u64());
+            let status = node_status.get();
+            let signed = signed_height.load(Ordering::Relaxed);
+            if status.participation.is_none() || status.catching_up || decided < signed {
+                continue;
+            }
+
+            match self
+                .lease
+                .take(None, self.config.lease_duration, grace)
+                .await
+            {
+                Ok(Some(generation)) => {
+                    warn!(
+                        %decided,
+                        %signed,
+                        %generation,
+                        "Took over the signing lease, stopping to restart with the validator key"
+                    );
+                    return "Took over the signing lease".to_string();
+                }
+                Ok(None) => {}
+                Err(e) => warn!(%e, "Failed to read the signing lease"),
+            }
+        }
+    }
+}
+
+/// Records the highest height of the consensus messages signed with the validator key,
+/// received from the node holding the lease.
+async fn track_signed_heights(
+    mut events: RxEvent<EmeraldContext>,
+    validator_address: Address,
+    signed_height: Arc<AtomicU64>,
+) {
+    loop {
+        let (address, height) = match events.recv().await {
+            Ok(ConsensusEvent::Received(SignedConsensusMsg::Vote(vote))) => {
+                (vote.validator_address, vote.height)
+            }
+            Ok(ConsensusEvent::Received(SignedConsensusMsg::Proposal(proposal))) => {
+                (proposal.validator_address, proposal.height)
+            }
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(%skipped, "Missed consensus events, the messages of the other node may be missed");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if address == validator_address {
+            signed_height.fetch_max(height.as_u64(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// Reason for which the node must stop to switch keys, never resolved without failover
+pub struct FailoverStop(Option<oneshot::Receiver<String>>);
+
+impl FailoverStop {
+    pub fn disabled() -> Self {
+        Self(None)
+    }
+
+    pub async fn wait(self) -> String {
+        match self.0 {
+            Some(rx) => match rx.await {
+                Ok(reason) => reason,
+                Err(_) => core::future::pending().await,
+            },
+            None => core::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_eth_types::secp256k1::PrivateKey;
+
+    use super::*;
+
+    #[test]
+    fn test_may_take() {
+        let grace = Duration::from_secs(10);
+        let lease = Lease {
+            holder: "primary".to_string(),
+            expires_at: 1_000,
+        };
+
+        assert!(may_take(None, "standby", 0, grace));
+        // The holder renews its lease, even once expired
+        assert!(may_take(Some(&lease), "primary", 5_000, grace));
+        // The other node waits for the grace period after the expiry
+        assert!(!may_take(Some(&lease), "standby", 999, Duration::ZERO));
+        assert!(may_take(Some(&lease), "standby", 1_000, Duration::ZERO));
+        assert!(!may_take(Some(&lease), "standby", 10_999, grace));
+        assert!(may_take(Some(&lease), "standby", 11_000, grace));
+    }
+
+    #[tokio::test]
+    async fn test_lease_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+        let primary = LeaseFile::new(path.clone(), "primary".to_string());
+        let standby = LeaseFile::new(path, "standby".to_string());
+        let duration = Duration::from_secs(60);
+
+        assert_eq!(primary.read().await.unwrap(), None);
+        let taken = primary.take(None, duration, Duration::ZERO).await.unwrap();
+        assert_eq!(taken, Some(1));
+        assert_eq!(
+            standby.take(None, duration, Duration::ZERO).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            primary
+                .take(Some(1), duration, Duration::ZERO)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+        assert!(primary.holds(1).await.unwrap());
+        assert!(!standby.holds(1).await.unwrap());
+
+        // Once expired, the lease is taken over with the next generation
+        primary
+            .write_locked(&Lease {
+                holder: "primary".to_string(),
+                generation: 1,
+                expires_at: unix_millis() - 1,
+            })
+            .unwrap();
+        assert!(!primary.holds(1).await.unwrap());
+        assert_eq!(
+            standby.take(None, duration, Duration::ZERO).await.unwrap(),
+            Some(2)
+        );
+        assert_eq!(standby.read().await.unwrap().unwrap().holder, "standby");
+        assert!(standby.holds(2).await.unwrap());
+
+        // The previous holder cannot renew the lease of its generation anymore
+        assert_eq!(
+            primary
+                .take(Some(1), duration, Duration::ZERO)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(!primary.holds(1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_taken_by_one_node() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+        let duration = Duration::from_secs(60);
+
+        for round in 0..20 {
+            LeaseFile::new(path.clone(), "primary".to_string())
+                .write_locked(&Lease {
+                    holder: "other".to_string(),
+                    generation: round,
+                    expires_at: 0,
+                })
+                .unwrap();
+
+            let takes = ["primary", "standby"].map(|holder| {
+                let lease = LeaseFile::new(path.clone(), holder.to_string());
+                tokio::spawn(async move { lease.take(None, duration, Duration::ZERO).await })
+            });
+            let mut taken = vec![];
+            for take in takes {
+                taken.extend(take.await.unwrap().unwrap());
+            }
+            assert_eq!(taken, vec![round + 1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fenced_provider_refuses_without_lease() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+        let primary = LeaseFile::new(path.clone(), "primary".to_string());
+        let standby = LeaseFile::new(path, "standby".to_string());
+        let duration = Duration::from_secs(60);
+
+        let generation = primary.take(None, duration, Duration::ZERO).await.unwrap();
+        let key = PrivateKey::from_slice(&[1; 32]).unwrap();
+        let provider = FencedProvider::new(
+            K256Provider::new(key.clone()),
+            Some(SigningFence {
+                lease: primary.clone(),
+                generation: generation.unwrap(),
+            }),
+        );
+        let signature = provider.sign(&[0; 32]).await.unwrap();
+        assert!(provider
+            .provider()
+            .verify(&[0; 32], &signature, &key.public_key()));
+
+        // Once the lease was taken over, the signatures are refused rather than stopping
+        primary
+            .write_locked(&Lease {
+                holder: "primary".to_string(),
+                generation: 1,
+                expires_at: 0,
+            })
+            .unwrap();
+        standby.take(None, duration, Duration::ZERO).await.unwrap();
+        assert!(provider.sign(&[0; 32]).await.is_err());
+    }
+}
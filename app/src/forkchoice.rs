@@ -0,0 +1,118 @@
+//! Tracking of the safe and finalized blocks reported to the execution client.
+//!
+//! The head is the last decided block, which is also reported as safe. The finalized
+//! block lags the head by `finalized_block_depth` blocks, or is the head itself when
+//! the depth is 0, since decided blocks are final. The finalized block is persisted,
+//! so that the finalized tag of the execution client never regresses across restarts.
+
+use alloy_rpc_types_engine::ForkchoiceState;
+use malachitebft_eth_types::{BlockHash, Height, B256};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FinalizedBlock {
+    pub height: Height,
+    pub block_hash: BlockHash,
+}
+
+#[derive(Clone, Debug)]
+pub struct Forkchoice {
+    depth: u64,
+    finalized: Option<FinalizedBlock>,
+}
+
+impl Forkchoice {
+    pub fn new(depth: u64, finalized: Option<FinalizedBlock>) -> Self {
+        Self { depth, finalized }
+    }
+
+    /// Returns the height to finalize once `head` is decided, if it is above the
+    /// current finalized block.
+    pub fn next_finalized_height(&self, head: Height) -> Option<Height> {
+        let height = Height::new(head.as_u64().checked_sub(self.depth)?);
+        match self.finalized {
+            Some(finalized) if finalized.height >= height => None,
+            _ => Some(height),
+        }
+    }
+
+    pub fn set_finalized(&mut self, finalized: FinalizedBlock) {
+        self.finalized = Some(finalized);
+    }
+
+    /// Forkchoice state to report with the block at `height` as head.
+    ///
+    /// When the head is below the finalized block, e.g. while replaying blocks to the
+    /// execution client, the head is reported as finalized.
+    pub fn state(&self, height: Height, head_block_hash: BlockHash) -> ForkchoiceState {
+        let finalized_block_hash = match self.finalized {
+            Some(finalized) if finalized.height <= height => finalized.block_hash,
+            Some(_) => head_block_hash,
+            None if self.depth == 0 => head_block_hash,
+            // Nothing is finalized yet
+            None => B256::ZERO,
+        };
+
+        ForkchoiceState {
+            head_block_hash,
+            safe_block_hash: head_block_hash,
+            finalized_block_hash,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u64) -> FinalizedBlock {
+        FinalizedBlock {
+            height: Height::new(height),
+            block_hash: B256::with_last_byte(height as u8),
+        }
+    }
+
+    #[test]
+    fn test_finalized_lags_head() {
+        let mut forkchoice = Forkchoice::new(2, None);
+        assert_eq!(forkchoice.next_finalized_height(Height::new(1)), None);
+        assert_eq!(
+            forkchoice
+                .state(Height::new(1), block(1).block_hash)
+                .finalized_block_hash,
+            B256::ZERO
+        );
+
+        assert_eq!(
+            forkchoice.next_finalized_height(Height::new(5)),
+            Some(Height::new(3))
+        );
+        forkchoice.set_finalized(block(3));
+
+        let state = forkchoice.state(Height::new(5), block(5).block_hash);
+        assert_eq!(state.safe_block_hash, block(5).block_hash);
+        assert_eq!(state.finalized_block_hash, block(3).block_hash);
+
+        // Never regresses, e.g. when the depth is increased across a restart
+        let forkchoice = Forkchoice::new(4, forkchoice.finalized);
+        assert_eq!(forkchoice.next_finalized_height(Height::new(6)), None);
+        assert_eq!(
+            forkchoice.next_finalized_height(Height::new(8)),
+            Some(Height::new(4))
+        );
+
+        // Replaying blocks below the finalized one
+        let state = forkchoice.state(Height::new(2), block(2).block_hash);
+        assert_eq!(state.finalized_block_hash, block(2).block_hash);
+    }
+
+    #[test]
+    fn test_instant_finality() {
+        let forkchoice = Forkchoice::new(0, None);
+        let state = forkchoice.state(Height::new(1), block(1).block_hash);
+        assert_eq!(state.finalized_block_hash, block(1).block_hash);
+        assert_eq!(
+            forkchoice.next_finalized_height(Height::new(1)),
+            Some(Height::new(1))
+        );
+    }
+}
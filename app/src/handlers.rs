@@ -0,0 +1,28 @@
+//! Handlers of the messages sent by the consensus engine to the application,
+//! one module per [`AppMsg`](malachitebft_app_channel::AppMsg) variant.
+//!
+//! Each handler takes the message it handles and replies to consensus through the
+//! reply channel of the message, if any. Messages are dispatched to the handlers by
+//! [`process_consensus_message`](crate::app::process_consensus_message).
+
+mod consensus_ready;
+mod decided;
+mod get_decided_value;
+mod get_history_min_height;
+mod get_value;
+mod process_synced_value;
+mod received_proposal_part;
+mod restream_proposal;
+mod started_round;
+mod vote_extension;
+
+pub use consensus_ready::on_consensus_ready;
+pub use decided::on_decided;
+pub use get_decided_value::on_get_decided_value;
+pub use get_history_min_height::on_get_history_min_height;
+pub use get_value::{on_get_value, wait_for_transactions, IdleProposal};
+pub use process_synced_value::on_process_synced_value;
+pub use received_proposal_part::on_received_proposal_part;
+pub use restream_proposal::on_restream_proposal;
+pub use started_round::on_started_round;
+pub use vote_extension::{on_extended_vote, on_verify_vote_extention};
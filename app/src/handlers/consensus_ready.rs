@@ -0,0 +1,124 @@
+use color_eyre::eyre;
+use malachitebft_app_channel::AppMsg;
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::{EmeraldContext, Height};
+use tracing::{error, info, warn};
+
+use crate::bootstrap::{
+    initialize_state_from_el_snapshot, initialize_state_from_existing_block,
+    initialize_state_from_genesis,
+};
+use crate::el_snapshot::snapshot_to_start_from;
+use crate::event_log::Event;
+use crate::shadow_fork;
+use crate::state::State;
+
+/// Handle ConsensusReady messages from the consensus engine
+///
+/// Notifies the application that consensus is ready.
+///
+/// The application MUST reply with a message to instruct
+/// consensus to start at a given height.
+pub async fn on_consensus_ready(
+    consensus_ready: AppMsg<EmeraldContext>,
+    state: &mut State,
+    engine: &Engine,
+    emerald_config: &EmeraldConfig,
+) -> eyre::Result<()> {
+    let AppMsg::ConsensusReady { reply } = consensus_ready else {
+        unreachable!("on_consensus_ready called with non-ConsensusReady message");
+    };
+
+    info!("🟢🟢 Consensus is ready");
+
+    // Node start-up: https://hackmd.io/@danielrachi/engine_api#Node-startup
+    // Check compatibility with execution client
+    let capabilities = engine.check_capabilities().await?;
+
+    let client_version = engine.get_client_version(&capabilities).await?;
+    match &client_version {
+        Some(v) => {
+            info!(name = %v.name, version = %v.version, commit = %v.commit, "Connected to execution client");
+            state.metrics.el.set_client_version(v);
+            state.build_info.set_el_client_version(v.clone());
+        }
+        None => warn!("Execution client does not support engine_getClientVersionV1"),
+    }
+    emerald_config
+        .el_version_policy
+        .check(client_version.as_ref())?;
+
+    // Get latest decided height from local store
+    let latest_height_from_store = state.store.max_decided_value_height();
+    let snapshot = snapshot_to_start_from(
+        emerald_config.el_snapshot.as_ref(),
+        state.store.get_snapshot_anchor().await?,
+        &state.decided_heights(),
+    )?;
+
+    match (snapshot, latest_height_from_store) {
+        // The shadow execution client does not have the decided blocks to catch up with
+        _ if state.shadow_fork.is_some() => {
+            shadow_fork::initialize_state(state, engine).await?;
+        }
+        (Some(trusted), _) => {
+            initialize_state_from_el_snapshot(state, engine, trusted, emerald_config).await?;
+            info!(
+                trusted_height = %trusted.height,
+                trusted_hash = %trusted.block_hash,
+                "Starting from the snapshot of the execution client. Current tip (consensus height): {:?}",
+                state.consensus_height
+            );
+        }
+        (None, Some(h)) => {
+            initialize_state_from_existing_block(state, engine, h, emerald_config).await?;
+            info!(
+                "Starting from existing block at height {:?}. Current tip (consensus height): {:?} ",
+                h,
+                state.consensus_height
+            );
+        }
+        (None, None) => {
+            // Get the genesis block from the execution engine
+            initialize_state_from_genesis(state, engine).await?;
+            info!(
+                "Starting from genesis. Current tip (consensus height): {:?}",
+                state.consensus_height
+            );
+        }
+    }
+
+    // In shadow-fork mode, the execution client follows its own chain
+    let el_head = match &state.shadow_fork {
+        Some(shadow_fork) => shadow_fork.head(),
+        None => state.latest_block,
+    };
+    if let Some(latest_block) = el_head {
+        state.node_status.set_el_head(&latest_block);
+
+        let height = Height::new(latest_block.block_number);
+        state
+            .refresh_base_fee_floor(engine, &latest_block.block_hash)
+            .await;
+        state
+            .load_chain_params(engine, height, &latest_block.block_hash)
+            .await?;
+    }
+
+    state.event_log.record(Event::ConsensusReady {
+        height: state.consensus_height.as_u64(),
+    });
+
+    // We can simply respond by telling the engine to start consensus
+    // at consensus_height (which tracks the tip where consensus will work)
+    let validator_set = state
+        .validator_set_at(engine, state.consensus_height)
+        .await?;
+
+    if reply.send((state.consensus_height, validator_set)).is_err() {
+        error!("Failed to send ConsensusReady reply");
+    }
+
+    Ok(())
+}
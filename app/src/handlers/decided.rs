@@ -0,0 +1,361 @@
+use core::time::Duration;
+
+use alloy_rpc_types_engine::ExecutionPayloadV3;
+use color_eyre::eyre::{self, eyre, OptionExt};
+use malachitebft_app_channel::app::engine::host::Next;
+use malachitebft_app_channel::app::types::core::{Round, Validity};
+use malachitebft_app_channel::AppMsg;
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_types::{Address, EmeraldContext, Height};
+use ssz::Decode;
+use tracing::{debug, error, info};
+
+use crate::block_profile::Stage;
+use crate::certificate::verify_commit_certificate;
+use crate::el_divergence::{self, ElDivergence};
+use crate::event_log::Event;
+use crate::payload::validate_execution_payload;
+use crate::shadow_fork;
+use crate::state::State;
+use crate::validators::{limit_power_change, read_validators_from_contract};
+use crate::webhooks::{WebhookEvent, WebhookValidator};
+
+/// Handle Decided messages from the consensus engine
+///
+/// Notifies the application that consensus has decided on a value.
+///
+/// This message includes a commit certificate containing the ID of
+/// the value that was decided on, the height and round at which it was decided,
+/// and the aggregated signatures of the validators that committed to it.
+/// It also includes to the vote extensions received for that height.
+///
+/// In response to this message, the application MUST send a [`Next`]
+/// message back to consensus, instructing it to either start the next height if
+/// the application was able to commit the decided value, or to restart the current height
+/// otherwise.
+///
+/// If the application does not reply, consensus will stall.
+pub async fn on_decided(
+    decided: AppMsg<EmeraldContext>,
+    state: &mut State,
+    engine: &Engine,
+    emerald_config: &EmeraldConfig,
+) -> eyre::Result<()> {
+    let AppMsg::Decided {
+        certificate, reply, ..
+    } = decided
+    else {
+        unreachable!("on_decided called with non-Decided message");
+    };
+
+    let height = certificate.height;
+    let round = certificate.round;
+    let value_id = certificate.value_id;
+    let signatures = certificate.commit_signatures.len();
+    info!(
+        %height, %round, value = %certificate.value_id,
+        "🟢🟢 Consensus has decided on value"
+    );
+    state.block_profile.reached(height, Stage::Decided);
+
+    // The certificate of a value synced from the peers is verified before the value is
+    // committed, the height being synced again if it is rejected. The validator set of an
+    // old height is read from the contract at its parent block if not in memory anymore.
+    if state.synced_height.is_some_and(|synced| synced >= height) {
+        let validator_set = state.validator_set_at(engine, height).await?;
+
+        if let Err(e) = verify_commit_certificate(
+            state.signing_provider.provider(),
+            &validator_set,
+            &certificate,
+        ) {
+            error!(%height, %round, %e, "Rejecting the commit certificate of a synced value");
+            state
+                .metrics
+                .validation
+                .inc_rejected_certificates(e.as_str());
+
+            if reply.send(Next::Restart(height, validator_set)).is_err() {
+                error!("Failed to send Decided reply");
+            }
+            return Ok(());
+        }
+    }
+
+    // The consensus engine only sends Decided messages for values (proposals)
+    // that were completely received by the local node
+    let block_bytes = state
+        .get_block_data(height, round, value_id)
+        .await
+        .ok_or_eyre("app: certificate should have associated block data")?;
+    debug!("🎁 block size: {:?}, height: {}", block_bytes.len(), height);
+
+    // Decode bytes into execution payload (a block) and get relevant fields
+    let execution_payload = ExecutionPayloadV3::from_ssz_bytes(&block_bytes).unwrap();
+    let block_hash = execution_payload.payload_inner.payload_inner.block_hash;
+    let block_timestamp = execution_payload.timestamp();
+    let block_number = execution_payload.payload_inner.payload_inner.block_number;
+    let block_prev_randao = execution_payload.payload_inner.payload_inner.prev_randao;
+    let parent_block_hash = execution_payload.payload_inner.payload_inner.parent_hash;
+    let tx_count = execution_payload
+        .payload_inner
+        .payload_inner
+        .transactions
+        .len();
+    debug!("🦄 Block at height {height} contains {tx_count} transactions");
+
+    // Sanity check: verify payload.parent_hash == state.latest_block.block_hash
+    let latest_block = state
+        .latest_block
+        .ok_or_eyre("missing latest block in state")?;
+    let latest_block_hash = latest_block.block_hash;
+    // In shadow-fork mode, the first decided block extends the genesis of the network, not
+    // the one of the shadow execution client
+    if state.shadow_fork.is_none() || latest_block.block_number > 0 {
+        assert_eq!(latest_block_hash, parent_block_hash);
+    }
+
+    let latest_valid_hash = if state.shadow_fork.is_some() {
+        // The decided block is re-executed, as its hash commits to the state of the network
+        let latest_valid_hash = shadow_fork::reexecute(
+            state,
+            engine,
+            height,
+            &execution_payload,
+            &emerald_config.retry_config,
+        )
+        .await?;
+        state.block_profile.reached(height, Stage::Validated);
+        state
+            .block_profile
+            .reached(height, Stage::ForkchoiceUpdated);
+        latest_valid_hash
+    } else {
+        // Validate the execution payload (uses cache internally), again once the execution
+        // client recovered if it diverged from the decided blocks
+        let parent = state.latest_block;
+        let metrics = state.metrics.validation.clone();
+        let mut recovered = false;
+        let validity = loop {
+            let result = validate_execution_payload(
+                state.validated_cache_mut(),
+                &block_bytes,
+                height,
+                round,
+                parent.as_ref(),
+                // Decided values are not checked against the fee recipient policy
+                None,
+                engine,
+                &emerald_config.retry_config,
+                &metrics,
+            )
+            .await;
+
+            match result.as_ref().err().and_then(ElDivergence::find) {
+                Some(divergence) if !recovered => {
+                    el_divergence::recover(state, engine, &divergence).await?;
+                    recovered = true;
+                }
+                _ => break result?,
+            }
+        };
+
+        if validity == Validity::Invalid {
+            return Err(eyre!("Block validation failed for hash: {}", block_hash));
+        }
+        state.block_profile.reached(height, Stage::Validated);
+
+        debug!(
+            "💡 Block validated at height {} with hash: {}",
+            height, block_hash
+        );
+
+        // Notify the EL of the new block.
+        // Update the execution head state to this block, and advance the finalized block.
+        state
+            .advance_finalized_block(engine, height, block_hash)
+            .await?;
+        let latest_valid_hash = engine
+            .set_latest_forkchoice_state(
+                state.forkchoice.state(height, block_hash),
+                &emerald_config.retry_config,
+            )
+            .await?;
+        state
+            .block_profile
+            .reached(height, Stage::ForkchoiceUpdated);
+        debug!(
+            "🚀 Forkchoice updated to height {} for block hash={} and latest_valid_hash={}",
+            height, block_hash, latest_valid_hash
+        );
+
+        state.event_log.record(Event::ForkchoiceUpdated {
+            height: height.as_u64(),
+            head_block_hash: block_hash.to_string(),
+            latest_valid_hash: latest_valid_hash.to_string(),
+        });
+
+        // Hand the block to the other execution clients, now that this one made it its head
+        state.el_announcer.announce(&execution_payload);
+
+        latest_valid_hash
+    };
+
+    // Get the new validator set for the next height and update the local state
+    let mut new_validator_set = read_validators_from_contract(
+        engine.eth.url().as_ref(),
+        &state.validator_manager_address,
+        &latest_valid_hash,
+    )
+    .await?;
+    debug!("🌈 Got validator set: {:?}", new_validator_set);
+    if let Some(limit) = &state.power_change_limit {
+        let current = state
+            .get_validator_set(height)
+            .ok_or_eyre("Validator set of the decided height not found")?;
+        new_validator_set =
+            limit_power_change(height.increment(), current, new_validator_set, limit);
+    }
+    if state.get_validator_set(height) != Some(&new_validator_set) {
+        state.webhooks.notify(WebhookEvent::ValidatorSetChanged {
+            height: height.increment().as_u64(),
+            validators: new_validator_set
+                .validators
+                .iter()
+                .map(|validator| WebhookValidator {
+                    address: validator.address.to_string(),
+                    voting_power: validator.voting_power,
+                })
+                .collect(),
+            total_voting_power: new_validator_set.total_voting_power(),
+        });
+    }
+    state.set_validator_set(height.increment(), new_validator_set.clone());
+
+    let decided_block = ExecutionBlock {
+        block_hash,
+        block_number,
+        parent_hash: latest_block_hash,
+        timestamp: block_timestamp, // Note: This was a fix related to the sync reactor
+        prev_randao: block_prev_randao,
+    };
+
+    // Start building the next payload if this node proposes it, before waiting for
+    // min_block_time when committing
+    if emerald_config.optimistic_payload_building {
+        state
+            .start_pending_payload(engine, height.increment(), &decided_block)
+            .await;
+    }
+
+    // The proposals of the previous rounds are pruned once committed
+    let proposer = state.original_proposer(height, round, value_id).await;
+
+    // When that happens, we store the decided value in our store
+    // TODO: we should return an error reply if commit fails
+    state.commit(certificate).await?;
+
+    // The validator set is stored after the decided value, so that it can be limited again
+    // from the one of the decided height after a restart
+    if state.power_change_limit.is_some() {
+        state
+            .store
+            .store_validator_set(height.increment(), new_validator_set)
+            .await?;
+    }
+
+    state.event_log.record(Event::Decided {
+        height: height.as_u64(),
+        round: round.as_i64(),
+        value_id: value_id.to_string(),
+        block_hash: block_hash.to_string(),
+    });
+    state.webhooks.notify(WebhookEvent::DecidedBlock {
+        height: height.as_u64(),
+        round: round.as_i64(),
+        block_hash: block_hash.to_string(),
+        block_number,
+        block_timestamp,
+        tx_count,
+    });
+
+    // Calculate and log per-block statistics
+    let block_time = state.clock.elapsed(state.previous_block_commit_time);
+    let block_time_secs = block_time.as_secs_f64();
+    observe_proposers(state, height, round, proposer, block_time);
+    state
+        .log_block_stats(height, tx_count, block_bytes.len(), block_time_secs)
+        .await?;
+
+    // Update previous_block_commit_time to track when this block was committed
+    // This is used to calculate per-block TPS for the next block
+    state.previous_block_commit_time = state.clock.now();
+
+    // Save the latest block
+    state.latest_block = Some(decided_block);
+
+    let validators = state
+        .get_validator_set(height)
+        .map_or(0, |validator_set| validator_set.validators.len());
+    state
+        .node_status
+        .decided(height, round, signatures, validators, &decided_block);
+
+    // Update consensus_height and consensus_round to track the tip of the blockchain
+    // After committing height H, the tip advances to H+1 where consensus will work next
+    state.consensus_height = height.increment();
+    state.consensus_round = Round::ZERO;
+
+    // The base fee floor and consensus parameters may have been changed by the decided block
+    state
+        .refresh_base_fee_floor(engine, &latest_valid_hash)
+        .await;
+    state
+        .refresh_chain_params(engine, height, &latest_valid_hash)
+        .await;
+
+    state
+        .block_profile
+        .committed(height, std::time::Instant::now());
+
+    // And then we instruct consensus to start the next height
+    if reply
+        .send(Next::Start(
+            state.consensus_height,
+            state
+                .get_validator_set(state.consensus_height)
+                .ok_or_eyre("Validator set not found for height {state.consensus_height}")?
+                .clone(),
+        ))
+        .is_err()
+    {
+        error!("Failed to send Decided reply");
+    }
+
+    Ok(())
+}
+
+/// Reports the decided block, for the proposer of its value, and the rounds of the height
+/// which failed before it in the per-proposer metrics
+fn observe_proposers(
+    state: &State,
+    height: Height,
+    round: Round,
+    proposer: Option<Address>,
+    block_time: Duration,
+) {
+    if let Some(proposer) = proposer {
+        state
+            .metrics
+            .blocks
+            .observe_decided_block(&proposer, block_time);
+    }
+
+    for failed in 0..round.as_u32().unwrap_or_default() {
+        if let Some(proposer) = state.proposer_of(height, Round::new(failed)) {
+            state.metrics.blocks.inc_failed_rounds(&proposer);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::time::Instant;
+
+use color_eyre::eyre;
+use malachitebft_app_channel::app::types::sync::RawDecidedValue;
+use malachitebft_app_channel::AppMsg;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::{EmeraldContext, Height};
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+use crate::event_log::Event;
+use crate::metrics::SyncMetrics;
+use crate::state::State;
+use crate::store::{Store, StoreError};
+use crate::sync_handler::{
+    get_decided_value_for_sync, HeightUnavailable, SharedRebuiltValueCache, SyncError,
+};
+use crate::sync_limiter::{priority_below, RebuildSlot};
+use crate::sync_stats::SharedSyncStats;
+
+/// Handle GetDecidedValue messages from the consensus engine
+///
+/// Requests a previously decided value from the application's storage.
+///
+/// The application MUST respond with that value if available, or `None` otherwise.
+pub async fn on_get_decided_value(
+    get_decided_value: AppMsg<EmeraldContext>,
+    state: &mut State,
+    engine: &Engine,
+) -> eyre::Result<()> {
+    let AppMsg::GetDecidedValue { height, reply } = get_decided_value else {
+        unreachable!("on_decided_value called with non-GetDecidedValue message");
+    };
+
+    info!(%height, "🟢🟢 GetDecidedValue");
+
+    let decided_heights = state.decided_heights();
+    // Check if requested height is beyond our consensus height
+    let served = if !decided_heights.contains(height) || height >= state.consensus_height {
+        info!(%height, consensus_height = %state.consensus_height, "Requested height is >= consensus height or < earliest_height_available.");
+        Err(HeightUnavailable::NotDecided)
+    } else if !admit(state, height) {
+        Err(HeightUnavailable::RateLimited)
+    } else {
+        // Without any decided value, e.g. with only the certificates backfilled after
+        // starting from a snapshot of the execution client, all the heights are rebuilt
+        let earliest_unpruned = decided_heights
+            .earliest_unpruned
+            .unwrap_or(state.consensus_height);
+        let el_retained_from = state.el_retained_from();
+        let rebuilt = height < earliest_unpruned && !state.rebuilt_values.contains(height);
+        if rebuilt && state.shadow_fork.is_some() {
+            // The execution client does not have the blocks of the network
+            Err(HeightUnavailable::ShadowFork)
+        } else if rebuilt && !state.el_health.is_ready() {
+            // Asking an execution client which is syncing or unreachable would fail, or
+            // stall the node until it times out
+            Err(HeightUnavailable::ElNotReady)
+        } else if rebuilt {
+            // Rebuilding the value waits for the execution client, so it is done in the
+            // background, which replies to the peer
+            match state.rebuild_slots.try_acquire() {
+                Some(slot) => {
+                    let rebuild = Rebuild {
+                        store: state.store.clone(),
+                        engine: engine.clone(),
+                        cache: state.rebuilt_values.clone(),
+                        metrics: state.metrics.sync.clone(),
+                        sync_stats: state.sync_stats.clone(),
+                    };
+                    tokio::spawn(rebuild.run(
+                        height,
+                        earliest_unpruned,
+                        el_retained_from,
+                        reply,
+                        slot,
+                    ));
+                    state.report_served_heights();
+                    return Ok(());
+                }
+                None => Err(HeightUnavailable::Busy),
+            }
+        } else {
+            match get_decided_value_for_sync(
+                &state.store,
+                engine,
+                &state.rebuilt_values,
+                height,
+                earliest_unpruned,
+                el_retained_from,
+            )
+            .await
+            {
+                Ok(served) => served,
+                Err(SyncError::Storage(e))
+                    if matches!(
+                        e.downcast_ref::<StoreError>(),
+                        Some(StoreError::Corrupted { .. })
+                    ) =>
+                {
+                    error!(%height, "Not serving a corrupted decided value: {e}");
+                    Err(HeightUnavailable::Corrupted)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+
+    // Replying without a value lets the peer sync this height from another node
+    let raw_decided_value = record_served(
+        &state.metrics.sync,
+        &state.sync_stats,
+        height,
+        served,
+        false,
+    );
+    state.report_served_heights();
+
+    state.event_log.record(Event::GetDecidedValue {
+        height: height.as_u64(),
+        found: raw_decided_value.is_some(),
+    });
+
+    if reply.send(raw_decided_value).is_err() {
+        error!("Failed to send GetDecidedValue reply");
+    }
+
+    Ok(())
+}
+
+/// Rebuild of a decided value pruned from the store, from its block header and the body
+/// of its block returned by the execution client
+struct Rebuild {
+    store: Store,
+    engine: Engine,
+    cache: SharedRebuiltValueCache,
+    metrics: SyncMetrics,
+    sync_stats: SharedSyncStats,
+}
+
+impl Rebuild {
+    /// Rebuilds the value of `height` and replies with it, the failures being answered
+    /// without a value as they only concern the peer
+    async fn run(
+        self,
+        height: Height,
+        earliest_unpruned: Height,
+        el_retained_from: Option<Height>,
+        reply: oneshot::Sender<Option<RawDecidedValue<EmeraldContext>>>,
+        slot: RebuildSlot,
+    ) {
+        let served = match get_decided_value_for_sync(
+            &self.store,
+            &self.engine,
+            &self.cache,
+            height,
+            earliest_unpruned,
+            el_retained_from,
+        )
+        .await
+        {
+            Ok(served) => served,
+            Err(e @ SyncError::ExecutionClient(_)) => {
+                warn!(%height, "Failed to rebuild the decided value: {e}");
+                Err(HeightUnavailable::ElNotReady)
+            }
+            Err(e) => {
+                error!(%height, "Failed to rebuild the decided value: {e}");
+                Err(HeightUnavailable::Corrupted)
+            }
+        };
+
+        let raw_decided_value =
+            record_served(&self.metrics, &self.sync_stats, height, served, true);
+        slot.complete(height, raw_decided_value.is_some());
+
+        if reply.send(raw_decided_value).is_err() {
+            error!("Failed to send GetDecidedValue reply");
+        }
+    }
+}
+
+/// Records what is served for `height` in the metrics and the statistics
+fn record_served(
+    metrics: &SyncMetrics,
+    sync_stats: &SharedSyncStats,
+    height: Height,
+    served: Result<RawDecidedValue<EmeraldContext>, HeightUnavailable>,
+    rebuilt: bool,
+) -> Option<RawDecidedValue<EmeraldContext>> {
+    match served {
+        Ok(raw_decided_value) => {
+            let bytes = raw_decided_value.value_bytes.len() as u64;
+            metrics.inc_served_values(bytes);
+            sync_stats.served(bytes, rebuilt, Instant::now());
+            Some(raw_decided_value)
+        }
+        Err(reason) => {
+            info!(%height, reason = reason.as_str(), "Height unavailable for sync");
+            metrics.inc_unavailable_heights(reason);
+            sync_stats.unavailable(reason, Instant::now());
+            None
+        }
+    }
+}
+
+/// Whether the request for `height` is within the rate limit of the values served to
+/// syncing peers, if any
+fn admit(state: &mut State, height: Height) -> bool {
+    let priority_below = priority_below(state.served_heights());
+    let now = Instant::now();
+    let recent_bytes = state.sync_stats.recent_bytes(now);
+    state
+        .sync_limiter
+        .as_mut()
+        .is_none_or(|limiter| limiter.admit(height, priority_below, recent_bytes, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::el_health::ElState;
+    use crate::state::testing::TestNode;
+    use crate::sync_limiter::RebuildSlots;
+
+    async fn get_decided_value(
+        node: &mut TestNode,
+        height: Height,
+    ) -> Option<RawDecidedValue<EmeraldContext>> {
+        let (reply, value) = oneshot::channel();
+        on_get_decided_value(
+            AppMsg::GetDecidedValue { height, reply },
+            &mut node.state,
+            &node.engine,
+        )
+        .await
+        .unwrap();
+        value.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serves_decided_heights_only() {
+        let mut node = TestNode::new(1, Height::new(3), |_| {}).await;
+        for height in 1..3 {
+            node.decide(Height::new(height), Bytes::from_static(b"block"))
+                .await;
+        }
+
+        let served = get_decided_value(&mut node, Height::new(2))
+            .await
+            .expect("Decided height is served");
+        assert_eq!(served.certificate.height, Height::new(2));
+
+        // Neither the height being decided nor the heights above are served
+        assert!(get_decided_value(&mut node, Height::new(3)).await.is_none());
+        assert!(get_decided_value(&mut node, Height::new(4)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pruned_heights_are_rebuilt_in_the_background() {
+        let mut node = TestNode::new(1, Height::new(4), |_| {}).await;
+        for height in 1..4 {
+            node.decide(Height::new(height), Bytes::from_static(b"block"))
+                .await;
+        }
+        // The decided value of height 1 is pruned, its certificate and header are kept
+        node.state
+            .store
+            .prune(u64::MAX, 1, Height::new(3), false)
+            .await
+            .unwrap();
+        node.state
+            .el_health
+            .update(ElState::Ready, Some(3), Instant::now());
+        node.state.rebuild_slots = RebuildSlots::new(1);
+
+        // No value is served above the number of values rebuilt at the same time
+        let slot = node.state.rebuild_slots.try_acquire().unwrap();
+        assert!(get_decided_value(&mut node, Height::new(1)).await.is_none());
+        let unavailable = node.state.sync_stats.summary().total.unavailable;
+        assert_eq!(unavailable.get("busy"), Some(&1));
+        drop(slot);
+
+        // The stored header is not a block header, so the value cannot be rebuilt,
+        // which is answered without a value rather than failing
+        assert!(get_decided_value(&mut node, Height::new(1)).await.is_none());
+        let unavailable = node.state.sync_stats.summary().total.unavailable;
+        assert_eq!(unavailable.get("busy"), Some(&1));
+        assert_eq!(unavailable.values().sum::<u64>(), 2);
+
+        // The slot is released once the value is rebuilt
+        assert_eq!(
+            node.state.rebuild_slots.take_completed(),
+            vec![(Height::new(1), false)]
+        );
+        assert!(node.state.rebuild_slots.try_acquire().is_some());
+    }
+}
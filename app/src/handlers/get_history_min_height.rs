@@ -0,0 +1,58 @@
+use color_eyre::eyre;
+use malachitebft_app_channel::AppMsg;
+use malachitebft_eth_types::EmeraldContext;
+use tracing::error;
+
+use crate::state::State;
+
+/// Handle GetHistoryMinHeight messages from the consensus engine
+///
+/// Requests the earliest height available in the history maintained by the application.
+///
+/// The application MUST respond with its earliest available height.
+pub async fn on_get_history_min_height(
+    get_history_min_height: AppMsg<EmeraldContext>,
+    state: &State,
+) -> eyre::Result<()> {
+    let AppMsg::GetHistoryMinHeight { reply } = get_history_min_height else {
+        unreachable!("on_get_history_min_height called with non-GetHistoryMinHeight message");
+    };
+
+    let min_height = state.get_earliest_height();
+
+    if reply.send(min_height).is_err() {
+        error!("Failed to send GetHistoryMinHeight reply");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use malachitebft_eth_types::Height;
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::state::testing::TestNode;
+
+    async fn min_height(state: &State) -> Height {
+        let (reply, min_height) = oneshot::channel();
+        on_get_history_min_height(AppMsg::GetHistoryMinHeight { reply }, state)
+            .await
+            .unwrap();
+        min_height.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_earliest_decided_height() {
+        let node = TestNode::new(1, Height::new(1), |_| {}).await;
+        assert_eq!(min_height(&node.state).await, Height::default());
+
+        for height in 3..6 {
+            node.decide(Height::new(height), Bytes::from_static(b"block"))
+                .await;
+        }
+        assert_eq!(min_height(&node.state).await, Height::new(3));
+    }
+}
@@ -0,0 +1,578 @@
+use core::time::Duration;
+
+use alloy_rpc_types_engine::{ExecutionPayloadV3, PayloadId};
+use bytes::Bytes;
+use color_eyre::eyre::{self, eyre};
+use malachitebft_app_channel::app::types::core::Round;
+use malachitebft_app_channel::{AppMsg, Channels};
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_engine::payload_builder::PayloadRequest;
+use malachitebft_eth_types::{EmeraldContext, Height};
+use ssz::Encode;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+use crate::block_profile::Stage;
+use crate::el_health::ElState;
+use crate::event_log::Event;
+use crate::payload::{build_payload, check_linkage, BuildFailure};
+use crate::state::State;
+use crate::streaming::publish_stream;
+
+/// Handle GetValue messages from the consensus engine
+///
+/// Requests the application to build a value for consensus to propose.
+///
+/// The application MUST reply to this message with the requested value
+/// within the specified timeout duration.
+pub async fn on_get_value(
+    get_value: AppMsg<EmeraldContext>,
+    state: &mut State,
+    channels: &Channels<EmeraldContext>,
+    engine: &Engine,
+    emerald_config: &EmeraldConfig,
+) -> eyre::Result<()> {
+    let AppMsg::GetValue {
+        height,
+        round,
+        timeout,
+        reply,
+    } = get_value
+    else {
+        unreachable!("on_get_value called with non-GetValue message");
+    };
+
+    // NOTE: We can ignore the timeout as we are building the value right away.
+    // If we were let's say reaping as many txes from a mempool and executing them,
+    // then we would need to respect the timeout and stop at a certain point.
+
+    let start = Instant::now();
+
+    info!(%height, %round, "🟢🟢 Consensus is requesting a value to propose");
+
+    // Here it is important that, if we have previously built a value for this height and round,
+    // we send back the very same value.
+    let (proposal, bytes) = match state.get_previously_built_value(height, round).await? {
+        Some(proposal) => {
+            info!(value = %proposal.value.id(), "Re-using previously built value");
+            // Fetch the block data for the previously built value
+            let bytes = state
+                .store
+                .get_block_data(height, round, proposal.value.id())
+                .await?
+                .ok_or_else(|| eyre!("Block data not found for previously built value"))?;
+            (proposal, bytes)
+        }
+        None => {
+            // Check if the execution client is syncing and behind the consensus height,
+            // or unreachable, e.g. while it restarts
+            let unavailable = match state.el_health.refresh(engine).await {
+                ElState::Syncing { highest_block } if highest_block >= height.as_u64() => {
+                    warn!(
+                        "⚠️  Execution client is syncing (current: {}, target: {}), waiting for timeout",
+                        highest_block,
+                        height.as_u64()
+                    );
+                    state.metrics.proposer.inc_el_syncing();
+                    Some((
+                        "el_syncing",
+                        format!("execution client is syncing up to height {highest_block}"),
+                    ))
+                }
+                ElState::Unreachable => {
+                    warn!("⚠️  Execution client is unreachable, waiting for timeout");
+                    Some((
+                        "el_unreachable",
+                        "execution client is unreachable".to_string(),
+                    ))
+                }
+                ElState::Unknown => {
+                    warn!(
+                        "⚠️  Sync status of the execution client is unknown, waiting for timeout"
+                    );
+                    Some((
+                        "el_sync_status_unknown",
+                        "execution client failed to answer its sync status".to_string(),
+                    ))
+                }
+                _ => None,
+            };
+            if let Some((reason, error)) = unavailable {
+                state.event_log.record(Event::ProposalAbandoned {
+                    height: height.as_u64(),
+                    round: round.as_i64(),
+                    reason: reason.to_string(),
+                    error,
+                });
+                abandon_until(reply, start + timeout * 2);
+                return Ok(());
+            } else {
+                if let Some(max_idle_block_interval) = emerald_config.max_idle_block_interval {
+                    // Deferred rather than waited for, so that the other messages of
+                    // consensus keep being handled in the meantime
+                    let deadline = state.previous_block_commit_time + max_idle_block_interval;
+                    if Instant::now() < deadline && !has_pending_transactions(&engine.eth).await {
+                        debug!(%height, %round, "No transactions since the previous block, deferring the proposal");
+                        state.idle_proposal = Some(IdleProposal {
+                            msg: AppMsg::GetValue {
+                                height,
+                                round,
+                                timeout,
+                                reply,
+                            },
+                            deadline,
+                        });
+                        return Ok(());
+                    }
+                }
+
+                // If we have not previously built a value for that very same height and round,
+                // we need to create a new value to propose and send it back to consensus.
+                info!("Building a new value to propose");
+                state.block_profile.reached(height, Stage::BuildStarted);
+                // We need to ask the execution engine for a new value to
+                // propose. Then we send it back to consensus.
+
+                let latest_block = state.latest_block.expect("Head block hash is not set");
+
+                let payload_key = state
+                    .built_payload_cache
+                    .key(latest_block.block_hash, emerald_config.fee_recipient);
+                let cached_payload = payload_key
+                    .and_then(|key| state.built_payload_cache.get(&key))
+                    .cloned();
+
+                let execution_payload = match cached_payload {
+                    Some(payload) => {
+                        info!("Re-using payload built on the same parent block");
+                        state.metrics.proposer.inc_payload_cache_hits();
+                        payload
+                    }
+                    None => {
+                        let request = PayloadRequest {
+                            parent: &latest_block,
+                            forkchoice_state: state.forkchoice.state(
+                                Height::new(latest_block.block_number),
+                                latest_block.block_hash,
+                            ),
+                            fee_recipient: emerald_config.fee_recipient,
+                            fork: state.get_fork(latest_block.timestamp),
+                            retry_config: &emerald_config.retry_config,
+                        };
+                        let mut pipelined = match state.pending_payload.take() {
+                            Some(pending)
+                                if pending.matches(
+                                    latest_block.block_hash,
+                                    emerald_config.fee_recipient,
+                                ) =>
+                            {
+                                fetch_pending_payload(engine, &request, height, pending.payload_id)
+                                    .await
+                            }
+                            _ => None,
+                        };
+
+                        // Retry a few times, e.g. while the execution client restarts, then give up
+                        // on the round so that the next proposer takes over.
+                        let attempts = emerald_config.proposer_build_attempts.max(1);
+                        let mut attempt = 1;
+                        let payload = loop {
+                            if let Some(payload) = pipelined.take() {
+                                state.metrics.proposer.inc_pipelined_payloads();
+                                break payload;
+                            }
+
+                            let e = match build_payload(
+                                engine,
+                                state.external_builder.as_ref(),
+                                &request,
+                                height,
+                                &state.metrics.proposer,
+                            )
+                            .await
+                            {
+                                Ok(payload) => break payload,
+                                Err(e) => e,
+                            };
+
+                            let failure = BuildFailure::classify(&e);
+                            state.metrics.proposer.inc_build_failures(failure);
+
+                            if attempt >= attempts || Instant::now() >= start + timeout {
+                                error!(
+                                    %height, %round, attempt,
+                                    reason = failure.as_str(),
+                                    "⚠️  Failed to build a payload, waiting for timeout: {e}"
+                                );
+                                state.event_log.record(Event::ProposalAbandoned {
+                                    height: height.as_u64(),
+                                    round: round.as_i64(),
+                                    reason: failure.as_str().to_string(),
+                                    error: e.to_string(),
+                                });
+                                state.built_payload_cache.clear();
+                                abandon_until(reply, start + timeout * 2);
+                                return Ok(());
+                            }
+
+                            let delay = emerald_config.retry_config.initial_delay;
+                            warn!(
+                                attempt,
+                                attempts,
+                                reason = failure.as_str(),
+                                "⚠️  Failed to build a payload, retrying in {delay:?}: {e}"
+                            );
+                            tokio::time::sleep(delay).await;
+                            attempt += 1;
+                        };
+                        state.metrics.proposer.inc_payload_cache_misses();
+                        if let Some(key) = payload_key {
+                            state.built_payload_cache.insert(key, payload.clone());
+                        }
+                        payload
+                    }
+                };
+
+                debug!("🌈 Got execution payload: {:?}", execution_payload);
+                state.block_profile.reached(height, Stage::Built);
+
+                let execution_payload = if state.tx_filter.is_some()
+                    || state.min_base_fee_per_gas.is_some()
+                {
+                    match filter_payload(
+                        state,
+                        engine,
+                        execution_payload,
+                        &latest_block,
+                        emerald_config,
+                    )
+                    .await
+                    {
+                        Some(payload) => payload,
+                        None => {
+                            state.event_log.record(Event::ProposalAbandoned {
+                                height: height.as_u64(),
+                                round: round.as_i64(),
+                                reason: "filtered_txs".to_string(),
+                                error: "no payload without filtered or underpriced transactions"
+                                    .to_string(),
+                            });
+                            abandon_until(reply, Instant::now() + timeout * 2);
+                            return Ok(());
+                        }
+                    }
+                } else {
+                    execution_payload
+                };
+
+                // Store block in state and propagate to peers.
+                let bytes = Bytes::from(execution_payload.as_ssz_bytes());
+
+                if state.exceeds_max_payload_bytes(&bytes) {
+                    error!(
+                        size = bytes.len(),
+                        max_payload_bytes = ?state.max_payload_bytes(),
+                        "⚠️  Execution client built a payload above the maximum size, not proposing it"
+                    );
+                    state.built_payload_cache.clear();
+                    abandon_until(reply, Instant::now() + timeout * 2);
+                    return Ok(());
+                }
+                debug!("🎁 block size: {:?}, height: {}", bytes.len(), height);
+
+                // Prepare block proposal.
+                let Some(proposal) = state.propose_value(height, round, bytes.clone()).await?
+                else {
+                    state.event_log.record(Event::ProposalAbandoned {
+                        height: height.as_u64(),
+                        round: round.as_i64(),
+                        reason: "undecided_limits".to_string(),
+                        error: "the limits of the undecided proposals are reached".to_string(),
+                    });
+                    abandon_until(reply, Instant::now() + timeout * 2);
+                    return Ok(());
+                };
+
+                (proposal, bytes)
+            }
+        }
+    };
+
+    // The POL round is always nil when we propose a newly built value.
+    // See L15/L18 of the Tendermint algorithm.
+    let pol_round = Round::Nil;
+    // Now what's left to do is to break down the value to propose into parts,
+    // and send those parts over the network to our peers, for them to re-assemble the full value.
+    // The parts are signed before replying, so that nothing is proposed if they cannot be.
+    let stream = match state
+        .stream_proposal(proposal.clone(), bytes, pol_round)
+        .await
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!(%height, %round, "⚠️  Failed to sign the proposal parts, waiting for timeout: {e}");
+            abandon_until(reply, Instant::now() + timeout * 2);
+            return Ok(());
+        }
+    };
+
+    state.event_log.record(Event::GetValue {
+        height: height.as_u64(),
+        round: round.as_i64(),
+        value_id: proposal.value.id().to_string(),
+    });
+
+    // Send it to consensus
+    if reply.send(proposal).is_err() {
+        error!("Failed to send GetValue reply");
+    }
+
+    publish_stream(
+        &channels.network,
+        stream,
+        emerald_config.proposal_upload_rate,
+    )
+    .await?;
+    debug!(%height, %round, "✅ Proposal sent");
+    state.block_profile.reached(height, Stage::Proposed);
+
+    Ok(())
+}
+
+/// Gives up on proposing a value in this round without blocking the message loop.
+///
+/// The reply is held until `deadline`, long enough to trigger `timeout_propose`, so that
+/// consensus moves on to the next round rather than failing the request, while the other
+/// messages, e.g. the values requested by syncing peers, are handled in the meantime.
+fn abandon_until<T: Send + 'static>(reply: T, deadline: Instant) {
+    tokio::spawn(async move {
+        tokio::time::sleep_until(deadline).await;
+        drop(reply);
+    });
+}
+
+/// Returns whether the transaction filters or the base fee floor reject the payload,
+/// logging the rejected transactions. A payload which cannot be decoded is rejected.
+fn has_filtered_txs(state: &State, execution_payload: &ExecutionPayloadV3) -> bool {
+    let mut filtered = false;
+
+    if let Some(tx_filter) = &state.tx_filter {
+        match tx_filter.check(execution_payload) {
+            Ok(txs) => {
+                for tx in &txs {
+                    warn!(tx_hash = %tx.tx_hash, reason = %tx.reason, "Transaction rejected by the filters");
+                }
+                filtered |= !txs.is_empty();
+            }
+            Err(e) => {
+                warn!("⚠️  Failed to check the payload against the transaction filters: {e}");
+                return true;
+            }
+        }
+    }
+
+    match state.txs_below_base_fee_floor(execution_payload) {
+        Ok(txs) => {
+            for tx_hash in &txs {
+                warn!(
+                    %tx_hash,
+                    base_fee_per_gas = %execution_payload.payload_inner.payload_inner.base_fee_per_gas,
+                    min_base_fee_per_gas = ?state.min_base_fee_per_gas,
+                    "Transaction does not pay the base fee floor"
+                );
+            }
+            filtered |= !txs.is_empty();
+        }
+        Err(e) => {
+            warn!("⚠️  Failed to check the payload against the base fee floor: {e}");
+            return true;
+        }
+    }
+
+    filtered
+}
+
+/// Returns the payload to propose under the transaction filters and the base fee floor:
+/// the built payload if they accept it, otherwise a payload built without the transactions
+/// of the pool, which keep being picked by the execution client, or `None` if none can be
+/// built.
+async fn filter_payload(
+    state: &mut State,
+    engine: &Engine,
+    execution_payload: ExecutionPayloadV3,
+    latest_block: &ExecutionBlock,
+    emerald_config: &EmeraldConfig,
+) -> Option<ExecutionPayloadV3> {
+    if !has_filtered_txs(state, &execution_payload) {
+        return Some(execution_payload);
+    }
+
+    state.metrics.proposer.inc_filtered_blocks();
+    state.built_payload_cache.clear();
+    warn!("⚠️  Not proposing payload containing rejected transactions, building one without the transactions of the pool");
+
+    // A timestamp other than the one of the rejected payload, so that the execution client
+    // starts building a new payload instead of returning the rejected one
+    let timestamp = execution_payload.payload_inner.payload_inner.timestamp + 1;
+    let empty = engine
+        .build_empty_block(
+            latest_block,
+            state.forkchoice.state(
+                Height::new(latest_block.block_number),
+                latest_block.block_hash,
+            ),
+            &emerald_config.retry_config,
+            &emerald_config.fee_recipient,
+            timestamp,
+            state.get_fork(timestamp),
+        )
+        .await;
+
+    match empty {
+        Ok(payload) if !has_filtered_txs(state, &payload) => Some(payload),
+        Ok(_) => {
+            warn!("⚠️  Execution client kept the rejected transactions, waiting for timeout");
+            None
+        }
+        Err(e) => {
+            warn!("⚠️  Failed to build a payload without the rejected transactions, waiting for timeout: {e}");
+            None
+        }
+    }
+}
+
+/// Fetches the payload which the execution client started building when the previous
+/// block was decided, or returns `None` if it cannot be proposed, e.g. if the execution
+/// client dropped it after its build deadline.
+async fn fetch_pending_payload(
+    engine: &Engine,
+    request: &PayloadRequest<'_>,
+    height: Height,
+    payload_id: PayloadId,
+) -> Option<ExecutionPayloadV3> {
+    // Not retried, as the payload is built again from scratch on failure
+    let payload = match engine.api.get_payload(payload_id, request.fork).await {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Failed to fetch the payload built ahead of time, building a new one: {e}");
+            return None;
+        }
+    };
+
+    if let Err(e) = check_linkage(&payload, height, request.parent) {
+        warn!(
+            reason = e.as_str(),
+            "Payload built ahead of time does not extend the parent block, building a new one"
+        );
+        return None;
+    }
+
+    info!("Using the payload built since the previous block was decided");
+    Some(payload)
+}
+
+/// Request for a value deferred until the txpool of the execution client has pending
+/// transactions, or until `max_idle_block_interval` has elapsed since the previous block,
+/// after which an empty block is proposed. It is handled again by [`crate::app::run`] once
+/// [`wait_for_transactions`] returns, unless a new round starts in the meantime.
+pub struct IdleProposal {
+    pub msg: AppMsg<EmeraldContext>,
+    pub deadline: Instant,
+}
+
+/// Returns whether the txpool of the execution client has pending transactions, or
+/// cannot be inspected, in which case the proposer does not wait for them.
+async fn has_pending_transactions(eth: &EthereumRPC) -> bool {
+    match eth.txpool_status().await {
+        Ok(status) => status.pending > 0,
+        Err(e) => {
+            warn!("Failed to get the txpool status, proposing without waiting: {e}");
+            true
+        }
+    }
+}
+
+/// Waits until the txpool of the execution client has pending transactions, or until
+/// `deadline`.
+pub async fn wait_for_transactions(eth: &EthereumRPC, deadline: Instant) {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    while Instant::now() < deadline {
+        if has_pending_transactions(eth).await {
+            return;
+        }
+        tokio::time::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())))
+            .await;
+    }
+
+    debug!("No transactions since the previous block, proposing an empty block");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use url::Url;
+
+    use super::*;
+    use crate::state::testing::spawn_execution_client;
+
+    /// Execution client whose txpool has the given number of pending transactions
+    async fn txpool_client(pending: Arc<AtomicU64>) -> EthereumRPC {
+        let url = spawn_execution_client(move |_| {
+            let pending = pending.load(Ordering::Relaxed);
+            Ok(json!({ "result": { "pending": format!("0x{pending:x}"), "queued": "0x0" } }))
+        })
+        .await;
+
+        EthereumRPC::new(url).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_transactions_until_deadline() {
+        let eth = txpool_client(Arc::new(AtomicU64::new(0))).await;
+        assert!(!has_pending_transactions(&eth).await);
+
+        let deadline = Instant::now() + Duration::from_millis(300);
+        wait_for_transactions(&eth, deadline).await;
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_transactions_until_pending() {
+        let pending = Arc::new(AtomicU64::new(0));
+        let eth = txpool_client(pending.clone()).await;
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let wait = tokio::spawn(async move { wait_for_transactions(&eth, deadline).await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!wait.is_finished());
+
+        pending.store(2, Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("Stopped waiting once transactions are pending")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_txpool_is_not_waited_for() {
+        // Bind then drop a listener, so that nothing listens on its port
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        let eth = EthereumRPC::new(url).unwrap();
+
+        assert!(has_pending_transactions(&eth).await);
+
+        let start = Instant::now();
+        wait_for_transactions(&eth, start + Duration::from_secs(30)).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
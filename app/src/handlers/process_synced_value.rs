@@ -0,0 +1,217 @@
+use color_eyre::eyre;
+use malachitebft_app_channel::app::types::core::Validity;
+use malachitebft_app_channel::AppMsg;
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::EmeraldContext;
+use tracing::{debug, error, info};
+
+use crate::el_divergence::{self, ElDivergence};
+use crate::event_log::Event;
+use crate::shadow_fork::DecodingValidator;
+use crate::state::State;
+use crate::sync_handler::{self, EnginePayloadValidator};
+
+/// Handle ProcessSyncedValue messages from the consensus engine
+///
+/// Notifies the application that a value has been synced from the network.
+/// This may happen when the node is catching up with the network.
+///
+/// If a value can be decoded from the bytes provided, then the application MUST reply
+/// to this message with the decoded value. Otherwise, it MUST reply with `None`.
+pub async fn on_process_synced_value(
+    process_synced_value: AppMsg<EmeraldContext>,
+    state: &mut State,
+    engine: &Engine,
+    emerald_config: &EmeraldConfig,
+) -> eyre::Result<()> {
+    let AppMsg::ProcessSyncedValue {
+        height,
+        round,
+        proposer,
+        value_bytes,
+        reply,
+    } = process_synced_value
+    else {
+        unreachable!("on_process_synced_value called with non-ProcessSyncedValue message");
+    };
+
+    info!(%height, %round, "🟢🟢 Processing synced value");
+
+    // The value is executed by the execution client below, which also serves RPC traffic
+    if let Some(throttled) = state.catch_up_throttle.wait(&state.clock).await {
+        debug!(%height, delay = ?throttled.delay, reason = throttled.reason.as_str(), "Throttled the catch-up");
+        state
+            .metrics
+            .sync
+            .inc_throttled_values(throttled.reason.as_str());
+        state.node_status.catch_up_throttled(throttled.delay);
+    }
+
+    state.node_status.synced(height);
+    state.synced_height = state.synced_height.max(Some(height));
+
+    let proposed_value = if state.shadow_fork.is_some() {
+        // Re-executed once decided, the shadow execution client not having the blocks
+        sync_handler::process_synced_value(
+            &mut DecodingValidator,
+            height,
+            round,
+            proposer,
+            value_bytes,
+        )
+        .await
+    } else {
+        let mut validator = EnginePayloadValidator {
+            engine,
+            parent: state.latest_block,
+            metrics: state.metrics.validation.clone(),
+            cache: state.validated_cache_mut(),
+            retry_config: &emerald_config.retry_config,
+        };
+        sync_handler::process_synced_value(&mut validator, height, round, proposer, value_bytes)
+            .await
+    };
+
+    // The height is requested again once the execution client recovered
+    if let Some(divergence) = proposed_value
+        .as_ref()
+        .err()
+        .and_then(ElDivergence::find_in_sync)
+    {
+        if let Err(e) = el_divergence::recover(state, engine, &divergence).await {
+            error!(
+                %height,
+                "Failed to recover from the divergence of the execution client: {e:#}"
+            );
+        }
+        if reply.send(None).is_err() {
+            error!("Failed to send ProcessSyncedValue rejection reply");
+        }
+        return Ok(());
+    }
+
+    let Some(proposed_value) = proposed_value? else {
+        // Undecodable value, consensus drops it and requests the height again
+        state
+            .metrics
+            .validation
+            .inc_rejected_payloads("undecodable_value");
+        if reply.send(None).is_err() {
+            error!("Failed to send ProcessSyncedValue rejection reply");
+        }
+        return Ok(());
+    };
+
+    state.event_log.record(Event::ProcessSyncedValue {
+        height: height.as_u64(),
+        round: round.as_i64(),
+        proposer: proposed_value.proposer.to_string(),
+        value_id: proposed_value.value.id().to_string(),
+        valid: proposed_value.validity == Validity::Valid,
+    });
+
+    if proposed_value.validity == Validity::Invalid {
+        // Reject invalid blocks - don't store or reply with them
+        if reply.send(Some(proposed_value)).is_err() {
+            error!("Failed to send ProcessSyncedValue rejection reply");
+        }
+        return Ok(());
+    }
+
+    let block_bytes = proposed_value.value.extensions.clone();
+
+    if let Err(e) = state
+        .store_undecided_value(&proposed_value, block_bytes)
+        .await
+    {
+        error!(%height, %round, error = %e, "Failed to store synced value");
+    }
+
+    // Send to consensus to see if it has been decided on
+    if reply.send(Some(proposed_value)).is_err() {
+        error!(%height, %round, "Failed to send ProcessSyncedValue reply");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_rpc_types_engine::ExecutionPayloadV3;
+    use bytes::Bytes;
+    use malachitebft_app_channel::app::types::codec::Codec;
+    use malachitebft_app_channel::app::types::core::Round;
+    use malachitebft_app_channel::app::types::ProposedValue;
+    use malachitebft_eth_types::codec::proto::ProtobufCodec;
+    use malachitebft_eth_types::{Address, Height, Value, B256};
+    use ssz::Encode;
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::state::testing::TestNode;
+
+    async fn process_synced_value(
+        node: &mut TestNode,
+        value_bytes: Bytes,
+    ) -> Option<ProposedValue<EmeraldContext>> {
+        let (reply, proposed_value) = oneshot::channel();
+        let msg = AppMsg::ProcessSyncedValue {
+            height: Height::new(1),
+            round: Round::new(0),
+            proposer: Address::repeat_byte(1),
+            value_bytes,
+            reply,
+        };
+        let emerald_config = node.state.emerald_config.clone();
+        on_process_synced_value(msg, &mut node.state, &node.engine, &emerald_config)
+            .await
+            .unwrap();
+        proposed_value.await.unwrap()
+    }
+
+    fn encode(data: Bytes) -> Bytes {
+        ProtobufCodec::default().encode(&Value::new(data)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_value_is_dropped() {
+        let mut node = TestNode::new(1, Height::new(1), |_| {}).await;
+        let proposed = process_synced_value(&mut node, Bytes::from_static(b"\xff\xff")).await;
+        assert!(proposed.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_synced_value_is_validated_and_stored() {
+        let mut node = TestNode::new(1, Height::new(1), |_| {}).await;
+
+        // Not an execution payload
+        let proposed = process_synced_value(&mut node, encode(Bytes::from_static(b"block")))
+            .await
+            .expect("Invalid value is handed to consensus");
+        assert_eq!(proposed.validity, Validity::Invalid);
+
+        // Already validated by the execution client
+        let block_hash = B256::repeat_byte(1);
+        let mut payload = ExecutionPayloadV3::default();
+        payload.payload_inner.payload_inner.block_number = 1;
+        payload.payload_inner.payload_inner.block_hash = block_hash;
+        node.state
+            .validated_cache_mut()
+            .insert(block_hash, Validity::Valid);
+
+        let proposed = process_synced_value(&mut node, encode(Bytes::from(payload.as_ssz_bytes())))
+            .await
+            .expect("Valid value is handed to consensus");
+        assert_eq!(proposed.validity, Validity::Valid);
+        assert_eq!(
+            node.state
+                .store
+                .get_undecided_proposals(Height::new(1), Round::new(0))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Instant;
+
+use color_eyre::eyre;
+use malachitebft_app_channel::app::streaming::{StreamContent, StreamMessage};
+use malachitebft_app_channel::app::types::core::Validity;
+use malachitebft_app_channel::{AppMsg, Channels};
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::{EmeraldContext, ProposalPart};
+use tracing::{debug, error};
+
+use crate::block_profile::Stage;
+use crate::event_log::Event;
+use crate::state::State;
+use crate::streaming::publish_stream;
+
+/// Handle ReceivedProposalPart messages from the consensus engine
+///
+/// Notifies the application that consensus has received a proposal part over the network.
+///
+/// If this part completes the full proposal, the application MUST respond
+/// with the complete proposed value. Otherwise, it MUST respond with `None`.
+///
+/// The requests of the peers for the parts they missed are replied to with the parts of
+/// the stream, if this node has it, and the parts recovered from the peers are added to
+/// the stream they were missing from.
+pub async fn on_received_proposal_part(
+    received_proposal_part: AppMsg<EmeraldContext>,
+    state: &mut State,
+    channels: &mut Channels<EmeraldContext>,
+    engine: &Engine,
+    emerald_config: &EmeraldConfig,
+) -> eyre::Result<()> {
+    let AppMsg::ReceivedProposalPart { from, part, reply } = received_proposal_part else {
+        unreachable!("on_received_proposal_part called with non-ReceivedProposalPart message");
+    };
+
+    if let Err(rejection) = state.peer_filter.check_peer(&from) {
+        debug!(%from, reason = rejection.as_str(), "Ignoring proposal part from a peer rejected by the peer filter");
+        state.metrics.peers.inc_rejected_proposal_parts(rejection);
+        if reply.send(None).is_err() {
+            error!("Failed to send ReceivedProposalPart reply");
+        }
+        return Ok(());
+    }
+
+    let height = match &part.content {
+        StreamContent::Data(ProposalPart::Init(init)) => Some(init.height),
+        _ => None,
+    };
+    state.peer_registry.record(from, height, Instant::now());
+
+    // Requests and recovered parts are not parts of a stream of the sender
+    let (from, part) = match part.content {
+        StreamContent::Data(ProposalPart::Request(request)) => {
+            let msgs = state.serve_parts_request(from, &request);
+            publish_stream(
+                &channels.network,
+                msgs,
+                state.emerald_config.proposal_upload_rate,
+            )
+            .await?;
+            (from, None)
+        }
+        StreamContent::Data(ProposalPart::Recovered(recovered)) => {
+            match state.recovered_part(recovered) {
+                Some((peer_id, part)) => (peer_id, Some(part)),
+                None => (from, None),
+            }
+        }
+        content => (
+            from,
+            Some(StreamMessage::new(part.stream_id, part.sequence, content)),
+        ),
+    };
+
+    let Some(part) = part else {
+        if reply.send(None).is_err() {
+            error!("Failed to send ReceivedProposalPart reply");
+        }
+        return Ok(());
+    };
+
+    let (part_type, part_size) = match &part.content {
+        StreamContent::Data(part) => (part.get_type(), part.size_bytes()),
+        StreamContent::Fin => ("end of stream", 0),
+    };
+
+    debug!(
+        %from, %part.sequence, part.type = %part_type, part.size = %part_size,
+        "Received proposal part"
+    );
+
+    let init = match &part.content {
+        StreamContent::Data(ProposalPart::Init(init)) => Some(init.clone()),
+        _ => None,
+    };
+    let stream_id = part.stream_id.clone();
+
+    // Try to reassemble the proposal from received parts
+    let parts = state.reassemble_proposal(from, part).await?;
+
+    // If we have complete parts, validate and store the proposal
+    let proposed_value = match (parts, init) {
+        (Some(parts), _) => {
+            state
+                .process_complete_proposal_parts(&parts, engine, &emerald_config.retry_config)
+                .await?
+        }
+        // Otherwise the init part may announce a payload this node already has
+        (None, Some(init)) => {
+            state
+                .proposal_from_known_payload(
+                    from,
+                    stream_id,
+                    &init,
+                    engine,
+                    &emerald_config.retry_config,
+                )
+                .await?
+        }
+        (None, None) => None,
+    };
+
+    if let Some(ref proposed_value) = proposed_value {
+        debug!("✅ Received complete proposal: {:?}", proposed_value);
+        state
+            .block_profile
+            .reached(proposed_value.height, Stage::Proposed);
+
+        state.event_log.record(Event::ProposalReceived {
+            height: proposed_value.height.as_u64(),
+            round: proposed_value.round.as_i64(),
+            proposer: proposed_value.proposer.to_string(),
+            value_id: proposed_value.value.id().to_string(),
+            valid: proposed_value.validity == Validity::Valid,
+        });
+    }
+
+    if reply.send(proposed_value).is_err() {
+        error!("Failed to send ReceivedProposalPart reply");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use color_eyre::eyre::{self, eyre};
+use malachitebft_app_channel::app::types::core::Round;
+use malachitebft_app_channel::{AppMsg, Channels};
+use malachitebft_eth_types::EmeraldContext;
+use tracing::{debug, error, info};
+
+use crate::state::State;
+use crate::streaming::publish_stream;
+
+/// Handle RestreamProposal messages from the consensus engine
+///
+/// Requests the application to re-stream a proposal that it has already seen.
+///
+/// The application MUST re-publish again all the proposal parts pertaining
+/// to that value by sending [`NetworkMsg::PublishProposalPart`] messages through
+/// the [`Channels::network`] channel.
+///
+/// If this node did not complete the proposal, the parts it is missing are requested from
+/// the peers instead.
+///
+/// [`NetworkMsg::PublishProposalPart`]: malachitebft_app_channel::NetworkMsg::PublishProposalPart
+pub async fn on_restream_proposal(
+    restream_proposal: AppMsg<EmeraldContext>,
+    state: &mut State,
+    channels: &mut Channels<EmeraldContext>,
+) -> eyre::Result<()> {
+    let AppMsg::RestreamProposal {
+        height,
+        round,
+        valid_round,
+        address,
+        value_id,
+    } = restream_proposal
+    else {
+        unreachable!("on_restream_proposal called with non-RestreamProposal message");
+    };
+
+    //  Look for a proposal at valid_round or round(should be already stored)
+    let proposal_round = if valid_round == Round::Nil {
+        round
+    } else {
+        valid_round
+    };
+    info!(%height, %proposal_round, "Restreaming existing proposal...");
+
+    //let (proposal, bytes) =
+    match state
+        .get_previous_proposal_by_value_and_proposer(height, round, value_id, address)
+        .await?
+    {
+        Some(proposal) => {
+            info!(value = %proposal.value.id(), "Re-using previously built value");
+            // Fetch the block data for the previously built value
+            let bytes = state
+                .store
+                .get_block_data(height, round, proposal.value.id())
+                .await?
+                .ok_or_else(|| eyre!("Block data not found for previously built value"))?;
+            // Now what's left to do is to break down the value to propose into parts,
+            // and send those parts over the network to our peers, for them to re-assemble the full value.
+            let stream = match state.stream_proposal(proposal, bytes, proposal_round).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!(%height, %round, "Failed to sign the parts of the proposal to re-send: {e}");
+                    return Ok(());
+                }
+            };
+            publish_stream(
+                &channels.network,
+                stream,
+                state.emerald_config.proposal_upload_rate,
+            )
+            .await?;
+            state.metrics.proposer.inc_proposal_restreams();
+
+            debug!(%height, %round, "✅ Re-sent proposal");
+        }
+        None => {
+            let requests = state.request_missing_parts(height, round, address);
+            if requests.is_empty() {
+                debug!(%height, %round, "✅ No proposal to re-send");
+            } else {
+                publish_stream(
+                    &channels.network,
+                    requests,
+                    state.emerald_config.proposal_upload_rate,
+                )
+                .await?;
+                debug!(%height, %round, "✅ Requested the missing parts of the proposal");
+            }
+        }
+    }
+
+    Ok(())
+}
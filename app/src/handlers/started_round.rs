@@ -0,0 +1,123 @@
+use color_eyre::eyre;
+use malachitebft_app_channel::app::types::core::{Round, Validity};
+use malachitebft_app_channel::{AppMsg, Channels};
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_types::EmeraldContext;
+use tracing::{debug, error, info, warn};
+
+use crate::block_profile::Stage;
+use crate::event_log::Event;
+use crate::state::State;
+use crate::streaming::publish_stream;
+
+/// Handle StartedRound messages from the consensus engine
+///
+/// Notifies the application that a new consensus round has begun.
+pub async fn on_started_round(
+    started_round: AppMsg<EmeraldContext>,
+    state: &mut State,
+    channels: &mut Channels<EmeraldContext>,
+    engine: &Engine,
+    emerald_config: &EmeraldConfig,
+) -> eyre::Result<()> {
+    let AppMsg::StartedRound {
+        height,
+        round,
+        proposer,
+        role,
+        reply_value,
+    } = started_round
+    else {
+        unreachable!("on_started_round called with non-StartedRound message");
+    };
+
+    info!(%height, %round, %proposer, ?role, "🟢🟢 Started round");
+
+    state.event_log.record(Event::StartedRound {
+        height: height.as_u64(),
+        round: round.as_i64(),
+        proposer: proposer.to_string(),
+    });
+
+    // The consensus_height stored in state should match
+    // the one in the StartedRound message
+    if state.consensus_height != height {
+        warn!(
+            consensus_height = %state.consensus_height,
+            new_height = %height,
+            "Started round mismatch between state and message"
+        );
+    }
+
+    // A value deferred until transactions are available is not requested anymore
+    state.idle_proposal = None;
+
+    // We can use that opportunity to update our internal state
+    state.consensus_height = height;
+    state.consensus_round = round;
+    state.block_profile.reached(height, Stage::Started);
+    state.prune_proposal_streams();
+
+    // The proposals of the previous rounds may still be recovered from the peers
+    let requests = state.request_stalled_parts();
+    publish_stream(
+        &channels.network,
+        requests,
+        emerald_config.proposal_upload_rate,
+    )
+    .await?;
+
+    let validator_set = state.get_validator_set(height);
+    state
+        .node_status
+        .started_round(height, round, proposer, validator_set);
+
+    if state.consensus_round == Round::ZERO {
+        state.last_block_time = state.clock.now();
+    }
+
+    let pending_parts = state
+        .store
+        .get_pending_proposal_parts(height, round)
+        .await?;
+    debug!(
+        %height,
+        %round,
+        "Found {} pending proposal parts, validating...",
+        pending_parts.len()
+    );
+
+    for parts in &pending_parts {
+        // Validate and store the pending proposal
+        let result = state
+            .process_complete_proposal_parts(parts, engine, &emerald_config.retry_config)
+            .await?;
+
+        if result.is_some_and(|value| value.validity == Validity::Valid) {
+            info!(
+                height = %parts.height,
+                round = %parts.round,
+                proposer = %parts.proposer,
+                "Moved valid pending proposal to undecided after validation"
+            );
+        }
+
+        // Remove the parts from pending regardless of validation outcome
+        state
+            .store
+            .remove_pending_proposal_parts(parts.clone())
+            .await?;
+    }
+
+    // If we have already built or seen values for this height and round,
+    // send them all back to consensus. This may happen when we are restarting after a crash.
+    let proposals = state.store.get_undecided_proposals(height, round).await?;
+    debug!(%height, %round, "Found {} undecided proposals", proposals.len());
+
+    if reply_value.send(proposals).is_err() {
+        error!("Failed to send undecided proposals");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use color_eyre::eyre;
+use malachitebft_app_channel::AppMsg;
+use malachitebft_eth_types::EmeraldContext;
+use tracing::error;
+
+/// Handle ExtendVote messages from the consensus engine
+///
+/// ExtendVote allows the application to extend the pre-commit vote with arbitrary data.
+///
+/// When consensus is preparing to send a pre-commit vote, it first calls `ExtendVote`.
+/// The application then returns a blob of data called a vote extension.
+/// This data is opaque to the consensus algorithm but can contain application-specific information.
+/// The proposer of the next block will receive all vote extensions along with the commit certificate.
+pub async fn on_extended_vote(extended_vote: AppMsg<EmeraldContext>) -> eyre::Result<()> {
+    let AppMsg::ExtendVote { reply, .. } = extended_vote else {
+        unreachable!("on_extended_vote called with non-ExtendVote message");
+    };
+
+    if reply.send(None).is_err() {
+        error!("🔴 Failed to send ExtendVote reply");
+    }
+
+    Ok(())
+}
+
+/// Handle VerifyVoteExtension messages from the consensus engine
+///
+/// Verify a vote extension
+///
+/// If the vote extension is deemed invalid, the vote it was part of
+/// will be discarded altogether.
+pub async fn on_verify_vote_extention(
+    verify_vote_extenstion: AppMsg<EmeraldContext>,
+) -> eyre::Result<()> {
+    let AppMsg::VerifyVoteExtension { reply, .. } = verify_vote_extenstion else {
+        unreachable!("on_verify_vote_extention called with non-VerifyVoteExtension message");
+    };
+
+    if reply.send(Ok(())).is_err() {
+        error!("🔴 Failed to send VerifyVoteExtension reply");
+    }
+
+    Ok(())
+}
@@ -1,10 +1,98 @@
+//! Emerald, a PoA EVM chain on top of Malachite consensus.
+//!
+//! The library is split in features, so that embedders can depend on a minimal surface:
+//!
+//! - `types`: the consensus types, re-exported as [`types`]
+//! - `light-client`: the verification of the commit certificates of decided values, in
+//!   [`certificate`]
+//! - `engine`: the Engine API client of the execution client, re-exported as [`engine`]
+//! - `app` (default): the node itself, with the CLI configuration and the `emerald` binary
+//!
+//! e.g. `emerald = { default-features = false, features = ["light-client"] }`.
+
+#[cfg(feature = "types")]
+pub use malachitebft_eth_types as types;
+
+#[cfg(feature = "engine")]
+pub use malachitebft_eth_engine as engine;
+
+#[cfg(feature = "app")]
+mod admin;
+#[cfg(feature = "app")]
 pub mod app;
-mod bootstrap;
+#[cfg(feature = "app")]
+mod base_fee;
+#[cfg(feature = "app")]
+mod block_profile;
+#[cfg(feature = "app")]
+pub mod bootstrap;
+#[cfg(feature = "app")]
+mod build_info;
+#[cfg(feature = "app")]
+mod catch_up;
+#[cfg(feature = "light-client")]
+pub mod certificate;
+#[cfg(feature = "app")]
+mod config_snapshot;
+#[cfg(feature = "app")]
+mod consensus_params;
+#[cfg(feature = "app")]
+mod direct_tx;
+#[cfg(feature = "app")]
+mod el_announce;
+#[cfg(feature = "app")]
+mod el_divergence;
+#[cfg(feature = "app")]
+mod el_health;
+#[cfg(feature = "app")]
+mod el_snapshot;
+#[cfg(feature = "app")]
+pub mod event_log;
+#[cfg(feature = "app")]
+mod failover;
+#[cfg(feature = "app")]
+mod forkchoice;
+#[cfg(feature = "app")]
+mod handlers;
+#[cfg(feature = "app")]
 mod metrics;
+#[cfg(feature = "app")]
+mod metrics_aggregator;
+#[cfg(feature = "app")]
+mod nat;
+#[cfg(feature = "app")]
 pub mod node;
+#[cfg(feature = "app")]
+mod node_status;
+#[cfg(feature = "app")]
 mod payload;
+#[cfg(feature = "app")]
+mod peer_filter;
+#[cfg(feature = "app")]
+mod peer_registry;
+#[cfg(all(test, feature = "app"))]
+mod proposal_conformance;
+#[cfg(feature = "app")]
+mod rpc_proxy;
+#[cfg(feature = "app")]
+mod shadow_fork;
+#[cfg(feature = "app")]
 pub mod state;
+#[cfg(feature = "app")]
 mod store;
+#[cfg(feature = "app")]
 mod streaming;
-mod sync_handler;
+#[cfg(feature = "app")]
+pub mod sync_handler;
+#[cfg(feature = "app")]
+mod sync_limiter;
+#[cfg(feature = "app")]
+mod sync_stats;
+#[cfg(feature = "app")]
+mod tx_filter;
+#[cfg(feature = "app")]
 mod validators;
+#[cfg(feature = "app")]
+mod vote_stats;
+#[cfg(feature = "app")]
+mod webhooks;
@@ -1,13 +1,23 @@
+use core::time::Duration;
+use std::path::Path;
+
 use color_eyre::eyre::{eyre, Result};
-use emerald::node::App;
+use emerald::node::{App, MultiNode};
 use malachitebft_app_channel::app::node::Node;
 use malachitebft_eth_cli::args::{Args, Commands};
+use malachitebft_eth_cli::chains::{ChainEntry, ChainsConfig};
+use malachitebft_eth_cli::cmd::dev::DevCmd;
+use malachitebft_eth_cli::cmd::doctor::DoctorCmd;
 use malachitebft_eth_cli::cmd::init::InitCmd;
-use malachitebft_eth_cli::cmd::start::StartCmd;
-use malachitebft_eth_cli::cmd::testnet::TestnetCmd;
+use malachitebft_eth_cli::cmd::service::ServiceSubcommand;
+use malachitebft_eth_cli::cmd::start::{NodeMode, StartCmd};
+use malachitebft_eth_cli::cmd::store::{compact_online, StoreCmd, StoreSubcommand};
+use malachitebft_eth_cli::cmd::testnet::{metrics, TestnetCmd, TestnetStartCmd, TestnetSubcommand};
+use malachitebft_eth_cli::cmd::unsafe_reset::UnsafeResetCmd;
+use malachitebft_eth_cli::cmd::verify_store::VerifyStoreCmd;
 use malachitebft_eth_cli::{config, logging, runtime};
-use malachitebft_eth_types::Height;
-use tracing::{info, trace};
+use malachitebft_eth_types::{Hashable, Height};
+use tracing::{info, trace, warn};
 
 /// Main entry point for the application
 ///
@@ -43,28 +53,52 @@ fn main() -> Result<()> {
         Commands::Start(cmd) => start(&args, cmd, logging),
         Commands::Init(cmd) => init(&args, cmd, logging),
         Commands::Testnet(cmd) => testnet(&args, cmd, logging),
+        Commands::Dev(cmd) => dev(&args, cmd, logging),
         Commands::ShowPubkey(cmd) => cmd.run(),
+        Commands::Status(cmd) => cmd.run(
+            &args.get_chains_file_path()?,
+            &args.get_emerald_config_file()?,
+        ),
+        Commands::Schedule(cmd) => cmd.run(),
+        Commands::UnsafeReset(cmd) => unsafe_reset(&args, cmd),
+        Commands::Store(cmd) => store(&args, cmd),
+        Commands::Doctor(cmd) => doctor(&args, cmd),
+        Commands::VerifyStore(cmd) => verify_store(&args, cmd),
+        Commands::Service(cmd) => match &cmd.command {
+            ServiceSubcommand::Install(install) => {
+                install.run(&args.get_home_dir()?, &args.get_emerald_config_file()?)
+            }
+        },
         _ => unimplemented!(),
     }
 }
 
 fn start(args: &Args, cmd: &StartCmd, logging: config::LoggingConfig) -> Result<()> {
+    if cmd.is_multi_chain() {
+        return start_multi_chain(args, cmd, logging);
+    }
+
     // Load configuration file if it exists. Some commands do not require a configuration file.
     let config_file = args
         .get_config_file_path()
         .map_err(|error| eyre!("Failed to get configuration file path: {error}"))?;
 
-    let mut config = config::load_config(&config_file, None)
+    let emerald_config_file = args.get_emerald_config_file()?;
+
+    let mut config = config::load_node_config(&config_file, &emerald_config_file)
         .map_err(|error| eyre!("Failed to load configuration file: {error}"))?;
 
     config.logging = logging;
 
     let rt = runtime::build_runtime(config.runtime)?;
 
-    info!(
-        file = %args.get_config_file_path().unwrap_or_default().display(),
-        "Loaded configuration",
-    );
+    // The configuration is taken from the emerald config file if it has a `[consensus]` table
+    let loaded_file = if config::has_consensus_table(&emerald_config_file) {
+        &emerald_config_file
+    } else {
+        &config_file
+    };
+    info!(file = %loaded_file.display(), "Loaded configuration");
 
     trace!(?config, "Configuration");
 
@@ -73,9 +107,13 @@ fn start(args: &Args, cmd: &StartCmd, logging: config::LoggingConfig) -> Result<
         config,
         home_dir: args.get_home_dir()?,
         genesis_file: args.get_genesis_file_path()?,
-        emerald_config_file: args.get_emerald_config_file()?,
+        emerald_config_file,
         private_key_file: args.get_priv_validator_key_file_path()?,
         start_height: cmd.start_height.map(Height::new),
+        mode: cmd.mode,
+        profile_blocks: cmd.profile_blocks,
+        fast_sync: cmd.fast_sync,
+        signing_fence: None,
     };
 
     // Start the node
@@ -83,6 +121,67 @@ fn start(args: &Args, cmd: &StartCmd, logging: config::LoggingConfig) -> Result<
         .map_err(|error| eyre!("Failed to run the application node: {error}"))
 }
 
+fn start_multi_chain(args: &Args, cmd: &StartCmd, logging: config::LoggingConfig) -> Result<()> {
+    let chains_file = match &cmd.chains {
+        Some(path) => path.clone(),
+        None => args.get_chains_file_path()?,
+    };
+    let chains = ChainsConfig::load(&chains_file)?;
+
+    let apps = chains
+        .select(&cmd.chain_ids)?
+        .into_iter()
+        .map(|chain| Ok((chain.id.clone(), chain_app(chain, cmd, &logging)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    info!(
+        file = %chains_file.display(),
+        chains = %apps.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>().join(", "),
+        "Loaded chains",
+    );
+
+    // All chains share the runtime configured by the first one
+    let (_, first) = apps
+        .first()
+        .ok_or_else(|| eyre!("No chain to run in {}", chains_file.display()))?;
+    let rt = runtime::build_runtime(first.config.runtime)?;
+    let node = MultiNode::new(apps)?;
+
+    rt.block_on(node.run())
+        .map_err(|error| eyre!("Failed to run the multi-chain node: {error}"))
+}
+
+/// Builds the application for one of the chains of a multi-chain node
+fn chain_app(chain: &ChainEntry, cmd: &StartCmd, logging: &config::LoggingConfig) -> Result<App> {
+    let config_dir = chain.config_dir();
+
+    let mut config = config::load_node_config(
+        &config_dir.join("config.toml"),
+        &chain.emerald_config_file(),
+    )
+    .map_err(|error| {
+        eyre!(
+            "Failed to load configuration file of chain `{}`: {error}",
+            chain.id
+        )
+    })?;
+
+    config.logging = logging.clone();
+
+    Ok(App {
+        config,
+        home_dir: chain.home.clone(),
+        genesis_file: config_dir.join("genesis.json"),
+        emerald_config_file: chain.emerald_config_file(),
+        private_key_file: config_dir.join("priv_validator_key.json"),
+        start_height: chain.start_height.map(Height::new),
+        mode: cmd.mode,
+        profile_blocks: None,
+        fast_sync: cmd.fast_sync,
+        signing_fence: None,
+    })
+}
+
 fn init(args: &Args, cmd: &InitCmd, logging: config::LoggingConfig) -> Result<()> {
     // Setup the application
     let app = App {
@@ -92,16 +191,27 @@ fn init(args: &Args, cmd: &InitCmd, logging: config::LoggingConfig) -> Result<()
         emerald_config_file: args.get_emerald_config_file()?,
         private_key_file: args.get_priv_validator_key_file_path()?,
         start_height: Some(Height::new(1)), // We always start at height 1
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
     };
 
     cmd.run(
         &app,
         &args.get_config_file_path()?,
+        &app.emerald_config_file,
         &args.get_genesis_file_path()?,
         &args.get_priv_validator_key_file_path()?,
         logging,
     )
-    .map_err(|error| eyre!("Failed to run init command {error:?}"))
+    .map_err(|error| eyre!("Failed to run init command {error:?}"))?;
+
+    // Operators can pin this hash with `expected_genesis_hash` in the emerald config
+    let genesis = app.load_genesis()?;
+    info!(genesis_hash = %genesis.hash(), "Genesis");
+
+    Ok(())
 }
 
 fn testnet(args: &Args, cmd: &TestnetCmd, logging: config::LoggingConfig) -> Result<()> {
@@ -113,8 +223,304 @@ fn testnet(args: &Args, cmd: &TestnetCmd, logging: config::LoggingConfig) -> Res
         emerald_config_file: args.get_emerald_config_file()?,
         private_key_file: args.get_priv_validator_key_file_path()?,
         start_height: Some(Height::new(1)), // We always start at height 1
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
     };
 
+    if let Some(TestnetSubcommand::Start(start)) = &cmd.command {
+        if start.in_process {
+            return testnet_in_process(&app, start, &args.get_home_dir()?, logging);
+        }
+    }
+
     cmd.run(&app, &args.get_home_dir()?, logging)
         .map_err(|error| eyre!("Failed to run testnet command {:?}", error))
 }
+
+/// Runs all the Emerald nodes of a new testnet in this process, until Ctrl-C
+fn testnet_in_process(
+    generator: &App,
+    cmd: &TestnetStartCmd,
+    home_dir: &Path,
+    logging: config::LoggingConfig,
+) -> Result<()> {
+    let reth_processes = cmd
+        .prepare(generator, home_dir, logging.clone())
+        .map_err(|error| eyre!("Failed to prepare the testnet: {error:?}"))?;
+
+    let apps = (0..cmd.nodes)
+        .map(|node_id| {
+            let node_home = home_dir.join(node_id.to_string());
+            let config_dir = node_home.join("config");
+
+            let mut config = config::load_node_config(
+                &config_dir.join("config.toml"),
+                &config_dir.join("emerald.toml"),
+            )
+            .map_err(|error| {
+                eyre!("Failed to load configuration file of node {node_id}: {error}")
+            })?;
+            config.logging = logging.clone();
+
+            let app = App {
+                config,
+                home_dir: node_home,
+                genesis_file: config_dir.join("genesis.json"),
+                emerald_config_file: config_dir.join("emerald.toml"),
+                private_key_file: config_dir.join("priv_validator_key.json"),
+                start_height: None,
+                mode: NodeMode::Validator,
+                profile_blocks: None,
+                fast_sync: false,
+                signing_fence: None,
+            };
+
+            Ok((format!("node-{node_id}"), app))
+        })
+        .collect::<Result<Vec<_>>>();
+
+    // The Reth nodes are stopped whichever way the Emerald nodes exit
+    let result = apps.and_then(|apps| {
+        let (_, first) = apps
+            .first()
+            .ok_or_else(|| eyre!("The testnet has no node to run"))?;
+        let rt = runtime::build_runtime(first.config.runtime)?;
+        let node = MultiNode::new(apps)?;
+
+        println!(
+            "\n💎 Running {} Emerald nodes in-process, press Ctrl-C to stop the testnet",
+            cmd.nodes
+        );
+        println!(
+            "📈 Serving the metrics of all nodes on http://{}/metrics",
+            cmd.metrics_addr
+        );
+
+        rt.block_on(async {
+            let aggregator = metrics::serve(cmd.metrics_addr, home_dir.to_path_buf());
+            tokio::spawn(async move {
+                if let Err(error) = aggregator.await {
+                    warn!("Failed to serve the metrics of the testnet: {error}");
+                }
+            });
+
+            tokio::select! {
+                result = node.run() => result,
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Stopping the testnet");
+                    Ok(())
+                }
+            }
+        })
+    });
+
+    for reth in &reth_processes {
+        if let Err(error) = reth.stop(Duration::from_secs(10)) {
+            warn!(pid = reth.pid, "Failed to stop the Reth node: {error}");
+        }
+    }
+
+    result.map_err(|error| eyre!("Failed to run the in-process testnet: {error}"))
+}
+
+fn dev(args: &Args, cmd: &DevCmd, logging: config::LoggingConfig) -> Result<()> {
+    let home_dir = args.get_home_dir()?;
+    let node_home = DevCmd::node_home(&home_dir);
+    let config_dir = node_home.join("config");
+
+    let generator = App {
+        config: Default::default(), // There is not existing configuration yet
+        home_dir: node_home.clone(),
+        genesis_file: config_dir.join("genesis.json"),
+        emerald_config_file: config_dir.join("emerald.toml"),
+        private_key_file: config_dir.join("priv_validator_key.json"),
+        start_height: Some(Height::new(1)), // We always start at height 1
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
+    };
+
+    let reth = cmd
+        .prepare(&generator, &home_dir, logging.clone())
+        .map_err(|error| eyre!("Failed to prepare the dev chain: {error:?}"))?;
+
+    let mut config = config::load_node_config(
+        &config_dir.join("config.toml"),
+        &generator.emerald_config_file,
+    )
+    .map_err(|error| eyre!("Failed to load configuration file: {error}"))?;
+    config.logging = logging;
+
+    let rt = runtime::build_runtime(config.runtime)?;
+
+    let app = App {
+        config,
+        start_height: None,
+        ..generator
+    };
+
+    // The Reth node is stopped whichever way the Emerald node exits
+    let result = rt.block_on(async {
+        tokio::select! {
+            result = app.run() => result,
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopping the dev chain");
+                Ok(())
+            }
+        }
+    });
+
+    if let Err(error) = reth.stop(Duration::from_secs(10)) {
+        warn!("Failed to stop the Reth node: {error}");
+    }
+
+    result.map_err(|error| eyre!("Failed to run the dev node: {error}"))
+}
+
+fn unsafe_reset(args: &Args, cmd: &UnsafeResetCmd) -> Result<()> {
+    let config_file = args
+        .get_config_file_path()
+        .map_err(|error| eyre!("Failed to get configuration file path: {error}"))?;
+
+    let config = config::load_node_config(&config_file, &args.get_emerald_config_file()?)
+        .map_err(|error| eyre!("Failed to load configuration file: {error}"))?;
+
+    let rt = runtime::build_runtime(config.runtime)?;
+
+    let app = App {
+        config,
+        home_dir: args.get_home_dir()?,
+        genesis_file: args.get_genesis_file_path()?,
+        emerald_config_file: args.get_emerald_config_file()?,
+        private_key_file: args.get_priv_validator_key_file_path()?,
+        start_height: None,
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
+    };
+
+    rt.block_on(app.unsafe_reset(Height::new(cmd.to_height), cmd.skip_el))
+        .map_err(|error| eyre!("Failed to reset the node: {error:?}"))
+}
+
+fn store(args: &Args, cmd: &StoreCmd) -> Result<()> {
+    // The running node compacts its own store
+    if let StoreSubcommand::Compact {
+        online: true,
+        node: Some(node),
+        auth_token_file,
+    } = &cmd.command
+    {
+        let report = compact_online(node, auth_token_file.as_deref())?;
+        report.print();
+        return Ok(());
+    }
+
+    let config_file = args
+        .get_config_file_path()
+        .map_err(|error| eyre!("Failed to get configuration file path: {error}"))?;
+
+    let config = config::load_node_config(&config_file, &args.get_emerald_config_file()?)
+        .map_err(|error| eyre!("Failed to load configuration file: {error}"))?;
+
+    let rt = runtime::build_runtime(config.runtime)?;
+
+    let app = App {
+        config,
+        home_dir: args.get_home_dir()?,
+        genesis_file: args.get_genesis_file_path()?,
+        emerald_config_file: args.get_emerald_config_file()?,
+        private_key_file: args.get_priv_validator_key_file_path()?,
+        start_height: None,
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
+    };
+
+    match &cmd.command {
+        StoreSubcommand::Export { path } => rt
+            .block_on(app.export_store(path))
+            .map_err(|error| eyre!("Failed to export the store: {error:?}")),
+        StoreSubcommand::Import { path } => rt
+            .block_on(app.import_store(path))
+            .map_err(|error| eyre!("Failed to import the store: {error:?}")),
+        StoreSubcommand::Compact { .. } => {
+            let report = rt
+                .block_on(app.compact_store())
+                .map_err(|error| eyre!("Failed to compact the store: {error:?}"))?;
+            report.print();
+            Ok(())
+        }
+    }
+}
+
+fn verify_store(args: &Args, cmd: &VerifyStoreCmd) -> Result<()> {
+    let config_file = args
+        .get_config_file_path()
+        .map_err(|error| eyre!("Failed to get configuration file path: {error}"))?;
+
+    let config = config::load_node_config(&config_file, &args.get_emerald_config_file()?)
+        .map_err(|error| eyre!("Failed to load configuration file: {error}"))?;
+
+    let rt = runtime::build_runtime(config.runtime)?;
+
+    let app = App {
+        config,
+        home_dir: args.get_home_dir()?,
+        genesis_file: args.get_genesis_file_path()?,
+        emerald_config_file: args.get_emerald_config_file()?,
+        private_key_file: args.get_priv_validator_key_file_path()?,
+        start_height: None,
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
+    };
+
+    let verification = rt
+        .block_on(app.verify_store(cmd.from_height, cmd.to_height))
+        .map_err(|error| eyre!("Failed to verify the store: {error:?}"))?;
+
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&verification)?);
+    } else {
+        verification.print();
+    }
+
+    match verification.broken {
+        Some(broken) => Err(eyre!("The store is broken at height {}", broken.height)),
+        None => Ok(()),
+    }
+}
+
+fn doctor(args: &Args, cmd: &DoctorCmd) -> Result<()> {
+    let app = App {
+        config: Default::default(), // The configuration file is checked by the doctor
+        home_dir: args.get_home_dir()?,
+        genesis_file: args.get_genesis_file_path()?,
+        emerald_config_file: args.get_emerald_config_file()?,
+        private_key_file: args.get_priv_validator_key_file_path()?,
+        start_height: None,
+        mode: NodeMode::Validator,
+        profile_blocks: None,
+        fast_sync: false,
+        signing_fence: None,
+    };
+
+    let rt = runtime::build_runtime(Default::default())?;
+
+    let mut report = rt.block_on(cmd.check(
+        &app.home_dir,
+        &args.get_config_file_path()?,
+        &app.genesis_file,
+        &app.emerald_config_file,
+    ));
+    report.push(rt.block_on(app.check_store()));
+
+    report.finish()
+}
@@ -1,13 +1,72 @@
 use core::ops::Deref;
 use core::time::Duration;
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
 
 use malachitebft_app_channel::app::metrics;
+use malachitebft_app_channel::app::types::core::VoteType;
+use malachitebft_eth_cli::config::AppMetricsConfig;
+use malachitebft_eth_engine::json_structures::ClientVersionV1;
+use malachitebft_eth_types::{Address, Vote};
 use metrics::prometheus::metrics::counter::Counter;
+use metrics::prometheus::metrics::family::Family;
 use metrics::prometheus::metrics::gauge::Gauge;
 use metrics::prometheus::metrics::histogram::{exponential_buckets, Histogram};
+use metrics::prometheus::registry::Registry;
 use metrics::SharedRegistry;
 
+use crate::build_info;
+use crate::el_health::ElState;
+use crate::payload::BuildFailure;
+use crate::peer_filter::Rejection;
+use crate::sync_handler::HeightUnavailable;
+
+/// Registers metrics under the configured namespace, with the configured constant labels
+fn with_scope(
+    registry: &SharedRegistry,
+    config: &AppMetricsConfig,
+    register: impl FnOnce(&mut Registry),
+) {
+    registry.with_prefix(&config.namespace, |registry| {
+        let registry = config
+            .labels
+            .iter()
+            .fold(registry, |registry, (name, value)| {
+                registry.sub_registry_with_label((name.clone().into(), value.clone().into()))
+            });
+        register(registry);
+    });
+}
+
+/// Tables of the store broken down in the per-table metrics
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DbTable {
+    DecidedValues,
+    Certificates,
+    /// Undecided proposals and their block data
+    Undecided,
+    /// Pending proposal parts
+    Pending,
+    /// Decided block data and headers
+    BlockData,
+}
+
+impl DbTable {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DecidedValues => "decided_values",
+            Self::Certificates => "certificates",
+            Self::Undecided => "undecided",
+            Self::Pending => "pending",
+            Self::BlockData => "block_data",
+        }
+    }
+
+    fn labels(&self) -> Vec<(String, String)> {
+        vec![("table".to_string(), self.as_str().to_string())]
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct DbMetrics(Arc<Inner>);
 
@@ -42,6 +101,15 @@ pub struct Inner {
     /// Total number of deletions to the database
     db_delete_count: Counter,
 
+    /// Total number of reads of corrupted data, detected by a checksum mismatch
+    db_corrupted_reads: Counter,
+
+    /// Number of proposals evicted to stay within the limits of their table, by table
+    db_evicted_entries: Family<Vec<(String, String)>, Counter>,
+
+    /// Number of proposals not stored because of the limits of their table, by table
+    db_rejected_entries: Family<Vec<(String, String)>, Counter>,
+
     /// Time taken to read from the database (seconds)
     db_read_time: Histogram,
 
@@ -50,6 +118,18 @@ pub struct Inner {
 
     /// Time taken to delete from the database (seconds)
     db_delete_time: Histogram,
+
+    /// Amount of data read from the database (bytes), by table
+    db_table_read_bytes: Family<Vec<(String, String)>, Counter>,
+
+    /// Amount of data written to the database (bytes), by table
+    db_table_write_bytes: Family<Vec<(String, String)>, Counter>,
+
+    /// Time taken to read from the database (seconds), by table
+    db_table_read_time: Family<Vec<(String, String)>, Histogram, fn() -> Histogram>,
+
+    /// Time taken to write to the database (seconds), by table
+    db_table_write_time: Family<Vec<(String, String)>, Histogram, fn() -> Histogram>,
 }
 
 impl Inner {
@@ -62,9 +142,20 @@ impl Inner {
             db_read_count: Counter::default(),
             db_write_count: Counter::default(),
             db_delete_count: Counter::default(),
+            db_corrupted_reads: Counter::default(),
+            db_evicted_entries: Family::default(),
+            db_rejected_entries: Family::default(),
             db_read_time: Histogram::new(exponential_buckets(0.001, 2.0, 10)), // Start from 1ms
             db_write_time: Histogram::new(exponential_buckets(0.001, 2.0, 10)),
             db_delete_time: Histogram::new(exponential_buckets(0.001, 2.0, 10)),
+            db_table_read_bytes: Family::default(),
+            db_table_write_bytes: Family::default(),
+            db_table_read_time: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.001, 2.0, 10))
+            }),
+            db_table_write_time: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.001, 2.0, 10))
+            }),
         }
     }
 }
@@ -80,10 +171,10 @@ impl DbMetrics {
         Self(Arc::new(Inner::new()))
     }
 
-    pub fn register(registry: &SharedRegistry) -> Self {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
         let metrics = Self::new();
 
-        registry.with_prefix("app_channel", |registry| {
+        with_scope(registry, config, |registry| {
             registry.register(
                 "db_size",
                 "Size of the database (bytes)",
@@ -126,6 +217,24 @@ impl DbMetrics {
                 metrics.db_delete_count.clone(),
             );
 
+            registry.register(
+                "db_corrupted_reads",
+                "Total number of reads of corrupted data, detected by a checksum mismatch",
+                metrics.db_corrupted_reads.clone(),
+            );
+
+            registry.register(
+                "db_evicted_entries",
+                "Number of proposals evicted to stay within the limits of their table, by table",
+                metrics.db_evicted_entries.clone(),
+            );
+
+            registry.register(
+                "db_rejected_entries",
+                "Number of proposals not stored because of the limits of their table, by table",
+                metrics.db_rejected_entries.clone(),
+            );
+
             registry.register(
                 "db_read_time",
                 "Time taken to read bytes from the database (seconds)",
@@ -143,6 +252,30 @@ impl DbMetrics {
                 "Time taken to delete bytes from the database (seconds)",
                 metrics.db_delete_time.clone(),
             );
+
+            registry.register(
+                "db_table_read_bytes",
+                "Amount of data read from the database (bytes), by table",
+                metrics.db_table_read_bytes.clone(),
+            );
+
+            registry.register(
+                "db_table_write_bytes",
+                "Amount of data written to the database (bytes), by table",
+                metrics.db_table_write_bytes.clone(),
+            );
+
+            registry.register(
+                "db_table_read_time",
+                "Time taken to read from the database (seconds), by table",
+                metrics.db_table_read_time.clone(),
+            );
+
+            registry.register(
+                "db_table_write_time",
+                "Time taken to write to the database (seconds), by table",
+                metrics.db_table_write_time.clone(),
+            );
         });
 
         metrics
@@ -167,6 +300,22 @@ impl DbMetrics {
         self.db_key_read_bytes.inc_by(bytes);
     }
 
+    pub fn inc_corrupted_reads(&self) {
+        self.db_corrupted_reads.inc();
+    }
+
+    pub fn add_evicted_entries(&self, table: &str, count: u64) {
+        self.db_evicted_entries
+            .get_or_create(&vec![("table".to_string(), table.to_string())])
+            .inc_by(count);
+    }
+
+    pub fn inc_rejected_entries(&self, table: &str) {
+        self.db_rejected_entries
+            .get_or_create(&vec![("table".to_string(), table.to_string())])
+            .inc();
+    }
+
     pub fn observe_read_time(&self, duration: Duration) {
         self.db_read_time.observe(duration.as_secs_f64());
     }
@@ -178,6 +327,34 @@ impl DbMetrics {
     pub fn observe_delete_time(&self, duration: Duration) {
         self.db_delete_time.observe(duration.as_secs_f64());
     }
+
+    /// Bytes read from `table`, on top of the total counted by [`Self::add_read_bytes`]
+    pub fn add_table_read_bytes(&self, table: DbTable, bytes: u64) {
+        self.db_table_read_bytes
+            .get_or_create(&table.labels())
+            .inc_by(bytes);
+    }
+
+    /// Bytes written to `table`, on top of the total counted by [`Self::add_write_bytes`]
+    pub fn add_table_write_bytes(&self, table: DbTable, bytes: u64) {
+        self.db_table_write_bytes
+            .get_or_create(&table.labels())
+            .inc_by(bytes);
+    }
+
+    /// Time of a read mostly from `table`, which also reads the tables it depends on
+    pub fn observe_table_read_time(&self, table: DbTable, duration: Duration) {
+        self.db_table_read_time
+            .get_or_create(&table.labels())
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Time of a write mostly to `table`, which also writes the tables it depends on
+    pub fn observe_table_write_time(&self, table: DbTable, duration: Duration) {
+        self.db_table_write_time
+            .get_or_create(&table.labels())
+            .observe(duration.as_secs_f64());
+    }
 }
 
 impl Default for DbMetrics {
@@ -242,10 +419,10 @@ impl TxStatsMetrics {
         Self(Arc::new(TxStatsInner::new()))
     }
 
-    pub fn register(registry: &SharedRegistry) -> Self {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
         let metrics = Self::new();
 
-        registry.with_prefix("app_channel", |registry| {
+        with_scope(registry, config, |registry| {
             registry.register(
                 "txs_count",
                 "Total number of transactions committed",
@@ -317,25 +494,889 @@ impl Default for TxStatsMetrics {
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct NodeMetrics {
+    /// Build of the node, as labels of a gauge set to 1
+    build_info: Family<Vec<(String, String)>, Gauge>,
+}
+
+impl NodeMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "build_info",
+                "Build of the node",
+                metrics.build_info.clone(),
+            );
+        });
+
+        metrics
+            .build_info
+            .get_or_create(&vec![
+                ("version".to_string(), build_info::VERSION.to_string()),
+                ("git_commit".to_string(), build_info::GIT_COMMIT.to_string()),
+                (
+                    "rustc_version".to_string(),
+                    build_info::RUSTC_VERSION.to_string(),
+                ),
+                ("features".to_string(), build_info::FEATURES.to_string()),
+                (
+                    "malachite_version".to_string(),
+                    build_info::MALACHITE_VERSION.to_string(),
+                ),
+            ])
+            .set(1);
+
+        metrics
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ElMetrics {
+    /// Version of the execution client, as labels of a gauge set to 1
+    client_info: Family<Vec<(String, String)>, Gauge>,
+    /// Engine API calls which exceeded their timeout, by method
+    engine_timeouts: Family<Vec<(String, String)>, Counter>,
+    /// State of the execution client, as a gauge per state set to 1 for the current one
+    state: Family<Vec<(String, String)>, Gauge>,
+    /// Changes of the state of the execution client, by previous and new state
+    state_transitions: Family<Vec<(String, String)>, Counter>,
+    /// Decided blocks announced to other execution clients, by endpoint and result
+    block_announcements: Family<Vec<(String, String)>, Counter>,
+}
+
+impl ElMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "el_client_info",
+                "Version of the execution client",
+                metrics.client_info.clone(),
+            );
+
+            registry.register(
+                "el_engine_timeouts",
+                "Engine API calls which exceeded their timeout",
+                metrics.engine_timeouts.clone(),
+            );
+
+            registry.register(
+                "el_state",
+                "State of the execution client (ready, syncing or unreachable), set to 1",
+                metrics.state.clone(),
+            );
+
+            registry.register(
+                "el_state_transitions",
+                "Changes of the state of the execution client",
+                metrics.state_transitions.clone(),
+            );
+
+            registry.register(
+                "el_block_announcements",
+                "Decided blocks announced to other execution clients, by endpoint and result",
+                metrics.block_announcements.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn set_client_version(&self, client_version: &ClientVersionV1) {
+        self.client_info.clear();
+        self.client_info
+            .get_or_create(&vec![
+                ("code".to_string(), client_version.code.clone()),
+                ("name".to_string(), client_version.name.clone()),
+                ("version".to_string(), client_version.version.clone()),
+                ("commit".to_string(), client_version.commit.clone()),
+            ])
+            .set(1);
+    }
+
+    pub fn inc_engine_timeouts(&self, method: &str) {
+        self.engine_timeouts
+            .get_or_create(&vec![("method".to_string(), method.to_string())])
+            .inc();
+    }
+
+    pub fn set_state(&self, current: &str) {
+        for state in ElState::ALL {
+            self.state
+                .get_or_create(&vec![("state".to_string(), state.to_string())])
+                .set(i64::from(state == current));
+        }
+    }
+
+    pub fn inc_state_transitions(&self, from: &str, to: &str) {
+        self.state_transitions
+            .get_or_create(&vec![
+                ("from".to_string(), from.to_string()),
+                ("to".to_string(), to.to_string()),
+            ])
+            .inc();
+    }
+
+    pub fn inc_block_announcements(&self, endpoint: &str, result: &str) {
+        self.block_announcements
+            .get_or_create(&vec![
+                ("endpoint".to_string(), endpoint.to_string()),
+                ("result".to_string(), result.to_string()),
+            ])
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProposerMetrics {
+    /// Number of proposals reusing a previously built payload
+    payload_cache_hits: Counter,
+
+    /// Number of proposals for which a new payload was built
+    payload_cache_misses: Counter,
+
+    /// Number of built payloads not proposed because of the transaction filters
+    filtered_blocks: Counter,
+
+    /// Number of payloads built locally because the external builder failed
+    external_builder_fallbacks: Counter,
+
+    /// Number of proposals of a payload whose building started when the previous block was decided
+    pipelined_payloads: Counter,
+
+    /// Number of failed attempts at building the payload to propose, by category
+    build_failures: Family<Vec<(String, String)>, Counter>,
+
+    /// Number of rounds not proposed because the execution client was syncing
+    el_syncing: Counter,
+
+    /// Number of chunks of the payload of each proposal streamed by this node
+    proposal_chunks: Histogram,
+
+    /// Size in bytes of the chunks of the last proposal streamed by this node
+    proposal_chunk_size: Gauge,
+
+    /// Number of proposals streamed again by this node, for the peers which missed them
+    proposal_restreams: Counter,
+
+    /// Number of proposal streams from the peers dropped before completing
+    lost_proposal_streams: Counter,
+
+    /// Number of requests for the missing parts of a proposal stream sent to the peers
+    proposal_part_requests: Counter,
+
+    /// Number of proposal parts sent again by this node, for the peers which requested them
+    served_proposal_parts: Counter,
+
+    /// Number of missing proposal parts recovered from the peers
+    recovered_proposal_parts: Counter,
+
+    /// Number of transactions received on the direct transaction endpoint, by outcome
+    direct_txs: Family<Vec<(String, String)>, Counter>,
+}
+
+impl Default for ProposerMetrics {
+    fn default() -> Self {
+        Self {
+            payload_cache_hits: Counter::default(),
+            payload_cache_misses: Counter::default(),
+            filtered_blocks: Counter::default(),
+            external_builder_fallbacks: Counter::default(),
+            pipelined_payloads: Counter::default(),
+            build_failures: Family::default(),
+            el_syncing: Counter::default(),
+            proposal_chunks: Histogram::new(exponential_buckets(1.0, 2.0, 12)),
+            proposal_chunk_size: Gauge::default(),
+            proposal_restreams: Counter::default(),
+            lost_proposal_streams: Counter::default(),
+            proposal_part_requests: Counter::default(),
+            served_proposal_parts: Counter::default(),
+            recovered_proposal_parts: Counter::default(),
+            direct_txs: Family::default(),
+        }
+    }
+}
+
+impl ProposerMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "payload_cache_hits",
+                "Number of proposals reusing a previously built payload",
+                metrics.payload_cache_hits.clone(),
+            );
+
+            registry.register(
+                "payload_cache_misses",
+                "Number of proposals for which a new payload was built",
+                metrics.payload_cache_misses.clone(),
+            );
+
+            registry.register(
+                "filtered_blocks",
+                "Number of built payloads not proposed because of the transaction filters",
+                metrics.filtered_blocks.clone(),
+            );
+
+            registry.register(
+                "external_builder_fallbacks",
+                "Number of payloads built locally because the external builder failed",
+                metrics.external_builder_fallbacks.clone(),
+            );
+
+            registry.register(
+                "pipelined_payloads",
+                "Number of proposals of a payload whose building started when the previous block was decided",
+                metrics.pipelined_payloads.clone(),
+            );
+
+            registry.register(
+                "proposer_build_failures",
+                "Number of failed attempts at building the payload to propose, by category",
+                metrics.build_failures.clone(),
+            );
+
+            registry.register(
+                "proposer_el_syncing",
+                "Number of rounds not proposed because the execution client was syncing",
+                metrics.el_syncing.clone(),
+            );
+
+            registry.register(
+                "proposal_chunks",
+                "Number of chunks of the payload of each proposal streamed by this node",
+                metrics.proposal_chunks.clone(),
+            );
+
+            registry.register(
+                "proposal_chunk_size",
+                "Size in bytes of the chunks of the last proposal streamed by this node",
+                metrics.proposal_chunk_size.clone(),
+            );
+
+            registry.register(
+                "proposal_restreams",
+                "Number of proposals streamed again by this node, for the peers which missed them",
+                metrics.proposal_restreams.clone(),
+            );
+
+            registry.register(
+                "lost_proposal_streams",
+                "Number of proposal streams from the peers dropped before completing",
+                metrics.lost_proposal_streams.clone(),
+            );
+
+            registry.register(
+                "proposal_part_requests",
+                "Number of requests for the missing parts of a proposal stream sent to the peers",
+                metrics.proposal_part_requests.clone(),
+            );
+
+            registry.register(
+                "served_proposal_parts",
+                "Number of proposal parts sent again by this node, for the peers which requested them",
+                metrics.served_proposal_parts.clone(),
+            );
+
+            registry.register(
+                "recovered_proposal_parts",
+                "Number of missing proposal parts recovered from the peers",
+                metrics.recovered_proposal_parts.clone(),
+            );
+
+            registry.register(
+                "direct_txs",
+                "Number of transactions received on the direct transaction endpoint, by outcome",
+                metrics.direct_txs.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn inc_payload_cache_hits(&self) {
+        self.payload_cache_hits.inc();
+    }
+
+    pub fn inc_payload_cache_misses(&self) {
+        self.payload_cache_misses.inc();
+    }
+
+    pub fn inc_filtered_blocks(&self) {
+        self.filtered_blocks.inc();
+    }
+
+    pub fn inc_pipelined_payloads(&self) {
+        self.pipelined_payloads.inc();
+    }
+
+    pub fn inc_external_builder_fallbacks(&self) {
+        self.external_builder_fallbacks.inc();
+    }
+
+    pub fn inc_build_failures(&self, failure: BuildFailure) {
+        self.build_failures
+            .get_or_create(&vec![(
+                "category".to_string(),
+                failure.as_str().to_string(),
+            )])
+            .inc();
+    }
+
+    pub fn inc_el_syncing(&self) {
+        self.el_syncing.inc();
+    }
+
+    pub fn observe_proposal_chunks(&self, chunks: usize, chunk_size: usize) {
+        self.proposal_chunks.observe(chunks as f64);
+        self.proposal_chunk_size.set(chunk_size as i64);
+    }
+
+    pub fn inc_proposal_restreams(&self) {
+        self.proposal_restreams.inc();
+    }
+
+    pub fn inc_lost_proposal_streams(&self, count: usize) {
+        self.lost_proposal_streams.inc_by(count as u64);
+    }
+
+    pub fn inc_proposal_part_requests(&self) {
+        self.proposal_part_requests.inc();
+    }
+
+    pub fn inc_served_proposal_parts(&self, count: usize) {
+        self.served_proposal_parts.inc_by(count as u64);
+    }
+
+    pub fn inc_recovered_proposal_parts(&self) {
+        self.recovered_proposal_parts.inc();
+    }
+
+    pub fn inc_direct_txs(&self, outcome: &str, count: usize) {
+        self.direct_txs
+            .get_or_create(&vec![("outcome".to_string(), outcome.to_string())])
+            .inc_by(count as u64);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PeerMetrics {
+    /// Number of proposal parts ignored because their peer is rejected by the peer filter,
+    /// by reason
+    rejected_proposal_parts: Family<Vec<(String, String)>, Counter>,
+    /// Number of requests of the mapping of the consensus port to the gateway, by mapping
+    /// and result
+    nat_port_mappings: Family<Vec<(String, String)>, Counter>,
+}
+
+impl PeerMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "peer_filter_rejected_proposal_parts",
+                "Number of proposal parts ignored because their peer is rejected by the peer filter, by reason",
+                metrics.rejected_proposal_parts.clone(),
+            );
+
+            registry.register(
+                "p2p_nat_port_mappings",
+                "Number of requests of the mapping of the consensus port to the gateway, by mapping and result",
+                metrics.nat_port_mappings.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn inc_rejected_proposal_parts(&self, reason: Rejection) {
+        self.rejected_proposal_parts
+            .get_or_create(&vec![("reason".to_string(), reason.as_str().to_string())])
+            .inc();
+    }
+
+    pub fn inc_nat_port_mappings(&self, mapping: &str, result: &str) {
+        self.nat_port_mappings
+            .get_or_create(&vec![
+                ("mapping".to_string(), mapping.to_string()),
+                ("result".to_string(), result.to_string()),
+            ])
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SyncMetrics {
+    /// Number of decided values served to syncing peers
+    served_values: Counter,
+
+    /// Size in bytes of the decided values served to syncing peers
+    served_bytes: Counter,
+
+    /// Number of heights requested by syncing peers and not served, by reason
+    unavailable_heights: Family<Vec<(String, String)>, Counter>,
+
+    /// Lowest height served to syncing peers
+    served_earliest_height: Gauge,
+
+    /// Highest height served to syncing peers
+    served_latest_height: Gauge,
+
+    /// Number of pruned heights served from the cache of the rebuilt values
+    value_cache_hits: Counter,
+
+    /// Number of pruned heights rebuilt from the execution client
+    value_cache_misses: Counter,
+
+    /// Size in bytes of the values held by the cache of the rebuilt values
+    value_cache_bytes: Gauge,
+
+    /// Number of values synced from the peers delayed by the catch-up throttle, by reason
+    throttled_values: Family<Vec<(String, String)>, Counter>,
+}
+
+impl SyncMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "sync_served_values",
+                "Number of decided values served to syncing peers",
+                metrics.served_values.clone(),
+            );
+
+            registry.register(
+                "sync_served_bytes",
+                "Size in bytes of the decided values served to syncing peers",
+                metrics.served_bytes.clone(),
+            );
+
+            registry.register(
+                "sync_unavailable_heights",
+                "Number of heights requested by syncing peers and not served, by reason",
+                metrics.unavailable_heights.clone(),
+            );
+
+            registry.register(
+                "sync_served_earliest_height",
+                "Lowest height served to syncing peers",
+                metrics.served_earliest_height.clone(),
+            );
+
+            registry.register(
+                "sync_served_latest_height",
+                "Highest height served to syncing peers",
+                metrics.served_latest_height.clone(),
+            );
+
+            registry.register(
+                "sync_value_cache_hits",
+                "Number of pruned heights served from the cache of the rebuilt values",
+                metrics.value_cache_hits.clone(),
+            );
+
+            registry.register(
+                "sync_value_cache_misses",
+                "Number of pruned heights rebuilt from the execution client",
+                metrics.value_cache_misses.clone(),
+            );
+
+            registry.register(
+                "sync_value_cache_bytes",
+                "Size in bytes of the values held by the cache of the rebuilt values",
+                metrics.value_cache_bytes.clone(),
+            );
+
+            registry.register(
+                "sync_throttled_values",
+                "Number of values synced from the peers delayed by the catch-up throttle, by reason",
+                metrics.throttled_values.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn inc_served_values(&self, bytes: u64) {
+        self.served_values.inc();
+        self.served_bytes.inc_by(bytes);
+    }
+
+    pub fn inc_unavailable_heights(&self, reason: HeightUnavailable) {
+        self.unavailable_heights
+            .get_or_create(&vec![("reason".to_string(), reason.as_str().to_string())])
+            .inc();
+    }
+
+    pub fn inc_throttled_values(&self, reason: &str) {
+        self.throttled_values
+            .get_or_create(&vec![("reason".to_string(), reason.to_string())])
+            .inc();
+    }
+
+    pub fn set_served_heights(&self, earliest: u64, latest: u64) {
+        self.served_earliest_height.set(earliest as i64);
+        self.served_latest_height.set(latest as i64);
+    }
+
+    pub fn inc_value_cache_lookups(&self, hit: bool) {
+        if hit {
+            self.value_cache_hits.inc();
+        } else {
+            self.value_cache_misses.inc();
+        }
+    }
+
+    pub fn set_value_cache_bytes(&self, bytes: u64) {
+        self.value_cache_bytes.set(bytes as i64);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ValidationMetrics {
+    /// Number of payloads rejected without asking the execution client, by reason
+    rejected_payloads: Family<Vec<(String, String)>, Counter>,
+
+    /// Number of proposals handled from their init part, their payload being already known
+    deduplicated_proposals: Counter,
+
+    /// Number of commit certificates of synced values rejected, by reason
+    rejected_certificates: Family<Vec<(String, String)>, Counter>,
+
+    /// Number of proposal streams dropped for being malformed, by reason
+    malformed_streams: Family<Vec<(String, String)>, Counter>,
+
+    /// Number of times the execution client diverged from the decided blocks
+    el_divergences: Counter,
+
+    /// Number of divergences of the blocks re-executed in shadow-fork mode, by kind
+    shadow_fork_divergences: Family<Vec<(String, String)>, Counter>,
+}
+
+impl ValidationMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "rejected_payloads",
+                "Number of payloads rejected without asking the execution client, by reason",
+                metrics.rejected_payloads.clone(),
+            );
+
+            registry.register(
+                "deduplicated_proposals",
+                "Number of proposals handled from their init part, their payload being already known",
+                metrics.deduplicated_proposals.clone(),
+            );
+
+            registry.register(
+                "rejected_certificates",
+                "Number of commit certificates of synced values rejected, by reason",
+                metrics.rejected_certificates.clone(),
+            );
+
+            registry.register(
+                "malformed_streams",
+                "Number of proposal streams dropped for being malformed, by reason",
+                metrics.malformed_streams.clone(),
+            );
+
+            registry.register(
+                "el_divergences",
+                "Number of times the execution client diverged from the decided blocks",
+                metrics.el_divergences.clone(),
+            );
+
+            registry.register(
+                "shadow_fork_divergences",
+                "Number of divergences of the blocks re-executed in shadow-fork mode, by kind",
+                metrics.shadow_fork_divergences.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn inc_rejected_payloads(&self, reason: &str) {
+        self.rejected_payloads
+            .get_or_create(&vec![("reason".to_string(), reason.to_string())])
+            .inc();
+    }
+
+    pub fn inc_deduplicated_proposals(&self) {
+        self.deduplicated_proposals.inc();
+    }
+
+    pub fn inc_rejected_certificates(&self, reason: &str) {
+        self.rejected_certificates
+            .get_or_create(&vec![("reason".to_string(), reason.to_string())])
+            .inc();
+    }
+
+    pub fn inc_malformed_streams(&self, reason: &str) {
+        self.malformed_streams
+            .get_or_create(&vec![("reason".to_string(), reason.to_string())])
+            .inc();
+    }
+
+    pub fn inc_el_divergences(&self) {
+        self.el_divergences.inc();
+    }
+
+    pub fn inc_shadow_fork_divergences(&self, kind: &str) {
+        self.shadow_fork_divergences
+            .get_or_create(&vec![("kind".to_string(), kind.to_string())])
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VoteMetrics {
+    /// Number of votes seen, by validator and vote type
+    votes: Family<Vec<(String, String)>, Counter>,
+
+    /// Delay of the votes relative to the first vote of the same type in the round (seconds),
+    /// by validator and vote type
+    vote_delay: Family<Vec<(String, String)>, Histogram, fn() -> Histogram>,
+
+    /// Latest height at which a vote was seen, by validator
+    vote_latest_height: Family<Vec<(String, String)>, Gauge>,
+}
+
+impl Default for VoteMetrics {
+    fn default() -> Self {
+        Self {
+            votes: Family::default(),
+            vote_delay: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.001, 2.0, 14)) // Start from 1ms
+            }),
+            vote_latest_height: Family::default(),
+        }
+    }
+}
+
+impl VoteMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self::default();
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "votes",
+                "Number of votes seen, by validator and vote type",
+                metrics.votes.clone(),
+            );
+
+            registry.register(
+                "vote_delay",
+                "Delay of the votes relative to the first vote of the same type in the round (seconds)",
+                metrics.vote_delay.clone(),
+            );
+
+            registry.register(
+                "vote_latest_height",
+                "Latest height at which a vote was seen, by validator",
+                metrics.vote_latest_height.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn observe_vote(&self, vote: &Vote, delay: Duration) {
+        let validator = ("validator".to_string(), vote.validator_address.to_string());
+        let vote_type = match vote.typ {
+            VoteType::Prevote => "prevote",
+            VoteType::Precommit => "precommit",
+        };
+        let labels = vec![
+            validator.clone(),
+            ("type".to_string(), vote_type.to_string()),
+        ];
+
+        self.votes.get_or_create(&labels).inc();
+        self.vote_delay
+            .get_or_create(&labels)
+            .observe(delay.as_secs_f64());
+
+        let latest_height = self.vote_latest_height.get_or_create(&vec![validator]);
+        latest_height.set(latest_height.get().max(vote.height.as_u64() as i64));
+    }
+}
+
+/// Number of proposers not listed in `validator_monikers` labelled with their address,
+/// the next ones being labelled as `other`
+const MAX_UNLISTED_PROPOSERS: usize = 64;
+
+/// Labels of the proposers in the per-proposer metrics: their moniker if listed in the
+/// address book of the config, otherwise their address, up to [`MAX_UNLISTED_PROPOSERS`]
+#[derive(Debug, Default)]
+struct ProposerLabels {
+    monikers: BTreeMap<Address, String>,
+    unlisted: Mutex<BTreeSet<Address>>,
+}
+
+impl ProposerLabels {
+    fn labels(&self, proposer: &Address) -> Vec<(String, String)> {
+        let label = match self.monikers.get(proposer) {
+            Some(moniker) => moniker.clone(),
+            None => {
+                let mut unlisted = self.unlisted.lock().expect("poisoned lock");
+                if unlisted.contains(proposer) || unlisted.len() < MAX_UNLISTED_PROPOSERS {
+                    unlisted.insert(*proposer);
+                    proposer.to_string()
+                } else {
+                    "other".to_string()
+                }
+            }
+        };
+
+        vec![("proposer".to_string(), label)]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockMetrics {
+    proposers: Arc<ProposerLabels>,
+
+    /// Number of blocks decided, by proposer
+    decided_blocks: Family<Vec<(String, String)>, Counter>,
+
+    /// Time between the commit of a block and of the previous one (seconds), by proposer
+    block_time: Family<Vec<(String, String)>, Histogram, fn() -> Histogram>,
+
+    /// Number of rounds of the decided heights which ended without a decision, by proposer
+    failed_rounds: Family<Vec<(String, String)>, Counter>,
+
+    /// Number of proposals with a valid signature rejected by this node, by proposer
+    rejected_proposals: Family<Vec<(String, String)>, Counter>,
+}
+
+impl Default for BlockMetrics {
+    fn default() -> Self {
+        Self {
+            proposers: Arc::default(),
+            decided_blocks: Family::default(),
+            block_time: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.05, 2.0, 12)) // Start from 50ms
+            }),
+            failed_rounds: Family::default(),
+            rejected_proposals: Family::default(),
+        }
+    }
+}
+
+impl BlockMetrics {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
+        let metrics = Self {
+            proposers: Arc::new(ProposerLabels {
+                monikers: config.validator_monikers.clone(),
+                unlisted: Mutex::default(),
+            }),
+            ..Self::default()
+        };
+
+        with_scope(registry, config, |registry| {
+            registry.register(
+                "decided_blocks",
+                "Number of blocks decided, by proposer",
+                metrics.decided_blocks.clone(),
+            );
+
+            registry.register(
+                "proposer_block_time",
+                "Time between the commit of a block and of the previous one (seconds), by proposer",
+                metrics.block_time.clone(),
+            );
+
+            registry.register(
+                "failed_rounds",
+                "Number of rounds of the decided heights which ended without a decision, by proposer",
+                metrics.failed_rounds.clone(),
+            );
+
+            registry.register(
+                "rejected_proposals",
+                "Number of proposals with a valid signature rejected by this node, by proposer",
+                metrics.rejected_proposals.clone(),
+            );
+        });
+
+        metrics
+    }
+
+    pub fn observe_decided_block(&self, proposer: &Address, block_time: Duration) {
+        let labels = self.proposers.labels(proposer);
+        self.decided_blocks.get_or_create(&labels).inc();
+        self.block_time
+            .get_or_create(&labels)
+            .observe(block_time.as_secs_f64());
+    }
+
+    pub fn inc_failed_rounds(&self, proposer: &Address) {
+        self.failed_rounds
+            .get_or_create(&self.proposers.labels(proposer))
+            .inc();
+    }
+
+    pub fn inc_rejected_proposals(&self, proposer: &Address) {
+        self.rejected_proposals
+            .get_or_create(&self.proposers.labels(proposer))
+            .inc();
+    }
+}
+
 /// Unified metrics container for all application metrics
 #[derive(Clone, Debug)]
 pub struct Metrics {
+    pub node: NodeMetrics,
     pub db: DbMetrics,
     pub tx_stats: TxStatsMetrics,
+    pub el: ElMetrics,
+    pub proposer: ProposerMetrics,
+    pub validation: ValidationMetrics,
+    pub peers: PeerMetrics,
+    pub sync: SyncMetrics,
+    pub votes: VoteMetrics,
+    pub blocks: BlockMetrics,
 }
 
 impl Metrics {
     pub fn new() -> Self {
         Self {
+            node: NodeMetrics::default(),
             db: DbMetrics::new(),
             tx_stats: TxStatsMetrics::new(),
+            el: ElMetrics::default(),
+            proposer: ProposerMetrics::default(),
+            validation: ValidationMetrics::default(),
+            peers: PeerMetrics::default(),
+            sync: SyncMetrics::default(),
+            votes: VoteMetrics::default(),
+            blocks: BlockMetrics::default(),
         }
     }
 
-    pub fn register(registry: &SharedRegistry) -> Self {
+    pub fn register(registry: &SharedRegistry, config: &AppMetricsConfig) -> Self {
         Self {
-            db: DbMetrics::register(registry),
-            tx_stats: TxStatsMetrics::register(registry),
+            node: NodeMetrics::register(registry, config),
+            db: DbMetrics::register(registry, config),
+            tx_stats: TxStatsMetrics::register(registry, config),
+            el: ElMetrics::register(registry, config),
+            proposer: ProposerMetrics::register(registry, config),
+            validation: ValidationMetrics::register(registry, config),
+            peers: PeerMetrics::register(registry, config),
+            sync: SyncMetrics::register(registry, config),
+            votes: VoteMetrics::register(registry, config),
+            blocks: BlockMetrics::register(registry, config),
         }
     }
 }
@@ -345,3 +1386,79 @@ impl Default for Metrics {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(proposers: &ProposerLabels, proposer: &Address) -> String {
+        let labels = proposers.labels(proposer);
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].0, "proposer");
+        labels[0].1.clone()
+    }
+
+    #[test]
+    fn test_proposers_are_labelled_by_moniker_or_address() {
+        let listed = Address::repeat_byte(1);
+        let proposers = ProposerLabels {
+            monikers: [(listed, "validator-1".to_string())].into(),
+            unlisted: Mutex::default(),
+        };
+
+        assert_eq!(labels(&proposers, &listed), "validator-1");
+        let unlisted = Address::repeat_byte(2);
+        assert_eq!(labels(&proposers, &unlisted), unlisted.to_string());
+        // The listed proposers do not count towards the unlisted ones
+        assert_eq!(proposers.unlisted.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unlisted_proposers_are_capped() {
+        let proposers = ProposerLabels::default();
+        let address = |i: usize| {
+            let mut bytes = [0; 20];
+            bytes[12..].copy_from_slice(&(i as u64).to_be_bytes());
+            Address::new(bytes)
+        };
+
+        for i in 0..MAX_UNLISTED_PROPOSERS {
+            assert_eq!(labels(&proposers, &address(i)), address(i).to_string());
+        }
+
+        // The next proposers share a label, the ones already seen keep theirs
+        let next = address(MAX_UNLISTED_PROPOSERS);
+        assert_eq!(labels(&proposers, &next), "other");
+        assert_eq!(labels(&proposers, &address(0)), address(0).to_string());
+    }
+
+    #[test]
+    fn test_blocks_are_counted_by_proposer() {
+        let metrics = BlockMetrics {
+            proposers: Arc::new(ProposerLabels {
+                monikers: [(Address::repeat_byte(1), "validator-1".to_string())].into(),
+                unlisted: Mutex::default(),
+            }),
+            ..BlockMetrics::default()
+        };
+        let by = |proposer: &str| vec![("proposer".to_string(), proposer.to_string())];
+
+        metrics.observe_decided_block(&Address::repeat_byte(1), Duration::from_millis(500));
+        metrics.observe_decided_block(&Address::repeat_byte(1), Duration::from_millis(500));
+        metrics.inc_failed_rounds(&Address::repeat_byte(2));
+
+        assert_eq!(
+            metrics
+                .decided_blocks
+                .get_or_create(&by("validator-1"))
+                .get(),
+            2
+        );
+        let unlisted = Address::repeat_byte(2).to_string();
+        assert_eq!(metrics.failed_rounds.get_or_create(&by(&unlisted)).get(), 1);
+        assert_eq!(
+            metrics.decided_blocks.get_or_create(&by(&unlisted)).get(),
+            0
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Persistence of the cumulative metrics, off the path of the decided values.
+//!
+//! The number of transactions, the bytes of the chain and the time spent measuring them
+//! are persisted so that the throughput keeps accumulating across restarts. Rather than
+//! being written to the store for every decided block, the latest values are sent to an
+//! aggregator task, which writes them at most once per [`FLUSH_INTERVAL`] and once more
+//! when the application stops. A crash loses at most the last interval of these metrics.
+
+use core::time::Duration;
+
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::error;
+
+use crate::store::Store;
+
+/// Interval between two writes of the cumulative metrics
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Metrics accumulated since the first start of the node
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CumulativeMetrics {
+    pub txs_count: u64,
+    pub chain_bytes: u64,
+    pub elapsed_seconds: u64,
+}
+
+/// Handle of the aggregator task writing the cumulative metrics to the store
+#[derive(Debug)]
+pub struct MetricsAggregator {
+    tx: watch::Sender<CumulativeMetrics>,
+    stop: Option<(oneshot::Sender<()>, JoinHandle<()>)>,
+}
+
+impl MetricsAggregator {
+    /// Spawns the aggregator, starting from the metrics loaded from the store
+    pub fn spawn(store: Store, initial: CumulativeMetrics) -> Self {
+        Self::spawn_with_interval(store, initial, FLUSH_INTERVAL)
+    }
+
+    fn spawn_with_interval(store: Store, initial: CumulativeMetrics, interval: Duration) -> Self {
+        let (tx, rx) = watch::channel(initial);
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(run(store, rx, stop_rx, interval));
+
+        Self {
+            tx,
+            stop: Some((stop_tx, task)),
+        }
+    }
+
+    /// Records the latest metrics, written with the next flush
+    pub fn update(&self, metrics: CumulativeMetrics) {
+        self.tx.send_replace(metrics);
+    }
+
+    /// Writes the latest metrics and stops the aggregator
+    pub async fn shutdown(&mut self) {
+        if let Some((stop, task)) = self.stop.take() {
+            let _ = stop.send(());
+            if let Err(e) = task.await {
+                error!(%e, "Metrics aggregator failed");
+            }
+        }
+    }
+}
+
+async fn run(
+    store: Store,
+    mut rx: watch::Receiver<CumulativeMetrics>,
+    mut stop: oneshot::Receiver<()>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        // The application dropping the handle stops the aggregator as well
+        let stopping = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = &mut stop => true,
+        };
+
+        // Once the handle is dropped, the last metrics are written anyway
+        if rx.has_changed().unwrap_or(true) {
+            let metrics = *rx.borrow_and_update();
+            if let Err(e) = store
+                .store_cumulative_metrics(
+                    metrics.txs_count,
+                    metrics.chain_bytes,
+                    metrics.elapsed_seconds,
+                )
+                .await
+            {
+                error!(%e, "Failed to persist the cumulative metrics");
+            }
+        }
+
+        if stopping {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_eth_cli::config::StoreLimitsConfig;
+
+    use super::*;
+    use crate::metrics::DbMetrics;
+
+    fn metrics(txs_count: u64) -> CumulativeMetrics {
+        CumulativeMetrics {
+            txs_count,
+            chain_bytes: txs_count * 100,
+            elapsed_seconds: txs_count * 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_aggregator() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(
+            dir.path().join("store.db"),
+            DbMetrics::new(),
+            None,
+            StoreLimitsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut aggregator = MetricsAggregator::spawn_with_interval(
+            store.clone(),
+            metrics(0),
+            Duration::from_secs(3600),
+        );
+        aggregator.update(metrics(1));
+        aggregator.update(metrics(2));
+
+        // Only the latest metrics are written, on shutdown at the latest
+        aggregator.shutdown().await;
+        assert_eq!(
+            store.load_cumulative_metrics().await.unwrap(),
+            Some((2, 200, 4))
+        );
+    }
+}
@@ -0,0 +1,518 @@
+//! Reachability of the consensus port of a node behind a NAT.
+//!
+//! The peers dial a node at the addresses of their persistent peers, which the generated
+//! configs set to the listen address of the node. A node behind a NAT is reached at the
+//! public address of its gateway instead, the consensus port being forwarded to it:
+//! - statically by the operator, the node being reached at `external_address`,
+//! - with UPnP IGD or NAT-PMP, the node requesting the mapping from its gateway at startup,
+//!   and renewing it at half of its lifetime.
+//!
+//! The multiaddr at which the peers reach the node is logged and served in the status of
+//! the admin API, to be put in the persistent peers of the other nodes. The generator of
+//! distributed testnets reads `external_address` and `external_port` from the emerald
+//! config of each node for that purpose.
+//!
+//! The mappings are not removed when the node stops, and expire with their lifetime.
+
+use core::time::Duration;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+use color_eyre::eyre::{self, eyre, Context};
+use igd_next::aio::tokio::search_gateway;
+use igd_next::{PortMappingProtocol, SearchOptions};
+use malachitebft_eth_cli::config::{NatMapping, P2pNatConfig};
+use tracing::{info, warn};
+
+use crate::metrics::PeerMetrics;
+use crate::node_status::SharedNodeStatus;
+
+/// Delay before requesting a mapping again after a failure
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Time to wait for the UPnP gateway to answer the discovery
+const UPNP_SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Description of the UPnP mappings, shown by the gateway
+const UPNP_DESCRIPTION: &str = "emerald consensus";
+
+/// Port of the NAT-PMP server of the gateway
+const NAT_PMP_PORT: u16 = 5351;
+
+/// Number of NAT-PMP requests sent before giving up, the first one waiting 250ms for an
+/// answer and each retransmission twice as long as the previous one
+const NAT_PMP_ATTEMPTS: u32 = 5;
+
+/// Transport protocol of the consensus port
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PortProtocol {
+    Tcp,
+    Udp,
+}
+
+impl PortProtocol {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+/// Consensus port, as found in the listen multiaddr of the node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListenPort {
+    /// Address the node listens on, if the multiaddr has one
+    pub ip: Option<IpAddr>,
+    pub protocol: PortProtocol,
+    pub port: u16,
+    /// Protocols after the port, e.g. `/quic-v1`
+    pub suffix: String,
+}
+
+impl ListenPort {
+    /// Parses a listen multiaddr such as `/ip4/0.0.0.0/udp/27000/quic-v1`
+    pub fn parse(multiaddr: &str) -> Option<Self> {
+        let parts: Vec<&str> = multiaddr.split('/').collect();
+        let index = parts
+            .iter()
+            .position(|part| matches!(*part, "tcp" | "udp"))?;
+        let protocol = match parts[index] {
+            "tcp" => PortProtocol::Tcp,
+            _ => PortProtocol::Udp,
+        };
+        let port = parts.get(index + 1)?.parse().ok()?;
+        let ip = match parts.get(1..3) {
+            Some(["ip4" | "ip6", ip]) => ip.parse().ok(),
+            _ => None,
+        };
+        let suffix = parts[index + 2..]
+            .iter()
+            .map(|part| format!("/{part}"))
+            .collect();
+
+        Some(Self {
+            ip,
+            protocol,
+            port,
+            suffix,
+        })
+    }
+
+    /// Multiaddr at which the peers reach this port at `host`, an IP or a DNS name, on
+    /// `port` of the gateway
+    pub fn external_multiaddr(&self, host: &str, port: u16) -> String {
+        let host = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => format!("/ip4/{ip}"),
+            Ok(IpAddr::V6(ip)) => format!("/ip6/{ip}"),
+            Err(_) => format!("/dns/{host}"),
+        };
+        format!("{host}/{}/{port}{}", self.protocol.as_str(), self.suffix)
+    }
+}
+
+/// Name of a mapping, in the logs and the metrics
+fn mapping_name(mapping: NatMapping) -> &'static str {
+    match mapping {
+        NatMapping::Static => "static",
+        NatMapping::Upnp => "upnp",
+        NatMapping::NatPmp => "nat_pmp",
+    }
+}
+
+/// Port mapped on the gateway
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Mapping {
+    external_ip: IpAddr,
+    external_port: u16,
+    lifetime: Duration,
+}
+
+/// Makes the consensus port reachable as set by `config`, and records the multiaddr at
+/// which the peers reach it in the status of the node
+#[tracing::instrument(name = "p2p_nat", skip_all)]
+pub async fn run(
+    config: P2pNatConfig,
+    listen: ListenPort,
+    status: SharedNodeStatus,
+    metrics: PeerMetrics,
+) {
+    if listen.ip.is_some_and(|ip| ip.is_loopback()) {
+        warn!("⚠️  The consensus port listens on a loopback address, unreachable from the peers");
+    }
+
+    let external_port = config.external_port.unwrap_or(listen.port);
+    let mapping = mapping_name(config.mapping);
+
+    if config.mapping == NatMapping::Static {
+        // The external address is required by the validation of the config
+        let host = config.external_address.as_deref().unwrap_or_default();
+        let multiaddr = listen.external_multiaddr(host, external_port);
+        info!(%mapping, %multiaddr, "Peers reach the consensus port at the external address");
+        status.set_external_address(multiaddr);
+        return;
+    }
+
+    let mut advertised = None;
+    loop {
+        let delay = match map_port(&config, &listen, external_port).await {
+            Ok(mapped) => {
+                metrics.inc_nat_port_mappings(mapping, "mapped");
+
+                let host = config
+                    .external_address
+                    .clone()
+                    .unwrap_or_else(|| mapped.external_ip.to_string());
+                let multiaddr = listen.external_multiaddr(&host, mapped.external_port);
+                if advertised.as_ref() != Some(&multiaddr) {
+                    info!(%mapping, %multiaddr, "Mapped the consensus port on the gateway");
+                    status.set_external_address(multiaddr.clone());
+                    advertised = Some(multiaddr);
+                }
+
+                (mapped.lifetime / 2).max(RETRY_DELAY)
+            }
+            Err(e) => {
+                metrics.inc_nat_port_mappings(mapping, "failed");
+                warn!(%mapping, "⚠️  Failed to map the consensus port on the gateway: {e:#}");
+                RETRY_DELAY
+            }
+        };
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn map_port(
+    config: &P2pNatConfig,
+    listen: &ListenPort,
+    external_port: u16,
+) -> eyre::Result<Mapping> {
+    match config.mapping {
+        NatMapping::Static => Err(eyre!("Static mappings are set on the gateway")),
+        NatMapping::Upnp => map_upnp(listen, external_port, config.lease_duration).await,
+        NatMapping::NatPmp => {
+            let gateway = match config.gateway {
+                Some(IpAddr::V4(gateway)) => gateway,
+                Some(IpAddr::V6(gateway)) => {
+                    return Err(eyre!("NAT-PMP gateway {gateway} is not an IPv4 address"))
+                }
+                None => default_gateway()?,
+            };
+            map_nat_pmp(gateway, listen, external_port, config.lease_duration).await
+        }
+    }
+}
+
+/// Address of this host on the network of `gateway`, unless the node listens on a
+/// specific one
+fn internal_ip(listen: &ListenPort, gateway: IpAddr) -> eyre::Result<IpAddr> {
+    if let Some(ip) = listen.ip.filter(|ip| !ip.is_unspecified()) {
+        return Ok(ip);
+    }
+
+    // Connecting a UDP socket sends nothing, but picks the interface routing to the gateway
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect((gateway, NAT_PMP_PORT))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Lifetime of a mapping in seconds, as requested from the gateway
+fn lifetime_secs(lease_duration: Duration) -> u32 {
+    u32::try_from(lease_duration.as_secs()).unwrap_or(u32::MAX)
+}
+
+async fn map_upnp(
+    listen: &ListenPort,
+    external_port: u16,
+    lease_duration: Duration,
+) -> eyre::Result<Mapping> {
+    let gateway = search_gateway(SearchOptions {
+        timeout: Some(UPNP_SEARCH_TIMEOUT),
+        ..Default::default()
+    })
+    .await
+    .wrap_err("No UPnP gateway found")?;
+
+    let internal = SocketAddr::new(internal_ip(listen, gateway.addr.ip())?, listen.port);
+    let protocol = match listen.protocol {
+        PortProtocol::Tcp => PortMappingProtocol::TCP,
+        PortProtocol::Udp => PortMappingProtocol::UDP,
+    };
+    gateway
+        .add_port(
+            protocol,
+            external_port,
+            internal,
+            lifetime_secs(lease_duration),
+            UPNP_DESCRIPTION,
+        )
+        .await
+        .wrap_err_with(|| format!("UPnP gateway {} refused the mapping", gateway.addr))?;
+
+    let external_ip = gateway
+        .get_external_ip()
+        .await
+        .wrap_err("Failed to get the external address of the UPnP gateway")?;
+
+    Ok(Mapping {
+        external_ip,
+        external_port,
+        lifetime: lease_duration,
+    })
+}
+
+async fn map_nat_pmp(
+    gateway: Ipv4Addr,
+    listen: &ListenPort,
+    external_port: u16,
+    lease_duration: Duration,
+) -> eyre::Result<Mapping> {
+    let response = nat_pmp_request(gateway, &nat_pmp::external_address_request()).await?;
+    let external_ip = nat_pmp::decode_external_address(&response)
+        .map_err(|e| eyre!("NAT-PMP gateway {gateway} did not give its external address: {e}"))?;
+
+    let request = nat_pmp::mapping_request(
+        listen.protocol,
+        listen.port,
+        external_port,
+        lifetime_secs(lease_duration),
+    );
+    let response = nat_pmp_request(gateway, &request).await?;
+    let mapped = nat_pmp::decode_mapping(&response, listen.protocol)
+        .map_err(|e| eyre!("NAT-PMP gateway {gateway} refused the mapping: {e}"))?;
+    if mapped.internal_port != listen.port {
+        return Err(eyre!(
+            "NAT-PMP gateway {gateway} mapped port {} instead of {}",
+            mapped.internal_port,
+            listen.port
+        ));
+    }
+
+    Ok(Mapping {
+        external_ip: IpAddr::V4(external_ip),
+        external_port: mapped.external_port,
+        lifetime: Duration::from_secs(mapped.lifetime_secs.into()),
+    })
+}
+
+/// Sends a NAT-PMP request to `gateway`, retransmitting it until it is answered
+async fn nat_pmp_request(gateway: Ipv4Addr, request: &[u8]) -> eyre::Result<Vec<u8>> {
+    let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect((gateway, NAT_PMP_PORT)).await?;
+
+    let mut buf = [0; 16];
+    let mut timeout = Duration::from_millis(250);
+    for _ in 0..NAT_PMP_ATTEMPTS {
+        socket.send(request).await?;
+        if let Ok(received) = tokio::time::timeout(timeout, socket.recv(&mut buf)).await {
+            return Ok(buf[..received?].to_vec());
+        }
+        timeout *= 2;
+    }
+
+    Err(eyre!("NAT-PMP gateway {gateway} did not answer"))
+}
+
+/// Default IPv4 gateway of the host, from the routing table of Linux
+fn default_gateway() -> eyre::Result<Ipv4Addr> {
+    let route_table = std::fs::read_to_string("/proc/net/route")
+        .wrap_err("Failed to read the routing table, set the gateway of p2p_nat")?;
+    parse_default_gateway(&route_table)
+        .ok_or_else(|| eyre!("No default route found, set the gateway of p2p_nat"))
+}
+
+/// Gateway of the default route in `/proc/net/route`, whose addresses are hex-encoded
+/// in the byte order of the host
+fn parse_default_gateway(route_table: &str) -> Option<Ipv4Addr> {
+    route_table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        (gateway != 0).then(|| Ipv4Addr::from(gateway.to_ne_bytes()))
+    })
+}
+
+/// Messages of NAT-PMP, as specified by RFC 6886
+mod nat_pmp {
+    use std::net::Ipv4Addr;
+
+    use super::PortProtocol;
+
+    const VERSION: u8 = 0;
+    /// Added to the opcode of a request in its response
+    const RESPONSE: u8 = 128;
+
+    /// Port mapped by the gateway
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct MappedPort {
+        pub internal_port: u16,
+        pub external_port: u16,
+        pub lifetime_secs: u32,
+    }
+
+    fn opcode(protocol: PortProtocol) -> u8 {
+        match protocol {
+            PortProtocol::Udp => 1,
+            PortProtocol::Tcp => 2,
+        }
+    }
+
+    /// Request of the external address of the gateway, section 3.2
+    pub fn external_address_request() -> [u8; 2] {
+        [VERSION, 0]
+    }
+
+    /// Request of the mapping of `internal_port`, section 3.3
+    pub fn mapping_request(
+        protocol: PortProtocol,
+        internal_port: u16,
+        external_port: u16,
+        lifetime_secs: u32,
+    ) -> [u8; 12] {
+        let mut request = [0; 12];
+        request[0] = VERSION;
+        request[1] = opcode(protocol);
+        request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+        request[6..8].copy_from_slice(&external_port.to_be_bytes());
+        request[8..12].copy_from_slice(&lifetime_secs.to_be_bytes());
+        request
+    }
+
+    /// Checks the header of a response to a request with `opcode`, section 3.5
+    fn check_header(response: &[u8], opcode: u8, len: usize) -> Result<(), String> {
+        if response.len() < len {
+            return Err(format!("response of {} bytes is too short", response.len()));
+        }
+        if response[0] != VERSION || response[1] != RESPONSE + opcode {
+            return Err(format!(
+                "unexpected response version {} and opcode {}",
+                response[0], response[1]
+            ));
+        }
+
+        match u16::from_be_bytes([response[2], response[3]]) {
+            0 => Ok(()),
+            1 => Err("unsupported version".to_string()),
+            2 => Err("not authorized".to_string()),
+            3 => Err("network failure".to_string()),
+            4 => Err("out of resources".to_string()),
+            5 => Err("unsupported opcode".to_string()),
+            code => Err(format!("result code {code}")),
+        }
+    }
+
+    pub fn decode_external_address(response: &[u8]) -> Result<Ipv4Addr, String> {
+        check_header(response, 0, 12)?;
+        Ok(Ipv4Addr::new(
+            response[8],
+            response[9],
+            response[10],
+            response[11],
+        ))
+    }
+
+    pub fn decode_mapping(response: &[u8], protocol: PortProtocol) -> Result<MappedPort, String> {
+        check_header(response, opcode(protocol), 16)?;
+        Ok(MappedPort {
+            internal_port: u16::from_be_bytes([response[8], response[9]]),
+            external_port: u16::from_be_bytes([response[10], response[11]]),
+            lifetime_secs: u32::from_be_bytes([
+                response[12],
+                response[13],
+                response[14],
+                response[15],
+            ]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_port() {
+        let quic = ListenPort::parse("/ip4/0.0.0.0/udp/27000/quic-v1").unwrap();
+        assert_eq!(
+            quic,
+            ListenPort {
+                ip: Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                protocol: PortProtocol::Udp,
+                port: 27000,
+                suffix: "/quic-v1".to_string(),
+            }
+        );
+        assert_eq!(
+            quic.external_multiaddr("203.0.113.7", 30000),
+            "/ip4/203.0.113.7/udp/30000/quic-v1"
+        );
+        assert_eq!(
+            quic.external_multiaddr("validator-1.example.com", 27000),
+            "/dns/validator-1.example.com/udp/27000/quic-v1"
+        );
+
+        let tcp = ListenPort::parse("/ip6/::1/tcp/27001").unwrap();
+        assert_eq!(tcp.ip, Some("::1".parse().unwrap()));
+        assert_eq!(tcp.protocol, PortProtocol::Tcp);
+        assert_eq!(
+            tcp.external_multiaddr("2001:db8::7", 27001),
+            "/ip6/2001:db8::7/tcp/27001"
+        );
+
+        assert_eq!(ListenPort::parse("/ip4/127.0.0.1"), None);
+        assert_eq!(ListenPort::parse("/ip4/127.0.0.1/udp/port"), None);
+    }
+
+    #[test]
+    fn test_nat_pmp_messages() {
+        assert_eq!(
+            nat_pmp::mapping_request(PortProtocol::Udp, 27000, 30000, 3600),
+            [0, 1, 0, 0, 0x69, 0x78, 0x75, 0x30, 0, 0, 0x0e, 0x10]
+        );
+
+        let external_address = [0, 128, 0, 0, 0, 0, 0, 42, 203, 0, 113, 7];
+        assert_eq!(
+            nat_pmp::decode_external_address(&external_address),
+            Ok(Ipv4Addr::new(203, 0, 113, 7))
+        );
+
+        let mapping = [
+            0, 129, 0, 0, 0, 0, 0, 42, 0x69, 0x78, 0x75, 0x31, 0, 0, 0x07, 0x08,
+        ];
+        assert_eq!(
+            nat_pmp::decode_mapping(&mapping, PortProtocol::Udp),
+            Ok(nat_pmp::MappedPort {
+                internal_port: 27000,
+                external_port: 30001,
+                lifetime_secs: 1800,
+            })
+        );
+        // A response to another opcode
+        assert!(nat_pmp::decode_mapping(&mapping, PortProtocol::Tcp).is_err());
+
+        let refused = [0, 129, 0, 2, 0, 0, 0, 42, 0x69, 0x78, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            nat_pmp::decode_mapping(&refused, PortProtocol::Udp),
+            Err("not authorized".to_string())
+        );
+        assert!(nat_pmp::decode_external_address(&external_address[..8]).is_err());
+    }
+
+    #[test]
+    fn test_parse_default_gateway() {
+        let gateway = Ipv4Addr::new(192, 168, 1, 254);
+        let encoded = format!("{:08X}", u32::from_ne_bytes(gateway.octets()));
+        let route_table = format!(
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+             eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+             eth0\t00000000\t{encoded}\t0003\t0\t0\t100\t00000000\n"
+        );
+        assert_eq!(parse_default_gateway(&route_table), Some(gateway));
+
+        let no_default_route = "Iface\tDestination\tGateway \tFlags\n\
+             eth0\t0001A8C0\t00000000\t0001\n";
+        assert_eq!(parse_default_gateway(no_default_route), None);
+    }
+}
@@ -2,11 +2,15 @@
 //! cryptographic library used for signing.
 
 use core::str::FromStr;
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use alloy_rpc_types_engine::ForkchoiceState;
 use async_trait::async_trait;
-use color_eyre::eyre;
+use color_eyre::eyre::{self, eyre, Context};
+use emerald_retry::SystemClock;
 use libp2p_identity::Keypair;
 use malachitebft_app_channel::app::events::{RxEvent, TxEvent};
 use malachitebft_app_channel::app::metrics::SharedRegistry;
@@ -15,23 +19,57 @@ use malachitebft_app_channel::app::node::{
 };
 use malachitebft_app_channel::app::types::core::VotingPower;
 use malachitebft_app_channel::Channels;
+use malachitebft_eth_cli::cmd::doctor::Check;
+use malachitebft_eth_cli::cmd::start::NodeMode;
+use malachitebft_eth_cli::cmd::store::CompactionReport;
+use malachitebft_eth_cli::cmd::verify_store::StoreVerification;
 use malachitebft_eth_cli::config::{Config, EmeraldConfig};
+use malachitebft_eth_cli::file::save_priv_validator_key;
+use malachitebft_eth_cli::http::EndpointSecurity;
 use malachitebft_eth_cli::metrics;
+use malachitebft_eth_cli::validation::{validate_config, validate_emerald_config};
 use malachitebft_eth_engine::engine::Engine;
 use malachitebft_eth_engine::engine_rpc::EngineRPC;
 use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use malachitebft_eth_engine::payload_builder::ExternalBuilder;
+use malachitebft_eth_types::codec::proto::version::SharedPeerVersions;
 use malachitebft_eth_types::codec::proto::ProtobufCodec;
 use malachitebft_eth_types::secp256k1::{K256Provider, PrivateKey, PublicKey};
-use malachitebft_eth_types::{Address, EmeraldContext, Genesis, Height, Validator, ValidatorSet};
+use malachitebft_eth_types::{
+    Address, EmeraldContext, Genesis, Hashable, Height, SharedRetryConfig, Validator, ValidatorSet,
+};
 use rand::{CryptoRng, RngCore};
-use tokio::task::JoinHandle;
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{error, info, warn};
 use url::Url;
 
 // Use the same types used for integration tests.
 // A real application would use its own types and context instead.
-use crate::metrics::Metrics;
+use crate::admin;
+use crate::build_info::SharedBuildInfo;
+use crate::catch_up::CatchUpThrottle;
+use crate::config_snapshot::ConfigSnapshot;
+use crate::direct_tx;
+use crate::el_announce::ElAnnouncer;
+use crate::el_health::SharedElHealth;
+use crate::el_snapshot::{backfill_range, Backfill};
+use crate::event_log::EventLog;
+use crate::failover::{Failover, FailoverStop, FencedProvider, LeaseFile, SigningFence};
+use crate::forkchoice::Forkchoice;
+use crate::metrics::{DbMetrics, ElMetrics, Metrics};
+use crate::nat::{self, ListenPort};
+use crate::node_status::SharedNodeStatus;
+use crate::payload::check_fee_recipient;
+use crate::peer_filter::{PeerFilter, SharedPeerFilter};
+use crate::peer_registry::SharedPeerRegistry;
+use crate::rpc_proxy;
+use crate::shadow_fork::ShadowFork;
 use crate::state::{State, StateMetrics};
-use crate::store::Store;
+use crate::store::{Store, StoreCipher, StoreError, STORE_SCHEMA_VERSION};
+use crate::sync_stats::SharedSyncStats;
+use crate::tx_filter::TxFilter;
+use crate::vote_stats::{self, SharedVoteStats};
+use crate::webhooks::Webhooks;
 
 /// Main application struct implementing the consensus node functionality
 #[derive(Clone)]
@@ -42,16 +80,31 @@ pub struct App {
     pub emerald_config_file: PathBuf,
     pub private_key_file: PathBuf,
     pub start_height: Option<Height>,
+    pub mode: NodeMode,
+    /// Number of blocks to profile before stopping, if any
+    pub profile_blocks: Option<u64>,
+    /// Whether the catch-up throttle of the emerald config is disabled
+    pub fast_sync: bool,
+    /// Signing lease checked before each signature with the validator key, with failover
+    pub signing_fence: Option<SigningFence>,
 }
 
+/// File of the key generated at each start in the home directory of a node which does not
+/// take part in consensus
+const EPHEMERAL_KEY_FILE: &str = "ephemeral_key.json";
+
 /// Components needed to run the application
 pub struct AppRuntime {
     pub state: State,
     pub channels: Channels<EmeraldContext>,
     pub engine: Engine,
     pub emerald_config: EmeraldConfig,
+    pub retry_config: SharedRetryConfig,
     pub engine_handle: EngineHandle,
     pub tx_event: TxEvent<EmeraldContext>,
+    pub mode: NodeMode,
+    /// Resolved when the node must stop to switch keys, with failover
+    pub failover: FailoverStop,
 }
 
 impl App {
@@ -64,28 +117,125 @@ impl App {
     /// Returns a [AppRuntime] struct containing the state and all components
     /// needed to run the app.
     pub async fn build_runtime(&self) -> eyre::Result<AppRuntime> {
-        let config = self.load_config()?;
+        let mut config = self.load_config()?;
         let span = tracing::error_span!("node", moniker = %config.moniker);
         let _enter = span.enter();
 
-        let private_key_file = self.load_private_key_file()?;
-        let private_key = self.load_private_key(private_key_file);
-        let public_key = self.get_public_key(&private_key);
-        let address = self.get_address(&public_key);
-        let signing_provider = self.get_signing_provider(private_key);
+        let emerald_config = self.load_emerald_config()?;
+
+        // All the invalid fields are reported before any component starts
+        validate_config(&config).map_err(|errors| eyre!("Invalid node config:\n{errors}"))?;
+        validate_emerald_config(&emerald_config, &config, &self.get_home_dir()).map_err(
+            |errors| {
+                eyre!(
+                    "Invalid emerald config `{}`:\n{errors}",
+                    self.emerald_config_file.display()
+                )
+            },
+        )?;
+
+        let peer_filter = PeerFilter::from_config(&emerald_config.peer_filter)
+            .map_err(|e| eyre!("Invalid peer_filter: {e}"))?;
+        peer_filter.apply(&mut config.consensus.p2p);
+
+        // Validators wait for the proposer delaying its proposal until transactions are available
+        if let Some(max_idle_block_interval) = emerald_config.max_idle_block_interval {
+            config.consensus.timeouts.timeout_propose += max_idle_block_interval;
+        }
+
+        // A node not taking part in consensus never loads the validator key, which failover
+        // would switch to
+        if emerald_config.failover.is_some() {
+            match self.mode {
+                NodeMode::Validator => {}
+                NodeMode::ArchiveSync => {
+                    return Err(eyre!("failover cannot be used in archive-sync mode"))
+                }
+                NodeMode::ShadowFork => {
+                    return Err(eyre!("failover cannot be used in shadow-fork mode"))
+                }
+            }
+        }
+
+        // With failover, the node only signs with the validator key while it holds the lease
+        let failover = match &emerald_config.failover {
+            Some(failover_config) => {
+                let lease = LeaseFile::new(
+                    self.get_home_dir().join(&failover_config.lease_file),
+                    emerald_config.moniker.clone(),
+                );
+                let failover = Failover::start(failover_config.clone(), lease)
+                    .await
+                    .wrap_err("Failed to read the signing lease")?;
+                Some(failover)
+            }
+            None => None,
+        };
+        let validator_address = {
+            let private_key = self.load_private_key(self.load_private_key_file()?);
+            self.get_address(&self.get_public_key(&private_key))
+        };
+        let node = match &failover {
+            None if self.mode != NodeMode::Validator => self.with_ephemeral_key()?,
+            Some(failover) if !failover.is_active() => {
+                let standby_key_file = self.get_home_dir().join(failover.standby_key_file());
+                self.with_standby_key(standby_key_file)?
+            }
+            Some(failover) => Self {
+                signing_fence: failover.fence(),
+                ..self.clone()
+            },
+            None => self.clone(),
+        };
+
+        let private_key_file = node.load_private_key_file()?;
+        let private_key = node.load_private_key(private_key_file);
+        let public_key = node.get_public_key(&private_key);
+        let address = node.get_address(&public_key);
+        let signing_provider = node.get_signing_provider(private_key);
         let ctx = EmeraldContext::new();
 
         let genesis = self.load_genesis()?;
         let initial_validator_set = genesis.validator_set.clone();
 
-        let codec = ProtobufCodec;
+        if self.mode == NodeMode::ArchiveSync {
+            if emerald_config.num_certificates_to_retain != u64::MAX {
+                warn!(
+                    num_certificates_to_retain = emerald_config.num_certificates_to_retain,
+                    "Running in archive-sync mode with pruning enabled, old heights cannot be served"
+                );
+            }
+            info!("Running in archive-sync mode, not taking part in consensus");
+        }
+
+        if self.mode == NodeMode::ShadowFork {
+            // The execution client does not have the blocks of the network
+            if emerald_config.el_snapshot.is_some() {
+                return Err(eyre!("el_snapshot cannot be used in shadow-fork mode"));
+            }
+            info!("Running in shadow-fork mode, re-executing the decided blocks locally");
+        }
+
+        // Versions of the peers of this node only, other nodes possibly running in the process
+        let peer_versions = SharedPeerVersions::default();
+        let codec = ProtobufCodec::new(peer_versions.clone());
+
+        // The standby key must not sign for a validator
+        if failover.is_some()
+            && address != validator_address
+            && initial_validator_set.get_by_address(&address).is_some()
+        {
+            return Err(eyre!(
+                "The standby key of {address} is a validator in the genesis, it cannot be used for failover"
+            ));
+        }
 
         let (channels, engine_handle) = malachitebft_app_channel::start_engine(
             ctx,
-            self.clone(),
+            node,
             config.clone(),
-            codec, // WAL codec
-            codec, // Network codec
+            codec.clone(), // WAL codec
+            codec,         // Network codec
             self.start_height,
             initial_validator_set,
         )
@@ -94,15 +244,61 @@ impl App {
         let tx_event = channels.events.clone();
 
         let registry = SharedRegistry::global().with_moniker(&config.moniker);
-        let metrics = Metrics::register(&registry);
+        let metrics = Metrics::register(&registry, &emerald_config.metrics);
 
         if config.metrics.enabled {
-            tokio::spawn(metrics::serve(config.metrics.listen_addr));
+            let security = EndpointSecurity::load(
+                emerald_config.metrics.tls.as_ref(),
+                emerald_config.metrics.auth_token.as_ref(),
+                &self.get_home_dir(),
+            )
+            .wrap_err("Invalid metrics endpoint configuration")?;
+            tokio::spawn(metrics::serve(config.metrics.listen_addr, security));
+        }
+
+        let cipher = emerald_config
+            .store_encryption_key
+            .as_ref()
+            .map(StoreCipher::from_key_source)
+            .transpose()?;
+
+        let store = Store::open(
+            self.get_home_dir().join("store.db"),
+            metrics.db.clone(),
+            cipher,
+            emerald_config.store_limits.clone(),
+        )
+        .await?;
+
+        let genesis_hash = genesis.hash();
+        if let Some(expected) = emerald_config.expected_genesis_hash {
+            if expected != genesis_hash {
+                return Err(eyre!(
+                    "Genesis file `{}` hashes to {genesis_hash}, but {expected} is expected by the emerald config",
+                    self.genesis_file.display()
+                ));
+            }
         }
+        store.check_genesis_hash(genesis_hash).await?;
+        info!(%genesis_hash, "Loaded genesis");
+
+        check_latest_heights(&store, emerald_config.store_startup_check_heights).await?;
 
-        let store = Store::open(self.get_home_dir().join("store.db"), metrics.db.clone()).await?;
         let start_height = self.start_height.unwrap_or_default();
 
+        // The node resumes from the height after the last decided one
+        let config_snapshot = ConfigSnapshot::new(
+            &emerald_config,
+            &config,
+            genesis_hash,
+            store
+                .max_decided_value_height()
+                .map_or(start_height, |height| height.increment()),
+        )?;
+        let config_hash = config_snapshot.hash;
+        store.store_config_snapshot(config_snapshot).await?;
+        info!(%config_hash, "Recorded the effective configuration");
+
         // Load cumulative metrics from database for crash recovery
         let (txs_count, chain_bytes, elapsed_seconds) =
             store.load_cumulative_metrics().await?.unwrap_or_else(|| {
@@ -117,24 +313,133 @@ impl App {
             metrics,
         };
 
-        let emerald_config = self.load_emerald_config()?;
-        let engine: Engine = {
-            let engine_url = Url::parse(&emerald_config.ethereum_config.engine_authrpc_address)?;
-            let jwt_path = PathBuf::from_str(&emerald_config.ethereum_config.jwt_token_path)?;
-            let eth_url = Url::parse(&emerald_config.ethereum_config.execution_authrpc_address)?;
-            Engine::new(
-                EngineRPC::new(engine_url, jwt_path.as_path())?,
-                EthereumRPC::new(eth_url)?,
-            )
+        let clock = SystemClock::shared();
+        let engine = build_engine(&emerald_config, Some(state_metrics.metrics.el.clone()))?
+            .with_clock(clock.clone());
+
+        let nat_listen_port = match &emerald_config.p2p_nat {
+            Some(_) => {
+                let listen_addr = config.consensus.p2p.listen_addr.to_string();
+                let listen_port = ListenPort::parse(&listen_addr).ok_or_else(|| {
+                    eyre!("Invalid p2p_nat: no TCP or UDP port in the consensus listen address {listen_addr}")
+                })?;
+                Some(listen_port)
+            }
+            None => None,
         };
 
-        // Check the validity of the configuration parameters
-        let num_certificates_to_retain = emerald_config.num_certificates_to_retain;
-        let num_temp_blocks_retained = emerald_config.num_temp_blocks_retained;
+        if let Some(snapshot) = &emerald_config.el_snapshot {
+            let range = backfill_range(
+                snapshot,
+                store.decided_heights().latest,
+                emerald_config.num_certificates_to_retain,
+            );
+            if let Some(range) = range.filter(|_| !snapshot.backfill_peers.is_empty()) {
+                let backfill = Backfill {
+                    store: store.clone(),
+                    engine: build_engine(&emerald_config, None)?.with_clock(clock.clone()),
+                    signing_provider: K256Provider::new(
+                        signing_provider.provider().private_key().clone(),
+                    ),
+                    snapshot: snapshot.clone(),
+                    auth_token: snapshot
+                        .backfill_auth_token
+                        .as_ref()
+                        .map(|source| source.read("backfill auth token"))
+                        .transpose()?,
+                    validator_manager_address: genesis.validator_manager_address(),
+                    power_change_limit: genesis.power_change_limit,
+                };
+                tokio::spawn(backfill.run(range));
+            }
+        }
+
+        let vote_stats = SharedVoteStats::default();
+        tokio::spawn(vote_stats::run(
+            tx_event.subscribe(),
+            vote_stats.clone(),
+            state_metrics.metrics.votes.clone(),
+        ));
 
-        if num_certificates_to_retain < num_temp_blocks_retained {
-            return Err(eyre::eyre!(
-                "num_certificates_to_retain has to be >= than num_temp_blocks_retained."
+        let retry_config = SharedRetryConfig::new(emerald_config.retry_config.clone());
+        let build_info = SharedBuildInfo::default();
+        let peer_filter = SharedPeerFilter::new(peer_filter);
+        let peer_registry = SharedPeerRegistry::default();
+        let sync_stats = SharedSyncStats::default();
+        let node_status = SharedNodeStatus::new(vote_stats.clone(), genesis.max_proposal_bytes);
+        let throttle_config = emerald_config.catch_up_throttle.as_ref();
+        let catch_up_throttle = CatchUpThrottle::new(throttle_config, self.fast_sync);
+        node_status
+            .set_catch_up_throttle(CatchUpThrottle::describe(throttle_config, self.fast_sync));
+        let el_health = SharedElHealth::new(state_metrics.metrics.el.clone());
+        tokio::spawn(el_health.clone().run(
+            build_engine(&emerald_config, None)?.with_clock(clock.clone()),
+            emerald_config.el_health_interval,
+        ));
+        if let (Some(p2p_nat), Some(listen_port)) = (&emerald_config.p2p_nat, nat_listen_port) {
+            tokio::spawn(nat::run(
+                p2p_nat.clone(),
+                listen_port,
+                node_status.clone(),
+                state_metrics.metrics.peers.clone(),
+            ));
+        }
+        let failover = match failover {
+            Some(failover) => failover.spawn(
+                tx_event.subscribe(),
+                validator_address,
+                store.clone(),
+                node_status.clone(),
+            ),
+            None => FailoverStop::disabled(),
+        };
+        if let Some(admin_listen_addr) = emerald_config.admin_listen_addr {
+            let security = EndpointSecurity::load(
+                emerald_config.admin_tls.as_ref(),
+                emerald_config.admin_auth_token.as_ref(),
+                &self.get_home_dir(),
+            )
+            .wrap_err("Invalid admin API configuration")?;
+            tokio::spawn(admin::serve(
+                admin_listen_addr,
+                retry_config.clone(),
+                vote_stats,
+                build_info.clone(),
+                peer_filter.clone(),
+                peer_registry.clone(),
+                node_status.clone(),
+                sync_stats.clone(),
+                el_health.clone(),
+                store.clone(),
+                security,
+            ));
+        }
+
+        if let Some(rpc_proxy_listen_addr) = emerald_config.rpc_proxy_listen_addr {
+            let eth_url = Url::parse(&emerald_config.ethereum_config.execution_authrpc_address)?;
+            tokio::spawn(rpc_proxy::serve(
+                rpc_proxy_listen_addr,
+                EthereumRPC::new(eth_url)?,
+                store.clone(),
+            ));
+        }
+
+        if let Some(direct_tx) = &emerald_config.direct_tx {
+            let security = EndpointSecurity::load(
+                direct_tx.tls.as_ref(),
+                Some(&direct_tx.auth_token),
+                &self.get_home_dir(),
+            )
+            .wrap_err("Invalid direct transaction endpoint configuration")?;
+            let eth_url = Url::parse(&emerald_config.ethereum_config.execution_authrpc_address)?;
+            tokio::spawn(direct_tx::serve(
+                direct_tx.listen_addr,
+                EthereumRPC::new(eth_url)?,
+                validator_address,
+                node_status.clone(),
+                direct_tx.validator_endpoints.clone(),
+                state_metrics.metrics.proposer.clone(),
+                security,
             ));
         }
 
@@ -145,6 +450,56 @@ impl App {
             "prune block interval cannot be 0"
         );
 
+        let event_log = match &emerald_config.event_log {
+            Some(config) => {
+                EventLog::open(config, &self.get_home_dir(), emerald_config.moniker.clone())?
+            }
+            None => EventLog::disabled(),
+        };
+
+        let webhooks = match &emerald_config.webhooks {
+            Some(config) => {
+                Webhooks::spawn(config, &self.get_home_dir(), emerald_config.moniker.clone())?
+            }
+            None => Webhooks::disabled(),
+        };
+
+        let el_announcer = match &emerald_config.el_announce {
+            Some(config) => ElAnnouncer::spawn(
+                config,
+                &emerald_config.engine_timeouts,
+                state_metrics.metrics.el.clone(),
+            )?,
+            None => ElAnnouncer::disabled(),
+        };
+
+        let tx_filter = emerald_config
+            .tx_filter_file
+            .as_ref()
+            .map(|path| TxFilter::load(&self.get_home_dir().join(path)))
+            .transpose()?;
+
+        let external_builder = emerald_config
+            .external_builder
+            .as_ref()
+            .map(|config| ExternalBuilder::new(Url::parse(&config.url)?, config.timeout))
+            .transpose()?;
+
+        let forkchoice = Forkchoice::new(
+            emerald_config.finalized_block_depth,
+            store.get_finalized_block().await?,
+        );
+
+        if let Some(policy) = &genesis.fee_recipient_policy {
+            if let Err(e) = check_fee_recipient(emerald_config.fee_recipient, policy, &address) {
+                warn!(
+                    fee_recipient = %emerald_config.fee_recipient,
+                    reason = e.as_str(),
+                    "Configured fee recipient is not accepted by the network, proposals of this node will be rejected"
+                );
+            }
+        }
+
         let state = State::new(
             genesis,
             ctx,
@@ -154,6 +509,23 @@ impl App {
             store,
             state_metrics,
             emerald_config.clone(),
+            event_log,
+            webhooks,
+            el_announcer,
+            (self.mode == NodeMode::ShadowFork).then(ShadowFork::default),
+            tx_filter,
+            external_builder,
+            build_info,
+            peer_filter,
+            peer_registry,
+            peer_versions,
+            node_status,
+            sync_stats,
+            el_health,
+            catch_up_throttle,
+            forkchoice,
+            self.profile_blocks,
+            clock,
         );
 
         Ok(AppRuntime {
@@ -161,11 +533,265 @@ impl App {
             channels,
             engine,
             emerald_config,
+            retry_config,
             engine_handle,
             tx_event,
+            mode: self.mode,
+            failover,
+        })
+    }
+
+    /// This node running with the standby key of the failover, generated if missing
+    fn with_standby_key(&self, standby_key_file: PathBuf) -> eyre::Result<Self> {
+        if !standby_key_file.exists() {
+            let private_key = self.generate_private_key(rand::rngs::OsRng);
+            save_priv_validator_key(
+                self,
+                &standby_key_file,
+                &self.make_private_key_file(private_key),
+            )?;
+            info!(path = %standby_key_file.display(), "Generated the standby key");
+        }
+
+        Ok(Self {
+            private_key_file: standby_key_file,
+            ..self.clone()
         })
     }
 
+    /// This node running with a key generated at each start, in archive-sync and shadow-fork
+    /// modes, whose address cannot be in any validator set, including the ones added later
+    /// through the validator manager contract, so that the node never votes
+    fn with_ephemeral_key(&self) -> eyre::Result<Self> {
+        let key_file = self.get_home_dir().join(EPHEMERAL_KEY_FILE);
+        let private_key = self.generate_private_key(rand::rngs::OsRng);
+        save_priv_validator_key(self, &key_file, &self.make_private_key_file(private_key))?;
+        info!(path = %key_file.display(), mode = ?self.mode, "Generated the key of the node");
+
+        Ok(Self {
+            private_key_file: key_file,
+            ..self.clone()
+        })
+    }
+
+    /// Writes the store to a portable archive at `path`. Must only be run while the node is stopped.
+    pub async fn export_store(&self, path: &Path) -> eyre::Result<()> {
+        let emerald_config = self.load_emerald_config()?;
+        let store = self.open_stopped_store(&emerald_config).await?;
+
+        let entries = store.export_archive(path).await?;
+        info!(%entries, path = %path.display(), "Exported the store");
+
+        Ok(())
+    }
+
+    /// Restores the store from the archive at `path`. The store must not contain any
+    /// decided value, and must use the encryption key of the exported store, if any.
+    /// Must only be run while the node is stopped.
+    pub async fn import_store(&self, path: &Path) -> eyre::Result<()> {
+        let emerald_config = self.load_emerald_config()?;
+        let store = self.open_stopped_store(&emerald_config).await?;
+
+        let entries = store.import_archive(path).await?;
+        info!(%entries, path = %path.display(), "Imported the store");
+
+        Ok(())
+    }
+
+    /// Compacts the store into a new file which then replaces it.
+    /// Must only be run while the node is stopped, the store of a running node being
+    /// compacted through its admin API.
+    pub async fn compact_store(&self) -> eyre::Result<CompactionReport> {
+        let emerald_config = self.load_emerald_config()?;
+        let store = self.open_stopped_store(&emerald_config).await?;
+
+        let report = store.compact().await?;
+        info!(
+            entries = %report.entries,
+            size_before = %report.size_before,
+            size_after = %report.size_after,
+            "Compacted the store"
+        );
+
+        Ok(report)
+    }
+
+    /// Verifies the consistency of the decided heights of the store between `from_height`
+    /// and `to_height`, for `emerald verify-store`.
+    pub async fn verify_store(
+        &self,
+        from_height: Option<u64>,
+        to_height: Option<u64>,
+    ) -> eyre::Result<StoreVerification> {
+        let emerald_config = self.load_emerald_config()?;
+        let store = self.open_stopped_store(&emerald_config).await?;
+
+        let heights = store.decided_heights();
+        let range = match (heights.earliest, heights.latest) {
+            (Some(earliest), Some(latest)) => Some(
+                from_height.map_or(earliest, Height::new)..=to_height.map_or(latest, Height::new),
+            ),
+            _ => None,
+        };
+
+        Ok(store.verify(range).await?)
+    }
+
+    /// Checks that this node can open its store, and that the store belongs to the
+    /// chain of the genesis file, for `emerald doctor`.
+    pub async fn check_store(&self) -> Check {
+        const NAME: &str = "store";
+
+        let path = self.get_home_dir().join("store.db");
+        if !path.exists() {
+            return Check::skip(NAME, format!("No store at `{}` yet", path.display()));
+        }
+
+        let store = match self.load_emerald_config() {
+            Ok(emerald_config) => self.open_stopped_store(&emerald_config).await,
+            Err(e) => Err(e),
+        };
+
+        let store = match store {
+            Ok(store) => store,
+            Err(e) => {
+                return match e.downcast_ref::<StoreError>() {
+                    Some(StoreError::Database(redb::DatabaseError::DatabaseAlreadyOpen)) => {
+                        Check::skip(NAME, "The store is in use by the running node")
+                    }
+                    Some(StoreError::UnsupportedSchema { .. }) => Check::fail(
+                        NAME,
+                        format!("{e:#}"),
+                        "Run the release of emerald which wrote the store, or sync the node again from an empty store",
+                    ),
+                    Some(StoreError::Encryption(_)) => Check::fail(
+                        NAME,
+                        format!("{e:#}"),
+                        "Set `store_encryption_key` to the key the store was created with",
+                    ),
+                    _ => Check::fail(
+                        NAME,
+                        format!("{e:#}"),
+                        "Restore the store with `emerald store import`, or sync the node again from an empty store",
+                    ),
+                };
+            }
+        };
+
+        let schema_version = match store.schema_version().await {
+            Ok(schema_version) => schema_version,
+            Err(e) => {
+                return Check::fail(
+                    NAME,
+                    format!("Failed to read the schema version: {e}"),
+                    "Restore the store with `emerald store import`, or sync the node again from an empty store",
+                );
+            }
+        };
+
+        let genesis_hash = self.load_genesis().map(|genesis| genesis.hash());
+        match (store.genesis_hash().await, genesis_hash) {
+            (Ok(Some(stored)), Ok(genesis_hash)) if stored != genesis_hash => Check::fail(
+                NAME,
+                format!("The store was created with genesis {stored}, but the genesis file hashes to {genesis_hash}"),
+                "The store belongs to another chain: use the genesis file of that chain, or start from an empty store",
+            ),
+            _ => {
+                let heights = store.decided_heights();
+                Check::pass(
+                    NAME,
+                    format!(
+                        "Schema version {schema_version} (supported: {STORE_SCHEMA_VERSION}), decided heights {}..={}",
+                        heights.earliest.unwrap_or_default(),
+                        heights.latest.unwrap_or_default(),
+                    ),
+                )
+            }
+        }
+    }
+
+    /// Opens the store of a stopped node, for offline maintenance.
+    async fn open_stopped_store(&self, emerald_config: &EmeraldConfig) -> eyre::Result<Store> {
+        let cipher = emerald_config
+            .store_encryption_key
+            .as_ref()
+            .map(StoreCipher::from_key_source)
+            .transpose()?;
+
+        Store::open(
+            self.get_home_dir().join("store.db"),
+            DbMetrics::new(),
+            cipher,
+            emerald_config.store_limits.clone(),
+        )
+        .await
+        .wrap_err("Failed to open the store, make sure the node is stopped")
+    }
+
+    /// Rolls the node back to the given height.
+    ///
+    /// Moves the forkchoice of the execution client back to the block at `to_height`
+    /// (unless `skip_el` is set), then removes everything above that height from the
+    /// store. Must only be run while the node is stopped.
+    pub async fn unsafe_reset(&self, to_height: Height, skip_el: bool) -> eyre::Result<()> {
+        let emerald_config = self.load_emerald_config()?;
+        let store = self.open_stopped_store(&emerald_config).await?;
+
+        let max_height = store
+            .max_decided_value_height()
+            .ok_or_else(|| eyre!("The store does not contain any decided value"))?;
+
+        if to_height >= max_height {
+            return Err(eyre!(
+                "Cannot reset to height {to_height}, the latest decided height is {max_height}"
+            ));
+        }
+
+        if skip_el {
+            warn!("Skipping forkchoice update of the execution client");
+        } else {
+            let engine = build_engine(&emerald_config, None)?;
+
+            // Consensus height `h` decides the execution block number `h`
+            let block = engine
+                .eth
+                .get_block_by_number(&format!("0x{:x}", to_height.as_u64()))
+                .await?
+                .ok_or_else(|| eyre!("Execution client does not know block {to_height}"))?;
+
+            // The finalized block is moved back as well, it would otherwise be above the head
+            let forkchoice_state = ForkchoiceState {
+                head_block_hash: block.block_hash,
+                safe_block_hash: block.block_hash,
+                finalized_block_hash: block.block_hash,
+            };
+            engine
+                .set_latest_forkchoice_state(forkchoice_state, &emerald_config.retry_config)
+                .await
+                .wrap_err_with(|| format!("Failed to move the forkchoice to block {to_height}"))?;
+
+            info!(height = %to_height, hash = %block.block_hash, "Moved execution client forkchoice");
+
+            // Execution clients may treat a forkchoice update to an ancestor of the
+            // canonical head as a no-op, in which case the blocks must be unwound offline.
+            if let Some(latest) = engine.get_latest_block_number().await? {
+                if latest > to_height.as_u64() {
+                    warn!(
+                        %latest,
+                        "Execution client head is still above the reset height, \
+                         unwind it with `reth stage unwind to-block {to_height}` before restarting"
+                    );
+                }
+            }
+        }
+
+        store.truncate_above(to_height).await?;
+
+        info!(height = %to_height, previous = %max_height, "Reset store");
+
+        Ok(())
+    }
+
     fn load_emerald_config(&self) -> eyre::Result<EmeraldConfig> {
         let emerald_config_content =
             fs::read_to_string(&self.emerald_config_file).map_err(|e| {
@@ -180,6 +806,52 @@ impl App {
     }
 }
 
+/// Timeouts of the Engine API calls are counted in `el_metrics`, when given
+fn build_engine(
+    emerald_config: &EmeraldConfig,
+    el_metrics: Option<ElMetrics>,
+) -> eyre::Result<Engine> {
+    let engine_url = Url::parse(&emerald_config.ethereum_config.engine_authrpc_address)?;
+    let jwt_path = PathBuf::from_str(&emerald_config.ethereum_config.jwt_token_path)?;
+    let eth_url = Url::parse(&emerald_config.ethereum_config.execution_authrpc_address)?;
+
+    let mut api = EngineRPC::new(
+        engine_url,
+        jwt_path.as_path(),
+        emerald_config.engine_timeouts.clone(),
+    )?;
+    if let Some(el_metrics) = el_metrics {
+        api = api.with_timeout_hook(Arc::new(move |method: &str| {
+            el_metrics.inc_engine_timeouts(method)
+        }));
+    }
+
+    Ok(Engine::new(api, EthereumRPC::new(eth_url)?))
+}
+
+/// Verifies the consistency of the `heights` latest decided heights of the store,
+/// refusing to start the node on a broken one
+async fn check_latest_heights(store: &Store, heights: u64) -> eyre::Result<()> {
+    let Some(latest) = store.decided_heights().latest.filter(|_| heights > 0) else {
+        return Ok(());
+    };
+
+    let from = Height::new(latest.as_u64().saturating_sub(heights - 1));
+    let verification = store.verify(Some(from..=latest)).await?;
+    match &verification.broken {
+        None => {
+            info!(checked = verification.checked, %latest, "Verified the latest decided heights of the store");
+            Ok(())
+        }
+        Some(broken) => Err(eyre!(
+            "The store is broken at height {}: {}. {}, or check the whole store with `emerald verify-store`",
+            broken.height,
+            broken.reason,
+            verification.suggestion().unwrap_or_default(),
+        )),
+    }
+}
+
 pub struct Handle {
     pub app: JoinHandle<()>,
     pub engine: EngineHandle,
@@ -206,7 +878,7 @@ impl Node for App {
     type Config = Config;
     type Genesis = Genesis;
     type PrivateKeyFile = PrivateKey;
-    type SigningProvider = K256Provider;
+    type SigningProvider = FencedProvider;
     type NodeHandle = Handle;
 
     fn get_home_dir(&self) -> PathBuf {
@@ -218,7 +890,7 @@ impl Node for App {
     }
 
     fn get_signing_provider(&self, private_key: PrivateKey) -> Self::SigningProvider {
-        K256Provider::new(private_key)
+        FencedProvider::new(K256Provider::new(private_key), self.signing_fence.clone())
     }
 
     fn get_address(&self, pk: &PublicKey) -> Address {
@@ -258,15 +930,34 @@ impl Node for App {
             mut channels,
             engine,
             emerald_config,
+            retry_config,
             engine_handle,
             tx_event,
+            mode,
+            failover,
         } = self.build_runtime().await?;
 
         let app_handle = tokio::spawn(async move {
-            if let Err(e) = crate::app::run(&mut state, &mut channels, engine, emerald_config).await
-            {
+            let result = tokio::select! {
+                result = crate::app::run(
+                    &mut state,
+                    &mut channels,
+                    engine,
+                    emerald_config,
+                    retry_config,
+                    mode,
+                ) => result,
+                reason = failover.wait() => {
+                    // Exits at once, without signing anything else, and with a failure so
+                    // that the service manager restarts the node with the key of its new role
+                    error!(%reason, "Stopping the node to switch keys");
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = result {
                 tracing::error!(%e, "Application error");
             }
+            state.metrics_aggregator.shutdown().await;
         });
 
         Ok(Handle {
@@ -282,6 +973,93 @@ impl Node for App {
     }
 }
 
+/// Supervisor running several independent chains in one process.
+///
+/// Each chain has its own configuration, store and execution client endpoints.
+/// The moniker of every chain is prefixed with its id, which namespaces the
+/// logs and the metrics of each chain through the `moniker` label.
+pub struct MultiNode {
+    chains: Vec<(String, App)>,
+}
+
+impl MultiNode {
+    pub fn new(chains: Vec<(String, App)>) -> eyre::Result<Self> {
+        if chains.is_empty() {
+            return Err(eyre!("A multi-chain node needs at least one chain"));
+        }
+
+        let mut ids = HashSet::new();
+        let mut p2p_addrs = HashSet::new();
+        let mut metrics_addrs = HashSet::new();
+
+        for (id, app) in &chains {
+            if !ids.insert(id.as_str()) {
+                return Err(eyre!("Duplicate chain id `{id}`"));
+            }
+
+            let p2p_addr = &app.config.consensus.p2p.listen_addr;
+            if !p2p_addrs.insert(p2p_addr.clone()) {
+                return Err(eyre!(
+                    "Chain `{id}` listens for consensus on `{p2p_addr}`, already used by another chain"
+                ));
+            }
+
+            let metrics = &app.config.metrics;
+            if metrics.enabled && !metrics_addrs.insert(metrics.listen_addr) {
+                return Err(eyre!(
+                    "Chain `{id}` serves metrics on `{}`, already used by another chain",
+                    metrics.listen_addr
+                ));
+            }
+        }
+
+        let chains = chains
+            .into_iter()
+            .map(|(id, mut app)| {
+                app.config.moniker = format!("{id}/{}", app.config.moniker);
+                (id, app)
+            })
+            .collect();
+
+        Ok(Self { chains })
+    }
+
+    /// Starts all chains and waits until they have all stopped.
+    ///
+    /// Startup is all-or-nothing, but once running, a chain stopping does not stop the others.
+    pub async fn run(self) -> eyre::Result<()> {
+        let mut running = JoinSet::new();
+
+        for (id, app) in self.chains {
+            info!(chain = %id, moniker = %app.config.moniker, "Starting chain");
+
+            let handle = app
+                .start()
+                .await
+                .wrap_err_with(|| format!("Failed to start chain `{id}`"))?;
+
+            running.spawn(async move { (id, handle.app.await) });
+        }
+
+        let mut failed = Vec::new();
+        while let Some(joined) = running.join_next().await {
+            match joined? {
+                (id, Ok(())) => warn!(chain = %id, "Chain has stopped"),
+                (id, Err(e)) => {
+                    error!(chain = %id, %e, "Chain has failed");
+                    failed.push(id);
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!("Chains failed: {}", failed.join(", ")))
+        }
+    }
+}
+
 impl CanMakeGenesis for App {
     fn make_genesis(&self, validators: Vec<(PublicKey, VotingPower)>) -> Self::Genesis {
         let validators = validators
@@ -290,7 +1068,14 @@ impl CanMakeGenesis for App {
 
         let validator_set = ValidatorSet::new(validators);
 
-        Genesis { validator_set }
+        Genesis {
+            validator_set,
+            base_fee_floor: None,
+            fee_recipient_policy: None,
+            max_proposal_bytes: None,
+            power_change_limit: None,
+            validator_manager_address: None,
+        }
     }
 }
 
@@ -0,0 +1,403 @@
+//! Status of the node served by the admin API, for the `emerald status` command.
+//!
+//! The status is updated by the handlers of the consensus messages: the height, round
+//! and proposer when a round starts, and the head of the execution client when a value
+//! is decided. The participation of the validators in the last decided height is taken
+//! from its commit certificate and from the precommits seen by the vote statistics.
+//!
+//! The consensus engine does not tell the application whether it is syncing, so the node
+//! is reported as catching up when the last decided height was synced from the peers.
+//!
+//! The validator set of the current height is kept as well, to schedule the proposers of
+//! the upcoming heights. The schedule assumes that the validator set does not change
+//! until then.
+
+use core::time::Duration;
+use std::sync::{Arc, RwLock};
+
+use malachitebft_app_channel::app::types::core::{Context, Round};
+use malachitebft_eth_cli::cmd::schedule::{ProposerSchedule, ScheduleQuery, ScheduledHeight};
+use malachitebft_eth_cli::cmd::status::{CatchUpThrottleStatus, ElHead, NodeStatus, Participation};
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_types::{Address, EmeraldContext, Height, ValidatorSet};
+
+use crate::vote_stats::SharedVoteStats;
+
+#[derive(Debug, Default)]
+struct Inner {
+    status: NodeStatus,
+    /// Highest height of the values synced from the peers
+    synced_height: Option<Height>,
+    /// Validator set of the current height
+    validator_set: Option<ValidatorSet>,
+}
+
+/// Proposers of the current round of consensus and of the next height
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UpcomingProposers {
+    pub height: Height,
+    pub round: Round,
+    /// Proposer of the current round
+    pub current: Address,
+    /// Proposer of the first round of the next height
+    pub next: Address,
+}
+
+/// Status of the node shared between the handlers and the admin API
+#[derive(Clone, Debug)]
+pub struct SharedNodeStatus {
+    inner: Arc<RwLock<Inner>>,
+    vote_stats: SharedVoteStats,
+}
+
+impl SharedNodeStatus {
+    pub fn new(vote_stats: SharedVoteStats, max_proposal_bytes: Option<u64>) -> Self {
+        let status = NodeStatus {
+            max_proposal_bytes,
+            ..NodeStatus::default()
+        };
+
+        Self {
+            inner: Arc::new(RwLock::new(Inner {
+                status,
+                synced_height: None,
+                validator_set: None,
+            })),
+            vote_stats,
+        }
+    }
+
+    pub fn get(&self) -> NodeStatus {
+        let mut status = self
+            .inner
+            .read()
+            .expect("node status lock poisoned")
+            .status
+            .clone();
+
+        if let Some(participation) = &mut status.participation {
+            let precommits = self
+                .vote_stats
+                .summary()
+                .into_iter()
+                .find(|round| {
+                    round.height == participation.height && round.round == participation.round
+                })
+                .map_or(0, |round| round.precommits.len());
+            participation.precommits = precommits.max(participation.signatures);
+        }
+
+        status
+    }
+
+    pub fn started_round(
+        &self,
+        height: Height,
+        round: Round,
+        proposer: Address,
+        validator_set: Option<&ValidatorSet>,
+    ) {
+        let mut inner = self.inner.write().expect("node status lock poisoned");
+        inner.status.height = height.as_u64();
+        inner.status.round = round.as_i64();
+        inner.status.proposer = Some(proposer.to_string());
+        inner.status.validators = validator_set.map_or(0, |set| set.validators.len());
+        inner.validator_set = validator_set.cloned();
+    }
+
+    /// Proposers of the heights and rounds of `query`, selected from the validator set of
+    /// the current height, if known
+    pub fn proposer_schedule(&self, query: &ScheduleQuery) -> Option<ProposerSchedule> {
+        let inner = self.inner.read().expect("node status lock poisoned");
+        let validator_set = inner
+            .validator_set
+            .as_ref()
+            .filter(|set| !set.validators.is_empty())?;
+
+        let context = EmeraldContext::new();
+        let heights = query
+            .heights()
+            .map(|height| ScheduledHeight {
+                height,
+                proposers: (0..query.rounds)
+                    .map(|round| {
+                        context
+                            .select_proposer(validator_set, Height::new(height), Round::new(round))
+                            .address
+                            .to_string()
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Some(ProposerSchedule {
+            validator_set_height: inner.status.height,
+            validators: validator_set.validators.len(),
+            heights,
+        })
+    }
+
+    /// Proposers of the current round and of the first round of the next height, selected
+    /// from the validator set of the current height, if known
+    pub fn upcoming_proposers(&self) -> Option<UpcomingProposers> {
+        let inner = self.inner.read().expect("node status lock poisoned");
+        let validator_set = inner
+            .validator_set
+            .as_ref()
+            .filter(|set| !set.validators.is_empty())?;
+
+        let context = EmeraldContext::new();
+        let height = Height::new(inner.status.height);
+        let round = Round::new(u32::try_from(inner.status.round).ok()?);
+
+        Some(UpcomingProposers {
+            height,
+            round,
+            current: context
+                .select_proposer(validator_set, height, round)
+                .address,
+            next: context
+                .select_proposer(validator_set, height.increment(), Round::ZERO)
+                .address,
+        })
+    }
+
+    /// Records that a value was synced from the peers for `height`
+    pub fn synced(&self, height: Height) {
+        let mut inner = self.inner.write().expect("node status lock poisoned");
+        inner.synced_height = Some(inner.synced_height.map_or(height, |h| h.max(height)));
+    }
+
+    /// Records the decision of `height` in `round` with the precommits of `signatures`
+    /// out of `validators`, and the block committed to the execution client
+    pub fn decided(
+        &self,
+        height: Height,
+        round: Round,
+        signatures: usize,
+        validators: usize,
+        block: &ExecutionBlock,
+    ) {
+        let mut inner = self.inner.write().expect("node status lock poisoned");
+        inner.status.catching_up = inner.synced_height.is_some_and(|h| h >= height);
+        inner.status.participation = Some(Participation {
+            height: height.as_u64(),
+            round: round.as_i64(),
+            signatures,
+            precommits: signatures,
+            validators,
+        });
+        inner.status.el_head = Some(el_head(block));
+    }
+
+    /// Records the limits of the catch-up throttle, if any
+    pub fn set_catch_up_throttle(&self, limits: Option<String>) {
+        self.inner
+            .write()
+            .expect("node status lock poisoned")
+            .status
+            .catch_up_throttle = limits.map(|limits| CatchUpThrottleStatus {
+            limits,
+            delayed_values: 0,
+            delay_ms: 0,
+        });
+    }
+
+    /// Records a synced value delayed by `delay` by the catch-up throttle
+    pub fn catch_up_throttled(&self, delay: Duration) {
+        let mut inner = self.inner.write().expect("node status lock poisoned");
+        if let Some(throttle) = &mut inner.status.catch_up_throttle {
+            throttle.delayed_values += 1;
+            throttle.delay_ms = throttle
+                .delay_ms
+                .saturating_add(u64::try_from(delay.as_millis()).unwrap_or(u64::MAX));
+        }
+    }
+
+    /// Records the multiaddr at which the peers reach the consensus port
+    pub fn set_external_address(&self, multiaddr: String) {
+        self.inner
+            .write()
+            .expect("node status lock poisoned")
+            .status
+            .external_address = Some(multiaddr);
+    }
+
+    /// Records the head of the execution client when the node starts
+    pub fn set_el_head(&self, block: &ExecutionBlock) {
+        self.inner
+            .write()
+            .expect("node status lock poisoned")
+            .status
+            .el_head = Some(el_head(block));
+    }
+}
+
+fn el_head(block: &ExecutionBlock) -> ElHead {
+    ElHead {
+        number: block.block_number,
+        hash: block.block_hash.to_string(),
+        timestamp: block.timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+    use malachitebft_app_channel::app::types::core::NilOrVal;
+    use malachitebft_eth_types::{PrivateKey, Validator, Vote};
+
+    use super::*;
+
+    fn block(number: u64) -> ExecutionBlock {
+        ExecutionBlock {
+            block_hash: B256::repeat_byte(number as u8),
+            block_number: number,
+            parent_hash: B256::ZERO,
+            timestamp: 1_000 + number,
+            prev_randao: B256::ZERO,
+        }
+    }
+
+    fn validator_set(count: u64) -> ValidatorSet {
+        ValidatorSet::new((1..=count).map(|seed| {
+            let public_key = PrivateKey::from_slice(&[seed as u8; 32])
+                .unwrap()
+                .public_key();
+            Validator::new(public_key, 1)
+        }))
+    }
+
+    #[test]
+    fn test_node_status() {
+        let vote_stats = SharedVoteStats::default();
+        let status = SharedNodeStatus::new(vote_stats.clone(), None);
+        assert_eq!(status.get(), NodeStatus::default());
+        let validator_set = validator_set(4);
+
+        // Height 1 is synced from the peers
+        status.started_round(
+            Height::new(1),
+            Round::ZERO,
+            Address::repeat_byte(1),
+            Some(&validator_set),
+        );
+        status.synced(Height::new(1));
+        status.decided(Height::new(1), Round::ZERO, 3, 4, &block(1));
+        let synced = status.get();
+        assert!(synced.catching_up);
+        assert_eq!(synced.el_head.unwrap().number, 1);
+
+        // Height 2 is decided through consensus, all the validators precommitted
+        status.started_round(
+            Height::new(2),
+            Round::new(1),
+            Address::repeat_byte(2),
+            Some(&validator_set),
+        );
+        for byte in 1..=4 {
+            let precommit = Vote::new_precommit(
+                Height::new(2),
+                Round::new(1),
+                NilOrVal::Nil,
+                Address::repeat_byte(byte),
+            );
+            vote_stats.record(&precommit, std::time::Instant::now());
+        }
+        status.decided(Height::new(2), Round::new(1), 3, 4, &block(2));
+        status.started_round(
+            Height::new(3),
+            Round::ZERO,
+            Address::repeat_byte(3),
+            Some(&validator_set),
+        );
+
+        let status = status.get();
+        assert_eq!(status.height, 3);
+        assert_eq!(status.round, 0);
+        assert_eq!(status.proposer, Some(Address::repeat_byte(3).to_string()));
+        assert!(!status.catching_up);
+        assert_eq!(
+            status.participation,
+            Some(Participation {
+                height: 2,
+                round: 1,
+                signatures: 3,
+                precommits: 4,
+                validators: 4,
+            })
+        );
+        assert_eq!(status.el_head.unwrap().timestamp, 1_002);
+    }
+
+    #[test]
+    fn test_proposer_schedule() {
+        let status = SharedNodeStatus::new(SharedVoteStats::default(), None);
+        let query = ScheduleQuery {
+            from: 100,
+            to: 103,
+            rounds: 2,
+        };
+        assert_eq!(status.proposer_schedule(&query), None);
+
+        let validator_set = validator_set(3);
+        let address = |index: usize| validator_set.validators[index].address.to_string();
+        status.started_round(
+            Height::new(10),
+            Round::ZERO,
+            Address::repeat_byte(1),
+            Some(&validator_set),
+        );
+
+        // Round r of height h is proposed by the validator (h - 1 + r) % n
+        let schedule = status.proposer_schedule(&query).unwrap();
+        assert_eq!(schedule.validator_set_height, 10);
+        assert_eq!(schedule.validators, 3);
+        assert_eq!(
+            schedule.heights,
+            vec![
+                ScheduledHeight {
+                    height: 100,
+                    proposers: vec![address(0), address(1)],
+                },
+                ScheduledHeight {
+                    height: 101,
+                    proposers: vec![address(1), address(2)],
+                },
+                ScheduledHeight {
+                    height: 102,
+                    proposers: vec![address(2), address(0)],
+                },
+                ScheduledHeight {
+                    height: 103,
+                    proposers: vec![address(0), address(1)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upcoming_proposers() {
+        let status = SharedNodeStatus::new(SharedVoteStats::default(), None);
+        assert_eq!(status.upcoming_proposers(), None);
+
+        let validator_set = validator_set(3);
+        let address = |index: usize| validator_set.validators[index].address;
+        status.started_round(
+            Height::new(10),
+            Round::new(1),
+            Address::repeat_byte(1),
+            Some(&validator_set),
+        );
+
+        assert_eq!(
+            status.upcoming_proposers(),
+            Some(UpcomingProposers {
+                height: Height::new(10),
+                round: Round::new(1),
+                current: address(1),
+                next: address(1),
+            })
+        );
+    }
+}
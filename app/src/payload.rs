@@ -1,17 +1,29 @@
 //! Execution payload utilities for validation, caching, and manipulation.
 
-use alloy_rpc_types_engine::{ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3};
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_rpc_types_engine::{
+    ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3, PayloadId,
+};
 use bytes::Bytes;
 use caches::lru::AdaptiveCache;
 use caches::Cache;
-use color_eyre::eyre::{self, eyre};
+use color_eyre::eyre::{self, WrapErr};
 use malachitebft_app_channel::app::types::core::{Round, Validity};
 use malachitebft_eth_engine::engine::Engine;
-use malachitebft_eth_engine::json_structures::ExecutionPayloadBodyV1;
-use malachitebft_eth_types::{Block, BlockHash, Height, RetryConfig};
+use malachitebft_eth_engine::error::EngineError;
+use malachitebft_eth_engine::json_structures::{ExecutionBlock, ExecutionPayloadBodyV1};
+use malachitebft_eth_engine::payload_builder::{ExternalBuilder, PayloadBuilder, PayloadRequest};
+use malachitebft_eth_types::{
+    Address, Block, BlockHash, FeeRecipientPolicy, Height, RetryConfig, RetryOperation,
+};
 use ssz::Decode;
 use tracing::{debug, error, warn};
 
+use crate::el_divergence::ElDivergence;
+use crate::metrics::{ProposerMetrics, ValidationMetrics};
+
 /// Cache for tracking recently validated execution payloads to avoid redundant validation.
 /// Stores both the block hash and its validity result (Valid or Invalid).
 pub struct ValidatedPayloadCache {
@@ -37,19 +49,308 @@ impl ValidatedPayloadCache {
     }
 }
 
+/// Identifies the payloads which can be reused when proposing again: those built on
+/// the same parent block, for the same fee recipient, in the same time bucket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BuiltPayloadKey {
+    parent_hash: BlockHash,
+    fee_recipient: Address,
+    timestamp_bucket: u128,
+}
+
+/// Last payload built by this node, reused when it proposes again on the same parent
+/// (e.g. in a later round of the same height) instead of asking the execution client
+/// to build a new one with `forkchoiceUpdated` and `getPayload`.
+pub struct BuiltPayloadCache {
+    window: Duration,
+    last: Option<(BuiltPayloadKey, ExecutionPayloadV3)>,
+}
+
+impl BuiltPayloadCache {
+    /// Creates a cache reusing payloads for up to `window`, or never if `window` is zero.
+    pub fn new(window: Duration) -> Self {
+        Self { window, last: None }
+    }
+
+    /// Key of a payload built now, or `None` if payloads are not reused
+    pub fn key(&self, parent_hash: BlockHash, fee_recipient: Address) -> Option<BuiltPayloadKey> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        self.key_at(parent_hash, fee_recipient, now)
+    }
+
+    /// Key of a payload built at `now`, since the unix epoch
+    fn key_at(
+        &self,
+        parent_hash: BlockHash,
+        fee_recipient: Address,
+        now: Duration,
+    ) -> Option<BuiltPayloadKey> {
+        if self.window.is_zero() {
+            return None;
+        }
+
+        Some(BuiltPayloadKey {
+            parent_hash,
+            fee_recipient,
+            timestamp_bucket: now.as_millis() / self.window.as_millis().max(1),
+        })
+    }
+
+    pub fn get(&self, key: &BuiltPayloadKey) -> Option<&ExecutionPayloadV3> {
+        self.last
+            .as_ref()
+            .filter(|(k, _)| k == key)
+            .map(|(_, payload)| payload)
+    }
+
+    pub fn insert(&mut self, key: BuiltPayloadKey, payload: ExecutionPayloadV3) {
+        self.last = Some((key, payload));
+    }
+
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Payload which the execution client started building right after the previous block
+/// was decided, on top of it, for this node to propose at the next height.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PendingPayload {
+    pub parent_hash: BlockHash,
+    pub fee_recipient: Address,
+    pub payload_id: PayloadId,
+}
+
+impl PendingPayload {
+    /// Whether the payload can be proposed on top of `parent_hash` for `fee_recipient`
+    pub fn matches(&self, parent_hash: BlockHash, fee_recipient: Address) -> bool {
+        self.parent_hash == parent_hash && self.fee_recipient == fee_recipient
+    }
+}
+
+/// Timestamp of a payload built ahead of time on top of a block with `parent_timestamp`,
+/// expected to be proposed after `delay`, or `None` if it would be before its parent.
+/// As in [`check_linkage`], a block may have the timestamp of its parent.
+pub fn pending_payload_timestamp(parent_timestamp: u64, delay: Duration) -> Option<u64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    Some((now + delay).as_secs()).filter(|timestamp| *timestamp >= parent_timestamp)
+}
+
+/// Structural defect of a payload which does not extend the expected parent block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkageError {
+    /// The block number is not the consensus height
+    BlockNumber,
+    /// The parent hash is not the hash of the parent block
+    ParentHash,
+    /// The timestamp is before the one of the parent block
+    Timestamp,
+}
+
+impl LinkageError {
+    /// Reason reported in the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BlockNumber => "block_number",
+            Self::ParentHash => "parent_hash",
+            Self::Timestamp => "timestamp",
+        }
+    }
+}
+
+/// Fee recipient of a payload which the fee recipient policy of the network does not accept
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FeeRecipientError {
+    /// The fee recipient is not the address of the proposer
+    NotProposer,
+    /// The fee recipient is not one of the allowed addresses
+    NotAllowed,
+}
+
+impl FeeRecipientError {
+    /// Reason reported in the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotProposer => "fee_recipient_not_proposer",
+            Self::NotAllowed => "fee_recipient_not_allowed",
+        }
+    }
+}
+
+/// Fee recipient policy of the network, with the proposer of the payload to check
+#[derive(Copy, Clone, Debug)]
+pub struct FeeRecipientCheck<'a> {
+    pub policy: &'a FeeRecipientPolicy,
+    pub proposer: Address,
+}
+
+/// Category of the error returned when building a payload to propose, telling apart
+/// an unavailable execution client from one which refuses to build
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildFailure {
+    /// The execution client did not answer in time
+    Timeout,
+    /// The execution client could not be reached, e.g. while it restarts
+    Unreachable,
+    /// The execution client refused the payload attributes, e.g. while it syncs
+    InvalidStatus,
+    /// The execution client answered with a JSON-RPC error
+    RpcError,
+    Other,
+}
+
+impl BuildFailure {
+    /// Categorizes an error returned by [`build_payload`]
+    pub fn classify(error: &eyre::Report) -> Self {
+        if let Some(error) = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<EngineError>())
+        {
+            return Self::from_engine_error(error);
+        }
+
+        // Errors not raised by the clients of the execution client, e.g. by other
+        // payload builders, are categorized from their messages
+        let matches = |patterns: &[&str]| {
+            error.chain().any(|cause| {
+                let cause = cause.to_string();
+                patterns.iter().any(|pattern| cause.contains(pattern))
+            })
+        };
+
+        if matches(&["timed out", "Timeout after"]) {
+            Self::Timeout
+        } else if matches(&["error sending request", "HTTP status"]) {
+            Self::Unreachable
+        } else if matches(&["Invalid payload status"]) {
+            Self::InvalidStatus
+        } else if matches(&["Server Message"]) {
+            Self::RpcError
+        } else {
+            Self::Other
+        }
+    }
+
+    fn from_engine_error(error: &EngineError) -> Self {
+        match error {
+            error if error.is_timeout() => Self::Timeout,
+            EngineError::Transport { .. } => Self::Unreachable,
+            EngineError::InvalidPayloadStatus(_) => Self::InvalidStatus,
+            EngineError::Rpc { .. } => Self::RpcError,
+            _ => Self::Other,
+        }
+    }
+
+    /// Category reported in the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Unreachable => "unreachable",
+            Self::InvalidStatus => "invalid_status",
+            Self::RpcError => "rpc_error",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Checks that the payload proposed at `height` extends the `parent` block.
+pub fn check_linkage(
+    payload: &ExecutionPayloadV3,
+    height: Height,
+    parent: &ExecutionBlock,
+) -> Result<(), LinkageError> {
+    let payload = &payload.payload_inner.payload_inner;
+
+    // Consensus height `h` decides the execution block number `h`
+    if payload.block_number != height.as_u64() || payload.block_number != parent.block_number + 1 {
+        return Err(LinkageError::BlockNumber);
+    }
+
+    if payload.parent_hash != parent.block_hash {
+        return Err(LinkageError::ParentHash);
+    }
+
+    // Consecutive blocks may share a timestamp, see the consensus of custom-reth
+    if payload.timestamp < parent.timestamp {
+        return Err(LinkageError::Timestamp);
+    }
+
+    Ok(())
+}
+
+/// Checks that the `policy` accepts the `fee_recipient` of a payload proposed by `proposer`.
+pub fn check_fee_recipient(
+    fee_recipient: Address,
+    policy: &FeeRecipientPolicy,
+    proposer: &Address,
+) -> Result<(), FeeRecipientError> {
+    match policy {
+        FeeRecipientPolicy::Proposer if fee_recipient != *proposer => {
+            Err(FeeRecipientError::NotProposer)
+        }
+        FeeRecipientPolicy::Allowed(addresses) if !addresses.contains(&fee_recipient) => {
+            Err(FeeRecipientError::NotAllowed)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Builds the payload to propose at `height`, with the external builder if any.
+///
+/// Falls back to the local execution client when the external builder fails, or when
+/// its payload does not extend the parent block of the request.
+pub async fn build_payload(
+    engine: &Engine,
+    external_builder: Option<&ExternalBuilder>,
+    request: &PayloadRequest<'_>,
+    height: Height,
+    metrics: &ProposerMetrics,
+) -> eyre::Result<ExecutionPayloadV3> {
+    if let Some(builder) = external_builder {
+        match builder.build_payload(request).await {
+            Ok(payload) => match check_linkage(&payload, height, request.parent) {
+                Ok(()) => return Ok(payload),
+                Err(e) => {
+                    warn!(
+                        builder = builder.name(),
+                        reason = e.as_str(),
+                        "Built payload does not extend the parent block, building locally"
+                    );
+                }
+            },
+            Err(e) => {
+                warn!(
+                    builder = builder.name(),
+                    "Failed to build payload, building locally: {e}"
+                );
+            }
+        }
+        metrics.inc_external_builder_fallbacks();
+    }
+
+    engine.build_payload(request).await
+}
+
 /// Validates execution payload bytes with the execution engine.
-/// Decodes the payload, extracts versioned hashes, and validates.
+/// Decodes the payload, checks that it extends the `parent` block if known and that
+/// its fee recipient is accepted by the policy of the network if any,
+/// extracts versioned hashes, and validates.
 /// Uses cache to avoid duplicate validation calls.
 ///
 /// Returns `Ok(Validity::Invalid)` if decoding fails or payload is invalid,
-/// `Ok(Validity::Valid)` if valid, or `Err` for engine communication failures.
+/// `Ok(Validity::Valid)` if valid, or `Err` for engine communication failures and
+/// for an [`ElDivergence`] of the execution client.
+#[allow(clippy::too_many_arguments)]
 pub async fn validate_execution_payload(
     cache: &mut ValidatedPayloadCache,
     data: &Bytes,
     height: Height,
     round: Round,
+    parent: Option<&ExecutionBlock>,
+    fee_recipient: Option<FeeRecipientCheck<'_>>,
     engine: &Engine,
     retry_config: &RetryConfig,
+    metrics: &ValidationMetrics,
 ) -> eyre::Result<Validity> {
     // Decode execution payload
     let execution_payload = match ExecutionPayloadV3::from_ssz_bytes(data) {
@@ -65,6 +366,37 @@ pub async fn validate_execution_payload(
         }
     };
 
+    // Reject payloads not extending the parent block without a round-trip to the engine
+    if let Some(parent) = parent {
+        if let Err(e) = check_linkage(&execution_payload, height, parent) {
+            warn!(
+                height = %height,
+                round = %round,
+                reason = e.as_str(),
+                "Proposal does not extend the parent block"
+            );
+            metrics.inc_rejected_payloads(e.as_str());
+            return Ok(Validity::Invalid);
+        }
+    }
+
+    if let Some(FeeRecipientCheck { policy, proposer }) = fee_recipient {
+        let fee_recipient =
+            Address::from(execution_payload.payload_inner.payload_inner.fee_recipient);
+        if let Err(e) = check_fee_recipient(fee_recipient, policy, &proposer) {
+            warn!(
+                height = %height,
+                round = %round,
+                %proposer,
+                %fee_recipient,
+                reason = e.as_str(),
+                "Proposal has a fee recipient not accepted by the network policy"
+            );
+            metrics.inc_rejected_payloads(e.as_str());
+            return Ok(Validity::Invalid);
+        }
+    }
+
     let block_hash = execution_payload.payload_inner.payload_inner.block_hash;
 
     // Check if we've already validated this block
@@ -91,20 +423,27 @@ pub async fn validate_execution_payload(
     };
     let versioned_hashes: Vec<BlockHash> =
         block.body.blob_versioned_hashes_iter().copied().collect();
+    let parent_hash = execution_payload.payload_inner.payload_inner.parent_hash;
 
     // Validate with execution engine
     let payload_status = engine
         .notify_new_block_with_retry(execution_payload, versioned_hashes, retry_config)
         .await
-        .map_err(|e| {
-            eyre!(
-                "Execution client stuck in SYNCING for {:?} at height {}: {}",
-                retry_config.max_elapsed_time,
+        .wrap_err_with(|| {
+            format!(
+                "Execution client stuck in SYNCING for {:?} at height {}",
+                retry_config
+                    .for_operation(RetryOperation::NewPayload)
+                    .max_elapsed_time,
                 height,
-                e
             )
         })?;
 
+    // Not cached, the payload being validated again once the execution client recovered
+    if let Some(divergence) = ElDivergence::detect(&payload_status, height, parent_hash) {
+        return Err(divergence.into());
+    }
+
     let validity = if payload_status.status.is_valid() {
         Validity::Valid
     } else {
@@ -155,3 +494,225 @@ pub fn reconstruct_execution_payload(
         ..header
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy_rpc_types_engine::PayloadStatusEnum;
+    use color_eyre::eyre::eyre;
+    use malachitebft_eth_types::B256;
+
+    use super::*;
+
+    fn parent() -> ExecutionBlock {
+        ExecutionBlock {
+            block_hash: B256::repeat_byte(1),
+            block_number: 4,
+            parent_hash: B256::repeat_byte(0),
+            timestamp: 1_000,
+            prev_randao: B256::ZERO,
+        }
+    }
+
+    fn payload(block_number: u64, parent_hash: BlockHash, timestamp: u64) -> ExecutionPayloadV3 {
+        let mut payload = ExecutionPayloadV3::default();
+        payload.payload_inner.payload_inner.block_number = block_number;
+        payload.payload_inner.payload_inner.parent_hash = parent_hash;
+        payload.payload_inner.payload_inner.timestamp = timestamp;
+        payload
+    }
+
+    #[test]
+    fn test_check_linkage() {
+        let parent = parent();
+        let height = Height::new(5);
+
+        assert_eq!(
+            check_linkage(&payload(5, parent.block_hash, 1_001), height, &parent),
+            Ok(())
+        );
+        assert_eq!(
+            check_linkage(&payload(6, parent.block_hash, 1_001), height, &parent),
+            Err(LinkageError::BlockNumber)
+        );
+        assert_eq!(
+            check_linkage(
+                &payload(5, parent.block_hash, 1_001),
+                Height::new(6),
+                &parent
+            ),
+            Err(LinkageError::BlockNumber)
+        );
+        assert_eq!(
+            check_linkage(&payload(5, B256::repeat_byte(2), 1_001), height, &parent),
+            Err(LinkageError::ParentHash)
+        );
+        assert_eq!(
+            check_linkage(&payload(5, parent.block_hash, 1_000), height, &parent),
+            Ok(())
+        );
+        assert_eq!(
+            check_linkage(&payload(5, parent.block_hash, 999), height, &parent),
+            Err(LinkageError::Timestamp)
+        );
+    }
+
+    #[test]
+    fn test_built_payload_cache() {
+        let mut cache = BuiltPayloadCache::new(Duration::from_secs(5));
+        let parent_hash = BlockHash::repeat_byte(1);
+        let fee_recipient = Address::repeat_byte(2);
+        let now = Duration::from_secs(1_000);
+
+        let key = cache.key_at(parent_hash, fee_recipient, now).unwrap();
+        assert_eq!(cache.get(&key), None);
+
+        let built = payload(1, parent_hash, 1_000);
+        cache.insert(key, built.clone());
+        assert_eq!(cache.get(&key), Some(&built));
+
+        // Same parent and fee recipient, within the window
+        let later = cache
+            .key_at(parent_hash, fee_recipient, now + Duration::from_secs(4))
+            .unwrap();
+        assert_eq!(cache.get(&later), Some(&built));
+
+        // Another parent, fee recipient, or window
+        let other_parent = cache
+            .key_at(BlockHash::repeat_byte(3), fee_recipient, now)
+            .unwrap();
+        assert_eq!(cache.get(&other_parent), None);
+        let other_recipient = cache
+            .key_at(parent_hash, Address::repeat_byte(3), now)
+            .unwrap();
+        assert_eq!(cache.get(&other_recipient), None);
+        let expired = cache
+            .key_at(parent_hash, fee_recipient, now + Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(cache.get(&expired), None);
+
+        // Only the last payload is kept
+        let other = payload(1, BlockHash::repeat_byte(3), 1_000);
+        cache.insert(other_parent, other.clone());
+        assert_eq!(cache.get(&key), None);
+        assert_eq!(cache.get(&other_parent), Some(&other));
+
+        cache.clear();
+        assert_eq!(cache.get(&other_parent), None);
+    }
+
+    #[test]
+    fn test_built_payload_cache_disabled() {
+        let cache = BuiltPayloadCache::new(Duration::ZERO);
+        assert_eq!(
+            cache.key_at(
+                BlockHash::ZERO,
+                Address::repeat_byte(0),
+                Duration::from_secs(1_000)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pending_payload() {
+        let pending = PendingPayload {
+            parent_hash: B256::repeat_byte(1),
+            fee_recipient: Address::repeat_byte(2),
+            payload_id: PayloadId::new([3; 8]),
+        };
+
+        assert!(pending.matches(B256::repeat_byte(1), Address::repeat_byte(2)));
+        assert!(!pending.matches(B256::repeat_byte(4), Address::repeat_byte(2)));
+        assert!(!pending.matches(B256::repeat_byte(1), Address::repeat_byte(4)));
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let delay = Duration::from_secs(2);
+        assert!(pending_payload_timestamp(now.as_secs() - 1, delay).is_some());
+        assert!(pending_payload_timestamp(now.as_secs() + 2, delay).is_some());
+        assert_eq!(pending_payload_timestamp(now.as_secs() + 10, delay), None);
+    }
+
+    #[test]
+    fn test_check_fee_recipient() {
+        let (proposer, other) = (Address::repeat_byte(1), Address::repeat_byte(2));
+
+        let policy = FeeRecipientPolicy::Proposer;
+        assert_eq!(check_fee_recipient(proposer, &policy, &proposer), Ok(()));
+        assert_eq!(
+            check_fee_recipient(other, &policy, &proposer),
+            Err(FeeRecipientError::NotProposer)
+        );
+
+        let policy = FeeRecipientPolicy::Allowed(vec![other]);
+        assert_eq!(check_fee_recipient(other, &policy, &proposer), Ok(()));
+        assert_eq!(
+            check_fee_recipient(proposer, &policy, &proposer),
+            Err(FeeRecipientError::NotAllowed)
+        );
+    }
+
+    #[test]
+    fn test_classify_build_failures() {
+        let cases = [
+            (
+                eyre!("engine_getPayloadV4 timed out after 2s"),
+                BuildFailure::Timeout,
+            ),
+            (
+                eyre!("Timeout after 20s waiting for execution client to return the payload"),
+                BuildFailure::Timeout,
+            ),
+            (
+                eyre!("error sending request for url (http://localhost:8551/)"),
+                BuildFailure::Unreachable,
+            ),
+            (
+                eyre!("Invalid payload status: SYNCING"),
+                BuildFailure::InvalidStatus,
+            ),
+            (
+                eyre!("Server Message: code: -38003, message: Invalid payload attributes"),
+                BuildFailure::RpcError,
+            ),
+            (eyre!("Unsupported fork"), BuildFailure::Other),
+            (
+                eyre!("error sending request").wrap_err("Failed to build payload"),
+                BuildFailure::Unreachable,
+            ),
+            (
+                eyre::Report::new(EngineError::Timeout {
+                    method: "engine_getPayloadV4".to_string(),
+                    timeout: Duration::from_secs(2),
+                }),
+                BuildFailure::Timeout,
+            ),
+            (
+                eyre::Report::new(EngineError::SyncTimeout(Duration::from_secs(20))),
+                BuildFailure::Timeout,
+            ),
+            (
+                eyre::Report::new(EngineError::InvalidPayloadStatus(
+                    PayloadStatusEnum::Syncing,
+                ))
+                .wrap_err("Failed to build payload"),
+                BuildFailure::InvalidStatus,
+            ),
+            (
+                eyre::Report::new(EngineError::Rpc {
+                    method: "engine_forkchoiceUpdatedV3".to_string(),
+                    code: -38003,
+                    message: "Invalid payload attributes".to_string(),
+                }),
+                BuildFailure::RpcError,
+            ),
+            (
+                eyre::Report::new(EngineError::UnsupportedFork),
+                BuildFailure::Other,
+            ),
+        ];
+
+        for (error, expected) in cases {
+            assert_eq!(BuildFailure::classify(&error), expected, "{error}");
+        }
+    }
+}
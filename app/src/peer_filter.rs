@@ -0,0 +1,244 @@
+//! Allow and deny lists of the peers of the node on the consensus network.
+//!
+//! The lists are set by the `peer_filter` section of the emerald config, and are served
+//! by `GET /peer_filter` of the admin API:
+//!
+//! ```toml
+//! [peer_filter]
+//! # Peers which are never accepted
+//! denied_peers = ["16Uiu2HAm..."]
+//! # When not empty, only these peers are accepted
+//! allowed_peers = ["16Uiu2HAm..."]
+//! # Same, by IP address, for the peers dialed by the node
+//! denied_ips = ["10.0.0.1"]
+//! allowed_ips = ["10.0.0.2"]
+//! ```
+//!
+//! The networking layer does not let the application refuse connections, so the lists
+//! are enforced where the application knows the peers:
+//! - the persistent peers rejected by the lists are not dialed, and discovery is disabled
+//!   when `allowed_peers` is set, so that the node only dials the allowed peers
+//! - the proposal parts streamed by rejected peers are ignored
+//!
+//! Votes and synced values are handled by the consensus engine without telling the
+//! application which peer sent them, and are not filtered. Neither are the connections
+//! of the peers dialing the node, whatever their IP address: `allowed_ips` and `denied_ips`
+//! only apply to the persistent peers. Since the persistent peers are only dialed at startup,
+//! the lists cannot be replaced while the node is running, and changing them requires a
+//! restart. A firewall is needed to keep the rejected peers off the network entirely.
+
+use core::net::IpAddr;
+use core::str::FromStr;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use color_eyre::eyre::{self, eyre};
+use malachitebft_app_channel::app::types::PeerId;
+use malachitebft_eth_cli::config::{P2pConfig, PeerFilterConfig};
+use tracing::{info, warn};
+
+/// Reason why a peer is rejected
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The peer id is denied
+    DeniedPeer,
+    /// The peer id is not allowed
+    UnlistedPeer,
+    /// The IP address is denied
+    DeniedIp,
+    /// The IP address is not allowed
+    UnlistedIp,
+}
+
+impl Rejection {
+    /// Reason reported in the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DeniedPeer => "denied_peer",
+            Self::UnlistedPeer => "unlisted_peer",
+            Self::DeniedIp => "denied_ip",
+            Self::UnlistedIp => "unlisted_ip",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerFilter {
+    allowed_peers: BTreeSet<PeerId>,
+    denied_peers: BTreeSet<PeerId>,
+    allowed_ips: BTreeSet<IpAddr>,
+    denied_ips: BTreeSet<IpAddr>,
+}
+
+impl PeerFilter {
+    pub fn from_config(config: &PeerFilterConfig) -> eyre::Result<Self> {
+        let parse_peers = |peers: &[String]| {
+            peers
+                .iter()
+                .map(|peer| {
+                    PeerId::from_str(peer).map_err(|e| eyre!("Invalid peer id `{peer}`: {e}"))
+                })
+                .collect::<eyre::Result<BTreeSet<_>>>()
+        };
+
+        Ok(Self {
+            allowed_peers: parse_peers(&config.allowed_peers)?,
+            denied_peers: parse_peers(&config.denied_peers)?,
+            allowed_ips: config.allowed_ips.iter().copied().collect(),
+            denied_ips: config.denied_ips.iter().copied().collect(),
+        })
+    }
+
+    pub fn to_config(&self) -> PeerFilterConfig {
+        PeerFilterConfig {
+            allowed_peers: self.allowed_peers.iter().map(ToString::to_string).collect(),
+            denied_peers: self.denied_peers.iter().map(ToString::to_string).collect(),
+            allowed_ips: self.allowed_ips.iter().copied().collect(),
+            denied_ips: self.denied_ips.iter().copied().collect(),
+        }
+    }
+
+    pub fn check_peer(&self, peer_id: &PeerId) -> Result<(), Rejection> {
+        if self.denied_peers.contains(peer_id) {
+            return Err(Rejection::DeniedPeer);
+        }
+
+        if !self.allowed_peers.is_empty() && !self.allowed_peers.contains(peer_id) {
+            return Err(Rejection::UnlistedPeer);
+        }
+
+        Ok(())
+    }
+
+    pub fn check_ip(&self, ip: &IpAddr) -> Result<(), Rejection> {
+        if self.denied_ips.contains(ip) {
+            return Err(Rejection::DeniedIp);
+        }
+
+        if !self.allowed_ips.is_empty() && !self.allowed_ips.contains(ip) {
+            return Err(Rejection::UnlistedIp);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the IP address and the peer id of a multiaddr, e.g.
+    /// `/ip4/10.0.0.1/tcp/27000/p2p/16Uiu2HAm...`.
+    ///
+    /// A multiaddr without peer id is only rejected by an IP address, and one with a DNS
+    /// name instead of an IP address only by its peer id.
+    pub fn check_addr(&self, addr: &str) -> Result<(), Rejection> {
+        let components: Vec<&str> = addr.split('/').filter(|c| !c.is_empty()).collect();
+
+        for pair in components.windows(2) {
+            match pair {
+                ["ip4" | "ip6", ip] => {
+                    if let Ok(ip) = IpAddr::from_str(ip) {
+                        self.check_ip(&ip)?;
+                    }
+                }
+                ["p2p", peer_id] => {
+                    if let Ok(peer_id) = PeerId::from_str(peer_id) {
+                        self.check_peer(&peer_id)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the rejected persistent peers of the P2P configuration, and disables
+    /// discovery if only some peers are allowed.
+    pub fn apply(&self, p2p: &mut P2pConfig) {
+        p2p.persistent_peers.retain(|addr| match self.check_addr(&addr.to_string()) {
+            Ok(()) => true,
+            Err(rejection) => {
+                warn!(%addr, reason = rejection.as_str(), "Not dialing persistent peer rejected by the peer filter");
+                false
+            }
+        });
+
+        if !self.allowed_peers.is_empty() && p2p.discovery.enabled {
+            info!("Disabling peer discovery, only the allowed persistent peers are dialed");
+            p2p.discovery.enabled = false;
+        }
+    }
+}
+
+/// Peer filter shared between the application and the admin API
+#[derive(Clone, Debug, Default)]
+pub struct SharedPeerFilter(Arc<PeerFilter>);
+
+impl SharedPeerFilter {
+    pub fn new(filter: PeerFilter) -> Self {
+        Self(Arc::new(filter))
+    }
+
+    pub fn get(&self) -> PeerFilterConfig {
+        self.0.to_config()
+    }
+
+    pub fn check_peer(&self, peer_id: &PeerId) -> Result<(), Rejection> {
+        self.0.check_peer(peer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p_identity::secp256k1::{Keypair as Secp256k1Keypair, SecretKey};
+    use libp2p_identity::Keypair;
+
+    use super::*;
+
+    fn peer_id(seed: u8) -> String {
+        let secret_key = SecretKey::try_from_bytes([seed; 32]).unwrap();
+        Keypair::from(Secp256k1Keypair::from(secret_key))
+            .public()
+            .to_peer_id()
+            .to_string()
+    }
+
+    #[test]
+    fn test_peer_filter() {
+        let (allowed, denied, unlisted) = (peer_id(1), peer_id(2), peer_id(3));
+
+        let filter = PeerFilter::from_config(&PeerFilterConfig {
+            allowed_peers: vec![allowed.clone(), denied.clone()],
+            denied_peers: vec![denied.clone()],
+            allowed_ips: vec![],
+            denied_ips: vec!["10.0.0.1".parse().unwrap()],
+        })
+        .unwrap();
+
+        let check = |peer: &str| filter.check_peer(&PeerId::from_str(peer).unwrap());
+        assert_eq!(check(&allowed), Ok(()));
+        assert_eq!(check(&denied), Err(Rejection::DeniedPeer));
+        assert_eq!(check(&unlisted), Err(Rejection::UnlistedPeer));
+
+        assert_eq!(filter.check_addr("/ip4/10.0.0.2/tcp/27000"), Ok(()));
+        assert_eq!(
+            filter.check_addr("/ip4/10.0.0.1/tcp/27000"),
+            Err(Rejection::DeniedIp)
+        );
+        assert_eq!(
+            filter.check_addr(&format!("/ip4/10.0.0.2/tcp/27000/p2p/{allowed}")),
+            Ok(())
+        );
+        assert_eq!(
+            filter.check_addr(&format!("/dns/node-1/tcp/27000/p2p/{unlisted}")),
+            Err(Rejection::UnlistedPeer)
+        );
+
+        assert_eq!(
+            PeerFilter::from_config(&filter.to_config()).unwrap(),
+            filter
+        );
+        assert!(PeerFilter::from_config(&PeerFilterConfig {
+            denied_peers: vec!["not-a-peer-id".to_string()],
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
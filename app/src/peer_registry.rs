@@ -0,0 +1,158 @@
+//! Registry of the peers known to the application on the consensus network.
+//!
+//! The consensus engine does not notify the application of the peers joining and leaving
+//! the network, nor of the heights they advertise to the sync protocol, so the registry
+//! is built from the messages which tell the application which peer sent them: the parts
+//! of the proposals streamed by the peers. Each peer is recorded with the lowest and
+//! highest heights of the proposals it streamed, and when it was last seen.
+//!
+//! Peers which have not been seen for a while are dropped, as they have most likely left.
+//! The registry is served by the admin API.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use malachitebft_app_channel::app::types::PeerId;
+use malachitebft_eth_types::Height;
+use serde::Serialize;
+
+/// Time after which a peer which has not been seen is dropped from the registry
+const PEER_EXPIRY: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Copy, Clone, Debug)]
+struct PeerEntry {
+    earliest_height: Option<Height>,
+    latest_height: Option<Height>,
+    proposal_parts: u64,
+    last_seen: Instant,
+    last_seen_unix: u64,
+}
+
+/// Peer as served by the admin API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PeerSummary {
+    pub peer_id: String,
+    /// Lowest height of the proposals streamed by the peer
+    pub earliest_height: Option<u64>,
+    /// Highest height of the proposals streamed by the peer
+    pub latest_height: Option<u64>,
+    /// Number of proposal parts received from the peer
+    pub proposal_parts: u64,
+    /// When the peer was last seen, in seconds since the Unix epoch
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct PeerRegistry {
+    peers: BTreeMap<PeerId, PeerEntry>,
+}
+
+impl PeerRegistry {
+    /// Records a part of a proposal streamed by `peer_id` at `now`, with the height of
+    /// the proposal if the part tells it, and drops the peers which have not been seen
+    /// for [`PEER_EXPIRY`].
+    pub fn record(&mut self, peer_id: PeerId, height: Option<Height>, now: Instant) {
+        let last_seen_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let entry = self.peers.entry(peer_id).or_insert(PeerEntry {
+            earliest_height: None,
+            latest_height: None,
+            proposal_parts: 0,
+            last_seen: now,
+            last_seen_unix,
+        });
+
+        if let Some(height) = height {
+            entry.earliest_height = Some(entry.earliest_height.map_or(height, |h| h.min(height)));
+            entry.latest_height = Some(entry.latest_height.map_or(height, |h| h.max(height)));
+        }
+        entry.proposal_parts += 1;
+        entry.last_seen = now;
+        entry.last_seen_unix = last_seen_unix;
+
+        self.peers
+            .retain(|_, entry| now.saturating_duration_since(entry.last_seen) < PEER_EXPIRY);
+    }
+
+    pub fn summary(&self) -> Vec<PeerSummary> {
+        self.peers
+            .iter()
+            .map(|(peer_id, entry)| PeerSummary {
+                peer_id: peer_id.to_string(),
+                earliest_height: entry.earliest_height.map(|height| height.as_u64()),
+                latest_height: entry.latest_height.map(|height| height.as_u64()),
+                proposal_parts: entry.proposal_parts,
+                last_seen: entry.last_seen_unix,
+            })
+            .collect()
+    }
+}
+
+/// Peer registry shared between the application and the admin API
+#[derive(Clone, Debug, Default)]
+pub struct SharedPeerRegistry(Arc<Mutex<PeerRegistry>>);
+
+impl SharedPeerRegistry {
+    pub fn record(&self, peer_id: PeerId, height: Option<Height>, now: Instant) {
+        self.0
+            .lock()
+            .expect("peer registry lock poisoned")
+            .record(peer_id, height, now)
+    }
+
+    pub fn summary(&self) -> Vec<PeerSummary> {
+        self.0
+            .lock()
+            .expect("peer registry lock poisoned")
+            .summary()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use libp2p_identity::secp256k1::{Keypair as Secp256k1Keypair, SecretKey};
+    use libp2p_identity::Keypair;
+
+    use super::*;
+
+    fn peer_id(seed: u8) -> PeerId {
+        let secret_key = SecretKey::try_from_bytes([seed; 32]).unwrap();
+        let peer_id = Keypair::from(Secp256k1Keypair::from(secret_key))
+            .public()
+            .to_peer_id();
+        PeerId::from_str(&peer_id.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_record_peers() {
+        let (a, b) = (peer_id(1), peer_id(2));
+        let now = Instant::now();
+        let mut registry = PeerRegistry::default();
+
+        registry.record(a, None, now);
+        registry.record(a, Some(Height::new(5)), now);
+        registry.record(a, Some(Height::new(3)), now);
+        registry.record(b, Some(Height::new(7)), now);
+
+        let summary = registry.summary();
+        let a_summary = summary.iter().find(|p| p.peer_id == a.to_string()).unwrap();
+        assert_eq!(a_summary.earliest_height, Some(3));
+        assert_eq!(a_summary.latest_height, Some(5));
+        assert_eq!(a_summary.proposal_parts, 3);
+
+        assert_eq!(summary.len(), 2);
+
+        // Peers not seen for a while are dropped
+        registry.record(b, Some(Height::new(8)), now + PEER_EXPIRY);
+        let summary = registry.summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].peer_id, b.to_string());
+        assert_eq!(summary[0].latest_height, Some(8));
+    }
+}
@@ -0,0 +1,358 @@
+//! Conformance suite of the validation of the proposals streamed by peers, driven by the
+//! vectors of `app/tests/vectors/proposal_validation.json`, so that alternative
+//! implementations can check that they reject the same proposals for the same reasons.
+//!
+//! Each vector describes a proposal for the block following the `parent` block:
+//! - the validators have the secp256k1 private keys `[i + 1; 32]` and a voting power of 1,
+//!   and the proposal is made by the `proposer` of the round, by `other`, the first other
+//!   validator, or by an `outsider` of the validator set with the private key `[0xff; 32]`
+//! - the proposal is signed by the `signer`, which is the proposer unless set
+//! - the payload is the SSZ encoding of an execution payload with the given fields, or
+//!   `raw` bytes, split into data parts of `chunk_size` bytes (64 by default)
+//! - the stream is made of the init part, the data parts, the part carrying the signature
+//!   and the end of the stream, and is altered by the `mutations` before being delivered
+//!
+//! The `expected` outcome is either `accepted`, if the proposal is handed to the execution
+//! client, or the reason of the rejection.
+
+use core::str::FromStr;
+
+use bytes::Bytes;
+use libp2p_identity::secp256k1::{Keypair as Secp256k1Keypair, SecretKey};
+use libp2p_identity::Keypair;
+use malachitebft_app_channel::app::streaming::{StreamContent, StreamId, StreamMessage};
+use malachitebft_app_channel::app::types::core::{Context, Round};
+use malachitebft_app_channel::app::types::PeerId;
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_types::secp256k1::{K256Provider, PrivateKey};
+use malachitebft_eth_types::{
+    Address, BlockHash, EmeraldContext, Height, ProposalData, ProposalFin, ProposalInit,
+    ProposalPart, Validator, ValidatorSet, B256,
+};
+use serde::Deserialize;
+use sha3::Digest;
+use ssz::{Decode, Encode};
+
+use alloy_rpc_types_engine::ExecutionPayloadV3;
+
+use crate::payload::check_linkage;
+use crate::state::{assemble_value_from_parts, validate_proposal_parts};
+use crate::streaming::PartStreamsMap;
+
+const VECTORS: &str = include_str!("../tests/vectors/proposal_validation.json");
+
+#[derive(Deserialize)]
+struct Vectors {
+    validators: u8,
+    parent: Parent,
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+struct Parent {
+    block_number: u64,
+    block_hash: BlockHash,
+    timestamp: u64,
+}
+
+#[derive(Copy, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    #[default]
+    Proposer,
+    Other,
+    Outsider,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    Raw {
+        raw: String,
+    },
+    Fields {
+        block_number: u64,
+        parent_hash: BlockHash,
+        timestamp: u64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Mutation {
+    /// The data part at `sequence` is altered after the proposal is signed
+    Tamper { sequence: u64 },
+    /// The message at `sequence` is not delivered
+    Drop { sequence: u64 },
+    /// The end of the stream is not delivered
+    DropEndOfStream,
+    /// The stream is built without the init part
+    OmitInitPart,
+    /// The stream is built without the part carrying the signature
+    OmitFinPart,
+    /// The message at `sequence` is delivered twice, possibly altered, before or after the original
+    Duplicate {
+        sequence: u64,
+        tamper: bool,
+        before: bool,
+    },
+    /// The messages are delivered in reverse order
+    Reverse,
+}
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    #[serde(default = "default_height")]
+    height: u64,
+    #[serde(default)]
+    round: u32,
+    #[serde(default)]
+    proposer: Role,
+    #[serde(default)]
+    signer: Role,
+    payload: Payload,
+    #[serde(default = "default_chunk_size")]
+    chunk_size: usize,
+    #[serde(default)]
+    max_payload_bytes: Option<u64>,
+    #[serde(default)]
+    mutations: Vec<Mutation>,
+    expected: String,
+}
+
+fn default_height() -> u64 {
+    5
+}
+
+fn default_chunk_size() -> usize {
+    64
+}
+
+struct Keys {
+    validator_set: ValidatorSet,
+    keys: Vec<(Address, PrivateKey)>,
+    outsider: (Address, PrivateKey),
+}
+
+impl Keys {
+    fn new(count: u8) -> Self {
+        let address = |key: &PrivateKey| Validator::new(key.public_key(), 1).address;
+
+        let keys = (0..count)
+            .map(|i| PrivateKey::from_slice(&[i + 1; 32]).unwrap())
+            .map(|key| (address(&key), key))
+            .collect::<Vec<_>>();
+        let validator_set = ValidatorSet::new(
+            keys.iter()
+                .map(|(_, key)| Validator::new(key.public_key(), 1)),
+        );
+        let outsider = PrivateKey::from_slice(&[0xff; 32]).unwrap();
+
+        Self {
+            validator_set,
+            keys,
+            outsider: (address(&outsider), outsider),
+        }
+    }
+
+    fn get(&self, role: Role, height: Height, round: Round) -> &(Address, PrivateKey) {
+        let proposer = EmeraldContext::new()
+            .select_proposer(&self.validator_set, height, round)
+            .address;
+
+        match role {
+            Role::Proposer => self.keys.iter().find(|(a, _)| *a == proposer).unwrap(),
+            Role::Other => self.keys.iter().find(|(a, _)| *a != proposer).unwrap(),
+            Role::Outsider => &self.outsider,
+        }
+    }
+}
+
+fn payload_bytes(payload: &Payload) -> Bytes {
+    match payload {
+        Payload::Raw { raw } => Bytes::from(hex::decode(raw.trim_start_matches("0x")).unwrap()),
+        Payload::Fields {
+            block_number,
+            parent_hash,
+            timestamp,
+        } => {
+            let mut payload = ExecutionPayloadV3::default();
+            payload.payload_inner.payload_inner.block_number = *block_number;
+            payload.payload_inner.payload_inner.parent_hash = *parent_hash;
+            payload.payload_inner.payload_inner.timestamp = *timestamp;
+            Bytes::from(payload.as_ssz_bytes())
+        }
+    }
+}
+
+fn tampered(msg: &StreamMessage<ProposalPart>) -> StreamMessage<ProposalPart> {
+    let StreamContent::Data(ProposalPart::Data(data)) = &msg.content else {
+        panic!("only data parts can be tampered with");
+    };
+
+    let mut bytes = data.bytes.to_vec();
+    bytes[0] ^= 0xff;
+    StreamMessage::new(
+        msg.stream_id.clone(),
+        msg.sequence,
+        StreamContent::Data(ProposalPart::Data(ProposalData::new(Bytes::from(bytes)))),
+    )
+}
+
+/// Builds the stream of the proposal of a vector, as delivered to the node
+fn stream(vector: &Vector, keys: &Keys) -> Vec<StreamMessage<ProposalPart>> {
+    let height = Height::new(vector.height);
+    let round = Round::new(vector.round);
+    let (proposer, _) = keys.get(vector.proposer, height, round);
+    let (_, signer) = keys.get(vector.signer, height, round);
+    let data = payload_bytes(&vector.payload);
+
+    let mut hasher = sha3::Keccak256::new();
+    hasher.update(height.as_u64().to_be_bytes());
+    hasher.update(round.as_i64().to_be_bytes());
+
+    let mut parts = vec![ProposalPart::Init(ProposalInit::new(
+        height,
+        round,
+        Round::Nil,
+        *proposer,
+    ))];
+    for chunk in data.chunks(vector.chunk_size) {
+        parts.push(ProposalPart::Data(ProposalData::new(
+            Bytes::copy_from_slice(chunk),
+        )));
+        hasher.update(chunk);
+    }
+    let signature = K256Provider::new(signer.clone()).sign(&hasher.finalize());
+    parts.push(ProposalPart::Fin(ProposalFin::new(signature)));
+
+    for mutation in &vector.mutations {
+        match mutation {
+            Mutation::OmitInitPart => {
+                parts.remove(0);
+            }
+            Mutation::OmitFinPart => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let stream_id = StreamId::new(Bytes::from_static(b"conformance"));
+    let end = parts.len() as u64;
+    let mut msgs = parts
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, part)| {
+            StreamMessage::new(
+                stream_id.clone(),
+                sequence as u64,
+                StreamContent::Data(part),
+            )
+        })
+        .chain([StreamMessage::new(
+            stream_id.clone(),
+            end,
+            StreamContent::Fin,
+        )])
+        .collect::<Vec<_>>();
+
+    for mutation in &vector.mutations {
+        match *mutation {
+            Mutation::Tamper { sequence } => {
+                let i = msgs.iter().position(|m| m.sequence == sequence).unwrap();
+                msgs[i] = tampered(&msgs[i]);
+            }
+            Mutation::Drop { sequence } => msgs.retain(|m| m.sequence != sequence),
+            Mutation::DropEndOfStream => msgs.retain(|m| m.sequence != end),
+            Mutation::Duplicate {
+                sequence,
+                tamper,
+                before,
+            } => {
+                let i = msgs.iter().position(|m| m.sequence == sequence).unwrap();
+                let copy = if tamper {
+                    tampered(&msgs[i])
+                } else {
+                    msgs[i].clone()
+                };
+                msgs.insert(if before { i } else { i + 1 }, copy);
+            }
+            Mutation::Reverse => msgs.reverse(),
+            Mutation::OmitInitPart | Mutation::OmitFinPart => {}
+        }
+    }
+
+    msgs
+}
+
+/// Delivers the stream of a vector to the node, and returns the outcome of its validation
+fn outcome(vector: &Vector, keys: &Keys, parent: &ExecutionBlock) -> String {
+    let secret_key = SecretKey::try_from_bytes([1; 32]).unwrap();
+    let peer_id = Keypair::from(Secp256k1Keypair::from(secret_key))
+        .public()
+        .to_peer_id();
+    let peer_id = PeerId::from_str(&peer_id.to_string()).unwrap();
+    let mut streams = PartStreamsMap::new(None);
+
+    let mut completed = None;
+    for msg in stream(vector, keys) {
+        match streams.insert(peer_id, msg) {
+            Ok(Some(parts)) => {
+                completed = Some(parts);
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => return e.as_str().to_string(),
+        }
+    }
+    let Some(parts) = completed else {
+        return "incomplete_stream".to_string();
+    };
+
+    let ctx = EmeraldContext::new();
+    let signing_provider = K256Provider::new(keys.outsider.1.clone());
+    if let Err(e) = validate_proposal_parts(&ctx, &signing_provider, &keys.validator_set, &parts) {
+        return e.as_str().to_string();
+    }
+
+    let (_, data) = assemble_value_from_parts(parts);
+    if vector
+        .max_payload_bytes
+        .is_some_and(|max_payload_bytes| data.len() as u64 > max_payload_bytes)
+    {
+        return "oversized_payload".to_string();
+    }
+
+    let Ok(payload) = ExecutionPayloadV3::from_ssz_bytes(&data) else {
+        return "invalid_ssz".to_string();
+    };
+
+    match check_linkage(&payload, Height::new(vector.height), parent) {
+        Ok(()) => "accepted".to_string(),
+        Err(e) => e.as_str().to_string(),
+    }
+}
+
+#[test]
+fn test_proposal_validation_vectors() {
+    let vectors: Vectors = serde_json::from_str(VECTORS).unwrap();
+    let keys = Keys::new(vectors.validators);
+    let parent = ExecutionBlock {
+        block_hash: vectors.parent.block_hash,
+        block_number: vectors.parent.block_number,
+        parent_hash: B256::ZERO,
+        timestamp: vectors.parent.timestamp,
+        prev_randao: B256::ZERO,
+    };
+
+    for vector in &vectors.vectors {
+        assert_eq!(
+            outcome(vector, &keys, &parent),
+            vector.expected,
+            "vector: {}",
+            vector.name
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! JSON-RPC proxy in front of the execution client, giving consensus-aware answers.
+//!
+//! Requests, single or batched, are forwarded as is to the execution client. The blocks
+//! returned by `eth_getBlockByNumber` and `eth_getBlockByHash` are extended with an
+//! `emerald` field:
+//!
+//! ```json
+//! "emerald": {
+//!   "finalized": true,
+//!   "certificate": { "height": 42, "round": 0, "valueId": "...", "signers": ["0x..."] }
+//! }
+//! ```
+//!
+//! A block is finalized once it has been decided. The certificate is `null` for blocks
+//! which are not finalized, or whose certificate has been pruned from the store.
+
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::io;
+use std::sync::Arc;
+
+use alloy_rpc_types_engine::ExecutionPayloadV3;
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use color_eyre::eyre::{self, eyre, OptionExt};
+use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use malachitebft_eth_types::{BlockHash, Height};
+use serde_json::{json, Value};
+use ssz::Decode;
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+
+use crate::store::Store;
+
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Standard JSON-RPC error code for internal errors
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Clone)]
+struct Proxy {
+    eth: Arc<EthereumRPC>,
+    store: Store,
+}
+
+#[tracing::instrument(name = "rpc_proxy", skip_all)]
+pub async fn serve(listen_addr: SocketAddr, eth: EthereumRPC, store: Store) {
+    if let Err(e) = inner(listen_addr, eth, store).await {
+        error!("RPC proxy failed: {e}");
+    }
+}
+
+async fn inner(listen_addr: SocketAddr, eth: EthereumRPC, store: Store) -> io::Result<()> {
+    let proxy = Proxy {
+        eth: Arc::new(eth),
+        store,
+    };
+
+    let app = Router::new().route("/", post(handle)).with_state(proxy);
+
+    let listener = TcpListener::bind(listen_addr).await?;
+    let local_addr = listener.local_addr()?;
+
+    info!(address = %local_addr, "Serving RPC proxy");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn handle(State(proxy): State<Proxy>, Json(request): Json<Value>) -> Json<Value> {
+    let response = match request {
+        Value::Array(requests) => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(proxy.handle_request(request).await);
+            }
+            Value::Array(responses)
+        }
+        request => proxy.handle_request(request).await,
+    };
+
+    Json(response)
+}
+
+impl Proxy {
+    async fn handle_request(&self, request: Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+        let mut response = match self.eth.forward(&request, FORWARD_TIMEOUT).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Failed to forward request to the execution client: {e}");
+                return json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": INTERNAL_ERROR, "message": e.to_string() },
+                });
+            }
+        };
+
+        let method = request.get("method").and_then(Value::as_str);
+        if !matches!(method, Some("eth_getBlockByNumber" | "eth_getBlockByHash")) {
+            return response;
+        }
+
+        if let Some(block) = response.get_mut("result").filter(|block| block.is_object()) {
+            match self.finality(block).await {
+                Ok(finality) => block["emerald"] = finality,
+                Err(e) => warn!("Failed to get the finality of a block: {e}"),
+            }
+        }
+
+        response
+    }
+
+    async fn finality(&self, block: &Value) -> eyre::Result<Value> {
+        let number = block
+            .get("number")
+            .and_then(Value::as_str)
+            .ok_or_eyre("Block has no number")?;
+        let number = u64::from_str_radix(number.trim_start_matches("0x"), 16)?;
+        let hash: BlockHash =
+            serde_json::from_value(block.get("hash").cloned().ok_or_eyre("Block has no hash")?)?;
+
+        // Consensus height `h` decides the execution block number `h`
+        let height = Height::new(number);
+
+        let decided = self.store.max_decided_value_height();
+        if decided.is_none_or(|decided| height > decided) {
+            return Ok(json!({ "finalized": false, "certificate": null }));
+        }
+
+        let (finalized, certificate) = match self.store.get_certificate_and_header(height).await? {
+            Some((certificate, header)) => {
+                let header = ExecutionPayloadV3::from_ssz_bytes(&header)
+                    .map_err(|e| eyre!("Invalid block header at height {height}: {e:?}"))?;
+                let finalized = header.payload_inner.payload_inner.block_hash == hash;
+                (finalized, finalized.then_some(certificate))
+            }
+            None => {
+                // The certificate has been pruned, but the decided blocks are the
+                // canonical blocks of the execution client
+                let canonical = self
+                    .eth
+                    .get_block_by_number(&format!("0x{number:x}"))
+                    .await?;
+                (
+                    canonical.is_some_and(|canonical| canonical.block_hash == hash),
+                    None,
+                )
+            }
+        };
+
+        let certificate = certificate.map(|certificate| {
+            json!({
+                "height": certificate.height.as_u64(),
+                "round": certificate.round.as_i64(),
+                "valueId": certificate.value_id.to_string(),
+                "signers": certificate
+                    .commit_signatures
+                    .iter()
+                    .map(|signature| signature.address.to_string())
+                    .collect::<Vec<_>>(),
+            })
+        });
+
+        Ok(json!({ "finalized": finalized, "certificate": certificate }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use malachitebft_app_channel::app::types::core::{CommitCertificate, Round};
+    use malachitebft_eth_cli::config::StoreLimitsConfig;
+    use malachitebft_eth_types::{Value as DecidedValue, B256};
+    use ssz::Encode;
+    use url::Url;
+
+    use super::*;
+    use crate::metrics::DbMetrics;
+    use crate::state::testing::{spawn_execution_client, MockAnswer};
+
+    const DECIDED_HASH: B256 = B256::repeat_byte(1);
+
+    /// Execution client answering `eth_getBlockByHash` with block 1 of the requested hash,
+    /// `eth_getBlockByNumber` with a block of another hash, and `0x1` to other methods
+    fn execution_client(request: &Value) -> MockAnswer {
+        let result = match request["method"].as_str() {
+            Some("eth_getBlockByHash") => json!({ "number": "0x1", "hash": request["params"][0] }),
+            Some("eth_getBlockByNumber") => {
+                json!({ "number": request["params"][0], "hash": B256::repeat_byte(2) })
+            }
+            _ => json!("0x1"),
+        };
+
+        Ok(json!({ "result": result }))
+    }
+
+    /// Proxy whose store decided height 1, with block hash [`DECIDED_HASH`]
+    async fn proxy(eth_url: Url, dir: &tempfile::TempDir) -> Proxy {
+        let store = Store::open(
+            dir.path().join("store.db"),
+            DbMetrics::new(),
+            None,
+            StoreLimitsConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let value = DecidedValue::new(Bytes::from_static(b"value"));
+        let certificate = CommitCertificate {
+            height: Height::new(1),
+            round: Round::new(0),
+            value_id: value.id(),
+            commit_signatures: vec![],
+        };
+        let mut header = ExecutionPayloadV3::default();
+        header.payload_inner.payload_inner.block_number = 1;
+        header.payload_inner.payload_inner.block_hash = DECIDED_HASH;
+        store
+            .store_decided_value(&certificate, value, Bytes::from(header.as_ssz_bytes()))
+            .await
+            .unwrap();
+
+        Proxy {
+            eth: Arc::new(EthereumRPC::new(eth_url).unwrap()),
+            store,
+        }
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let proxy = proxy(spawn_execution_client(execution_client).await, &dir).await;
+
+        // Other methods are forwarded as is
+        let response = proxy
+            .handle_request(request(7, "eth_chainId", json!([])))
+            .await;
+        assert_eq!(
+            response,
+            json!({ "jsonrpc": "2.0", "id": 7, "result": "0x1" })
+        );
+
+        // Batches are answered in order
+        let Json(responses) = handle(
+            State(proxy),
+            Json(json!([
+                request(1, "eth_chainId", json!([])),
+                request(2, "eth_blockNumber", json!([])),
+            ])),
+        )
+        .await;
+        let ids: Vec<_> = responses
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|response| response["id"].clone())
+            .collect();
+        assert_eq!(ids, vec![json!(1), json!(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_finality() {
+        let dir = tempfile::tempdir().unwrap();
+        let proxy = proxy(spawn_execution_client(execution_client).await, &dir).await;
+
+        // The decided block carries its certificate
+        let response = proxy
+            .handle_request(request(
+                1,
+                "eth_getBlockByHash",
+                json!([DECIDED_HASH, false]),
+            ))
+            .await;
+        let emerald = &response["result"]["emerald"];
+        assert_eq!(emerald["finalized"], json!(true));
+        assert_eq!(emerald["certificate"]["height"], json!(1));
+        assert_eq!(emerald["certificate"]["round"], json!(0));
+        assert_eq!(emerald["certificate"]["signers"], json!([]));
+
+        // Another block at the decided height is not finalized
+        let response = proxy
+            .handle_request(request(2, "eth_getBlockByNumber", json!(["0x1", false])))
+            .await;
+        assert_eq!(
+            response["result"]["emerald"],
+            json!({ "finalized": false, "certificate": null })
+        );
+
+        // Blocks above the decided height are not finalized
+        let response = proxy
+            .handle_request(request(3, "eth_getBlockByNumber", json!(["0x2", false])))
+            .await;
+        assert_eq!(
+            response["result"]["emerald"],
+            json!({ "finalized": false, "certificate": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_execution_client() {
+        // Bind then drop a listener, so that nothing listens on its port
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+
+        let dir = tempfile::tempdir().unwrap();
+        let proxy = proxy(url, &dir).await;
+
+        let response = proxy
+            .handle_request(request(5, "eth_chainId", json!([])))
+            .await;
+        assert_eq!(response["id"], json!(5));
+        assert_eq!(response["error"]["code"], json!(INTERNAL_ERROR));
+        assert!(response.get("result").is_none());
+    }
+}
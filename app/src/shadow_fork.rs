@@ -0,0 +1,547 @@
+//! Shadow fork: the node follows an existing network without taking part in its consensus,
+//! and re-executes the decided blocks on an execution client whose genesis or fork schedule
+//! was modified, e.g. to test an upgrade of the execution layer against the real traffic
+//! and consensus data of the network.
+//!
+//! The decided blocks cannot be imported as they are by the shadow execution client, their
+//! hashes committing to the state of the network. For each decided block, its transactions
+//! are instead submitted to the pool of the shadow execution client, which is then asked to
+//! build a block on top of its own head with the timestamp and fee recipient of the decided
+//! block, and to make it its head. The transactions left out of the re-executed block, the
+//! ones it took from its pool instead, and a different amount of gas used are reported as
+//! divergences in the logs, the `shadow_fork_divergences` metric and the event log, without
+//! stopping the node.
+//!
+//! The values synced from the peers are only decoded, their commit certificates being
+//! verified before they are committed, and the next validator sets are read from the shadow
+//! execution client, which must thus keep the validator manager contract of the network.
+//!
+//! As that contract may be modified along with the genesis of the shadow execution client,
+//! the node never loads the validator key: as in archive-sync mode, it runs with a key
+//! generated at each start, which no validator set can contain, and never proposes a value.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use alloy_rpc_types_engine::{ExecutionPayloadV3, ForkchoiceState};
+use async_trait::async_trait;
+use bytes::Bytes;
+use color_eyre::eyre::{self, eyre, OptionExt};
+use malachitebft_app_channel::app::types::core::{Round, Validity};
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_types::{Address, Block, BlockHash, Height, RetryConfig};
+use ssz::Decode;
+use tracing::{debug, info, warn};
+
+use crate::bootstrap::limited_validator_set;
+use crate::event_log::Event;
+use crate::state::State;
+use crate::sync_handler::{BoxError, PayloadValidator};
+use crate::validators::read_validators_from_contract;
+
+/// Head of the chain of the shadow execution client, which has its own block hashes
+#[derive(Debug, Default)]
+pub struct ShadowFork {
+    head: Option<ExecutionBlock>,
+}
+
+impl ShadowFork {
+    /// Head of the shadow execution client, once the node is initialized
+    pub fn head(&self) -> Option<ExecutionBlock> {
+        self.head
+    }
+}
+
+/// Divergence of a block re-executed by the shadow execution client from the decided one
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    /// Transactions of the decided block missing from the re-executed one, e.g. rejected
+    /// by the modified rules
+    MissingTxs(usize),
+    /// Transactions of the re-executed block missing from the decided one, left in the
+    /// pool by a previous block
+    ExtraTxs(usize),
+    /// Same transactions, in a different order
+    ReorderedTxs,
+    /// Same transactions, using a different amount of gas
+    GasUsed { decided: u64, shadow: u64 },
+}
+
+impl Divergence {
+    /// Kind of the divergence, in the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MissingTxs(_) => "missing_txs",
+            Self::ExtraTxs(_) => "extra_txs",
+            Self::ReorderedTxs => "reordered_txs",
+            Self::GasUsed { .. } => "gas_used",
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTxs(count) => write!(f, "{count} transactions missing"),
+            Self::ExtraTxs(count) => write!(f, "{count} extra transactions"),
+            Self::ReorderedTxs => write!(f, "transactions reordered"),
+            Self::GasUsed { decided, shadow } => {
+                write!(f, "{shadow} gas used instead of {decided}")
+            }
+        }
+    }
+}
+
+/// Divergences of the re-executed block from the decided one
+pub fn compare(decided: &ExecutionPayloadV3, shadow: &ExecutionPayloadV3) -> Vec<Divergence> {
+    let decided = &decided.payload_inner.payload_inner;
+    let shadow = &shadow.payload_inner.payload_inner;
+
+    let decided_txs: HashSet<_> = decided.transactions.iter().collect();
+    let shadow_txs: HashSet<_> = shadow.transactions.iter().collect();
+    let missing = decided_txs.difference(&shadow_txs).count();
+    let extra = shadow_txs.difference(&decided_txs).count();
+
+    let mut divergences = Vec::new();
+    if missing > 0 {
+        divergences.push(Divergence::MissingTxs(missing));
+    }
+    if extra > 0 {
+        divergences.push(Divergence::ExtraTxs(extra));
+    }
+    if missing == 0 && extra == 0 {
+        // The gas used is only comparable for the same transactions
+        if decided.transactions != shadow.transactions {
+            divergences.push(Divergence::ReorderedTxs);
+        } else if decided.gas_used != shadow.gas_used {
+            divergences.push(Divergence::GasUsed {
+                decided: decided.gas_used,
+                shadow: shadow.gas_used,
+            });
+        }
+    }
+    divergences
+}
+
+/// [`PayloadValidator`] of the values synced in shadow-fork mode, which cannot be validated
+/// by the shadow execution client and are only decoded
+pub struct DecodingValidator;
+
+#[async_trait]
+impl PayloadValidator for DecodingValidator {
+    async fn validate_payload(
+        &mut self,
+        data: &Bytes,
+        _height: Height,
+        _round: Round,
+    ) -> Result<Validity, BoxError> {
+        Ok(match ExecutionPayloadV3::from_ssz_bytes(data) {
+            Ok(_) => Validity::Valid,
+            Err(_) => Validity::Invalid,
+        })
+    }
+}
+
+/// Initializes the state from the store and the head of the shadow execution client, in
+/// place of the bootstrap of the node, which would apply the stored decided blocks to it.
+pub async fn initialize_state(state: &mut State, engine: &Engine) -> eyre::Result<()> {
+    let head = engine
+        .eth
+        .get_block_by_number("latest")
+        .await?
+        .ok_or_eyre("Shadow execution client has no head block")?;
+
+    match state.store.max_decided_value_height() {
+        Some(height) => {
+            // Each decided block is re-executed as the block of the same number
+            if head.block_number != height.as_u64() {
+                return Err(eyre!(
+                    "Shadow execution client is at block {}, the store at height {height}: reset both to restart the shadow fork",
+                    head.block_number
+                ));
+            }
+            state.latest_block = Some(
+                state
+                    .get_latest_block_candidate(height)
+                    .await
+                    .ok_or_eyre("Latest decided block not found in the store")?,
+            );
+        }
+        None => {
+            if head.block_number != 0 {
+                return Err(eyre!(
+                    "Shadow execution client is at block {} with an empty store: reset it to its genesis to start the shadow fork",
+                    head.block_number
+                ));
+            }
+            // Stands for the genesis of the network, which has another hash
+            state.latest_block = Some(head);
+        }
+    }
+    state.consensus_height = Height::new(head.block_number).increment();
+
+    let validator_set = match &state.power_change_limit {
+        Some(limit) => limited_validator_set(state, engine, state.consensus_height, limit).await?,
+        None => {
+            read_validators_from_contract(
+                engine.eth.url().as_ref(),
+                &state.validator_manager_address,
+                &head.block_hash,
+            )
+            .await?
+        }
+    };
+    state.set_validator_set(state.consensus_height, validator_set);
+
+    info!(
+        head = head.block_number,
+        head_hash = %head.block_hash,
+        "Following the network on a shadow fork"
+    );
+    state.shadow_fork = Some(ShadowFork { head: Some(head) });
+
+    Ok(())
+}
+
+/// Re-executes the decided block at `height` on the shadow execution client, reports its
+/// divergences from the decided one, and returns the hash of the new head of the shadow
+/// execution client.
+pub async fn reexecute(
+    state: &mut State,
+    engine: &Engine,
+    height: Height,
+    decided: &ExecutionPayloadV3,
+    retry_config: &RetryConfig,
+) -> eyre::Result<BlockHash> {
+    let parent = state
+        .shadow_fork
+        .as_ref()
+        .and_then(ShadowFork::head)
+        .ok_or_eyre("Shadow fork not initialized")?;
+    let decided_block = &decided.payload_inner.payload_inner;
+
+    // A transaction rejected by the pool, e.g. under the modified rules, is reported as
+    // missing from the re-executed block
+    for tx in &decided_block.transactions {
+        if let Err(e) = engine.eth.send_raw_transaction(tx).await {
+            debug!(%height, "Shadow execution client rejected a transaction: {e}");
+        }
+    }
+
+    // The shadow chain is never reorganized
+    let forkchoice_state = ForkchoiceState {
+        head_block_hash: parent.block_hash,
+        safe_block_hash: parent.block_hash,
+        finalized_block_hash: parent.block_hash,
+    };
+    let shadow = engine
+        .build_block(
+            &parent,
+            forkchoice_state,
+            retry_config,
+            &Address::from(decided_block.fee_recipient),
+            decided_block.timestamp,
+            state.get_fork(decided_block.timestamp),
+        )
+        .await?;
+
+    let block: Block = shadow
+        .clone()
+        .try_into_block()
+        .map_err(|e| eyre!("Invalid block built by the shadow execution client: {e}"))?;
+    let versioned_hashes: Vec<BlockHash> =
+        block.body.blob_versioned_hashes_iter().copied().collect();
+    let payload_status = engine
+        .notify_new_block_with_retry(shadow.clone(), versioned_hashes, retry_config)
+        .await?;
+    if !payload_status.status.is_valid() {
+        return Err(eyre!(
+            "Shadow execution client rejected the block it built at height {height}: {:?}",
+            payload_status.status
+        ));
+    }
+
+    let shadow_block = &shadow.payload_inner.payload_inner;
+    let latest_valid_hash = engine
+        .set_latest_forkchoice_state(
+            ForkchoiceState {
+                head_block_hash: shadow_block.block_hash,
+                safe_block_hash: shadow_block.block_hash,
+                finalized_block_hash: shadow_block.block_hash,
+            },
+            retry_config,
+        )
+        .await?;
+
+    for divergence in compare(decided, &shadow) {
+        warn!(
+            %height,
+            block_hash = %decided_block.block_hash,
+            shadow_block_hash = %shadow_block.block_hash,
+            %divergence,
+            "Shadow fork diverged from the decided block"
+        );
+        state
+            .metrics
+            .validation
+            .inc_shadow_fork_divergences(divergence.as_str());
+        state.event_log.record(Event::ShadowForkDiverged {
+            height: height.as_u64(),
+            block_hash: decided_block.block_hash.to_string(),
+            shadow_block_hash: shadow_block.block_hash.to_string(),
+            divergence: divergence.to_string(),
+        });
+    }
+
+    debug!(
+        %height,
+        shadow_block_hash = %shadow_block.block_hash,
+        txs = shadow_block.transactions.len(),
+        "Re-executed the decided block on the shadow fork"
+    );
+    state.shadow_fork = Some(ShadowFork {
+        head: Some(ExecutionBlock {
+            block_hash: shadow_block.block_hash,
+            block_number: shadow_block.block_number,
+            parent_hash: shadow_block.parent_hash,
+            timestamp: shadow_block.timestamp,
+            prev_randao: shadow_block.prev_randao,
+        }),
+    });
+
+    Ok(latest_valid_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use malachitebft_eth_types::B256;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::state::testing::{engine, spawn_execution_client, MockAnswer, TestNode};
+
+    /// Shadow execution client building `built` on top of any head, answering the new
+    /// payloads with `status`, and recording the transactions submitted to its pool
+    struct ShadowClient {
+        built: ExecutionPayloadV3,
+        status: &'static str,
+        submitted: Mutex<Vec<String>>,
+    }
+
+    fn handle(client: &ShadowClient, request: &Value) -> MockAnswer {
+        let params = &request["params"];
+        let result = match request["method"].as_str().unwrap() {
+            "eth_sendRawTransaction" => {
+                let tx = params[0].as_str().unwrap().to_string();
+                client.submitted.lock().unwrap().push(tx);
+                json!(B256::ZERO)
+            }
+            "engine_forkchoiceUpdatedV3" => {
+                let head = &params[0]["headBlockHash"];
+                let payload_id = if params[1].is_null() {
+                    Value::Null
+                } else {
+                    json!("0x0000000000000001")
+                };
+                json!({
+                    "payloadStatus": {
+                        "status": "VALID",
+                        "latestValidHash": head,
+                        "validationError": null,
+                    },
+                    "payloadId": payload_id,
+                })
+            }
+            "engine_getPayloadV4" => json!({
+                "executionPayload": client.built,
+                "blockValue": "0x0",
+                "blobsBundle": { "commitments": [], "proofs": [], "blobs": [] },
+                "shouldOverrideBuilder": false,
+                "executionRequests": [],
+            }),
+            "engine_newPayloadV4" => json!({
+                "status": client.status,
+                "latestValidHash": null,
+                "validationError": null,
+            }),
+            method => panic!("Unexpected call to {method}"),
+        };
+        Ok(json!({ "result": result }))
+    }
+
+    /// Node in shadow-fork mode at height 1, whose shadow execution client is at the
+    /// block `0xaa..`
+    async fn shadow_node(client: Arc<ShadowClient>) -> (TestNode, Engine, tempfile::TempDir) {
+        let url = spawn_execution_client(move |request| handle(&client, request)).await;
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine(url, dir.path());
+
+        let mut node = TestNode::new(1, Height::new(1), |_| {}).await;
+        node.state.eth_chain_config.prague_time = Some(0);
+        node.state.shadow_fork = Some(ShadowFork {
+            head: Some(ExecutionBlock {
+                block_hash: B256::repeat_byte(0xaa),
+                block_number: 0,
+                parent_hash: B256::ZERO,
+                timestamp: 0,
+                prev_randao: B256::ZERO,
+            }),
+        });
+        (node, engine, dir)
+    }
+
+    fn built(transactions: &[&'static [u8]], gas_used: u64) -> ExecutionPayloadV3 {
+        let mut built = payload(transactions, gas_used);
+        let block = &mut built.payload_inner.payload_inner;
+        block.block_hash = B256::repeat_byte(0xbb);
+        block.parent_hash = B256::repeat_byte(0xaa);
+        block.block_number = 1;
+        built
+    }
+
+    fn payload(transactions: &[&'static [u8]], gas_used: u64) -> ExecutionPayloadV3 {
+        let mut payload = ExecutionPayloadV3::default();
+        let block = &mut payload.payload_inner.payload_inner;
+        block.transactions = transactions
+            .iter()
+            .map(|tx| malachitebft_eth_types::Bytes::from_static(tx))
+            .collect();
+        block.gas_used = gas_used;
+        payload
+    }
+
+    #[test]
+    fn test_compare() {
+        let decided = payload(&[b"a", b"b"], 42_000);
+        assert!(compare(&decided, &payload(&[b"a", b"b"], 42_000)).is_empty());
+
+        assert_eq!(
+            compare(&decided, &payload(&[b"a", b"b"], 50_000)),
+            vec![Divergence::GasUsed {
+                decided: 42_000,
+                shadow: 50_000,
+            }]
+        );
+        assert_eq!(
+            compare(&decided, &payload(&[b"b", b"a"], 42_000)),
+            vec![Divergence::ReorderedTxs]
+        );
+        assert_eq!(
+            compare(&decided, &payload(&[b"a", b"c", b"d"], 63_000)),
+            vec![Divergence::MissingTxs(1), Divergence::ExtraTxs(2)]
+        );
+    }
+
+    #[test]
+    fn test_compare_counts_each_side() {
+        let decided = payload(&[b"a", b"b", b"c"], 63_000);
+        assert_eq!(
+            compare(&decided, &payload(&[b"a"], 21_000)),
+            vec![Divergence::MissingTxs(2)]
+        );
+        assert_eq!(
+            compare(&payload(&[], 0), &payload(&[b"a"], 21_000)),
+            vec![Divergence::ExtraTxs(1)]
+        );
+        assert!(compare(&payload(&[], 0), &payload(&[], 0)).is_empty());
+    }
+
+    #[test]
+    fn test_divergence_labels() {
+        let gas_used = Divergence::GasUsed {
+            decided: 21_000,
+            shadow: 23_000,
+        };
+        assert_eq!(gas_used.as_str(), "gas_used");
+        assert_eq!(gas_used.to_string(), "23000 gas used instead of 21000");
+        assert_eq!(Divergence::MissingTxs(2).as_str(), "missing_txs");
+        assert_eq!(
+            Divergence::MissingTxs(2).to_string(),
+            "2 transactions missing"
+        );
+        assert_eq!(Divergence::ExtraTxs(1).to_string(), "1 extra transactions");
+        assert_eq!(
+            Divergence::ReorderedTxs.to_string(),
+            "transactions reordered"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reexecute_requires_initialized_shadow_fork() {
+        let mut node = TestNode::new(1, Height::new(1), |_| {}).await;
+        let decided = payload(&[b"a"], 21_000);
+
+        let e = reexecute(
+            &mut node.state,
+            &node.engine,
+            Height::new(1),
+            &decided,
+            &RetryConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(e.to_string().contains("not initialized"), "{e}");
+        assert!(node.state.shadow_fork.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reexecute_moves_the_head_of_the_shadow_fork() {
+        let client = Arc::new(ShadowClient {
+            built: built(&[b"a", b"c"], 42_000),
+            status: "VALID",
+            submitted: Mutex::default(),
+        });
+        let (mut node, engine, _dir) = shadow_node(client.clone()).await;
+        let decided = payload(&[b"a", b"b"], 42_000);
+
+        let head = reexecute(
+            &mut node.state,
+            &engine,
+            Height::new(1),
+            &decided,
+            &RetryConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // Each transaction of the decided block is submitted, the divergent block is kept
+        assert_eq!(*client.submitted.lock().unwrap(), ["0x61", "0x62"]);
+        assert_eq!(head, B256::repeat_byte(0xbb));
+        let shadow_head = node.state.shadow_fork.as_ref().unwrap().head().unwrap();
+        assert_eq!(shadow_head.block_hash, B256::repeat_byte(0xbb));
+        assert_eq!(shadow_head.parent_hash, B256::repeat_byte(0xaa));
+        assert_eq!(shadow_head.block_number, 1);
+        assert_eq!(
+            compare(&decided, &client.built),
+            vec![Divergence::MissingTxs(1), Divergence::ExtraTxs(1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reexecute_fails_on_rejected_block() {
+        let client = Arc::new(ShadowClient {
+            built: built(&[b"a"], 21_000),
+            status: "INVALID",
+            submitted: Mutex::default(),
+        });
+        let (mut node, engine, _dir) = shadow_node(client).await;
+
+        let e = reexecute(
+            &mut node.state,
+            &engine,
+            Height::new(1),
+            &payload(&[b"a"], 21_000),
+            &RetryConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(e.to_string().contains("rejected the block"), "{e}");
+
+        // The head is left on the last block accepted by the shadow execution client
+        let shadow_head = node.state.shadow_fork.as_ref().unwrap().head().unwrap();
+        assert_eq!(shadow_head.block_hash, B256::repeat_byte(0xaa));
+    }
+}
@@ -2,6 +2,7 @@
 //! A regular application would have mempool implemented, a proper database and input methods like RPC.
 
 use core::str::FromStr;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::{fmt, fs};
 
@@ -9,20 +10,26 @@ use alloy_genesis::{ChainConfig, Genesis as EvmGenesis};
 use alloy_rpc_types_engine::ExecutionPayloadV3;
 use bytes::Bytes;
 use color_eyre::eyre;
+use emerald_retry::SharedClock;
 use malachitebft_app_channel::app::streaming::{StreamContent, StreamId, StreamMessage};
 use malachitebft_app_channel::app::types::codec::Codec;
 use malachitebft_app_channel::app::types::core::{CommitCertificate, Context, Round, Validity};
 use malachitebft_app_channel::app::types::{LocallyProposedValue, PeerId, ProposedValue};
-use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_cli::config::{ElNodeType, EmeraldConfig, StoreLimitsConfig};
 use malachitebft_eth_engine::engine::Engine;
 use malachitebft_eth_engine::engine_rpc::Fork;
 use malachitebft_eth_engine::json_structures::ExecutionBlock;
+use malachitebft_eth_engine::payload_builder::ExternalBuilder;
+use malachitebft_eth_types::codec::proto::version::{SharedPeerVersions, PARTS_RECOVERY_VERSION};
 use malachitebft_eth_types::codec::proto::ProtobufCodec;
 use malachitebft_eth_types::secp256k1::K256Provider;
 use malachitebft_eth_types::{
-    Address, BlockTimestamp, EmeraldContext, Genesis, Height, ProposalData, ProposalFin,
-    ProposalInit, ProposalPart, RetryConfig, ValidatorSet, Value, ValueId,
+    Address, BaseFeeFloor, Block, BlockHash, BlockTimestamp, EmeraldContext, FeeRecipientPolicy,
+    Genesis, Height, PartsRequest, PayloadSummary, PowerChangeLimit, ProposalData, ProposalFin,
+    ProposalInit, ProposalPart, RecoveredPart, RetryConfig, ValidatorSet, Value, ValueId, B256,
 };
+use malachitebft_proto::Error as ProtoError;
+use malachitebft_signing::Error as SigningError;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use sha3::Digest;
@@ -30,10 +37,41 @@ use ssz::{Decode, Encode};
 use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
 
+use crate::base_fee;
+use crate::block_profile::BlockProfiler;
+use crate::build_info::SharedBuildInfo;
+use crate::catch_up::CatchUpThrottle;
+use crate::consensus_params::{read_consensus_params_from_contract, ChainParams};
+use crate::el_announce::ElAnnouncer;
+use crate::el_divergence::{self, ElDivergence};
+use crate::el_health::SharedElHealth;
+use crate::event_log::EventLog;
+use crate::failover::FencedProvider;
+use crate::forkchoice::{FinalizedBlock, Forkchoice};
+use crate::handlers::IdleProposal;
 use crate::metrics::Metrics;
-use crate::payload::{extract_block_header, validate_execution_payload, ValidatedPayloadCache};
-use crate::store::Store;
-use crate::streaming::{PartStreamsMap, ProposalParts};
+use crate::metrics_aggregator::{CumulativeMetrics, MetricsAggregator};
+use crate::node_status::SharedNodeStatus;
+use crate::payload::{
+    extract_block_header, pending_payload_timestamp, validate_execution_payload, BuiltPayloadCache,
+    FeeRecipientCheck, PendingPayload, ValidatedPayloadCache,
+};
+use crate::peer_filter::SharedPeerFilter;
+use crate::peer_registry::SharedPeerRegistry;
+use crate::shadow_fork::ShadowFork;
+use crate::store::{DecidedHeights, Store, StoreError};
+use crate::streaming::{ChunkSizer, PartStreamsMap, ProposalParts, StreamError};
+use crate::sync_handler::{RebuiltValueCache, SharedRebuiltValueCache};
+use crate::sync_limiter::{RebuildSlots, SyncLimiter};
+use crate::sync_stats::SharedSyncStats;
+use crate::tx_filter::TxFilter;
+use crate::validators::{
+    read_validators_from_contract, ValidatorSetHistory, VALIDATOR_SET_CACHE_SIZE,
+};
+use crate::webhooks::Webhooks;
+
+#[cfg(test)]
+pub(crate) mod testing;
 
 pub struct StateMetrics {
     pub txs_count: u64,
@@ -46,19 +84,18 @@ pub struct StateMetrics {
 #[allow(dead_code)]
 const BLOCK_SIZE: usize = 10 * 1024 * 1024; // 10 MiB
 
-/// Size of chunks in which the data is split for streaming
-const CHUNK_SIZE: usize = 128 * 1024; // 128 KiB
-
 /// Represents the internal state of the application node
 /// Contains information about current height, round, proposals and blocks
 pub struct State {
     #[allow(dead_code)]
     ctx: EmeraldContext,
-    pub signing_provider: K256Provider,
+    pub signing_provider: FencedProvider,
     address: Address,
     pub store: Store,
     stream_nonce: u32,
     streams_map: PartStreamsMap,
+    /// Size of the chunks the payloads of the proposals of this node are split into
+    chunk_sizer: ChunkSizer,
     #[allow(dead_code)]
     rng: StdRng,
 
@@ -87,11 +124,90 @@ pub struct State {
 
     pub latest_block: Option<ExecutionBlock>,
 
-    validator_set: Option<(Height, ValidatorSet)>,
+    /// Safe and finalized blocks reported to the execution client
+    pub forkchoice: Forkchoice,
+
+    /// Validator sets of the recent heights
+    validator_sets: ValidatorSetHistory,
 
     // Cache for tracking recently validated payloads to avoid duplicate validation
     validated_payload_cache: ValidatedPayloadCache,
 
+    /// Round and value of the undecided values of the current height, by block hash.
+    /// Used to handle proposals of known payloads from their init part.
+    known_payloads: BTreeMap<(Height, BlockHash), (Round, ValueId)>,
+
+    /// Last payload built for a proposal, reused when proposing again on the same parent
+    pub built_payload_cache: BuiltPayloadCache,
+
+    /// Payload being built since the previous block was decided, when optimistic payload
+    /// building is enabled and this node proposes at the next height
+    pub pending_payload: Option<PendingPayload>,
+
+    /// Compliance filters applied to the transactions of the payloads this node proposes
+    pub tx_filter: Option<TxFilter>,
+
+    /// External builder asked for the payloads this node proposes, if any
+    pub external_builder: Option<ExternalBuilder>,
+
+    /// Build of the node and version of its execution client, served by the admin API
+    pub build_info: SharedBuildInfo,
+
+    /// Allow and deny lists of the peers, shared with the admin API
+    pub peer_filter: SharedPeerFilter,
+
+    /// Peers which streamed proposals to this node, shared with the admin API
+    pub peer_registry: SharedPeerRegistry,
+
+    /// Versions of the wire format advertised by the peers, shared with the codec
+    pub peer_versions: SharedPeerVersions,
+
+    /// Height, round and head of the node, shared with the admin API
+    pub node_status: SharedNodeStatus,
+
+    /// Highest height of the values synced from the peers, whose commit certificates are
+    /// verified before they are committed
+    pub synced_height: Option<Height>,
+
+    /// Rate limit of the decided values served to syncing peers, if any
+    pub sync_limiter: Option<SyncLimiter>,
+
+    /// Throttle of the values synced from the peers, see [`CatchUpThrottle`]
+    pub catch_up_throttle: CatchUpThrottle,
+
+    /// Decided values rebuilt from the execution client for syncing peers
+    pub rebuilt_values: SharedRebuiltValueCache,
+
+    /// Slots of the decided values being rebuilt for syncing peers in the background
+    pub rebuild_slots: RebuildSlots,
+
+    /// Decided values served to syncing peers, shared with the admin API
+    pub sync_stats: SharedSyncStats,
+
+    /// Health of the execution client, polled in the background
+    pub el_health: SharedElHealth,
+
+    /// Base fee floor set in the genesis
+    pub base_fee_floor: Option<BaseFeeFloor>,
+
+    /// Minimum base fee per gas of proposals, after the on-chain override if any
+    pub min_base_fee_per_gas: Option<u64>,
+
+    /// Fee recipients accepted in proposals, set in the genesis
+    pub fee_recipient_policy: Option<FeeRecipientPolicy>,
+
+    /// Maximum size of the encoded payload of proposals, set in the genesis
+    pub max_proposal_bytes: Option<u64>,
+
+    /// Maximum change of the voting power of the validator set per height, set in the genesis
+    pub power_change_limit: Option<PowerChangeLimit>,
+
+    /// Address of the validator manager contract, set in the genesis
+    pub validator_manager_address: Address,
+
+    /// Consensus parameters read from the `ConsensusParams` contract
+    pub chain_params: ChainParams,
+
     /// Time it took to execute last block.
     /// Used to decide on whether we should sleep in case min_block_time
     /// is set.
@@ -100,14 +216,33 @@ pub struct State {
     /// Tracks when the previous block was committed (for per-block TPS calculation)
     pub previous_block_commit_time: Instant,
 
+    /// Request for a value deferred until transactions are available, see
+    /// `max_idle_block_interval`
+    pub idle_proposal: Option<IdleProposal>,
+
     // --------------
 
     // -------------- Stat collection - persisted to DB
     pub txs_count: u64,
     pub chain_bytes: u64,
     pub start_time: Instant,
+    /// Clock of the block times and of the statistics, see [`SharedClock`]
+    pub clock: SharedClock,
     pub metrics: Metrics,
+    /// Writes the cumulative counters to the store, off the path of the decided values
+    pub metrics_aggregator: MetricsAggregator,
     // --------------
+    /// Structured log of consensus events, see [`EventLog`]
+    pub event_log: EventLog,
+    /// Webhooks notified of the decided blocks, see [`Webhooks`]
+    pub webhooks: Webhooks,
+    /// Execution clients the decided blocks are announced to, see [`ElAnnouncer`]
+    pub el_announcer: ElAnnouncer,
+    /// Chain of the shadow execution client, in shadow-fork mode, see [`ShadowFork`]
+    pub shadow_fork: Option<ShadowFork>,
+
+    /// Timings of the stages of the production of each block
+    pub block_profile: BlockProfiler,
 }
 
 /// Represents errors that can occur during the verification of a proposal's signature.
@@ -136,6 +271,21 @@ pub enum ProposalValidationError {
     ValidatorSetNotFound { height: Height },
 }
 
+impl ProposalValidationError {
+    /// Reason of the rejection, as listed in the conformance vectors of the proposal validation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::WrongProposer { .. } => "wrong_proposer",
+            Self::Signature(SignatureVerificationError::MissingInitPart) => "missing_init_part",
+            Self::Signature(SignatureVerificationError::MissingFinPart) => "missing_fin_part",
+            Self::Signature(SignatureVerificationError::ProposerNotFound) => "proposer_not_found",
+            Self::Signature(SignatureVerificationError::InvalidSignature) => "invalid_signature",
+            Self::Signature(SignatureVerificationError::ValidatorSetNotFound { .. })
+            | Self::ValidatorSetNotFound { .. } => "validator_set_not_found",
+        }
+    }
+}
+
 impl fmt::Display for ProposalValidationError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -180,20 +330,37 @@ impl State {
     /// Creates a new State instance with the given validator address and starting height
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        _genesis: Genesis, // all genesis data is in EVM via genesis.json
+        genesis: Genesis, // apart from the base fee floor, all genesis data is in EVM via genesis.json
         ctx: EmeraldContext,
-        signing_provider: K256Provider,
+        signing_provider: FencedProvider,
         address: Address,
         height: Height,
         store: Store,
         state_metrics: StateMetrics,
         emerald_config: EmeraldConfig,
+        event_log: EventLog,
+        webhooks: Webhooks,
+        el_announcer: ElAnnouncer,
+        shadow_fork: Option<ShadowFork>,
+        tx_filter: Option<TxFilter>,
+        external_builder: Option<ExternalBuilder>,
+        build_info: SharedBuildInfo,
+        peer_filter: SharedPeerFilter,
+        peer_registry: SharedPeerRegistry,
+        peer_versions: SharedPeerVersions,
+        node_status: SharedNodeStatus,
+        sync_stats: SharedSyncStats,
+        el_health: SharedElHealth,
+        catch_up_throttle: CatchUpThrottle,
+        forkchoice: Forkchoice,
+        profile_blocks: Option<u64>,
+        clock: SharedClock,
     ) -> Self {
         // Calculate start_time by subtracting elapsed_seconds from now.
         // It represents the start time of measuring metrics, not the actual node start time.
         // This allows us to continue accumulating time correctly after a restart
         let start_time =
-            Instant::now() - core::time::Duration::from_secs(state_metrics.elapsed_seconds);
+            clock.now() - core::time::Duration::from_secs(state_metrics.elapsed_seconds);
 
         let eth_genesis_path = PathBuf::from_str(&emerald_config.ethereum_config.eth_genesis_path)
             .unwrap_or_else(|_| panic!("failed to read evm genesis file path from config"));
@@ -203,6 +370,15 @@ impl State {
         let eth_genesis: EvmGenesis = serde_json::from_str(eth_genesis_path_str)
             .unwrap_or_else(|_| panic!("failed to read evm genesis file"));
 
+        let metrics_aggregator = MetricsAggregator::spawn(
+            store.clone(),
+            CumulativeMetrics {
+                txs_count: state_metrics.txs_count,
+                chain_bytes: state_metrics.chain_bytes,
+                elapsed_seconds: state_metrics.elapsed_seconds,
+            },
+        );
+
         Self {
             ctx,
             signing_provider,
@@ -211,22 +387,63 @@ impl State {
             address,
             store,
             stream_nonce: 0,
-            streams_map: PartStreamsMap::new(),
+            streams_map: PartStreamsMap::new(genesis.max_proposal_bytes),
+            chunk_sizer: ChunkSizer::new(emerald_config.proposal_chunking.clone()),
             rng: StdRng::seed_from_u64(seed_from_address(&address)),
 
             latest_block: None,
-            validator_set: None,
+            forkchoice,
+            validator_sets: ValidatorSetHistory::new(VALIDATOR_SET_CACHE_SIZE),
 
             validated_payload_cache: ValidatedPayloadCache::new(10),
+            known_payloads: BTreeMap::new(),
+            built_payload_cache: BuiltPayloadCache::new(emerald_config.payload_reuse_window),
+            pending_payload: None,
+            tx_filter,
+            external_builder,
+            build_info,
+            peer_filter,
+            peer_registry,
+            peer_versions,
+            node_status,
+            synced_height: None,
+            sync_limiter: emerald_config
+                .sync_rate_limit
+                .as_ref()
+                .map(|config| SyncLimiter::new(config, std::time::Instant::now())),
+            rebuilt_values: SharedRebuiltValueCache::new(RebuiltValueCache::new(
+                emerald_config.sync_value_cache_bytes,
+                state_metrics.metrics.sync.clone(),
+            )),
+            rebuild_slots: RebuildSlots::new(emerald_config.sync_max_concurrent_rebuilds),
+            sync_stats,
+            el_health,
+            catch_up_throttle,
+            base_fee_floor: genesis.base_fee_floor,
+            min_base_fee_per_gas: genesis
+                .base_fee_floor
+                .map(|floor| floor.min_base_fee_per_gas),
+            fee_recipient_policy: genesis.fee_recipient_policy,
+            max_proposal_bytes: genesis.max_proposal_bytes,
+            power_change_limit: genesis.power_change_limit,
+            validator_manager_address: genesis.validator_manager_address(),
 
             txs_count: state_metrics.txs_count,
             chain_bytes: state_metrics.chain_bytes,
             start_time,
             metrics: state_metrics.metrics,
-            last_block_time: Instant::now(),
-            previous_block_commit_time: Instant::now(),
+            metrics_aggregator,
+            last_block_time: clock.now(),
+            previous_block_commit_time: clock.now(),
+            idle_proposal: None,
             eth_chain_config: eth_genesis.config,
             emerald_config,
+            event_log,
+            webhooks,
+            el_announcer,
+            shadow_fork,
+            block_profile: BlockProfiler::new(profile_blocks),
+            clock,
         }
     }
 
@@ -248,6 +465,233 @@ impl State {
         Fork::Unsupported
     }
 
+    /// Minimum time between two blocks, set on-chain or else by the local configuration
+    pub fn min_block_time(&self) -> core::time::Duration {
+        self.chain_params
+            .min_block_time
+            .unwrap_or(self.emerald_config.min_block_time)
+    }
+
+    /// Maximum size of the encoded execution payload, the lowest of the size set in the
+    /// genesis and of the size set on-chain
+    pub fn max_payload_bytes(&self) -> Option<u64> {
+        match (self.max_proposal_bytes, self.chain_params.max_payload_bytes) {
+            (Some(genesis), Some(on_chain)) => Some(genesis.min(on_chain)),
+            (genesis, on_chain) => genesis.or(on_chain),
+        }
+    }
+
+    /// Returns whether the encoded execution payload exceeds the maximum size.
+    pub fn exceeds_max_payload_bytes(&self, data: &[u8]) -> bool {
+        self.max_payload_bytes()
+            .is_some_and(|max_payload_bytes| data.len() as u64 > max_payload_bytes)
+    }
+
+    /// Reads the consensus parameters again once `height` is decided, if it is an
+    /// epoch boundary. The parameters take effect from the next height. The current
+    /// parameters are kept if they cannot be read.
+    pub async fn refresh_chain_params(
+        &mut self,
+        engine: &Engine,
+        height: Height,
+        block_hash: &BlockHash,
+    ) {
+        if !self.chain_params.is_epoch_boundary(height) {
+            return;
+        }
+
+        let chain_params = match read_consensus_params_from_contract(engine, block_hash).await {
+            Ok(chain_params) => chain_params,
+            Err(e) => {
+                warn!(%height, chain_params = ?self.chain_params, "Failed to read the consensus parameters, keeping the current ones: {e}");
+                return;
+            }
+        };
+
+        if chain_params != self.chain_params {
+            info!(%height, ?chain_params, "Consensus parameters updated");
+        }
+        self.chain_params = chain_params;
+
+        // Recorded so that a restart reads them at the same boundary
+        if let Err(e) = self.store.store_chain_params_epoch(height).await {
+            warn!(%height, "Failed to record the epoch of the consensus parameters: {e}");
+        }
+    }
+
+    /// Loads the consensus parameters in force after the given block height on start-up,
+    /// i.e. those read at the last epoch boundary, as recorded in the store. Stores which
+    /// did not record it locate the boundary with the epoch length in force at the given
+    /// block.
+    pub async fn load_chain_params(
+        &mut self,
+        engine: &Engine,
+        height: Height,
+        block_hash: &BlockHash,
+    ) -> eyre::Result<()> {
+        let epoch_start = match self.store.get_chain_params_epoch().await? {
+            Some(epoch_start) if epoch_start <= height => epoch_start,
+            _ => read_consensus_params_from_contract(engine, block_hash)
+                .await?
+                .epoch_start(height),
+        };
+
+        let chain_params = if epoch_start == height {
+            read_consensus_params_from_contract(engine, block_hash).await?
+        } else {
+            let block = engine
+                .eth
+                .get_block_by_number(&format!("0x{:x}", epoch_start.as_u64()))
+                .await?
+                .ok_or_else(|| eyre::eyre!("Execution client does not know block {epoch_start}"))?;
+            read_consensus_params_from_contract(engine, &block.block_hash).await?
+        };
+
+        info!(%epoch_start, ?chain_params, "Loaded consensus parameters");
+        self.chain_params = chain_params;
+
+        Ok(())
+    }
+
+    /// Advances the finalized block once `head` is decided with the given block.
+    /// The finalized block is persisted before it is reported to the execution client.
+    pub async fn advance_finalized_block(
+        &mut self,
+        engine: &Engine,
+        head: Height,
+        head_block_hash: BlockHash,
+    ) -> eyre::Result<()> {
+        let Some(height) = self.forkchoice.next_finalized_height(head) else {
+            return Ok(());
+        };
+
+        let block_hash = if height == head {
+            head_block_hash
+        } else {
+            self.decided_block_hash(engine, height).await?
+        };
+
+        let finalized = FinalizedBlock { height, block_hash };
+        self.store.store_finalized_block(finalized).await?;
+        self.forkchoice.set_finalized(finalized);
+
+        Ok(())
+    }
+
+    pub(crate) async fn decided_block_hash(
+        &self,
+        engine: &Engine,
+        height: Height,
+    ) -> eyre::Result<BlockHash> {
+        if let Some((_, header)) = self.store.get_certificate_and_header(height).await? {
+            let header = ExecutionPayloadV3::from_ssz_bytes(&header)
+                .map_err(|e| eyre::eyre!("Invalid block header at height {height}: {e:?}"))?;
+            return Ok(header.payload_inner.payload_inner.block_hash);
+        }
+
+        // The header has been pruned, but the decided blocks are the canonical blocks
+        // of the execution client
+        let block = engine
+            .eth
+            .get_block_by_number(&format!("0x{:x}", height.as_u64()))
+            .await?
+            .ok_or_else(|| eyre::eyre!("Execution client does not know block {height}"))?;
+
+        Ok(block.block_hash)
+    }
+
+    /// Reads the minimum base fee per gas in force after the given block, which may
+    /// be overridden on-chain. The current floor is kept if it cannot be read.
+    pub async fn refresh_base_fee_floor(&mut self, engine: &Engine, block_hash: &BlockHash) {
+        let Some(floor) = self.base_fee_floor else {
+            return;
+        };
+
+        match base_fee::read_min_base_fee_per_gas(engine, &floor, block_hash).await {
+            Ok(min_base_fee_per_gas) => self.min_base_fee_per_gas = Some(min_base_fee_per_gas),
+            Err(e) => warn!(
+                %block_hash,
+                min_base_fee_per_gas = ?self.min_base_fee_per_gas,
+                "Failed to read the base fee floor, keeping the current one: {e}"
+            ),
+        }
+    }
+
+    /// Asks the execution client to start building the payload to propose at `height` on top
+    /// of `parent`, if this node is the proposer of its first round, so that it is built
+    /// during `min_block_time` and only fetched when consensus asks for a value.
+    pub async fn start_pending_payload(
+        &mut self,
+        engine: &Engine,
+        height: Height,
+        parent: &ExecutionBlock,
+    ) {
+        self.pending_payload = None;
+
+        // The external builder is asked for the payloads when consensus asks for a value
+        if self.external_builder.is_some() || !self.is_proposer(height, Round::ZERO) {
+            return;
+        }
+
+        let Some(timestamp) = pending_payload_timestamp(parent.timestamp, self.min_block_time())
+        else {
+            debug!(%height, "Not building the next payload ahead of time, it would not be after its parent");
+            return;
+        };
+
+        let fee_recipient = self.emerald_config.fee_recipient;
+        let result = engine
+            .start_payload(
+                parent,
+                self.forkchoice
+                    .state(Height::new(parent.block_number), parent.block_hash),
+                &self.emerald_config.retry_config,
+                &fee_recipient,
+                timestamp,
+            )
+            .await;
+
+        match result {
+            Ok(payload_id) => {
+                debug!(%height, %payload_id, "Started building the payload to propose");
+                self.pending_payload = Some(PendingPayload {
+                    parent_hash: parent.block_hash,
+                    fee_recipient,
+                    payload_id,
+                });
+            }
+            Err(e) => warn!(%height, "Failed to start building the payload to propose: {e}"),
+        }
+    }
+
+    /// Returns whether this node is the proposer of the given height and round.
+    /// Returns false if the validator set of that height is not in memory.
+    pub fn is_proposer(&self, height: Height, round: Round) -> bool {
+        self.get_validator_set(height).is_some_and(|validator_set| {
+            self.ctx
+                .select_proposer(validator_set, height, round)
+                .address
+                == self.address
+        })
+    }
+
+    /// Returns the hashes of the transactions of the payload which do not pay the base fee
+    /// floor, see [`base_fee::txs_below_floor`]. Fails if the payload cannot be decoded.
+    pub fn txs_below_base_fee_floor(
+        &self,
+        execution_payload: &ExecutionPayloadV3,
+    ) -> eyre::Result<Vec<B256>> {
+        let Some(min_base_fee_per_gas) = self.min_base_fee_per_gas else {
+            return Ok(Vec::new());
+        };
+
+        let block: Block = execution_payload
+            .clone()
+            .try_into_block()
+            .map_err(|e| eyre::eyre!("Failed to decode the transactions of the payload: {e}"))?;
+        Ok(base_fee::txs_below_floor(&block, min_base_fee_per_gas))
+    }
+
     pub fn validated_cache_mut(&mut self) -> &mut ValidatedPayloadCache {
         &mut self.validated_payload_cache
     }
@@ -270,19 +714,89 @@ impl State {
     }
 
     /// Returns the earliest height available via EL
-    pub async fn get_earliest_height(&self) -> Height {
-        self.store
-            .min_decided_value_height()
-            .await
-            .unwrap_or_default()
+    pub fn get_earliest_height(&self) -> Height {
+        self.store.decided_heights().earliest.unwrap_or_default()
     }
 
-    /// Returns the earliest height available in the state
-    pub async fn get_earliest_unpruned_height(&self) -> Height {
-        self.store
-            .min_unpruned_decided_value_height()
+    /// Returns the range of decided heights in the store
+    pub fn decided_heights(&self) -> DecidedHeights {
+        self.store.decided_heights()
+    }
+
+    /// Returns the lowest height whose block is retained by the execution client,
+    /// or `None` if it is an archive node
+    pub fn el_retained_from(&self) -> Option<Height> {
+        if self.emerald_config.el_node_type == ElNodeType::Archive {
+            return None;
+        }
+
+        let latest = self.decided_heights().latest.unwrap_or_default();
+        Some(Height::new(
+            latest
+                .as_u64()
+                .saturating_sub(self.emerald_config.el_retained_blocks),
+        ))
+    }
+
+    /// Returns the lowest and highest heights served to syncing peers, if any.
+    ///
+    /// The values pruned from the store are rebuilt from the blocks of the execution
+    /// client, so a pruned execution client only serves its retained blocks.
+    pub fn served_heights(&self) -> Option<(Height, Height)> {
+        let heights = self.decided_heights();
+        let (earliest, latest) = heights.earliest.zip(heights.latest)?;
+
+        let earliest = match self.el_retained_from() {
+            Some(retained_from) => {
+                let earliest_unpruned = heights.earliest_unpruned.unwrap_or(latest);
+                earliest.max(retained_from.min(earliest_unpruned))
+            }
+            None => earliest,
+        };
+
+        Some((earliest, latest))
+    }
+
+    /// Reports the heights served to syncing peers in the metrics
+    pub fn report_served_heights(&self) {
+        if let Some((earliest, latest)) = self.served_heights() {
+            self.metrics
+                .sync
+                .set_served_heights(earliest.as_u64(), latest.as_u64());
+        }
+    }
+
+    /// Expected proposer of a round, if the validator set of its height is known
+    pub fn proposer_of(&self, height: Height, round: Round) -> Option<Address> {
+        let validator_set = self.get_validator_set(height)?;
+        Some(
+            self.ctx
+                .select_proposer(validator_set, height, round)
+                .address,
+        )
+    }
+
+    /// Proposer of the value decided at `height` in `round`, or of the round in which it
+    /// was first proposed if it was proposed again with a POL round, as far as the proposals
+    /// of the previous rounds are stored
+    pub async fn original_proposer(
+        &self,
+        height: Height,
+        round: Round,
+        value_id: ValueId,
+    ) -> Option<Address> {
+        let mut proposed = round;
+        while let Ok(Some(proposal)) = self
+            .store
+            .get_undecided_proposal(height, proposed, value_id)
             .await
-            .unwrap_or_default()
+        {
+            if !proposal.valid_round.is_defined() || proposal.valid_round >= proposed {
+                break;
+            }
+            proposed = proposal.valid_round;
+        }
+        self.proposer_of(height, proposed)
     }
 
     /// Validates a proposal by checking both proposer and signature
@@ -290,75 +804,42 @@ impl State {
         &self,
         parts: &ProposalParts,
     ) -> Result<(), ProposalValidationError> {
-        let height = parts.height;
-        let round = parts.round;
-
-        // Get the expected proposer for this height and round
-        let validator_set = self
-            .get_validator_set(height)
-            .ok_or(ProposalValidationError::ValidatorSetNotFound { height })?;
-        let expected_proposer = self
-            .ctx
-            .select_proposer(validator_set, height, round)
-            .address;
-
-        // Check if the proposer matches the expected proposer
-        if parts.proposer != expected_proposer {
-            return Err(ProposalValidationError::WrongProposer {
-                actual: parts.proposer,
-                expected: expected_proposer,
-            });
-        }
-
-        // If proposer is correct, verify the signature
-        self.verify_proposal_parts_signature(parts)
-            .map_err(ProposalValidationError::Signature)?;
+        let validator_set = self.get_validator_set(parts.height).ok_or(
+            ProposalValidationError::ValidatorSetNotFound {
+                height: parts.height,
+            },
+        )?;
 
-        Ok(())
+        validate_proposal_parts(
+            &self.ctx,
+            self.signing_provider.provider(),
+            validator_set,
+            parts,
+        )
     }
 
-    /// Verify proposal signature
-    fn verify_proposal_parts_signature(
+    /// Verify the signature of the payload summary of an init part
+    fn verify_payload_summary_signature(
         &self,
-        parts: &ProposalParts,
+        init: &ProposalInit,
+        payload: &PayloadSummary,
     ) -> Result<(), SignatureVerificationError> {
-        let mut hasher = sha3::Keccak256::new();
-
-        let init = parts
-            .init()
-            .ok_or(SignatureVerificationError::MissingInitPart)?;
+        let hash =
+            sha3::Keccak256::digest(init.payload_sign_bytes(&payload.block_hash, payload.len));
 
-        let fin = parts
-            .fin()
-            .ok_or(SignatureVerificationError::MissingFinPart)?;
-
-        let hash = {
-            hasher.update(init.height.as_u64().to_be_bytes());
-            hasher.update(init.round.as_i64().to_be_bytes());
-
-            // The correctness of the hash computation relies on the parts being ordered by sequence
-            // number, which is guaranteed by the `PartStreamsMap`.
-            for part in parts.parts.iter().filter_map(|part| part.as_data()) {
-                hasher.update(part.bytes.as_ref());
-            }
-
-            hasher.finalize()
-        };
-
-        // Retrieve the proposer from the validator set for the given height
-        let validator_set = self.get_validator_set(parts.height).ok_or(
+        let validator_set = self.get_validator_set(init.height).ok_or(
             SignatureVerificationError::ValidatorSetNotFound {
-                _height: parts.height,
+                _height: init.height,
             },
         )?;
         let proposer = validator_set
-            .get_by_address(&parts.proposer)
+            .get_by_address(&init.proposer)
             .ok_or(SignatureVerificationError::ProposerNotFound)?;
 
-        // Verify the signature
         if !self
             .signing_provider
-            .verify(&hash, &fin.signature, &proposer.public_key)
+            .provider()
+            .verify(&hash, &payload.signature, &proposer.public_key)
         {
             return Err(SignatureVerificationError::InvalidSignature);
         }
@@ -382,6 +863,7 @@ impl State {
                 height = %parts.height,
                 round = %parts.round,
                 proposer = %parts.proposer,
+                reason = error.as_str(),
                 error = ?error,
                 "Rejecting invalid proposal"
             );
@@ -399,29 +881,112 @@ impl State {
             "Proposal data"
         );
 
+        self.validate_proposed_value(value, data, engine, retry_config)
+            .await
+    }
+
+    /// Validates the payload of a proposal whose proposer and signature were verified: its
+    /// size, the base fee floor, and with the execution engine, its linkage to the parent
+    /// block and its fee recipient. Stores the proposal as undecided if it is valid.
+    ///
+    /// Returns the proposal to hand to consensus, if any.
+    async fn validate_proposed_value(
+        &mut self,
+        value: ProposedValue<EmeraldContext>,
+        data: Bytes,
+        engine: &Engine,
+        retry_config: &RetryConfig,
+    ) -> eyre::Result<Option<ProposedValue<EmeraldContext>>> {
+        if self.exceeds_max_payload_bytes(&data) {
+            warn!(
+                height = %value.height,
+                round = %value.round,
+                size = data.len(),
+                max_payload_bytes = ?self.max_payload_bytes(),
+                "Proposal exceeds the maximum payload size, rejecting"
+            );
+            self.metrics.blocks.inc_rejected_proposals(&value.proposer);
+            return Ok(Some(ProposedValue {
+                validity: Validity::Invalid,
+                ..value
+            }));
+        }
+
+        // Check the base fee floor, which the execution engine is not aware of.
+        // Invalid encodings are rejected below, when validating the payload.
+        if let Ok(execution_payload) = ExecutionPayloadV3::from_ssz_bytes(&data) {
+            let below_floor = self
+                .txs_below_base_fee_floor(&execution_payload)
+                .map_or(0, |txs| txs.len());
+            if below_floor > 0 {
+                warn!(
+                    height = %value.height,
+                    round = %value.round,
+                    base_fee_per_gas = %execution_payload.payload_inner.payload_inner.base_fee_per_gas,
+                    min_base_fee_per_gas = ?self.min_base_fee_per_gas,
+                    txs = below_floor,
+                    "Proposal has transactions below the base fee floor, rejecting"
+                );
+                self.metrics.blocks.inc_rejected_proposals(&value.proposer);
+                return Ok(None);
+            }
+        }
+
+        // A value proposed again from a previous round carries the fee recipient chosen by
+        // the proposer of that round
+        let fee_recipient_proposer = match value.valid_round {
+            Round::Some(_) => self
+                .proposer_of(value.height, value.valid_round)
+                .unwrap_or(value.proposer),
+            _ => value.proposer,
+        };
+
         // Validate the execution payload with the execution engine
         let validity = validate_execution_payload(
             &mut self.validated_payload_cache,
             &data,
             value.height,
             value.round,
+            self.latest_block.as_ref(),
+            self.fee_recipient_policy
+                .as_ref()
+                .map(|policy| FeeRecipientCheck {
+                    policy,
+                    proposer: fee_recipient_proposer,
+                }),
             engine,
             retry_config,
+            &self.metrics.validation,
         )
-        .await?;
+        .await;
+
+        // The proposal is not voted for, consensus deciding it without this node if valid
+        if let Some(divergence) = validity.as_ref().err().and_then(ElDivergence::find) {
+            if let Err(e) = el_divergence::recover(self, engine, &divergence).await {
+                error!(
+                    height = %value.height,
+                    round = %value.round,
+                    "Failed to recover from the divergence of the execution client: {e:#}"
+                );
+            }
+            return Ok(None);
+        }
 
-        if validity == Validity::Invalid {
+        if validity? == Validity::Invalid {
             warn!(
-                height = %parts.height,
-                round = %parts.round,
+                height = %value.height,
+                round = %value.round,
                 "Proposal has invalid execution payload, rejecting"
             );
+            self.metrics.blocks.inc_rejected_proposals(&value.proposer);
             return Ok(None);
         }
 
         // Store as undecided
         info!(%value.height, %value.round, %value.proposer, "Storing validated proposal as undecided");
-        self.store_undecided_value(&value, data).await?;
+        if !self.store_undecided_value(&value, data).await? {
+            return Ok(None);
+        }
 
         Ok(Some(value))
     }
@@ -443,8 +1008,26 @@ impl State {
         let sequence = part.sequence;
 
         // Check if we have a full proposal
-        let Some(parts) = self.streams_map.insert(from, part) else {
-            return Ok(None);
+        let inserted = self.streams_map.insert(from, part);
+        if let Some(timing) = self.streams_map.take_completed() {
+            self.chunk_sizer.observe_stream(timing);
+        }
+
+        let parts = match inserted {
+            Ok(Some(parts)) => parts,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                warn!(%from, "Dropping proposal stream: {e}");
+                match e {
+                    StreamError::TooLarge(_) => {
+                        self.metrics.validation.inc_rejected_payloads(e.as_str())
+                    }
+                    StreamError::Malformed(_) => {
+                        self.metrics.validation.inc_malformed_streams(e.as_str())
+                    }
+                }
+                return Ok(None);
+            }
         };
 
         // Check if the proposal is outdated
@@ -464,7 +1047,11 @@ impl State {
         // Store future proposals parts in pending without validation
         if parts.height > self.consensus_height {
             info!(%parts.height, %parts.round, "Storing proposal parts for a future height in pending");
-            self.store.store_pending_proposal_parts(parts).await?;
+            match self.store.store_pending_proposal_parts(parts).await {
+                Ok(()) => {}
+                Err(e @ StoreError::LimitExceeded { .. }) => warn!(%from, "{e}"),
+                Err(e) => return Err(e.into()),
+            }
             return Ok(None);
         }
 
@@ -472,6 +1059,103 @@ impl State {
         Ok(Some(parts))
     }
 
+    /// Handles a proposal from its init part, if its payload is already known.
+    ///
+    /// A payload proposed again, e.g. in a later round, was already validated and stored
+    /// by this node. Instead of waiting for the rest of the stream, the proposal is stored
+    /// with the known payload and the rest of the stream is ignored.
+    ///
+    /// Returns `None` if the init part does not announce a known payload of the current
+    /// height, in which case the proposal is processed once complete.
+    pub async fn proposal_from_known_payload(
+        &mut self,
+        from: PeerId,
+        stream_id: StreamId,
+        init: &ProposalInit,
+        engine: &Engine,
+        retry_config: &RetryConfig,
+    ) -> eyre::Result<Option<ProposedValue<EmeraldContext>>> {
+        let Some(payload) = &init.payload else {
+            return Ok(None);
+        };
+
+        if init.height != self.consensus_height {
+            return Ok(None);
+        }
+
+        let Some(&(round, value_id)) = self.known_payloads.get(&(init.height, payload.block_hash))
+        else {
+            return Ok(None);
+        };
+
+        let Some(validator_set) = self.get_validator_set(init.height) else {
+            return Ok(None);
+        };
+
+        if let Err(error) = check_proposer(
+            &self.ctx,
+            validator_set,
+            init.height,
+            init.round,
+            init.proposer,
+        )
+        .and_then(|()| {
+            self.verify_payload_summary_signature(init, payload)
+                .map_err(ProposalValidationError::Signature)
+        }) {
+            warn!(
+                height = %init.height,
+                round = %init.round,
+                proposer = %init.proposer,
+                error = %error,
+                "Invalid payload summary in proposal init part, waiting for the full proposal"
+            );
+            return Ok(None);
+        }
+
+        let Some(data) = self
+            .store
+            .get_block_data(init.height, round, value_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        if data.len() as u64 != payload.len {
+            return Ok(None);
+        }
+
+        let value = ProposedValue {
+            height: init.height,
+            round: init.round,
+            valid_round: init.pol_round,
+            proposer: init.proposer,
+            value: Value::new(data.clone()),
+            validity: Validity::Valid,
+        };
+
+        info!(
+            height = %init.height,
+            round = %init.round,
+            block_hash = %payload.block_hash,
+            known_round = %round,
+            "Proposal of a known payload, skipping the rest of the stream"
+        );
+
+        // Validated as a complete proposal, the proposer or the policies of the network
+        // possibly differing from those of the round where the payload was proposed
+        let Some(value) = self
+            .validate_proposed_value(value, data, engine, retry_config)
+            .await?
+        else {
+            return Ok(None);
+        };
+        self.streams_map.skip(from, stream_id);
+        self.metrics.validation.inc_deduplicated_proposals();
+
+        Ok(Some(value))
+    }
+
     /// Retrieves a decided block data at the given height
     pub async fn get_block_data(
         &self,
@@ -493,16 +1177,42 @@ impl State {
     /// leaving a proposal that references non-existent block data. If a crash occurs
     /// between the operations, orphaned block data is safe, but a dangling proposal
     /// reference would cause retrieval failures.
+    ///
+    /// Returns whether the value is stored, which it is not when above the limits of the
+    /// undecided proposals, see [`StoreLimitsConfig`].
     pub async fn store_undecided_value(
-        &self,
+        &mut self,
         value: &ProposedValue<EmeraldContext>,
         data: Bytes,
-    ) -> eyre::Result<()> {
-        self.store
+    ) -> eyre::Result<bool> {
+        let block_hash = ExecutionPayloadV3::from_ssz_bytes(&data)
+            .ok()
+            .map(|payload| payload.payload_inner.payload_inner.block_hash);
+
+        match self
+            .store
             .store_undecided_block_data(value.height, value.round, value.value.id(), data)
-            .await?;
+            .await
+        {
+            Ok(()) => {}
+            Err(e @ StoreError::LimitExceeded { .. }) => {
+                warn!(proposer = %value.proposer, "{e}");
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        }
         self.store.store_undecided_proposal(value.clone()).await?;
-        Ok(())
+
+        if let Some(block_hash) = block_hash {
+            let consensus_height = self.consensus_height;
+            self.known_payloads
+                .retain(|(height, _), _| *height >= consensus_height);
+            self.known_payloads
+                .entry((value.height, block_hash))
+                .or_insert((value.round, value.value.id()));
+        }
+
+        Ok(true)
     }
 
     /// Commits a value with the given certificate, updating internal state
@@ -577,17 +1287,29 @@ impl State {
             )
             .await?;
 
-        // Sleep to reduce the block speed, if set via config.
-        debug!(timeout_commit = ?self.emerald_config.min_block_time);
-        let elapsed_height_time = self.last_block_time.elapsed();
+        self.report_served_heights();
+
+        if prune_certificates {
+            self.validator_sets.prune(Height::new(
+                certificate
+                    .height
+                    .as_u64()
+                    .saturating_sub(self.emerald_config.num_certificates_to_retain),
+            ));
+        }
+
+        // Sleep to reduce the block speed, if set on-chain or via config.
+        let min_block_time = self.min_block_time();
+        debug!(timeout_commit = ?min_block_time);
+        let elapsed_height_time = self.clock.elapsed(self.last_block_time);
 
         info!(
             "👉 stats at {:?}: block_time {:?}",
             certificate.height, elapsed_height_time
         );
 
-        if elapsed_height_time < self.emerald_config.min_block_time {
-            tokio::time::sleep(self.emerald_config.min_block_time - elapsed_height_time).await;
+        if elapsed_height_time < min_block_time {
+            self.clock.sleep(min_block_time - elapsed_height_time).await;
         }
 
         Ok(())
@@ -666,13 +1388,14 @@ impl State {
     }
 
     /// Creates a new proposal value for the given height
-    /// Returns either a previously built proposal or creates a new one
+    /// Returns either a previously built proposal or creates a new one, or `None` if it
+    /// cannot be stored within the limits of the undecided proposals
     pub async fn propose_value(
         &mut self,
         height: Height,
         round: Round,
         data: Bytes,
-    ) -> eyre::Result<LocallyProposedValue<EmeraldContext>> {
+    ) -> eyre::Result<Option<LocallyProposedValue<EmeraldContext>>> {
         assert_eq!(height, self.consensus_height);
         assert_eq!(round, self.consensus_round);
 
@@ -689,12 +1412,136 @@ impl State {
         };
 
         // Store the proposal and its block data
-        self.store_undecided_value(&proposal, data).await?;
+        if !self.store_undecided_value(&proposal, data).await? {
+            warn!(%height, %round, "Not proposing, the limits of the undecided proposals are reached");
+            return Ok(None);
+        }
 
-        Ok(LocallyProposedValue::new(
+        Ok(Some(LocallyProposedValue::new(
             proposal.height,
             proposal.round,
             proposal.value,
+        )))
+    }
+
+    /// Drops the proposal streams left incomplete for too long. The streams lost make the
+    /// chunks of the next proposals of this node smaller, when adaptive.
+    pub fn prune_proposal_streams(&mut self) {
+        let lost = self.streams_map.prune_expired(self.clock.now());
+        if lost > 0 {
+            warn!(lost, "Dropped incomplete proposal streams");
+            self.metrics.proposer.inc_lost_proposal_streams(lost);
+            self.chunk_sizer.observe_lost_streams(lost);
+        }
+    }
+
+    /// Requests the parts missing from the proposal streams which stopped progressing,
+    /// returning the messages to publish to the peers
+    pub fn request_stalled_parts(&mut self) -> Vec<StreamMessage<ProposalPart>> {
+        let requests = self.streams_map.stalled_streams(self.clock.now());
+        self.parts_requests(requests)
+    }
+
+    /// Requests the parts missing from the streams of a proposal, e.g. when consensus
+    /// asks to restream a proposal this node did not complete
+    pub fn request_missing_parts(
+        &mut self,
+        height: Height,
+        round: Round,
+        proposer: Address,
+    ) -> Vec<StreamMessage<ProposalPart>> {
+        let requests = self
+            .streams_map
+            .missing_parts_of(height, round, proposer, self.clock.now());
+        self.parts_requests(requests)
+    }
+
+    /// Whether all the peers decode the requests for missing parts and the parts sent in
+    /// reply, since they are gossiped to all of them
+    fn recovers_parts(&self) -> bool {
+        self.peer_versions.negotiated() >= PARTS_RECOVERY_VERSION
+    }
+
+    fn parts_requests(&mut self, requests: Vec<PartsRequest>) -> Vec<StreamMessage<ProposalPart>> {
+        if !self.recovers_parts() {
+            return Vec::new();
+        }
+
+        requests
+            .into_iter()
+            .map(|request| {
+                info!(
+                    height = %request.height,
+                    round = %request.round,
+                    proposer = %request.proposer,
+                    missing = request.sequences.len(),
+                    from_sequence = ?request.from_sequence,
+                    "Requesting the missing parts of a proposal from the peers"
+                );
+                self.metrics.proposer.inc_proposal_part_requests();
+                let part = ProposalPart::Request(request);
+                StreamMessage::new(self.stream_id(), 0, StreamContent::Data(part))
+            })
+            .collect()
+    }
+
+    /// Replies to a request of a peer with the parts it missed, if this node proposed the
+    /// stream, so that a request is answered by a single node rather than by all the peers
+    pub fn serve_parts_request(
+        &mut self,
+        from: PeerId,
+        request: &PartsRequest,
+    ) -> Vec<StreamMessage<ProposalPart>> {
+        if request.proposer != self.address || !self.recovers_parts() {
+            return Vec::new();
+        }
+
+        let parts = self
+            .streams_map
+            .requested_parts(from, request, self.clock.now());
+        if parts.is_empty() {
+            return Vec::new();
+        }
+
+        debug!(
+            %from,
+            height = %request.height,
+            round = %request.round,
+            parts = parts.len(),
+            "Sending the proposal parts requested by a peer"
+        );
+        self.metrics.proposer.inc_served_proposal_parts(parts.len());
+
+        let stream_id = self.stream_id();
+        parts
+            .into_iter()
+            .zip(0..)
+            .map(|(part, sequence)| {
+                let part = ProposalPart::Recovered(part);
+                StreamMessage::new(stream_id.clone(), sequence, StreamContent::Data(part))
+            })
+            .collect()
+    }
+
+    /// Part of a stream recovered from a peer, with the peer which streamed the rest of
+    /// it, if this node requested it and is still missing it
+    pub fn recovered_part(
+        &self,
+        recovered: RecoveredPart,
+    ) -> Option<(PeerId, StreamMessage<ProposalPart>)> {
+        let stream_id = StreamId::new(recovered.stream_id);
+        let peer_id = self
+            .streams_map
+            .recovering_peer(&stream_id, recovered.sequence)?;
+        let content = match recovered.part {
+            Some(part) => StreamContent::Data(*part),
+            None => StreamContent::Fin,
+        };
+
+        self.metrics.proposer.inc_recovered_proposal_parts();
+        Some((
+            peer_id,
+            StreamMessage::new(stream_id, recovered.sequence, content),
         ))
     }
 
@@ -709,16 +1556,34 @@ impl State {
 
     /// Creates a stream message containing a proposal part.
     /// Updates internal sequence number and current proposal.
-    pub fn stream_proposal(
+    /// Fails if the signing provider refuses to sign the parts, e.g. without the signing lease.
+    pub async fn stream_proposal(
         &mut self,
         value: LocallyProposedValue<EmeraldContext>,
         data: Bytes,
         pol_round: Round,
-    ) -> impl Iterator<Item = StreamMessage<ProposalPart>> {
-        let parts = self.make_proposal_parts(value, data, pol_round);
+    ) -> Result<impl Iterator<Item = StreamMessage<ProposalPart>>, SigningError> {
+        let (height, round) = (value.height, value.round);
+        let parts = self.make_proposal_parts(value, data, pol_round).await?;
+
+        // All the parts but the init and fin ones are chunks of the payload
+        self.metrics
+            .proposer
+            .observe_proposal_chunks(parts.len().saturating_sub(2), self.chunk_sizer.size());
 
         let stream_id = self.stream_id();
 
+        // Kept to reply to the peers missing some of the parts
+        self.streams_map.keep_recent(
+            stream_id.clone(),
+            ProposalParts {
+                height,
+                round,
+                proposer: self.address,
+                parts: parts.clone(),
+            },
+        );
+
         let mut msgs = Vec::with_capacity(parts.len() + 1);
         let mut sequence = 0;
 
@@ -729,26 +1594,35 @@ impl State {
         }
 
         msgs.push(StreamMessage::new(stream_id, sequence, StreamContent::Fin));
-        msgs.into_iter()
+        Ok(msgs.into_iter())
     }
 
-    fn make_proposal_parts(
+    async fn make_proposal_parts(
         &self,
         value: LocallyProposedValue<EmeraldContext>,
         data: Bytes,
         pol_round: Round,
-    ) -> Vec<ProposalPart> {
+    ) -> Result<Vec<ProposalPart>, SigningError> {
         let mut hasher = sha3::Keccak256::new();
         let mut parts = Vec::new();
 
         // Init
         {
-            parts.push(ProposalPart::Init(ProposalInit::new(
-                value.height,
-                value.round,
-                pol_round,
-                self.address,
-            )));
+            let mut init = ProposalInit::new(value.height, value.round, pol_round, self.address);
+
+            // Announce the payload, so that peers which already have it can skip the stream
+            if let Ok(payload) = ExecutionPayloadV3::from_ssz_bytes(&data) {
+                let block_hash = payload.payload_inner.payload_inner.block_hash;
+                let len = data.len() as u64;
+                let hash = sha3::Keccak256::digest(init.payload_sign_bytes(&block_hash, len));
+                init = init.with_payload(PayloadSummary {
+                    block_hash,
+                    len,
+                    signature: self.signing_provider.sign(&hash).await?,
+                });
+            }
+
+            parts.push(ProposalPart::Init(init));
 
             hasher.update(value.height.as_u64().to_be_bytes().as_slice());
             hasher.update(value.round.as_i64().to_be_bytes().as_slice());
@@ -756,7 +1630,7 @@ impl State {
 
         // Data
         {
-            for chunk in data.chunks(CHUNK_SIZE) {
+            for chunk in data.chunks(self.chunk_sizer.size()) {
                 let chunk_data = ProposalData::new(Bytes::copy_from_slice(chunk));
                 parts.push(ProposalPart::Data(chunk_data));
                 hasher.update(chunk);
@@ -765,24 +1639,70 @@ impl State {
 
         {
             let hash = hasher.finalize().to_vec();
-            let signature = self.signing_provider.sign(&hash);
+            let signature = self.signing_provider.sign(&hash).await?;
             parts.push(ProposalPart::Fin(ProposalFin::new(signature)));
         }
 
-        parts
+        Ok(parts)
     }
 
     /// Returns the set of validators for the given consensus height.
-    /// Returns None if the height doesn't match the stored validator set height.
+    /// Returns None if the validator set of that height is not in memory.
     pub fn get_validator_set(&self, height: Height) -> Option<&ValidatorSet> {
-        self.validator_set
-            .as_ref()
-            .and_then(|(h, vs)| if *h == height { Some(vs) } else { None })
+        self.validator_sets.get(height)
     }
 
     /// Sets the validator set for the given consensus height.
     pub fn set_validator_set(&mut self, height: Height, validator_set: ValidatorSet) {
-        self.validator_set = Some((height, validator_set));
+        self.validator_sets.insert(height, validator_set);
+    }
+
+    /// Returns the set of validators for the given consensus height, reading it from the
+    /// store or from the validator manager contract at the block decided at the previous
+    /// height if it is not in memory anymore.
+    ///
+    /// With a power change limit, the validator set is the one stored when the previous
+    /// height was decided, as the one of the contract is not limited. Otherwise, reading the
+    /// contract at an old block requires the execution client to keep the state of all
+    /// blocks, so it is only done if it is an archive node.
+    pub async fn validator_set_at(
+        &mut self,
+        engine: &Engine,
+        height: Height,
+    ) -> eyre::Result<ValidatorSet> {
+        if let Some(validator_set) = self.get_validator_set(height) {
+            return Ok(validator_set.clone());
+        }
+
+        if self.power_change_limit.is_some() {
+            let validator_set = self.store.get_validator_set(height).await?.ok_or_else(|| {
+                eyre::eyre!("Validator set not found for height {height}, nor in the store")
+            })?;
+            self.set_validator_set(height, validator_set.clone());
+            return Ok(validator_set);
+        }
+
+        if self.emerald_config.el_node_type != ElNodeType::Archive {
+            return Err(eyre::eyre!(
+                "Validator set not found for height {height}, and the execution client is not an archive node"
+            ));
+        }
+
+        let parent_height = height
+            .decrement()
+            .ok_or_else(|| eyre::eyre!("No validator set before height {height}"))?;
+        let block_hash = self.decided_block_hash(engine, parent_height).await?;
+
+        let validator_set = read_validators_from_contract(
+            engine.eth.url().as_ref(),
+            &self.validator_manager_address,
+            &block_hash,
+        )
+        .await?;
+        debug!(%height, %block_hash, "Read validator set of an archived block");
+
+        self.set_validator_set(height, validator_set.clone());
+        Ok(validator_set)
     }
 
     /// Update and log per-block statistics
@@ -808,7 +1728,7 @@ impl State {
         // Update cumulative counters
         self.txs_count += tx_count as u64;
         self.chain_bytes += block_bytes_len as u64;
-        let elapsed_time = self.start_time.elapsed();
+        let elapsed_time = self.clock.elapsed(self.start_time);
 
         // Update metrics
         self.metrics.tx_stats.add_txs(tx_count as u64);
@@ -820,10 +1740,12 @@ impl State {
         self.metrics.tx_stats.set_block_tx_count(tx_count as u64);
         self.metrics.tx_stats.set_block_size(block_bytes_len as u64);
 
-        // Persist cumulative metrics to database for crash recovery
-        self.store
-            .store_cumulative_metrics(self.txs_count, self.chain_bytes, elapsed_time.as_secs())
-            .await?;
+        // Persisted in the background for crash recovery
+        self.metrics_aggregator.update(CumulativeMetrics {
+            txs_count: self.txs_count,
+            chain_bytes: self.chain_bytes,
+            elapsed_seconds: elapsed_time.as_secs(),
+        });
 
         info!(
             "👉 stats at height {}: block_time={:.3}s, #txs={}, txs/s={:.2}, block_bytes={}, bytes/s={:.2}, total_txs={}, total_bytes={}",
@@ -841,6 +1763,97 @@ impl State {
     }
 }
 
+/// Validates a proposal by checking both proposer and signature against the validator set
+/// of its height.
+///
+/// These checks, along with the reassembly of the stream, the size of the payload and its
+/// linkage to the parent block, are covered by the conformance vectors of
+/// `app/tests/vectors/proposal_validation.json`.
+pub fn validate_proposal_parts(
+    ctx: &EmeraldContext,
+    signing_provider: &K256Provider,
+    validator_set: &ValidatorSet,
+    parts: &ProposalParts,
+) -> Result<(), ProposalValidationError> {
+    check_proposer(
+        ctx,
+        validator_set,
+        parts.height,
+        parts.round,
+        parts.proposer,
+    )?;
+
+    // If proposer is correct, verify the signature
+    verify_proposal_parts_signature(signing_provider, validator_set, parts)
+        .map_err(ProposalValidationError::Signature)?;
+
+    Ok(())
+}
+
+/// Checks that `proposer` is the expected proposer for the given height and round
+fn check_proposer(
+    ctx: &EmeraldContext,
+    validator_set: &ValidatorSet,
+    height: Height,
+    round: Round,
+    proposer: Address,
+) -> Result<(), ProposalValidationError> {
+    // Get the expected proposer for this height and round
+    let expected_proposer = ctx.select_proposer(validator_set, height, round).address;
+
+    // Check if the proposer matches the expected proposer
+    if proposer != expected_proposer {
+        return Err(ProposalValidationError::WrongProposer {
+            actual: proposer,
+            expected: expected_proposer,
+        });
+    }
+
+    Ok(())
+}
+
+/// Verify proposal signature
+fn verify_proposal_parts_signature(
+    signing_provider: &K256Provider,
+    validator_set: &ValidatorSet,
+    parts: &ProposalParts,
+) -> Result<(), SignatureVerificationError> {
+    let mut hasher = sha3::Keccak256::new();
+
+    let init = parts
+        .init()
+        .ok_or(SignatureVerificationError::MissingInitPart)?;
+
+    let fin = parts
+        .fin()
+        .ok_or(SignatureVerificationError::MissingFinPart)?;
+
+    let hash = {
+        hasher.update(init.height.as_u64().to_be_bytes());
+        hasher.update(init.round.as_i64().to_be_bytes());
+
+        // The correctness of the hash computation relies on the parts being ordered by sequence
+        // number, which is guaranteed by the `PartStreamsMap`.
+        for part in parts.parts.iter().filter_map(|part| part.as_data()) {
+            hasher.update(part.bytes.as_ref());
+        }
+
+        hasher.finalize()
+    };
+
+    // Retrieve the proposer from the validator set of the height
+    let proposer = validator_set
+        .get_by_address(&parts.proposer)
+        .ok_or(SignatureVerificationError::ProposerNotFound)?;
+
+    // Verify the signature
+    if !signing_provider.verify(&hash, &fin.signature, &proposer.public_key) {
+        return Err(SignatureVerificationError::InvalidSignature);
+    }
+
+    Ok(())
+}
+
 /// Re-assemble a [`ProposedValue`] from its [`ProposalParts`].
 ///
 /// This is done by multiplying all the factors in the parts.
@@ -882,6 +1895,287 @@ pub fn assemble_value_from_parts(parts: ProposalParts) -> (ProposedValue<Emerald
 }
 
 /// Decodes a Value from its byte representation using ProtobufCodec
-pub fn decode_value(bytes: Bytes) -> Value {
-    ProtobufCodec.decode(bytes).unwrap()
+pub fn decode_value(bytes: Bytes) -> Result<Value, ProtoError> {
+    ProtobufCodec::default().decode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_app_channel::app::types::PeerId;
+
+    use super::testing::TestNode;
+    use super::*;
+
+    const BLOCK_HASH: B256 = B256::repeat_byte(0xbb);
+
+    fn payload(fee_recipient: Address) -> Bytes {
+        let mut payload = ExecutionPayloadV3::default();
+        payload.payload_inner.payload_inner.block_number = 1;
+        payload.payload_inner.payload_inner.block_hash = BLOCK_HASH;
+        payload.payload_inner.payload_inner.fee_recipient = fee_recipient.to_alloy_address();
+        Bytes::from(payload.as_ssz_bytes())
+    }
+
+    fn peer_id() -> PeerId {
+        PeerId::from_multihash(Default::default()).unwrap()
+    }
+
+    /// Stores the payload as proposed in round 0 and validated by the execution client,
+    /// then receives the init part of its proposal in round 1, with the given POL round
+    async fn propose_known_payload_again(
+        node: &mut TestNode,
+        data: Bytes,
+        pol_round: Round,
+    ) -> Option<ProposedValue<EmeraldContext>> {
+        let height = Height::new(1);
+        let value = ProposedValue {
+            height,
+            round: Round::new(0),
+            valid_round: Round::Nil,
+            proposer: node.state.proposer_of(height, Round::new(0)).unwrap(),
+            value: Value::new(data.clone()),
+            validity: Validity::Valid,
+        };
+        assert!(node
+            .state
+            .store_undecided_value(&value, data.clone())
+            .await
+            .unwrap());
+        node.state
+            .validated_payload_cache
+            .insert(BLOCK_HASH, Validity::Valid);
+
+        let round = Round::new(1);
+        let init = node.init(
+            height,
+            round,
+            pol_round,
+            node.proposer(height, round),
+            BLOCK_HASH,
+            data.len() as u64,
+        );
+        let stream_id = StreamId::new(Bytes::from_static(b"stream"));
+        node.state
+            .proposal_from_known_payload(
+                peer_id(),
+                stream_id,
+                &init,
+                &node.engine,
+                &RetryConfig::default(),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn propose_known_payload(
+        node: &mut TestNode,
+        data: Bytes,
+    ) -> Option<ProposedValue<EmeraldContext>> {
+        propose_known_payload_again(node, data, Round::Nil).await
+    }
+
+    #[tokio::test]
+    async fn test_known_payload_is_accepted() {
+        let mut node = TestNode::new(3, Height::new(1), |_| {}).await;
+        let value = propose_known_payload(&mut node, payload(Address::repeat_byte(1)))
+            .await
+            .expect("Known payload is proposed again");
+        assert_eq!(value.round, Round::new(1));
+        assert_eq!(value.validity, Validity::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_known_payload_is_validated_as_complete_proposal() {
+        // Fee recipient not accepted by the policy
+        let allowed = Address::repeat_byte(1);
+        let mut node = TestNode::new(3, Height::new(1), |genesis| {
+            genesis.fee_recipient_policy = Some(FeeRecipientPolicy::Allowed(vec![allowed]))
+        })
+        .await;
+        let proposed = propose_known_payload(&mut node, payload(Address::repeat_byte(2))).await;
+        assert!(proposed.is_none());
+        assert!(node
+            .state
+            .store
+            .get_undecided_proposals(Height::new(1), Round::new(1))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Payload larger than the maximum size
+        let mut node = TestNode::new(3, Height::new(1), |genesis| {
+            genesis.max_proposal_bytes = Some(16)
+        })
+        .await;
+        let proposed = propose_known_payload(&mut node, payload(allowed))
+            .await
+            .expect("Oversized proposal is handed to consensus");
+        assert_eq!(proposed.validity, Validity::Invalid);
+    }
+
+    #[tokio::test]
+    async fn test_pol_proposal_carries_fee_recipient_of_pol_round() {
+        let height = Height::new(1);
+        let new_node = || {
+            TestNode::new(3, height, |genesis| {
+                genesis.fee_recipient_policy = Some(FeeRecipientPolicy::Proposer)
+            })
+        };
+
+        let mut node = new_node().await;
+        let pol_proposer = node.validator(node.proposer(height, Round::new(0))).0;
+        let proposer = node.validator(node.proposer(height, Round::new(1))).0;
+        assert_ne!(pol_proposer, proposer);
+
+        // Proposed again with its POL round, for the proposer of that round
+        let value = propose_known_payload_again(&mut node, payload(pol_proposer), Round::new(0))
+            .await
+            .expect("POL proposal is accepted");
+        assert_eq!(value.validity, Validity::Valid);
+        assert_eq!(value.proposer, proposer);
+
+        // Without a POL round, the fee recipient must be the proposer of the round
+        let mut node = new_node().await;
+        let proposed = propose_known_payload(&mut node, payload(pol_proposer)).await;
+        assert!(proposed.is_none());
+
+        let mut node = new_node().await;
+        let value = propose_known_payload(&mut node, payload(proposer))
+            .await
+            .expect("Proposal for the proposer of the round is accepted");
+        assert_eq!(value.validity, Validity::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_pol_proposal_is_attributed_to_original_proposer() {
+        let node = TestNode::new(3, Height::new(1), |_| {}).await;
+        let height = Height::new(1);
+        let data = payload(Address::repeat_byte(1));
+        let value = Value::new(data.clone());
+        let proposer = |round| node.state.proposer_of(height, Round::new(round)).unwrap();
+        let store = |round, valid_round| {
+            let value = ProposedValue {
+                height,
+                round: Round::new(round),
+                valid_round,
+                proposer: proposer(round),
+                value: value.clone(),
+                validity: Validity::Valid,
+            };
+            let data = data.clone();
+            let state = &node.state;
+            async move { assert!(state.store_undecided_value(&value, data).await.unwrap()) }
+        };
+        let original = |round| {
+            node.state
+                .original_proposer(height, Round::new(round), value.id())
+        };
+
+        // Proposed in round 0, then again in round 2 with round 1 as POL round, whose
+        // proposal was not received
+        store(0, Round::Nil).await;
+        store(2, Round::new(1)).await;
+        assert_eq!(original(0).await, Some(proposer(0)));
+        assert_eq!(original(2).await, Some(proposer(1)));
+
+        // Proposed again in round 1 from round 0
+        store(1, Round::new(0)).await;
+        assert_eq!(original(1).await, Some(proposer(0)));
+        assert_eq!(original(2).await, Some(proposer(0)));
+        assert_ne!(proposer(0), proposer(2));
+    }
+
+    #[tokio::test]
+    async fn test_value_above_undecided_limits_is_not_proposed() {
+        let mut node = TestNode::new(1, Height::new(1), |_| {}).await;
+        let (height, round) = (Height::new(1), Round::new(0));
+        let proposer = node.validator(0).0;
+
+        // The round already holds as many proposals as the store accepts
+        let limit = node
+            .state
+            .emerald_config
+            .store_limits
+            .undecided_per_round
+            .entries;
+        for i in 0..limit {
+            let data = Bytes::from(i.to_be_bytes().to_vec());
+            let value = ProposedValue {
+                height,
+                round,
+                valid_round: Round::Nil,
+                proposer,
+                value: Value::new(data.clone()),
+                validity: Validity::Valid,
+            };
+            assert!(node
+                .state
+                .store_undecided_value(&value, data)
+                .await
+                .unwrap());
+        }
+
+        let proposed = node
+            .state
+            .propose_value(height, round, Bytes::from_static(b"block"))
+            .await
+            .unwrap();
+        assert!(proposed.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_limited_validator_set_is_never_read_from_the_contract() {
+        let limit = PowerChangeLimit {
+            max_percent: 33,
+            policy: Default::default(),
+        };
+        let mut node = TestNode::new(3, Height::new(5), |genesis| {
+            genesis.power_change_limit = Some(limit)
+        })
+        .await;
+        let validator_set = node
+            .state
+            .get_validator_set(Height::new(5))
+            .unwrap()
+            .clone();
+
+        // Not stored, and the execution client cannot be read to limit it again
+        assert!(node
+            .state
+            .validator_set_at(&node.engine, Height::new(3))
+            .await
+            .is_err());
+        assert!(crate::bootstrap::limited_validator_set(
+            &node.state,
+            &node.engine,
+            Height::new(3),
+            &limit
+        )
+        .await
+        .is_err());
+
+        node.state
+            .store
+            .store_validator_set(Height::new(3), validator_set.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            node.state
+                .validator_set_at(&node.engine, Height::new(3))
+                .await
+                .unwrap(),
+            validator_set
+        );
+        assert_eq!(
+            crate::bootstrap::limited_validator_set(
+                &node.state,
+                &node.engine,
+                Height::new(3),
+                &limit
+            )
+            .await
+            .unwrap(),
+            validator_set
+        );
+    }
 }
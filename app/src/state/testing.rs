@@ -0,0 +1,270 @@
+//! [`State`] of a node for the tests of the validation of the proposals and of the handlers,
+//! whose execution client is unreachable, so that only the payloads already validated by
+//! it can be accepted, and mock execution clients for the tests calling one.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use alloy_genesis::Genesis as EvmGenesis;
+use axum::extract::State as AxumState;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+use bytes::Bytes;
+use emerald_retry::SystemClock;
+use malachitebft_app_channel::app::types::core::{CommitCertificate, Round};
+use malachitebft_eth_cli::config::EmeraldConfig;
+use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::engine_rpc::EngineRPC;
+use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use malachitebft_eth_types::codec::proto::version::SharedPeerVersions;
+use malachitebft_eth_types::secp256k1::{K256Provider, PrivateKey};
+use malachitebft_eth_types::{
+    Address, EmeraldContext, EngineTimeouts, Genesis, Height, PayloadSummary, ProposalInit,
+    Validator, ValidatorSet, Value,
+};
+use serde_json::{json, Value as JsonValue};
+use sha3::Digest;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use url::Url;
+
+use super::{State, StateMetrics};
+use crate::build_info::SharedBuildInfo;
+use crate::catch_up::CatchUpThrottle;
+use crate::el_announce::ElAnnouncer;
+use crate::el_health::SharedElHealth;
+use crate::event_log::EventLog;
+use crate::failover::FencedProvider;
+use crate::forkchoice::Forkchoice;
+use crate::metrics::{DbMetrics, ElMetrics, Metrics};
+use crate::node_status::SharedNodeStatus;
+use crate::peer_filter::SharedPeerFilter;
+use crate::peer_registry::SharedPeerRegistry;
+use crate::store::Store;
+use crate::sync_stats::SharedSyncStats;
+use crate::vote_stats::SharedVoteStats;
+use crate::webhooks::Webhooks;
+
+/// Address on which nothing listens
+const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+/// Node running with the first of the validator keys `[i + 1; 32]`
+pub(crate) struct TestNode {
+    pub state: State,
+    pub engine: Engine,
+    pub keys: Vec<PrivateKey>,
+    _dir: TempDir,
+}
+
+impl TestNode {
+    /// Starts a node of a set of `validators` validators of voting power 1 at `height`,
+    /// with the genesis adjusted by `genesis`
+    pub async fn new(validators: u8, height: Height, genesis: impl FnOnce(&mut Genesis)) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+
+        let keys: Vec<_> = (0..validators)
+            .map(|i| PrivateKey::from_slice(&[i + 1; 32]).unwrap())
+            .collect();
+        let validator_set =
+            ValidatorSet::new(keys.iter().map(|key| Validator::new(key.public_key(), 1)));
+
+        let mut emerald_genesis = Genesis {
+            validator_set: validator_set.clone(),
+            base_fee_floor: None,
+            fee_recipient_policy: None,
+            max_proposal_bytes: None,
+            power_change_limit: None,
+            validator_manager_address: None,
+        };
+        genesis(&mut emerald_genesis);
+
+        let emerald_config = emerald_config(dir.path());
+        let engine = Engine::new(
+            EngineRPC::new(
+                Url::parse(UNREACHABLE).unwrap(),
+                &dir.path().join("jwt.hex"),
+                emerald_config.engine_timeouts.clone(),
+            )
+            .unwrap(),
+            EthereumRPC::new(Url::parse(UNREACHABLE).unwrap()).unwrap(),
+        );
+
+        let store = Store::open(
+            dir.path().join("store.db"),
+            DbMetrics::new(),
+            None,
+            emerald_config.store_limits.clone(),
+        )
+        .await
+        .unwrap();
+
+        let metrics = Metrics::new();
+        let mut state = State::new(
+            emerald_genesis,
+            EmeraldContext::new(),
+            FencedProvider::new(K256Provider::new(keys[0].clone()), None),
+            validator_set.validators[0].address,
+            height,
+            store,
+            StateMetrics {
+                txs_count: 0,
+                chain_bytes: 0,
+                elapsed_seconds: 0,
+                metrics,
+            },
+            emerald_config,
+            EventLog::disabled(),
+            Webhooks::disabled(),
+            ElAnnouncer::disabled(),
+            None,
+            None,
+            None,
+            SharedBuildInfo::default(),
+            SharedPeerFilter::default(),
+            SharedPeerRegistry::default(),
+            SharedPeerVersions::default(),
+            SharedNodeStatus::new(SharedVoteStats::default(), None),
+            SharedSyncStats::default(),
+            SharedElHealth::new(ElMetrics::default()),
+            CatchUpThrottle::new(None, false),
+            Forkchoice::new(0, None),
+            None,
+            SystemClock::shared(),
+        );
+        state.validator_sets.insert(height, validator_set);
+
+        Self {
+            state,
+            engine,
+            keys,
+            _dir: dir,
+        }
+    }
+
+    /// Address and signing provider of the validator with the given key
+    pub fn validator(&self, index: usize) -> (Address, K256Provider) {
+        let key = self.keys[index].clone();
+        (
+            Validator::new(key.public_key(), 1).address,
+            K256Provider::new(key),
+        )
+    }
+
+    /// Index of the key of the proposer of the given round
+    pub fn proposer(&self, height: Height, round: Round) -> usize {
+        let proposer = self.state.proposer_of(height, round).unwrap();
+        (0..self.keys.len())
+            .find(|index| self.validator(*index).0 == proposer)
+            .unwrap()
+    }
+
+    /// Stores the value of `data` as decided at `height` in round 0, without signatures
+    pub async fn decide(&self, height: Height, data: Bytes) -> Value {
+        let value = Value::new(data.clone());
+        let certificate = CommitCertificate {
+            height,
+            round: Round::new(0),
+            value_id: value.id(),
+            commit_signatures: vec![],
+        };
+        self.state
+            .store
+            .store_decided_value(&certificate, value.clone(), data)
+            .await
+            .unwrap();
+        value
+    }
+
+    /// Init part of a proposal of the payload with the given hash and length, signed by
+    /// the validator with the key of the given index
+    pub fn init(
+        &self,
+        height: Height,
+        round: Round,
+        pol_round: Round,
+        proposer: usize,
+        block_hash: alloy_primitives::B256,
+        len: u64,
+    ) -> ProposalInit {
+        let (address, signer) = self.validator(proposer);
+        let init = ProposalInit::new(height, round, pol_round, address);
+        let hash = sha3::Keccak256::digest(init.payload_sign_bytes(&block_hash, len));
+        init.with_payload(PayloadSummary {
+            block_hash,
+            len,
+            signature: signer.sign(&hash),
+        })
+    }
+}
+
+/// Configuration of a node whose execution client is unreachable
+fn emerald_config(dir: &Path) -> EmeraldConfig {
+    let genesis_path = dir.join("genesis.json");
+    std::fs::write(
+        &genesis_path,
+        serde_json::to_string(&EvmGenesis::default()).unwrap(),
+    )
+    .unwrap();
+
+    let jwt_path = dir.join("jwt.hex");
+    std::fs::write(&jwt_path, hex::encode([0x11; 32])).unwrap();
+
+    toml::from_str(&format!(
+        r#"
+        moniker = "test"
+        fee_recipient = "0x0000000000000000000000000000000000000000"
+
+        [ethereum_config]
+        execution_authrpc_address = "{UNREACHABLE}"
+        engine_authrpc_address = "{UNREACHABLE}"
+        jwt_token_path = "{}"
+        eth_genesis_path = "{}"
+        "#,
+        jwt_path.display(),
+        genesis_path.display(),
+    ))
+    .unwrap()
+}
+
+/// Answer of a mock execution client to a JSON-RPC request: the `result` or `error` member
+/// of the response, or the status of the HTTP response when failing the request
+pub(crate) type MockAnswer = Result<JsonValue, StatusCode>;
+
+type Answer = Arc<dyn Fn(&JsonValue) -> MockAnswer + Send + Sync>;
+
+/// Spawns an execution client answering each JSON-RPC request with `answer`, on both its
+/// engine and Ethereum APIs, and returns its URL
+pub(crate) async fn spawn_execution_client(
+    answer: impl Fn(&JsonValue) -> MockAnswer + Send + Sync + 'static,
+) -> Url {
+    async fn handle(
+        AxumState(answer): AxumState<Answer>,
+        Json(request): Json<JsonValue>,
+    ) -> Result<Json<JsonValue>, StatusCode> {
+        let mut response = answer(&request)?;
+        response["jsonrpc"] = json!("2.0");
+        response["id"] = request["id"].clone();
+        Ok(Json(response))
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new()
+        .route("/", post(handle))
+        .with_state(Arc::new(answer) as Answer);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    Url::parse(&format!("http://{addr}")).unwrap()
+}
+
+/// Engine of the execution client at `url`, with a JWT secret written in `dir`
+pub(crate) fn engine(url: Url, dir: &Path) -> Engine {
+    let jwt_path = dir.join("jwt.hex");
+    std::fs::write(&jwt_path, hex::encode([0x11; 32])).unwrap();
+
+    Engine::new(
+        EngineRPC::new(url.clone(), &jwt_path, EngineTimeouts::default()).unwrap(),
+        EthereumRPC::new(url).unwrap(),
+    )
+}
@@ -1,28 +1,48 @@
 #![allow(clippy::result_large_err)]
 
 use core::mem::size_of;
+use core::ops::RangeInclusive;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 
+use alloy_primitives::B256;
 use bytes::Bytes;
-use color_eyre::eyre;
 use malachitebft_app_channel::app::types::codec::Codec;
 use malachitebft_app_channel::app::types::core::{CommitCertificate, Round};
 use malachitebft_app_channel::app::types::sync::RawDecidedValue;
 use malachitebft_app_channel::app::types::ProposedValue;
+use malachitebft_eth_cli::cmd::store::CompactionReport;
+use malachitebft_eth_cli::cmd::verify_store::StoreVerification;
+use malachitebft_eth_cli::config::StoreLimitsConfig;
 use malachitebft_eth_types::codec::proto as codec;
 use malachitebft_eth_types::codec::proto::ProtobufCodec;
-use malachitebft_eth_types::{proto, EmeraldContext, Height, Value, ValueId};
+use malachitebft_eth_types::{proto, EmeraldContext, Height, ValidatorSet, Value, ValueId};
 use malachitebft_proto::{Error as ProtoError, Protobuf};
 use prost::Message;
-use redb::ReadableTable;
+use redb::{ReadableTable, ReadableTableMetadata, TableHandle};
 use thiserror::Error;
-
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+mod archive;
+mod cipher;
+mod compaction;
+mod failpoints;
+mod heights;
 mod keys;
+mod limits;
+mod verify;
+pub use cipher::StoreCipher;
+use compaction::{Database, Journal};
+use failpoints::fail_point;
+pub use heights::DecidedHeights;
 use keys::{HeightKey, UndecidedValueKey};
+use limits::{Admission, EntryKey, Policy, TableUsage, Usage};
 
-use crate::metrics::DbMetrics;
+use crate::config_snapshot::ConfigSnapshot;
+use crate::forkchoice::FinalizedBlock;
+use crate::metrics::{DbMetrics, DbTable};
 use crate::store::keys::PendingValueKey;
 use crate::streaming::ProposalParts;
 
@@ -32,6 +52,13 @@ pub struct DecidedValue {
     pub certificate: CommitCertificate<EmeraldContext>,
 }
 
+/// Checksum of a value as stored, i.e. after encryption if enabled
+fn checksum(bytes: &[u8]) -> Vec<u8> {
+    use sha3::{Digest, Sha3_256};
+
+    Sha3_256::digest(bytes).to_vec()
+}
+
 fn decode_certificate(bytes: &[u8]) -> Result<CommitCertificate<EmeraldContext>, ProtoError> {
     let proto = proto::CommitCertificate::decode(bytes)?;
     codec::decode_certificate(proto)
@@ -69,6 +96,37 @@ pub enum StoreError {
 
     #[error("Failed to serialize/deserialize JSON: {0}")]
     Serialization(#[from] serde_json::Error),
+
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid store archive: {0}")]
+    Archive(String),
+
+    #[error("Failed to compact the store: {0}")]
+    Compaction(String),
+
+    #[error("Invalid store metadata `{0}`")]
+    InvalidMetadata(&'static str),
+
+    #[error("Checksum mismatch in `{table}` at height {height}, the data is corrupted and the height must be synced again")]
+    Corrupted { table: &'static str, height: Height },
+
+    #[error("Genesis mismatch: store was created with genesis {stored}, but the genesis file hashes to {expected}")]
+    GenesisMismatch { stored: B256, expected: B256 },
+
+    #[error("Store schema version {stored} is newer than the version {supported} supported by this node")]
+    UnsupportedSchema { stored: u64, supported: u64 },
+
+    #[error("Limits of `{table}` reached, not storing the proposal of height {height} and round {round}")]
+    LimitExceeded {
+        table: &'static str,
+        height: Height,
+        round: Round,
+    },
 }
 
 const CERTIFICATES_TABLE: redb::TableDefinition<'_, HeightKey, Vec<u8>> =
@@ -89,25 +147,150 @@ const UNDECIDED_BLOCK_DATA_TABLE: redb::TableDefinition<'_, UndecidedValueKey, V
 const DECIDED_BLOCK_HEADERS_TABLE: redb::TableDefinition<'_, HeightKey, Vec<u8>> =
     redb::TableDefinition::new("decided_block_headers");
 
+/// Checksums of the values of `certificates`, as stored
+const CERTIFICATE_CHECKSUMS_TABLE: redb::TableDefinition<'_, HeightKey, Vec<u8>> =
+    redb::TableDefinition::new("certificate_checksums");
+
+/// Checksums of the values of `decided_block_data`, as stored
+const DECIDED_BLOCK_DATA_CHECKSUMS_TABLE: redb::TableDefinition<'_, HeightKey, Vec<u8>> =
+    redb::TableDefinition::new("decided_block_data_checksums");
+
 const PERSISTENT_METRICS_TABLE: redb::TableDefinition<'_, &str, u64> =
     redb::TableDefinition::new("persistent_metrics");
 
+/// Number of decided values read ahead of the consumer of [`Store::stream_raw_decided_values`]
+const RAW_DECIDED_VALUES_BUFFER: usize = 16;
+
 const PENDING_PROPOSAL_PARTS_TABLE: redb::TableDefinition<'_, PendingValueKey, Vec<u8>> =
     redb::TableDefinition::new("pending_proposal_parts");
 
+const STORE_METADATA_TABLE: redb::TableDefinition<'_, &str, Vec<u8>> =
+    redb::TableDefinition::new("store_metadata");
+
+/// Snapshots of the effective configuration, one per startup of the node in order
+const CONFIG_SNAPSHOTS_TABLE: redb::TableDefinition<'_, u64, Vec<u8>> =
+    redb::TableDefinition::new("config_snapshots");
+
+/// Validator set of each height, as limited by the power change limit of the genesis, which
+/// can differ from the one of the validator manager contract
+const VALIDATOR_SETS_TABLE: redb::TableDefinition<'_, HeightKey, Vec<u8>> =
+    redb::TableDefinition::new("validator_sets");
+
+/// Known plaintext sealed under the store key, used to detect a missing or wrong key on open
+const ENCRYPTION_CHECK_KEY: &str = "encryption_check";
+const ENCRYPTION_CHECK_VALUE: &[u8] = b"emerald";
+
+/// Hash of the genesis the store was created with
+const GENESIS_HASH_KEY: &str = "genesis_hash";
+
+/// Version of the layout of the tables the store was written with
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Version of the layout of the tables written by this node.
+/// Stores created before the version was recorded have version 1.
+pub const STORE_SCHEMA_VERSION: u64 = 1;
+
+/// Height and hash of the last block reported as finalized to the execution client
+const FINALIZED_BLOCK_KEY: &str = "finalized_block";
+
+/// Height and hash of the trusted block of the execution client snapshot the node started
+/// from, from which it starts again while it has not decided any height
+const SNAPSHOT_ANCHOR_KEY: &str = "snapshot_anchor";
+
+/// Epoch boundary at which the consensus parameters in force were last read
+const CHAIN_PARAMS_EPOCH_KEY: &str = "chain_params_epoch";
+
+fn decode_height(bytes: &[u8]) -> Option<Height> {
+    let height = bytes.try_into().ok()?;
+    Some(Height::new(u64::from_be_bytes(height)))
+}
+
+/// Height of a block recorded under a metadata key
+fn decode_block_height(bytes: &[u8]) -> Option<Height> {
+    let (height, _) = bytes.split_first_chunk::<8>()?;
+    Some(Height::new(u64::from_be_bytes(*height)))
+}
+
+fn decode_schema_version(bytes: &[u8]) -> Result<u64, StoreError> {
+    <[u8; 8]>::try_from(bytes)
+        .map(u64::from_be_bytes)
+        .map_err(|_| StoreError::InvalidMetadata(SCHEMA_VERSION_KEY))
+}
+
 struct Db {
-    db: redb::Database,
+    db: Database,
     metrics: DbMetrics,
+    cipher: Option<StoreCipher>,
+    heights: RwLock<DecidedHeights>,
+    limits: StoreLimitsConfig,
+    /// Sizes of the entries of the pending and undecided tables, locked before
+    /// opening a write transaction on them
+    usage: Mutex<Usage>,
+    journal: Journal,
 }
 
 impl Db {
-    fn new(path: impl AsRef<Path>, metrics: DbMetrics) -> Result<Self, StoreError> {
+    fn new(
+        path: impl AsRef<Path>,
+        metrics: DbMetrics,
+        cipher: Option<StoreCipher>,
+    ) -> Result<Self, StoreError> {
         Ok(Self {
-            db: redb::Database::create(path).map_err(StoreError::Database)?,
+            db: Database::create(path)?,
             metrics,
+            cipher,
+            heights: RwLock::new(DecidedHeights::default()),
+            limits: StoreLimitsConfig::default(),
+            usage: Mutex::new(Usage::default()),
+            journal: Journal::default(),
         })
     }
 
+    /// Encrypts a value about to be written at `key` in `table`, if encryption is enabled.
+    fn seal<K: redb::Key + 'static>(
+        &self,
+        table: redb::TableDefinition<'_, K, Vec<u8>>,
+        key: &K::SelfType<'_>,
+        bytes: Vec<u8>,
+    ) -> Result<Vec<u8>, StoreError> {
+        match &self.cipher {
+            Some(cipher) => cipher.seal(table.name(), K::as_bytes(key).as_ref(), &bytes),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Decrypts a value read at `key` in `table`, if encryption is enabled.
+    fn unseal<K: redb::Key + 'static>(
+        &self,
+        table: redb::TableDefinition<'_, K, Vec<u8>>,
+        key: &K::SelfType<'_>,
+        bytes: Vec<u8>,
+    ) -> Result<Vec<u8>, StoreError> {
+        match &self.cipher {
+            Some(cipher) => cipher.open(table.name(), K::as_bytes(key).as_ref(), &bytes),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Checks a value read from `table` against its checksum in `checksums`.
+    ///
+    /// Values written before checksums were introduced have none and are not checked.
+    fn verify_checksum(
+        &self,
+        checksums: &impl ReadableTable<HeightKey, Vec<u8>>,
+        table: &'static str,
+        height: Height,
+        bytes: &[u8],
+    ) -> Result<(), StoreError> {
+        match checksums.get(&height)? {
+            Some(expected) if expected.value() != checksum(bytes) => {
+                self.metrics.inc_corrupted_reads();
+                Err(StoreError::Corrupted { table, height })
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn get_decided_value(&self, height: Height) -> Result<Option<DecidedValue>, StoreError> {
         let start = Instant::now();
         let mut read_bytes = 0;
@@ -117,24 +300,38 @@ impl Db {
         let value = {
             let table = tx.open_table(DECIDED_VALUES_TABLE)?;
             let value = table.get(&height)?;
-            value.and_then(|value| {
-                let bytes = value.value();
-                read_bytes = bytes.len() as u64;
-                Value::from_bytes(&bytes).ok()
-            })
+            value
+                .map(|value| {
+                    let bytes = value.value();
+                    read_bytes = bytes.len() as u64;
+                    self.metrics
+                        .add_table_read_bytes(DbTable::DecidedValues, read_bytes);
+                    self.unseal(DECIDED_VALUES_TABLE, &height, bytes)
+                })
+                .transpose()?
+                .and_then(|bytes| Value::from_bytes(&bytes).ok())
         };
 
         let certificate = {
             let table = tx.open_table(CERTIFICATES_TABLE)?;
+            let checksums = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
             let value = table.get(&height)?;
-            value.and_then(|value| {
-                let bytes = value.value();
-                read_bytes += bytes.len() as u64;
-                decode_certificate(&bytes).ok()
-            })
+            value
+                .map(|value| {
+                    let bytes = value.value();
+                    read_bytes += bytes.len() as u64;
+                    self.metrics
+                        .add_table_read_bytes(DbTable::Certificates, bytes.len() as u64);
+                    self.verify_checksum(&checksums, CERTIFICATES_TABLE.name(), height, &bytes)?;
+                    self.unseal(CERTIFICATES_TABLE, &height, bytes)
+                })
+                .transpose()?
+                .and_then(|bytes| decode_certificate(&bytes).ok())
         };
 
         self.metrics.observe_read_time(start.elapsed());
+        self.metrics
+            .observe_table_read_time(DbTable::DecidedValues, start.elapsed());
         self.metrics.add_read_bytes(read_bytes);
         self.metrics.add_key_read_bytes(size_of::<Height>() as u64);
 
@@ -145,6 +342,21 @@ impl Db {
         Ok(decided_value)
     }
 
+    /// Returns the decided value at `height` with its value encoded as sent to syncing peers.
+    fn get_raw_decided_value(
+        &self,
+        height: Height,
+    ) -> Result<Option<RawDecidedValue<EmeraldContext>>, StoreError> {
+        self.get_decided_value(height)?
+            .map(|decided_value| {
+                Ok(RawDecidedValue {
+                    certificate: decided_value.certificate,
+                    value_bytes: ProtobufCodec::default().encode(&decided_value.value)?,
+                })
+            })
+            .transpose()
+    }
+
     fn insert_decided_value(
         &self,
         decided_value: DecidedValue,
@@ -158,27 +370,119 @@ impl Db {
 
         {
             let mut values = tx.open_table(DECIDED_VALUES_TABLE)?;
-            let values_bytes = decided_value.value.to_bytes()?.to_vec();
+            let values_bytes = self.seal(
+                DECIDED_VALUES_TABLE,
+                &height,
+                decided_value.value.to_bytes()?.to_vec(),
+            )?;
             write_bytes += values_bytes.len() as u64;
+            self.metrics
+                .add_table_write_bytes(DbTable::DecidedValues, values_bytes.len() as u64);
             values.insert(height, values_bytes)?;
         }
 
+        fail_point("insert_decided_value::certificate");
+
+        {
+            let mut certificates = tx.open_table(CERTIFICATES_TABLE)?;
+            let encoded_certificate = self.seal(
+                CERTIFICATES_TABLE,
+                &height,
+                encode_certificate(&decided_value.certificate)?,
+            )?;
+            write_bytes += encoded_certificate.len() as u64;
+            self.metrics
+                .add_table_write_bytes(DbTable::Certificates, encoded_certificate.len() as u64);
+
+            let mut checksums = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
+            checksums.insert(height, checksum(&encoded_certificate))?;
+            certificates.insert(height, encoded_certificate)?;
+        }
+
+        fail_point("insert_decided_value::header");
+
+        {
+            let mut headers = tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?;
+            let header_bytes = self.seal(
+                DECIDED_BLOCK_HEADERS_TABLE,
+                &height,
+                block_header_bytes.to_vec(),
+            )?;
+            write_bytes += header_bytes.len() as u64;
+            self.metrics
+                .add_table_write_bytes(DbTable::BlockData, header_bytes.len() as u64);
+            headers.insert(height, header_bytes)?;
+        }
+
+        fail_point("insert_decided_value::commit");
+        tx.commit()?;
+        fail_point("insert_decided_value::after_commit");
+
+        self.heights
+            .write()
+            .expect("decided heights lock poisoned")
+            .insert(height);
+
+        self.metrics.observe_write_time(start.elapsed());
+        self.metrics
+            .observe_table_write_time(DbTable::DecidedValues, start.elapsed());
+        self.metrics.add_write_bytes(write_bytes);
+
+        Ok(())
+    }
+
+    /// Stores the certificate and block header of a height without its value, which is
+    /// rebuilt from the execution client when served, as for a pruned height.
+    fn insert_certificate_and_header(
+        &self,
+        certificate: &CommitCertificate<EmeraldContext>,
+        block_header_bytes: Bytes,
+    ) -> Result<(), StoreError> {
+        let start = Instant::now();
+        let mut write_bytes = 0;
+
+        let height = certificate.height;
+        let tx = self.db.begin_write()?;
+
         {
             let mut certificates = tx.open_table(CERTIFICATES_TABLE)?;
-            let encoded_certificate = encode_certificate(&decided_value.certificate)?;
+            let encoded_certificate = self.seal(
+                CERTIFICATES_TABLE,
+                &height,
+                encode_certificate(certificate)?,
+            )?;
             write_bytes += encoded_certificate.len() as u64;
+            self.metrics
+                .add_table_write_bytes(DbTable::Certificates, encoded_certificate.len() as u64);
+
+            let mut checksums = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
+            checksums.insert(height, checksum(&encoded_certificate))?;
             certificates.insert(height, encoded_certificate)?;
         }
 
         {
             let mut headers = tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?;
-            write_bytes += block_header_bytes.len() as u64;
-            headers.insert(height, block_header_bytes.to_vec())?;
+            let header_bytes = self.seal(
+                DECIDED_BLOCK_HEADERS_TABLE,
+                &height,
+                block_header_bytes.to_vec(),
+            )?;
+            write_bytes += header_bytes.len() as u64;
+            self.metrics
+                .add_table_write_bytes(DbTable::BlockData, header_bytes.len() as u64);
+            headers.insert(height, header_bytes)?;
         }
 
         tx.commit()?;
 
+        self.heights
+            .write()
+            .expect("decided heights lock poisoned")
+            .insert_pruned(height);
+
         self.metrics.observe_write_time(start.elapsed());
+        self.metrics
+            .observe_table_write_time(DbTable::Certificates, start.elapsed());
         self.metrics.add_write_bytes(write_bytes);
 
         Ok(())
@@ -200,8 +504,10 @@ impl Db {
         let value = if let Ok(Some(value)) = table.get(&(height, round, value_id)) {
             let bytes = value.value();
             read_bytes += bytes.len() as u64;
+            let bytes =
+                self.unseal(UNDECIDED_PROPOSALS_TABLE, &(height, round, value_id), bytes)?;
 
-            let proposal = ProtobufCodec
+            let proposal = ProtobufCodec::default()
                 .decode(Bytes::from(bytes))
                 .map_err(StoreError::Protobuf)?;
 
@@ -211,7 +517,11 @@ impl Db {
         };
 
         self.metrics.observe_read_time(start.elapsed());
+        self.metrics
+            .observe_table_read_time(DbTable::Undecided, start.elapsed());
         self.metrics.add_read_bytes(read_bytes);
+        self.metrics
+            .add_table_read_bytes(DbTable::Undecided, read_bytes);
         self.metrics
             .add_key_read_bytes(size_of::<(Height, Round, ValueId)>() as u64);
 
@@ -232,13 +542,15 @@ impl Db {
         let mut proposals = Vec::new();
         for result in table.iter()? {
             let (key, value) = result?;
-            let (h, r, _) = key.value();
+            let key = key.value();
+            let (h, r, _) = key;
 
             if h == height && r == round {
                 let bytes = value.value();
                 read_bytes += bytes.len() as u64;
+                let bytes = self.unseal(UNDECIDED_PROPOSALS_TABLE, &key, bytes)?;
 
-                let proposal = ProtobufCodec
+                let proposal = ProtobufCodec::default()
                     .decode(Bytes::from(bytes))
                     .map_err(StoreError::Protobuf)?;
 
@@ -247,7 +559,11 @@ impl Db {
         }
 
         self.metrics.observe_read_time(start.elapsed());
+        self.metrics
+            .observe_table_read_time(DbTable::Undecided, start.elapsed());
         self.metrics.add_read_bytes(read_bytes);
+        self.metrics
+            .add_table_read_bytes(DbTable::Undecided, read_bytes);
         self.metrics.add_key_read_bytes(
             size_of::<(Height, Round, ValueId)>() as u64 * proposals.len() as u64,
         );
@@ -262,20 +578,28 @@ impl Db {
         let start = Instant::now();
 
         let key = (proposal.height, proposal.round, proposal.value.id());
-        let value = ProtobufCodec.encode(&proposal)?;
+        let value = self.seal(
+            UNDECIDED_PROPOSALS_TABLE,
+            &key,
+            ProtobufCodec::default().encode(&proposal)?.to_vec(),
+        )?;
 
         let tx = self.db.begin_write()?;
         {
             let mut table = tx.open_table(UNDECIDED_PROPOSALS_TABLE)?;
             // Only insert if no value exists at this key
             if table.get(&key)?.is_none() {
-                table.insert(key, value.to_vec())?;
+                table.insert(key, value.clone())?;
             }
         }
         tx.commit()?;
 
         self.metrics.observe_write_time(start.elapsed());
+        self.metrics
+            .observe_table_write_time(DbTable::Undecided, start.elapsed());
         self.metrics.add_write_bytes(value.len() as u64);
+        self.metrics
+            .add_table_write_bytes(DbTable::Undecided, value.len() as u64);
 
         Ok(())
     }
@@ -294,20 +618,26 @@ impl Db {
         let mut proposals = Vec::new();
         for result in table.iter()? {
             let (key, value) = result?;
-            let (h, r, _) = key.value();
+            let key = key.value();
+            let (h, r, _) = key;
 
             if h == height && r == round {
                 let bytes = value.value();
                 read_bytes += bytes.len() as u64;
+                let bytes = self.unseal(PENDING_PROPOSAL_PARTS_TABLE, &key, bytes)?;
 
-                let parts: ProposalParts = serde_json::from_slice(&bytes)?;
+                let parts = ProposalParts::from_bytes(&bytes)?;
 
                 proposals.push(parts);
             }
         }
 
         self.metrics.observe_read_time(start.elapsed());
+        self.metrics
+            .observe_table_read_time(DbTable::Pending, start.elapsed());
         self.metrics.add_read_bytes(read_bytes);
+        self.metrics
+            .add_table_read_bytes(DbTable::Pending, read_bytes);
         self.metrics.add_key_read_bytes(
             size_of::<(Height, Round, ValueId)>() as u64 * proposals.len() as u64,
         );
@@ -321,12 +651,14 @@ impl Db {
             parts.round,
             Self::generate_value_id_from_parts(&parts),
         );
+        let mut usage = self.usage.lock().unwrap();
         let tx = self.db.begin_write()?;
         {
             let mut table = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?;
             table.remove(key)?;
         }
         tx.commit()?;
+        usage.pending.remove(&key);
         Ok(())
     }
 
@@ -337,17 +669,47 @@ impl Db {
             parts.round,
             Self::generate_value_id_from_parts(&parts),
         );
-        let value = serde_json::to_vec(&parts)?;
+        let value = self.seal(
+            PENDING_PROPOSAL_PARTS_TABLE,
+            &key,
+            parts.to_bytes()?.to_vec(),
+        )?;
+
+        let mut usage = self.usage.lock().unwrap();
+        // A proposal stored again replaces the previous one
+        let evicted = if usage.pending.contains_key(&key) {
+            Vec::new()
+        } else {
+            self.admit(
+                PENDING_PROPOSAL_PARTS_TABLE.name(),
+                &usage.pending,
+                key,
+                value.len() as u64,
+                Policy::Pending,
+            )?
+        };
 
         let tx = self.db.begin_write()?;
         {
             let mut table = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?;
+            for key in &evicted {
+                table.remove(key)?;
+            }
             table.insert(key, value.clone())?;
         }
         tx.commit()?;
 
+        for key in &evicted {
+            usage.pending.remove(key);
+        }
+        usage.pending.insert(key, value.len() as u64);
+
         self.metrics.observe_write_time(start.elapsed());
+        self.metrics
+            .observe_table_write_time(DbTable::Pending, start.elapsed());
         self.metrics.add_write_bytes(value.len() as u64);
+        self.metrics
+            .add_table_write_bytes(DbTable::Pending, value.len() as u64);
 
         Ok(())
     }
@@ -405,6 +767,7 @@ impl Db {
     ) -> Result<(), StoreError> {
         let start = Instant::now();
 
+        let mut usage = self.usage.lock().unwrap();
         let tx = self.db.begin_write().unwrap();
 
         {
@@ -428,6 +791,11 @@ impl Db {
                 let mut pending = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?;
                 pending.retain(|k, _| k.0 >= block_data_retain_height)?;
 
+                usage
+                    .undecided
+                    .retain(|k, _| k.0 >= block_data_retain_height);
+                usage.pending.retain(|k, _| k.0 >= block_data_retain_height);
+
                 // Remove all decided values with height < retain_height
                 let mut decided = tx.open_table(DECIDED_VALUES_TABLE)?;
                 decided.retain(|k, _| k >= block_data_retain_height)?;
@@ -435,6 +803,9 @@ impl Db {
                 // Remove all decided block data with height < retain_height
                 let mut decided_block_data = tx.open_table(DECIDED_BLOCK_DATA_TABLE)?;
                 decided_block_data.retain(|k, _| k >= block_data_retain_height)?;
+
+                let mut block_data_checksums = tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?;
+                block_data_checksums.retain(|k, _| k >= block_data_retain_height)?;
             }
             if prune_certificates {
                 // This will compute the retain height for the certificates which is based on the
@@ -449,56 +820,188 @@ impl Db {
                 // We prune certificates only if pruning is set.
                 let mut certificate_data = tx.open_table(CERTIFICATES_TABLE)?;
                 certificate_data.retain(|k, _| k >= certificate_retain_height)?;
+
+                let mut certificate_checksums = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
+                certificate_checksums.retain(|k, _| k >= certificate_retain_height)?;
+
+                // The validator sets are only needed to verify the certificates retained
+                let mut validator_sets = tx.open_table(VALIDATOR_SETS_TABLE)?;
+                validator_sets.retain(|k, _| k >= certificate_retain_height)?;
             }
         }
 
+        fail_point("prune::commit");
         tx.commit()?;
+        self.reload_decided_heights()?;
 
         self.metrics.observe_delete_time(start.elapsed());
 
         Ok(())
     }
 
-    fn min_decided_value_height(&self) -> Option<Height> {
+    fn truncate_above(&self, height: Height) -> Result<(), StoreError> {
         let start = Instant::now();
 
-        let tx = self.db.begin_read().unwrap();
-        let table = tx.open_table(CERTIFICATES_TABLE).unwrap();
-        let (key, value) = table.first().ok()??;
+        let mut usage = self.usage.lock().unwrap();
+        let tx = self.db.begin_write()?;
 
-        self.metrics.observe_read_time(start.elapsed());
-        self.metrics.add_read_bytes(value.value().len() as u64);
-        self.metrics.add_key_read_bytes(size_of::<Height>() as u64);
+        {
+            // Remove all decided data with height > height
+            let mut decided = tx.open_table(DECIDED_VALUES_TABLE)?;
+            decided.retain(|k, _| k <= height)?;
+
+            let mut certificates = tx.open_table(CERTIFICATES_TABLE)?;
+            certificates.retain(|k, _| k <= height)?;
+
+            let mut certificate_checksums = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
+            certificate_checksums.retain(|k, _| k <= height)?;
 
-        Some(key.value())
+            let mut decided_block_data = tx.open_table(DECIDED_BLOCK_DATA_TABLE)?;
+            decided_block_data.retain(|k, _| k <= height)?;
+
+            let mut block_data_checksums = tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?;
+            block_data_checksums.retain(|k, _| k <= height)?;
+
+            let mut headers = tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?;
+            headers.retain(|k, _| k <= height)?;
+
+            // Undecided and pending data is only meaningful for the heights being rolled back
+            let mut undecided = tx.open_table(UNDECIDED_PROPOSALS_TABLE)?;
+            undecided.retain(|_, _| false)?;
+
+            let mut undecided_block_data = tx.open_table(UNDECIDED_BLOCK_DATA_TABLE)?;
+            undecided_block_data.retain(|_, _| false)?;
+
+            let mut pending = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?;
+            pending.retain(|_, _| false)?;
+
+            // The finalized block is recomputed at the next decision
+            let mut metadata = tx.open_table(STORE_METADATA_TABLE)?;
+            metadata.remove(FINALIZED_BLOCK_KEY)?;
+
+            // A node rolled back below its snapshot cannot start from it anymore
+            let stale = metadata
+                .get(SNAPSHOT_ANCHOR_KEY)?
+                .and_then(|value| decode_block_height(&value.value()))
+                .is_some_and(|anchor| anchor > height);
+            if stale {
+                metadata.remove(SNAPSHOT_ANCHOR_KEY)?;
+            }
+
+            // The validator sets above the next height cannot be used anymore
+            let mut validator_sets = tx.open_table(VALIDATOR_SETS_TABLE)?;
+            validator_sets.retain(|k, _| k <= height.increment())?;
+
+            // Parameters read above the truncation height are not in force anymore
+            let stale = metadata
+                .get(CHAIN_PARAMS_EPOCH_KEY)?
+                .and_then(|value| decode_height(&value.value()))
+                .is_some_and(|stored| stored > height);
+            if stale {
+                metadata.remove(CHAIN_PARAMS_EPOCH_KEY)?;
+            }
+        }
+
+        tx.commit()?;
+        *usage = Usage::default();
+        self.reload_decided_heights()?;
+
+        self.metrics.observe_delete_time(start.elapsed());
+
+        Ok(())
+    }
+
+    /// Reads the sizes of the entries of the pending and undecided tables,
+    /// after the store is opened or imported.
+    fn reload_usage(&self) -> Result<(), StoreError> {
+        fn table_usage(
+            table: &impl ReadableTable<UndecidedValueKey, Vec<u8>>,
+        ) -> Result<TableUsage, StoreError> {
+            let mut usage = TableUsage::new();
+            for result in table.iter()? {
+                let (key, value) = result?;
+                usage.insert(key.value(), value.value().len() as u64);
+            }
+            Ok(usage)
+        }
+
+        let mut usage = self.usage.lock().unwrap();
+        let tx = self.db.begin_read()?;
+        *usage = Usage {
+            pending: table_usage(&tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?)?,
+            undecided: table_usage(&tx.open_table(UNDECIDED_BLOCK_DATA_TABLE)?)?,
+        };
+
+        Ok(())
+    }
+
+    /// Applies the limits of `table` to the insertion of an entry of `size` bytes at `key`,
+    /// returning the entries to evict to make room for it.
+    fn admit(
+        &self,
+        table: &'static str,
+        usage: &TableUsage,
+        key: EntryKey,
+        size: u64,
+        policy: Policy,
+    ) -> Result<Vec<EntryKey>, StoreError> {
+        let (hard, soft) = match policy {
+            Policy::Pending => (&self.limits.pending_per_height, &self.limits.pending_total),
+            Policy::Undecided => (
+                &self.limits.undecided_per_round,
+                &self.limits.undecided_total,
+            ),
+        };
+
+        match limits::admit(usage, key, size, policy, hard, soft) {
+            Admission::Admitted { evicted } => {
+                if !evicted.is_empty() {
+                    warn!(%table, evicted = evicted.len(), "Evicted proposals to stay within the limits of the table");
+                    self.metrics
+                        .add_evicted_entries(table, evicted.len() as u64);
+                }
+                Ok(evicted)
+            }
+            Admission::Rejected => {
+                self.metrics.inc_rejected_entries(table);
+                Err(StoreError::LimitExceeded {
+                    table,
+                    height: key.0,
+                    round: key.1,
+                })
+            }
+        }
     }
 
-    fn min_unpruned_decided_value_height(&self) -> Option<Height> {
+    /// Reads the decided heights from the tables, after they have been pruned or truncated.
+    fn reload_decided_heights(&self) -> Result<(), StoreError> {
         let start = Instant::now();
 
-        let tx = self.db.begin_read().expect("failed to open db for reading");
-        let table = tx
-            .open_table(DECIDED_VALUES_TABLE)
-            .expect("failed to open DECIDED_VALUES_TABLE");
-        let (key, value) = table.first().ok()??;
+        let tx = self.db.begin_read()?;
+        let certificates = tx.open_table(CERTIFICATES_TABLE)?;
+        let decided = tx.open_table(DECIDED_VALUES_TABLE)?;
+
+        let heights = DecidedHeights {
+            earliest: certificates.first()?.map(|(key, _)| key.value()),
+            earliest_unpruned: decided.first()?.map(|(key, _)| key.value()),
+            latest: certificates.last()?.map(|(key, _)| key.value()),
+        };
 
         self.metrics.observe_read_time(start.elapsed());
-        self.metrics.add_read_bytes(value.value().len() as u64);
-        self.metrics.add_key_read_bytes(size_of::<Height>() as u64);
+        self.metrics
+            .add_key_read_bytes(3 * size_of::<Height>() as u64);
+
+        *self.heights.write().expect("decided heights lock poisoned") = heights;
+
+        Ok(())
+    }
 
-        Some(key.value())
+    fn decided_heights(&self) -> DecidedHeights {
+        *self.heights.read().expect("decided heights lock poisoned")
     }
 
     fn max_decided_value_height(&self) -> Option<Height> {
-        let tx = self
-            .db
-            .begin_read()
-            .expect("failed for open db for reading");
-        let table = tx
-            .open_table(DECIDED_VALUES_TABLE)
-            .expect("failed to open DECIDED_VALUES_TABLE");
-        let (key, _) = table.last().ok()??;
-        Some(key.value())
+        self.decided_heights().latest
     }
 
     fn create_tables(&self) -> Result<(), StoreError> {
@@ -511,78 +1014,401 @@ impl Db {
         let _ = tx.open_table(DECIDED_BLOCK_DATA_TABLE)?;
         let _ = tx.open_table(UNDECIDED_BLOCK_DATA_TABLE)?;
         let _ = tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?;
+        let _ = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
+        let _ = tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?;
         let _ = tx.open_table(PERSISTENT_METRICS_TABLE)?;
         let _ = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?;
+        let _ = tx.open_table(STORE_METADATA_TABLE)?;
+        let _ = tx.open_table(CONFIG_SNAPSHOTS_TABLE)?;
+        let _ = tx.open_table(VALIDATOR_SETS_TABLE)?;
 
         tx.commit()?;
 
         Ok(())
     }
 
-    fn insert_cumulative_metrics(
-        &self,
-        txs_count: u64,
-        chain_bytes: u64,
-        elapsed_seconds: u64,
-    ) -> Result<(), StoreError> {
-        let start = Instant::now();
-        let write_bytes = (size_of::<u64>() * 3) as u64;
-
+    /// Ensures the store is opened with the key it was created with, if any.
+    ///
+    /// The first time an empty store is opened with a key, a known value is sealed with it
+    /// and recorded, so that later opens without the key or with a different one fail early
+    /// instead of when first reading a value.
+    fn check_encryption(&self) -> Result<(), StoreError> {
         let tx = self.db.begin_write()?;
         {
-            let mut table = tx.open_table(PERSISTENT_METRICS_TABLE)?;
-            table.insert("txs_count", txs_count)?;
-            table.insert("chain_bytes", chain_bytes)?;
-            table.insert("elapsed_seconds", elapsed_seconds)?;
+            // All the tables whose values are sealed by the cipher
+            let sealed_tables_empty = tx.open_table(CERTIFICATES_TABLE)?.is_empty()?
+                && tx.open_table(DECIDED_VALUES_TABLE)?.is_empty()?
+                && tx.open_table(UNDECIDED_PROPOSALS_TABLE)?.is_empty()?
+                && tx.open_table(DECIDED_BLOCK_DATA_TABLE)?.is_empty()?
+                && tx.open_table(UNDECIDED_BLOCK_DATA_TABLE)?.is_empty()?
+                && tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?.is_empty()?
+                && tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?.is_empty()?
+                && tx.open_table(CONFIG_SNAPSHOTS_TABLE)?.is_empty()?;
+            let mut table = tx.open_table(STORE_METADATA_TABLE)?;
+            let check = table.get(ENCRYPTION_CHECK_KEY)?.map(|v| v.value());
+
+            match (&self.cipher, check) {
+                (Some(cipher), Some(sealed)) => {
+                    let plaintext = cipher.open(
+                        STORE_METADATA_TABLE.name(),
+                        ENCRYPTION_CHECK_KEY.as_bytes(),
+                        &sealed,
+                    )?;
+                    if plaintext != ENCRYPTION_CHECK_VALUE {
+                        return Err(StoreError::Encryption(
+                            "Store encryption check value does not match".to_string(),
+                        ));
+                    }
+                }
+                (Some(cipher), None) => {
+                    if !sealed_tables_empty {
+                        return Err(StoreError::Encryption(
+                            "Store already contains unencrypted data, \
+                             cannot enable encryption on an existing store"
+                                .to_string(),
+                        ));
+                    }
+                    let sealed = cipher.seal(
+                        STORE_METADATA_TABLE.name(),
+                        ENCRYPTION_CHECK_KEY.as_bytes(),
+                        ENCRYPTION_CHECK_VALUE,
+                    )?;
+                    table.insert(ENCRYPTION_CHECK_KEY, sealed)?;
+                }
+                (None, Some(_)) => {
+                    return Err(StoreError::Encryption(
+                        "Store is encrypted but no `store_encryption_key` is configured"
+                            .to_string(),
+                    ));
+                }
+                (None, None) => {}
+            }
         }
         tx.commit()?;
 
-        self.metrics.observe_write_time(start.elapsed());
-        self.metrics.add_write_bytes(write_bytes);
+        Ok(())
+    }
+
+    /// Records the schema version on first open, and refuses stores written by a newer node.
+    fn check_schema_version(&self) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(STORE_METADATA_TABLE)?;
+            let stored = table.get(SCHEMA_VERSION_KEY)?.map(|v| v.value());
+
+            match stored {
+                Some(bytes) => {
+                    let stored = decode_schema_version(&bytes)?;
+                    if stored > STORE_SCHEMA_VERSION {
+                        return Err(StoreError::UnsupportedSchema {
+                            stored,
+                            supported: STORE_SCHEMA_VERSION,
+                        });
+                    }
+                }
+                None => {
+                    table.insert(
+                        SCHEMA_VERSION_KEY,
+                        STORE_SCHEMA_VERSION.to_be_bytes().to_vec(),
+                    )?;
+                }
+            }
+        }
+        tx.commit()?;
 
         Ok(())
     }
 
-    fn get_cumulative_metrics(&self) -> Result<Option<(u64, u64, u64)>, StoreError> {
-        let start = Instant::now();
-        let mut read_bytes = 0;
+    fn get_schema_version(&self) -> Result<u64, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(STORE_METADATA_TABLE)?;
+        match table.get(SCHEMA_VERSION_KEY)? {
+            Some(value) => decode_schema_version(&value.value()),
+            None => Ok(1),
+        }
+    }
 
+    fn get_genesis_hash(&self) -> Result<Option<B256>, StoreError> {
         let tx = self.db.begin_read()?;
-        let table = tx.open_table(PERSISTENT_METRICS_TABLE)?;
+        let table = tx.open_table(STORE_METADATA_TABLE)?;
+        let hash = table
+            .get(GENESIS_HASH_KEY)?
+            .map(|v| B256::from_slice(&v.value()));
 
-        let txs_count = table.get("txs_count")?.map(|v| {
-            read_bytes += size_of::<u64>() as u64;
-            v.value()
-        });
+        Ok(hash)
+    }
 
-        let chain_bytes = table.get("chain_bytes")?.map(|v| {
-            read_bytes += size_of::<u64>() as u64;
-            v.value()
-        });
+    /// Records the genesis hash on first open, and checks it on every later one.
+    fn check_genesis_hash(&self, genesis_hash: B256) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(STORE_METADATA_TABLE)?;
+            let stored = table
+                .get(GENESIS_HASH_KEY)?
+                .map(|v| B256::from_slice(&v.value()));
+
+            match stored {
+                Some(stored) if stored != genesis_hash => {
+                    return Err(StoreError::GenesisMismatch {
+                        stored,
+                        expected: genesis_hash,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    table.insert(GENESIS_HASH_KEY, genesis_hash.to_vec())?;
+                }
+            }
+        }
+        tx.commit()?;
 
-        let elapsed_seconds = table.get("elapsed_seconds")?.map(|v| {
-            read_bytes += size_of::<u64>() as u64;
-            v.value()
-        });
+        Ok(())
+    }
 
-        self.metrics.observe_read_time(start.elapsed());
-        self.metrics.add_read_bytes(read_bytes);
-        self.metrics.add_key_read_bytes(
-            ("txs_count".len() + "chain_bytes".len() + "elapsed_seconds".len()) as u64,
-        );
+    fn insert_finalized_block(&self, finalized: FinalizedBlock) -> Result<(), StoreError> {
+        self.insert_block(FINALIZED_BLOCK_KEY, finalized)
+    }
 
-        Ok(txs_count
-            .zip(chain_bytes)
-            .and_then(|(t, c)| elapsed_seconds.map(|e| (t, c, e))))
+    fn insert_snapshot_anchor(&self, anchor: FinalizedBlock) -> Result<(), StoreError> {
+        self.insert_block(SNAPSHOT_ANCHOR_KEY, anchor)
     }
 
-    fn get_block_data(
+    /// Records the height and hash of a block under the metadata `key`
+    fn insert_block(&self, key: &str, block: FinalizedBlock) -> Result<(), StoreError> {
+        let mut bytes = block.height.as_u64().to_be_bytes().to_vec();
+        bytes.extend_from_slice(block.block_hash.as_slice());
+
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(STORE_METADATA_TABLE)?;
+            table.insert(key, bytes)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn insert_validator_set(
         &self,
         height: Height,
-        round: Round,
-        value_id: ValueId,
-    ) -> Result<Option<Bytes>, StoreError> {
-        let start = Instant::now();
+        validator_set: &ValidatorSet,
+    ) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(VALIDATOR_SETS_TABLE)?;
+            table.insert(height, serde_json::to_vec(validator_set)?)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn get_validator_set(&self, height: Height) -> Result<Option<ValidatorSet>, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(VALIDATOR_SETS_TABLE)?;
+        let Some(value) = table.get(height)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(serde_json::from_slice(&value.value())?))
+    }
+
+    fn get_latest_validator_set_at_or_below(
+        &self,
+        height: Height,
+    ) -> Result<Option<(Height, ValidatorSet)>, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(VALIDATOR_SETS_TABLE)?;
+        let Some((key, value)) = table.range(..=height)?.next_back().transpose()? else {
+            return Ok(None);
+        };
+
+        Ok(Some((key.value(), serde_json::from_slice(&value.value())?)))
+    }
+
+    /// Records the configuration of a startup after those of the previous ones
+    fn insert_config_snapshot(&self, snapshot: &ConfigSnapshot) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(CONFIG_SNAPSHOTS_TABLE)?;
+            let index = table.len()?;
+            let bytes = self.seal(
+                CONFIG_SNAPSHOTS_TABLE,
+                &index,
+                serde_json::to_vec(snapshot)?,
+            )?;
+            table.insert(index, bytes)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn get_config_snapshots(&self) -> Result<Vec<ConfigSnapshot>, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(CONFIG_SNAPSHOTS_TABLE)?;
+
+        table
+            .iter()?
+            .map(|entry| {
+                let (index, value) = entry?;
+                let bytes = self.unseal(CONFIG_SNAPSHOTS_TABLE, &index.value(), value.value())?;
+                Ok(serde_json::from_slice(&bytes)?)
+            })
+            .collect()
+    }
+
+    fn insert_chain_params_epoch(&self, height: Height) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(STORE_METADATA_TABLE)?;
+            table.insert(
+                CHAIN_PARAMS_EPOCH_KEY,
+                height.as_u64().to_be_bytes().to_vec(),
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn get_chain_params_epoch(&self) -> Result<Option<Height>, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(STORE_METADATA_TABLE)?;
+        let Some(value) = table.get(CHAIN_PARAMS_EPOCH_KEY)? else {
+            return Ok(None);
+        };
+
+        decode_height(&value.value())
+            .map(Some)
+            .ok_or(StoreError::InvalidMetadata(CHAIN_PARAMS_EPOCH_KEY))
+    }
+
+    fn get_finalized_block(&self) -> Result<Option<FinalizedBlock>, StoreError> {
+        self.get_block(FINALIZED_BLOCK_KEY)
+    }
+
+    fn get_snapshot_anchor(&self) -> Result<Option<FinalizedBlock>, StoreError> {
+        self.get_block(SNAPSHOT_ANCHOR_KEY)
+    }
+
+    fn get_block(&self, key: &'static str) -> Result<Option<FinalizedBlock>, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(STORE_METADATA_TABLE)?;
+        let Some(value) = table.get(key)? else {
+            return Ok(None);
+        };
+
+        let bytes = value.value();
+        let (height, block_hash) = bytes
+            .split_first_chunk::<8>()
+            .filter(|(_, block_hash)| block_hash.len() == B256::len_bytes())
+            .ok_or(StoreError::InvalidMetadata(key))?;
+
+        Ok(Some(FinalizedBlock {
+            height: Height::new(u64::from_be_bytes(*height)),
+            block_hash: B256::from_slice(block_hash),
+        }))
+    }
+
+    /// Re-encode pending proposal parts still stored in the legacy JSON format as protobuf.
+    /// Returns the number of migrated entries.
+    fn migrate_pending_proposal_parts(&self) -> Result<usize, StoreError> {
+        let tx = self.db.begin_write()?;
+        let mut migrated = 0;
+        {
+            let mut table = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE)?;
+
+            // Protobuf-encoded entries never start with `{`, so they never parse as JSON
+            let mut legacy = Vec::new();
+            for result in table.iter()? {
+                let (key, value) = result?;
+                let key = key.value();
+                let bytes = self.unseal(PENDING_PROPOSAL_PARTS_TABLE, &key, value.value())?;
+                if let Ok(parts) = serde_json::from_slice::<ProposalParts>(&bytes) {
+                    legacy.push((key, parts));
+                }
+            }
+
+            for (key, parts) in legacy {
+                let bytes = self.seal(
+                    PENDING_PROPOSAL_PARTS_TABLE,
+                    &key,
+                    parts.to_bytes()?.to_vec(),
+                )?;
+                table.insert(key, bytes)?;
+                migrated += 1;
+            }
+        }
+        tx.commit()?;
+
+        Ok(migrated)
+    }
+
+    fn insert_cumulative_metrics(
+        &self,
+        txs_count: u64,
+        chain_bytes: u64,
+        elapsed_seconds: u64,
+    ) -> Result<(), StoreError> {
+        let start = Instant::now();
+        let write_bytes = (size_of::<u64>() * 3) as u64;
+
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(PERSISTENT_METRICS_TABLE)?;
+            table.insert("txs_count", txs_count)?;
+            table.insert("chain_bytes", chain_bytes)?;
+            table.insert("elapsed_seconds", elapsed_seconds)?;
+        }
+        tx.commit()?;
+
+        self.metrics.observe_write_time(start.elapsed());
+        self.metrics.add_write_bytes(write_bytes);
+
+        Ok(())
+    }
+
+    fn get_cumulative_metrics(&self) -> Result<Option<(u64, u64, u64)>, StoreError> {
+        let start = Instant::now();
+        let mut read_bytes = 0;
+
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(PERSISTENT_METRICS_TABLE)?;
+
+        let txs_count = table.get("txs_count")?.map(|v| {
+            read_bytes += size_of::<u64>() as u64;
+            v.value()
+        });
+
+        let chain_bytes = table.get("chain_bytes")?.map(|v| {
+            read_bytes += size_of::<u64>() as u64;
+            v.value()
+        });
+
+        let elapsed_seconds = table.get("elapsed_seconds")?.map(|v| {
+            read_bytes += size_of::<u64>() as u64;
+            v.value()
+        });
+
+        self.metrics.observe_read_time(start.elapsed());
+        self.metrics.add_read_bytes(read_bytes);
+        self.metrics.add_key_read_bytes(
+            ("txs_count".len() + "chain_bytes".len() + "elapsed_seconds".len()) as u64,
+        );
+
+        Ok(txs_count
+            .zip(chain_bytes)
+            .and_then(|(t, c)| elapsed_seconds.map(|e| (t, c, e))))
+    }
+
+    fn get_block_data(
+        &self,
+        height: Height,
+        round: Round,
+        value_id: ValueId,
+    ) -> Result<Option<Bytes>, StoreError> {
+        let start = Instant::now();
 
         let tx = self.db.begin_read()?;
 
@@ -591,12 +1417,21 @@ impl Db {
         if let Some(data) = undecided_table.get(&(height, round, value_id))? {
             let bytes = data.value();
             let read_bytes = bytes.len() as u64;
+            let bytes = self.unseal(
+                UNDECIDED_BLOCK_DATA_TABLE,
+                &(height, round, value_id),
+                bytes,
+            )?;
             self.metrics.observe_read_time(start.elapsed());
+            self.metrics
+                .observe_table_read_time(DbTable::Undecided, start.elapsed());
             self.metrics.add_read_bytes(read_bytes);
+            self.metrics
+                .add_table_read_bytes(DbTable::Undecided, read_bytes);
             self.metrics.add_key_read_bytes(
                 (size_of::<Height>() + size_of::<Round>() + size_of::<ValueId>()) as u64,
             );
-            return Ok(Some(Bytes::copy_from_slice(&bytes)));
+            return Ok(Some(Bytes::from(bytes)));
         }
 
         // Then try decided block data
@@ -604,13 +1439,22 @@ impl Db {
         if let Some(data) = decided_table.get(&height)? {
             let bytes = data.value();
             let read_bytes = bytes.len() as u64;
+            let checksums = tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?;
+            self.verify_checksum(&checksums, DECIDED_BLOCK_DATA_TABLE.name(), height, &bytes)?;
+            let bytes = self.unseal(DECIDED_BLOCK_DATA_TABLE, &height, bytes)?;
             self.metrics.observe_read_time(start.elapsed());
+            self.metrics
+                .observe_table_read_time(DbTable::BlockData, start.elapsed());
             self.metrics.add_read_bytes(read_bytes);
+            self.metrics
+                .add_table_read_bytes(DbTable::BlockData, read_bytes);
             self.metrics.add_key_read_bytes(size_of::<Height>() as u64);
-            return Ok(Some(Bytes::copy_from_slice(&bytes)));
+            return Ok(Some(Bytes::from(bytes)));
         }
 
         self.metrics.observe_read_time(start.elapsed());
+        self.metrics
+            .observe_table_read_time(DbTable::BlockData, start.elapsed());
         Ok(None)
     }
 
@@ -622,27 +1466,56 @@ impl Db {
         data: Bytes,
     ) -> Result<(), StoreError> {
         let start = Instant::now();
+        let key = (height, round, value_id);
+        let data = self.seal(UNDECIDED_BLOCK_DATA_TABLE, &key, data.to_vec())?;
         let write_bytes = data.len() as u64;
 
+        let mut usage = self.usage.lock().unwrap();
+        // Only insert if no value exists at this key
+        if usage.undecided.contains_key(&key) {
+            return Ok(());
+        }
+
+        let evicted = self.admit(
+            UNDECIDED_BLOCK_DATA_TABLE.name(),
+            &usage.undecided,
+            key,
+            write_bytes,
+            Policy::Undecided,
+        )?;
+
         let tx = self.db.begin_write()?;
         {
             let mut table = tx.open_table(UNDECIDED_BLOCK_DATA_TABLE)?;
-            let key = (height, round, value_id);
-            // Only insert if no value exists at this key
+            let mut proposals = tx.open_table(UNDECIDED_PROPOSALS_TABLE)?;
+            for key in &evicted {
+                table.remove(key)?;
+                proposals.remove(key)?;
+            }
             if table.get(&key)?.is_none() {
-                table.insert(key, data.to_vec())?;
+                table.insert(key, data)?;
             }
         }
         tx.commit()?;
 
+        for key in &evicted {
+            usage.undecided.remove(key);
+        }
+        usage.undecided.insert(key, write_bytes);
+
         self.metrics.observe_write_time(start.elapsed());
+        self.metrics
+            .observe_table_write_time(DbTable::Undecided, start.elapsed());
         self.metrics.add_write_bytes(write_bytes);
+        self.metrics
+            .add_table_write_bytes(DbTable::Undecided, write_bytes);
 
         Ok(())
     }
 
     fn insert_decided_block_data(&self, height: Height, data: Bytes) -> Result<(), StoreError> {
         let start = Instant::now();
+        let data = self.seal(DECIDED_BLOCK_DATA_TABLE, &height, data.to_vec())?;
         let write_bytes = data.len() as u64;
 
         let tx = self.db.begin_write()?;
@@ -650,13 +1523,20 @@ impl Db {
             let mut table = tx.open_table(DECIDED_BLOCK_DATA_TABLE)?;
             // Only insert if no value exists at this key
             if table.get(&height)?.is_none() {
-                table.insert(height, data.to_vec())?;
+                let mut checksums = tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?;
+                checksums.insert(height, checksum(&data))?;
+                table.insert(height, data)?;
             }
         }
+        fail_point("insert_decided_block_data::commit");
         tx.commit()?;
 
         self.metrics.observe_write_time(start.elapsed());
+        self.metrics
+            .observe_table_write_time(DbTable::BlockData, start.elapsed());
         self.metrics.add_write_bytes(write_bytes);
+        self.metrics
+            .add_table_write_bytes(DbTable::BlockData, write_bytes);
 
         Ok(())
     }
@@ -672,23 +1552,39 @@ impl Db {
 
         let certificate = {
             let table = tx.open_table(CERTIFICATES_TABLE)?;
-            table.get(&height)?.and_then(|v| {
-                let bytes = v.value();
-                read_bytes += bytes.len() as u64;
-                decode_certificate(&bytes).ok()
-            })
+            let checksums = tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?;
+            table
+                .get(&height)?
+                .map(|v| {
+                    let bytes = v.value();
+                    read_bytes += bytes.len() as u64;
+                    self.metrics
+                        .add_table_read_bytes(DbTable::Certificates, bytes.len() as u64);
+                    self.verify_checksum(&checksums, CERTIFICATES_TABLE.name(), height, &bytes)?;
+                    self.unseal(CERTIFICATES_TABLE, &height, bytes)
+                })
+                .transpose()?
+                .and_then(|bytes| decode_certificate(&bytes).ok())
         };
 
         let header = {
             let table = tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?;
-            table.get(&height)?.map(|v| {
-                let bytes = v.value();
-                read_bytes += bytes.len() as u64;
-                Bytes::copy_from_slice(&bytes)
-            })
+            table
+                .get(&height)?
+                .map(|v| {
+                    let bytes = v.value();
+                    read_bytes += bytes.len() as u64;
+                    self.metrics
+                        .add_table_read_bytes(DbTable::BlockData, bytes.len() as u64);
+                    self.unseal(DECIDED_BLOCK_HEADERS_TABLE, &height, bytes)
+                })
+                .transpose()?
+                .map(Bytes::from)
         };
 
         self.metrics.observe_read_time(start.elapsed());
+        self.metrics
+            .observe_table_read_time(DbTable::Certificates, start.elapsed());
         self.metrics.add_read_bytes(read_bytes);
         self.metrics.add_key_read_bytes(size_of::<Height>() as u64);
 
@@ -704,41 +1600,63 @@ pub struct Store {
 impl Store {
     /// Opens a new store at the given path with the provided metrics.
     /// Called by the application when initializing the store.
-    pub async fn open(path: impl AsRef<Path>, metrics: DbMetrics) -> Result<Self, StoreError> {
+    /// Values are encrypted at rest when a cipher is given.
+    /// The proposals stored before being decided are bounded by `limits`.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        metrics: DbMetrics,
+        cipher: Option<StoreCipher>,
+        limits: StoreLimitsConfig,
+    ) -> Result<Self, StoreError> {
         let path = path.as_ref().to_owned();
 
         tokio::task::spawn_blocking(move || {
-            let db = Db::new(path, metrics)?;
+            let mut db = Db::new(path, metrics, cipher)?;
+            db.limits = limits;
             db.create_tables()?;
+            db.check_schema_version()?;
+            db.check_encryption()?;
+            db.reload_decided_heights()?;
+
+            let migrated = db.migrate_pending_proposal_parts()?;
+            if migrated > 0 {
+                info!(%migrated, "Migrated pending proposal parts from JSON to protobuf");
+            }
+            db.reload_usage()?;
+
             Ok(Self { db: Arc::new(db) })
         })
         .await?
     }
 
-    /// Returns the minimum height of decided values in the store.
-    /// Called by the application to determine the earliest available height.
-    pub async fn min_decided_value_height(&self) -> Option<Height> {
+    /// Pins the store to the given genesis hash.
+    /// Called by the application on startup, fails if the store was created with another genesis.
+    pub async fn check_genesis_hash(&self, genesis_hash: B256) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.min_decided_value_height())
-            .await
-            .ok()
-            .flatten()
+        tokio::task::spawn_blocking(move || db.write(move |db| db.check_genesis_hash(genesis_hash)))
+            .await?
     }
 
-    pub async fn min_unpruned_decided_value_height(&self) -> Option<Height> {
+    /// Returns the version of the layout of the tables the store was written with
+    pub async fn schema_version(&self) -> Result<u64, StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.min_unpruned_decided_value_height())
-            .await
-            .ok()
-            .flatten()
+        tokio::task::spawn_blocking(move || db.get_schema_version()).await?
     }
 
-    pub async fn max_decided_value_height(&self) -> Option<Height> {
+    /// Returns the hash of the genesis the store was created with, if already recorded
+    pub async fn genesis_hash(&self) -> Result<Option<B256>, StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.max_decided_value_height())
-            .await
-            .ok()
-            .flatten()
+        tokio::task::spawn_blocking(move || db.get_genesis_hash()).await?
+    }
+
+    /// Returns the range of decided heights in the store, without reading the store.
+    /// Called by the application to decide whether a height can be served to syncing peers.
+    pub fn decided_heights(&self) -> DecidedHeights {
+        self.db.decided_heights()
+    }
+
+    pub fn max_decided_value_height(&self) -> Option<Height> {
+        self.db.max_decided_value_height()
     }
 
     /// Retrieves a decided value for the given height.
@@ -767,7 +1685,9 @@ impl Store {
 
         let db = Arc::clone(&self.db);
         tokio::task::spawn_blocking(move || {
-            db.insert_decided_value(decided_value, block_header_bytes)
+            db.write(move |db| {
+                db.insert_decided_value(decided_value.clone(), block_header_bytes.clone())
+            })
         })
         .await?
     }
@@ -779,7 +1699,10 @@ impl Store {
         value: ProposedValue<EmeraldContext>,
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.insert_undecided_proposal(value)).await?
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_undecided_proposal(value.clone()))
+        })
+        .await?
     }
 
     /// Retrieves a specific undecided proposal by height, round, and value ID.
@@ -813,7 +1736,10 @@ impl Store {
         value: ProposalParts,
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.insert_pending_proposal_parts(value)).await?
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_pending_proposal_parts(value.clone()))
+        })
+        .await?
     }
 
     /// Retrieves all pendingproposal parts for a given height and round.
@@ -834,7 +1760,10 @@ impl Store {
         value: ProposalParts,
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.remove_pending_proposal_parts(value)).await?
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.remove_pending_proposal_parts(value.clone()))
+        })
+        .await?
     }
 
     /// Prunes the store by removing all undecided proposals and decided values up to the retain height.
@@ -850,16 +1779,155 @@ impl Store {
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
         tokio::task::spawn_blocking(move || {
-            db.prune(
-                num_certificates_to_retain,
-                num_temp_blocks_retained,
-                curr_height,
-                prune_certificates,
-            )
+            db.write(move |db| {
+                db.prune(
+                    num_certificates_to_retain,
+                    num_temp_blocks_retained,
+                    curr_height,
+                    prune_certificates,
+                )
+            })
+        })
+        .await?
+    }
+
+    pub async fn store_finalized_block(&self, finalized: FinalizedBlock) -> Result<(), StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_finalized_block(finalized))
         })
         .await?
     }
 
+    pub async fn get_finalized_block(&self) -> Result<Option<FinalizedBlock>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_finalized_block()).await?
+    }
+
+    /// Records the trusted block of the execution client snapshot the node starts from
+    pub async fn store_snapshot_anchor(&self, anchor: FinalizedBlock) -> Result<(), StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.write(move |db| db.insert_snapshot_anchor(anchor)))
+            .await?
+    }
+
+    /// Returns the trusted block of the execution client snapshot the node started from
+    pub async fn get_snapshot_anchor(&self) -> Result<Option<FinalizedBlock>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_snapshot_anchor()).await?
+    }
+
+    /// Records the epoch boundary at which the consensus parameters in force were read
+    pub async fn store_chain_params_epoch(&self, height: Height) -> Result<(), StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_chain_params_epoch(height))
+        })
+        .await?
+    }
+
+    pub async fn get_chain_params_epoch(&self) -> Result<Option<Height>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_chain_params_epoch()).await?
+    }
+
+    /// Stores the validator set of `height`
+    pub async fn store_validator_set(
+        &self,
+        height: Height,
+        validator_set: ValidatorSet,
+    ) -> Result<(), StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_validator_set(height, &validator_set))
+        })
+        .await?
+    }
+
+    /// Returns the validator set stored for `height`
+    pub async fn get_validator_set(
+        &self,
+        height: Height,
+    ) -> Result<Option<ValidatorSet>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_validator_set(height)).await?
+    }
+
+    /// Returns the validator set stored for the highest height at or below `height`,
+    /// with that height
+    pub async fn get_latest_validator_set_at_or_below(
+        &self,
+        height: Height,
+    ) -> Result<Option<(Height, ValidatorSet)>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_latest_validator_set_at_or_below(height)).await?
+    }
+
+    /// Records the effective configuration of this startup of the node
+    pub async fn store_config_snapshot(&self, snapshot: ConfigSnapshot) -> Result<(), StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_config_snapshot(&snapshot))
+        })
+        .await?
+    }
+
+    /// Returns the configurations recorded by the startups of the node, in order
+    pub async fn get_config_snapshots(&self) -> Result<Vec<ConfigSnapshot>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_config_snapshots()).await?
+    }
+
+    /// Removes all decided values, certificates and block data above the given height,
+    /// as well as all undecided proposals and pending proposal parts.
+    /// Called by `unsafe-reset` to roll the node back to an earlier height.
+    pub async fn truncate_above(&self, height: Height) -> Result<(), StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.write(move |db| db.truncate_above(height))).await?
+    }
+
+    /// Writes the whole store to a portable archive, returning the number of entries written.
+    /// Called by `emerald store export`, while the node is stopped.
+    pub async fn export_archive(&self, path: impl AsRef<Path>) -> Result<u64, StoreError> {
+        let db = Arc::clone(&self.db);
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || db.export_archive(&path)).await?
+    }
+
+    /// Restores the store from an archive written by [`Store::export_archive`],
+    /// returning the number of entries read. The store must not contain any decided value.
+    /// Called by `emerald store import`, while the node is stopped.
+    pub async fn import_archive(&self, path: impl AsRef<Path>) -> Result<u64, StoreError> {
+        let db = Arc::clone(&self.db);
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let entries = db.write(move |db| db.import_archive(&path))?;
+            db.reload_usage()?;
+            Ok(entries)
+        })
+        .await?
+    }
+
+    /// Compacts the store into a new file which then replaces it, returning what was done.
+    /// The store stays available for reads during the compaction, and for writes except
+    /// while the writes made during the copy are replayed on the new file.
+    /// Called through the admin API by `emerald store compact --online`, and by
+    /// `emerald store compact` while the node is stopped.
+    pub async fn compact(&self) -> Result<CompactionReport, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.compact()).await?
+    }
+
+    /// Verifies the consistency of the decided heights within `range`, all of them if none.
+    /// Called by `emerald verify-store`, and on the latest heights before the node starts.
+    pub async fn verify(
+        &self,
+        range: Option<RangeInclusive<Height>>,
+    ) -> Result<StoreVerification, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.verify(range)).await?
+    }
+
     pub async fn get_block_data(
         &self,
         height: Height,
@@ -879,7 +1947,9 @@ impl Store {
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
         tokio::task::spawn_blocking(move || {
-            db.insert_undecided_block_data(height, round, value_id, data)
+            db.write(move |db| {
+                db.insert_undecided_block_data(height, round, value_id, data.clone())
+            })
         })
         .await?
     }
@@ -890,7 +1960,10 @@ impl Store {
         data: Bytes,
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || db.insert_decided_block_data(height, data)).await?
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| db.insert_decided_block_data(height, data.clone()))
+        })
+        .await?
     }
 
     pub async fn get_certificate_and_header(
@@ -901,6 +1974,23 @@ impl Store {
         tokio::task::spawn_blocking(move || db.get_certificate_and_header(height)).await?
     }
 
+    /// Stores the certificate and block header of a height decided before the node started
+    /// from a snapshot of the execution client, without its value.
+    pub async fn store_certificate_and_header(
+        &self,
+        certificate: &CommitCertificate<EmeraldContext>,
+        block_header_bytes: Bytes,
+    ) -> Result<(), StoreError> {
+        let certificate = certificate.clone();
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            db.write(move |db| {
+                db.insert_certificate_and_header(&certificate, block_header_bytes.clone())
+            })
+        })
+        .await?
+    }
+
     pub async fn store_cumulative_metrics(
         &self,
         txs_count: u64,
@@ -909,7 +1999,9 @@ impl Store {
     ) -> Result<(), StoreError> {
         let db = Arc::clone(&self.db);
         tokio::task::spawn_blocking(move || {
-            db.insert_cumulative_metrics(txs_count, chain_bytes, elapsed_seconds)
+            db.write(move |db| {
+                db.insert_cumulative_metrics(txs_count, chain_bytes, elapsed_seconds)
+            })
         })
         .await?
     }
@@ -921,26 +2013,64 @@ impl Store {
 
     /// Retrieves a decided value encoded as a RawDecidedValue for the given height.
     /// Returns None if no decided value exists at the given height.
+    /// Called by the application to serve syncing peers, and to replay heights to the
+    /// execution client.
     pub async fn get_raw_decided_value(
         &self,
         height: Height,
-    ) -> eyre::Result<Option<RawDecidedValue<EmeraldContext>>> {
-        self.get_decided_value(height)
-            .await?
-            .map(|decided_value| {
-                Ok(RawDecidedValue {
-                    certificate: decided_value.certificate,
-                    value_bytes: ProtobufCodec.encode(&decided_value.value)?,
-                })
-            })
-            .transpose()
+    ) -> Result<Option<RawDecidedValue<EmeraldContext>>, StoreError> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.get_raw_decided_value(height)).await?
+    }
+
+    /// Streams the decided values of the heights in `range`, in increasing order of height,
+    /// encoded as by [`Store::get_raw_decided_value`].
+    ///
+    /// Values are read in the background, a few heights ahead of the consumer, and reading
+    /// stops at the first error or once the receiver is dropped. Heights without a decided
+    /// value, e.g. pruned ones, are skipped: consumers expecting every height of the range
+    /// must check the height of the certificates.
+    pub fn stream_raw_decided_values(
+        &self,
+        range: RangeInclusive<Height>,
+    ) -> mpsc::Receiver<Result<RawDecidedValue<EmeraldContext>, StoreError>> {
+        let (tx, rx) = mpsc::channel(RAW_DECIDED_VALUES_BUFFER);
+        let db = Arc::clone(&self.db);
+
+        tokio::task::spawn_blocking(move || {
+            let heights = db.decided_heights();
+            let (Some(earliest), Some(latest)) = (heights.earliest_unpruned, heights.latest) else {
+                return;
+            };
+
+            let start = (*range.start()).max(earliest).as_u64();
+            let end = (*range.end()).min(latest).as_u64();
+
+            for height in start..=end {
+                let result = match db.get_raw_decided_value(Height::new(height)) {
+                    Ok(Some(value)) => Ok(value),
+                    Ok(None) => continue,
+                    Err(e) => Err(e),
+                };
+
+                let failed = result.is_err();
+                if tx.blocking_send(result).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        rx
     }
 }
 
 #[cfg(test)]
 mod tests {
     use malachitebft_app_channel::app::types::core::{CommitCertificate, Validity};
-    use malachitebft_eth_types::Address;
+    use malachitebft_eth_types::secp256k1::{PrivateKey, Signature};
+    use malachitebft_eth_types::{
+        Address, ProposalData, ProposalFin, ProposalInit, ProposalPart, Validator,
+    };
 
     use super::*;
 
@@ -948,7 +2078,12 @@ mod tests {
     /// Returns both the Db and the TempDir (must be kept alive for the DB to remain valid).
     fn create_test_db(name: &str) -> (Db, tempfile::TempDir) {
         let dir = tempfile::tempdir().unwrap();
-        let db = Db::new(dir.path().join(format!("{name}.redb")), DbMetrics::new()).unwrap();
+        let db = Db::new(
+            dir.path().join(format!("{name}.redb")),
+            DbMetrics::new(),
+            None,
+        )
+        .unwrap();
         db.create_tables().unwrap();
         (db, dir)
     }
@@ -979,6 +2114,248 @@ mod tests {
         }
     }
 
+    /// Build complete proposal parts for a given height carrying `data_len` bytes of block data.
+    fn make_proposal_parts(height: u64, data_len: usize) -> ProposalParts {
+        let proposer = Address::new([height as u8; 20]);
+        let mut signature = [0u8; 64];
+        signature[0] = 1;
+        signature[32] = 1;
+
+        ProposalParts {
+            height: Height::new(height),
+            round: Round::new(0),
+            proposer,
+            parts: vec![
+                ProposalPart::Init(ProposalInit::new(
+                    Height::new(height),
+                    Round::new(0),
+                    Round::Nil,
+                    proposer,
+                )),
+                ProposalPart::Data(ProposalData::new(Bytes::from(vec![0xab; data_len]))),
+                ProposalPart::Fin(ProposalFin::new(Signature::from_slice(&signature).unwrap())),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_pending_proposal_parts_roundtrip() {
+        let (db, _dir) = create_test_db("pending_parts_roundtrip");
+
+        let parts = make_proposal_parts(1, 1024);
+        db.insert_pending_proposal_parts(parts.clone()).unwrap();
+
+        let stored = db
+            .get_pending_proposal_parts(Height::new(1), Round::new(0))
+            .unwrap();
+        assert_eq!(stored, vec![parts.clone()]);
+
+        db.remove_pending_proposal_parts(parts).unwrap();
+        assert!(db
+            .get_pending_proposal_parts(Height::new(1), Round::new(0))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_migrate_pending_proposal_parts_from_json() {
+        let (db, _dir) = create_test_db("pending_parts_migration");
+
+        // Write an entry in the legacy JSON format
+        let legacy = make_proposal_parts(2, 1024);
+        let key = (
+            legacy.height,
+            legacy.round,
+            Db::generate_value_id_from_parts(&legacy),
+        );
+        let tx = db.db.begin_write().unwrap();
+        {
+            let mut table = tx.open_table(PENDING_PROPOSAL_PARTS_TABLE).unwrap();
+            table
+                .insert(key, serde_json::to_vec(&legacy).unwrap())
+                .unwrap();
+        }
+        tx.commit().unwrap();
+
+        // And one already in the protobuf format, which must be left untouched
+        let current = make_proposal_parts(3, 1024);
+        db.insert_pending_proposal_parts(current.clone()).unwrap();
+
+        assert_eq!(db.migrate_pending_proposal_parts().unwrap(), 1);
+        assert_eq!(db.migrate_pending_proposal_parts().unwrap(), 0);
+
+        assert_eq!(
+            db.get_pending_proposal_parts(Height::new(2), Round::new(0))
+                .unwrap(),
+            vec![legacy]
+        );
+        assert_eq!(
+            db.get_pending_proposal_parts(Height::new(3), Round::new(0))
+                .unwrap(),
+            vec![current]
+        );
+    }
+
+    /// Compares the size of a multi-megabyte proposal in both formats
+    #[test]
+    fn test_pending_proposal_parts_encoding_size() {
+        let payload_size = 4 * 1024 * 1024;
+        let parts = make_proposal_parts(1, payload_size);
+
+        let json = serde_json::to_vec(&parts).unwrap();
+        let protobuf = parts.to_bytes().unwrap();
+        assert_eq!(ProposalParts::from_bytes(&protobuf).unwrap(), parts);
+
+        // JSON writes every byte as a decimal number followed by a comma, while protobuf
+        // only adds the framing of the parts to the payload
+        assert!(json.len() > 2 * payload_size);
+        assert!(protobuf.len() >= payload_size);
+        assert!(protobuf.len() < payload_size + 1024);
+    }
+
+    /// Compares the time to encode and decode a multi-megabyte proposal in both formats.
+    /// Run with `cargo test --release -p emerald bench_pending_proposal_parts -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_pending_proposal_parts_encoding() {
+        const ITERATIONS: u32 = 10;
+        let parts = make_proposal_parts(1, 4 * 1024 * 1024);
+
+        let start = std::time::Instant::now();
+        for _ in 0..ITERATIONS {
+            let json = serde_json::to_vec(&parts).unwrap();
+            let decoded: ProposalParts = serde_json::from_slice(&json).unwrap();
+            assert_eq!(decoded.parts.len(), parts.parts.len());
+        }
+        let json = start.elapsed() / ITERATIONS;
+
+        let start = std::time::Instant::now();
+        for _ in 0..ITERATIONS {
+            let protobuf = parts.to_bytes().unwrap();
+            let decoded = ProposalParts::from_bytes(&protobuf).unwrap();
+            assert_eq!(decoded.parts.len(), parts.parts.len());
+        }
+        let protobuf = start.elapsed() / ITERATIONS;
+
+        println!("JSON: {json:?}, protobuf: {protobuf:?} per round-trip");
+        assert!(protobuf < json);
+    }
+
+    #[test]
+    fn test_encrypted_store_requires_its_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("encrypted.redb");
+        let open = |cipher: Option<StoreCipher>| {
+            let db = Db::new(&path, DbMetrics::new(), cipher)?;
+            db.create_tables()?;
+            db.check_encryption()?;
+            Ok::<_, StoreError>(db)
+        };
+
+        {
+            let db = open(Some(StoreCipher::new([1; 32]))).unwrap();
+            let (decided, header) = make_decided_value(1);
+            db.insert_decided_value(decided.clone(), header.clone())
+                .unwrap();
+
+            let stored = db.get_decided_value(Height::new(1)).unwrap().unwrap();
+            assert_eq!(stored.value, decided.value);
+            assert_eq!(
+                db.get_certificate_and_header(Height::new(1))
+                    .unwrap()
+                    .map(|(_, h)| h),
+                Some(header)
+            );
+        }
+
+        assert!(matches!(open(None), Err(StoreError::Encryption(_))));
+        assert!(matches!(
+            open(Some(StoreCipher::new([2; 32]))),
+            Err(StoreError::Encryption(_))
+        ));
+        assert!(open(Some(StoreCipher::new([1; 32]))).is_ok());
+    }
+
+    #[test]
+    fn test_swapped_encrypted_values_fail_to_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(
+            dir.path().join("encrypted.redb"),
+            DbMetrics::new(),
+            Some(StoreCipher::new([1; 32])),
+        )
+        .unwrap();
+        db.create_tables().unwrap();
+
+        for height in [1, 2] {
+            let (decided, header) = make_decided_value(height);
+            db.insert_decided_value(decided, header).unwrap();
+        }
+
+        // Someone with write access to the database swaps the values of two heights
+        let tx = db.db.begin_write().unwrap();
+        {
+            let mut values = tx.open_table(DECIDED_VALUES_TABLE).unwrap();
+            let first = values.get(&Height::new(1)).unwrap().unwrap().value();
+            let second = values.get(&Height::new(2)).unwrap().unwrap().value();
+            values.insert(Height::new(1), second).unwrap();
+            values.insert(Height::new(2), first).unwrap();
+        }
+        tx.commit().unwrap();
+
+        assert!(matches!(
+            db.get_decided_value(Height::new(1)),
+            Err(StoreError::Encryption(_))
+        ));
+        assert!(matches!(
+            db.get_decided_value(Height::new(2)),
+            Err(StoreError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_cannot_enable_encryption_on_existing_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plaintext.redb");
+
+        {
+            let db = Db::new(&path, DbMetrics::new(), None).unwrap();
+            db.create_tables().unwrap();
+            db.check_encryption().unwrap();
+            let (decided, header) = make_decided_value(1);
+            db.insert_decided_value(decided, header).unwrap();
+        }
+
+        let db = Db::new(&path, DbMetrics::new(), Some(StoreCipher::new([1; 32]))).unwrap();
+        db.create_tables().unwrap();
+        assert!(matches!(
+            db.check_encryption(),
+            Err(StoreError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_cannot_enable_encryption_with_pending_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plaintext.redb");
+
+        // No decided value yet, but unencrypted proposal parts of a future height
+        {
+            let db = Db::new(&path, DbMetrics::new(), None).unwrap();
+            db.create_tables().unwrap();
+            db.check_encryption().unwrap();
+            db.insert_pending_proposal_parts(make_proposal_parts(2, 32))
+                .unwrap();
+        }
+
+        let db = Db::new(&path, DbMetrics::new(), Some(StoreCipher::new([1; 32]))).unwrap();
+        db.create_tables().unwrap();
+        assert!(matches!(
+            db.check_encryption(),
+            Err(StoreError::Encryption(_))
+        ));
+    }
+
     #[test]
     fn test_prune() {
         let (db, _dir) = create_test_db("prune_test");
@@ -1133,5 +2510,545 @@ mod tests {
                 .is_empty(),
             "undecided proposals at height 1 should be pruned"
         );
+
+        // === Decided heights follow the pruned tables ===
+        assert_eq!(
+            db.decided_heights(),
+            DecidedHeights {
+                earliest: Some(Height::new(2)),
+                earliest_unpruned: Some(Height::new(3)),
+                latest: Some(Height::new(4)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_backfilled_certificates() {
+        let (db, _dir) = create_test_db("backfill_test");
+
+        // Backfilled below the height the node started from
+        for height in [5, 4] {
+            let (decided_value, header) = make_decided_value(height);
+            db.insert_certificate_and_header(&decided_value.certificate, header)
+                .unwrap();
+        }
+        assert_eq!(
+            db.decided_heights(),
+            DecidedHeights {
+                earliest: Some(Height::new(4)),
+                earliest_unpruned: None,
+                latest: Some(Height::new(5)),
+            }
+        );
+        assert!(db
+            .get_certificate_and_header(Height::new(4))
+            .unwrap()
+            .is_some());
+        assert!(db.get_decided_value(Height::new(4)).unwrap().is_none());
+
+        let (decided_value, header) = make_decided_value(6);
+        db.insert_decided_value(decided_value, header).unwrap();
+        assert_eq!(
+            db.decided_heights(),
+            DecidedHeights {
+                earliest: Some(Height::new(4)),
+                earliest_unpruned: Some(Height::new(6)),
+                latest: Some(Height::new(6)),
+            }
+        );
+
+        // The index is rebuilt the same way from the tables
+        db.reload_decided_heights().unwrap();
+        assert_eq!(db.decided_heights().earliest_unpruned, Some(Height::new(6)));
+    }
+
+    #[test]
+    fn test_truncate_above() {
+        let (db, _dir) = create_test_db("truncate_test");
+
+        for h in 1..=4u64 {
+            let (decided, header) = make_decided_value(h);
+            db.insert_decided_value(decided, header).unwrap();
+            db.insert_decided_block_data(Height::new(h), Bytes::from(vec![h as u8; 30]))
+                .unwrap();
+            db.insert_undecided_proposal(make_proposed_value(h))
+                .unwrap();
+            db.insert_pending_proposal_parts(make_proposal_parts(h, 16))
+                .unwrap();
+        }
+
+        db.truncate_above(Height::new(2)).unwrap();
+
+        assert_eq!(db.max_decided_value_height(), Some(Height::new(2)));
+        for h in 1..=2u64 {
+            assert!(db.get_decided_value(Height::new(h)).unwrap().is_some());
+            assert!(db
+                .get_block_data(Height::new(h), Round::new(0), ValueId::new(0))
+                .unwrap()
+                .is_some());
+        }
+        for h in 3..=4u64 {
+            assert!(db.get_decided_value(Height::new(h)).unwrap().is_none());
+            assert!(db
+                .get_certificate_and_header(Height::new(h))
+                .unwrap()
+                .is_none());
+            assert!(db
+                .get_block_data(Height::new(h), Round::new(0), ValueId::new(0))
+                .unwrap()
+                .is_none());
+        }
+        for h in 1..=4u64 {
+            assert!(db
+                .get_undecided_proposals(Height::new(h), Round::new(0))
+                .unwrap()
+                .is_empty());
+            assert!(db
+                .get_pending_proposal_parts(Height::new(h), Round::new(0))
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_raw_decided_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(
+            dir.path().join("store.db"),
+            DbMetrics::new(),
+            None,
+            StoreLimitsConfig::default(),
+        )
+        .await
+        .unwrap();
+        for height in 1..=5 {
+            let (decided_value, header) = make_decided_value(height);
+            store
+                .db
+                .insert_decided_value(decided_value, header)
+                .unwrap();
+        }
+
+        let collect = |range: RangeInclusive<Height>| {
+            let mut values = store.stream_raw_decided_values(range);
+            async move {
+                let mut heights = Vec::new();
+                while let Some(value) = values.recv().await {
+                    heights.push(value.unwrap().certificate.height.as_u64());
+                }
+                heights
+            }
+        };
+
+        assert_eq!(
+            collect(Height::new(2)..=Height::new(4)).await,
+            vec![2, 3, 4]
+        );
+        // The range is clamped to the decided heights
+        assert_eq!(
+            collect(Height::new(0)..=Height::new(10)).await,
+            vec![1, 2, 3, 4, 5]
+        );
+
+        let raw = store.get_raw_decided_value(Height::new(3)).await.unwrap();
+        let (expected, _) = make_decided_value(3);
+        assert_eq!(
+            raw.unwrap().value_bytes,
+            ProtobufCodec::default().encode(&expected.value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let (db, dir) = create_test_db("archive_source");
+        db.check_genesis_hash(B256::repeat_byte(1)).unwrap();
+        for height in 1..=3 {
+            let (decided_value, header) = make_decided_value(height);
+            db.insert_decided_value(decided_value, header).unwrap();
+        }
+        db.insert_undecided_proposal(make_proposed_value(4))
+            .unwrap();
+        db.insert_cumulative_metrics(10, 20, 30).unwrap();
+
+        let archive = dir.path().join("store.archive");
+        let exported = db.export_archive(&archive).unwrap();
+
+        let (imported_db, _imported_dir) = create_test_db("archive_target");
+        assert_eq!(imported_db.import_archive(&archive).unwrap(), exported);
+
+        for height in 1..=3 {
+            let (expected, header) = make_decided_value(height);
+            let decided = imported_db
+                .get_decided_value(Height::new(height))
+                .unwrap()
+                .unwrap();
+            assert_eq!(decided.value, expected.value);
+            let (_, imported_header) = imported_db
+                .get_certificate_and_header(Height::new(height))
+                .unwrap()
+                .unwrap();
+            assert_eq!(imported_header, header);
+        }
+        assert!(imported_db
+            .get_undecided_proposal(
+                Height::new(4),
+                Round::new(0),
+                make_proposed_value(4).value.id()
+            )
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            imported_db.get_cumulative_metrics().unwrap(),
+            Some((10, 20, 30))
+        );
+        imported_db
+            .check_genesis_hash(B256::repeat_byte(1))
+            .unwrap();
+
+        // Importing again would overwrite the decided values
+        assert!(matches!(
+            imported_db.import_archive(&archive),
+            Err(StoreError::Archive(_))
+        ));
+    }
+
+    #[test]
+    fn test_corruption_is_detected() {
+        let (db, _dir) = create_test_db("checksum_test");
+        let height = Height::new(1);
+        let (decided_value, header) = make_decided_value(1);
+        db.insert_decided_value(decided_value, header).unwrap();
+        db.insert_decided_block_data(height, Bytes::from(vec![1; 32]))
+            .unwrap();
+
+        assert!(db.get_decided_value(height).unwrap().is_some());
+        assert!(db
+            .get_block_data(height, Round::new(0), ValueId::new(0))
+            .unwrap()
+            .is_some());
+
+        // Flip a byte of the stored values, as silent disk corruption would
+        let tx = db.db.begin_write().unwrap();
+        for table in [CERTIFICATES_TABLE, DECIDED_BLOCK_DATA_TABLE] {
+            let mut table = tx.open_table(table).unwrap();
+            let mut bytes = table.get(&height).unwrap().unwrap().value();
+            bytes[0] ^= 0xff;
+            table.insert(height, bytes).unwrap();
+        }
+        tx.commit().unwrap();
+
+        assert!(matches!(
+            db.get_decided_value(height),
+            Err(StoreError::Corrupted { table, .. }) if table == CERTIFICATES_TABLE.name()
+        ));
+        assert!(matches!(
+            db.get_certificate_and_header(height),
+            Err(StoreError::Corrupted { table, .. }) if table == CERTIFICATES_TABLE.name()
+        ));
+        assert!(matches!(
+            db.get_block_data(height, Round::new(0), ValueId::new(0)),
+            Err(StoreError::Corrupted { table, .. }) if table == DECIDED_BLOCK_DATA_TABLE.name()
+        ));
+
+        // Values written before checksums were introduced are not checked
+        let tx = db.db.begin_write().unwrap();
+        tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)
+            .unwrap()
+            .remove(&height)
+            .unwrap();
+        tx.commit().unwrap();
+        assert!(db
+            .get_block_data(height, Round::new(0), ValueId::new(0))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_genesis_hash_is_pinned() {
+        let (db, _dir) = create_test_db("genesis_hash_test");
+
+        let genesis_hash = B256::repeat_byte(1);
+        db.check_genesis_hash(genesis_hash).unwrap();
+        db.check_genesis_hash(genesis_hash).unwrap();
+
+        assert!(matches!(
+            db.check_genesis_hash(B256::repeat_byte(2)),
+            Err(StoreError::GenesisMismatch { stored, .. }) if stored == genesis_hash
+        ));
+    }
+
+    #[test]
+    fn test_newer_schema_version_is_refused() {
+        let (db, _dir) = create_test_db("schema_version_test");
+
+        assert_eq!(db.get_schema_version().unwrap(), 1);
+        db.check_schema_version().unwrap();
+        assert_eq!(db.get_schema_version().unwrap(), STORE_SCHEMA_VERSION);
+
+        let tx = db.db.begin_write().unwrap();
+        tx.open_table(STORE_METADATA_TABLE)
+            .unwrap()
+            .insert(
+                SCHEMA_VERSION_KEY,
+                (STORE_SCHEMA_VERSION + 1).to_be_bytes().to_vec(),
+            )
+            .unwrap();
+        tx.commit().unwrap();
+
+        assert!(matches!(
+            db.check_schema_version(),
+            Err(StoreError::UnsupportedSchema { stored, .. }) if stored == STORE_SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_finalized_block_is_persisted() {
+        let (db, _dir) = create_test_db("finalized_block_test");
+        db.check_genesis_hash(B256::repeat_byte(1)).unwrap();
+        assert_eq!(db.get_finalized_block().unwrap(), None);
+
+        let finalized = FinalizedBlock {
+            height: Height::new(7),
+            block_hash: B256::repeat_byte(7),
+        };
+        db.insert_finalized_block(finalized).unwrap();
+        assert_eq!(db.get_finalized_block().unwrap(), Some(finalized));
+
+        db.truncate_above(Height::new(5)).unwrap();
+        assert_eq!(db.get_finalized_block().unwrap(), None);
+    }
+
+    #[test]
+    fn test_snapshot_anchor_is_persisted() {
+        let (db, _dir) = create_test_db("snapshot_anchor_test");
+        db.check_genesis_hash(B256::repeat_byte(1)).unwrap();
+        assert_eq!(db.get_snapshot_anchor().unwrap(), None);
+
+        let anchor = FinalizedBlock {
+            height: Height::new(7),
+            block_hash: B256::repeat_byte(7),
+        };
+        db.insert_snapshot_anchor(anchor).unwrap();
+        assert_eq!(db.get_snapshot_anchor().unwrap(), Some(anchor));
+
+        db.truncate_above(Height::new(7)).unwrap();
+        assert_eq!(db.get_snapshot_anchor().unwrap(), Some(anchor));
+
+        db.truncate_above(Height::new(6)).unwrap();
+        assert_eq!(db.get_snapshot_anchor().unwrap(), None);
+    }
+
+    #[test]
+    fn test_chain_params_epoch_is_persisted() {
+        let (db, _dir) = create_test_db("chain_params_epoch_test");
+        db.check_genesis_hash(B256::repeat_byte(1)).unwrap();
+        assert_eq!(db.get_chain_params_epoch().unwrap(), None);
+
+        db.insert_chain_params_epoch(Height::new(10)).unwrap();
+        db.insert_chain_params_epoch(Height::new(20)).unwrap();
+        assert_eq!(db.get_chain_params_epoch().unwrap(), Some(Height::new(20)));
+
+        db.truncate_above(Height::new(20)).unwrap();
+        assert_eq!(db.get_chain_params_epoch().unwrap(), Some(Height::new(20)));
+
+        db.truncate_above(Height::new(19)).unwrap();
+        assert_eq!(db.get_chain_params_epoch().unwrap(), None);
+    }
+
+    #[test]
+    fn test_validator_sets_are_persisted_per_height() {
+        let (db, _dir) = create_test_db("validator_set_test");
+        db.check_genesis_hash(B256::repeat_byte(1)).unwrap();
+        assert_eq!(db.get_validator_set(Height::new(8)).unwrap(), None);
+        assert_eq!(
+            db.get_latest_validator_set_at_or_below(Height::new(8))
+                .unwrap(),
+            None
+        );
+
+        let validator_set = |power| {
+            let private_key = PrivateKey::from_slice(&[1; 32]).unwrap();
+            ValidatorSet::new([Validator::new(private_key.public_key(), power)])
+        };
+        for height in 6..=8 {
+            db.insert_validator_set(Height::new(height), &validator_set(height))
+                .unwrap();
+        }
+        assert_eq!(
+            db.get_validator_set(Height::new(7)).unwrap(),
+            Some(validator_set(7))
+        );
+        assert_eq!(
+            db.get_latest_validator_set_at_or_below(Height::new(20))
+                .unwrap(),
+            Some((Height::new(8), validator_set(8)))
+        );
+
+        // Kept for the next height after the truncation
+        db.truncate_above(Height::new(6)).unwrap();
+        assert_eq!(
+            db.get_validator_set(Height::new(7)).unwrap(),
+            Some(validator_set(7))
+        );
+        assert_eq!(db.get_validator_set(Height::new(8)).unwrap(), None);
+
+        // Pruned with the certificates
+        db.prune(1, 1, Height::new(8), true).unwrap();
+        assert_eq!(db.get_validator_set(Height::new(6)).unwrap(), None);
+        assert_eq!(
+            db.get_latest_validator_set_at_or_below(Height::new(8))
+                .unwrap(),
+            Some((Height::new(7), validator_set(7)))
+        );
+    }
+
+    #[test]
+    fn test_config_snapshots_are_kept_in_order() {
+        let (db, _dir) = create_test_db("config_snapshots_test");
+        assert_eq!(db.get_config_snapshots().unwrap(), vec![]);
+
+        let snapshots: Vec<_> = [1, 1, 12]
+            .into_iter()
+            .map(|start_height| ConfigSnapshot {
+                start_height,
+                started_at: 1_700_000_000 + start_height,
+                version: "0.1.0".to_string(),
+                genesis_hash: B256::repeat_byte(1),
+                hash: B256::repeat_byte(start_height as u8),
+                emerald: serde_json::json!({ "min_block_time": "1s" }),
+                consensus: serde_json::json!({ "moniker": "node-0" }),
+            })
+            .collect();
+        for snapshot in &snapshots {
+            db.insert_config_snapshot(snapshot).unwrap();
+        }
+        assert_eq!(db.get_config_snapshots().unwrap(), snapshots);
+
+        // Kept across a truncation, as the history of the startups
+        db.truncate_above(Height::new(5)).unwrap();
+        assert_eq!(db.get_config_snapshots().unwrap(), snapshots);
+    }
+
+    /// Crash-consistency tests, aborting the process at the failpoints of the store
+    #[cfg(feature = "failpoints")]
+    mod crash_recovery {
+        use super::*;
+
+        /// Failpoints hit while a height is decided, and whether the height is decided
+        /// after a crash at each of them
+        const CRASH_POINTS: &[(&str, bool)] = &[
+            ("insert_decided_value::certificate", false),
+            ("insert_decided_value::header", false),
+            ("insert_decided_value::commit", false),
+            ("insert_decided_value::after_commit", true),
+            ("insert_decided_block_data::commit", true),
+            ("prune::commit", true),
+        ];
+
+        /// Env var pointing the crashing child process of [`test_crash_recovery`] to its store
+        const CRASH_TEST_DB_ENV: &str = "EMERALD_CRASH_TEST_DB";
+
+        /// Opens the store like the node does on startup
+        fn reopen_db(path: &Path) -> Db {
+            let db = Db::new(path, DbMetrics::new(), None).unwrap();
+            db.create_tables().unwrap();
+            db.reload_decided_heights().unwrap();
+            db
+        }
+
+        /// Makes the writes of the application when a proposal is received and then decided
+        fn decide_height(db: &Db, height: u64) {
+            let (decided, header) = make_decided_value(height);
+            let data = Bytes::from(vec![height as u8; 30]);
+            let certificate = decided.certificate.clone();
+
+            db.insert_undecided_block_data(
+                certificate.height,
+                certificate.round,
+                certificate.value_id,
+                data.clone(),
+            )
+            .unwrap();
+            db.insert_decided_value(decided, header).unwrap();
+            db.insert_decided_block_data(certificate.height, data)
+                .unwrap();
+            db.prune(u64::MAX, 10, certificate.height, false).unwrap();
+        }
+
+        /// Checks that the store holds what `bootstrap` reads to restart from the latest
+        /// decided height: its value, certificate, header and block data, and the values of
+        /// all the heights which may have to be replayed to the execution client.
+        fn assert_recoverable(db: &Db) -> Height {
+            let heights = db.decided_heights();
+            let latest = heights.latest.expect("store must contain a decided height");
+
+            let decided = db
+                .get_decided_value(latest)
+                .unwrap()
+                .expect("latest height must have a decided value");
+            assert!(db.get_certificate_and_header(latest).unwrap().is_some());
+            assert!(db
+                .get_block_data(
+                    latest,
+                    decided.certificate.round,
+                    decided.certificate.value_id
+                )
+                .unwrap()
+                .is_some());
+
+            let earliest = heights.earliest_unpruned.expect("store must not be pruned");
+            for height in earliest.as_u64()..=latest.as_u64() {
+                assert!(db.get_decided_value(Height::new(height)).unwrap().is_some());
+            }
+
+            latest
+        }
+
+        /// Aborts a child process at each failpoint while it decides height 3,
+        /// then restarts from its store and checks that the node can recover.
+        #[test]
+        fn test_crash_recovery() {
+            // In the child process, decide the next height until a failpoint aborts it
+            if let Ok(path) = std::env::var(CRASH_TEST_DB_ENV) {
+                let db = reopen_db(Path::new(&path));
+                decide_height(&db, 3);
+                return;
+            }
+
+            for (failpoint, decided) in CRASH_POINTS {
+                let dir = tempfile::tempdir().unwrap();
+                let path = dir.path().join("store.redb");
+
+                {
+                    let db = reopen_db(&path);
+                    decide_height(&db, 1);
+                    decide_height(&db, 2);
+                }
+
+                let status = std::process::Command::new(std::env::current_exe().unwrap())
+                    .args([
+                        "--exact",
+                        "store::tests::crash_recovery::test_crash_recovery",
+                        "--nocapture",
+                    ])
+                    .env(CRASH_TEST_DB_ENV, &path)
+                    .env("EMERALD_FAILPOINTS", format!("{failpoint}=abort"))
+                    .status()
+                    .unwrap();
+                assert!(!status.success(), "{failpoint} was not hit");
+
+                let db = reopen_db(&path);
+                let expected = if *decided { 3 } else { 2 };
+                assert_eq!(
+                    assert_recoverable(&db),
+                    Height::new(expected),
+                    "unexpected latest height after a crash at {failpoint}"
+                );
+
+                // The node carries on deciding heights after the restart
+                decide_height(&db, expected + 1);
+                assert_eq!(assert_recoverable(&db), Height::new(expected + 1));
+            }
+        }
     }
 }
@@ -0,0 +1,263 @@
+//! Portable archive of the whole store, for backups and for moving a node to another machine.
+//!
+//! The archive does not depend on the file format of redb. It is a zstd-compressed stream
+//! made of a header followed by one section per table, all integers being big-endian:
+//!
+//! ```text
+//! header:  magic "EMRLDARC" | version: u32
+//! section: name_len: u16 | name | entry_count: u64 | entries
+//! entry:   key_len: u32 | key | value_len: u32 | value
+//! end:     name_len = 0
+//! ```
+//!
+//! Keys and values are written as encoded in the store, so the values of an encrypted
+//! store stay encrypted and the archive can only be imported with the same key.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition, TableHandle};
+
+use super::{
+    Db, StoreError, CERTIFICATES_TABLE, CERTIFICATE_CHECKSUMS_TABLE, CONFIG_SNAPSHOTS_TABLE,
+    DECIDED_BLOCK_DATA_CHECKSUMS_TABLE, DECIDED_BLOCK_DATA_TABLE, DECIDED_BLOCK_HEADERS_TABLE,
+    DECIDED_VALUES_TABLE, ENCRYPTION_CHECK_KEY, ENCRYPTION_CHECK_VALUE,
+    PENDING_PROPOSAL_PARTS_TABLE, PERSISTENT_METRICS_TABLE, STORE_METADATA_TABLE,
+    UNDECIDED_BLOCK_DATA_TABLE, UNDECIDED_PROPOSALS_TABLE, VALIDATOR_SETS_TABLE,
+};
+
+const MAGIC: &[u8; 8] = b"EMRLDARC";
+const VERSION: u32 = 1;
+
+const ZSTD_LEVEL: i32 = 3;
+
+impl Db {
+    /// Writes all tables to the archive at `path`, returning the number of entries written.
+    pub(super) fn export_archive(&self, path: &Path) -> Result<u64, StoreError> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut writer = zstd::Encoder::new(file, ZSTD_LEVEL)?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_be_bytes())?;
+
+        let tx = self.db.begin_read()?;
+        let mut entries = 0;
+
+        entries += export_table(&tx, DECIDED_VALUES_TABLE, &mut writer)?;
+        entries += export_table(&tx, CERTIFICATES_TABLE, &mut writer)?;
+        entries += export_table(&tx, UNDECIDED_PROPOSALS_TABLE, &mut writer)?;
+        entries += export_table(&tx, DECIDED_BLOCK_DATA_TABLE, &mut writer)?;
+        entries += export_table(&tx, UNDECIDED_BLOCK_DATA_TABLE, &mut writer)?;
+        entries += export_table(&tx, DECIDED_BLOCK_HEADERS_TABLE, &mut writer)?;
+        entries += export_table(&tx, CERTIFICATE_CHECKSUMS_TABLE, &mut writer)?;
+        entries += export_table(&tx, DECIDED_BLOCK_DATA_CHECKSUMS_TABLE, &mut writer)?;
+        entries += export_table(&tx, PERSISTENT_METRICS_TABLE, &mut writer)?;
+        entries += export_table(&tx, PENDING_PROPOSAL_PARTS_TABLE, &mut writer)?;
+        entries += export_table(&tx, STORE_METADATA_TABLE, &mut writer)?;
+        entries += export_table(&tx, CONFIG_SNAPSHOTS_TABLE, &mut writer)?;
+        entries += export_table(&tx, VALIDATOR_SETS_TABLE, &mut writer)?;
+
+        // End of the archive
+        writer.write_all(&0u16.to_be_bytes())?;
+        writer.finish()?.flush()?;
+
+        Ok(entries)
+    }
+
+    /// Restores the tables from the archive at `path`, returning the number of entries read.
+    ///
+    /// Refuses to import into a store which already contains decided values.
+    pub(super) fn import_archive(&self, path: &Path) -> Result<u64, StoreError> {
+        let mut reader = zstd::Decoder::new(BufReader::new(File::open(path)?))?;
+
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(StoreError::Archive(
+                "not an Emerald store archive".to_string(),
+            ));
+        }
+
+        let version = u32::from_be_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(StoreError::Archive(format!(
+                "unsupported archive version {version}, expected {VERSION}"
+            )));
+        }
+
+        let tx = self.db.begin_write()?;
+        if !tx.open_table(DECIDED_VALUES_TABLE)?.is_empty()? {
+            return Err(StoreError::Archive(
+                "the store already contains decided values".to_string(),
+            ));
+        }
+
+        // Replaced by the encryption check value of the archive, if any
+        tx.open_table(STORE_METADATA_TABLE)?
+            .remove(ENCRYPTION_CHECK_KEY)?;
+
+        let mut entries = 0;
+        loop {
+            let name_len = u16::from_be_bytes(read_array(&mut reader)?);
+            if name_len == 0 {
+                break;
+            }
+
+            let name = String::from_utf8(read_bytes(&mut reader, name_len.into())?)
+                .map_err(|_| StoreError::Archive("invalid table name".to_string()))?;
+
+            entries += match name.as_str() {
+                n if n == DECIDED_VALUES_TABLE.name() => {
+                    import_table(&tx, DECIDED_VALUES_TABLE, &mut reader)?
+                }
+                n if n == CERTIFICATES_TABLE.name() => {
+                    import_table(&tx, CERTIFICATES_TABLE, &mut reader)?
+                }
+                n if n == UNDECIDED_PROPOSALS_TABLE.name() => {
+                    import_table(&tx, UNDECIDED_PROPOSALS_TABLE, &mut reader)?
+                }
+                n if n == DECIDED_BLOCK_DATA_TABLE.name() => {
+                    import_table(&tx, DECIDED_BLOCK_DATA_TABLE, &mut reader)?
+                }
+                n if n == UNDECIDED_BLOCK_DATA_TABLE.name() => {
+                    import_table(&tx, UNDECIDED_BLOCK_DATA_TABLE, &mut reader)?
+                }
+                n if n == DECIDED_BLOCK_HEADERS_TABLE.name() => {
+                    import_table(&tx, DECIDED_BLOCK_HEADERS_TABLE, &mut reader)?
+                }
+                n if n == CERTIFICATE_CHECKSUMS_TABLE.name() => {
+                    import_table(&tx, CERTIFICATE_CHECKSUMS_TABLE, &mut reader)?
+                }
+                n if n == DECIDED_BLOCK_DATA_CHECKSUMS_TABLE.name() => {
+                    import_table(&tx, DECIDED_BLOCK_DATA_CHECKSUMS_TABLE, &mut reader)?
+                }
+                n if n == PERSISTENT_METRICS_TABLE.name() => {
+                    import_table(&tx, PERSISTENT_METRICS_TABLE, &mut reader)?
+                }
+                n if n == PENDING_PROPOSAL_PARTS_TABLE.name() => {
+                    import_table(&tx, PENDING_PROPOSAL_PARTS_TABLE, &mut reader)?
+                }
+                n if n == STORE_METADATA_TABLE.name() => {
+                    import_table(&tx, STORE_METADATA_TABLE, &mut reader)?
+                }
+                n if n == CONFIG_SNAPSHOTS_TABLE.name() => {
+                    import_table(&tx, CONFIG_SNAPSHOTS_TABLE, &mut reader)?
+                }
+                n if n == VALIDATOR_SETS_TABLE.name() => {
+                    import_table(&tx, VALIDATOR_SETS_TABLE, &mut reader)?
+                }
+                _ => return Err(StoreError::Archive(format!("unknown table `{name}`"))),
+            };
+        }
+
+        // Values are imported sealed, so the store must use the key of the exported store
+        {
+            let table = tx.open_table(STORE_METADATA_TABLE)?;
+            let check = table.get(ENCRYPTION_CHECK_KEY)?.map(|v| v.value());
+
+            match (&self.cipher, check) {
+                (Some(cipher), Some(sealed)) => {
+                    let plaintext = cipher.open(
+                        STORE_METADATA_TABLE.name(),
+                        ENCRYPTION_CHECK_KEY.as_bytes(),
+                        &sealed,
+                    )?;
+                    if plaintext != ENCRYPTION_CHECK_VALUE {
+                        return Err(StoreError::Encryption(
+                            "Store encryption check value does not match".to_string(),
+                        ));
+                    }
+                }
+                (None, None) => {}
+                (Some(_), None) => {
+                    return Err(StoreError::Archive(
+                        "the archive is not encrypted, but the store is".to_string(),
+                    ));
+                }
+                (None, Some(_)) => {
+                    return Err(StoreError::Archive(
+                        "the archive is encrypted, but no `store_encryption_key` is configured"
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        tx.commit()?;
+        self.reload_decided_heights()?;
+
+        Ok(entries)
+    }
+}
+
+fn export_table<K, V>(
+    tx: &redb::ReadTransaction,
+    definition: TableDefinition<'_, K, V>,
+    writer: &mut impl Write,
+) -> Result<u64, StoreError>
+where
+    K: redb::Key + 'static,
+    V: redb::Value + 'static,
+{
+    let table = tx.open_table(definition)?;
+    let name = definition.name().as_bytes();
+
+    writer.write_all(&(name.len() as u16).to_be_bytes())?;
+    writer.write_all(name)?;
+    writer.write_all(&table.len()?.to_be_bytes())?;
+
+    let mut entries = 0;
+    for entry in table.iter()? {
+        let (key, value) = entry?;
+        write_bytes(writer, K::as_bytes(&key.value()).as_ref())?;
+        write_bytes(writer, V::as_bytes(&value.value()).as_ref())?;
+        entries += 1;
+    }
+
+    Ok(entries)
+}
+
+fn import_table<K, V>(
+    tx: &redb::WriteTransaction,
+    definition: TableDefinition<'_, K, V>,
+    reader: &mut impl Read,
+) -> Result<u64, StoreError>
+where
+    K: redb::Key + 'static,
+    V: redb::Value + 'static,
+{
+    let mut table = tx.open_table(definition)?;
+    let entries = u64::from_be_bytes(read_array(reader)?);
+
+    for _ in 0..entries {
+        let key_len = u32::from_be_bytes(read_array(reader)?);
+        let key = read_bytes(reader, key_len as usize)?;
+        let value_len = u32::from_be_bytes(read_array(reader)?);
+        let value = read_bytes(reader, value_len as usize)?;
+
+        table.insert(K::from_bytes(&key), V::from_bytes(&value))?;
+    }
+
+    Ok(entries)
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result<(), StoreError> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| StoreError::Archive("entry too large".to_string()))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], StoreError> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_bytes(reader: &mut impl Read, len: usize) -> Result<Vec<u8>, StoreError> {
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,164 @@
+//! Encryption at rest of the values held in the store.
+//!
+//! Values are sealed with XChaCha20-Poly1305 before being written, using a fresh random
+//! nonce per write which is stored in front of the ciphertext. The name of the table
+//! and the encoded key of the record a value belongs to are authenticated alongside it,
+//! so that sealed values cannot be moved between tables or records, e.g. swapped between
+//! two heights, without being detected. Keys are left in the clear, as they are needed
+//! to iterate and prune tables by height.
+
+use core::mem::size_of;
+
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload};
+use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce};
+use color_eyre::eyre::{self, eyre, Context};
+use malachitebft_eth_cli::config::SecretSource;
+
+use super::StoreError;
+
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct StoreCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl StoreCipher {
+    pub fn new(key: [u8; KEY_LEN]) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// Loads the hex-encoded key from the given source.
+    pub fn from_key_source(source: &SecretSource) -> eyre::Result<Self> {
+        let encoded = source.read("store key")?;
+        let bytes = hex::decode(encoded.strip_prefix("0x").unwrap_or(&encoded))
+            .wrap_err("Store key is not valid hex")?;
+
+        let key = <[u8; KEY_LEN]>::try_from(bytes.as_slice()).map_err(|_| {
+            eyre!(
+                "Store key must be {KEY_LEN} bytes long, got {} bytes",
+                bytes.len()
+            )
+        })?;
+
+        Ok(Self::new(key))
+    }
+
+    /// Encrypts a value, binding it to the table and the encoded key of the record it is
+    /// written to.
+    pub fn seal(&self, table: &str, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, StoreError> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = associated_data(table, key);
+        let payload = Payload {
+            msg: plaintext,
+            aad: &aad,
+        };
+
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| StoreError::Encryption(format!("Failed to encrypt value for {table}")))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts a value previously sealed for the given table and record key.
+    pub fn open(&self, table: &str, key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, StoreError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(StoreError::Encryption(format!(
+                "Encrypted value in {table} is too short"
+            )));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let aad = associated_data(table, key);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &aad,
+        };
+
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                StoreError::Encryption(format!(
+                    "Failed to decrypt value in {table}, wrong key or corrupted data"
+                ))
+            })
+    }
+}
+
+/// Table name, prefixed by its length so that it cannot run into the key, followed by the
+/// key of the record
+fn associated_data(table: &str, key: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(size_of::<u64>() + table.len() + key.len());
+    aad.extend_from_slice(&(table.len() as u64).to_be_bytes());
+    aad.extend_from_slice(table.as_bytes());
+    aad.extend_from_slice(key);
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEIGHT_1: [u8; 8] = 1u64.to_be_bytes();
+    const HEIGHT_2: [u8; 8] = 2u64.to_be_bytes();
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let cipher = StoreCipher::new([7; KEY_LEN]);
+        let sealed = cipher.seal("decided_values", &HEIGHT_1, b"value").unwrap();
+
+        assert_ne!(&sealed[NONCE_LEN..], b"value");
+        assert_eq!(
+            cipher.open("decided_values", &HEIGHT_1, &sealed).unwrap(),
+            b"value"
+        );
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_key_table_or_record() {
+        let cipher = StoreCipher::new([7; KEY_LEN]);
+        let sealed = cipher.seal("decided_values", &HEIGHT_1, b"value").unwrap();
+
+        assert!(StoreCipher::new([8; KEY_LEN])
+            .open("decided_values", &HEIGHT_1, &sealed)
+            .is_err());
+        assert!(cipher.open("certificates", &HEIGHT_1, &sealed).is_err());
+        assert!(cipher.open("decided_values", &HEIGHT_2, &sealed).is_err());
+        assert!(cipher
+            .open("decided_values", &HEIGHT_1, &sealed[..4])
+            .is_err());
+
+        // The table name does not run into the key
+        let sealed = cipher.seal("a", b"bc", b"value").unwrap();
+        assert!(cipher.open("ab", b"c", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_key_from_env() {
+        let var = "EMERALD_TEST_STORE_KEY";
+        std::env::set_var(var, format!("0x{}\n", hex::encode([7; KEY_LEN])));
+
+        let cipher = StoreCipher::from_key_source(&SecretSource::Env {
+            var: var.to_string(),
+        })
+        .unwrap();
+        let sealed = StoreCipher::new([7; KEY_LEN])
+            .seal("t", b"k", b"v")
+            .unwrap();
+        assert_eq!(cipher.open("t", b"k", &sealed).unwrap(), b"v");
+
+        std::env::set_var(var, "abcd");
+        assert!(StoreCipher::from_key_source(&SecretSource::Env {
+            var: var.to_string()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Compaction of the store while the node keeps running.
+//!
+//! Rather than compacting the database in place, which blocks every read and write until
+//! it is done, the tables are copied from a snapshot to a new file in the background.
+//! The writes made during the copy are recorded, and replayed on the new file once the
+//! copy is done. The new file then replaces the store, the writes being blocked only
+//! while they are replayed. A compaction interrupted by a crash leaves the store as it
+//! was, with a leftover file removed by the next compaction.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use malachitebft_eth_cli::cmd::store::CompactionReport;
+use redb::{ReadableTable, TableDefinition};
+use tracing::info;
+
+use super::{
+    Db, StoreError, CERTIFICATES_TABLE, CERTIFICATE_CHECKSUMS_TABLE, CONFIG_SNAPSHOTS_TABLE,
+    DECIDED_BLOCK_DATA_CHECKSUMS_TABLE, DECIDED_BLOCK_DATA_TABLE, DECIDED_BLOCK_HEADERS_TABLE,
+    DECIDED_VALUES_TABLE, PENDING_PROPOSAL_PARTS_TABLE, PERSISTENT_METRICS_TABLE,
+    STORE_METADATA_TABLE, UNDECIDED_BLOCK_DATA_TABLE, UNDECIDED_PROPOSALS_TABLE,
+};
+
+/// Number of entries copied per transaction, bounding the memory used by the copy
+const COPY_BATCH: usize = 10_000;
+
+/// Write to the store, replayed on the compacted database
+type Write = Box<dyn Fn(&Db) -> Result<(), StoreError> + Send>;
+
+/// Database of the store, replaced by its compacted copy once a compaction is done
+pub(super) struct Database {
+    path: PathBuf,
+    current: RwLock<Arc<redb::Database>>,
+}
+
+impl Database {
+    pub(super) fn create(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_owned();
+        let db = redb::Database::create(&path)?;
+
+        Ok(Self {
+            path,
+            current: RwLock::new(Arc::new(db)),
+        })
+    }
+
+    fn current(&self) -> Arc<redb::Database> {
+        Arc::clone(&self.current.read().expect("store database lock poisoned"))
+    }
+
+    pub(super) fn begin_read(&self) -> Result<redb::ReadTransaction, redb::TransactionError> {
+        self.current().begin_read()
+    }
+
+    pub(super) fn begin_write(&self) -> Result<redb::WriteTransaction, redb::TransactionError> {
+        self.current().begin_write()
+    }
+}
+
+/// Writes made since the start of the compaction in progress, if any
+#[derive(Default)]
+pub(super) struct Journal(Mutex<Option<Vec<Write>>>);
+
+impl Db {
+    /// Runs `write` on the store. While a compaction is in progress, the write is recorded
+    /// to be replayed on the compacted database.
+    ///
+    /// The writes are serialized by the journal, so that none is missed between the
+    /// snapshot of the compaction and the switch to the compacted database.
+    pub(super) fn write<T, F>(&self, write: F) -> Result<T, StoreError>
+    where
+        T: 'static,
+        F: Fn(&Db) -> Result<T, StoreError> + Send + 'static,
+    {
+        let mut journal = self.journal.0.lock().expect("store journal lock poisoned");
+
+        // A failed write leaves the store as it was, and is not replayed
+        let result = write(self)?;
+        if let Some(writes) = journal.as_mut() {
+            writes.push(Box::new(move |db| write(db).map(|_| ())));
+        }
+
+        Ok(result)
+    }
+
+    /// Compacts the store into a new file, which then replaces the store
+    pub(super) fn compact(&self) -> Result<CompactionReport, StoreError> {
+        let path = self.db.path.clone();
+        let target = path.with_extension("db.compact");
+
+        // Left over by an interrupted compaction
+        if let Err(e) = fs::remove_file(&target) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+
+        let snapshot = {
+            let mut journal = self.journal.0.lock().expect("store journal lock poisoned");
+            if journal.is_some() {
+                return Err(StoreError::Compaction(
+                    "a compaction is already in progress".to_string(),
+                ));
+            }
+
+            let snapshot = self.db.begin_read()?;
+            *journal = Some(Vec::new());
+            snapshot
+        };
+
+        let result = self.compact_into(snapshot, &path, &target);
+        if result.is_err() {
+            *self.journal.0.lock().expect("store journal lock poisoned") = None;
+            let _ = fs::remove_file(&target);
+        }
+
+        result
+    }
+
+    fn compact_into(
+        &self,
+        snapshot: redb::ReadTransaction,
+        path: &Path,
+        target: &Path,
+    ) -> Result<CompactionReport, StoreError> {
+        let size_before = fs::metadata(path)?.len();
+
+        let mut replica = Db::new(target, self.metrics.clone(), self.cipher.clone())?;
+        replica.limits = self.limits.clone();
+
+        let mut entries = 0;
+        entries += copy_table(&snapshot, &replica.db, DECIDED_VALUES_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, CERTIFICATES_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, UNDECIDED_PROPOSALS_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, DECIDED_BLOCK_DATA_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, UNDECIDED_BLOCK_DATA_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, DECIDED_BLOCK_HEADERS_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, CERTIFICATE_CHECKSUMS_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, PERSISTENT_METRICS_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, PENDING_PROPOSAL_PARTS_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, STORE_METADATA_TABLE)?;
+        entries += copy_table(&snapshot, &replica.db, CONFIG_SNAPSHOTS_TABLE)?;
+        drop(snapshot);
+
+        // Makes the batches of the copy durable
+        replica.db.begin_write()?.commit()?;
+        replica.reload_decided_heights()?;
+        replica.reload_usage()?;
+
+        // Writes are blocked from now on, until the store is switched to the new file
+        let mut journal = self.journal.0.lock().expect("store journal lock poisoned");
+        let writes = journal.take().unwrap_or_default();
+        info!(%entries, writes = writes.len(), "Copied the store, replaying the writes made during the copy");
+
+        for write in &writes {
+            write(&replica)?;
+        }
+
+        fs::rename(target, path)?;
+        *self
+            .db
+            .current
+            .write()
+            .expect("store database lock poisoned") = replica.db.current();
+        drop(journal);
+
+        Ok(CompactionReport {
+            entries,
+            replayed_writes: writes.len() as u64,
+            size_before,
+            size_after: fs::metadata(path)?.len(),
+        })
+    }
+}
+
+/// Copies the table of `definition` from the `snapshot` to the `target` database,
+/// returning the number of entries copied.
+fn copy_table<K, V>(
+    snapshot: &redb::ReadTransaction,
+    target: &Database,
+    definition: TableDefinition<'_, K, V>,
+) -> Result<u64, StoreError>
+where
+    K: redb::Key + 'static,
+    V: redb::Value + 'static,
+{
+    let table = snapshot.open_table(definition)?;
+    let mut entries = table.iter()?.peekable();
+    let mut copied = 0;
+
+    loop {
+        let mut tx = target.begin_write()?;
+        // Made durable at the end of the copy
+        tx.set_durability(redb::Durability::None);
+
+        {
+            // Created even if the table is empty
+            let mut target_table = tx.open_table(definition)?;
+            for entry in entries.by_ref().take(COPY_BATCH) {
+                let (key, value) = entry?;
+                target_table.insert(key.value(), value.value())?;
+                copied += 1;
+            }
+        }
+
+        tx.commit()?;
+
+        if entries.peek().is_none() {
+            return Ok(copied);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use malachitebft_app_channel::app::types::core::{CommitCertificate, Round};
+    use malachitebft_eth_types::{Height, Value};
+
+    use super::*;
+    use crate::metrics::DbMetrics;
+    use crate::store::DecidedValue;
+
+    fn insert_decided_value(db: &Db, height: u64) -> Result<(), StoreError> {
+        let value = Value::new(Bytes::from(vec![height as u8; 100_000]));
+        let certificate = CommitCertificate {
+            height: Height::new(height),
+            round: Round::new(0),
+            value_id: value.id(),
+            commit_signatures: vec![],
+        };
+        db.insert_decided_value(
+            DecidedValue { value, certificate },
+            Bytes::from(vec![height as u8; 20]),
+        )
+    }
+
+    #[test]
+    fn test_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.db");
+        let db = Db::new(&path, DbMetrics::new(), None).unwrap();
+        db.create_tables().unwrap();
+
+        for height in 1..=200 {
+            db.write(move |db| insert_decided_value(db, height))
+                .unwrap();
+        }
+        db.write(|db| db.prune(10, 10, Height::new(200), true))
+            .unwrap();
+
+        // Height 201 is decided during the copy
+        let snapshot = db.db.begin_read().unwrap();
+        *db.journal.0.lock().unwrap() = Some(Vec::new());
+        db.write(|db| insert_decided_value(db, 201)).unwrap();
+
+        let target = path.with_extension("db.compact");
+        let report = db.compact_into(snapshot, &path, &target).unwrap();
+        assert_eq!(report.replayed_writes, 1);
+        assert!(report.size_after < report.size_before);
+        assert!(!target.exists());
+        assert!(db.journal.0.lock().unwrap().is_none());
+
+        // The store is switched to the compacted file, with the writes made during the copy
+        assert!(db.get_decided_value(Height::new(100)).unwrap().is_none());
+        assert!(db.get_decided_value(Height::new(201)).unwrap().is_some());
+        db.write(|db| insert_decided_value(db, 202)).unwrap();
+
+        // A single compaction at a time
+        *db.journal.0.lock().unwrap() = Some(Vec::new());
+        assert!(matches!(db.compact(), Err(StoreError::Compaction(_))));
+        *db.journal.0.lock().unwrap() = None;
+
+        drop(db);
+        let db = Db::new(&path, DbMetrics::new(), None).unwrap();
+        for height in [195, 201, 202] {
+            assert!(db.get_decided_value(Height::new(height)).unwrap().is_some());
+        }
+    }
+}
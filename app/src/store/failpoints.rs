@@ -0,0 +1,60 @@
+//! Failpoints in the write paths of the store, to test that the node recovers from
+//! a crash at any point of the writes made when a value is decided.
+//!
+//! Failpoints are compiled only with the `failpoints` feature, and armed with the
+//! `EMERALD_FAILPOINTS` env var, as a `;`-separated list of `name=action`, where the
+//! action is `abort` to abort the process, or `panic`. They are no-ops otherwise.
+//!
+//! For instance, `EMERALD_FAILPOINTS=insert_decided_value::certificate=abort` crashes the
+//! node after writing the decided value of a height, before writing its certificate.
+
+#[cfg(feature = "failpoints")]
+mod imp {
+    use std::collections::BTreeMap;
+    use std::sync::LazyLock;
+
+    /// Env var listing the armed failpoints
+    const FAILPOINTS_ENV: &str = "EMERALD_FAILPOINTS";
+
+    #[derive(Copy, Clone, Debug)]
+    enum Action {
+        Abort,
+        Panic,
+    }
+
+    static FAILPOINTS: LazyLock<BTreeMap<String, Action>> = LazyLock::new(|| {
+        std::env::var(FAILPOINTS_ENV)
+            .unwrap_or_default()
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (name, action) = entry.split_once('=').unwrap_or((entry, "abort"));
+                let action = match action.trim() {
+                    "abort" => Action::Abort,
+                    "panic" => Action::Panic,
+                    other => panic!("Unknown action `{other}` for failpoint `{name}`"),
+                };
+                (name.trim().to_string(), action)
+            })
+            .collect()
+    });
+
+    pub fn hit(name: &str) {
+        match FAILPOINTS.get(name) {
+            Some(Action::Abort) => {
+                tracing::error!("Failpoint `{name}` hit, aborting");
+                std::process::abort();
+            }
+            Some(Action::Panic) => panic!("Failpoint `{name}` hit"),
+            None => {}
+        }
+    }
+}
+
+/// Triggers the failpoint `name`, if armed
+#[cfg_attr(not(feature = "failpoints"), allow(unused_variables))]
+#[inline]
+pub(super) fn fail_point(name: &str) {
+    #[cfg(feature = "failpoints")]
+    imp::hit(name);
+}
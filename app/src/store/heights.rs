@@ -0,0 +1,42 @@
+//! In-memory index of the decided heights held in the store.
+//!
+//! Answering whether a decided value can be served to a syncing peer would otherwise
+//! take several reads of the store per request. The index is updated by the store
+//! after each write touching the decided values, so it is always consistent with them.
+
+use malachitebft_eth_types::Height;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecidedHeights {
+    /// Lowest height with a certificate, the earliest height which can be served
+    pub earliest: Option<Height>,
+    /// Lowest height with a decided value, below which values are rebuilt from the
+    /// block header and the execution client
+    pub earliest_unpruned: Option<Height>,
+    /// Highest decided height
+    pub latest: Option<Height>,
+}
+
+impl DecidedHeights {
+    /// Records a value decided at `height`, with its certificate.
+    pub fn insert(&mut self, height: Height) {
+        self.earliest = Some(self.earliest.map_or(height, |h| h.min(height)));
+        self.earliest_unpruned = Some(self.earliest_unpruned.map_or(height, |h| h.min(height)));
+        self.latest = Some(self.latest.map_or(height, |h| h.max(height)));
+    }
+
+    /// Records the certificate and block header of `height` without its value, as for a
+    /// pruned height, e.g. when backfilled after starting from a snapshot of the
+    /// execution client.
+    pub fn insert_pruned(&mut self, height: Height) {
+        self.earliest = Some(self.earliest.map_or(height, |h| h.min(height)));
+        self.latest = Some(self.latest.map_or(height, |h| h.max(height)));
+    }
+
+    /// Returns whether the value decided at `height` is available, possibly pruned.
+    pub fn contains(&self, height: Height) -> bool {
+        self.earliest
+            .zip(self.latest)
+            .is_some_and(|(earliest, latest)| (earliest..=latest).contains(&height))
+    }
+}
@@ -0,0 +1,231 @@
+//! Limits on the growth of the tables holding the proposals which are not decided yet.
+//!
+//! The parts of the proposals of future heights are stored as pending without being
+//! validated, and a byzantine proposer can stream many valid proposals for its round, so
+//! both tables are bounded, in number of proposals and in bytes:
+//! - the hard limits, per height for the pending proposals and per round for the undecided
+//!   ones, reject the proposals above them
+//! - the soft limits of the whole tables evict entries to make room for a new one: the
+//!   pending proposals of the furthest heights first, as they are the least likely to be
+//!   needed soon, and the undecided proposals of the oldest heights first, which are left
+//!   over until pruned. A proposal which would itself be evicted first is rejected.
+//!
+//! The sizes of the entries of both tables are kept in memory, so that the limits are
+//! enforced without reading the tables.
+
+use core::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use malachitebft_app_channel::app::types::core::Round;
+use malachitebft_eth_cli::config::TableLimit;
+use malachitebft_eth_types::{Height, ValueId};
+
+pub type EntryKey = (Height, Round, ValueId);
+
+/// Sizes in bytes of the entries of a bounded table, by key
+pub type TableUsage = BTreeMap<EntryKey, u64>;
+
+/// Sizes of the entries of the bounded tables
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub pending: TableUsage,
+    pub undecided: TableUsage,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Policy {
+    /// Hard limit per height, the furthest heights being evicted first
+    Pending,
+    /// Hard limit per round, the oldest heights being evicted first
+    Undecided,
+}
+
+/// Outcome of the insertion of an entry in a bounded table
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    /// The entry is inserted, after evicting these entries
+    Admitted { evicted: Vec<EntryKey> },
+    /// The entry is not inserted
+    Rejected,
+}
+
+fn fits(limit: &TableLimit, entries: usize, bytes: u64, size: u64) -> bool {
+    entries < limit.entries && bytes.saturating_add(size) <= limit.bytes
+}
+
+fn total<'a>(sizes: impl Iterator<Item = &'a u64>) -> (usize, u64) {
+    sizes.fold((0, 0), |(count, bytes), size| (count + 1, bytes + size))
+}
+
+/// Decides whether an entry of `size` bytes is inserted at `key` in a table,
+/// and which entries are evicted to make room for it.
+pub fn admit(
+    usage: &TableUsage,
+    key: EntryKey,
+    size: u64,
+    policy: Policy,
+    hard: &TableLimit,
+    soft: &TableLimit,
+) -> Admission {
+    let (height, round, _) = key;
+
+    let (group_entries, group_bytes) = total(
+        usage
+            .iter()
+            .filter(|((h, r, _), _)| match policy {
+                Policy::Pending => *h == height,
+                Policy::Undecided => *h == height && *r == round,
+            })
+            .map(|(_, size)| size),
+    );
+    if !fits(hard, group_entries, group_bytes, size) {
+        return Admission::Rejected;
+    }
+
+    let mut candidates = usage
+        .iter()
+        .filter(|((h, _, _), _)| match policy {
+            Policy::Pending => *h > height,
+            Policy::Undecided => *h < height,
+        })
+        .collect::<Vec<_>>();
+    match policy {
+        Policy::Pending => candidates.sort_by_key(|((h, r, _), _)| Reverse((*h, *r))),
+        Policy::Undecided => candidates.sort_by_key(|((h, r, _), _)| (*h, *r)),
+    }
+
+    let (mut count, mut bytes) = total(usage.values());
+    let mut candidates = candidates.into_iter();
+    let mut evicted = Vec::new();
+    while !fits(soft, count, bytes, size) {
+        let Some((candidate, candidate_size)) = candidates.next() else {
+            return Admission::Rejected;
+        };
+
+        count -= 1;
+        bytes -= candidate_size;
+        evicted.push(*candidate);
+    }
+
+    Admission::Admitted { evicted }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(height: u64, round: u32, id: u64) -> EntryKey {
+        (Height::new(height), Round::new(round), ValueId::new(id))
+    }
+
+    fn usage(entries: &[(EntryKey, u64)]) -> TableUsage {
+        entries.iter().copied().collect()
+    }
+
+    const UNLIMITED: TableLimit = TableLimit {
+        entries: usize::MAX,
+        bytes: u64::MAX,
+    };
+
+    #[test]
+    fn test_hard_limit_rejects() {
+        let hard = TableLimit {
+            entries: 2,
+            bytes: 100,
+        };
+        let usage = usage(&[(key(5, 0, 1), 10), (key(5, 1, 2), 10), (key(6, 0, 3), 10)]);
+
+        // Two proposals are already stored for height 5
+        assert_eq!(
+            admit(&usage, key(5, 2, 4), 10, Policy::Pending, &hard, &UNLIMITED),
+            Admission::Rejected
+        );
+        // But only one for its round
+        assert_eq!(
+            admit(
+                &usage,
+                key(5, 1, 4),
+                10,
+                Policy::Undecided,
+                &hard,
+                &UNLIMITED
+            ),
+            Admission::Admitted { evicted: vec![] }
+        );
+        // Above the size of the height
+        assert_eq!(
+            admit(&usage, key(6, 0, 4), 91, Policy::Pending, &hard, &UNLIMITED),
+            Admission::Rejected
+        );
+    }
+
+    #[test]
+    fn test_soft_limit_evicts_furthest_pending() {
+        let soft = TableLimit {
+            entries: 3,
+            bytes: 100,
+        };
+        let usage = usage(&[(key(5, 0, 1), 10), (key(9, 0, 2), 10), (key(7, 0, 3), 10)]);
+
+        assert_eq!(
+            admit(&usage, key(6, 0, 4), 10, Policy::Pending, &UNLIMITED, &soft),
+            Admission::Admitted {
+                evicted: vec![key(9, 0, 2)]
+            }
+        );
+        // Making room in bytes
+        assert_eq!(
+            admit(&usage, key(6, 0, 4), 81, Policy::Pending, &UNLIMITED, &soft),
+            Admission::Admitted {
+                evicted: vec![key(9, 0, 2), key(7, 0, 3)]
+            }
+        );
+        // The new proposal is the furthest
+        assert_eq!(
+            admit(
+                &usage,
+                key(10, 0, 4),
+                10,
+                Policy::Pending,
+                &UNLIMITED,
+                &soft
+            ),
+            Admission::Rejected
+        );
+    }
+
+    #[test]
+    fn test_soft_limit_evicts_oldest_undecided() {
+        let soft = TableLimit {
+            entries: 3,
+            bytes: 100,
+        };
+        let usage = usage(&[(key(7, 1, 1), 10), (key(6, 0, 2), 10), (key(7, 0, 3), 10)]);
+
+        assert_eq!(
+            admit(
+                &usage,
+                key(7, 2, 4),
+                10,
+                Policy::Undecided,
+                &UNLIMITED,
+                &soft
+            ),
+            Admission::Admitted {
+                evicted: vec![key(6, 0, 2)]
+            }
+        );
+        // The rounds of the same height are never evicted
+        assert_eq!(
+            admit(
+                &usage,
+                key(7, 2, 4),
+                81,
+                Policy::Undecided,
+                &UNLIMITED,
+                &soft
+            ),
+            Admission::Rejected
+        );
+    }
+}
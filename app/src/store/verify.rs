@@ -0,0 +1,379 @@
+//! Verification of the integrity of the decided heights of the store.
+//!
+//! Checksums catch the corruption of a single entry when it is read, but not a height whose
+//! value, certificate and block header disagree, e.g. after a partial restore, nor a chain
+//! of headers broken by a bad rollback. `emerald verify-store` walks the decided heights in
+//! a single read transaction and reports the first broken one, and the node checks its
+//! latest heights the same way before starting.
+
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use alloy_rpc_types_engine::ExecutionPayloadV3;
+use malachitebft_app_channel::app::types::core::CommitCertificate;
+use malachitebft_eth_cli::cmd::verify_store::{BrokenHeight, StoreVerification};
+use malachitebft_eth_types::{BlockHash, EmeraldContext, Height, Value};
+use malachitebft_proto::Protobuf;
+use redb::{ReadOnlyTable, ReadableTable, TableHandle};
+use ssz::Decode;
+
+use super::keys::HeightKey;
+use super::{
+    checksum, decode_certificate, Db, StoreError, CERTIFICATES_TABLE, CERTIFICATE_CHECKSUMS_TABLE,
+    DECIDED_BLOCK_DATA_CHECKSUMS_TABLE, DECIDED_BLOCK_DATA_TABLE, DECIDED_BLOCK_HEADERS_TABLE,
+    DECIDED_VALUES_TABLE,
+};
+
+/// Reason why a decided height is broken
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Inconsistency {
+    Missing(&'static str),
+    Corrupted(&'static str),
+    Undecodable {
+        table: &'static str,
+        error: String,
+    },
+    CertificateHeight(Height),
+    ValueId,
+    BlockHash {
+        value: BlockHash,
+        header: BlockHash,
+    },
+    HashLink {
+        parent: BlockHash,
+        previous: BlockHash,
+    },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(table) => write!(f, "missing entry in `{table}`"),
+            Self::Corrupted(table) => write!(f, "checksum mismatch in `{table}`"),
+            Self::Undecodable { table, error } => {
+                write!(f, "failed to decode the entry in `{table}`: {error}")
+            }
+            Self::CertificateHeight(height) => {
+                write!(f, "the certificate is for height {height}")
+            }
+            Self::ValueId => write!(f, "the value does not match the certificate"),
+            Self::BlockHash { value, header } => write!(
+                f,
+                "the value is block {value}, but the block header is block {header}"
+            ),
+            Self::HashLink { parent, previous } => write!(
+                f,
+                "the block header has parent {parent}, but the previous height is block {previous}"
+            ),
+        }
+    }
+}
+
+/// Tables read by the verification
+struct Tables {
+    certificates: ReadOnlyTable<HeightKey, Vec<u8>>,
+    certificate_checksums: ReadOnlyTable<HeightKey, Vec<u8>>,
+    values: ReadOnlyTable<HeightKey, Vec<u8>>,
+    headers: ReadOnlyTable<HeightKey, Vec<u8>>,
+    block_data: ReadOnlyTable<HeightKey, Vec<u8>>,
+    block_data_checksums: ReadOnlyTable<HeightKey, Vec<u8>>,
+}
+
+impl Db {
+    /// Verifies the decided heights within `range`, all of them if none
+    pub(super) fn verify(
+        &self,
+        range: Option<RangeInclusive<Height>>,
+    ) -> Result<StoreVerification, StoreError> {
+        let heights = self.decided_heights();
+        let (Some(earliest), Some(latest)) = (heights.earliest, heights.latest) else {
+            return Ok(StoreVerification {
+                from_height: None,
+                to_height: None,
+                checked: 0,
+                broken: None,
+            });
+        };
+
+        let (from, to) = match range {
+            Some(range) => ((*range.start()).max(earliest), (*range.end()).min(latest)),
+            None => (earliest, latest),
+        };
+        // Values below it were pruned, only their certificate and header are kept
+        let earliest_unpruned = heights.earliest_unpruned.unwrap_or(latest.increment());
+
+        let tx = self.db.begin_read()?;
+        let tables = Tables {
+            certificates: tx.open_table(CERTIFICATES_TABLE)?,
+            certificate_checksums: tx.open_table(CERTIFICATE_CHECKSUMS_TABLE)?,
+            values: tx.open_table(DECIDED_VALUES_TABLE)?,
+            headers: tx.open_table(DECIDED_BLOCK_HEADERS_TABLE)?,
+            block_data: tx.open_table(DECIDED_BLOCK_DATA_TABLE)?,
+            block_data_checksums: tx.open_table(DECIDED_BLOCK_DATA_CHECKSUMS_TABLE)?,
+        };
+
+        // The first header checked links to the one below the range, if any
+        let mut previous = match from.decrement().filter(|height| *height >= earliest) {
+            Some(height) => self.read_header(&tables, height)?.ok().map(|h| h.0),
+            None => None,
+        };
+
+        let mut verification = StoreVerification {
+            from_height: Some(from.as_u64()),
+            to_height: Some(to.as_u64()),
+            checked: 0,
+            broken: None,
+        };
+
+        for height in from.as_u64()..=to.as_u64() {
+            let height = Height::new(height);
+            let checked =
+                self.verify_height(&tables, height, height >= earliest_unpruned, previous)?;
+
+            match checked {
+                Ok(block_hash) => {
+                    previous = Some(block_hash);
+                    verification.checked += 1;
+                }
+                Err(inconsistency) => {
+                    verification.broken = Some(BrokenHeight {
+                        height: height.as_u64(),
+                        reason: inconsistency.to_string(),
+                    });
+                    break;
+                }
+            }
+        }
+
+        Ok(verification)
+    }
+
+    /// Verifies a decided height, returning the hash of its block if it is consistent
+    fn verify_height(
+        &self,
+        tables: &Tables,
+        height: Height,
+        unpruned: bool,
+        previous: Option<BlockHash>,
+    ) -> Result<Result<BlockHash, Inconsistency>, StoreError> {
+        let certificate = match self.read_certificate(tables, height)? {
+            Ok(certificate) => certificate,
+            Err(inconsistency) => return Ok(Err(inconsistency)),
+        };
+        if certificate.height != height {
+            return Ok(Err(Inconsistency::CertificateHeight(certificate.height)));
+        }
+
+        let (block_hash, parent_hash) = match self.read_header(tables, height)? {
+            Ok(hashes) => hashes,
+            Err(inconsistency) => return Ok(Err(inconsistency)),
+        };
+        if let Some(previous) = previous.filter(|previous| *previous != parent_hash) {
+            return Ok(Err(Inconsistency::HashLink {
+                parent: parent_hash,
+                previous,
+            }));
+        }
+
+        if unpruned {
+            let value = match self.read_value(tables, height)? {
+                Ok(value) => value,
+                Err(inconsistency) => return Ok(Err(inconsistency)),
+            };
+            if value.id() != certificate.value_id || Value::new(value.extensions.clone()) != value {
+                return Ok(Err(Inconsistency::ValueId));
+            }
+
+            let table = DECIDED_VALUES_TABLE.name();
+            let payload = match ExecutionPayloadV3::from_ssz_bytes(&value.extensions) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    return Ok(Err(Inconsistency::Undecodable {
+                        table,
+                        error: format!("{e:?}"),
+                    }))
+                }
+            };
+            let value_hash = payload.payload_inner.payload_inner.block_hash;
+            if value_hash != block_hash {
+                return Ok(Err(Inconsistency::BlockHash {
+                    value: value_hash,
+                    header: block_hash,
+                }));
+            }
+        }
+
+        // The block data is only kept until the value is decided and pruned
+        if let Some(bytes) = tables.block_data.get(&height)? {
+            match tables.block_data_checksums.get(&height)? {
+                Some(expected) if expected.value() != checksum(&bytes.value()) => {
+                    return Ok(Err(Inconsistency::Corrupted(
+                        DECIDED_BLOCK_DATA_TABLE.name(),
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Ok(block_hash))
+    }
+
+    fn read_certificate(
+        &self,
+        tables: &Tables,
+        height: Height,
+    ) -> Result<Result<CommitCertificate<EmeraldContext>, Inconsistency>, StoreError> {
+        let table = CERTIFICATES_TABLE.name();
+        let Some(bytes) = tables.certificates.get(&height)? else {
+            return Ok(Err(Inconsistency::Missing(table)));
+        };
+        let bytes = bytes.value();
+
+        match tables.certificate_checksums.get(&height)? {
+            Some(expected) if expected.value() != checksum(&bytes) => {
+                return Ok(Err(Inconsistency::Corrupted(table)));
+            }
+            _ => {}
+        }
+
+        Ok(self
+            .unseal(CERTIFICATES_TABLE, &height, bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| decode_certificate(&bytes).map_err(|e| e.to_string()))
+            .map_err(|error| Inconsistency::Undecodable { table, error }))
+    }
+
+    /// Hashes of the block of `height` and of its parent, read from its header
+    fn read_header(
+        &self,
+        tables: &Tables,
+        height: Height,
+    ) -> Result<Result<(BlockHash, BlockHash), Inconsistency>, StoreError> {
+        let table = DECIDED_BLOCK_HEADERS_TABLE.name();
+        let Some(bytes) = tables.headers.get(&height)? else {
+            return Ok(Err(Inconsistency::Missing(table)));
+        };
+
+        Ok(self
+            .unseal(DECIDED_BLOCK_HEADERS_TABLE, &height, bytes.value())
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                ExecutionPayloadV3::from_ssz_bytes(&bytes).map_err(|e| format!("{e:?}"))
+            })
+            .map(|header| {
+                let header = header.payload_inner.payload_inner;
+                (header.block_hash, header.parent_hash)
+            })
+            .map_err(|error| Inconsistency::Undecodable { table, error }))
+    }
+
+    fn read_value(
+        &self,
+        tables: &Tables,
+        height: Height,
+    ) -> Result<Result<Value, Inconsistency>, StoreError> {
+        let table = DECIDED_VALUES_TABLE.name();
+        let Some(bytes) = tables.values.get(&height)? else {
+            return Ok(Err(Inconsistency::Missing(table)));
+        };
+
+        Ok(self
+            .unseal(DECIDED_VALUES_TABLE, &height, bytes.value())
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| Value::from_bytes(&bytes).map_err(|e| e.to_string()))
+            .map_err(|error| Inconsistency::Undecodable { table, error }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use malachitebft_app_channel::app::types::core::Round;
+    use malachitebft_eth_types::B256;
+    use ssz::Encode;
+
+    use super::*;
+    use crate::metrics::DbMetrics;
+    use crate::store::DecidedValue;
+
+    fn block_hash(height: u64) -> BlockHash {
+        B256::repeat_byte(height as u8)
+    }
+
+    fn insert_decided_value(db: &Db, height: u64, parent_hash: BlockHash) {
+        let mut payload = ExecutionPayloadV3::default();
+        payload.payload_inner.payload_inner.block_number = height;
+        payload.payload_inner.payload_inner.block_hash = block_hash(height);
+        payload.payload_inner.payload_inner.parent_hash = parent_hash;
+
+        let value = Value::new(Bytes::from(payload.as_ssz_bytes()));
+        let certificate = CommitCertificate {
+            height: Height::new(height),
+            round: Round::new(0),
+            value_id: value.id(),
+            commit_signatures: vec![],
+        };
+        db.insert_decided_value(
+            DecidedValue { value, certificate },
+            Bytes::from(payload.as_ssz_bytes()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(dir.path().join("store.db"), DbMetrics::new(), None).unwrap();
+        db.create_tables().unwrap();
+
+        let empty = db.verify(None).unwrap();
+        assert_eq!((empty.from_height, empty.broken), (None, None));
+
+        for height in 1..=5 {
+            insert_decided_value(&db, height, block_hash(height - 1));
+        }
+
+        let verification = db.verify(None).unwrap();
+        assert_eq!(verification.checked, 5);
+        assert_eq!(verification.broken, None);
+
+        // Height 4 no longer links to height 3
+        insert_decided_value(&db, 4, block_hash(9));
+        let verification = db.verify(None).unwrap();
+        assert_eq!(verification.checked, 3);
+        assert_eq!(
+            verification.broken.as_ref().map(|broken| broken.height),
+            Some(4)
+        );
+        assert_eq!(
+            verification.suggestion().as_deref(),
+            Some("Roll the node back with `emerald unsafe-reset --to-height 3`, and let it sync the heights above again")
+        );
+
+        // The link is checked from the height below the range
+        let verification = db.verify(Some(Height::new(4)..=Height::new(10))).unwrap();
+        assert_eq!(
+            (verification.from_height, verification.to_height),
+            (Some(4), Some(5))
+        );
+        assert_eq!(verification.checked, 0);
+
+        // Corrupted certificate
+        insert_decided_value(&db, 4, block_hash(3));
+        let tx = db.db.begin_write().unwrap();
+        {
+            let mut table = tx.open_table(CERTIFICATES_TABLE).unwrap();
+            let mut bytes = table.get(&Height::new(2)).unwrap().unwrap().value();
+            bytes[0] ^= 0xff;
+            table.insert(Height::new(2), bytes).unwrap();
+        }
+        tx.commit().unwrap();
+
+        let broken = db.verify(None).unwrap().broken.unwrap();
+        assert_eq!(broken.height, 2);
+        assert_eq!(
+            broken.reason,
+            Inconsistency::Corrupted(CERTIFICATES_TABLE.name()).to_string()
+        );
+    }
+}
@@ -1,10 +1,45 @@
 use core::cmp::Ordering;
-use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use core::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet, VecDeque};
 
-use malachitebft_app_channel::app::streaming::{Sequence, StreamId, StreamMessage};
+use color_eyre::eyre;
+use malachitebft_app_channel::app::streaming::{Sequence, StreamContent, StreamId, StreamMessage};
 use malachitebft_app_channel::app::types::core::Round;
 use malachitebft_app_channel::app::types::PeerId;
-use malachitebft_eth_types::{Address, Height, ProposalFin, ProposalInit, ProposalPart};
+use malachitebft_app_channel::NetworkMsg;
+use malachitebft_eth_cli::config::ProposalChunkingConfig;
+use malachitebft_eth_types::{
+    proto, Address, EmeraldContext, Height, PartsRequest, ProposalFin, ProposalInit, ProposalPart,
+    RecoveredPart,
+};
+use malachitebft_proto::{Error as ProtoError, Protobuf};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+/// Number of bytes of a stream published at once before pacing starts, which covers the
+/// init part and the first data chunks.
+const PACING_BURST_BYTES: u64 = 512 * 1024;
+
+/// Time to transfer a chunk at the throughput of the streams received from the peers,
+/// which the adaptive chunk size aims at
+const CHUNK_TRANSFER_TIME: Duration = Duration::from_millis(20);
+
+/// Weight of the throughput of a stream in the estimate of the throughput of the peers
+const THROUGHPUT_SMOOTHING: f64 = 0.2;
+
+/// Time after which a stream left incomplete is dropped, and deemed lost
+pub const STREAM_EXPIRY: Duration = Duration::from_secs(30);
+
+/// Time without new parts after which the missing parts of a stream are requested from the
+/// peers, and between two requests for the same stream
+pub const STREAM_STALL: Duration = Duration::from_secs(2);
+
+/// Number of streams sent by this node kept to reply to the requests of the peers
+const RECENT_STREAMS: usize = 8;
+
+/// Number of parts requested at once, and served to a peer per [`STREAM_STALL`]
+pub const MAX_REQUESTED_PARTS: usize = 128;
 
 struct MinSeq<T>(StreamMessage<T>);
 
@@ -45,6 +80,13 @@ impl<T> MinHeap<T> {
         self.0.len()
     }
 
+    fn get(&self, sequence: Sequence) -> Option<&StreamMessage<T>> {
+        self.0
+            .iter()
+            .map(|MinSeq(msg)| msg)
+            .find(|msg| msg.sequence == sequence)
+    }
+
     fn drain(&mut self) -> Vec<T> {
         let mut vec = Vec::with_capacity(self.0.len());
         while let Some(MinSeq(msg)) = self.0.pop() {
@@ -63,6 +105,20 @@ struct StreamState {
     seen_sequences: HashSet<Sequence>,
     total_messages: usize,
     fin_received: bool,
+    /// The proposal was already handled from its init part, the rest of the stream is ignored
+    skipped: bool,
+    /// Bytes of payload data received so far
+    payload_bytes: u64,
+    /// The payload exceeds the maximum proposal size, the rest of the stream is dropped
+    rejected: bool,
+    /// Number of data parts received so far
+    data_parts: usize,
+    /// When the first part of the stream was received
+    started: Option<Instant>,
+    /// When the last new part of the stream was received
+    progressed: Option<Instant>,
+    /// When the missing parts of the stream were last requested from the peers
+    requested: Option<Instant>,
 }
 
 enum StreamProgress {
@@ -75,6 +131,108 @@ impl StreamState {
         self.init_info.is_some() && self.fin_received && self.buffer.len() == self.total_messages
     }
 
+    /// Checks that a part is consistent with the parts received so far.
+    ///
+    /// Parts may arrive in any order, from the gossip or recovered from the peers, and are
+    /// reordered by their sequence. A part which repeats a sequence with other content, or
+    /// lies past the end of the stream, or an init part out of place, makes the stream
+    /// malformed: no order of delivery would make it a valid one.
+    fn check(&self, msg: &StreamMessage<ProposalPart>) -> Result<(), MalformedStream> {
+        let sequence = msg.sequence;
+
+        if self.seen_sequences.contains(&sequence) {
+            let same = self
+                .buffer
+                .get(sequence)
+                .is_some_and(|seen| seen.content.as_data() == msg.content.as_data());
+            return if same {
+                Ok(())
+            } else {
+                Err(MalformedStream::ConflictingPart { sequence })
+            };
+        }
+
+        let is_init = matches!(msg.content, StreamContent::Data(ProposalPart::Init(_)));
+        match (msg.is_first(), is_init) {
+            (true, false) if !msg.is_fin() => return Err(MalformedStream::MissingInit),
+            (false, true) => return Err(MalformedStream::MisplacedInit { sequence }),
+            _ => {}
+        }
+
+        if self.fin_received {
+            let end = self.total_messages as Sequence - 1;
+            if msg.is_fin() {
+                return Err(MalformedStream::ConflictingEnd { sequence, end });
+            }
+            if sequence > end {
+                return Err(MalformedStream::PastEnd { sequence, end });
+            }
+        } else if msg.is_fin() {
+            if let Some(&last) = self
+                .seen_sequences
+                .iter()
+                .max()
+                .filter(|&&last| last > sequence)
+            {
+                return Err(MalformedStream::PastEnd {
+                    sequence: last,
+                    end: sequence,
+                });
+            }
+        }
+
+        let announced = match &msg.content {
+            StreamContent::Data(ProposalPart::Init(init)) => init.payload.as_ref(),
+            _ => self
+                .init_info
+                .as_ref()
+                .and_then(|init| init.payload.as_ref()),
+        };
+        if let Some(announced) = announced.map(|payload| payload.len) {
+            let bytes = self.payload_bytes + payload_len(msg) as u64;
+            if bytes > announced {
+                return Err(MalformedStream::PayloadPastAnnounced { bytes, announced });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Request for the parts missing from the stream, if its init part was received
+    fn missing_parts(&self, stream_id: &StreamId) -> Option<PartsRequest> {
+        let init = self.init_info.as_ref()?;
+
+        let (end, from_sequence) = if self.fin_received {
+            (self.total_messages as Sequence, None)
+        } else {
+            let last = self
+                .seen_sequences
+                .iter()
+                .max()
+                .copied()
+                .unwrap_or_default();
+            (last, Some(last + 1))
+        };
+
+        let sequences = (0..end)
+            .filter(|sequence| !self.seen_sequences.contains(sequence))
+            .take(MAX_REQUESTED_PARTS)
+            .collect::<Vec<_>>();
+
+        if sequences.is_empty() && from_sequence.is_none() {
+            return None;
+        }
+
+        Some(PartsRequest {
+            height: init.height,
+            round: init.round,
+            proposer: init.proposer,
+            stream_id: stream_id.to_bytes(),
+            sequences,
+            from_sequence,
+        })
+    }
+
     fn insert(mut self, msg: StreamMessage<ProposalPart>) -> StreamProgress {
         if self.seen_sequences.insert(msg.sequence) {
             if msg.is_first() {
@@ -114,6 +272,12 @@ pub struct ProposalParts {
     pub parts: Vec<ProposalPart>,
 }
 
+/// Stream sent by this node, kept to reply to the requests of the peers
+struct RecentStream {
+    stream_id: StreamId,
+    parts: ProposalParts,
+}
+
 impl ProposalParts {
     pub fn init(&self) -> Option<&ProposalInit> {
         self.parts.iter().find_map(|p| p.as_init())
@@ -124,51 +288,527 @@ impl ProposalParts {
     }
 }
 
+impl Protobuf for ProposalParts {
+    type Proto = proto::ProposalParts;
+
+    fn from_proto(proto: Self::Proto) -> Result<Self, ProtoError> {
+        let proposer = proto
+            .proposer
+            .ok_or_else(|| ProtoError::missing_field::<Self::Proto>("proposer"))
+            .and_then(Address::from_proto)?;
+
+        let parts = proto
+            .parts
+            .into_iter()
+            .map(ProposalPart::from_proto)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            height: Height::new(proto.height),
+            round: Round::new(proto.round),
+            proposer,
+            parts,
+        })
+    }
+
+    fn to_proto(&self) -> Result<Self::Proto, ProtoError> {
+        let round = self
+            .round
+            .as_u32()
+            .ok_or_else(|| ProtoError::Other("Proposal parts round should not be nil".into()))?;
+
+        Ok(proto::ProposalParts {
+            height: self.height.as_u64(),
+            round,
+            proposer: Some(self.proposer.to_proto()?),
+            parts: self
+                .parts
+                .iter()
+                .map(ProposalPart::to_proto)
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+}
+
+/// Stream whose payload exceeds the maximum proposal size set in the genesis
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Proposal of {bytes} bytes exceeds the maximum proposal size of {max_bytes} bytes")]
+pub struct ProposalTooLarge {
+    /// Size announced by the init part of the stream, or received so far
+    pub bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// Stream whose parts contradict each other, whatever the order they are received in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum MalformedStream {
+    #[error("Part {sequence} differs from the part already received with this sequence")]
+    ConflictingPart { sequence: Sequence },
+    #[error("Part {sequence} is past the end of the stream at sequence {end}")]
+    PastEnd { sequence: Sequence, end: Sequence },
+    #[error("End of the stream at sequence {sequence}, already received at sequence {end}")]
+    ConflictingEnd { sequence: Sequence, end: Sequence },
+    #[error("Stream does not start with an init part")]
+    MissingInit,
+    #[error("Init part at sequence {sequence} instead of the start of the stream")]
+    MisplacedInit { sequence: Sequence },
+    #[error("Stream carries {bytes} bytes of payload, more than the {announced} bytes announced by its init part")]
+    PayloadPastAnnounced { bytes: u64, announced: u64 },
+}
+
+impl MalformedStream {
+    /// Reason of the rejection, in the metrics and the conformance vectors
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ConflictingPart { .. } => "conflicting_part",
+            Self::PastEnd { .. } => "past_end",
+            Self::ConflictingEnd { .. } => "conflicting_end",
+            Self::MissingInit => "missing_init",
+            Self::MisplacedInit { .. } => "misplaced_init",
+            Self::PayloadPastAnnounced { .. } => "payload_past_announced",
+        }
+    }
+}
+
+/// Reason a stream is dropped
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum StreamError {
+    #[error(transparent)]
+    TooLarge(#[from] ProposalTooLarge),
+    #[error(transparent)]
+    Malformed(#[from] MalformedStream),
+}
+
+impl StreamError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TooLarge(_) => "proposal_too_large",
+            Self::Malformed(e) => e.as_str(),
+        }
+    }
+}
+
+/// Payload received through a stream, and the time taken from its first to its last part
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StreamTiming {
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
 #[derive(Default)]
 pub struct PartStreamsMap {
     streams: BTreeMap<(PeerId, StreamId), StreamState>,
+    max_proposal_bytes: Option<u64>,
+    completed: Option<StreamTiming>,
+    recent: VecDeque<RecentStream>,
+    /// Parts served to each peer since the start of its window of [`STREAM_STALL`]
+    served: BTreeMap<PeerId, (Instant, usize)>,
 }
 
 impl PartStreamsMap {
-    pub fn new() -> Self {
-        Self::default()
+    /// Creates a map rejecting the streams whose payload exceeds `max_proposal_bytes`, if set
+    pub fn new(max_proposal_bytes: Option<u64>) -> Self {
+        Self {
+            streams: BTreeMap::new(),
+            max_proposal_bytes,
+            completed: None,
+            recent: VecDeque::new(),
+            served: BTreeMap::new(),
+        }
     }
 
+    /// Adds a part to its stream, returning the parts of the proposal once complete.
+    ///
+    /// A stream is rejected as soon as its init part announces, or its data parts carry,
+    /// more payload than the maximum proposal size, or as soon as it is malformed, and its
+    /// parts are dropped.
     pub fn insert(
         &mut self,
         peer_id: PeerId,
         msg: StreamMessage<ProposalPart>,
-    ) -> Option<ProposalParts> {
+    ) -> Result<Option<ProposalParts>, StreamError> {
         let stream_id = msg.stream_id.clone();
         let stream_key = (peer_id, stream_id);
         let state_ref = self.streams.entry(stream_key.clone()).or_default();
 
+        if state_ref.rejected {
+            if msg.is_fin() {
+                self.streams.remove(&stream_key);
+            }
+            return Ok(None);
+        }
+
+        let now = Instant::now();
+        let started = *state_ref.started.get_or_insert(now);
+
+        if let Err(e) = state_ref.check(&msg) {
+            // Drop the parts received so far, and those still to come
+            *state_ref = StreamState {
+                rejected: true,
+                started: Some(started),
+                ..StreamState::default()
+            };
+            return Err(e.into());
+        }
+
+        if !state_ref.seen_sequences.contains(&msg.sequence) {
+            state_ref.progressed = Some(now);
+            let len = payload_len(&msg);
+            state_ref.payload_bytes += len as u64;
+            state_ref.data_parts += usize::from(len > 0);
+        }
+
+        if let Some(max_bytes) = self.max_proposal_bytes {
+            let announced = match &msg.content {
+                StreamContent::Data(ProposalPart::Init(init)) => {
+                    init.payload.as_ref().map_or(0, |payload| payload.len)
+                }
+                _ => 0,
+            };
+
+            let bytes = state_ref.payload_bytes.max(announced);
+            if bytes > max_bytes {
+                // Drop the parts received so far, and those still to come
+                *state_ref = StreamState {
+                    rejected: true,
+                    started: Some(started),
+                    ..StreamState::default()
+                };
+                return Err(ProposalTooLarge { bytes, max_bytes }.into());
+            }
+        }
+
         // Temporarily take ownership over the stream state since it's consumed
         // by `insert`. Return ownership if the stream isn't completed yet.
         let state = core::mem::take(state_ref);
+        let skipped = state.skipped;
+        let (payload_bytes, data_parts) = (state.payload_bytes, state.data_parts);
 
         match state.insert(msg) {
             StreamProgress::Incomplete(state) => {
                 *state_ref = state;
-                None
+                Ok(None)
             }
             StreamProgress::Complete(parts) => {
                 self.streams.remove(&stream_key);
-                Some(parts)
+
+                // A single chunk is received at once, it tells nothing of the throughput
+                if data_parts > 1 {
+                    self.completed = Some(StreamTiming {
+                        bytes: payload_bytes,
+                        elapsed: now.duration_since(started),
+                    });
+                }
+
+                Ok((!skipped).then_some(parts))
+            }
+        }
+    }
+
+    /// Timing of the last stream of several chunks completed, if not taken yet
+    pub fn take_completed(&mut self) -> Option<StreamTiming> {
+        self.completed.take()
+    }
+
+    /// Drops the streams which started more than [`STREAM_EXPIRY`] before `now` without
+    /// completing, and returns the number of those whose proposal was still expected.
+    pub fn prune_expired(&mut self, now: Instant) -> usize {
+        let mut lost = 0;
+        self.streams.retain(|_, state| {
+            let expired = state
+                .started
+                .is_some_and(|started| now.saturating_duration_since(started) > STREAM_EXPIRY);
+            if expired && !state.skipped && !state.rejected {
+                lost += 1;
             }
+            !expired
+        });
+        lost
+    }
+
+    /// Requests for the parts missing from the streams which received no new part for
+    /// [`STREAM_STALL`], and whose missing parts were not requested meanwhile
+    pub fn stalled_streams(&mut self, now: Instant) -> Vec<PartsRequest> {
+        let stalled = |at: Option<Instant>| {
+            at.is_none_or(|at| now.saturating_duration_since(at) >= STREAM_STALL)
+        };
+
+        self.requests(now, |state| {
+            stalled(state.progressed) && stalled(state.requested)
+        })
+    }
+
+    /// Requests for the parts missing from the streams of the proposal of `proposer` at
+    /// `height` and `round`
+    pub fn missing_parts_of(
+        &mut self,
+        height: Height,
+        round: Round,
+        proposer: Address,
+        now: Instant,
+    ) -> Vec<PartsRequest> {
+        self.requests(now, |state| {
+            state.init_info.as_ref().is_some_and(|init| {
+                init.height == height && init.round == round && init.proposer == proposer
+            })
+        })
+    }
+
+    fn requests(
+        &mut self,
+        now: Instant,
+        filter: impl Fn(&StreamState) -> bool,
+    ) -> Vec<PartsRequest> {
+        self.streams
+            .iter_mut()
+            .filter(|(_, state)| !state.skipped && !state.rejected && filter(state))
+            .filter_map(|((_, stream_id), state)| {
+                let request = state.missing_parts(stream_id)?;
+                state.requested = Some(now);
+                Some(request)
+            })
+            .collect()
+    }
+
+    /// Peer whose stream a part recovered from another peer completes, if its parts
+    /// were requested and the part is still missing
+    pub fn recovering_peer(&self, stream_id: &StreamId, sequence: Sequence) -> Option<PeerId> {
+        self.streams
+            .iter()
+            .find(|((_, id), state)| {
+                id == stream_id
+                    && state.requested.is_some()
+                    && !state.seen_sequences.contains(&sequence)
+            })
+            .map(|((peer_id, _), _)| *peer_id)
+    }
+
+    /// Keeps a stream sent by this node, to reply to the requests of the peers
+    pub fn keep_recent(&mut self, stream_id: StreamId, parts: ProposalParts) {
+        if self.recent.len() == RECENT_STREAMS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(RecentStream { stream_id, parts });
+    }
+
+    /// Parts requested by a peer, from the recent stream of this node it missed them from.
+    ///
+    /// Each part is served once per request, and at most [`MAX_REQUESTED_PARTS`] parts are
+    /// served to a peer per [`STREAM_STALL`], however many it requests.
+    pub fn requested_parts(
+        &mut self,
+        peer_id: PeerId,
+        request: &PartsRequest,
+        now: Instant,
+    ) -> Vec<RecoveredPart> {
+        let Some(stream) = self.recent.iter().find(|stream| {
+            stream.stream_id.to_bytes() == request.stream_id
+                && stream.parts.height == request.height
+                && stream.parts.round == request.round
+                && stream.parts.proposer == request.proposer
+        }) else {
+            return Vec::new();
+        };
+
+        // The parts are in the order of their sequences, followed by the end of the stream
+        let end = stream.parts.parts.len() as Sequence;
+        let after = request
+            .from_sequence
+            .map_or(0..0, |from| from.min(end + 1)..end + 1);
+
+        self.served
+            .retain(|_, (since, _)| now.saturating_duration_since(*since) < STREAM_STALL);
+        let (_, served) = self.served.entry(peer_id).or_insert((now, 0));
+        let budget = MAX_REQUESTED_PARTS.saturating_sub(*served);
+
+        let sequences = request
+            .sequences
+            .iter()
+            .copied()
+            .filter(|sequence| *sequence <= end)
+            .chain(after)
+            .collect::<BTreeSet<_>>();
+        let parts = sequences
+            .into_iter()
+            .take(budget)
+            .map(|sequence| RecoveredPart {
+                stream_id: request.stream_id.clone(),
+                sequence,
+                part: stream
+                    .parts
+                    .parts
+                    .get(sequence as usize)
+                    .cloned()
+                    .map(Box::new),
+            })
+            .collect::<Vec<_>>();
+
+        *served += parts.len();
+        parts
+    }
+
+    /// Ignores the rest of a stream, whose proposal was handled before its end.
+    /// The stream is still tracked until complete, so that its late parts are dropped.
+    pub fn skip(&mut self, peer_id: PeerId, stream_id: StreamId) {
+        if let Some(state) = self.streams.get_mut(&(peer_id, stream_id)) {
+            state.skipped = true;
         }
     }
 }
 
+/// Paces the publication of the parts of a proposal stream to an upload rate, so that the
+/// proposer does not hand all the chunks of a large payload to the network at once and
+/// saturate its uplink, which delays the propagation of all of them.
+///
+/// Peers relay the chunks they receive through the gossip mesh, so the chunks published
+/// first propagate while the next ones are being uploaded.
+pub struct StreamPacer {
+    bytes_per_sec: u64,
+    start: Instant,
+    sent_bytes: u64,
+}
+
+impl StreamPacer {
+    /// Creates a pacer for a stream starting at `start`, at `bytes_per_sec` bytes per second
+    pub fn new(bytes_per_sec: u64, start: Instant) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            start,
+            sent_bytes: 0,
+        }
+    }
+
+    /// Records a part of `len` bytes, and returns the instant at which it can be published
+    pub fn schedule(&mut self, len: usize) -> Instant {
+        let paced_bytes = self.sent_bytes.saturating_sub(PACING_BURST_BYTES);
+        self.sent_bytes += len as u64;
+
+        self.start + Duration::from_secs_f64(paced_bytes as f64 / self.bytes_per_sec as f64)
+    }
+}
+
+/// Size of the chunks the payloads of the proposals of this node are split into.
+///
+/// When adaptive, the size follows the throughput at which the proposals of the peers
+/// are received, so that a chunk takes about [`CHUNK_TRANSFER_TIME`] to transfer: larger
+/// chunks on fast links, where fewer messages mean less overhead, and smaller ones on slow
+/// links. The size is halved whenever streams are lost, as a lost message then costs less
+/// to the stream, and grows back at most twofold per stream received.
+#[derive(Clone, Debug)]
+pub struct ChunkSizer {
+    config: ProposalChunkingConfig,
+    size: usize,
+    /// Smoothed throughput of the streams received, in bytes per second
+    throughput: Option<f64>,
+}
+
+impl ChunkSizer {
+    pub fn new(config: ProposalChunkingConfig) -> Self {
+        Self {
+            size: config.chunk_size.max(1),
+            config,
+            throughput: None,
+        }
+    }
+
+    /// Current size of the chunks, in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Records a stream received from a peer
+    pub fn observe_stream(&mut self, timing: StreamTiming) {
+        if !self.config.adaptive || timing.bytes == 0 || timing.elapsed.is_zero() {
+            return;
+        }
+
+        let throughput = timing.bytes as f64 / timing.elapsed.as_secs_f64();
+        let throughput = match self.throughput {
+            Some(estimate) => estimate + THROUGHPUT_SMOOTHING * (throughput - estimate),
+            None => throughput,
+        };
+        self.throughput = Some(throughput);
+
+        let target = (throughput * CHUNK_TRANSFER_TIME.as_secs_f64()) as usize;
+        self.size = target
+            .min(self.size.saturating_mul(2))
+            .clamp(self.config.min_chunk_size, self.config.max_chunk_size);
+    }
+
+    /// Records that `count` streams received from the peers were lost
+    pub fn observe_lost_streams(&mut self, count: usize) {
+        if !self.config.adaptive || count == 0 {
+            return;
+        }
+
+        self.size = (self.size / 2).max(self.config.min_chunk_size);
+    }
+}
+
+/// Number of bytes of payload data carried by a stream message
+fn payload_len(msg: &StreamMessage<ProposalPart>) -> usize {
+    match &msg.content {
+        StreamContent::Data(ProposalPart::Data(data)) => data.bytes.len(),
+        _ => 0,
+    }
+}
+
+/// Publishes the parts of a proposal stream to the peers.
+///
+/// With an upload rate, the parts are paced by a [`StreamPacer`] from a background task,
+/// so that handling the other messages of consensus is not held up meanwhile.
+pub async fn publish_stream(
+    network: &mpsc::Sender<NetworkMsg<EmeraldContext>>,
+    msgs: impl IntoIterator<Item = StreamMessage<ProposalPart>>,
+    upload_rate: Option<u64>,
+) -> eyre::Result<()> {
+    let Some(bytes_per_sec) = upload_rate else {
+        for msg in msgs {
+            debug!("Streaming proposal part: {msg:?}");
+            network.send(NetworkMsg::PublishProposalPart(msg)).await?;
+        }
+        return Ok(());
+    };
+
+    let network = network.clone();
+    let msgs = msgs.into_iter().collect::<Vec<_>>();
+    tokio::spawn(async move {
+        let mut pacer = StreamPacer::new(bytes_per_sec, Instant::now());
+        for msg in msgs {
+            tokio::time::sleep_until(pacer.schedule(payload_len(&msg))).await;
+            debug!("Streaming proposal part: {msg:?}");
+            if network
+                .send(NetworkMsg::PublishProposalPart(msg))
+                .await
+                .is_err()
+            {
+                error!("Failed to publish proposal part, network channel closed");
+                return;
+            }
+        }
+    });
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
-    use malachitebft_app_channel::app::streaming::StreamContent;
-    use malachitebft_eth_types::secp256k1::Signature;
-    use malachitebft_eth_types::ProposalData;
+    use malachitebft_eth_types::secp256k1::{PrivateKey, Signature};
+    use malachitebft_eth_types::{BlockHash, PayloadSummary, ProposalData};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
 
     use super::*;
 
+    /// Parts of a pending proposal as stored by previous releases: the init part of
+    /// height 7 and round 2, a data part and the fin part
+    const STORED_PROPOSAL_PARTS: &str = "\
+        080710021a160a14aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa221e0a1c0807100222160a14aaaa\
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa221412120a10000102030405060708090a0b0c0d0e0f22461a\
+        440a420a40111111111111111111111111111111111111111111111111111111111111111122222222222222\
+        22222222222222222222222222222222222222222222222222";
+
     #[test]
     fn test_insert_prune_completed_streams() {
         let peer_id = PeerId::from_multihash(Default::default()).unwrap();
@@ -195,19 +835,622 @@ mod tests {
         let part2 = StreamMessage::new(stream_id.clone(), 2, StreamContent::Data(fin));
         let part3 = StreamMessage::new(stream_id, 3, StreamContent::Fin);
 
-        let mut streams_map = PartStreamsMap::new();
-        assert!(streams_map.insert(peer_id, part0).is_none()); // incomplete
+        let mut streams_map = PartStreamsMap::new(None);
+        assert!(streams_map.insert(peer_id, part0).unwrap().is_none()); // incomplete
         assert!(
             !streams_map.streams.is_empty(),
             "streams map must track active stream"
         );
-        assert!(streams_map.insert(peer_id, part1.clone()).is_none()); // incomplete
-        assert!(streams_map.insert(peer_id, part1).is_none()); // repeated seq; no-op
-        assert!(streams_map.insert(peer_id, part2).is_none()); // incomplete
-        assert!(streams_map.insert(peer_id, part3).is_some()); // complete
+        assert!(streams_map
+            .insert(peer_id, part1.clone())
+            .unwrap()
+            .is_none()); // incomplete
+        assert!(streams_map.insert(peer_id, part1).unwrap().is_none()); // repeated seq; no-op
+        assert!(streams_map.insert(peer_id, part2).unwrap().is_none()); // incomplete
+        assert!(streams_map.insert(peer_id, part3).unwrap().is_some()); // complete
         assert!(
             streams_map.streams.is_empty(),
             "streams map must drop complete streams"
         );
     }
+
+    #[test]
+    fn test_skipped_stream_is_not_completed() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::new());
+        let init = ProposalPart::Init(ProposalInit::new(
+            Height::new(1),
+            Round::Some(0),
+            Round::Nil,
+            Address::new([0; 20]),
+        ));
+
+        let mut streams_map = PartStreamsMap::new(None);
+        let part0 = StreamMessage::new(stream_id.clone(), 0, StreamContent::Data(init));
+        assert!(streams_map.insert(peer_id, part0).unwrap().is_none());
+
+        streams_map.skip(peer_id, stream_id.clone());
+
+        let part1 = StreamMessage::new(stream_id, 1, StreamContent::Fin);
+        assert!(streams_map.insert(peer_id, part1).unwrap().is_none());
+        assert!(
+            streams_map.streams.is_empty(),
+            "streams map must drop skipped streams once complete"
+        );
+    }
+
+    #[test]
+    fn test_oversized_stream_is_rejected() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::new());
+        let data = |sequence, len| {
+            let data = ProposalPart::Data(ProposalData::new(Bytes::from(vec![0; len])));
+            StreamMessage::new(stream_id.clone(), sequence, StreamContent::Data(data))
+        };
+
+        let mut streams_map = PartStreamsMap::new(Some(100));
+        assert_eq!(streams_map.insert(peer_id, data(1, 60)), Ok(None));
+        // Repeated parts are not counted twice
+        assert_eq!(streams_map.insert(peer_id, data(1, 60)), Ok(None));
+        assert_eq!(
+            streams_map.insert(peer_id, data(2, 60)),
+            Err(ProposalTooLarge {
+                bytes: 120,
+                max_bytes: 100
+            }
+            .into())
+        );
+        assert_eq!(streams_map.insert(peer_id, data(3, 10)), Ok(None));
+
+        let fin = StreamMessage::new(stream_id.clone(), 4, StreamContent::Fin);
+        assert_eq!(streams_map.insert(peer_id, fin), Ok(None));
+        assert!(
+            streams_map.streams.is_empty(),
+            "streams map must drop rejected streams once complete"
+        );
+    }
+
+    #[test]
+    fn test_stream_announcing_oversized_payload_is_rejected() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::new());
+        let signature = PrivateKey::from_slice(&[1; 32]).unwrap().sign(&[0; 32]);
+        let mut init = ProposalInit::new(
+            Height::new(1),
+            Round::Some(0),
+            Round::Nil,
+            Address::new([0; 20]),
+        );
+        init.payload = Some(PayloadSummary {
+            block_hash: BlockHash::from([0; 32]),
+            len: 101,
+            signature,
+        });
+        let part0 = StreamMessage::new(stream_id, 0, StreamContent::Data(ProposalPart::Init(init)));
+
+        let mut streams_map = PartStreamsMap::new(Some(100));
+        assert_eq!(
+            streams_map.insert(peer_id, part0),
+            Err(ProposalTooLarge {
+                bytes: 101,
+                max_bytes: 100
+            }
+            .into())
+        );
+    }
+
+    /// Stream of a proposal at height 1 with two data parts of `[1, 2]` and `[3, 4]`,
+    /// announcing its payload of 4 bytes
+    fn stream_of_two_chunks(stream_id: &StreamId) -> Vec<StreamMessage<ProposalPart>> {
+        let key = PrivateKey::from_slice(&[1; 32]).unwrap();
+        let init = ProposalInit::new(
+            Height::new(1),
+            Round::Some(0),
+            Round::Nil,
+            Address::new([0; 20]),
+        )
+        .with_payload(PayloadSummary {
+            block_hash: BlockHash::from([0; 32]),
+            len: 4,
+            signature: key.sign(&[0; 32]),
+        });
+
+        [
+            StreamContent::Data(ProposalPart::Init(init)),
+            StreamContent::Data(ProposalPart::Data(ProposalData::new(Bytes::from_static(
+                &[1, 2],
+            )))),
+            StreamContent::Data(ProposalPart::Data(ProposalData::new(Bytes::from_static(
+                &[3, 4],
+            )))),
+            StreamContent::Data(ProposalPart::Fin(ProposalFin::new(key.sign(&[1; 32])))),
+            StreamContent::Fin,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, content)| {
+            StreamMessage::new(stream_id.clone(), sequence as Sequence, content)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_reordered_stream_is_completed() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let msgs = stream_of_two_chunks(&stream_id);
+
+        let mut streams_map = PartStreamsMap::new(None);
+        for i in [4, 2, 0, 3] {
+            assert_eq!(streams_map.insert(peer_id, msgs[i].clone()), Ok(None));
+        }
+        // Identical duplicates are ignored
+        assert_eq!(streams_map.insert(peer_id, msgs[2].clone()), Ok(None));
+        assert_eq!(streams_map.insert(peer_id, msgs[4].clone()), Ok(None));
+
+        let parts = streams_map
+            .insert(peer_id, msgs[1].clone())
+            .unwrap()
+            .unwrap();
+        let expected = msgs[..4]
+            .iter()
+            .filter_map(|msg| msg.content.as_data().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(parts.parts, expected);
+    }
+
+    #[test]
+    fn test_stream_with_gap_is_not_completed() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let msgs = stream_of_two_chunks(&stream_id);
+
+        let mut streams_map = PartStreamsMap::new(None);
+        for i in [0, 2, 3, 4] {
+            assert_eq!(streams_map.insert(peer_id, msgs[i].clone()), Ok(None));
+        }
+
+        let requests = streams_map.stalled_streams(Instant::now() + STREAM_STALL);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].sequences, vec![1]);
+        assert_eq!(requests[0].from_sequence, None);
+
+        // Filling the gap completes the stream
+        assert!(streams_map
+            .insert(peer_id, msgs[1].clone())
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_malformed_streams_are_rejected() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let msgs = stream_of_two_chunks(&stream_id);
+        let data = |sequence, bytes: &'static [u8]| {
+            let data = ProposalData::new(Bytes::from_static(bytes));
+            StreamMessage::new(
+                stream_id.clone(),
+                sequence,
+                StreamContent::Data(ProposalPart::Data(data)),
+            )
+        };
+        let fin = |sequence| StreamMessage::new(stream_id.clone(), sequence, StreamContent::Fin);
+
+        let rejected = |delivered: Vec<StreamMessage<ProposalPart>>| {
+            let mut streams_map = PartStreamsMap::new(None);
+            let mut result = Ok(None);
+            for msg in delivered {
+                result = streams_map.insert(peer_id, msg);
+            }
+            // The rest of the stream is dropped
+            assert_eq!(streams_map.insert(peer_id, msgs[1].clone()), Ok(None));
+            result
+        };
+
+        assert_eq!(
+            rejected(vec![msgs[0].clone(), msgs[1].clone(), data(1, &[9, 9])]),
+            Err(MalformedStream::ConflictingPart { sequence: 1 }.into())
+        );
+        assert_eq!(
+            rejected(vec![msgs[0].clone(), msgs[4].clone(), data(5, &[9])]),
+            Err(MalformedStream::PastEnd {
+                sequence: 5,
+                end: 4
+            }
+            .into())
+        );
+        // The end of the stream comes before a part already received
+        assert_eq!(
+            rejected(vec![msgs[0].clone(), data(5, &[9]), msgs[4].clone()]),
+            Err(MalformedStream::PastEnd {
+                sequence: 5,
+                end: 4
+            }
+            .into())
+        );
+        assert_eq!(
+            rejected(vec![msgs[0].clone(), msgs[4].clone(), fin(6)]),
+            Err(MalformedStream::ConflictingEnd {
+                sequence: 6,
+                end: 4
+            }
+            .into())
+        );
+        assert_eq!(
+            rejected(vec![data(0, &[1, 2])]),
+            Err(MalformedStream::MissingInit.into())
+        );
+        let init = msgs[0].content.clone();
+        assert_eq!(
+            rejected(vec![StreamMessage::new(stream_id.clone(), 2, init)]),
+            Err(MalformedStream::MisplacedInit { sequence: 2 }.into())
+        );
+        assert_eq!(
+            rejected(vec![
+                data(1, &[1, 2]),
+                data(2, &[3, 4]),
+                data(3, &[5]),
+                msgs[0].clone()
+            ]),
+            Err(MalformedStream::PayloadPastAnnounced {
+                bytes: 5,
+                announced: 4
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_stored_proposal_parts() {
+        let bytes = hex::decode(STORED_PROPOSAL_PARTS).unwrap();
+        let parts = ProposalParts::from_bytes(&bytes).unwrap();
+
+        assert_eq!(parts.height, Height::new(7));
+        assert_eq!(parts.round, Round::new(2));
+        assert_eq!(parts.proposer, Address::new([0xaa; 20]));
+        assert!(parts.init().is_some() && parts.fin().is_some());
+        assert_eq!(parts.parts.len(), 3);
+        assert_eq!(parts.to_bytes().unwrap().as_ref(), bytes.as_slice());
+    }
+
+    proptest! {
+        #[test]
+        fn test_proposal_parts_roundtrip(
+            height in any::<u64>(),
+            round in 0..1000u32,
+            chunks in vec(vec(any::<u8>(), 0..64), 0..8),
+        ) {
+            let (height, round) = (Height::new(height), Round::new(round));
+            let proposer = Address::new([0xaa; 20]);
+            let init = ProposalInit::new(height, round, Round::Nil, proposer);
+
+            let parts = ProposalParts {
+                height,
+                round,
+                proposer,
+                parts: core::iter::once(ProposalPart::Init(init))
+                    .chain(chunks.into_iter().map(|chunk| {
+                        ProposalPart::Data(ProposalData::new(Bytes::from(chunk)))
+                    }))
+                    .collect(),
+            };
+
+            let bytes = parts.to_bytes().unwrap();
+            prop_assert_eq!(ProposalParts::from_bytes(&bytes).unwrap(), parts);
+        }
+    }
+
+    #[test]
+    fn test_stream_pacer() {
+        let start = Instant::now();
+        let mut pacer = StreamPacer::new(1024 * 1024, start);
+
+        // The burst is published right away
+        assert_eq!(pacer.schedule(256 * 1024), start);
+        assert_eq!(pacer.schedule(256 * 1024), start);
+        assert_eq!(pacer.schedule(512 * 1024), start);
+
+        // Then the parts are spaced by their size at the upload rate
+        assert_eq!(
+            pacer.schedule(512 * 1024),
+            start + Duration::from_millis(500)
+        );
+        assert_eq!(pacer.schedule(0), start + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_expired_streams_are_lost() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let init = |stream_id: &StreamId| {
+            let init = ProposalPart::Init(ProposalInit::new(
+                Height::new(1),
+                Round::Some(0),
+                Round::Nil,
+                Address::new([0; 20]),
+            ));
+            StreamMessage::new(stream_id.clone(), 0, StreamContent::Data(init))
+        };
+
+        let mut streams_map = PartStreamsMap::new(None);
+        let expected = StreamId::new(Bytes::from_static(&[1]));
+        let skipped = StreamId::new(Bytes::from_static(&[2]));
+        assert!(streams_map
+            .insert(peer_id, init(&expected))
+            .unwrap()
+            .is_none());
+        assert!(streams_map
+            .insert(peer_id, init(&skipped))
+            .unwrap()
+            .is_none());
+        streams_map.skip(peer_id, skipped);
+
+        let now = Instant::now();
+        assert_eq!(streams_map.prune_expired(now), 0);
+        assert_eq!(streams_map.streams.len(), 2);
+
+        // Only the stream whose proposal was still expected is lost
+        assert_eq!(
+            streams_map.prune_expired(now + STREAM_EXPIRY + Duration::from_secs(1)),
+            1
+        );
+        assert!(streams_map.streams.is_empty());
+        assert_eq!(streams_map.take_completed(), None);
+    }
+
+    #[test]
+    fn test_missing_parts_are_recovered_from_peers() {
+        let proposer = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let signature = PrivateKey::from_slice(&[1; 32]).unwrap().sign(&[0; 32]);
+        let init = ProposalInit::new(
+            Height::new(1),
+            Round::Some(0),
+            Round::Nil,
+            Address::new([0; 20]),
+        );
+        let parts = ProposalParts {
+            height: init.height,
+            round: init.round,
+            proposer: init.proposer,
+            parts: vec![
+                ProposalPart::Init(init),
+                ProposalPart::Data(ProposalData::new(Bytes::from_static(&[1, 2]))),
+                ProposalPart::Data(ProposalData::new(Bytes::from_static(&[3, 4]))),
+                ProposalPart::Fin(ProposalFin::new(signature)),
+            ],
+        };
+        let msg = |sequence: usize| {
+            let content = parts
+                .parts
+                .get(sequence)
+                .cloned()
+                .map_or(StreamContent::Fin, StreamContent::Data);
+            StreamMessage::new(stream_id.clone(), sequence as Sequence, content)
+        };
+
+        // The proposer, which keeps the full stream
+        let mut peer = PartStreamsMap::new(None);
+        peer.keep_recent(stream_id.clone(), parts.clone());
+
+        // Only the init part and the second chunk are received from the proposer
+        let mut streams_map = PartStreamsMap::new(None);
+        assert_eq!(streams_map.insert(proposer, msg(0)), Ok(None));
+        assert_eq!(streams_map.insert(proposer, msg(2)), Ok(None));
+
+        let now = Instant::now();
+        assert!(streams_map.stalled_streams(now).is_empty());
+        assert_eq!(streams_map.recovering_peer(&stream_id, 1), None);
+
+        let requests = streams_map.stalled_streams(now + STREAM_STALL);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].sequences, vec![1]);
+        assert_eq!(requests[0].from_sequence, Some(3));
+
+        // Not requested again before the stream stalls again
+        assert!(streams_map.stalled_streams(now + STREAM_STALL).is_empty());
+
+        let recovered = peer.requested_parts(proposer, &requests[0], now);
+        assert_eq!(
+            recovered
+                .iter()
+                .map(|part| part.sequence)
+                .collect::<Vec<_>>(),
+            vec![1, 3, 4]
+        );
+        assert!(recovered[2].part.is_none(), "end of the stream");
+
+        let mut completed = None;
+        for part in recovered {
+            let content = part
+                .part
+                .map_or(StreamContent::Fin, |part| StreamContent::Data(*part));
+            let stream_id = StreamId::new(part.stream_id);
+            let peer_id = streams_map
+                .recovering_peer(&stream_id, part.sequence)
+                .expect("missing part of a requested stream");
+            assert_eq!(peer_id, proposer);
+
+            let msg = StreamMessage::new(stream_id, part.sequence, content);
+            completed = streams_map.insert(peer_id, msg).unwrap();
+        }
+        assert_eq!(completed, Some(parts));
+        assert!(streams_map.streams.is_empty());
+    }
+
+    #[test]
+    fn test_parts_of_unknown_streams_are_not_served() {
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let proposer = Address::new([0; 20]);
+        let parts = ProposalParts {
+            height: Height::new(1),
+            round: Round::Some(0),
+            proposer,
+            parts: vec![ProposalPart::Init(ProposalInit::new(
+                Height::new(1),
+                Round::Some(0),
+                Round::Nil,
+                proposer,
+            ))],
+        };
+
+        let mut streams_map = PartStreamsMap::new(None);
+        streams_map.keep_recent(stream_id, parts);
+
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let now = Instant::now();
+        let request = |stream_id: &'static [u8], height| PartsRequest {
+            height: Height::new(height),
+            round: Round::Some(0),
+            proposer,
+            stream_id: Bytes::from_static(stream_id),
+            sequences: vec![0],
+            from_sequence: None,
+        };
+        let mut served = |request| streams_map.requested_parts(peer_id, &request, now);
+        assert_eq!(served(request(&[1], 1)).len(), 1);
+        assert!(served(request(&[2], 1)).is_empty());
+        assert!(served(request(&[1], 2)).is_empty());
+    }
+
+    #[test]
+    fn test_completed_streams_are_not_served() {
+        let peer_id = PeerId::from_multihash(Default::default()).unwrap();
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let init = ProposalInit::new(
+            Height::new(1),
+            Round::Some(0),
+            Round::Nil,
+            Address::new([0; 20]),
+        );
+
+        let mut streams_map = PartStreamsMap::new(None);
+        let msgs = [
+            StreamContent::Data(ProposalPart::Init(init.clone())),
+            StreamContent::Fin,
+        ];
+        for (sequence, content) in msgs.into_iter().enumerate() {
+            let msg = StreamMessage::new(stream_id.clone(), sequence as Sequence, content);
+            streams_map.insert(peer_id, msg).unwrap();
+        }
+
+        let request = PartsRequest {
+            height: init.height,
+            round: init.round,
+            proposer: init.proposer,
+            stream_id: stream_id.to_bytes(),
+            sequences: vec![0],
+            from_sequence: None,
+        };
+        assert!(streams_map
+            .requested_parts(peer_id, &request, Instant::now())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_requested_parts_are_deduplicated_and_capped_per_peer() {
+        let stream_id = StreamId::new(Bytes::from_static(&[1]));
+        let init = ProposalInit::new(
+            Height::new(1),
+            Round::Some(0),
+            Round::Nil,
+            Address::new([0; 20]),
+        );
+        let chunks = (0..2 * MAX_REQUESTED_PARTS)
+            .map(|_| ProposalPart::Data(ProposalData::new(Bytes::from_static(&[1]))));
+        let parts = ProposalParts {
+            height: init.height,
+            round: init.round,
+            proposer: init.proposer,
+            parts: core::iter::once(ProposalPart::Init(init.clone()))
+                .chain(chunks)
+                .collect(),
+        };
+
+        let mut streams_map = PartStreamsMap::new(None);
+        streams_map.keep_recent(stream_id.clone(), parts);
+
+        let request = |sequences: Vec<u64>, from_sequence| PartsRequest {
+            height: init.height,
+            round: init.round,
+            proposer: init.proposer,
+            stream_id: stream_id.to_bytes(),
+            sequences,
+            from_sequence,
+        };
+        let sequences = |parts: Vec<RecoveredPart>| {
+            parts
+                .into_iter()
+                .map(|part| part.sequence)
+                .collect::<Vec<_>>()
+        };
+        let peer_id = |byte| PeerId::from_bytes(&[0, 1, byte]).unwrap();
+        let (peer, other) = (peer_id(1), peer_id(2));
+        let now = Instant::now();
+
+        // Each part is served once, the sequences past the end of the stream are ignored
+        let served = streams_map.requested_parts(peer, &request(vec![3, 1, 3, 1, 1000], None), now);
+        assert_eq!(sequences(served), vec![1, 3]);
+
+        // Then only the rest of the budget of the peer is served
+        let served = streams_map.requested_parts(peer, &request(vec![], Some(0)), now);
+        assert_eq!(
+            sequences(served),
+            (0..MAX_REQUESTED_PARTS as u64 - 2).collect::<Vec<_>>()
+        );
+        assert!(streams_map
+            .requested_parts(peer, &request(vec![0], None), now)
+            .is_empty());
+
+        // Other peers have their own budget, which is renewed after a stall
+        let served = streams_map.requested_parts(other, &request(vec![0], None), now);
+        assert_eq!(sequences(served), vec![0]);
+        let served =
+            streams_map.requested_parts(peer, &request(vec![], Some(0)), now + STREAM_STALL);
+        assert_eq!(served.len(), MAX_REQUESTED_PARTS);
+    }
+
+    #[test]
+    fn test_chunk_sizer() {
+        let config = ProposalChunkingConfig {
+            chunk_size: 128 * 1024,
+            adaptive: true,
+            min_chunk_size: 16 * 1024,
+            max_chunk_size: 1024 * 1024,
+        };
+        let timing = |bytes, millis| StreamTiming {
+            bytes,
+            elapsed: Duration::from_millis(millis),
+        };
+
+        // Fixed size
+        let mut sizer = ChunkSizer::new(ProposalChunkingConfig {
+            adaptive: false,
+            ..config.clone()
+        });
+        sizer.observe_stream(timing(100 * 1024 * 1024, 100));
+        sizer.observe_lost_streams(1);
+        assert_eq!(sizer.size(), 128 * 1024);
+
+        // 1 GB/s on a LAN: grows twofold per stream, up to the largest size
+        let mut sizer = ChunkSizer::new(config.clone());
+        sizer.observe_stream(timing(100_000_000, 100));
+        assert_eq!(sizer.size(), 256 * 1024);
+        sizer.observe_stream(timing(100_000_000, 100));
+        sizer.observe_stream(timing(100_000_000, 100));
+        assert_eq!(sizer.size(), 1024 * 1024);
+
+        // Halved on losses, down to the smallest size
+        sizer.observe_lost_streams(3);
+        assert_eq!(sizer.size(), 512 * 1024);
+        for _ in 0..10 {
+            sizer.observe_lost_streams(1);
+        }
+        assert_eq!(sizer.size(), 16 * 1024);
+
+        // 1 MB/s on a slow link: 20 KB chunks
+        let mut sizer = ChunkSizer::new(config);
+        sizer.observe_stream(timing(1_000_000, 1000));
+        assert_eq!(sizer.size(), 20_000);
+    }
 }
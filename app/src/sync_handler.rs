@@ -1,38 +1,368 @@
-//! Sync handler functions for retrieving decided values for sync.
+//! Sync handler functions for serving and processing decided values during sync.
+//!
+//! The logic here is decoupled from the concrete [`Store`] and [`Engine`] through three
+//! small traits, so that alternative storages or test doubles can reuse it:
+//!
+//! - [`DecidedValueSource`] reads decided values, certificates and block headers from storage.
+//! - [`PayloadBodySource`] fetches execution payload bodies from the execution layer,
+//!   used to rebuild values which have been pruned from storage.
+//! - [`PayloadValidator`] validates execution payloads received from peers while catching up.
+//!
+//! Embedders serving sync requests call [`get_decided_value_for_sync`], and embedders
+//! catching up call [`process_synced_value`] before storing the returned value.
+//!
+//! The values rebuilt from the execution layer are kept in a [`RebuiltValueCache`], as
+//! the same heights are requested by all the peers catching up from the same point.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 
 use alloy_rpc_types_engine::ExecutionPayloadV3;
+use async_trait::async_trait;
 use bytes::Bytes;
-use color_eyre::eyre::{self, eyre};
 use malachitebft_app_channel::app::types::codec::Codec;
+use malachitebft_app_channel::app::types::core::{CommitCertificate, Round, Validity};
 use malachitebft_app_channel::app::types::sync::RawDecidedValue;
+use malachitebft_app_channel::app::types::ProposedValue;
 use malachitebft_eth_engine::engine::Engine;
+use malachitebft_eth_engine::json_structures::{ExecutionBlock, ExecutionPayloadBodyV1};
 use malachitebft_eth_types::codec::proto::ProtobufCodec;
-use malachitebft_eth_types::{EmeraldContext, Height, Value};
+use malachitebft_eth_types::{Address, EmeraldContext, Height, RetryConfig, Value};
+use malachitebft_proto::Error as ProtoError;
 use ssz::{Decode, Encode};
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::payload::reconstruct_execution_payload;
+use crate::el_divergence::ElDivergence;
+use crate::metrics::{SyncMetrics, ValidationMetrics};
+use crate::payload::{
+    reconstruct_execution_payload, validate_execution_payload, ValidatedPayloadCache,
+};
+use crate::state::decode_value;
 use crate::store::Store;
 
+/// Error returned by the implementations of the traits of this module
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Storage holding the decided values served to syncing peers.
+#[async_trait]
+pub trait DecidedValueSource: Send + Sync {
+    /// Returns the decided value and its commit certificate at the given height, if still stored.
+    async fn get_raw_decided_value(
+        &self,
+        height: Height,
+    ) -> Result<Option<RawDecidedValue<EmeraldContext>>, BoxError>;
+
+    /// Returns the commit certificate and the SSZ-encoded block header at the given height.
+    /// These outlive the decided value itself and are used to rebuild pruned values.
+    async fn get_certificate_and_header(
+        &self,
+        height: Height,
+    ) -> Result<Option<(CommitCertificate<EmeraldContext>, Bytes)>, BoxError>;
+}
+
+/// Execution layer access needed to rebuild decided values pruned from storage.
+#[async_trait]
+pub trait PayloadBodySource: Send + Sync {
+    /// Returns the payload bodies for `count` blocks starting at `start_block`,
+    /// following the semantics of `engine_getPayloadBodiesByRangeV1`.
+    async fn get_payload_bodies_by_range(
+        &self,
+        start_block: u64,
+        count: u64,
+    ) -> Result<Vec<Option<ExecutionPayloadBodyV1>>, BoxError>;
+}
+
+/// Validation of execution payloads received through sync.
+#[async_trait]
+pub trait PayloadValidator: Send {
+    /// Returns whether the SSZ-encoded execution payload is valid.
+    /// Undecodable payloads are reported as `Validity::Invalid`, not as errors.
+    async fn validate_payload(
+        &mut self,
+        data: &Bytes,
+        height: Height,
+        round: Round,
+    ) -> Result<Validity, BoxError>;
+}
+
+#[async_trait]
+impl DecidedValueSource for Store {
+    async fn get_raw_decided_value(
+        &self,
+        height: Height,
+    ) -> Result<Option<RawDecidedValue<EmeraldContext>>, BoxError> {
+        Ok(Self::get_raw_decided_value(self, height).await?)
+    }
+
+    async fn get_certificate_and_header(
+        &self,
+        height: Height,
+    ) -> Result<Option<(CommitCertificate<EmeraldContext>, Bytes)>, BoxError> {
+        Ok(Self::get_certificate_and_header(self, height).await?)
+    }
+}
+
+#[async_trait]
+impl PayloadBodySource for Engine {
+    async fn get_payload_bodies_by_range(
+        &self,
+        start_block: u64,
+        count: u64,
+    ) -> Result<Vec<Option<ExecutionPayloadBodyV1>>, BoxError> {
+        Ok(Self::get_payload_bodies_by_range(self, start_block, count).await?)
+    }
+}
+
+/// [`PayloadValidator`] backed by the execution engine, skipping payloads already validated.
+pub struct EnginePayloadValidator<'a> {
+    pub engine: &'a Engine,
+    /// Block the synced payload must extend, if known
+    pub parent: Option<ExecutionBlock>,
+    pub metrics: ValidationMetrics,
+    pub cache: &'a mut ValidatedPayloadCache,
+    pub retry_config: &'a RetryConfig,
+}
+
+#[async_trait]
+impl PayloadValidator for EnginePayloadValidator<'_> {
+    async fn validate_payload(
+        &mut self,
+        data: &Bytes,
+        height: Height,
+        round: Round,
+    ) -> Result<Validity, BoxError> {
+        validate_execution_payload(
+            self.cache,
+            data,
+            height,
+            round,
+            self.parent.as_ref(),
+            // Decided values are not checked against the fee recipient policy
+            None,
+            self.engine,
+            self.retry_config,
+            &self.metrics,
+        )
+        .await
+        .map_err(|e| match e.downcast::<ElDivergence>() {
+            // Kept as is, for the handler to recover the execution client
+            Ok(divergence) => divergence.into(),
+            Err(e) => e.into(),
+        })
+    }
+}
+
+/// Error returned when serving or processing a decided value during sync
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    /// The value of a height decided and not pruned is missing from storage
+    #[error("Decided value not found at height {height}, data integrity error")]
+    MissingDecidedValue { height: Height },
+
+    /// The stored block header of a pruned height cannot be decoded
+    #[error("Failed to deserialize block header at height {height}: {reason}")]
+    InvalidHeader { height: Height, reason: String },
+
+    /// The storage failed to read a decided value
+    #[error("Failed to read the decided value from storage: {0}")]
+    Storage(#[source] BoxError),
+
+    /// The execution client failed to return the payload body of a pruned height
+    #[error("Failed to get the payload body from the execution client: {0}")]
+    ExecutionClient(#[source] BoxError),
+
+    /// The payload of a synced value could not be validated
+    #[error("Failed to validate the synced payload: {0}")]
+    Validation(#[source] BoxError),
+
+    /// The rebuilt value could not be encoded
+    #[error("Failed to encode the decided value: {0}")]
+    Codec(#[from] ProtoError),
+}
+
+/// Reason why a decided value is not served to a syncing peer,
+/// which then requests the height from another peer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeightUnavailable {
+    /// The height is not decided yet, or below the earliest stored certificate
+    NotDecided,
+    /// The certificate or the block header of the height is not stored
+    MissingFromStore,
+    /// The block is older than the blocks retained by the pruned execution client
+    BeyondElRetention,
+    /// The execution client does not have the body of the block
+    MissingFromEl,
+    /// The value must be rebuilt from the execution client, which is syncing or unreachable
+    ElNotReady,
+    /// The value must be rebuilt from the execution client, which follows a shadow fork
+    ShadowFork,
+    /// The stored data of the height is corrupted
+    Corrupted,
+    /// The request exceeds the rate limit of the values served to syncing peers
+    RateLimited,
+    /// The value must be rebuilt from the execution client, which is already rebuilding
+    /// as many values for syncing peers as allowed at the same time
+    Busy,
+}
+
+impl HeightUnavailable {
+    /// Reason reported in the metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotDecided => "not_decided",
+            Self::MissingFromStore => "missing_from_store",
+            Self::BeyondElRetention => "beyond_el_retention",
+            Self::MissingFromEl => "missing_from_el",
+            Self::ElNotReady => "el_not_ready",
+            Self::ShadowFork => "shadow_fork",
+            Self::Corrupted => "corrupted",
+            Self::RateLimited => "rate_limited",
+            Self::Busy => "busy",
+        }
+    }
+}
+
+/// Decided values rebuilt from the execution layer, by height, served again without
+/// asking the execution layer. The least recently served values are evicted to keep
+/// the size of the encoded values under `max_bytes`.
+pub struct RebuiltValueCache {
+    max_bytes: u64,
+    bytes: u64,
+    clock: u64,
+    values: BTreeMap<Height, (RawDecidedValue<EmeraldContext>, u64)>,
+    /// Heights of the values, by time they were last served
+    recency: BTreeMap<u64, Height>,
+    metrics: SyncMetrics,
+}
+
+impl RebuiltValueCache {
+    /// Creates a cache holding up to `max_bytes` of encoded values, disabled if zero
+    pub fn new(max_bytes: u64, metrics: SyncMetrics) -> Self {
+        Self {
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            values: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            metrics,
+        }
+    }
+
+    pub fn get(&mut self, height: Height) -> Option<RawDecidedValue<EmeraldContext>> {
+        let value = self.values.get_mut(&height).map(|(value, last_served)| {
+            self.recency.remove(last_served);
+            self.clock += 1;
+            *last_served = self.clock;
+            self.recency.insert(self.clock, height);
+            value.clone()
+        });
+
+        self.metrics.inc_value_cache_lookups(value.is_some());
+        value
+    }
+
+    pub fn contains(&self, height: Height) -> bool {
+        self.values.contains_key(&height)
+    }
+
+    pub fn insert(&mut self, height: Height, value: RawDecidedValue<EmeraldContext>) {
+        let size = value.value_bytes.len() as u64;
+        if self.max_bytes == 0 || size > self.max_bytes {
+            return;
+        }
+
+        self.remove(height);
+        while self.bytes + size > self.max_bytes {
+            let Some((_, &oldest)) = self.recency.first_key_value() else {
+                break;
+            };
+            self.remove(oldest);
+        }
+
+        self.clock += 1;
+        self.values.insert(height, (value, self.clock));
+        self.recency.insert(self.clock, height);
+        self.bytes += size;
+        self.metrics.set_value_cache_bytes(self.bytes);
+    }
+
+    fn remove(&mut self, height: Height) {
+        if let Some((value, last_served)) = self.values.remove(&height) {
+            self.recency.remove(&last_served);
+            self.bytes -= value.value_bytes.len() as u64;
+            self.metrics.set_value_cache_bytes(self.bytes);
+        }
+    }
+}
+
+/// [`RebuiltValueCache`] shared between the application and the values rebuilt for
+/// syncing peers in the background
+#[derive(Clone)]
+pub struct SharedRebuiltValueCache(Arc<Mutex<RebuiltValueCache>>);
+
+impl SharedRebuiltValueCache {
+    pub fn new(cache: RebuiltValueCache) -> Self {
+        Self(Arc::new(Mutex::new(cache)))
+    }
+
+    pub fn get(&self, height: Height) -> Option<RawDecidedValue<EmeraldContext>> {
+        self.lock().get(height)
+    }
+
+    pub fn contains(&self, height: Height) -> bool {
+        self.lock().contains(height)
+    }
+
+    pub fn insert(&self, height: Height, value: RawDecidedValue<EmeraldContext>) {
+        self.lock().insert(height, value)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RebuiltValueCache> {
+        self.0.lock().expect("rebuilt value cache lock poisoned")
+    }
+}
+
 /// Retrieves a decided value for sync at the given height.
-/// If the value is pruned from storage, reconstructs it from the block header and execution layer.
-pub async fn get_decided_value_for_sync(
-    store: &Store,
-    engine: &Engine,
+/// If the value is pruned from storage, reconstructs it from the block header and execution layer,
+/// unless it is in `cache`.
+///
+/// `el_retained_from` is the lowest height whose block is retained by the execution
+/// layer if it is pruned, or `None` if it is an archive node. Pruned values of lower
+/// heights are reported as unavailable without asking the execution layer.
+pub async fn get_decided_value_for_sync<S, E>(
+    store: &S,
+    engine: &E,
+    cache: &SharedRebuiltValueCache,
     height: Height,
     earliest_unpruned_height: Height,
-) -> eyre::Result<Option<RawDecidedValue<EmeraldContext>>> {
+    el_retained_from: Option<Height>,
+) -> Result<Result<RawDecidedValue<EmeraldContext>, HeightUnavailable>, SyncError>
+where
+    S: DecidedValueSource + ?Sized,
+    E: PayloadBodySource + ?Sized,
+{
     if height >= earliest_unpruned_height {
         // Height is in our decided values table - get it directly
         info!(%height, earliest_unpruned_height = %earliest_unpruned_height, "Getting decided value from local storage");
         store
             .get_raw_decided_value(height)
-            .await?
-            .ok_or_else(|| {
-                eyre!("Decided value not found at height {height}, data integrity error")
-            })
-            .map(Some)
+            .await
+            .map_err(SyncError::Storage)?
+            .ok_or(SyncError::MissingDecidedValue { height })
+            .map(Ok)
     } else {
+        if let Some(raw_decided_value) = cache.get(height) {
+            debug!(%height, "Serving pruned height from the cache of rebuilt values");
+            return Ok(Ok(raw_decided_value));
+        }
+
+        if let Some(retained_from) = el_retained_from {
+            if height < retained_from {
+                info!(%height, %retained_from, "Height pruned from storage and older than the blocks retained by the execution client");
+                return Ok(Err(HeightUnavailable::BeyondElRetention));
+            }
+        }
+
         // Height has been pruned from decided values - try to reconstruct from header + EL
         info!(%height, earliest_unpruned_height = %earliest_unpruned_height, "Height pruned from storage, reconstructing from block header + EL");
 
@@ -41,33 +371,35 @@ pub async fn get_decided_value_for_sync(
             Ok(Some((cert, header))) => (cert, header),
             Ok(None) => {
                 error!(%height, "Certificate or block header not found for pruned height");
-                return Ok(None);
+                return Ok(Err(HeightUnavailable::MissingFromStore));
             }
             Err(e) => {
                 error!(%height, error = %e, "Failed to get certificate and header");
-                return Ok(None);
+                return Ok(Err(HeightUnavailable::MissingFromStore));
             }
         };
 
         // Deserialize header
         let header = ExecutionPayloadV3::from_ssz_bytes(&header_bytes).map_err(|e| {
-            eyre!(
-                "Failed to deserialize block header at height {}: {:?}",
+            SyncError::InvalidHeader {
                 height,
-                e
-            )
+                reason: format!("{e:?}"),
+            }
         })?;
 
         let block_number = header.payload_inner.payload_inner.block_number;
 
         // Request payload body from EL
-        let bodies = engine.get_payload_bodies_by_range(block_number, 1).await?;
+        let bodies = engine
+            .get_payload_bodies_by_range(block_number, 1)
+            .await
+            .map_err(SyncError::ExecutionClient)?;
 
         // Handle response according to spec
         if bodies.is_empty() {
             // Empty array means requested range is beyond latest known block
             error!(%height, block_number, "EL returned empty array - block beyond latest known");
-            return Ok(None);
+            return Ok(Err(HeightUnavailable::MissingFromEl));
         }
 
         let body = match bodies.first() {
@@ -75,11 +407,11 @@ pub async fn get_decided_value_for_sync(
             Some(None) => {
                 // Body is null - block unavailable (pruned or not downloaded by EL)
                 error!(%height, block_number, "EL returned null - block pruned or unavailable");
-                return Ok(None);
+                return Ok(Err(HeightUnavailable::MissingFromEl));
             }
             None => {
                 error!(%height, block_number, "EL returned unexpected empty response");
-                return Ok(None);
+                return Ok(Err(HeightUnavailable::MissingFromEl));
             }
         };
 
@@ -92,9 +424,361 @@ pub async fn get_decided_value_for_sync(
         // Create Value from payload bytes
         let value = Value::new(payload_bytes);
 
-        Ok(Some(RawDecidedValue {
+        let raw_decided_value = RawDecidedValue {
             certificate,
-            value_bytes: ProtobufCodec.encode(&value)?,
-        }))
+            value_bytes: ProtobufCodec::default().encode(&value)?,
+        };
+        cache.insert(height, raw_decided_value.clone());
+
+        Ok(Ok(raw_decided_value))
+    }
+}
+
+/// Decodes and validates a value received through sync.
+///
+/// Returns the proposed value to hand back to consensus, with its validity set,
+/// or `None` if the bytes sent by the peer do not decode to a value.
+/// Only values returned as `Validity::Valid` should be stored by the caller.
+pub async fn process_synced_value<V>(
+    validator: &mut V,
+    height: Height,
+    round: Round,
+    proposer: Address,
+    value_bytes: Bytes,
+) -> Result<Option<ProposedValue<EmeraldContext>>, SyncError>
+where
+    V: PayloadValidator + ?Sized,
+{
+    let value = match decode_value(value_bytes) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!(%height, %round, error = %e, "Synced value cannot be decoded");
+            return Ok(None);
+        }
+    };
+
+    // Validate the synced block
+    let validity = validator
+        .validate_payload(&value.extensions, height, round)
+        .await
+        .map_err(SyncError::Validation)?;
+
+    if validity == Validity::Valid {
+        debug!(%height, "💡 Sync block validated");
+    }
+
+    Ok(Some(ProposedValue {
+        height,
+        round,
+        valid_round: Round::Nil,
+        proposer,
+        value,
+        validity,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use malachitebft_eth_types::ValueId;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockStore {
+        decided: BTreeMap<Height, RawDecidedValue<EmeraldContext>>,
+        headers: BTreeMap<Height, (CommitCertificate<EmeraldContext>, Bytes)>,
+    }
+
+    #[async_trait]
+    impl DecidedValueSource for MockStore {
+        async fn get_raw_decided_value(
+            &self,
+            height: Height,
+        ) -> Result<Option<RawDecidedValue<EmeraldContext>>, BoxError> {
+            Ok(self.decided.get(&height).cloned())
+        }
+
+        async fn get_certificate_and_header(
+            &self,
+            height: Height,
+        ) -> Result<Option<(CommitCertificate<EmeraldContext>, Bytes)>, BoxError> {
+            Ok(self.headers.get(&height).cloned())
+        }
+    }
+
+    struct MockEngine(Vec<Option<ExecutionPayloadBodyV1>>);
+
+    #[async_trait]
+    impl PayloadBodySource for MockEngine {
+        async fn get_payload_bodies_by_range(
+            &self,
+            _start_block: u64,
+            _count: u64,
+        ) -> Result<Vec<Option<ExecutionPayloadBodyV1>>, BoxError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    struct MockValidator(Validity);
+
+    #[async_trait]
+    impl PayloadValidator for MockValidator {
+        async fn validate_payload(
+            &mut self,
+            _data: &Bytes,
+            _height: Height,
+            _round: Round,
+        ) -> Result<Validity, BoxError> {
+            Ok(self.0)
+        }
+    }
+
+    fn cache(max_bytes: u64) -> SharedRebuiltValueCache {
+        SharedRebuiltValueCache::new(RebuiltValueCache::new(max_bytes, SyncMetrics::default()))
+    }
+
+    fn certificate(height: u64) -> CommitCertificate<EmeraldContext> {
+        CommitCertificate {
+            height: Height::new(height),
+            round: Round::new(0),
+            value_id: ValueId::new(height),
+            commit_signatures: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_unpruned_value_from_storage() {
+        let mut store = MockStore::default();
+        let raw = RawDecidedValue {
+            certificate: certificate(5),
+            value_bytes: Bytes::from_static(b"value"),
+        };
+        store.decided.insert(Height::new(5), raw.clone());
+
+        let served = get_decided_value_for_sync(
+            &store,
+            &MockEngine(vec![]),
+            &cache(0),
+            Height::new(5),
+            Height::new(3),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(served.ok().map(|v| v.value_bytes), Some(raw.value_bytes));
+    }
+
+    #[tokio::test]
+    async fn test_missing_unpruned_value_is_an_error() {
+        let result = get_decided_value_for_sync(
+            &MockStore::default(),
+            &MockEngine(vec![]),
+            &cache(0),
+            Height::new(5),
+            Height::new(3),
+            None,
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(SyncError::MissingDecidedValue { height }) if height == Height::new(5)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pruned_value_without_header_is_not_served() {
+        let served = get_decided_value_for_sync(
+            &MockStore::default(),
+            &MockEngine(vec![]),
+            &cache(0),
+            Height::new(2),
+            Height::new(3),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(served.err(), Some(HeightUnavailable::MissingFromStore));
+    }
+
+    #[tokio::test]
+    async fn test_pruned_value_unavailable_on_el_is_not_served() {
+        let mut store = MockStore::default();
+        let mut header = ExecutionPayloadV3::default();
+        header.payload_inner.payload_inner.block_number = 2;
+        store.headers.insert(
+            Height::new(2),
+            (certificate(2), Bytes::from(header.as_ssz_bytes())),
+        );
+
+        let served = get_decided_value_for_sync(
+            &store,
+            &MockEngine(vec![None]),
+            &cache(0),
+            Height::new(2),
+            Height::new(3),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(served.err(), Some(HeightUnavailable::MissingFromEl));
+    }
+
+    #[tokio::test]
+    async fn test_pruned_value_beyond_el_retention_is_not_served() {
+        let mut store = MockStore::default();
+        let mut header = ExecutionPayloadV3::default();
+        header.payload_inner.payload_inner.block_number = 2;
+        store.headers.insert(
+            Height::new(2),
+            (certificate(2), Bytes::from(header.as_ssz_bytes())),
+        );
+
+        // The execution client would have the body, but it is not asked for it
+        let engine = MockEngine(vec![Some(ExecutionPayloadBodyV1 {
+            transactions: vec![],
+            withdrawals: None,
+        })]);
+
+        let served = get_decided_value_for_sync(
+            &store,
+            &engine,
+            &cache(0),
+            Height::new(2),
+            Height::new(3),
+            Some(Height::new(3)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(served.err(), Some(HeightUnavailable::BeyondElRetention));
+
+        let served = get_decided_value_for_sync(
+            &store,
+            &engine,
+            &cache(0),
+            Height::new(2),
+            Height::new(3),
+            Some(Height::new(2)),
+        )
+        .await
+        .unwrap();
+
+        assert!(served.is_ok());
+    }
+
+    async fn serve_pruned(cache: &SharedRebuiltValueCache, height: u64) -> Bytes {
+        let mut store = MockStore::default();
+        let mut header = ExecutionPayloadV3::default();
+        header.payload_inner.payload_inner.block_number = height;
+        store.headers.insert(
+            Height::new(height),
+            (certificate(height), Bytes::from(header.as_ssz_bytes())),
+        );
+        let engine = MockEngine(vec![Some(ExecutionPayloadBodyV1 {
+            transactions: vec![],
+            withdrawals: None,
+        })]);
+
+        get_decided_value_for_sync(
+            &store,
+            &engine,
+            cache,
+            Height::new(height),
+            Height::new(10),
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .value_bytes
+    }
+
+    #[tokio::test]
+    async fn test_rebuilt_values_are_cached() {
+        // Room for two values
+        let size = serve_pruned(&cache(0), 1).await.len() as u64;
+        let cache = cache(2 * size);
+
+        let value_bytes = serve_pruned(&cache, 1).await;
+        serve_pruned(&cache, 2).await;
+        // Height 1 is now more recently served than height 2
+        assert_eq!(
+            cache.get(Height::new(1)).map(|v| v.value_bytes),
+            Some(value_bytes)
+        );
+        serve_pruned(&cache, 3).await;
+
+        assert!(cache.get(Height::new(1)).is_some());
+        assert!(cache.get(Height::new(2)).is_none());
+        assert!(cache.get(Height::new(3)).is_some());
+        assert_eq!(cache.lock().bytes, 2 * size);
+
+        // Values larger than the cache are not kept
+        let small =
+            SharedRebuiltValueCache::new(RebuiltValueCache::new(size - 1, SyncMetrics::default()));
+        serve_pruned(&small, 1).await;
+        assert!(small.get(Height::new(1)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_process_synced_value_reports_validity() {
+        let value = Value::new(Bytes::from_static(b"block"));
+        let value_bytes = ProtobufCodec::default().encode(&value).unwrap();
+
+        for validity in [Validity::Valid, Validity::Invalid] {
+            let proposed = process_synced_value(
+                &mut MockValidator(validity),
+                Height::new(1),
+                Round::new(0),
+                Address::new([1; 20]),
+                value_bytes.clone(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(proposed.validity, validity);
+            assert_eq!(proposed.value, value);
+            assert_eq!(proposed.valid_round, Round::Nil);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_synced_value_rejects_corrupted_bytes() {
+        let value = Value::new(Bytes::from_static(b"block"));
+        let value_bytes = ProtobufCodec::default().encode(&value).unwrap();
+
+        let corrupted = [
+            // Truncated in the middle of the extensions
+            value_bytes.slice(..value_bytes.len() - 2),
+            // Length of the value beyond the end of the message
+            Bytes::from_static(&[0x0a, 0x7f, 0x00]),
+            // Value too short to hold its 8-byte header
+            Bytes::from_static(&[0x0a, 0x03, 0x01, 0x02, 0x03]),
+            // Value missing
+            Bytes::new(),
+            // Wire type not defined by protobuf
+            Bytes::from_static(&[0x0f]),
+        ];
+
+        for bytes in corrupted {
+            let proposed = process_synced_value(
+                &mut MockValidator(Validity::Valid),
+                Height::new(1),
+                Round::new(0),
+                Address::new([1; 20]),
+                bytes.clone(),
+            )
+            .await
+            .unwrap();
+
+            assert!(proposed.is_none(), "decoded {bytes:?}");
+        }
     }
 }
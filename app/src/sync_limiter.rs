@@ -0,0 +1,255 @@
+//! Rate limit of the decided values served to syncing peers.
+//!
+//! Each height requested by a syncing peer is read from the store, or rebuilt from the
+//! execution client when pruned, while the consensus messages wait: a peer requesting
+//! random heights can keep the node busy serving them. The requests are admitted from a
+//! token bucket refilled at `requests_per_second` and holding up to `burst` tokens, the
+//! rejected ones being answered without a value so that the peer asks another node.
+//!
+//! When `max_bytes_per_minute` is set, the requests are also rejected while the size of
+//! the values served over the last minute, as recorded by the statistics of the values
+//! served, is above it.
+//!
+//! The lower half of the bucket is reserved to the heights in the lower half of the range
+//! served by the node: a node catching up from far behind requests them in order, while
+//! a peer spamming random heights requests higher ones just as often.
+//!
+//! The values pruned from the store are rebuilt from the execution client in the
+//! background, so that the consensus messages do not wait for it, and at most
+//! `sync_max_concurrent_rebuilds` of them at the same time: the requests above the cap
+//! are answered without a value as well, bounding the load on the execution client.
+//!
+//! The consensus engine does not tell the application which peer requested a height,
+//! so the limits apply to all the peers together.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use malachitebft_eth_cli::config::SyncRateLimitConfig;
+use malachitebft_eth_types::Height;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug)]
+pub struct SyncLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+    max_bytes_per_minute: Option<u64>,
+}
+
+impl SyncLimiter {
+    pub fn new(config: &SyncRateLimitConfig, now: Instant) -> Self {
+        let burst = f64::from(config.burst());
+
+        Self {
+            rate: f64::from(config.requests_per_second),
+            burst,
+            tokens: burst,
+            last_refill: now,
+            max_bytes_per_minute: config.max_bytes_per_minute,
+        }
+    }
+
+    /// Whether a request for `height` is served at `now`, the heights below
+    /// `priority_below` having priority, `recent_bytes` having been served over the
+    /// last minute.
+    pub fn admit(
+        &mut self,
+        height: Height,
+        priority_below: Height,
+        recent_bytes: u64,
+        now: Instant,
+    ) -> bool {
+        if self
+            .max_bytes_per_minute
+            .is_some_and(|max_bytes| recent_bytes >= max_bytes)
+        {
+            return false;
+        }
+
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = now;
+
+        let reserved = if height < priority_below {
+            0.0
+        } else {
+            (self.burst / 2.0).floor()
+        };
+        if self.tokens < 1.0 + reserved {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Slots of the values rebuilt from the execution client in the background
+#[derive(Clone, Debug)]
+pub struct RebuildSlots {
+    semaphore: Arc<Semaphore>,
+    /// Heights rebuilt since they were last taken, and whether a value was served for them
+    completed: Arc<Mutex<Vec<(Height, bool)>>>,
+}
+
+impl RebuildSlots {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            completed: Arc::default(),
+        }
+    }
+
+    /// Takes a slot, if fewer than the maximum number of values are being rebuilt
+    pub fn try_acquire(&self) -> Option<RebuildSlot> {
+        let permit = self.semaphore.clone().try_acquire_owned().ok()?;
+        Some(RebuildSlot {
+            _permit: permit,
+            completed: self.completed.clone(),
+        })
+    }
+
+    /// Takes the heights rebuilt since the last call, and whether a value was served for them
+    pub fn take_completed(&self) -> Vec<(Height, bool)> {
+        std::mem::take(&mut *self.completed.lock().expect("rebuild slots lock poisoned"))
+    }
+}
+
+/// Slot of a value being rebuilt, released when dropped
+#[derive(Debug)]
+pub struct RebuildSlot {
+    _permit: OwnedSemaphorePermit,
+    completed: Arc<Mutex<Vec<(Height, bool)>>>,
+}
+
+impl RebuildSlot {
+    /// Records the outcome of the rebuild of `height` and releases the slot
+    pub fn complete(self, height: Height, served: bool) {
+        self.completed
+            .lock()
+            .expect("rebuild slots lock poisoned")
+            .push((height, served));
+    }
+}
+
+/// Height below which the requests have priority: the middle of the range served by the node
+pub fn priority_below(served_heights: Option<(Height, Height)>) -> Height {
+    served_heights.map_or(Height::default(), |(earliest, latest)| {
+        Height::new(earliest.as_u64() + (latest.as_u64() - earliest.as_u64()) / 2)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+
+    fn limiter(requests_per_second: u32, burst: u32, now: Instant) -> SyncLimiter {
+        let config = SyncRateLimitConfig {
+            requests_per_second,
+            burst: Some(burst),
+            max_bytes_per_minute: None,
+        };
+        SyncLimiter::new(&config, now)
+    }
+
+    #[test]
+    fn test_burst_then_rate() {
+        let now = Instant::now();
+        let mut limiter = limiter(2, 4, now);
+        let low = Height::new(1);
+        let priority_below = Height::new(10);
+
+        for _ in 0..4 {
+            assert!(limiter.admit(low, priority_below, 0, now));
+        }
+        assert!(!limiter.admit(low, priority_below, 0, now));
+
+        // Half a second refills one token
+        let now = now + Duration::from_millis(500);
+        assert!(limiter.admit(low, priority_below, 0, now));
+        assert!(!limiter.admit(low, priority_below, 0, now));
+
+        // The bucket does not hold more than the burst
+        let now = now + Duration::from_secs(60);
+        for _ in 0..4 {
+            assert!(limiter.admit(low, priority_below, 0, now));
+        }
+        assert!(!limiter.admit(low, priority_below, 0, now));
+    }
+
+    #[test]
+    fn test_lower_heights_have_priority() {
+        let now = Instant::now();
+        let mut limiter = limiter(1, 4, now);
+        let priority_below = Height::new(10);
+
+        // Higher heights only get the upper half of the bucket
+        assert!(limiter.admit(Height::new(50), priority_below, 0, now));
+        assert!(limiter.admit(Height::new(50), priority_below, 0, now));
+        assert!(!limiter.admit(Height::new(50), priority_below, 0, now));
+
+        // Lower heights get the rest of it
+        assert!(limiter.admit(Height::new(3), priority_below, 0, now));
+        assert!(limiter.admit(Height::new(3), priority_below, 0, now));
+        assert!(!limiter.admit(Height::new(3), priority_below, 0, now));
+    }
+
+    #[test]
+    fn test_bytes_per_minute() {
+        let now = Instant::now();
+        let config = SyncRateLimitConfig {
+            requests_per_second: 10,
+            burst: Some(10),
+            max_bytes_per_minute: Some(1000),
+        };
+        let mut limiter = SyncLimiter::new(&config, now);
+        let priority_below = Height::new(10);
+
+        assert!(limiter.admit(Height::new(1), priority_below, 999, now));
+        assert!(!limiter.admit(Height::new(1), priority_below, 1000, now));
+
+        // The rejected requests do not take tokens
+        for _ in 0..9 {
+            assert!(limiter.admit(Height::new(1), priority_below, 0, now));
+        }
+        assert!(!limiter.admit(Height::new(1), priority_below, 0, now));
+    }
+
+    #[test]
+    fn test_rebuild_slots() {
+        let slots = RebuildSlots::new(2);
+
+        let first = slots.try_acquire().unwrap();
+        let second = slots.try_acquire().unwrap();
+        assert!(slots.try_acquire().is_none());
+
+        // A slot is released once its rebuild completes, failed or not
+        first.complete(Height::new(5), true);
+        let third = slots.try_acquire().unwrap();
+        assert!(slots.try_acquire().is_none());
+        drop(second);
+        third.complete(Height::new(6), false);
+
+        assert_eq!(
+            slots.take_completed(),
+            vec![(Height::new(5), true), (Height::new(6), false)]
+        );
+        assert!(slots.take_completed().is_empty());
+        assert!(slots.try_acquire().is_some());
+    }
+
+    #[test]
+    fn test_priority_below() {
+        assert_eq!(priority_below(None), Height::default());
+        assert_eq!(
+            priority_below(Some((Height::new(100), Height::new(200)))),
+            Height::new(150)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Statistics of the decided values served to syncing peers, to see how much an archive
+//! node serves and how much of it is rebuilt from the execution client.
+//!
+//! The heights requested by the peers are recorded with the size of the values served,
+//! or the reason why they were not served, both since the start of the node and over the
+//! last minute. The statistics are served by the admin API.
+//!
+//! The size of the values served over the last minute also feeds the rate limit of the
+//! values served, which caps it when `max_bytes_per_minute` is set.
+//!
+//! The consensus engine does not tell the application which peer requested a height, so
+//! the statistics are those of all the peers together, as is the rate limit of the
+//! values served.
+
+use core::time::Duration;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::sync_handler::HeightUnavailable;
+
+/// Period over which the recent requests are counted
+const RECENT_PERIOD: Duration = Duration::from_secs(60);
+
+/// Requests for heights, and what was served for them
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ServingTotals {
+    /// Number of heights requested
+    pub requests: u64,
+    /// Number of decided values served
+    pub values: u64,
+    /// Size in bytes of the decided values served
+    pub bytes: u64,
+    /// Number of decided values rebuilt from the execution client to be served
+    pub rebuilt: u64,
+    /// Number of heights not served, by reason
+    pub unavailable: BTreeMap<&'static str, u64>,
+}
+
+impl ServingTotals {
+    fn add(&mut self, outcome: &Outcome) {
+        self.requests += 1;
+        match outcome {
+            Outcome::Served { bytes, rebuilt } => {
+                self.values += 1;
+                self.bytes += bytes;
+                self.rebuilt += u64::from(*rebuilt);
+            }
+            Outcome::Unavailable(reason) => {
+                *self.unavailable.entry(reason.as_str()).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Statistics as served by the admin API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ServingSummary {
+    /// Since the start of the node
+    pub total: ServingTotals,
+    /// Over the last minute
+    pub last_minute: ServingTotals,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Outcome {
+    Served { bytes: u64, rebuilt: bool },
+    Unavailable(HeightUnavailable),
+}
+
+impl Outcome {
+    fn bytes(&self) -> u64 {
+        match self {
+            Self::Served { bytes, .. } => *bytes,
+            Self::Unavailable(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncStats {
+    total: ServingTotals,
+    recent: VecDeque<(Instant, Outcome)>,
+    /// Size in bytes of the recent values served
+    recent_bytes: u64,
+}
+
+impl SyncStats {
+    /// Records a decided value of `bytes` served at `now`, `rebuilt` from the execution client
+    pub fn served(&mut self, bytes: u64, rebuilt: bool, now: Instant) {
+        self.record(Outcome::Served { bytes, rebuilt }, now);
+    }
+
+    /// Records a height not served at `now`
+    pub fn unavailable(&mut self, reason: HeightUnavailable, now: Instant) {
+        self.record(Outcome::Unavailable(reason), now);
+    }
+
+    /// Size in bytes of the decided values served over the last minute
+    pub fn recent_bytes(&mut self, now: Instant) -> u64 {
+        self.expire(now);
+        self.recent_bytes
+    }
+
+    fn record(&mut self, outcome: Outcome, now: Instant) {
+        self.total.add(&outcome);
+        self.recent_bytes += outcome.bytes();
+        self.recent.push_back((now, outcome));
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) >= RECENT_PERIOD)
+        {
+            if let Some((_, outcome)) = self.recent.pop_front() {
+                self.recent_bytes -= outcome.bytes();
+            }
+        }
+    }
+
+    pub fn summary(&mut self, now: Instant) -> ServingSummary {
+        self.expire(now);
+
+        let mut last_minute = ServingTotals::default();
+        for (_, outcome) in &self.recent {
+            last_minute.add(outcome);
+        }
+
+        ServingSummary {
+            total: self.total.clone(),
+            last_minute,
+        }
+    }
+}
+
+/// Statistics shared between the application and the admin API
+#[derive(Clone, Debug, Default)]
+pub struct SharedSyncStats(Arc<Mutex<SyncStats>>);
+
+impl SharedSyncStats {
+    pub fn served(&self, bytes: u64, rebuilt: bool, now: Instant) {
+        self.0
+            .lock()
+            .expect("sync stats lock poisoned")
+            .served(bytes, rebuilt, now)
+    }
+
+    pub fn unavailable(&self, reason: HeightUnavailable, now: Instant) {
+        self.0
+            .lock()
+            .expect("sync stats lock poisoned")
+            .unavailable(reason, now)
+    }
+
+    pub fn recent_bytes(&self, now: Instant) -> u64 {
+        self.0
+            .lock()
+            .expect("sync stats lock poisoned")
+            .recent_bytes(now)
+    }
+
+    pub fn summary(&self) -> ServingSummary {
+        self.0
+            .lock()
+            .expect("sync stats lock poisoned")
+            .summary(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_stats() {
+        let now = Instant::now();
+        let mut stats = SyncStats::default();
+
+        stats.served(100, false, now);
+        stats.served(300, true, now);
+        stats.unavailable(HeightUnavailable::RateLimited, now);
+
+        let later = now + Duration::from_secs(30);
+        stats.served(50, true, later);
+        stats.unavailable(HeightUnavailable::RateLimited, later);
+        stats.unavailable(HeightUnavailable::NotDecided, later);
+
+        let summary = stats.summary(later);
+        assert_eq!(summary.last_minute, summary.total);
+        assert_eq!(stats.recent_bytes(later), 450);
+        assert_eq!(
+            summary.total,
+            ServingTotals {
+                requests: 6,
+                values: 3,
+                bytes: 450,
+                rebuilt: 2,
+                unavailable: BTreeMap::from([("not_decided", 1), ("rate_limited", 2)]),
+            }
+        );
+
+        // The requests of a minute ago are no longer recent
+        assert_eq!(stats.recent_bytes(now + RECENT_PERIOD), 50);
+        let summary = stats.summary(now + RECENT_PERIOD);
+        assert_eq!(summary.total.requests, 6);
+        assert_eq!(
+            summary.last_minute,
+            ServingTotals {
+                requests: 3,
+                values: 1,
+                bytes: 50,
+                rebuilt: 1,
+                unavailable: BTreeMap::from([("not_decided", 1), ("rate_limited", 1)]),
+            }
+        );
+    }
+}
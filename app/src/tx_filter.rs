@@ -0,0 +1,140 @@
+//! Compliance filters applied by the proposer to the payloads it builds.
+//!
+//! The filters are loaded from a TOML file:
+//!
+//! ```toml
+//! # Transactions sent from or to these addresses are never proposed
+//! denied_addresses = ["0x..."]
+//! # When not empty, only transactions sent from these addresses are proposed
+//! allowed_senders = ["0x..."]
+//! ```
+//!
+//! The Engine API does not let the proposer choose the transactions of the payloads
+//! built by the execution client. A payload containing a filtered transaction is not
+//! proposed: the proposer asks the execution client for a payload without the
+//! transactions of its pool instead, and only lets the round time out if it cannot get
+//! one, so that the filtered transactions, which stay in the pools, do not halt the chain.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use alloy_consensus::transaction::SignerRecoverable;
+use alloy_consensus::Transaction;
+use alloy_primitives::{Address, B256};
+use alloy_rpc_types_engine::ExecutionPayloadV3;
+use color_eyre::eyre::{self, eyre, Context};
+use malachitebft_eth_types::Block;
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TxFilter {
+    #[serde(default)]
+    denied_addresses: HashSet<Address>,
+
+    #[serde(default)]
+    allowed_senders: HashSet<Address>,
+}
+
+/// Transaction rejected by the filters
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilteredTx {
+    pub tx_hash: B256,
+    pub reason: String,
+}
+
+impl TxFilter {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read transaction filter {}", path.display()))?;
+        toml::from_str(&content)
+            .wrap_err_with(|| format!("Failed to parse transaction filter {}", path.display()))
+    }
+
+    /// Returns the transactions of the payload rejected by the filters, including those
+    /// whose sender cannot be recovered. Fails if the payload cannot be decoded.
+    pub fn check(&self, execution_payload: &ExecutionPayloadV3) -> eyre::Result<Vec<FilteredTx>> {
+        let block: Block = execution_payload
+            .clone()
+            .try_into_block()
+            .map_err(|e| eyre!("Failed to decode the transactions of the payload: {e}"))?;
+
+        let mut filtered = Vec::new();
+        for tx in &block.body.transactions {
+            let tx_hash = *tx.tx_hash();
+            let reason = match tx.recover_signer() {
+                Ok(sender) => self.reason(sender, tx.to()),
+                Err(e) => Some(format!("sender cannot be recovered: {e}")),
+            };
+
+            if let Some(reason) = reason {
+                filtered.push(FilteredTx { tx_hash, reason });
+            }
+        }
+
+        Ok(filtered)
+    }
+
+    /// Reason why the filters reject a transaction from `sender` to `to`, if they do
+    fn reason(&self, sender: Address, to: Option<Address>) -> Option<String> {
+        if self.denied_addresses.contains(&sender) {
+            Some(format!("sender {sender} is denied"))
+        } else if let Some(to) = to.filter(|to| self.denied_addresses.contains(to)) {
+            Some(format!("recipient {to} is denied"))
+        } else if !self.allowed_senders.is_empty() && !self.allowed_senders.contains(&sender) {
+            Some(format!("sender {sender} is not allowed"))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Address = Address::repeat_byte(1);
+    const BOB: Address = Address::repeat_byte(2);
+    const MALLORY: Address = Address::repeat_byte(3);
+
+    #[test]
+    fn test_denied_addresses() {
+        let filter: TxFilter =
+            toml::from_str(&format!("denied_addresses = [\"{MALLORY}\"]")).unwrap();
+
+        assert_eq!(filter.reason(ALICE, Some(BOB)), None);
+        assert_eq!(filter.reason(ALICE, None), None);
+        assert!(filter
+            .reason(MALLORY, Some(BOB))
+            .unwrap()
+            .contains("sender"));
+        assert!(filter
+            .reason(ALICE, Some(MALLORY))
+            .unwrap()
+            .contains("recipient"));
+    }
+
+    #[test]
+    fn test_allowed_senders() {
+        let filter: TxFilter = toml::from_str(&format!("allowed_senders = [\"{ALICE}\"]")).unwrap();
+
+        assert_eq!(filter.reason(ALICE, Some(BOB)), None);
+        // Only the senders are restricted
+        assert_eq!(filter.reason(ALICE, Some(MALLORY)), None);
+        assert!(filter
+            .reason(BOB, Some(ALICE))
+            .unwrap()
+            .contains("not allowed"));
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = TxFilter::default();
+        assert_eq!(filter.reason(MALLORY, Some(MALLORY)), None);
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        assert!(toml::from_str::<TxFilter>("denied = []").is_err());
+    }
+}
@@ -1,11 +1,13 @@
-use alloy_primitives::{address, Address, U256};
+use std::collections::BTreeMap;
+
+use alloy_primitives::U256;
 use alloy_provider::ProviderBuilder;
 use color_eyre::eyre;
 use malachitebft_eth_types::secp256k1::PublicKey;
-use malachitebft_eth_types::{BlockHash, Validator, ValidatorSet};
-
-const GENESIS_VALIDATOR_MANAGER_ACCOUNT: Address =
-    address!("0x0000000000000000000000000000000000002000");
+use malachitebft_eth_types::{
+    Address, BlockHash, Height, PowerChangeLimit, PowerChangePolicy, Validator, ValidatorSet,
+};
+use tracing::{error, warn};
 
 alloy_sol_types::sol!(
     #[derive(Debug)]
@@ -37,14 +39,17 @@ fn parse_validators(
         .collect()
 }
 
+/// Validator set of the validator manager contract deployed at `validator_manager`, in the
+/// state of the block `block_hash`.
 pub async fn read_validators_from_contract(
     eth_url: &str,
+    validator_manager: &Address,
     block_hash: &BlockHash,
 ) -> eyre::Result<ValidatorSet> {
     let provider = ProviderBuilder::new().connect(eth_url).await?;
 
     let validator_manager_contract =
-        ValidatorManager::new(GENESIS_VALIDATOR_MANAGER_ACCOUNT, provider);
+        ValidatorManager::new(validator_manager.to_alloy_address(), provider);
 
     let genesis_validator_set_sol = validator_manager_contract
         .getValidators()
@@ -57,6 +62,124 @@ pub async fn read_validators_from_contract(
     Ok(ValidatorSet::new(validators))
 }
 
+/// Validator set of `height`, changing the `current` set towards the `target` set read from
+/// the validator manager contract by at most the power change limit.
+///
+/// Above the limit, the target set is either rejected, keeping the current set, or phased
+/// in by applying the changes of voting power of the validators in the order of their
+/// addresses until the limit is reached. The target set is read again at the next height,
+/// so that a phased in change completes over the following heights.
+pub fn limit_power_change(
+    height: Height,
+    current: &ValidatorSet,
+    target: ValidatorSet,
+    limit: &PowerChangeLimit,
+) -> ValidatorSet {
+    let total_power = current.total_voting_power();
+    let max_change = (u128::from(total_power) * u128::from(limit.max_percent) / 100).max(1);
+
+    // Voting power of each validator in the current and target sets
+    let mut powers = BTreeMap::new();
+    for validator in current.validators.iter() {
+        powers.insert(
+            validator.address,
+            (validator.public_key.clone(), validator.voting_power, 0),
+        );
+    }
+    for validator in target.validators.iter() {
+        powers
+            .entry(validator.address)
+            .or_insert_with(|| (validator.public_key.clone(), 0, 0))
+            .2 = validator.voting_power;
+    }
+
+    let change = powers
+        .values()
+        .map(|(_, from, to)| u128::from(from.abs_diff(*to)))
+        .sum::<u128>();
+    if change <= max_change {
+        return target;
+    }
+
+    if limit.policy == PowerChangePolicy::Reject {
+        error!(
+            %height,
+            change,
+            max_change,
+            total_power,
+            "Rejecting the new validator set, its voting power changes more than the limit, keeping the current set"
+        );
+        return current.clone();
+    }
+
+    let mut budget = max_change;
+    let validators = powers
+        .into_values()
+        .filter_map(|(public_key, from, to)| {
+            let step = u64::try_from(budget.min(u128::from(from.abs_diff(to))))
+                .expect("step below the change of a voting power");
+            budget -= u128::from(step);
+
+            let power = if to >= from { from + step } else { from - step };
+            (power > 0).then(|| Validator::new(public_key, power))
+        })
+        .collect::<Vec<_>>();
+
+    // Only possible if the limit allows the removal of all the current validators
+    if validators.is_empty() {
+        error!(%height, "Phasing in the new validator set would remove all the validators, keeping the current set");
+        return current.clone();
+    }
+
+    warn!(
+        %height,
+        change,
+        max_change,
+        total_power,
+        "Phasing in the new validator set, its voting power changes more than the limit"
+    );
+    ValidatorSet::new(validators)
+}
+
+/// Number of heights whose validator set is kept in memory
+pub const VALIDATOR_SET_CACHE_SIZE: usize = 256;
+
+/// Validator sets of the most recent heights.
+///
+/// The lowest heights are evicted once the cache is full. Heights are also pruned along
+/// with their certificates, since their decided values cannot be verified anymore.
+#[derive(Debug)]
+pub struct ValidatorSetHistory {
+    capacity: usize,
+    sets: BTreeMap<Height, ValidatorSet>,
+}
+
+impl ValidatorSetHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            sets: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, height: Height) -> Option<&ValidatorSet> {
+        self.sets.get(&height)
+    }
+
+    pub fn insert(&mut self, height: Height, validator_set: ValidatorSet) {
+        self.sets.insert(height, validator_set);
+
+        while self.sets.len() > self.capacity {
+            self.sets.pop_first();
+        }
+    }
+
+    /// Removes the validator sets of the heights below `retain_height`
+    pub fn prune(&mut self, retain_height: Height) {
+        self.sets = self.sets.split_off(&retain_height);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloy_primitives::U256;
@@ -186,4 +309,98 @@ mod tests {
         assert_eq!(validators.len(), 1);
         assert_eq!(validators[0].voting_power, 0);
     }
+
+    fn validator(key: u8, power: u64) -> Validator {
+        let private_key = malachitebft_eth_types::secp256k1::PrivateKey::from_slice(&[key; 32])
+            .expect("valid private key");
+        Validator::new(private_key.public_key(), power)
+    }
+
+    fn powers(validator_set: &ValidatorSet) -> BTreeMap<u8, u64> {
+        (1..=4)
+            .filter_map(|key| {
+                let address = validator(key, 0).address;
+                validator_set
+                    .get_by_address(&address)
+                    .map(|validator| (key, validator.voting_power))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_limit_power_change() {
+        let height = Height::new(5);
+        let limit = PowerChangeLimit {
+            max_percent: 33,
+            policy: PowerChangePolicy::PhaseIn,
+        };
+        let current = ValidatorSet::new([validator(1, 100), validator(2, 100), validator(3, 100)]);
+
+        // 99 out of 300 is within the limit
+        let target = ValidatorSet::new([validator(1, 100), validator(2, 100), validator(3, 199)]);
+        assert_eq!(
+            limit_power_change(height, &current, target.clone(), &limit),
+            target
+        );
+
+        // Replacing a validator changes 200 out of 300
+        let target = ValidatorSet::new([validator(1, 100), validator(2, 100), validator(4, 100)]);
+        let reject = PowerChangeLimit {
+            policy: PowerChangePolicy::Reject,
+            ..limit
+        };
+        assert_eq!(
+            limit_power_change(height, &current, target.clone(), &reject),
+            current
+        );
+
+        // Phased in over the next heights, changing at most a third at each height
+        let change = |from: &ValidatorSet, to: &ValidatorSet| {
+            let (from, to) = (powers(from), powers(to));
+            (1..=4)
+                .map(|key| {
+                    let from = from.get(&key).copied().unwrap_or(0);
+                    from.abs_diff(to.get(&key).copied().unwrap_or(0))
+                })
+                .sum::<u64>()
+        };
+
+        let mut validator_set = current;
+        let mut height = height;
+        while validator_set != target {
+            let next = limit_power_change(height, &validator_set, target.clone(), &limit);
+            assert!(change(&validator_set, &next) <= validator_set.total_voting_power() / 3);
+            assert!(change(&next, &target) < change(&validator_set, &target));
+
+            validator_set = next;
+            height = height.increment();
+        }
+        assert!(height.as_u64() <= 8);
+    }
+
+    #[test]
+    fn test_validator_set_history() {
+        let validator_set = |power| {
+            let info = make_validator_info(
+                "79BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+                "483ADA7726A3C4655DA4FBFC0E1108A8FD17B448A68554199C47D08FFB10D4B8",
+                power,
+            );
+            ValidatorSet::new(parse_validators(vec![info]).unwrap())
+        };
+
+        let mut history = ValidatorSetHistory::new(3);
+        for height in 1..=4 {
+            history.insert(Height::new(height), validator_set(height));
+        }
+
+        // The lowest height has been evicted
+        assert!(history.get(Height::new(1)).is_none());
+        assert_eq!(history.get(Height::new(4)), Some(&validator_set(4)));
+
+        history.prune(Height::new(3));
+        assert!(history.get(Height::new(2)).is_none());
+        assert_eq!(history.get(Height::new(3)), Some(&validator_set(3)));
+        assert_eq!(history.get(Height::new(4)), Some(&validator_set(4)));
+    }
 }
@@ -0,0 +1,220 @@
+//! Statistics of the votes seen for the recent heights, to spot slow or missing validators.
+//!
+//! The votes are taken from the consensus events, both those received from peers and those
+//! published by this node. For each height and round, the prevotes and precommits are
+//! recorded per validator, with their delay relative to the first vote of the same type,
+//! so that a validator which votes late or not at all stands out.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use malachitebft_app_channel::app::events::{Event as ConsensusEvent, RxEvent};
+use malachitebft_app_channel::app::types::core::VoteType;
+use malachitebft_app_channel::app::types::SignedConsensusMsg;
+use malachitebft_eth_types::{Address, EmeraldContext, Vote};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::metrics::VoteMetrics;
+
+/// Number of heights for which the votes are kept
+const MAX_HEIGHTS: u64 = 16;
+
+/// Votes of one type seen in a round
+#[derive(Debug, Default)]
+struct Votes {
+    first_seen: Option<Instant>,
+    delays: BTreeMap<Address, Duration>,
+}
+
+impl Votes {
+    /// Records the vote of `validator`, returning its delay if it was not seen before.
+    fn insert(&mut self, validator: Address, now: Instant) -> Option<Duration> {
+        if self.delays.contains_key(&validator) {
+            return None;
+        }
+
+        let delay = now.saturating_duration_since(*self.first_seen.get_or_insert(now));
+        self.delays.insert(validator, delay);
+        Some(delay)
+    }
+
+    fn summary(&self) -> BTreeMap<String, u64> {
+        self.delays
+            .iter()
+            .map(|(validator, delay)| (validator.to_string(), delay.as_millis() as u64))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct RoundVotes {
+    prevotes: Votes,
+    precommits: Votes,
+}
+
+/// Votes seen in a round, with the delay in milliseconds of each validator
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RoundSummary {
+    pub height: u64,
+    pub round: i64,
+    pub prevotes: BTreeMap<String, u64>,
+    pub precommits: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct VoteStats {
+    rounds: BTreeMap<(u64, i64), RoundVotes>,
+}
+
+impl VoteStats {
+    /// Records a vote seen at `now`, returning its delay if it was not seen before.
+    pub fn record(&mut self, vote: &Vote, now: Instant) -> Option<Duration> {
+        let height = vote.height.as_u64();
+        if self
+            .rounds
+            .last_key_value()
+            .is_some_and(|((latest, _), _)| height + MAX_HEIGHTS <= *latest)
+        {
+            // Too old to be of interest
+            return None;
+        }
+
+        let round = self
+            .rounds
+            .entry((height, vote.round.as_i64()))
+            .or_default();
+        let votes = match vote.typ {
+            VoteType::Prevote => &mut round.prevotes,
+            VoteType::Precommit => &mut round.precommits,
+        };
+        let delay = votes.insert(vote.validator_address, now);
+
+        // Only keep the latest heights
+        if let Some((&(latest, _), _)) = self.rounds.last_key_value() {
+            self.rounds
+                .retain(|(height, _), _| height + MAX_HEIGHTS > latest);
+        }
+
+        delay
+    }
+
+    /// Returns the votes seen for the recent heights, starting with the latest round.
+    pub fn summary(&self) -> Vec<RoundSummary> {
+        self.rounds
+            .iter()
+            .rev()
+            .map(|(&(height, round), votes)| RoundSummary {
+                height,
+                round,
+                prevotes: votes.prevotes.summary(),
+                precommits: votes.precommits.summary(),
+            })
+            .collect()
+    }
+}
+
+/// Vote statistics shared between the event listener and the admin API
+#[derive(Clone, Debug, Default)]
+pub struct SharedVoteStats(Arc<Mutex<VoteStats>>);
+
+impl SharedVoteStats {
+    pub fn record(&self, vote: &Vote, now: Instant) -> Option<Duration> {
+        self.0
+            .lock()
+            .expect("vote stats lock poisoned")
+            .record(vote, now)
+    }
+
+    pub fn summary(&self) -> Vec<RoundSummary> {
+        self.0.lock().expect("vote stats lock poisoned").summary()
+    }
+}
+
+/// Records the votes of the consensus events until the consensus engine stops.
+#[tracing::instrument(name = "vote_stats", skip_all)]
+pub async fn run(
+    mut events: RxEvent<EmeraldContext>,
+    stats: SharedVoteStats,
+    metrics: VoteMetrics,
+) {
+    loop {
+        let vote = match events.recv().await {
+            Ok(
+                ConsensusEvent::Received(SignedConsensusMsg::Vote(vote))
+                | ConsensusEvent::Published(SignedConsensusMsg::Vote(vote)),
+            ) => vote.message,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(%skipped, "Missed consensus events, vote statistics are incomplete");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if let Some(delay) = stats.record(&vote, Instant::now()) {
+            metrics.observe_vote(&vote, delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_app_channel::app::types::core::{NilOrVal, Round};
+    use malachitebft_eth_types::Height;
+
+    use super::*;
+
+    #[test]
+    fn test_vote_stats() {
+        let mut stats = VoteStats::default();
+        let start = Instant::now();
+        let height = Height::new(1);
+        let round = Round::new(0);
+
+        let prevote =
+            |byte| Vote::new_prevote(height, round, NilOrVal::Nil, Address::repeat_byte(byte));
+
+        assert_eq!(stats.record(&prevote(1), start), Some(Duration::ZERO));
+        assert_eq!(
+            stats.record(&prevote(2), start + Duration::from_millis(30)),
+            Some(Duration::from_millis(30))
+        );
+        // Duplicate votes are ignored
+        assert_eq!(
+            stats.record(&prevote(2), start + Duration::from_millis(50)),
+            None
+        );
+        assert_eq!(
+            stats.record(
+                &Vote::new_precommit(height, round, NilOrVal::Nil, Address::repeat_byte(1)),
+                start + Duration::from_millis(60)
+            ),
+            Some(Duration::ZERO)
+        );
+
+        let summary = stats.summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].prevotes.len(), 2);
+        assert_eq!(
+            summary[0].prevotes[&Address::repeat_byte(2).to_string()],
+            30
+        );
+        assert_eq!(summary[0].precommits.len(), 1);
+
+        // Old heights are dropped
+        let later = Vote::new_prevote(
+            Height::new(1 + MAX_HEIGHTS),
+            round,
+            NilOrVal::Nil,
+            Address::repeat_byte(1),
+        );
+        stats.record(&later, start);
+        assert_eq!(stats.summary().len(), 1);
+        assert_eq!(stats.summary()[0].height, 1 + MAX_HEIGHTS);
+        assert_eq!(stats.record(&prevote(3), start), None);
+    }
+}
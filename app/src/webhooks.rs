@@ -0,0 +1,519 @@
+//! Webhooks notified of the decided blocks, the changes of the validator set and the
+//! stalls of consensus, for integrations which do not run an indexer.
+//!
+//! Each event is POSTed as a JSON object to the endpoints subscribed to its kind:
+//!
+//! ```json
+//! {"timestamp_ms":1718000000000,"node":"node-0","event":"decided_block","height":12,"round":0,"block_hash":"0x...","block_number":12,"block_timestamp":1718000000,"tx_count":3}
+//! ```
+//!
+//! The kind of the event is repeated in the `X-Emerald-Event` header. When the endpoint has
+//! a secret, the body is signed with HMAC-SHA256 in the `X-Emerald-Signature` header, as
+//! `sha256=<hex>`.
+//!
+//! The events are delivered in order by a task per endpoint, so that a slow endpoint does
+//! not delay the others nor consensus. A failed delivery is retried with an exponential
+//! backoff, up to `max_attempts` times, after which the event is appended to the
+//! dead-letter log with the reason of the failure. So are the events which do not fit in
+//! the queue of an endpoint.
+
+use core::time::Duration;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::{self, eyre, Context};
+use emerald_retry::{retry, Backoff};
+use hmac::{Hmac, Mac};
+use malachitebft_eth_cli::config::{WebhookEventKind, WebhooksConfig};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, error, warn};
+
+/// Number of events queued for the dispatcher, and for each endpoint
+const QUEUE_SIZE: usize = 1024;
+
+/// Timeout of a delivery to an endpoint
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the first retry of a delivery, doubled at each attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Event sent to the webhooks
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    DecidedBlock {
+        height: u64,
+        round: i64,
+        block_hash: String,
+        block_number: u64,
+        block_timestamp: u64,
+        tx_count: usize,
+    },
+    /// The validator set of `height` differs from the one of the previous height
+    ValidatorSetChanged {
+        height: u64,
+        validators: Vec<WebhookValidator>,
+        total_voting_power: u64,
+    },
+    /// No block was decided since `last_decided_height`, for `stalled_for_secs`
+    ConsensusStalled {
+        last_decided_height: Option<u64>,
+        stalled_for_secs: u64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WebhookValidator {
+    pub address: String,
+    pub voting_power: u64,
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> WebhookEventKind {
+        match self {
+            Self::DecidedBlock { .. } => WebhookEventKind::DecidedBlock,
+            Self::ValidatorSetChanged { .. } => WebhookEventKind::ValidatorSetChanged,
+            Self::ConsensusStalled { .. } => WebhookEventKind::ConsensusStalled,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp_ms: u64,
+    node: &'a str,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+/// Handle notifying the webhooks of the events
+pub struct Webhooks {
+    sender: Option<mpsc::Sender<WebhookEvent>>,
+}
+
+impl Webhooks {
+    /// Spawns the tasks delivering the events to the endpoints of `config`, relative paths
+    /// being resolved against `home_dir`.
+    pub fn spawn(config: &WebhooksConfig, home_dir: &Path, node: String) -> eyre::Result<Self> {
+        let dead_letter = Arc::new(DeadLetter::open(home_dir.join(&config.dead_letter_path))?);
+
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .wrap_err("Failed to build the webhook client")?;
+
+        let mut endpoints = Vec::with_capacity(config.endpoints.len());
+        for endpoint in &config.endpoints {
+            let secret = endpoint
+                .secret
+                .as_ref()
+                .map(|secret| secret.read("webhook secret"))
+                .transpose()?
+                .map(String::into_bytes);
+
+            let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+            let delivery = Delivery {
+                client: client.clone(),
+                url: endpoint.url.clone(),
+                secret,
+                max_attempts: config.max_attempts,
+                dead_letter: dead_letter.clone(),
+            };
+            tokio::spawn(delivery.run(receiver));
+
+            endpoints.push(Endpoint {
+                url: endpoint.url.clone(),
+                events: endpoint.events.clone(),
+                sender,
+            });
+        }
+
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        let dispatcher = Dispatcher {
+            node,
+            endpoints,
+            dead_letter,
+            stall_timeout: config.stall_timeout,
+        };
+        tokio::spawn(dispatcher.run(receiver));
+
+        Ok(Self {
+            sender: Some(sender),
+        })
+    }
+
+    /// Webhooks which discard all events
+    pub fn disabled() -> Self {
+        Self { sender: None }
+    }
+
+    /// Queues the event for the endpoints subscribed to its kind, without waiting for its delivery
+    pub fn notify(&self, event: WebhookEvent) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        if let Err(e) = sender.try_send(event) {
+            warn!("Dropped a webhook event: {e}");
+        }
+    }
+}
+
+struct Endpoint {
+    url: String,
+    /// Kinds of events sent to the endpoint, all of them when empty
+    events: Vec<WebhookEventKind>,
+    sender: mpsc::Sender<Arc<Payload>>,
+}
+
+impl Endpoint {
+    fn subscribed(&self, kind: WebhookEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Serialized event, as sent to the endpoints
+struct Payload {
+    kind: WebhookEventKind,
+    body: String,
+}
+
+/// Serializes the events, sends them to the endpoints, and reports the stalls of consensus
+struct Dispatcher {
+    node: String,
+    endpoints: Vec<Endpoint>,
+    dead_letter: Arc<DeadLetter>,
+    stall_timeout: Duration,
+}
+
+impl Dispatcher {
+    async fn run(self, mut events: mpsc::Receiver<WebhookEvent>) {
+        let mut last_decided_height = None;
+        let mut last_decided_at = Instant::now();
+        let mut stalled = false;
+
+        loop {
+            let deadline = last_decided_at + self.stall_timeout;
+            let event = if stalled {
+                events.recv().await
+            } else {
+                match tokio::time::timeout_at(deadline, events.recv()).await {
+                    Ok(event) => event,
+                    Err(_) => {
+                        // Reported once per stall, until the next decided block
+                        stalled = true;
+                        Some(WebhookEvent::ConsensusStalled {
+                            last_decided_height,
+                            stalled_for_secs: last_decided_at.elapsed().as_secs(),
+                        })
+                    }
+                }
+            };
+
+            let Some(event) = event else {
+                return;
+            };
+
+            if let WebhookEvent::DecidedBlock { height, .. } = &event {
+                last_decided_height = Some(*height);
+                last_decided_at = Instant::now();
+                stalled = false;
+            }
+
+            self.dispatch(&event);
+        }
+    }
+
+    fn dispatch(&self, event: &WebhookEvent) {
+        let entry = Entry {
+            timestamp_ms: timestamp_ms(),
+            node: &self.node,
+            event,
+        };
+        let body = match serde_json::to_string(&entry) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize webhook event: {e}");
+                return;
+            }
+        };
+
+        let kind = event.kind();
+        let payload = Arc::new(Payload { kind, body });
+        for endpoint in self.endpoints.iter().filter(|e| e.subscribed(kind)) {
+            if endpoint.sender.try_send(payload.clone()).is_err() {
+                self.dead_letter.append(
+                    &endpoint.url,
+                    0,
+                    "queue of the endpoint is full",
+                    &payload.body,
+                );
+            }
+        }
+    }
+}
+
+/// Delivers the events to an endpoint, in order
+struct Delivery {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<Vec<u8>>,
+    max_attempts: u32,
+    dead_letter: Arc<DeadLetter>,
+}
+
+impl Delivery {
+    async fn run(self, mut payloads: mpsc::Receiver<Arc<Payload>>) {
+        while let Some(payload) = payloads.recv().await {
+            self.deliver(&payload).await;
+        }
+    }
+
+    async fn deliver(&self, payload: &Payload) {
+        let backoff = Backoff::exponential(INITIAL_BACKOFF, MAX_BACKOFF, 2.0);
+        // Bounds the attempts by their number rather than by time
+        let timeout = (DELIVERY_TIMEOUT + MAX_BACKOFF) * self.max_attempts;
+        let mut attempts = 0;
+
+        let outcome = retry(
+            &backoff,
+            timeout,
+            |outcome: &eyre::Result<()>, delay| {
+                attempts += 1;
+                match outcome {
+                    Err(e) if attempts < self.max_attempts => {
+                        warn!(url = %self.url, attempt = attempts, "Failed to deliver webhook event, retrying in {delay:?}: {e}");
+                        true
+                    }
+                    _ => false,
+                }
+            },
+            || self.send(payload),
+        )
+        .await;
+
+        let error = match outcome {
+            Ok(()) => {
+                debug!(url = %self.url, event = payload.kind.as_str(), "Delivered webhook event");
+                return;
+            }
+            Err(e) => e.to_string(),
+        };
+
+        error!(url = %self.url, attempts, "Failed to deliver webhook event: {error}");
+        self.dead_letter
+            .append(&self.url, attempts, &error, &payload.body);
+    }
+
+    async fn send(&self, payload: &Payload) -> eyre::Result<()> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Emerald-Event", payload.kind.as_str());
+        if let Some(secret) = &self.secret {
+            let signature = hmac_sha256(secret, payload.body.as_bytes());
+            request = request.header(
+                "X-Emerald-Signature",
+                format!("sha256={}", hex::encode(signature)),
+            );
+        }
+
+        let response = request.body(payload.body.clone()).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(eyre!("endpoint answered with {status}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Log of the events which could not be delivered, one JSON object per line
+struct DeadLetter {
+    path: PathBuf,
+    /// Serializes the appends of the endpoints
+    lock: Mutex<()>,
+}
+
+#[derive(Serialize)]
+struct DeadLetterEntry<'a> {
+    timestamp_ms: u64,
+    url: &'a str,
+    attempts: u32,
+    error: &'a str,
+    payload: serde_json::Value,
+}
+
+impl DeadLetter {
+    fn open(path: PathBuf) -> eyre::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).wrap_err_with(|| {
+                format!(
+                    "Failed to create webhook dead-letter directory {}",
+                    parent.display()
+                )
+            })?;
+        }
+
+        Ok(Self {
+            path,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn append(&self, url: &str, attempts: u32, error: &str, body: &str) {
+        if let Err(e) = self.try_append(url, attempts, error, body) {
+            error!(
+                path = %self.path.display(),
+                "Failed to write to the webhook dead-letter log: {e}"
+            );
+        }
+    }
+
+    fn try_append(&self, url: &str, attempts: u32, error: &str, body: &str) -> eyre::Result<()> {
+        let entry = DeadLetterEntry {
+            timestamp_ms: timestamp_ms(),
+            url,
+            attempts,
+            error,
+            payload: serde_json::from_str(body)?,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().expect("dead-letter lock poisoned");
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+
+        Ok(())
+    }
+}
+
+fn timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// HMAC-SHA256 of `message` with the `key` of the endpoint
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // Test case 2 of RFC 4231
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        assert_eq!(
+            hex::encode(hmac_sha256(b"secret", br#"{"event":"decided_block"}"#)),
+            "08d6733b433df1e42b8065bada2b069c395ea84ad934f0c30500ccba25207e3b"
+        );
+    }
+
+    #[test]
+    fn test_payload() {
+        let event = WebhookEvent::ValidatorSetChanged {
+            height: 12,
+            validators: vec![WebhookValidator {
+                address: "0x01".to_string(),
+                voting_power: 10,
+            }],
+            total_voting_power: 10,
+        };
+        let entry = Entry {
+            timestamp_ms: 1,
+            node: "node-0",
+            event: &event,
+        };
+        assert_eq!(
+            serde_json::to_value(&entry).unwrap(),
+            serde_json::json!({
+                "timestamp_ms": 1,
+                "node": "node-0",
+                "event": "validator_set_changed",
+                "height": 12,
+                "validators": [{ "address": "0x01", "voting_power": 10 }],
+                "total_voting_power": 10,
+            })
+        );
+        assert_eq!(event.kind().as_str(), "validator_set_changed");
+    }
+
+    #[test]
+    fn test_dead_letter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("webhooks/dead_letter.jsonl");
+        let dead_letter = DeadLetter::open(path.clone()).unwrap();
+
+        dead_letter.append(
+            "http://a",
+            5,
+            "endpoint answered with 500",
+            r#"{"height":1}"#,
+        );
+        dead_letter.append(
+            "http://b",
+            0,
+            "queue of the endpoint is full",
+            r#"{"height":2}"#,
+        );
+
+        let lines = fs::read_to_string(&path).unwrap();
+        let entries = lines
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["url"], "http://a");
+        assert_eq!(entries[0]["attempts"], 5);
+        assert_eq!(entries[0]["payload"], serde_json::json!({ "height": 1 }));
+        assert_eq!(entries[1]["error"], "queue of the endpoint is full");
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead_letter.jsonl");
+        let delivery = Delivery {
+            client: reqwest::Client::new(),
+            url: "http://127.0.0.1:1".to_string(),
+            secret: None,
+            max_attempts: 2,
+            dead_letter: Arc::new(DeadLetter::open(path.clone()).unwrap()),
+        };
+
+        delivery
+            .deliver(&Payload {
+                kind: WebhookEventKind::DecidedBlock,
+                body: r#"{"height":1}"#.to_string(),
+            })
+            .await;
+
+        let entry: serde_json::Value =
+            serde_json::from_str(fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(entry["url"], "http://127.0.0.1:1");
+        assert_eq!(entry["attempts"], 2);
+    }
+}
@@ -12,11 +12,20 @@ use clap::{Parser, Subcommand};
 use directories::BaseDirs;
 use malachitebft_config::{LogFormat, LogLevel};
 
+use crate::chains::CHAINS_FILE;
+use crate::cmd::dev::DevCmd;
 use crate::cmd::distributed_testnet::DistributedTestnetCmd;
+use crate::cmd::doctor::DoctorCmd;
 use crate::cmd::init::InitCmd;
+use crate::cmd::schedule::ScheduleCmd;
+use crate::cmd::service::ServiceCmd;
 use crate::cmd::show_pubkey::ShowPubkeyCmd;
 use crate::cmd::start::StartCmd;
+use crate::cmd::status::StatusCmd;
+use crate::cmd::store::StoreCmd;
 use crate::cmd::testnet::TestnetCmd;
+use crate::cmd::unsafe_reset::UnsafeResetCmd;
+use crate::cmd::verify_store::VerifyStoreCmd;
 use crate::error::Error;
 
 const EMERALD_FOLDER: &str = ".emerald";
@@ -61,8 +70,32 @@ pub enum Commands {
     /// Generate distributed testnet configuration
     DistributedTestnet(DistributedTestnetCmd),
 
+    /// Run a single-validator local chain, with its Reth node spawned automatically
+    Dev(DevCmd),
+
     /// Extract secp256k1 public key from a file containing a Secp256k1 private key
     ShowPubkey(ShowPubkeyCmd),
+
+    /// Show the status of the node, or of the chains of a multi-chain node
+    Status(StatusCmd),
+
+    /// Print the proposers of upcoming heights, as selected from the current validator set
+    Schedule(ScheduleCmd),
+
+    /// Roll the store and the execution client back to a given height
+    UnsafeReset(UnsafeResetCmd),
+
+    /// Check the integrity of the consensus store, and report the first broken height
+    VerifyStore(VerifyStoreCmd),
+
+    /// Export or import the consensus store as a portable archive
+    Store(StoreCmd),
+
+    /// Check the node for common misconfigurations, and print how to fix them
+    Doctor(DoctorCmd),
+
+    /// Run the node as a service of the system, next to an existing Reth service
+    Service(ServiceCmd),
 }
 
 impl Default for Commands {
@@ -120,6 +153,12 @@ impl Args {
         Ok(self.get_config_dir()?.join(GENESIS_FILE))
     }
 
+    /// get_chains_file_path returns the chains manifest path used by multi-chain nodes,
+    /// based on the home folder.
+    pub fn get_chains_file_path(&self) -> Result<PathBuf, Error> {
+        Ok(self.get_home_dir()?.join(CHAINS_FILE))
+    }
+
     /// get_log_level_or_default returns the log level from the command-line or the default value.
     pub fn get_log_level_or_default(&self) -> LogLevel {
         self.log_level.unwrap_or_default()
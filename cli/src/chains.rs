@@ -0,0 +1,211 @@
+//! Manifest of the chains hosted by a multi-chain node.
+//!
+//! Each chain is a regular node home directory (with its own `config/config.toml`,
+//! `config/genesis.json`, `config/priv_validator_key.json` and emerald config),
+//! listed in a `chains.toml` file:
+//!
+//! ```toml
+//! [[chain]]
+//! id = "appchain-a"
+//! home = "/var/lib/emerald/appchain-a"
+//!
+//! [[chain]]
+//! id = "appchain-b"
+//! home = "/var/lib/emerald/appchain-b"
+//! emerald_config = "/etc/emerald/appchain-b.toml"
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{self, eyre, Context};
+use serde::{Deserialize, Serialize};
+
+/// Default name of the chains manifest, relative to the home directory
+pub const CHAINS_FILE: &str = "chains.toml";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChainsConfig {
+    #[serde(rename = "chain", default)]
+    pub chains: Vec<ChainEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChainEntry {
+    /// Identifier used to address the chain on the command line and in logs and metrics
+    pub id: String,
+
+    /// Home directory of the chain
+    pub home: PathBuf,
+
+    /// Emerald config file of the chain (default: `<home>/config/emerald.toml`)
+    #[serde(default)]
+    pub emerald_config: Option<PathBuf>,
+
+    /// Height to start the chain from
+    #[serde(default)]
+    pub start_height: Option<u64>,
+}
+
+impl ChainEntry {
+    pub fn config_dir(&self) -> PathBuf {
+        self.home.join("config")
+    }
+
+    pub fn emerald_config_file(&self) -> PathBuf {
+        self.emerald_config
+            .clone()
+            .unwrap_or_else(|| self.config_dir().join("emerald.toml"))
+    }
+}
+
+impl ChainsConfig {
+    /// Loads and validates the chains manifest at the given path.
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read chains file `{}`", path.display()))?;
+        let chains: Self = toml::from_str(&content)
+            .wrap_err_with(|| format!("Failed to parse chains file `{}`", path.display()))?;
+        chains.validate()?;
+        Ok(chains)
+    }
+
+    fn validate(&self) -> eyre::Result<()> {
+        if self.chains.is_empty() {
+            return Err(eyre!("Chains file does not define any chain"));
+        }
+
+        let mut ids = HashSet::new();
+        let mut homes = HashSet::new();
+        for chain in &self.chains {
+            if chain.id.is_empty() {
+                return Err(eyre!("Chain id cannot be empty"));
+            }
+            if !ids.insert(chain.id.as_str()) {
+                return Err(eyre!("Duplicate chain id `{}`", chain.id));
+            }
+            if !homes.insert(chain.home.as_path()) {
+                return Err(eyre!(
+                    "Chain `{}` shares its home directory `{}` with another chain",
+                    chain.id,
+                    chain.home.display()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the chains with the given ids, in the order they are requested,
+    /// or all chains if no id is given.
+    pub fn select(&self, ids: &[String]) -> eyre::Result<Vec<&ChainEntry>> {
+        if ids.is_empty() {
+            return Ok(self.chains.iter().collect());
+        }
+
+        ids.iter()
+            .map(|id| {
+                self.chains
+                    .iter()
+                    .find(|chain| &chain.id == id)
+                    .ok_or_else(|| eyre!("Unknown chain `{id}`"))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> eyre::Result<ChainsConfig> {
+        let chains: ChainsConfig = toml::from_str(content)?;
+        chains.validate()?;
+        Ok(chains)
+    }
+
+    const TWO_CHAINS: &str = r#"
+        [[chain]]
+        id = "appchain-a"
+        home = "/var/lib/emerald/appchain-a"
+
+        [[chain]]
+        id = "appchain-b"
+        home = "/var/lib/emerald/appchain-b"
+        emerald_config = "/etc/emerald/appchain-b.toml"
+        start_height = 100
+    "#;
+
+    #[test]
+    fn test_parse_chains() {
+        let chains = parse(TWO_CHAINS).unwrap();
+        assert_eq!(chains.chains.len(), 2);
+
+        let a = &chains.chains[0];
+        assert_eq!(a.id, "appchain-a");
+        assert_eq!(a.start_height, None);
+        assert_eq!(
+            a.emerald_config_file(),
+            PathBuf::from("/var/lib/emerald/appchain-a/config/emerald.toml")
+        );
+
+        let b = &chains.chains[1];
+        assert_eq!(b.start_height, Some(100));
+        assert_eq!(
+            b.emerald_config_file(),
+            PathBuf::from("/etc/emerald/appchain-b.toml")
+        );
+    }
+
+    #[test]
+    fn test_validate_chains() {
+        assert!(parse("").is_err());
+
+        let empty_id = r#"
+            [[chain]]
+            id = ""
+            home = "/a"
+        "#;
+        assert!(parse(empty_id).is_err());
+
+        let duplicate_id = r#"
+            [[chain]]
+            id = "a"
+            home = "/a"
+
+            [[chain]]
+            id = "a"
+            home = "/b"
+        "#;
+        assert!(parse(duplicate_id).is_err());
+
+        let shared_home = r#"
+            [[chain]]
+            id = "a"
+            home = "/a"
+
+            [[chain]]
+            id = "b"
+            home = "/a"
+        "#;
+        assert!(parse(shared_home).is_err());
+    }
+
+    #[test]
+    fn test_select_chains() {
+        let chains = parse(TWO_CHAINS).unwrap();
+
+        assert_eq!(chains.select(&[]).unwrap().len(), 2);
+
+        // In the requested order
+        let selected = chains
+            .select(&["appchain-b".to_string(), "appchain-a".to_string()])
+            .unwrap();
+        let ids: Vec<_> = selected.iter().map(|chain| chain.id.as_str()).collect();
+        assert_eq!(ids, ["appchain-b", "appchain-a"]);
+
+        assert!(chains.select(&["appchain-c".to_string()]).is_err());
+    }
+}
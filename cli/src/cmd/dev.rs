@@ -0,0 +1,146 @@
+//! Dev command - Run a single-validator local chain, with its Reth node spawned automatically
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context as _};
+use color_eyre::Result;
+use malachitebft_app::node::{CanGeneratePrivateKey, CanMakeGenesis, CanMakePrivateKeyFile, Node};
+use malachitebft_config::LoggingConfig;
+use malachitebft_core_types::{Context, SigningScheme};
+use malachitebft_eth_types::Address;
+
+use crate::cmd::testnet::reth;
+use crate::cmd::testnet::{ProcessHandle, RethPorts, TestnetStartCmd};
+
+type PrivateKey<C> = <<C as Context>::SigningScheme as SigningScheme>::PrivateKey;
+
+/// Account funded in the genesis of the dev chain, which also owns the PoA contract
+pub const DEV_ACCOUNT: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+/// Private key of [`DEV_ACCOUNT`], the first account of the `test test ... junk` mnemonic
+pub const DEV_ACCOUNT_PRIVATE_KEY: &str =
+    "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+#[derive(Parser, Debug, Clone, PartialEq)]
+pub struct DevCmd {
+    /// Path to the `emerald-utils` executable. The program first checks the path provided here;
+    /// if the binary is not found, it will try to resolve
+    /// `emerald-utils` from $PATH instead.
+    #[clap(long, default_value = "./target/debug/emerald-utils")]
+    pub emerald_utils_bin: String,
+
+    /// Path to the `custom-reth` executable. The program first checks the path provided here;
+    /// if the binary is not found, it will try to resolve
+    /// `custom-reth` from $PATH instead.
+    #[clap(long, default_value = "./custom-reth/target/debug/custom-reth")]
+    pub custom_reth_bin: String,
+
+    /// Path to reth node spawning configurations. If not specified will use default values
+    #[clap(long)]
+    pub reth_config_path: Option<PathBuf>,
+
+    /// Remove the data of the previous dev chain and start a new one
+    #[clap(long)]
+    pub reset: bool,
+}
+
+impl DevCmd {
+    /// Directory of the dev chain, inside the home directory
+    pub fn dev_dir(home_dir: &Path) -> PathBuf {
+        home_dir.join("dev")
+    }
+
+    /// Home directory of the Emerald node of the dev chain
+    pub fn node_home(home_dir: &Path) -> PathBuf {
+        Self::dev_dir(home_dir).join("0")
+    }
+
+    /// Generates the dev chain on first use, or after `--reset`, and starts its Reth node.
+    ///
+    /// Returns the Reth process, which the caller stops once the Emerald node exits.
+    pub fn prepare<N>(
+        &self,
+        node: &N,
+        home_dir: &Path,
+        logging: LoggingConfig,
+    ) -> Result<ProcessHandle>
+    where
+        N: Node + CanGeneratePrivateKey + CanMakeGenesis + CanMakePrivateKeyFile,
+        PrivateKey<N::Context>: serde::de::DeserializeOwned,
+    {
+        let dev_dir = Self::dev_dir(home_dir);
+        if self.reset && dev_dir.exists() {
+            fs::remove_dir_all(&dev_dir)
+                .with_context(|| format!("Failed to remove {}", dev_dir.display()))?;
+        }
+
+        reth::check_installation(&self.custom_reth_bin).wrap_err(
+            "Custom reth is not available. Make sure custom-reth/ directory exists and contains a valid reth binary.",
+        )?;
+
+        let emerald_bin = std::env::current_exe()
+            .context("Failed to locate the emerald executable")?
+            .display()
+            .to_string();
+
+        let testnet = TestnetStartCmd {
+            nodes: 1,
+            node_keys: None,
+            emerald_bin,
+            emerald_utils_bin: self.emerald_utils_bin.clone(),
+            custom_reth_bin: self.custom_reth_bin.clone(),
+            reth_config_path: self.reth_config_path.clone(),
+            fee_receiver: None,
+            powers: vec![],
+            in_process: false,
+            single_config_file: false,
+            resume: false,
+            ports_file: None,
+        };
+
+        let emerald_config = Self::node_home(home_dir)
+            .join("config")
+            .join("emerald.toml");
+
+        if emerald_config.exists() {
+            println!("♻️  Resuming the dev chain in {}", dev_dir.display());
+        } else {
+            println!("📝 Generating a dev chain in {}", dev_dir.display());
+            testnet.generate_testnet_config(node, &dev_dir, logging)?;
+            testnet.setup_assets_directory(&dev_dir)?;
+            testnet.allocate_ports(&dev_dir)?;
+            // Produce blocks as fast as consensus allows
+            testnet.generate_emerald_configs(&dev_dir, Address::repeat_byte(42), "0ms")?;
+            // Only produce empty blocks once a minute while no transactions are sent
+            let mut config = fs::read_to_string(&emerald_config)?;
+            config.push_str("max_idle_block_interval = \"60s\"\n");
+            fs::write(&emerald_config, config)?;
+            testnet.extract_public_keys(&dev_dir)?;
+            testnet.generate_genesis(&dev_dir)?;
+        }
+
+        let reth_process = testnet
+            .spawn_reth_nodes(&dev_dir)?
+            .pop()
+            .ok_or_else(|| eyre!("Failed to start the Reth node"))?;
+
+        println!("⏳ Waiting for Reth to initialize...");
+        testnet.wait_for_reth_nodes(&dev_dir)?;
+
+        println!("\n✅ Dev chain ready");
+        println!(
+            "  RPC:         http://localhost:{}",
+            RethPorts::load(&dev_dir, 0).http
+        );
+        println!("  Dev account: {DEV_ACCOUNT}");
+        println!("  Private key: {DEV_ACCOUNT_PRIVATE_KEY}");
+        println!("  Reth logs:   {}", reth_process.log_file.display());
+
+        Ok(ProcessHandle {
+            pid: reth_process.pid,
+            name: "reth".to_string(),
+        })
+    }
+}
@@ -1,5 +1,6 @@
 //! Distributed testnet command
 
+use core::net::IpAddr;
 use core::time::Duration;
 use std::fs;
 use std::path::Path;
@@ -9,6 +10,7 @@ use clap::Parser;
 use color_eyre::eyre::{eyre, Result};
 use itertools::Itertools;
 use malachitebft_app::node::{CanGeneratePrivateKey, CanMakeGenesis, CanMakePrivateKeyFile, Node};
+use multiaddr::Multiaddr;
 use tracing::info;
 
 use crate::args::Args;
@@ -83,6 +85,43 @@ pub struct DistributedTestnetCmd {
     /// - "tcp": TCP + Noise
     #[clap(short, long, default_value = "quic", verbatim_doc_comment)]
     pub transport: TransportProtocol,
+
+    /// The public IPs or DNS names of the machines (comma separated, in the order of
+    /// `--machines`), at which the peers reach the nodes behind a NAT, the machines being
+    /// the addresses the nodes listen on. Written as `external_address` in the `[p2p_nat]`
+    /// section of the emerald config of the nodes which have none.
+    /// The `external_address` and `external_port` already in the emerald config of a node
+    /// take precedence.
+    #[clap(long, value_delimiter = ',', verbatim_doc_comment)]
+    pub external_addresses: Vec<String>,
+
+    /// How the nodes map their consensus port on their gateway, written in the `[p2p_nat]`
+    /// section of the emerald config of the nodes which have none
+    /// Possible values:
+    /// - "static": Forwarded by the operator, requires `--external-addresses`
+    ///   (default with `--external-addresses`)
+    /// - "upnp": UPnP IGD
+    /// - "nat-pmp": NAT-PMP
+    #[clap(long, verbatim_doc_comment)]
+    pub nat: Option<NatMappingArg>,
+}
+
+/// Port mapping of the nodes behind a NAT, see [`NatMapping`]
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NatMappingArg {
+    Static,
+    Upnp,
+    NatPmp,
+}
+
+impl From<NatMappingArg> for NatMapping {
+    fn from(mapping: NatMappingArg) -> Self {
+        match mapping {
+            NatMappingArg::Static => Self::Static,
+            NatMappingArg::Upnp => Self::Upnp,
+            NatMappingArg::NatPmp => Self::NatPmp,
+        }
+    }
 }
 
 impl DistributedTestnetCmd {
@@ -102,6 +141,23 @@ impl DistributedTestnetCmd {
             RuntimeFlavour::MultiThreaded(n) => RuntimeConfig::MultiThreaded { worker_threads: n },
         };
 
+        if !self.external_addresses.is_empty()
+            && self.external_addresses.len() != self.machines.len()
+        {
+            return Err(eyre!(
+                "Expected one external address per machine, got {} for {} machines",
+                self.external_addresses.len(),
+                self.machines.len()
+            ));
+        }
+        if self.nat == Some(NatMappingArg::Static) && self.external_addresses.is_empty() {
+            return Err(eyre!("--nat static requires --external-addresses"));
+        }
+        let nat = self
+            .nat
+            .map(NatMapping::from)
+            .or_else(|| (!self.external_addresses.is_empty()).then_some(NatMapping::Static));
+
         distributed_testnet(
             node,
             self.nodes,
@@ -109,6 +165,8 @@ impl DistributedTestnetCmd {
             emerald_config_dir,
             runtime,
             self.machines.clone(),
+            self.external_addresses.clone(),
+            nat,
             self.enable_discovery,
             self.bootstrap_protocol,
             self.selector,
@@ -137,6 +195,8 @@ fn distributed_testnet<N>(
     emerald_config_dir: &Path,
     runtime: RuntimeConfig,
     machines: Vec<String>,
+    external_addresses: Vec<String>,
+    nat: Option<NatMapping>,
     enable_discovery: bool,
     bootstrap_protocol: BootstrapProtocol,
     selector: Selector,
@@ -158,11 +218,10 @@ where
         .collect();
     let genesis = crate::new::generate_genesis(node, public_keys, deterministic);
 
-    for (i, private_key) in private_keys.iter().enumerate().take(nodes) {
-        let node_home_dir = home_dir
-            .join((i % machines.len()).to_string())
-            .join(i.to_string());
-
+    // The emerald configs of all the nodes are read first, as the persistent peers of a node
+    // are the external addresses of the other nodes
+    let mut emerald_configs = Vec::with_capacity(nodes);
+    for i in 0..nodes {
         let node_emerald_config_file = emerald_config_dir
             .join((i % machines.len()).to_string())
             .join(i.to_string())
@@ -174,7 +233,7 @@ where
                     node_emerald_config_file.display()
                 )
             })?;
-        let emerald_config = toml::from_str::<crate::config::EmeraldConfig>(
+        let mut emerald_config = toml::from_str::<crate::config::EmeraldConfig>(
             &emerald_config_content,
         )
         .map_err(|e| {
@@ -184,6 +243,45 @@ where
             )
         })?;
 
+        if let (None, Some(mapping)) = (&emerald_config.p2p_nat, nat) {
+            let p2p_nat = P2pNatConfig {
+                mapping,
+                external_address: external_addresses.get(i % machines.len()).cloned(),
+                external_port: None,
+                gateway: None,
+                lease_duration: Duration::from_secs(3600),
+            };
+            append_p2p_nat(&node_emerald_config_file, &emerald_config_content, &p2p_nat)?;
+            emerald_config.p2p_nat = Some(p2p_nat);
+        }
+
+        emerald_configs.push(emerald_config);
+    }
+
+    // Consensus multiaddrs at which the peers reach each node
+    let peer_addrs = (0..nodes)
+        .map(|j| {
+            let p2p_nat = emerald_configs[j].p2p_nat.as_ref();
+            let host = p2p_nat
+                .and_then(|p2p_nat| p2p_nat.external_address.as_deref())
+                .unwrap_or(&machines[j % machines.len()]);
+            let port = p2p_nat
+                .and_then(|p2p_nat| p2p_nat.external_port)
+                .map_or(CONSENSUS_BASE_PORT + (j / machines.len()), usize::from);
+            external_multiaddr(transport, host, port)
+        })
+        .collect::<Vec<_>>();
+
+    for (i, (private_key, emerald_config)) in private_keys
+        .iter()
+        .zip(emerald_configs)
+        .enumerate()
+        .take(nodes)
+    {
+        let node_home_dir = home_dir
+            .join((i % machines.len()).to_string())
+            .join(i.to_string());
+
         info!(
             id = %i,
             home = %node_home_dir.display(),
@@ -202,6 +300,7 @@ where
                 nodes,
                 runtime,
                 machines.clone(),
+                &peer_addrs,
                 enable_discovery,
                 bootstrap_protocol,
                 selector,
@@ -232,6 +331,40 @@ const CONSENSUS_BASE_PORT: usize = 27000;
 const MEMPOOL_BASE_PORT: usize = 28000;
 const METRICS_BASE_PORT: usize = 29000;
 
+/// Appends the `[p2p_nat]` section to the emerald config file of a node
+fn append_p2p_nat(file: &Path, content: &str, p2p_nat: &P2pNatConfig) -> Result<()> {
+    let section = toml::to_string(p2p_nat)
+        .map_err(|e| eyre!("Failed to serialize the p2p_nat section. Details {e}"))?;
+    let separator = if content.ends_with('\n') { "" } else { "\n" };
+
+    fs::write(file, format!("{content}{separator}\n[p2p_nat]\n{section}")).map_err(|e| {
+        eyre!(
+            "Failed to write the p2p_nat section to {}. Details {e}",
+            file.display()
+        )
+    })?;
+    info!(file = %file.display(), mapping = ?p2p_nat.mapping, "Added the p2p_nat section to the emerald config");
+
+    Ok(())
+}
+
+/// Multiaddr of the consensus port of a node at `host`, an IP or a DNS name
+fn external_multiaddr(transport: TransportProtocol, host: &str, port: usize) -> Multiaddr {
+    let host = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => return transport.multiaddr(host, port),
+        Ok(IpAddr::V6(ip)) => format!("/ip6/{ip}"),
+        Err(_) => format!("/dns/{host}"),
+    };
+
+    // The transport only builds IPv4 multiaddrs, whose address is replaced
+    transport
+        .multiaddr("0.0.0.0", port)
+        .to_string()
+        .replacen("/ip4/0.0.0.0", &host, 1)
+        .parse()
+        .expect("valid multiaddr")
+}
+
 /// Generate configuration for node "index" out of "total" number of nodes.
 #[allow(clippy::too_many_arguments)]
 fn generate_distributed_config(
@@ -239,6 +372,7 @@ fn generate_distributed_config(
     _total: usize,
     runtime: RuntimeConfig,
     machines: Vec<String>,
+    peer_addrs: &[Multiaddr],
     enable_discovery: bool,
     bootstrap_protocol: BootstrapProtocol,
     selector: Selector,
@@ -269,25 +403,12 @@ fn generate_distributed_config(
                     peers
                         .iter()
                         .unique()
-                        .map(|j| {
-                            transport.multiaddr(
-                                &machines[j % machines.len()].clone(),
-                                CONSENSUS_BASE_PORT + (j / machines.len()),
-                            )
-                        })
+                        .map(|j| peer_addrs[*j].clone())
                         .collect()
                 } else {
                     let peers = (0..index).collect::<Vec<_>>();
 
-                    peers
-                        .iter()
-                        .map(|j| {
-                            transport.multiaddr(
-                                &machines[*j % machines.len()],
-                                CONSENSUS_BASE_PORT + (*j / machines.len()),
-                            )
-                        })
-                        .collect()
+                    peers.iter().map(|j| peer_addrs[*j].clone()).collect()
                 },
                 discovery: DiscoveryConfig {
                     enabled: enable_discovery,
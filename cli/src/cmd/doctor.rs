@@ -0,0 +1,739 @@
+//! Doctor command - Diagnose common misconfigurations of a node
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_genesis::Genesis as EvmGenesis;
+use clap::Args;
+use color_eyre::eyre::{eyre, Context, Result};
+use malachitebft_eth_engine::engine_rpc::EngineRPC;
+use malachitebft_eth_engine::error::EngineError;
+use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use malachitebft_eth_types::{Genesis, Hashable};
+use reqwest::Url;
+use serde_json::json;
+
+use crate::config::{has_consensus_table, load_node_config, EmeraldConfig};
+use crate::validation::{multiaddr_port, validate_config, validate_emerald_config};
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Timeout of the requests made to the execution client and to the NTP server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Check the node for common misconfigurations, and print how to fix them
+///
+/// The configuration files, the execution client, the local clock and the store are
+/// checked. The command fails if any check fails, and can be run next to a running node.
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct DoctorCmd {
+    /// NTP server the local clock is compared to
+    #[clap(long, default_value = "pool.ntp.org:123")]
+    pub ntp_server: String,
+
+    /// Largest difference tolerated between the local clock and the NTP server, in milliseconds
+    #[clap(long, default_value = "500")]
+    pub max_clock_skew_ms: u64,
+
+    /// Skip the clock check, e.g. on machines without access to an NTP server
+    #[clap(long)]
+    pub skip_clock: bool,
+
+    /// Home directory of a local testnet whose nodes are checked for port conflicts
+    /// (default: the home directory, if it holds a testnet)
+    #[clap(long, value_name = "TESTNET_HOME")]
+    pub testnet_home: Option<PathBuf>,
+}
+
+/// Outcome of a check
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Skip,
+    Warn,
+    Fail,
+}
+
+/// Result of one check of the doctor
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+    /// How to fix the problem found, if any
+    pub fix: Option<String>,
+}
+
+impl Check {
+    pub fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    pub fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Skip,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    pub fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    pub fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Checks made by the doctor, in order
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn push(&mut self, check: Check) {
+        self.checks.push(check);
+    }
+
+    /// Prints the checks, and fails if any of them failed
+    pub fn finish(self) -> Result<()> {
+        println!("🩺 Emerald doctor\n");
+
+        for check in &self.checks {
+            let symbol = match check.outcome {
+                Outcome::Pass => "✓",
+                Outcome::Skip => "-",
+                Outcome::Warn => "⚠",
+                Outcome::Fail => "✗",
+            };
+            println!("{symbol} {}: {}", check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                println!("    → {fix}");
+            }
+        }
+
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| check.outcome == Outcome::Fail)
+            .count();
+        let warned = self
+            .checks
+            .iter()
+            .filter(|check| check.outcome == Outcome::Warn)
+            .count();
+
+        println!();
+        if failed > 0 {
+            return Err(eyre!("{failed} checks failed, {warned} warnings"));
+        }
+
+        println!("✅ No problem found ({warned} warnings)");
+        Ok(())
+    }
+}
+
+impl DoctorCmd {
+    /// Runs the checks of the configuration files, the execution client and the clock.
+    /// The store is checked by the application.
+    pub async fn check(
+        &self,
+        home_dir: &Path,
+        config_file: &Path,
+        genesis_file: &Path,
+        emerald_config_file: &Path,
+    ) -> Report {
+        let mut report = Report::default();
+
+        // The malachite configuration may be in the emerald config file
+        let source = if has_consensus_table(emerald_config_file) {
+            format!("`[consensus]` of `{}`", emerald_config_file.display())
+        } else {
+            format!("`{}`", config_file.display())
+        };
+        let config = load_node_config(config_file, emerald_config_file);
+        report.push(match &config {
+            Ok(config) => match validate_config(config) {
+                Ok(()) => Check::pass("config", format!("{source} is valid")),
+                Err(errors) => Check::fail(
+                    "config",
+                    format!("Invalid fields in {source}:\n{errors}"),
+                    "Fix the listed fields of the configuration file",
+                ),
+            },
+            Err(e) => Check::fail(
+                "config",
+                format!("Failed to load {source}: {e}"),
+                "Pass the configuration file with `--config`, or generate one with `emerald init`",
+            ),
+        });
+
+        report.push(check_genesis(genesis_file, emerald_config_file));
+
+        match load_emerald_config(emerald_config_file) {
+            Ok(emerald_config) => {
+                // Only validated with a node config, whose listen addresses it must not reuse
+                let errors = config.as_ref().ok().and_then(|config| {
+                    validate_emerald_config(&emerald_config, config, home_dir).err()
+                });
+                report.push(match errors {
+                    None => Check::pass(
+                        "emerald config",
+                        format!("`{}` is valid", emerald_config_file.display()),
+                    ),
+                    Some(errors) => Check::fail(
+                        "emerald config",
+                        format!(
+                            "Invalid fields in `{}`:\n{errors}",
+                            emerald_config_file.display()
+                        ),
+                        "Fix the listed fields of the emerald config, see the example in the operational docs",
+                    ),
+                });
+
+                report.push(check_execution_client(&emerald_config).await);
+                report.push(check_engine_api(&emerald_config).await);
+                report.push(check_chain_id(&emerald_config).await);
+            }
+            Err(e) => {
+                report.push(Check::fail(
+                    "emerald config",
+                    format!("{e:#}"),
+                    "Fix the emerald config file, see the example in the operational docs",
+                ));
+                report.push(Check::skip(
+                    "execution client",
+                    "Skipped without a valid emerald config",
+                ));
+            }
+        }
+
+        report.push(if self.skip_clock {
+            Check::skip("clock", "Skipped with `--skip-clock`")
+        } else {
+            self.check_clock().await
+        });
+
+        let testnet_home = self.testnet_home.clone().unwrap_or_else(|| home_dir.into());
+        report.push(check_testnet_ports(&testnet_home));
+
+        report
+    }
+
+    async fn check_clock(&self) -> Check {
+        let server = self.ntp_server.clone();
+        let offset = tokio::task::spawn_blocking(move || clock_offset(&server))
+            .await
+            .unwrap_or_else(|e| Err(eyre!(e)));
+
+        let max_skew = Duration::from_millis(self.max_clock_skew_ms);
+        match offset {
+            Ok(offset) if offset.abs() <= max_skew.as_secs_f64() => Check::pass(
+                "clock",
+                format!(
+                    "Local clock is {:.0}ms off `{}`",
+                    offset * 1000.0,
+                    self.ntp_server
+                ),
+            ),
+            Ok(offset) => Check::fail(
+                "clock",
+                format!(
+                    "Local clock is {:.0}ms off `{}`, more than {}ms",
+                    offset * 1000.0,
+                    self.ntp_server,
+                    self.max_clock_skew_ms
+                ),
+                "Synchronize the clock with NTP, e.g. with chrony or systemd-timesyncd; \
+                 a skewed clock delays proposals and makes blocks look invalid to the peers",
+            ),
+            Err(e) => Check::warn(
+                "clock",
+                format!("Failed to query `{}`: {e}", self.ntp_server),
+                "Pass a reachable server with `--ntp-server`, or skip the check with `--skip-clock`",
+            ),
+        }
+    }
+}
+
+fn load_emerald_config(emerald_config_file: &Path) -> Result<EmeraldConfig> {
+    let content = fs::read_to_string(emerald_config_file).with_context(|| {
+        format!(
+            "Failed to read emerald config file `{}`",
+            emerald_config_file.display()
+        )
+    })?;
+
+    toml::from_str(&content).context("Failed to parse emerald config file")
+}
+
+fn check_genesis(genesis_file: &Path, emerald_config_file: &Path) -> Check {
+    let genesis = fs::read_to_string(genesis_file)
+        .map_err(|e| eyre!(e))
+        .and_then(|genesis| serde_json::from_str::<Genesis>(&genesis).map_err(Into::into));
+
+    let genesis_hash = match genesis {
+        Ok(genesis) => genesis.hash(),
+        Err(e) => {
+            return Check::fail(
+                "genesis",
+                format!("Failed to load `{}`: {e}", genesis_file.display()),
+                "Copy the genesis file of the chain into the config directory of the node",
+            );
+        }
+    };
+
+    let expected = load_emerald_config(emerald_config_file)
+        .ok()
+        .and_then(|config| config.expected_genesis_hash);
+
+    match expected {
+        Some(expected) if expected != genesis_hash => Check::fail(
+            "genesis",
+            format!("Genesis hashes to {genesis_hash}, but {expected} is expected"),
+            "The genesis file belongs to another chain: get the genesis file of the chain, \
+             or update `expected_genesis_hash` if the chain has been restarted",
+        ),
+        Some(_) => Check::pass(
+            "genesis",
+            format!("Genesis hash {genesis_hash} matches `expected_genesis_hash`"),
+        ),
+        None => Check::pass(
+            "genesis",
+            format!(
+                "Genesis hash {genesis_hash}, pin it with `expected_genesis_hash` in the emerald config"
+            ),
+        ),
+    }
+}
+
+async fn check_execution_client(emerald_config: &EmeraldConfig) -> Check {
+    let address = &emerald_config.ethereum_config.execution_authrpc_address;
+
+    let block_number = async {
+        let rpc = EthereumRPC::new(Url::parse(address)?)?;
+        let result: String = rpc
+            .rpc_request("eth_blockNumber", json!([]), REQUEST_TIMEOUT)
+            .await?;
+        parse_quantity(&result)
+    };
+
+    match block_number.await {
+        Ok(block_number) => Check::pass(
+            "execution client",
+            format!("`{address}` is at block {block_number}"),
+        ),
+        Err(e) => Check::fail(
+            "execution client",
+            format!("Failed to reach `{address}`: {e}"),
+            "Start the execution client, and check `ethereum_config.execution_authrpc_address`",
+        ),
+    }
+}
+
+async fn check_engine_api(emerald_config: &EmeraldConfig) -> Check {
+    let ethereum_config = &emerald_config.ethereum_config;
+    let address = &ethereum_config.engine_authrpc_address;
+
+    let api = Url::parse(address).map_err(Into::into).and_then(|url| {
+        EngineRPC::new(
+            url,
+            Path::new(&ethereum_config.jwt_token_path),
+            emerald_config.engine_timeouts.clone(),
+        )
+    });
+
+    let api = match api {
+        Ok(api) => api,
+        Err(e) => {
+            return Check::fail(
+                "engine API",
+                format!("{e}"),
+                "Check `ethereum_config.engine_authrpc_address`, and that \
+                 `ethereum_config.jwt_token_path` points to the JWT secret of the execution client",
+            );
+        }
+    };
+
+    match api.exchange_capabilities().await {
+        Ok(_) => Check::pass("engine API", format!("`{address}` accepts the JWT secret")),
+        Err(e) if is_unauthorized(&e) => Check::fail(
+            "engine API",
+            format!("`{address}` rejects the JWT secret: {e}"),
+            "Use the same JWT secret for the node and the execution client \
+             (`--authrpc.jwtsecret` for Reth)",
+        ),
+        Err(e) => Check::fail(
+            "engine API",
+            format!("Failed to reach `{address}`: {e}"),
+            "Start the execution client with the engine API enabled, \
+             and check `ethereum_config.engine_authrpc_address`",
+        ),
+    }
+}
+
+fn is_unauthorized(error: &EngineError) -> bool {
+    matches!(error.http_status(), Some(401 | 403))
+}
+
+async fn check_chain_id(emerald_config: &EmeraldConfig) -> Check {
+    let eth_genesis_path = &emerald_config.ethereum_config.eth_genesis_path;
+
+    let expected = fs::read_to_string(eth_genesis_path)
+        .map_err(|e| eyre!(e))
+        .and_then(|genesis| serde_json::from_str::<EvmGenesis>(&genesis).map_err(Into::into));
+
+    let expected = match expected {
+        Ok(genesis) => genesis.config.chain_id,
+        Err(e) => {
+            return Check::fail(
+                "chain id",
+                format!("Failed to load the EVM genesis `{eth_genesis_path}`: {e}"),
+                "Check `ethereum_config.eth_genesis_path`",
+            );
+        }
+    };
+
+    let chain_id = async {
+        let rpc = EthereumRPC::new(Url::parse(
+            &emerald_config.ethereum_config.execution_authrpc_address,
+        )?)?;
+        parse_quantity(&rpc.get_chain_id().await?)
+    };
+
+    match chain_id.await {
+        Ok(chain_id) if chain_id == expected => Check::pass(
+            "chain id",
+            format!("Execution client runs chain {chain_id}"),
+        ),
+        Ok(chain_id) => Check::fail(
+            "chain id",
+            format!("Execution client runs chain {chain_id}, but the EVM genesis is for chain {expected}"),
+            "Start the execution client with the EVM genesis of the chain, \
+             as given by `ethereum_config.eth_genesis_path`",
+        ),
+        Err(e) => Check::skip(
+            "chain id",
+            format!("Failed to read the chain id of the execution client: {e}"),
+        ),
+    }
+}
+
+fn parse_quantity(quantity: &str) -> Result<u64> {
+    u64::from_str_radix(quantity.trim_start_matches("0x"), 16)
+        .map_err(|e| eyre!("Invalid quantity `{quantity}`: {e}"))
+}
+
+/// Returns the offset of the local clock to the NTP server, in seconds,
+/// with a single SNTP request
+fn clock_offset(server: &str) -> Result<f64> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    socket.connect(server)?;
+
+    // Version 3, client mode
+    let mut packet = [0u8; 48];
+    packet[0] = 0x1b;
+
+    let sent = unix_time(SystemTime::now())?;
+    socket.send(&packet)?;
+    let len = socket.recv(&mut packet)?;
+    let received = unix_time(SystemTime::now())?;
+
+    if len < packet.len() {
+        return Err(eyre!("Truncated NTP response of {len} bytes"));
+    }
+
+    // Transmit timestamp of the server
+    let seconds = u64::from(u32::from_be_bytes([
+        packet[40], packet[41], packet[42], packet[43],
+    ]));
+    let fraction = u32::from_be_bytes([packet[44], packet[45], packet[46], packet[47]]);
+    let seconds = seconds
+        .checked_sub(NTP_UNIX_OFFSET)
+        .ok_or_else(|| eyre!("Invalid NTP timestamp"))?;
+    let server_time = seconds as f64 + f64::from(fraction) / f64::from(u32::MAX);
+
+    Ok(server_time - (sent + received) / 2.0)
+}
+
+fn unix_time(time: SystemTime) -> Result<f64> {
+    Ok(time.duration_since(UNIX_EPOCH)?.as_secs_f64())
+}
+
+/// Checks that the nodes of a local testnet do not use the same ports
+fn check_testnet_ports(testnet_home: &Path) -> Check {
+    let mut node_dirs: Vec<(usize, PathBuf)> = fs::read_dir(testnet_home)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let node_id = entry.file_name().to_str()?.parse().ok()?;
+            let config_dir = entry.path().join("config");
+            config_dir
+                .join("config.toml")
+                .exists()
+                .then_some((node_id, config_dir))
+        })
+        .collect();
+    node_dirs.sort();
+
+    if node_dirs.is_empty() {
+        return Check::skip(
+            "testnet ports",
+            format!("No testnet in `{}`", testnet_home.display()),
+        );
+    }
+
+    let mut ports: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    for (node_id, config_dir) in &node_dirs {
+        for (kind, port) in node_ports(
+            &config_dir.join("config.toml"),
+            &config_dir.join("emerald.toml"),
+        ) {
+            ports
+                .entry(port)
+                .or_default()
+                .push(format!("node {node_id} {kind}"));
+        }
+    }
+
+    let conflicts: Vec<String> = ports
+        .iter()
+        .filter(|(_, users)| users.len() > 1)
+        .map(|(port, users)| format!("{port} ({})", users.join(", ")))
+        .collect();
+
+    if conflicts.is_empty() {
+        Check::pass(
+            "testnet ports",
+            format!("The {} nodes use distinct ports", node_dirs.len()),
+        )
+    } else {
+        Check::fail(
+            "testnet ports",
+            format!("Ports used several times: {}", conflicts.join("; ")),
+            "Give each node its own ports in its config files, \
+             or regenerate the testnet with `emerald testnet start`",
+        )
+    }
+}
+
+/// Ports used by a node, by kind
+fn node_ports(config_file: &Path, emerald_config_file: &Path) -> Vec<(&'static str, u16)> {
+    let mut ports = Vec::new();
+
+    if let Ok(config) = load_node_config(config_file, emerald_config_file) {
+        if let Some(port) = multiaddr_port(&config.consensus.p2p.listen_addr.to_string()) {
+            ports.push(("consensus p2p", port));
+        }
+        if config.metrics.enabled {
+            ports.push(("metrics", config.metrics.listen_addr.port()));
+        }
+    }
+
+    if let Ok(emerald_config) = load_emerald_config(emerald_config_file) {
+        let ethereum_config = &emerald_config.ethereum_config;
+        let endpoints = [
+            ("execution RPC", &ethereum_config.execution_authrpc_address),
+            ("engine API", &ethereum_config.engine_authrpc_address),
+        ];
+        for (kind, address) in endpoints {
+            if let Some(port) = Url::parse(address)
+                .ok()
+                .and_then(|url| url.port_or_known_default())
+            {
+                ports.push((kind, port));
+            }
+        }
+
+        if let Some(addr) = emerald_config.admin_listen_addr {
+            ports.push(("admin API", addr.port()));
+        }
+        if let Some(addr) = emerald_config.rpc_proxy_listen_addr {
+            ports.push(("RPC proxy", addr.port()));
+        }
+    }
+
+    ports
+}
+
+#[cfg(test)]
+mod tests {
+    use malachitebft_eth_types::secp256k1::PrivateKey;
+    use malachitebft_eth_types::{Validator, ValidatorSet, B256};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Address on which nothing listens
+    const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+    fn genesis() -> Genesis {
+        let key = PrivateKey::from_slice(&[1; 32]).unwrap();
+        Genesis {
+            validator_set: ValidatorSet::new(vec![Validator::new(key.public_key(), 1)]),
+            base_fee_floor: None,
+            fee_recipient_policy: None,
+            max_proposal_bytes: None,
+            power_change_limit: None,
+            validator_manager_address: None,
+        }
+    }
+
+    /// Writes an emerald config with the given endpoints of the execution client, followed
+    /// by `extra` fields, and returns its path
+    fn write_emerald_config(dir: &Path, execution: &str, engine: &str, extra: &str) -> PathBuf {
+        let path = dir.join("emerald.toml");
+        fs::write(
+            &path,
+            format!(
+                r#"
+                moniker = "test"
+                fee_recipient = "0x0000000000000000000000000000000000000000"
+                {extra}
+
+                [ethereum_config]
+                execution_authrpc_address = "{execution}"
+                engine_authrpc_address = "{engine}"
+                jwt_token_path = "{jwt}"
+                eth_genesis_path = "{genesis}"
+                "#,
+                jwt = dir.join("jwt.hex").display(),
+                genesis = dir.join("eth-genesis.json").display(),
+            ),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("0x1a").unwrap(), 26);
+        assert_eq!(parse_quantity("0x0").unwrap(), 0);
+        assert!(parse_quantity("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_report_fails_on_failed_checks_only() {
+        let mut report = Report::default();
+        report.push(Check::pass("config", "valid"));
+        report.push(Check::skip("clock", "skipped"));
+        report.push(Check::warn("chain id", "unknown", "fix it"));
+        assert!(report.clone().finish().is_ok());
+
+        report.push(Check::fail("genesis", "missing", "fix it"));
+        assert!(report.finish().is_err());
+    }
+
+    #[test]
+    fn test_check_genesis() {
+        let dir = TempDir::new().unwrap();
+        let genesis_file = dir.path().join("genesis.json");
+        let genesis_hash = genesis().hash();
+        fs::write(&genesis_file, serde_json::to_string(&genesis()).unwrap()).unwrap();
+
+        let check = |expected: Option<B256>| {
+            let extra = expected
+                .map(|hash| format!("expected_genesis_hash = \"{hash}\""))
+                .unwrap_or_default();
+            let emerald_config_file =
+                write_emerald_config(dir.path(), UNREACHABLE, UNREACHABLE, &extra);
+            check_genesis(&genesis_file, &emerald_config_file).outcome
+        };
+
+        assert_eq!(check(None), Outcome::Pass);
+        assert_eq!(check(Some(genesis_hash)), Outcome::Pass);
+        assert_eq!(check(Some(B256::repeat_byte(1))), Outcome::Fail);
+
+        let missing = dir.path().join("missing.json");
+        let emerald_config_file = write_emerald_config(dir.path(), UNREACHABLE, UNREACHABLE, "");
+        assert_eq!(
+            check_genesis(&missing, &emerald_config_file).outcome,
+            Outcome::Fail
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_execution_client() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("jwt.hex"), hex::encode([0x11; 32])).unwrap();
+        let emerald_config_file = write_emerald_config(dir.path(), UNREACHABLE, UNREACHABLE, "");
+        let emerald_config = load_emerald_config(&emerald_config_file).unwrap();
+
+        let check = check_execution_client(&emerald_config).await;
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert!(check.fix.is_some());
+
+        let check = check_engine_api(&emerald_config).await;
+        assert_eq!(check.outcome, Outcome::Fail);
+
+        // The EVM genesis the chain id is compared to is missing
+        let check = check_chain_id(&emerald_config).await;
+        assert_eq!(check.outcome, Outcome::Fail);
+    }
+
+    #[tokio::test]
+    async fn test_missing_jwt_secret() {
+        let dir = TempDir::new().unwrap();
+        let emerald_config_file = write_emerald_config(dir.path(), UNREACHABLE, UNREACHABLE, "");
+        let emerald_config = load_emerald_config(&emerald_config_file).unwrap();
+
+        let check = check_engine_api(&emerald_config).await;
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert!(check.fix.unwrap().contains("jwt_token_path"));
+    }
+
+    #[test]
+    fn test_check_testnet_ports() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(check_testnet_ports(dir.path()).outcome, Outcome::Skip);
+
+        let write_node = |node_id: usize, execution_port: u16, engine_port: u16| {
+            let config_dir = dir.path().join(node_id.to_string()).join("config");
+            fs::create_dir_all(&config_dir).unwrap();
+            fs::write(config_dir.join("config.toml"), "").unwrap();
+            write_emerald_config(
+                &config_dir,
+                &format!("http://127.0.0.1:{execution_port}"),
+                &format!("http://127.0.0.1:{engine_port}"),
+                "",
+            );
+        };
+
+        write_node(0, 8545, 8551);
+        write_node(1, 18545, 18551);
+        assert_eq!(check_testnet_ports(dir.path()).outcome, Outcome::Pass);
+
+        // The engine API of node 2 uses the port of the execution RPC of node 0
+        write_node(2, 28545, 8545);
+        let check = check_testnet_ports(dir.path());
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert!(check.detail.contains("8545"));
+        assert!(check.detail.contains("node 0 execution RPC"));
+        assert!(check.detail.contains("node 2 engine API"));
+    }
+}
@@ -9,9 +9,9 @@ use malachitebft_config::{
 };
 use tracing::{info, warn};
 
-use crate::config::Config;
+use crate::config::{has_consensus_table, Config};
 use crate::error::Error;
-use crate::file::{save_config, save_genesis, save_priv_validator_key};
+use crate::file::{save_config, save_consensus_config, save_genesis, save_priv_validator_key};
 use crate::new::{generate_config, generate_genesis, generate_private_keys};
 
 #[derive(Parser, Debug, Clone, Default, PartialEq)]
@@ -58,6 +58,11 @@ pub struct InitCmd {
     /// The duration in milliseconds an ephemeral connection is kept alive
     #[clap(long, default_value = "5000", verbatim_doc_comment)]
     pub ephemeral_connection_timeout_ms: u64,
+
+    /// Write the Malachite configuration to the `[consensus]` table of the Emerald config
+    /// file instead of a separate `config.toml`
+    #[clap(long)]
+    pub single_config_file: bool,
 }
 
 impl InitCmd {
@@ -66,6 +71,7 @@ impl InitCmd {
         &self,
         node: &N,
         config_file: &Path,
+        emerald_config_file: &Path,
         genesis_file: &Path,
         priv_validator_key_file: &Path,
         logging: LoggingConfig,
@@ -91,6 +97,12 @@ impl InitCmd {
             moniker,
         );
 
+        let config_file = if self.single_config_file {
+            ConfigFile::Merged(emerald_config_file)
+        } else {
+            ConfigFile::Separate(config_file)
+        };
+
         init(
             node,
             config,
@@ -104,11 +116,20 @@ impl InitCmd {
     }
 }
 
+/// File where the Malachite configuration is saved
+#[derive(Copy, Clone, Debug)]
+pub enum ConfigFile<'a> {
+    /// The Malachite config file
+    Separate(&'a Path),
+    /// The `[consensus]` table of the Emerald config file
+    Merged(&'a Path),
+}
+
 /// init command to generate defaults.
 pub fn init<N>(
     node: &N,
     config: &Config,
-    config_file: &Path,
+    config_file: ConfigFile<'_>,
     genesis_file: &Path,
     priv_validator_key_file: &Path,
     overwrite: bool,
@@ -117,11 +138,26 @@ where
     N: Node + CanMakePrivateKeyFile + CanGeneratePrivateKey + CanMakeGenesis,
 {
     // Save configuration
-    if config_file.exists() && !overwrite {
-        warn!(file = ?config_file.display(), "Configuration file already exists, skipping");
-    } else {
-        info!(file = ?config_file, "Saving configuration");
-        save_config(config_file, config)?;
+    match config_file {
+        ConfigFile::Separate(config_file) => {
+            if config_file.exists() && !overwrite {
+                warn!(file = ?config_file.display(), "Configuration file already exists, skipping");
+            } else {
+                info!(file = ?config_file, "Saving configuration");
+                save_config(config_file, config)?;
+            }
+        }
+        ConfigFile::Merged(emerald_config_file) => {
+            if has_consensus_table(emerald_config_file) && !overwrite {
+                warn!(
+                    file = ?emerald_config_file.display(),
+                    "Configuration already exists in the emerald config file, skipping",
+                );
+            } else {
+                info!(file = ?emerald_config_file, "Saving configuration to the emerald config file");
+                save_consensus_config(emerald_config_file, config)?;
+            }
+        }
     }
 
     // Save default priv_validator_key
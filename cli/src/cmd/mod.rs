@@ -1,5 +1,13 @@
+pub mod dev;
 pub mod distributed_testnet;
+pub mod doctor;
 pub mod init;
+pub mod schedule;
+pub mod service;
 pub mod show_pubkey;
 pub mod start;
+pub mod status;
+pub mod store;
 pub mod testnet;
+pub mod unsafe_reset;
+pub mod verify_store;
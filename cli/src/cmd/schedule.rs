@@ -0,0 +1,242 @@
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+use core::time::Duration;
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::eyre::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::cmd::status::{admin_url, read_auth_token};
+
+/// Timeout of the request for the schedule
+const SCHEDULE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of heights in a schedule
+pub const MAX_SCHEDULE_HEIGHTS: u64 = 10_000;
+
+/// Maximum number of rounds per height in a schedule
+pub const MAX_SCHEDULE_ROUNDS: u32 = 16;
+
+/// Heights and rounds of a schedule, as the query of the `GET /schedule` route
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleQuery {
+    /// First height, included
+    pub from: u64,
+    /// Last height, included
+    pub to: u64,
+    /// Number of rounds from round 0
+    #[serde(default = "default_rounds")]
+    pub rounds: u32,
+}
+
+fn default_rounds() -> u32 {
+    1
+}
+
+impl ScheduleQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.from == 0 {
+            return Err("heights start at 1".to_string());
+        }
+        if self.from > self.to {
+            return Err(format!(
+                "empty range of heights {}..={}",
+                self.from, self.to
+            ));
+        }
+        if self.to - self.from >= MAX_SCHEDULE_HEIGHTS {
+            return Err(format!(
+                "at most {MAX_SCHEDULE_HEIGHTS} heights per schedule"
+            ));
+        }
+        if self.rounds == 0 || self.rounds > MAX_SCHEDULE_ROUNDS {
+            return Err(format!(
+                "between 1 and {MAX_SCHEDULE_ROUNDS} rounds per height"
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn heights(&self) -> RangeInclusive<u64> {
+        self.from..=self.to
+    }
+}
+
+/// Proposers of upcoming heights, served on the `GET /schedule` route of the admin API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposerSchedule {
+    /// Height whose validator set the proposers are selected from
+    pub validator_set_height: u64,
+    /// Number of validators in the set
+    pub validators: usize,
+    pub heights: Vec<ScheduledHeight>,
+}
+
+/// Proposers of a height, by round
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledHeight {
+    pub height: u64,
+    pub proposers: Vec<String>,
+}
+
+/// Range of heights, `A..B` excluding `B`, `A..=B` including it, or a single height `A`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HeightRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+impl FromStr for HeightRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |height: &str| {
+            height
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("invalid height `{height}`: {e}"))
+        };
+
+        let (from, to) = if let Some((from, to)) = s.split_once("..=") {
+            (parse(from)?, parse(to)?)
+        } else if let Some((from, to)) = s.split_once("..") {
+            let to = parse(to)?
+                .checked_sub(1)
+                .ok_or_else(|| format!("empty range of heights `{s}`"))?;
+            (parse(from)?, to)
+        } else {
+            let height = parse(s)?;
+            (height, height)
+        };
+
+        if from > to {
+            return Err(format!("empty range of heights `{s}`"));
+        }
+
+        Ok(Self { from, to })
+    }
+}
+
+/// Print the proposers of upcoming heights, as selected from the current validator set
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct ScheduleCmd {
+    /// Heights to schedule: `100..110` excludes 110, `100..=110` includes it
+    #[clap(long, value_name = "RANGE")]
+    pub heights: HeightRange,
+
+    /// Number of rounds to schedule at each height, from round 0
+    #[clap(long, default_value_t = 1, value_name = "N")]
+    pub rounds: u32,
+
+    /// Only show the heights and rounds proposed by this validator address
+    #[clap(long, value_name = "ADDRESS")]
+    pub validator: Option<String>,
+
+    /// Admin API of a running node, e.g. `127.0.0.1:9100` or `https://node.example.com:9100`
+    #[clap(long, value_name = "ADDR")]
+    pub node: String,
+
+    /// File holding the bearer token required by the admin API of the node
+    #[clap(long, value_name = "FILE")]
+    pub auth_token_file: Option<PathBuf>,
+
+    /// Print the schedule as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+impl ScheduleCmd {
+    pub fn run(&self) -> Result<()> {
+        let query = ScheduleQuery {
+            from: self.heights.from,
+            to: self.heights.to,
+            rounds: self.rounds,
+        };
+        if let Err(e) = query.validate() {
+            bail!("Invalid schedule: {e}");
+        }
+
+        let auth_token = self
+            .auth_token_file
+            .as_deref()
+            .map(read_auth_token)
+            .transpose()?;
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        let mut schedule =
+            runtime.block_on(fetch_schedule(&self.node, &query, auth_token.as_deref()))?;
+
+        // Keeps the heights proposed by the validator at some round
+        let proposed_by = |proposer: &str| {
+            self.validator
+                .as_deref()
+                .is_none_or(|validator| proposer.eq_ignore_ascii_case(validator))
+        };
+        schedule.heights.retain(|height| {
+            height
+                .proposers
+                .iter()
+                .any(|proposer| proposed_by(proposer))
+        });
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&schedule)?);
+            return Ok(());
+        }
+
+        println!(
+            "Proposers from the validator set of height {} ({} validators):",
+            schedule.validator_set_height, schedule.validators
+        );
+        for height in &schedule.heights {
+            for (round, proposer) in height.proposers.iter().enumerate() {
+                if proposed_by(proposer) {
+                    println!(
+                        "  Height {:>10}  round {round:>2}  {proposer}",
+                        height.height
+                    );
+                }
+            }
+        }
+        if schedule.heights.is_empty() {
+            println!("  No height proposed by this validator");
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches the proposers of the heights and rounds of `query` from the admin API of a
+/// running node at `node`, an address or a URL, with the bearer token the API requires if any.
+pub async fn fetch_schedule(
+    node: &str,
+    query: &ScheduleQuery,
+    auth_token: Option<&str>,
+) -> Result<ProposerSchedule> {
+    let url = admin_url(node, "schedule")?;
+
+    let mut request = reqwest::Client::new()
+        .get(url)
+        .query(query)
+        .timeout(SCHEDULE_TIMEOUT);
+    if let Some(token) = auth_token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Failed to query the admin API of `{node}`"))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let reason = response.text().await.unwrap_or_default();
+        bail!("Failed to get the schedule of `{node}`: {status} {reason}");
+    }
+
+    response
+        .json()
+        .await
+        .context("Failed to parse the proposer schedule")
+}
@@ -0,0 +1,418 @@
+//! Service command - Render the service definitions running Emerald in production
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand, ValueEnum};
+use color_eyre::eyre::{bail, Context, Result};
+
+/// Run Emerald as a service of the system, next to an existing Reth service
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct ServiceCmd {
+    #[command(subcommand)]
+    pub command: ServiceSubcommand,
+}
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+pub enum ServiceSubcommand {
+    /// Render the service definition of the node and its environment file
+    Install(ServiceInstallCmd),
+}
+
+/// Service manager the definitions are rendered for
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ServiceManager {
+    /// systemd unit, on Linux
+    Systemd,
+    /// launchd property list, on macOS
+    Launchd,
+}
+
+impl Default for ServiceManager {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else {
+            Self::Systemd
+        }
+    }
+}
+
+/// Render the service definition of the node and its environment file
+///
+/// The service starts the node with the home directory and configuration file of this
+/// command, once the Reth service it depends on is started, and restarts it on failure.
+/// The secrets of the node, e.g. the key of an encrypted store, are read from the
+/// environment file. The files are written to the output directory, to be reviewed and
+/// installed by the operator.
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct ServiceInstallCmd {
+    /// Service manager the definitions are rendered for (default: the one of this system)
+    #[clap(long, value_enum, default_value_t)]
+    pub manager: ServiceManager,
+
+    /// Name of the service
+    #[clap(long, default_value = "emerald")]
+    pub name: String,
+
+    /// Name of the Reth service the node depends on
+    #[clap(long, default_value = "reth")]
+    pub reth_service: String,
+
+    /// User running the node
+    #[clap(long, default_value = "emerald")]
+    pub user: String,
+
+    /// Path of the emerald binary (default: this binary)
+    #[clap(long, value_name = "BINARY")]
+    pub binary: Option<PathBuf>,
+
+    /// Path of the environment file holding the secrets of the node, once installed
+    #[clap(
+        long,
+        value_name = "ENV_FILE",
+        default_value = "/etc/emerald/emerald.env"
+    )]
+    pub env_file: PathBuf,
+
+    /// Delay before the node is restarted after a failure, in seconds
+    #[clap(long, default_value = "10")]
+    pub restart_delay_secs: u64,
+
+    /// Directory the files are written to
+    #[clap(long, value_name = "DIR", default_value = ".")]
+    pub output: PathBuf,
+
+    /// Overwrite the files of a previous run
+    #[clap(long)]
+    pub force: bool,
+}
+
+/// Options of the node started by the service, with absolute paths
+struct ServiceSpec {
+    binary: PathBuf,
+    home_dir: PathBuf,
+    config_file: PathBuf,
+}
+
+impl ServiceSpec {
+    fn args(&self) -> Vec<String> {
+        vec![
+            self.binary.display().to_string(),
+            "start".to_string(),
+            "--home".to_string(),
+            self.home_dir.display().to_string(),
+            "--config".to_string(),
+            self.config_file.display().to_string(),
+        ]
+    }
+}
+
+impl ServiceInstallCmd {
+    pub fn run(&self, home_dir: &Path, config_file: &Path) -> Result<()> {
+        let binary = match &self.binary {
+            Some(binary) => binary.clone(),
+            None => std::env::current_exe().wrap_err("Failed to locate the emerald binary")?,
+        };
+
+        let spec = ServiceSpec {
+            binary: std::path::absolute(binary)?,
+            home_dir: std::path::absolute(home_dir)?,
+            config_file: std::path::absolute(config_file)?,
+        };
+
+        let (service_file, service) = match self.manager {
+            ServiceManager::Systemd => (format!("{}.service", self.name), self.systemd_unit(&spec)),
+            ServiceManager::Launchd => (format!("{}.plist", self.name), self.launchd_plist(&spec)),
+        };
+        let env_file = format!("{}.env", self.name);
+
+        fs::create_dir_all(&self.output)
+            .wrap_err_with(|| format!("Failed to create {}", self.output.display()))?;
+        self.write(&service_file, &service)?;
+        self.write(&env_file, &self.env_template())?;
+
+        self.print_instructions(&service_file, &env_file);
+
+        Ok(())
+    }
+
+    fn write(&self, file_name: &str, contents: &str) -> Result<()> {
+        let path = self.output.join(file_name);
+        if path.exists() && !self.force {
+            bail!(
+                "{} already exists, use `--force` to overwrite it",
+                path.display()
+            );
+        }
+
+        fs::write(&path, contents).wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The node is stopped and restarted with the Reth service it requires, and is
+    /// restarted on failure unless it keeps failing, e.g. on a corrupted store.
+    fn systemd_unit(&self, spec: &ServiceSpec) -> String {
+        let reth = format!("{}.service", self.reth_service);
+        let exec_start = spec
+            .args()
+            .iter()
+            .map(|arg| systemd_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" \\\n  ");
+
+        format!(
+            "\
+[Unit]
+Description=Emerald consensus node
+Documentation=https://github.com/informalsystems/emerald
+Wants=network-online.target
+After=network-online.target {reth}
+Requires={reth}
+StartLimitIntervalSec=600
+StartLimitBurst=5
+
+[Service]
+Type=simple
+User={user}
+Group={user}
+WorkingDirectory={home}
+EnvironmentFile=-{env_file}
+Environment=\"RUST_BACKTRACE=1\"
+
+ExecStart={exec_start}
+
+Restart=on-failure
+RestartSec={restart_delay}s
+TimeoutStopSec=30s
+KillSignal=SIGTERM
+LimitNOFILE=65536
+
+[Install]
+WantedBy=multi-user.target
+",
+            user = self.user,
+            home = systemd_escape(&spec.home_dir.display().to_string()),
+            env_file = systemd_escape(&self.env_file.display().to_string()),
+            restart_delay = self.restart_delay_secs,
+        )
+    }
+
+    /// launchd neither orders services nor reads environment files: the node is started
+    /// through a shell which loads the environment file, and is restarted until Reth is up.
+    fn launchd_plist(&self, spec: &ServiceSpec) -> String {
+        let command = format!(
+            "set -a; [ -f {env_file} ] && . {env_file}; exec {args}",
+            env_file = shell_quote(&self.env_file.display().to_string()),
+            args = spec
+                .args()
+                .iter()
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        let logs = spec.home_dir.join("logs");
+
+        format!(
+            "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\">
+<dict>
+  <key>Label</key>
+  <string>{label}</string>
+  <key>UserName</key>
+  <string>{user}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>/bin/sh</string>
+    <string>-c</string>
+    <string>{command}</string>
+  </array>
+  <key>WorkingDirectory</key>
+  <string>{home}</string>
+  <key>EnvironmentVariables</key>
+  <dict>
+    <key>RUST_BACKTRACE</key>
+    <string>1</string>
+  </dict>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <dict>
+    <key>SuccessfulExit</key>
+    <false/>
+  </dict>
+  <key>ThrottleInterval</key>
+  <integer>{restart_delay}</integer>
+  <key>StandardOutPath</key>
+  <string>{stdout}</string>
+  <key>StandardErrorPath</key>
+  <string>{stderr}</string>
+</dict>
+</plist>
+",
+            label = xml_escape(&self.name),
+            user = xml_escape(&self.user),
+            command = xml_escape(&command),
+            home = xml_escape(&spec.home_dir.display().to_string()),
+            restart_delay = self.restart_delay_secs,
+            stdout = xml_escape(&logs.join(format!("{}.out.log", self.name)).display().to_string()),
+            stderr = xml_escape(&logs.join(format!("{}.err.log", self.name)).display().to_string()),
+        )
+    }
+
+    fn env_template(&self) -> String {
+        format!(
+            "\
+# Environment of the `{name}` service, to be installed at {env_file}
+# and only readable by the user running the node (`chmod 600`).
+#
+# Log level of the node
+# RUST_LOG=info
+#
+# Key of an encrypted store, configured in the emerald configuration with
+#   [store_encryption_key]
+#   source = \"env\"
+#   var = \"EMERALD_STORE_KEY\"
+# EMERALD_STORE_KEY=0x...
+",
+            name = self.name,
+            env_file = self.env_file.display(),
+        )
+    }
+
+    fn print_instructions(&self, service_file: &str, env_file: &str) {
+        let service_path = self.output.join(service_file);
+        let env_path = self.output.join(env_file);
+
+        println!(
+            "Wrote {} and {}",
+            service_path.display(),
+            env_path.display()
+        );
+        println!();
+        println!("Review the files, then install them with:");
+        println!();
+        if let Some(env_dir) = self.env_file.parent() {
+            println!("  sudo mkdir -p {}", env_dir.display());
+        }
+        println!(
+            "  sudo install -m 600 -o {} {} {}",
+            self.user,
+            env_path.display(),
+            self.env_file.display()
+        );
+
+        match self.manager {
+            ServiceManager::Systemd => {
+                println!(
+                    "  sudo cp {} /etc/systemd/system/{service_file}",
+                    service_path.display()
+                );
+                println!("  sudo systemctl daemon-reload");
+                println!("  sudo systemctl enable --now {}", self.name);
+            }
+            ServiceManager::Launchd => {
+                println!(
+                    "  sudo cp {} /Library/LaunchDaemons/{service_file}",
+                    service_path.display()
+                );
+                println!("  sudo launchctl bootstrap system /Library/LaunchDaemons/{service_file}");
+                println!();
+                println!(
+                    "launchd does not order services: the node is restarted every {}s until the `{}` service is up",
+                    self.restart_delay_secs, self.reth_service
+                );
+            }
+        }
+    }
+}
+
+/// Escapes the specifiers of a value of a systemd unit, e.g. `%h`
+fn systemd_escape(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Quotes an argument of a systemd command line if needed, and escapes its specifiers
+/// and the environment variables it would otherwise expand, e.g. `$HOME`
+fn systemd_quote(arg: &str) -> String {
+    let arg = systemd_escape(arg).replace('$', "$$");
+    if arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
+
+/// Quotes an argument of a shell command line
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install_cmd(env_file: &str) -> ServiceInstallCmd {
+        ServiceInstallCmd {
+            manager: ServiceManager::Systemd,
+            name: "emerald".to_string(),
+            reth_service: "reth".to_string(),
+            user: "emerald".to_string(),
+            binary: None,
+            env_file: PathBuf::from(env_file),
+            restart_delay_secs: 10,
+            output: PathBuf::from("."),
+            force: false,
+        }
+    }
+
+    fn spec(home_dir: &str) -> ServiceSpec {
+        ServiceSpec {
+            binary: PathBuf::from("/usr/local/bin/emerald"),
+            home_dir: PathBuf::from(home_dir),
+            config_file: PathBuf::from(home_dir).join("config/emerald.toml"),
+        }
+    }
+
+    #[test]
+    fn test_systemd_quote() {
+        assert_eq!(systemd_quote("/usr/bin/emerald"), "/usr/bin/emerald");
+        assert_eq!(systemd_quote("/srv/node 1"), "\"/srv/node 1\"");
+        assert_eq!(systemd_quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(systemd_quote("C:\\node"), "\"C:\\\\node\"");
+
+        // Neither specifiers nor environment variables are expanded
+        assert_eq!(systemd_quote("/srv/%h"), "/srv/%%h");
+        assert_eq!(systemd_quote("/srv/$HOME"), "/srv/$$HOME");
+        assert_eq!(systemd_quote("/srv/${USER} 1"), "\"/srv/$${USER} 1\"");
+    }
+
+    #[test]
+    fn test_systemd_unit_escapes_paths() {
+        let unit = install_cmd("/etc/emerald/%i.env").systemd_unit(&spec("/srv/$node %n"));
+
+        assert!(unit.contains("WorkingDirectory=/srv/$node %%n\n"));
+        assert!(unit.contains("EnvironmentFile=-/etc/emerald/%%i.env\n"));
+        assert!(unit.contains("  --home \\\n  \"/srv/$$node %%n\" \\\n"));
+        assert!(unit.contains("Requires=reth.service\n"));
+    }
+
+    #[test]
+    fn test_launchd_plist_quotes_command() {
+        let plist = install_cmd("/etc/emerald/emerald.env").launchd_plist(&spec("/srv/it's $node"));
+
+        assert!(
+            plist.contains("exec '/usr/local/bin/emerald' 'start' '--home' '/srv/it'\\''s $node'")
+        );
+        assert_eq!(xml_escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -1,18 +1,65 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
 use color_eyre::eyre;
 use malachitebft_app::node::Node;
 use malachitebft_config::MetricsConfig;
 use tracing::info;
 
+use crate::http::EndpointSecurity;
 use crate::metrics;
 
 #[derive(Parser, Debug, Clone, Default, PartialEq)]
 pub struct StartCmd {
     #[clap(long)]
     pub start_height: Option<u64>,
+
+    /// Chains manifest to run several chains in this process (default: `<HOME>/chains.toml`)
+    #[clap(long, value_name = "CHAINS_FILE")]
+    pub chains: Option<PathBuf>,
+
+    /// Only start the chain with this id from the chains manifest (can be repeated)
+    #[clap(long = "chain", value_name = "CHAIN_ID")]
+    pub chain_ids: Vec<String>,
+
+    /// Role of the node in the network
+    #[clap(long, value_enum, default_value_t)]
+    pub mode: NodeMode,
+
+    /// Time the stages of the next N decided blocks, then print a summary and stop the node
+    #[clap(long, value_name = "N", conflicts_with_all = ["chains", "chain_ids"])]
+    pub profile_blocks: Option<u64>,
+
+    /// Apply the values synced from the peers as fast as the execution client allows,
+    /// ignoring the `catch_up_throttle` of the emerald config
+    #[clap(long)]
+    pub fast_sync: bool,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NodeMode {
+    /// Take part in consensus, voting when in the validator set
+    #[default]
+    Validator,
+
+    /// Do not take part in consensus: only ingest the decided values from peers,
+    /// apply them to the execution client and serve them to syncing peers.
+    /// Meant for dedicated archive and seed nodes, which never sign: they run with a key
+    /// generated at each start
+    ArchiveSync,
+
+    /// Follow the network like `archive-sync`, but re-execute the decided blocks on an
+    /// execution client with a modified genesis or fork schedule, reporting where its
+    /// blocks diverge. Meant for shadow-fork testing of upgrades of the execution layer
+    ShadowFork,
 }
 
 impl StartCmd {
+    /// Whether the node runs several chains from a chains manifest
+    pub fn is_multi_chain(&self) -> bool {
+        self.chains.is_some() || !self.chain_ids.is_empty()
+    }
+
     pub async fn run(&self, node: impl Node, metrics: Option<MetricsConfig>) -> eyre::Result<()> {
         info!("Node is starting...");
 
@@ -29,7 +76,10 @@ pub async fn start(node: impl Node, metrics: Option<MetricsConfig>) -> eyre::Res
     // Enable Prometheus
     if let Some(metrics) = metrics {
         if metrics.enabled {
-            tokio::spawn(metrics::serve(metrics.listen_addr));
+            tokio::spawn(metrics::serve(
+                metrics.listen_addr,
+                EndpointSecurity::default(),
+            ));
         }
     }
 
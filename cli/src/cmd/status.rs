@@ -0,0 +1,262 @@
+use core::time::Duration;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use color_eyre::eyre::{eyre, Context, Result};
+use malachitebft_eth_engine::ethereum_rpc::EthereumRPC;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chains::ChainsConfig;
+use crate::config::EmeraldConfig;
+
+/// Timeout of the requests to the admin API of a node
+const ADMIN_API_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Status of a running node, served on the `GET /status` route of its admin API
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeStatus {
+    /// Height consensus is working on
+    pub height: u64,
+    /// Round consensus is working on
+    pub round: i64,
+    /// Proposer of the current round
+    pub proposer: Option<String>,
+    /// Number of validators at the current height
+    pub validators: usize,
+    /// Votes for the last decided height
+    pub participation: Option<Participation>,
+    /// Whether the last height was decided from a value synced from the peers,
+    /// i.e. the node is behind the network
+    pub catching_up: bool,
+    /// Latest block decided and committed to the execution client
+    pub el_head: Option<ElHead>,
+    /// Maximum size of the encoded payload of proposals, set in the genesis
+    pub max_proposal_bytes: Option<u64>,
+    /// Multiaddr at which the peers reach the consensus port, when behind a NAT
+    pub external_address: Option<String>,
+    /// Throttle of the values synced from the peers, if configured
+    pub catch_up_throttle: Option<CatchUpThrottleStatus>,
+}
+
+/// Throttle of the values synced from the peers while the node catches up
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatchUpThrottleStatus {
+    /// Limits of the throttle, e.g. `20 blocks/s, 80% EL CPU`
+    pub limits: String,
+    /// Synced values delayed by the throttle since the start of the node
+    pub delayed_values: u64,
+    /// Total delay of the synced values, in milliseconds
+    pub delay_ms: u64,
+}
+
+/// Votes for a decided height
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participation {
+    pub height: u64,
+    pub round: i64,
+    /// Precommits in the commit certificate
+    pub signatures: usize,
+    /// Precommits seen for the decided round, including those received after the decision
+    pub precommits: usize,
+    /// Number of validators at the height
+    pub validators: usize,
+}
+
+/// Block at the head of the execution client
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElHead {
+    pub number: u64,
+    pub hash: String,
+    pub timestamp: u64,
+}
+
+/// Show the status of the node, or of the chains of a multi-chain node
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct StatusCmd {
+    /// Chains manifest of a multi-chain node (default: `<HOME>/chains.toml`)
+    #[clap(long, value_name = "CHAINS_FILE")]
+    pub chains: Option<PathBuf>,
+
+    /// Only show the chain with this id from the chains manifest (can be repeated)
+    #[clap(long = "chain", value_name = "CHAIN_ID")]
+    pub chain_ids: Vec<String>,
+
+    /// Query a running node through its admin API instead, e.g. `127.0.0.1:9100`
+    /// or `https://node.example.com:9100`
+    #[clap(long, value_name = "ADDR", conflicts_with_all = ["chains", "chain_ids"])]
+    pub node: Option<String>,
+
+    /// File holding the bearer token required by the admin API of the node
+    #[clap(long, value_name = "FILE", requires = "node")]
+    pub auth_token_file: Option<PathBuf>,
+
+    /// Print the status of the node as JSON
+    #[clap(long, requires = "node")]
+    pub json: bool,
+}
+
+impl StatusCmd {
+    pub fn run(&self, default_chains_file: &Path, emerald_config_file: &Path) -> Result<()> {
+        if let Some(node) = &self.node {
+            return self.print_node_status(node);
+        }
+
+        if self.chains.is_none() && self.chain_ids.is_empty() {
+            return print_status(None, emerald_config_file);
+        }
+
+        let chains_file = self.chains.as_deref().unwrap_or(default_chains_file);
+        let chains = ChainsConfig::load(chains_file)?;
+
+        for chain in chains.select(&self.chain_ids)? {
+            print_status(Some(&chain.id), &chain.emerald_config_file())?;
+        }
+
+        Ok(())
+    }
+
+    fn print_node_status(&self, node: &str) -> Result<()> {
+        let auth_token = self
+            .auth_token_file
+            .as_deref()
+            .map(read_auth_token)
+            .transpose()?;
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        let status = runtime.block_on(fetch_node_status(node, auth_token.as_deref()))?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            return Ok(());
+        }
+
+        println!("Node {node}:");
+        println!(
+            "  Height:        {} (round {})",
+            status.height, status.round
+        );
+        println!(
+            "  Proposer:      {}",
+            status.proposer.as_deref().unwrap_or("unknown")
+        );
+        println!("  Validators:    {}", status.validators);
+        match &status.participation {
+            Some(participation) => println!(
+                "  Participation: {}/{} precommits at height {} ({} in certificate)",
+                participation.precommits,
+                participation.validators,
+                participation.height,
+                participation.signatures
+            ),
+            None => println!("  Participation: unknown"),
+        }
+        if status.catching_up {
+            println!("  Sync:          catching up");
+        } else {
+            println!("  Sync:          caught up");
+        }
+        if let Some(throttle) = &status.catch_up_throttle {
+            println!(
+                "  Throttle:      {} ({} values delayed by {:.1}s)",
+                throttle.limits,
+                throttle.delayed_values,
+                throttle.delay_ms as f64 / 1000.0
+            );
+        }
+        match &status.el_head {
+            Some(head) => println!("  EL head:       {} ({})", head.number, head.hash),
+            None => println!("  EL head:       unknown"),
+        }
+        if let Some(max_proposal_bytes) = status.max_proposal_bytes {
+            println!("  Max proposal:  {max_proposal_bytes} bytes");
+        }
+        if let Some(external_address) = &status.external_address {
+            println!("  External addr: {external_address}");
+        }
+
+        Ok(())
+    }
+}
+
+/// URL of `route` on the admin API of a running node at `node`, an address or a URL
+pub fn admin_url(node: &str, route: &str) -> Result<Url> {
+    let base = if node.contains("://") {
+        node.to_string()
+    } else {
+        format!("http://{node}")
+    };
+    Url::parse(&base)
+        .and_then(|url| url.join(route))
+        .with_context(|| format!("Invalid node address `{node}`"))
+}
+
+/// Reads the bearer token required by the admin API of a node from `path`
+pub fn read_auth_token(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .map(|token| token.trim().to_string())
+        .with_context(|| format!("Failed to read auth token file `{}`", path.display()))
+}
+
+/// Fetches the status of a running node from its admin API at `node`,
+/// an address or a URL, with the bearer token the API requires if any.
+pub async fn fetch_node_status(node: &str, auth_token: Option<&str>) -> Result<NodeStatus> {
+    let url = admin_url(node, "status")?;
+
+    let mut request = reqwest::Client::new().get(url).timeout(ADMIN_API_TIMEOUT);
+    if let Some(token) = auth_token {
+        request = request.bearer_auth(token);
+    }
+
+    request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Failed to query the admin API of `{node}`"))?
+        .json()
+        .await
+        .context("Failed to parse the status of the node")
+}
+
+fn print_status(chain_id: Option<&str>, emerald_config_file: &Path) -> Result<()> {
+    let content = fs::read_to_string(emerald_config_file).with_context(|| {
+        format!(
+            "Failed to read emerald config file `{}`",
+            emerald_config_file.display()
+        )
+    })?;
+    let emerald_config: EmeraldConfig =
+        toml::from_str(&content).context("Failed to parse emerald config file")?;
+
+    let el_address = &emerald_config.ethereum_config.execution_authrpc_address;
+
+    match chain_id {
+        Some(id) => println!("Chain {id}:"),
+        None => println!("Node:"),
+    }
+    println!("  Moniker: {}", emerald_config.moniker);
+    println!("  EL:      {el_address}");
+    match get_block_number(el_address) {
+        Ok(height) => println!("  Height:  {height}"),
+        Err(e) => println!("  Height:  unavailable ({e})"),
+    }
+    println!();
+
+    Ok(())
+}
+
+fn get_block_number(el_address: &str) -> Result<u64> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let rpc = EthereumRPC::new(Url::parse(el_address)?)?;
+
+        let result: String = rpc
+            .rpc_request("eth_blockNumber", json!([]), Duration::from_secs(2))
+            .await?;
+
+        u64::from_str_radix(result.trim_start_matches("0x"), 16)
+            .map_err(|e| eyre!("Failed to parse block number: {e}"))
+    })
+}
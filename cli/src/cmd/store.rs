@@ -0,0 +1,113 @@
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+use color_eyre::eyre::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::cmd::status::{admin_url, read_auth_token};
+
+/// Timeout of the compaction of the store of a running node, which copies the whole store
+const COMPACTION_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Export, import or compact the consensus store
+///
+/// The archive holds all the tables of the store, independently of the file format of
+/// the database, and can be imported on another machine or architecture. The node must
+/// be stopped, except to compact the store with `--online`.
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct StoreCmd {
+    #[command(subcommand)]
+    pub command: StoreSubcommand,
+}
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+pub enum StoreSubcommand {
+    /// Write the whole store to an archive
+    Export {
+        /// Path of the archive to write
+        #[clap(value_name = "ARCHIVE")]
+        path: PathBuf,
+    },
+
+    /// Restore the store from an archive, the store must not contain any decided value
+    Import {
+        /// Path of the archive to read
+        #[clap(value_name = "ARCHIVE")]
+        path: PathBuf,
+    },
+
+    /// Copy the store to a new file without the space freed by pruning, which then
+    /// replaces the store
+    Compact {
+        /// Compact the store of a running node through its admin API, which keeps
+        /// serving reads and writes during the copy
+        #[clap(long, requires = "node")]
+        online: bool,
+
+        /// Address or URL of the admin API of the running node, e.g. `127.0.0.1:9100`
+        #[clap(long, value_name = "ADDR", requires = "online")]
+        node: Option<String>,
+
+        /// File holding the bearer token required by the admin API of the node
+        #[clap(long, value_name = "FILE", requires = "online")]
+        auth_token_file: Option<PathBuf>,
+    },
+}
+
+/// Outcome of a compaction of the store, served on the `POST /store/compact` route of
+/// the admin API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Number of entries copied to the new file
+    pub entries: u64,
+    /// Number of writes made during the copy, replayed on the new file
+    pub replayed_writes: u64,
+    /// Size in bytes of the store before the compaction
+    pub size_before: u64,
+    /// Size in bytes of the store after the compaction
+    pub size_after: u64,
+}
+
+impl CompactionReport {
+    pub fn print(&self) {
+        println!("Compacted the store:");
+        println!("  Entries copied:  {}", self.entries);
+        println!("  Writes replayed: {}", self.replayed_writes);
+        println!(
+            "  Size:            {} -> {} bytes",
+            self.size_before, self.size_after
+        );
+    }
+}
+
+/// Compacts the store of the running node whose admin API is at `node`, an address or a
+/// URL, with the bearer token in `auth_token_file` if the API requires one
+pub fn compact_online(node: &str, auth_token_file: Option<&Path>) -> Result<CompactionReport> {
+    let auth_token = auth_token_file.map(read_auth_token).transpose()?;
+    let url = admin_url(node, "store/compact")?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let mut request = reqwest::Client::new().post(url).timeout(COMPACTION_TIMEOUT);
+        if let Some(token) = &auth_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to query the admin API of `{node}`"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let reason = response.text().await.unwrap_or_default();
+            bail!("Failed to compact the store of `{node}`: {status} {reason}");
+        }
+
+        response
+            .json()
+            .await
+            .context("Failed to parse the outcome of the compaction")
+    })
+}
@@ -14,8 +14,9 @@ use color_eyre::Result;
 use malachitebft_eth_types::Address;
 use tracing::info;
 
+use super::ports;
 use super::reth::{self, RethProcess};
-use super::types::RethNode;
+use super::types::{RethNode, RethPorts};
 use crate::cmd::testnet::rpc::RpcClient;
 use crate::config::*;
 use crate::utils::retry::retry_with_timeout;
@@ -48,6 +49,23 @@ impl TestnetAddNodeCmd {
     pub fn run(&self, home_dir: &Path) -> Result<()> {
         println!("📝 Adding non-validator node to testnet...\n");
 
+        let node_id = self.setup_node(home_dir)?;
+
+        // 11. Spawn Emerald process
+        println!("\n💎 Starting Emerald consensus node...");
+        let emerald_process = self.spawn_emerald_node(home_dir, node_id)?;
+        println!("✓ Emerald node started (PID: {})", emerald_process.pid);
+
+        println!("\n✅ Non-validator node {node_id} added successfully!");
+        print_logs(home_dir, node_id);
+
+        Ok(())
+    }
+
+    /// Creates the directories, configs and key of a new node, and starts its Reth node.
+    ///
+    /// Returns the ID of the new node, whose Emerald node is left to the caller to start.
+    pub(super) fn setup_node(&self, home_dir: &Path) -> Result<usize> {
         // 1. Check if custom-reth is available
         print!("Checking custom-reth installation... ");
         match reth::check_installation(&self.custom_reth_bin) {
@@ -91,7 +109,12 @@ impl TestnetAddNodeCmd {
             Address::repeat_byte(42)
         };
 
-        // 6. Generate Emerald config
+        // 6. Allocate the Reth ports, then generate the Emerald config
+        let taken = ports::used_ports(home_dir, node_id);
+        let ports = RethPorts::allocate(node_id, &taken)?;
+        ports.save(home_dir, node_id)?;
+        println!("\n🔌 Reth ports allocated (HTTP: {})", ports.http);
+
         println!("\n⚙️  Generating Emerald config...");
         info!("Will use address `{fee_receiver}` as Fee Receiver address");
         self.generate_emerald_config(home_dir, node_id, fee_receiver)?;
@@ -133,21 +156,7 @@ impl TestnetAddNodeCmd {
         self.connect_to_peers(home_dir, node_id)?;
         println!("✓ Connected to peers");
 
-        // 11. Spawn Emerald process
-        println!("\n💎 Starting Emerald consensus node...");
-        let emerald_process = self.spawn_emerald_node(home_dir, node_id)?;
-        println!("✓ Emerald node started (PID: {})", emerald_process.pid);
-
-        println!("\n✅ Non-validator node {node_id} added successfully!");
-        println!("\n📁 Logs:");
-        println!("  Reth: {}/{}/logs/reth.log", home_dir.display(), node_id);
-        println!(
-            "  Emerald: {}/{}/logs/emerald.log",
-            home_dir.display(),
-            node_id
-        );
-
-        Ok(())
+        Ok(node_id)
     }
 
     fn find_next_node_id(&self, home_dir: &Path) -> Result<usize> {
@@ -302,11 +311,9 @@ impl TestnetAddNodeCmd {
         node_id: usize,
         fee_receiver: Address,
     ) -> Result<()> {
-        use super::types::RethPorts;
-
         let config_dir = home_dir.join(node_id.to_string()).join("config");
         let config_path = config_dir.join("emerald.toml");
-        let ports = RethPorts::for_node(node_id);
+        let ports = RethPorts::load(home_dir, node_id);
 
         // JWT secret is in the assets directory
         let jwt_path = home_dir.join("assets").join("jwtsecret");
@@ -436,7 +443,11 @@ fee_recipient = "{}"
         Ok(())
     }
 
-    fn spawn_emerald_node(&self, home_dir: &Path, node_id: usize) -> Result<EmeraldProcess> {
+    pub(super) fn spawn_emerald_node(
+        &self,
+        home_dir: &Path,
+        node_id: usize,
+    ) -> Result<EmeraldProcess> {
         let node_home = home_dir.join(node_id.to_string());
         let config_file = node_home.join("config").join("emerald.toml");
 
@@ -447,8 +458,8 @@ fee_recipient = "{}"
         let log_file_path = log_dir.join("emerald.log");
         let pid_file = node_home.join("emerald.pid");
 
-        // For non-validator nodes, we don't pass a priv_validator_key.json
-        // Emerald should handle this gracefully and run as a non-validator
+        // The node signs with the priv_validator_key.json of its home directory,
+        // and only acts as a validator once its key is part of the validator set
         // Check for built binary first, then fallback to PATH
         let emerald_bin = {
             let p = PathBuf::from(self.emerald_bin.clone());
@@ -500,7 +511,17 @@ fee_recipient = "{}"
 }
 
 #[allow(dead_code)]
-struct EmeraldProcess {
-    pid: u32,
+pub(super) struct EmeraldProcess {
+    pub(super) pid: u32,
     log_file: PathBuf,
 }
+
+pub(super) fn print_logs(home_dir: &Path, node_id: usize) {
+    println!("\n📁 Logs:");
+    println!("  Reth: {}/{}/logs/reth.log", home_dir.display(), node_id);
+    println!(
+        "  Emerald: {}/{}/logs/emerald.log",
+        home_dir.display(),
+        node_id
+    );
+}
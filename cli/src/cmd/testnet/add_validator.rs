@@ -0,0 +1,151 @@
+//! Add a validator node to a running testnet
+
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context as _};
+use color_eyre::Result;
+use tracing::info;
+
+use super::add_node::{print_logs, TestnetAddNodeCmd};
+use super::types::RethPorts;
+use crate::cmd::dev::DEV_ACCOUNT_PRIVATE_KEY;
+use crate::utils::retry::retry_with_timeout;
+
+#[derive(Parser, Debug, Clone, PartialEq)]
+pub struct TestnetAddValidatorCmd {
+    #[command(flatten)]
+    pub node: TestnetAddNodeCmd,
+
+    /// Path to the `emerald-utils` executable. The program first checks the path provided here;
+    /// if the binary is not found, it will try to resolve
+    /// `emerald-utils` from $PATH instead.
+    #[clap(long, default_value = "./target/debug/emerald-utils")]
+    pub emerald_utils_bin: String,
+
+    /// Voting power of the new validator
+    #[clap(long, default_value_t = 100)]
+    pub power: u64,
+
+    /// Private key of the owner of the ValidatorManager contract, which pays for the transaction.
+    /// If not specified will use the owner funded in the genesis of the testnet
+    #[clap(long)]
+    pub owner_private_key: Option<String>,
+
+    /// Seconds to wait for the validator to be part of the validator set on-chain
+    #[clap(long, default_value_t = 60)]
+    pub timeout: u64,
+}
+
+impl TestnetAddValidatorCmd {
+    /// Execute the add-validator command
+    pub fn run(&self, home_dir: &Path) -> Result<()> {
+        println!("📝 Adding validator node to testnet...\n");
+
+        let node_id = self.node.setup_node(home_dir)?;
+
+        // 11. Register the validator in the ValidatorManager contract
+        println!("\n🗳️  Registering validator with power {}...", self.power);
+        let pubkey = self.extract_public_key(home_dir, node_id)?;
+        self.poa(home_dir, ["add-validator", "--validator-pubkey", &pubkey])
+            .args(["--power", &self.power.to_string()])
+            .args([
+                "--owner-private-key",
+                self.owner_private_key
+                    .as_deref()
+                    .unwrap_or(DEV_ACCOUNT_PRIVATE_KEY),
+            ])
+            .output()
+            .context("Failed to execute emerald-utils")
+            .and_then(check_output)?;
+        println!("✓ Transaction included");
+
+        // 12. Wait for the validator set to be updated
+        println!("\n⏳ Waiting for the validator set to be updated...");
+        // `poa list` prints the keys in uncompressed form, with the 04 prefix
+        let listed_key = format!("04{}", pubkey.trim_start_matches("0x"));
+        retry_with_timeout(
+            "validator set update",
+            Duration::from_secs(self.timeout),
+            Duration::from_secs(1),
+            || {
+                let output = self
+                    .poa(home_dir, ["list"])
+                    .output()
+                    .context("Failed to execute emerald-utils")
+                    .and_then(check_output)?;
+
+                if output.contains(&listed_key) {
+                    Ok(())
+                } else {
+                    Err(eyre!("validator {pubkey} not in the validator set yet"))
+                }
+            },
+        )?;
+        println!("✓ Validator set updated");
+
+        // 13. Spawn Emerald process
+        println!("\n💎 Starting Emerald consensus node...");
+        let emerald_process = self.node.spawn_emerald_node(home_dir, node_id)?;
+        println!("✓ Emerald node started (PID: {})", emerald_process.pid);
+
+        println!("\n✅ Validator node {node_id} added successfully!");
+        print_logs(home_dir, node_id);
+
+        Ok(())
+    }
+
+    fn extract_public_key(&self, home_dir: &Path, node_id: usize) -> Result<String> {
+        let key_file = home_dir
+            .join(node_id.to_string())
+            .join("config")
+            .join("priv_validator_key.json");
+
+        let output = Command::new(resolve_bin(&self.node.emerald_bin, "emerald"))
+            .arg("show-pubkey")
+            .arg(&key_file)
+            .output()
+            .context("Failed to extract public key")
+            .and_then(check_output)?;
+
+        Ok(output.trim().to_string())
+    }
+
+    /// `emerald-utils poa` command against the Reth node of node 0
+    fn poa<const N: usize>(&self, home_dir: &Path, args: [&str; N]) -> Command {
+        let emerald_utils_bin = resolve_bin(&self.emerald_utils_bin, "emerald-utils");
+        info!(
+            "Using `{}` for emerald-utils binary when adding validator",
+            emerald_utils_bin.display()
+        );
+
+        let rpc_url = format!("http://127.0.0.1:{}", RethPorts::load(home_dir, 0).http);
+
+        let mut command = Command::new(emerald_utils_bin);
+        command.args(["poa", "--rpc-url", &rpc_url]).args(args);
+        command
+    }
+}
+
+// Check for built binary first, then fallback to PATH
+fn resolve_bin(path: &str, name: &str) -> PathBuf {
+    let p = PathBuf::from(path);
+    if p.exists() {
+        p
+    } else {
+        PathBuf::from(name)
+    }
+}
+
+fn check_output(output: std::process::Output) -> Result<String> {
+    if !output.status.success() {
+        return Err(eyre!(
+            "Command failed:\n\nSTDERR:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
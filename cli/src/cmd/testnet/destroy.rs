@@ -8,6 +8,8 @@ use clap::Parser;
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
 
+use super::metrics;
+
 #[derive(Parser, Debug, Clone, PartialEq)]
 pub struct TestnetDestroyCmd {
     /// Skip confirmation prompt
@@ -104,6 +106,11 @@ impl TestnetDestroyCmd {
             }
         }
 
+        // Stop the metrics aggregator
+        if metrics::stop(home_dir).is_some() {
+            stopped_count += 1;
+        }
+
         if stopped_count > 0 {
             println!("   Stopped {stopped_count} process(es)");
         }
@@ -0,0 +1,556 @@
+//! Aggregation of the metrics of the nodes of a testnet
+//!
+//! `emerald testnet metrics` serves the metrics of all the Emerald and Reth nodes of the
+//! testnet on a single `/metrics` endpoint. Each scrape of the endpoint scrapes the nodes,
+//! and adds to their samples the `node` label, i.e. the id of the node, and the
+//! `client_name` label, `malachite` or `reth` as in `monitoring/prometheus.yml`. The nodes
+//! are looked up in the home directory at each scrape, so that the nodes added to the
+//! testnet are scraped as well, and `testnet_node_up` tells which ones answered.
+//!
+//! `emerald testnet start` runs it in the background, and writes a Grafana dashboard of
+//! the main metrics of the nodes next to it.
+
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+use clap::Parser;
+use color_eyre::eyre::Context as _;
+use color_eyre::Result;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+use super::types::{ProcessHandle, RethPorts};
+use crate::config::load_node_config;
+
+/// Address the metrics of the testnet are served on by default
+pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:29500";
+
+/// Files of the aggregator, in the home directory of the testnet
+pub const PID_FILE: &str = "metrics.pid";
+pub const LOG_FILE: &str = "metrics.log";
+pub const DASHBOARD_FILE: &str = "grafana-dashboard.json";
+
+/// Timeout of the scrape of a node
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Parser, Debug, Clone, PartialEq)]
+pub struct TestnetMetricsCmd {
+    /// Address the metrics of all the nodes are served on, at `/metrics`
+    #[clap(long, default_value = DEFAULT_LISTEN_ADDR)]
+    pub listen_addr: SocketAddr,
+}
+
+impl TestnetMetricsCmd {
+    /// Serve the metrics of the testnet until the process is stopped
+    pub fn run(&self, home_dir: &Path) -> Result<()> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(serve(self.listen_addr, home_dir.to_path_buf()))
+    }
+}
+
+/// PID of the aggregator started by `testnet start`, if it is running
+pub fn running(home_dir: &Path) -> Option<u32> {
+    ProcessHandle::from_pid_file(&home_dir.join(PID_FILE))
+        .ok()
+        .filter(ProcessHandle::is_running)
+        .map(|handle| handle.pid)
+}
+
+/// Stops the aggregator started by `testnet start`, and returns its PID if it was running
+pub fn stop(home_dir: &Path) -> Option<u32> {
+    let handle = ProcessHandle::from_pid_file(&home_dir.join(PID_FILE))
+        .ok()
+        .filter(ProcessHandle::is_running);
+    if let Some(handle) = &handle {
+        let _ = handle.stop(Duration::from_secs(5));
+    }
+    let _ = fs::remove_file(home_dir.join(PID_FILE));
+    handle.map(|handle| handle.pid)
+}
+
+/// Metrics endpoint of a node
+struct Target {
+    node_id: usize,
+    client_name: &'static str,
+    url: String,
+}
+
+impl Target {
+    fn labels(&self) -> String {
+        format!(
+            "node=\"{}\",client_name=\"{}\"",
+            self.node_id, self.client_name
+        )
+    }
+}
+
+/// Metrics endpoints of the nodes of the testnet in `home_dir`. The Emerald metrics are
+/// only scraped when enabled, and must be served without TLS nor token.
+fn targets(home_dir: &Path) -> Vec<Target> {
+    let mut node_ids: Vec<usize> = fs::read_dir(home_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect();
+    node_ids.sort_unstable();
+
+    let mut targets = Vec::new();
+    for node_id in node_ids {
+        let config_dir = home_dir.join(node_id.to_string()).join("config");
+        let config = load_node_config(
+            &config_dir.join("config.toml"),
+            &config_dir.join("emerald.toml"),
+        );
+        if let Some(config) = config.ok().filter(|config| config.metrics.enabled) {
+            let mut addr = config.metrics.listen_addr;
+            if addr.ip().is_unspecified() {
+                addr.set_ip([127, 0, 0, 1].into());
+            }
+            targets.push(Target {
+                node_id,
+                client_name: "malachite",
+                url: format!("http://{addr}/metrics"),
+            });
+        }
+
+        targets.push(Target {
+            node_id,
+            client_name: "reth",
+            url: format!(
+                "http://127.0.0.1:{}/",
+                RethPorts::load(home_dir, node_id).metrics
+            ),
+        });
+    }
+
+    targets
+}
+
+struct Aggregator {
+    home_dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl Aggregator {
+    /// Scrapes the nodes concurrently, and merges their metrics
+    async fn scrape(&self) -> String {
+        let targets = targets(&self.home_dir);
+        let scrapes: Vec<_> = targets
+            .iter()
+            .map(|target| tokio::spawn(fetch(self.client.clone(), target.url.clone())))
+            .collect();
+
+        let mut families = Families::default();
+        let mut up = String::from(
+            "# HELP testnet_node_up Whether the metrics of the node were scraped\n\
+             # TYPE testnet_node_up gauge\n",
+        );
+        for (target, scrape) in targets.iter().zip(scrapes) {
+            let body = scrape.await.ok().flatten();
+            up.push_str(&format!(
+                "testnet_node_up{{{}}} {}\n",
+                target.labels(),
+                u8::from(body.is_some())
+            ));
+            if let Some(body) = body {
+                families.add(&body, &target.labels());
+            }
+        }
+        families.add(&up, "");
+
+        families.render()
+    }
+}
+
+async fn fetch(client: reqwest::Client, url: String) -> Option<String> {
+    let response = client.get(&url).send().await;
+    match response.and_then(|response| response.error_for_status()) {
+        Ok(response) => response.text().await.ok(),
+        Err(e) => {
+            debug!(%url, "Failed to scrape node: {e}");
+            None
+        }
+    }
+}
+
+/// Serves the metrics of the nodes of the testnet in `home_dir` on `listen_addr`
+#[tracing::instrument(name = "testnet_metrics", skip_all)]
+pub async fn serve(listen_addr: SocketAddr, home_dir: PathBuf) -> Result<()> {
+    let aggregator = Arc::new(Aggregator {
+        home_dir,
+        client: reqwest::Client::builder().timeout(SCRAPE_TIMEOUT).build()?,
+    });
+    let app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(aggregator);
+
+    let listener = TcpListener::bind(listen_addr)
+        .await
+        .with_context(|| format!("Failed to listen on {listen_addr}"))?;
+    info!(address = %listen_addr, "Serving the metrics of the testnet");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn get_metrics(State(aggregator): State<Arc<Aggregator>>) -> String {
+    aggregator.scrape().await
+}
+
+/// Metric families of several nodes, whose samples are grouped under a single `# HELP`
+/// and `# TYPE` per family, as the exposition format requires
+#[derive(Default)]
+struct Families {
+    names: Vec<String>,
+    families: HashMap<String, Family>,
+}
+
+#[derive(Default)]
+struct Family {
+    metadata: Vec<String>,
+    samples: Vec<String>,
+}
+
+impl Families {
+    fn family(&mut self, name: &str) -> &mut Family {
+        if !self.families.contains_key(name) {
+            self.names.push(name.to_string());
+        }
+        self.families.entry(name.to_string()).or_default()
+    }
+
+    /// Adds the metrics of a node, with `labels` added to each sample
+    fn add(&mut self, body: &str, labels: &str) {
+        // Family of the last `# HELP` or `# TYPE`, whose samples follow it
+        let mut current: Option<String> = None;
+
+        for line in body.lines().map(str::trim_end) {
+            if line.is_empty() || line == "# EOF" {
+                continue;
+            }
+
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut words = comment.split(' ');
+                if let (Some(kind @ ("HELP" | "TYPE" | "UNIT")), Some(name)) =
+                    (words.next(), words.next())
+                {
+                    let family = self.family(name);
+                    // Each node describes the family the same way
+                    if !family
+                        .metadata
+                        .iter()
+                        .any(|metadata| metadata.split(' ').nth(1) == Some(kind))
+                    {
+                        family.metadata.push(line.to_string());
+                    }
+                    current = Some(name.to_string());
+                }
+                continue;
+            }
+
+            let name = sample_name(line);
+            let family = match current.take() {
+                // e.g. the `_bucket`, `_sum` and `_count` samples of a histogram
+                Some(family) if name.starts_with(family.as_str()) => family,
+                _ => name.to_string(),
+            };
+            self.family(&family).samples.push(relabel(line, labels));
+            current = Some(family);
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = String::new();
+        for name in &self.names {
+            let family = &self.families[name];
+            for line in family.metadata.iter().chain(&family.samples) {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+        output
+    }
+}
+
+/// Name of the metric of a sample line
+fn sample_name(sample: &str) -> &str {
+    let end = sample.find(['{', ' ']).unwrap_or(sample.len());
+    &sample[..end]
+}
+
+/// Adds `labels` to the labels of a sample line
+fn relabel(sample: &str, labels: &str) -> String {
+    if labels.is_empty() {
+        return sample.to_string();
+    }
+
+    let name = sample_name(sample);
+    let rest = &sample[name.len()..];
+    match rest.strip_prefix('{') {
+        Some(rest) if rest.starts_with('}') => format!("{name}{{{labels}{rest}"),
+        Some(rest) => format!("{name}{{{labels},{rest}"),
+        None => format!("{name}{{{labels}}}{rest}"),
+    }
+}
+
+/// Writes the Grafana dashboard of the main metrics of the nodes, queried from the
+/// Prometheus datasource `prometheus` scraping the aggregator
+pub fn write_dashboard(home_dir: &Path) -> Result<PathBuf> {
+    let path = home_dir.join(DASHBOARD_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&dashboard())?)
+        .with_context(|| format!("Failed to write the dashboard {}", path.display()))?;
+    Ok(path)
+}
+
+fn dashboard() -> Value {
+    let node = r#"node=~"$node""#;
+    let panels = [
+        (
+            "Consensus height",
+            format!(r#"malachitebft_core_consensus_height{{{node}}}"#),
+            "node {{node}}",
+        ),
+        (
+            "Consensus round",
+            format!(r#"malachitebft_core_consensus_round{{{node}}}"#),
+            "node {{node}}",
+        ),
+        (
+            "Blocks per second",
+            format!(r#"rate(malachitebft_core_consensus_height{{{node}}}[$__rate_interval])"#),
+            "node {{node}}",
+        ),
+        (
+            "Transactions per second",
+            format!(r#"app_channel_txs_per_second{{{node}}}"#),
+            "node {{node}}",
+        ),
+        (
+            "Block time by proposer (p95)",
+            format!(
+                r#"histogram_quantile(0.95, sum by (le, proposer) (rate(app_channel_proposer_block_time_bucket{{{node}}}[$__rate_interval])))"#
+            ),
+            "{{proposer}}",
+        ),
+        (
+            "Failed rounds by proposer",
+            format!(
+                r#"sum by (proposer) (increase(app_channel_failed_rounds_total{{{node}}}[$__rate_interval]))"#
+            ),
+            "{{proposer}}",
+        ),
+        (
+            "Execution client state",
+            format!(r#"app_channel_el_state{{{node}}} == 1"#),
+            "node {{node}} {{state}}",
+        ),
+        (
+            "Reth chain height",
+            format!(r#"reth_blockchain_tree_canonical_chain_height{{{node}}}"#),
+            "node {{node}}",
+        ),
+        (
+            "Reth connected peers",
+            format!(r#"reth_network_connected_peers{{{node}}}"#),
+            "node {{node}}",
+        ),
+        (
+            "Scraped nodes",
+            format!(r#"testnet_node_up{{{node}}}"#),
+            "node {{node}} {{client_name}}",
+        ),
+    ];
+
+    let panels: Vec<Value> = panels
+        .into_iter()
+        .enumerate()
+        .map(|(i, (title, expr, legend))| {
+            json!({
+                "id": i + 1,
+                "type": "timeseries",
+                "title": title,
+                "datasource": { "type": "prometheus", "uid": "prometheus" },
+                "gridPos": { "h": 8, "w": 12, "x": (i % 2) * 12, "y": (i / 2) * 8 },
+                "targets": [{
+                    "refId": "A",
+                    "datasource": { "type": "prometheus", "uid": "prometheus" },
+                    "expr": expr,
+                    "legendFormat": legend,
+                }],
+            })
+        })
+        .collect();
+
+    json!({
+        "title": "Emerald testnet",
+        "uid": "emerald-testnet",
+        "schemaVersion": 39,
+        "refresh": "5s",
+        "time": { "from": "now-15m", "to": "now" },
+        "templating": {
+            "list": [{
+                "name": "node",
+                "label": "Node",
+                "type": "query",
+                "datasource": { "type": "prometheus", "uid": "prometheus" },
+                "query": "label_values(testnet_node_up, node)",
+                "multi": true,
+                "includeAll": true,
+                "current": { "text": "All", "value": "$__all" },
+                "refresh": 2,
+            }],
+        },
+        "panels": panels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE_METRICS: &str = "\
+# HELP reth_network_connected_peers Number of connected peers
+# TYPE reth_network_connected_peers gauge
+reth_network_connected_peers 3
+# HELP block_time Time between blocks
+# TYPE block_time histogram
+block_time_bucket{le=\"1\"} 2
+block_time_bucket{le=\"+Inf\"} 4
+block_time_sum 5.5
+block_time_count 4
+untyped_sample{kind=\"a\"} 1
+# EOF
+";
+
+    #[test]
+    fn test_relabel() {
+        assert_eq!(sample_name("up 1"), "up");
+        assert_eq!(sample_name("up{a=\"b\"} 1"), "up");
+
+        let labels = "node=\"0\"";
+        assert_eq!(relabel("up 1", labels), "up{node=\"0\"} 1");
+        assert_eq!(relabel("up{} 1", labels), "up{node=\"0\"} 1");
+        assert_eq!(
+            relabel("up{a=\"b\"} 1 1700000000", labels),
+            "up{node=\"0\",a=\"b\"} 1 1700000000"
+        );
+        assert_eq!(relabel("up{a=\"b\"} 1", ""), "up{a=\"b\"} 1");
+    }
+
+    #[test]
+    fn test_families_of_nodes_are_merged() {
+        let mut families = Families::default();
+        families.add(NODE_METRICS, "node=\"0\"");
+        families.add(NODE_METRICS, "node=\"1\"");
+
+        let output = families.render();
+        let lines: Vec<&str> = output.lines().collect();
+        let expected = [
+            "# HELP reth_network_connected_peers Number of connected peers",
+            "# TYPE reth_network_connected_peers gauge",
+            "reth_network_connected_peers{node=\"0\"} 3",
+            "reth_network_connected_peers{node=\"1\"} 3",
+            "# HELP block_time Time between blocks",
+            "# TYPE block_time histogram",
+            "block_time_bucket{node=\"0\",le=\"1\"} 2",
+            "block_time_bucket{node=\"0\",le=\"+Inf\"} 4",
+            "block_time_sum{node=\"0\"} 5.5",
+            "block_time_count{node=\"0\"} 4",
+            "block_time_bucket{node=\"1\",le=\"1\"} 2",
+            "block_time_bucket{node=\"1\",le=\"+Inf\"} 4",
+            "block_time_sum{node=\"1\"} 5.5",
+            "block_time_count{node=\"1\"} 4",
+            "untyped_sample{node=\"0\",kind=\"a\"} 1",
+            "untyped_sample{node=\"1\",kind=\"a\"} 1",
+        ];
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn test_targets_of_the_testnet() {
+        let dir = tempfile::tempdir().unwrap();
+        for node in ["1", "0", "logs"] {
+            fs::create_dir_all(dir.path().join(node)).unwrap();
+        }
+        fs::write(dir.path().join("2"), "not a node").unwrap();
+
+        // Without an Emerald config, only the Reth nodes are scraped
+        let targets = targets(dir.path());
+        let targets: Vec<_> = targets
+            .iter()
+            .map(|target| (target.labels(), target.url.as_str()))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (
+                    "node=\"0\",client_name=\"reth\"".to_string(),
+                    "http://127.0.0.1:8648/"
+                ),
+                (
+                    "node=\"1\",client_name=\"reth\"".to_string(),
+                    "http://127.0.0.1:8678/"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scrape_of_the_testnet() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Router::new().route("/", get(|| async { NODE_METRICS }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        // Node 1 does not answer
+        let unused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let unused_port = unused.local_addr().unwrap().port();
+        drop(unused);
+
+        for (node_id, metrics) in [(0, port), (1, unused_port)] {
+            let ports = RethPorts {
+                metrics,
+                ..RethPorts::for_node(node_id)
+            };
+            ports.save(dir.path(), node_id).unwrap();
+        }
+
+        let aggregator = Aggregator {
+            home_dir: dir.path().to_path_buf(),
+            client: reqwest::Client::builder()
+                .timeout(SCRAPE_TIMEOUT)
+                .build()
+                .unwrap(),
+        };
+        let output = aggregator.scrape().await;
+
+        assert!(output.contains("reth_network_connected_peers{node=\"0\",client_name=\"reth\"} 3"));
+        assert!(!output.contains("node=\"1\",client_name=\"reth\"} 3"));
+        assert!(output.contains("testnet_node_up{node=\"0\",client_name=\"reth\"} 1"));
+        assert!(output.contains("testnet_node_up{node=\"1\",client_name=\"reth\"} 0"));
+        assert_eq!(output.matches("# TYPE testnet_node_up gauge").count(), 1);
+    }
+
+    #[test]
+    fn test_dashboard_queries_the_aggregated_labels() {
+        let dashboard = dashboard();
+        let panels = dashboard["panels"].as_array().unwrap();
+        assert!(!panels.is_empty());
+        for panel in panels {
+            let expr = panel["targets"][0]["expr"].as_str().unwrap();
+            assert!(expr.contains("node=~\"$node\""), "{expr}");
+        }
+    }
+}
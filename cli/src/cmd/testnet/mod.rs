@@ -9,9 +9,12 @@ use malachitebft_config::LoggingConfig;
 use malachitebft_core_types::{Context, SigningScheme};
 
 mod add_node;
+mod add_validator;
 pub mod config;
 mod destroy;
 mod generate;
+pub mod metrics;
+pub mod ports;
 pub mod reth;
 mod rpc;
 mod start;
@@ -23,8 +26,10 @@ pub mod types;
 pub mod utils;
 
 pub use add_node::TestnetAddNodeCmd;
+pub use add_validator::TestnetAddValidatorCmd;
 pub use destroy::TestnetDestroyCmd;
 pub use generate::{RuntimeFlavour, TestnetConfig, TestnetGenerateCmd};
+pub use metrics::TestnetMetricsCmd;
 pub use reth::check_installation;
 pub use start::TestnetStartCmd;
 pub use start_node::TestnetStartNodeCmd;
@@ -60,6 +65,9 @@ pub enum TestnetSubcommand {
     /// Add a new node to an existing testnet
     AddNode(TestnetAddNodeCmd),
 
+    /// Add a new validator node to a running testnet
+    AddValidator(TestnetAddValidatorCmd),
+
     /// Restart an existing stopped node by ID
     StartNode(TestnetStartNodeCmd),
 
@@ -71,6 +79,9 @@ pub enum TestnetSubcommand {
 
     /// Remove all testnet data
     Destroy(TestnetDestroyCmd),
+
+    /// Serve the metrics of all the nodes on a single endpoint
+    Metrics(TestnetMetricsCmd),
 }
 
 impl TestnetCmd {
@@ -86,10 +97,12 @@ impl TestnetCmd {
             Some(TestnetSubcommand::Start(cmd)) => cmd.run(node, home_dir, logging),
             Some(TestnetSubcommand::Status(cmd)) => cmd.run(home_dir),
             Some(TestnetSubcommand::AddNode(cmd)) => cmd.run(home_dir),
+            Some(TestnetSubcommand::AddValidator(cmd)) => cmd.run(home_dir),
             Some(TestnetSubcommand::StartNode(cmd)) => cmd.run(home_dir),
             Some(TestnetSubcommand::StopNode(cmd)) => cmd.run(home_dir),
             Some(TestnetSubcommand::Stop(cmd)) => cmd.run(home_dir),
             Some(TestnetSubcommand::Destroy(cmd)) => cmd.run(home_dir),
+            Some(TestnetSubcommand::Metrics(cmd)) => cmd.run(home_dir),
             // Backward compatibility: if no subcommand, use generate with flattened opts
             None => self.generate_opts.run(node, home_dir, logging),
         }
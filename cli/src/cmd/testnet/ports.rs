@@ -0,0 +1,338 @@
+//! Allocation of the ports of the Reth nodes of a testnet
+//!
+//! The ports of a node start from the fixed layout of [`RethPorts::for_node`], and are
+//! shifted until all of them are free on this machine. The chosen ports are saved in the
+//! home directory of the node, from which the Reth spawn arguments and the Emerald config
+//! are rendered. A ports file can set the ports of some nodes instead.
+
+use std::collections::HashSet;
+use std::fs;
+use std::net::{TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context as _};
+use color_eyre::Result;
+use serde::Deserialize;
+
+use super::types::RethPorts;
+
+/// File of the ports of a node, in its home directory
+const PORTS_FILE: &str = "reth_ports.toml";
+
+/// Shift of the ports of a node between two layouts, beyond the default ports of the
+/// 20 nodes a testnet can have
+const LAYOUT_STRIDE: usize = 600;
+
+/// Number of layouts tried for a node before giving up
+const MAX_LAYOUTS: usize = 50;
+
+/// Ports of some nodes set by the user, e.g.
+///
+/// ```toml
+/// [[nodes]]
+/// id = 0
+/// http = 18645
+/// ws = 18646
+/// authrpc = 18647
+/// metrics = 18648
+/// discovery = 18649
+/// p2p = 18649
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PortsOverride {
+    #[serde(default)]
+    nodes: Vec<NodePorts>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct NodePorts {
+    id: usize,
+    #[serde(flatten)]
+    ports: RethPorts,
+}
+
+impl PortsOverride {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the ports file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse the ports file {}", path.display()))
+    }
+
+    fn get(&self, node_id: usize) -> Option<RethPorts> {
+        self.nodes
+            .iter()
+            .find(|node| node.id == node_id)
+            .map(|node| node.ports)
+    }
+}
+
+impl RethPorts {
+    fn path(home_dir: &Path, node_id: usize) -> PathBuf {
+        home_dir.join(node_id.to_string()).join(PORTS_FILE)
+    }
+
+    /// Ports saved for the node, or the default layout for the testnets created before
+    /// the ports were saved
+    pub fn load(home_dir: &Path, node_id: usize) -> Self {
+        Self::load_saved(home_dir, node_id).unwrap_or_else(|| Self::for_node(node_id))
+    }
+
+    fn load_saved(home_dir: &Path, node_id: usize) -> Option<Self> {
+        let contents = fs::read_to_string(Self::path(home_dir, node_id)).ok()?;
+        toml::from_str(&contents).ok()
+    }
+
+    pub fn save(&self, home_dir: &Path, node_id: usize) -> Result<()> {
+        let path = Self::path(home_dir, node_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, toml::to_string(self)?)
+            .with_context(|| format!("Failed to save the ports of node {node_id}"))
+    }
+
+    /// Distinct ports of the node
+    fn ports(&self) -> Vec<u16> {
+        let mut ports = vec![self.http, self.ws, self.authrpc, self.metrics, self.p2p];
+        if self.discovery != self.p2p {
+            ports.push(self.discovery);
+        }
+        ports
+    }
+
+    fn shifted(&self, offset: usize) -> Option<Self> {
+        let shift = |port: u16| u16::try_from(port as usize + offset).ok();
+        Some(Self {
+            http: shift(self.http)?,
+            ws: shift(self.ws)?,
+            authrpc: shift(self.authrpc)?,
+            metrics: shift(self.metrics)?,
+            discovery: shift(self.discovery)?,
+            p2p: shift(self.p2p)?,
+        })
+    }
+
+    /// First layout of ports of the node, from its default one, whose ports are all free
+    /// and not in `taken`
+    pub fn allocate(node_id: usize, taken: &HashSet<u16>) -> Result<Self> {
+        let default = Self::for_node(node_id);
+        (0..MAX_LAYOUTS)
+            .map_while(|layout| default.shifted(layout * LAYOUT_STRIDE))
+            .find(|ports| {
+                ports
+                    .ports()
+                    .into_iter()
+                    .all(|port| !taken.contains(&port) && is_free(port))
+            })
+            .ok_or_else(|| {
+                eyre!("No free ports found for the Reth node {node_id}, set them in a ports file")
+            })
+    }
+}
+
+/// Whether the Reth node can listen on `port`, over TCP for its RPC and p2p servers and
+/// over UDP for its discovery
+fn is_free(port: u16) -> bool {
+    TcpListener::bind(("0.0.0.0", port)).is_ok() && UdpSocket::bind(("0.0.0.0", port)).is_ok()
+}
+
+/// Allocates and saves the ports of the nodes `0..nodes`.
+///
+/// The ports set in `overrides` are used as they are. With `keep_saved`, e.g. when a start
+/// is resumed, the ports already saved for a node are kept, since its Reth node may be
+/// running on them.
+pub fn allocate_ports(
+    home_dir: &Path,
+    nodes: usize,
+    overrides: &PortsOverride,
+    keep_saved: bool,
+) -> Result<Vec<RethPorts>> {
+    let mut taken = HashSet::new();
+    let mut allocated = Vec::with_capacity(nodes);
+
+    for node_id in 0..nodes {
+        let saved = keep_saved
+            .then(|| RethPorts::load_saved(home_dir, node_id))
+            .flatten();
+
+        let ports = match overrides.get(node_id).or(saved) {
+            Some(ports) => {
+                if let Some(port) = ports.ports().into_iter().find(|p| taken.contains(p)) {
+                    return Err(eyre!(
+                        "Port {port} of the Reth node {node_id} is already used by another node"
+                    ));
+                }
+                ports
+            }
+            None => RethPorts::allocate(node_id, &taken)?,
+        };
+
+        taken.extend(ports.ports());
+        ports.save(home_dir, node_id)?;
+        allocated.push(ports);
+    }
+
+    Ok(allocated)
+}
+
+/// Ports used by the nodes `0..nodes` of an existing testnet
+pub fn used_ports(home_dir: &Path, nodes: usize) -> HashSet<u16> {
+    (0..nodes)
+        .flat_map(|node_id| RethPorts::load(home_dir, node_id).ports())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(toml: &str) -> PortsOverride {
+        toml::from_str(toml).unwrap()
+    }
+
+    /// Whether `ports` is the default layout of the node shifted by whole layouts
+    fn is_layout_of(ports: &RethPorts, node_id: usize) -> bool {
+        let default = RethPorts::for_node(node_id);
+        (0..MAX_LAYOUTS).any(|layout| default.shifted(layout * LAYOUT_STRIDE) == Some(*ports))
+    }
+
+    #[test]
+    fn test_ports_of_a_layout() {
+        let ports = RethPorts::for_node(0);
+        assert_eq!(ports.ports(), vec![8645, 8646, 8647, 8648, 8649]);
+
+        let ports = RethPorts {
+            discovery: 9000,
+            ..ports
+        };
+        assert_eq!(ports.ports(), vec![8645, 8646, 8647, 8648, 8649, 9000]);
+
+        assert_eq!(ports.shifted(0), Some(ports));
+        assert_eq!(ports.shifted(LAYOUT_STRIDE).unwrap().http, 8645 + 600);
+        // Beyond the last port
+        assert_eq!(ports.shifted(usize::from(u16::MAX)), None);
+    }
+
+    #[test]
+    fn test_allocate_skips_taken_ports() {
+        let default = RethPorts::for_node(3);
+        let taken: HashSet<u16> = [default.authrpc].into();
+
+        let ports = RethPorts::allocate(3, &taken).unwrap();
+        assert!(is_layout_of(&ports, 3));
+        assert_ne!(ports, default);
+        assert!(ports.ports().iter().all(|port| !taken.contains(port)));
+    }
+
+    #[test]
+    fn test_allocate_skips_ports_in_use() {
+        let ports = RethPorts::allocate(5, &HashSet::new()).unwrap();
+        let _listener = TcpListener::bind(("0.0.0.0", ports.metrics)).unwrap();
+
+        let allocated = RethPorts::allocate(5, &HashSet::new()).unwrap();
+        assert!(is_layout_of(&allocated, 5));
+        assert!(!allocated.ports().contains(&ports.metrics));
+    }
+
+    #[test]
+    fn test_allocate_fails_without_free_layout() {
+        let default = RethPorts::for_node(0);
+        let taken = (0..MAX_LAYOUTS)
+            .filter_map(|layout| default.shifted(layout * LAYOUT_STRIDE))
+            .map(|ports| ports.http)
+            .collect();
+
+        assert!(RethPorts::allocate(0, &taken).is_err());
+    }
+
+    #[test]
+    fn test_allocate_ports_of_a_testnet() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let allocated = allocate_ports(dir.path(), 4, &PortsOverride::default(), false).unwrap();
+        assert_eq!(allocated.len(), 4);
+
+        // No port is shared between the nodes, and each node gets its saved ports back
+        let ports: Vec<u16> = allocated.iter().flat_map(RethPorts::ports).collect();
+        let distinct: HashSet<u16> = ports.iter().copied().collect();
+        assert_eq!(distinct.len(), ports.len());
+        assert_eq!(used_ports(dir.path(), 4), distinct);
+        for (node_id, ports) in allocated.iter().enumerate() {
+            assert!(is_layout_of(ports, node_id));
+            assert_eq!(RethPorts::load(dir.path(), node_id), *ports);
+        }
+    }
+
+    #[test]
+    fn test_allocate_ports_around_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        // Node 0 takes the default ports of node 1
+        let overrides = overrides(
+            r#"
+            [[nodes]]
+            id = 0
+            http = 8675
+            ws = 8676
+            authrpc = 8677
+            metrics = 8678
+            discovery = 8679
+            p2p = 8679
+            "#,
+        );
+
+        let allocated = allocate_ports(dir.path(), 2, &overrides, false).unwrap();
+        assert_eq!(allocated[0], RethPorts::for_node(1));
+        assert_ne!(allocated[1], RethPorts::for_node(1));
+        assert!(is_layout_of(&allocated[1], 1));
+    }
+
+    #[test]
+    fn test_colliding_overrides_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let overrides = overrides(
+            r#"
+            [[nodes]]
+            id = 0
+            http = 18645
+            ws = 18646
+            authrpc = 18647
+            metrics = 18648
+            discovery = 18649
+            p2p = 18649
+
+            [[nodes]]
+            id = 1
+            http = 18655
+            ws = 18656
+            authrpc = 18647
+            metrics = 18658
+            discovery = 18659
+            p2p = 18659
+            "#,
+        );
+
+        let error = allocate_ports(dir.path(), 2, &overrides, false).unwrap_err();
+        assert!(error.to_string().contains("Port 18647 of the Reth node 1"));
+    }
+
+    #[test]
+    fn test_saved_ports_are_kept_on_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = RethPorts::for_node(0).shifted(7 * LAYOUT_STRIDE).unwrap();
+        saved.save(dir.path(), 0).unwrap();
+
+        let resumed = allocate_ports(dir.path(), 1, &PortsOverride::default(), true).unwrap();
+        assert_eq!(resumed, vec![saved]);
+
+        let reallocated = allocate_ports(dir.path(), 1, &PortsOverride::default(), false).unwrap();
+        assert_ne!(reallocated, vec![saved]);
+        assert_eq!(RethPorts::load(dir.path(), 0), reallocated[0]);
+    }
+
+    #[test]
+    fn test_default_ports_without_saved_ports() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(RethPorts::load(dir.path(), 2), RethPorts::for_node(2));
+    }
+}
@@ -1,5 +1,6 @@
 //! Reth process management
 
+use core::time::Duration;
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
@@ -189,4 +190,13 @@ impl RethProcess {
         }
         .is_running()
     }
+
+    /// Stop the process gracefully
+    pub fn stop(&self, timeout: Duration) -> Result<()> {
+        ProcessHandle {
+            pid: self.pid,
+            name: "reth".to_string(),
+        }
+        .stop(timeout)
+    }
 }
@@ -1,5 +1,6 @@
 //! Testnet start command - Initialize and run a complete testnet with Reth + Emerald nodes
 
+use core::net::SocketAddr;
 use core::str::FromStr;
 use core::time::Duration;
 use std::fs;
@@ -17,13 +18,20 @@ use malachitebft_eth_types::Address;
 use serde_json::{json, Value};
 use tracing::info;
 
+use super::metrics;
+use super::ports::{self, PortsOverride};
 use super::reth::{self, RethProcess};
-use super::types::RethNode;
+use super::types::{ProcessHandle, RethNode, RethPorts};
 use crate::cmd::testnet::rpc::RpcClient;
+use crate::config::Config;
+use crate::file::save_consensus_config;
 use crate::utils::retry::retry_with_timeout;
 
 type PrivateKey<C> = <<C as Context>::SigningScheme as SigningScheme>::PrivateKey;
 
+const RESUME_HINT: &str =
+    "Failed to start the testnet, run `emerald testnet start --resume` to continue from the failed step";
+
 #[derive(Parser, Debug, Clone, PartialEq)]
 pub struct TestnetStartCmd {
     /// Number of node pairs to create (max 20)
@@ -60,6 +68,41 @@ pub struct TestnetStartCmd {
     /// Address which will receive fees. If not specified will default to `0x4242424242424242424242424242424242424242`
     #[clap(long)]
     pub fee_receiver: Option<String>,
+
+    /// Voting powers of the validators, one per node in order, e.g. `100,50,10`.
+    /// If not specified all validators get the same power
+    #[clap(long, value_delimiter = ',')]
+    pub powers: Vec<u64>,
+
+    /// Run all Emerald nodes inside this process instead of spawning one process per node.
+    /// The Reth nodes are still separate processes. The testnet runs in the foreground
+    /// and is stopped with Ctrl-C
+    #[clap(long)]
+    pub in_process: bool,
+
+    /// Write the Malachite configuration of each node to the `[consensus]` table of its
+    /// `emerald.toml` instead of a separate `config.toml`
+    #[clap(long)]
+    pub single_config_file: bool,
+
+    /// Continue a start which failed midway, e.g. because a port was in use. The steps
+    /// already completed are skipped: the configurations, keys and genesis files present
+    /// in the home directory are kept, and the Reth and Emerald nodes already running are
+    /// not started again
+    #[clap(long)]
+    pub resume: bool,
+
+    /// TOML file setting the Reth ports of some nodes, as `[[nodes]]` tables with the `id`
+    /// of the node and its `http`, `ws`, `authrpc`, `metrics`, `discovery` and `p2p` ports.
+    /// The ports of the other nodes are the first free ones from their default layout
+    #[clap(long)]
+    pub ports_file: Option<PathBuf>,
+
+    /// Address the metrics of all the nodes are served on, at `/metrics`, each sample
+    /// labelled with the `node` it comes from. A Grafana dashboard of these metrics is
+    /// written to `grafana-dashboard.json` in the home directory
+    #[clap(long, default_value = metrics::DEFAULT_LISTEN_ADDR)]
+    pub metrics_addr: SocketAddr,
 }
 
 impl TestnetStartCmd {
@@ -69,6 +112,67 @@ impl TestnetStartCmd {
         N: Node + CanGeneratePrivateKey + CanMakeGenesis + CanMakePrivateKeyFile,
         PrivateKey<N::Context>: serde::de::DeserializeOwned,
     {
+        let reth_processes = self.prepare(node, home_dir, logging)?;
+
+        // 8. Spawn Emerald processes
+        println!("\n💎 Starting Emerald consensus nodes...");
+        let emerald_processes = self.spawn_emerald_nodes(home_dir).wrap_err(RESUME_HINT)?;
+        println!("✓ All Emerald nodes started");
+
+        // 9. Spawn the metrics aggregator
+        println!("\n📈 Starting the metrics aggregator...");
+        self.spawn_metrics_aggregator(home_dir)
+            .wrap_err(RESUME_HINT)?;
+
+        println!("\n✅ Testnet started successfully!");
+        println!("\n📊 Status:");
+        println!("  Reth processes: {} running", reth_processes.len());
+        println!("  Emerald processes: {} running", emerald_processes.len());
+        println!("\n📁 Logs:");
+        println!(
+            "  Reth: {}/{{0..{}}}/logs/reth.log",
+            home_dir.display(),
+            self.nodes - 1
+        );
+        println!(
+            "  Emerald: {}/{{0..{}}}/logs/emerald.log",
+            home_dir.display(),
+            self.nodes - 1
+        );
+        println!("\n📈 Metrics:");
+        println!("  All nodes: http://{}/metrics", self.metrics_addr);
+        println!(
+            "  Grafana dashboard: {}",
+            home_dir.join(metrics::DASHBOARD_FILE).display()
+        );
+
+        println!("\n💡 Commands:");
+        println!("    emerald testnet status           - Check status of all nodes");
+        println!("    emerald testnet stop-node <id>   - Stop a specific node");
+        println!("    emerald testnet stop             - Stop all nodes");
+        println!("    emerald testnet destroy          - Remove all testnet data");
+
+        Ok(())
+    }
+
+    /// Generates the testnet configuration and starts the Reth nodes, leaving it to the
+    /// caller to start the Emerald nodes, whose home directories are `<home_dir>/<node_id>`
+    pub fn prepare<N>(
+        &self,
+        node: &N,
+        home_dir: &Path,
+        logging: LoggingConfig,
+    ) -> Result<Vec<RethProcess>>
+    where
+        N: Node + CanGeneratePrivateKey + CanMakeGenesis + CanMakePrivateKeyFile,
+        PrivateKey<N::Context>: serde::de::DeserializeOwned,
+    {
+        self.validate()?;
+        self.prepare_steps(node, home_dir, logging)
+            .wrap_err(RESUME_HINT)
+    }
+
+    fn validate(&self) -> Result<()> {
         // Validate node count
         if self.nodes == 0 || self.nodes > 20 {
             return Err(eyre!(
@@ -77,7 +181,32 @@ impl TestnetStartCmd {
             ));
         }
 
-        println!("🚀 Initializing testnet with {} nodes...\n", self.nodes);
+        if !self.powers.is_empty() && self.powers.len() != self.nodes {
+            return Err(eyre!(
+                "Expected one voting power per node, got {} for {} nodes",
+                self.powers.len(),
+                self.nodes
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn prepare_steps<N>(
+        &self,
+        node: &N,
+        home_dir: &Path,
+        logging: LoggingConfig,
+    ) -> Result<Vec<RethProcess>>
+    where
+        N: Node + CanGeneratePrivateKey + CanMakeGenesis + CanMakePrivateKeyFile,
+        PrivateKey<N::Context>: serde::de::DeserializeOwned,
+    {
+        if self.resume {
+            println!("♻️  Resuming the testnet with {} nodes...\n", self.nodes);
+        } else {
+            println!("🚀 Initializing testnet with {} nodes...\n", self.nodes);
+        }
 
         // 1. Check if custom-reth is available
         print!("Checking custom-reth installation... ");
@@ -94,14 +223,22 @@ impl TestnetStartCmd {
         }
 
         // 2. Generate testnet configuration
-        println!("\n📝 Generating testnet configuration...");
-        self.generate_testnet_config(node, home_dir, logging)?;
-        println!("✓ Configuration generated");
+        if self.completed(|| self.node_files_exist(home_dir, "priv_validator_key.json")) {
+            println!("\n📝 Testnet configuration already generated");
+        } else {
+            println!("\n📝 Generating testnet configuration...");
+            self.generate_testnet_config(node, home_dir, logging)?;
+            println!("✓ Configuration generated");
+        }
 
         // 2b. Set up assets directory
-        println!("\n📦 Setting up assets directory...");
-        self.setup_assets_directory(home_dir)?;
-        println!("✓ Assets directory set up");
+        if self.completed(|| home_dir.join("assets").join("jwtsecret").exists()) {
+            println!("\n📦 Assets directory already set up");
+        } else {
+            println!("\n📦 Setting up assets directory...");
+            self.setup_assets_directory(home_dir)?;
+            println!("✓ Assets directory set up");
+        }
 
         let fee_receiver = if let Some(fee_receiver_str) = &self.fee_receiver {
             Address::from(AlloyAddress::from_str(fee_receiver_str)?)
@@ -109,21 +246,46 @@ impl TestnetStartCmd {
             Address::repeat_byte(42)
         };
 
-        // 2c. Generate Emerald configs
-        println!("\n⚙️  Generating Emerald configs...");
-        info!("Will use address `{fee_receiver}` as Fee Receiver address");
-        self.generate_emerald_configs(home_dir, fee_receiver)?;
-        println!("✓ Emerald configs generated");
+        // 2c. Allocate the Reth ports, kept when resuming since the Reth nodes may be running
+        println!("\n🔌 Allocating Reth ports...");
+        self.allocate_ports(home_dir)?;
+        println!("✓ Reth ports allocated");
+
+        // 2d. Generate Emerald configs
+        if self.completed(|| self.node_files_exist(home_dir, "emerald.toml")) {
+            println!("\n⚙️  Emerald configs already generated");
+        } else {
+            println!("\n⚙️  Generating Emerald configs...");
+            info!("Will use address `{fee_receiver}` as Fee Receiver address");
+            self.generate_emerald_configs(home_dir, fee_receiver, "500ms")?;
+            println!("✓ Emerald configs generated");
+        }
+        // Only moves the configs which are not merged yet
+        if self.single_config_file {
+            self.merge_configs(home_dir)?;
+        }
 
         // 3. Extract validator public keys
-        println!("\n🔑 Extracting validator public keys...");
-        self.extract_public_keys(home_dir)?;
-        println!("✓ Public keys extracted");
+        if self.completed(|| home_dir.join("validator_public_keys.txt").exists()) {
+            println!("\n🔑 Validator public keys already extracted");
+        } else {
+            println!("\n🔑 Extracting validator public keys...");
+            self.extract_public_keys(home_dir)?;
+            println!("✓ Public keys extracted");
+        }
 
         // 4. Generate genesis file
-        println!("\n⚙️  Generating genesis file...");
-        self.generate_genesis(home_dir)?;
-        println!("✓ Genesis file created");
+        let assets_dir = home_dir.join("assets");
+        if self.completed(|| {
+            assets_dir.join("genesis.json").exists()
+                && assets_dir.join("emerald_genesis.json").exists()
+        }) {
+            println!("\n⚙️  Genesis file already created");
+        } else {
+            println!("\n⚙️  Generating genesis file...");
+            self.generate_genesis(home_dir)?;
+            println!("✓ Genesis file created");
+        }
 
         // 5. Spawn Reth processes
         println!("\n🔗 Starting Reth execution clients...");
@@ -140,37 +302,57 @@ impl TestnetStartCmd {
         self.connect_reth_peers(home_dir)?;
         println!("✓ Reth peers connected");
 
-        // 8. Spawn Emerald processes
-        println!("\n💎 Starting Emerald consensus nodes...");
-        let emerald_processes = self.spawn_emerald_nodes(home_dir)?;
-        println!("✓ All Emerald nodes started");
+        // 7b. Write the Grafana dashboard of the metrics of the nodes
+        metrics::write_dashboard(home_dir)?;
 
-        println!("\n✅ Testnet started successfully!");
-        println!("\n📊 Status:");
-        println!("  Reth processes: {} running", reth_processes.len());
-        println!("  Emerald processes: {} running", emerald_processes.len());
-        println!("\n📁 Logs:");
-        println!(
-            "  Reth: {}/{{0..{}}}/logs/reth.log",
-            home_dir.display(),
-            self.nodes - 1
-        );
-        println!(
-            "  Emerald: {}/{{0..{}}}/logs/emerald.log",
-            home_dir.display(),
-            self.nodes - 1
-        );
+        Ok(reth_processes)
+    }
 
-        println!("\n💡 Commands:");
-        println!("    emerald testnet status           - Check status of all nodes");
-        println!("    emerald testnet stop-node <id>   - Stop a specific node");
-        println!("    emerald testnet stop             - Stop all nodes");
-        println!("    emerald testnet destroy          - Remove all testnet data");
+    /// Allocates the Reth ports of the nodes and saves them in their home directory
+    pub(crate) fn allocate_ports(&self, home_dir: &Path) -> Result<()> {
+        let overrides = match &self.ports_file {
+            Some(path) => PortsOverride::load(path)?,
+            None => PortsOverride::default(),
+        };
+
+        let allocated = ports::allocate_ports(home_dir, self.nodes, &overrides, self.resume)?;
+        for (i, ports) in allocated.iter().enumerate() {
+            if *ports != RethPorts::for_node(i) {
+                println!("  Reth node {i} uses HTTP port {}", ports.http);
+            }
+        }
 
         Ok(())
     }
 
-    fn generate_testnet_config<N>(
+    /// Whether a step is skipped, because the start is resumed and the step completed
+    fn completed(&self, done: impl FnOnce() -> bool) -> bool {
+        self.resume && done()
+    }
+
+    /// Whether the file `name` is in the config directory of every node
+    fn node_files_exist(&self, home_dir: &Path, name: &str) -> bool {
+        (0..self.nodes).all(|i| {
+            home_dir
+                .join(i.to_string())
+                .join("config")
+                .join(name)
+                .exists()
+        })
+    }
+
+    /// PID of the `name` process of a node, if it is running
+    fn running_process(&self, home_dir: &Path, node_id: usize, name: &str) -> Option<u32> {
+        let pid_file = home_dir
+            .join(node_id.to_string())
+            .join(format!("{name}.pid"));
+        ProcessHandle::from_pid_file(&pid_file)
+            .ok()
+            .filter(ProcessHandle::is_running)
+            .map(|handle| handle.pid)
+    }
+
+    pub(crate) fn generate_testnet_config<N>(
         &self,
         node: &N,
         home_dir: &Path,
@@ -228,7 +410,7 @@ impl TestnetStartCmd {
         .map_err(|e| eyre!("Failed to generate testnet configuration: {:?}", e))
     }
 
-    fn setup_assets_directory(&self, home_dir: &Path) -> Result<()> {
+    pub(crate) fn setup_assets_directory(&self, home_dir: &Path) -> Result<()> {
         let assets_dir = home_dir.join("assets");
         fs::create_dir_all(&assets_dir)?;
 
@@ -251,15 +433,18 @@ impl TestnetStartCmd {
         Ok(())
     }
 
-    fn generate_emerald_configs(&self, home_dir: &Path, fee_receiver: Address) -> Result<()> {
-        use super::types::RethPorts;
-
+    pub(crate) fn generate_emerald_configs(
+        &self,
+        home_dir: &Path,
+        fee_receiver: Address,
+        min_block_time: &str,
+    ) -> Result<()> {
         for i in 0..self.nodes {
             let config_dir = home_dir.join(i.to_string()).join("config");
             fs::create_dir_all(&config_dir)?;
 
             let config_path = config_dir.join("emerald.toml");
-            let ports = RethPorts::for_node(i);
+            let ports = RethPorts::load(home_dir, i);
 
             // JWT secret is in the assets directory
             let jwt_path = home_dir.join("assets").join("jwtsecret");
@@ -278,7 +463,7 @@ retry_config.initial_delay = "100ms"
 retry_config.max_delay = "2s"
 retry_config.max_elapsed_time = "20s"
 el_node_type = "archive"
-min_block_time = "500ms"
+min_block_time = "{}"
 fee_recipient = "{}"
 "#,
                 i,
@@ -286,6 +471,7 @@ fee_recipient = "{}"
                 ports.authrpc, // engine auth RPC port
                 jwt_path.display(),
                 eth_genesis_path.display(),
+                min_block_time,
                 fee_receiver,
             );
 
@@ -296,7 +482,28 @@ fee_recipient = "{}"
         Ok(())
     }
 
-    fn extract_public_keys(&self, home_dir: &Path) -> Result<()> {
+    /// Moves the Malachite configuration of each node to the `[consensus]` table of its
+    /// Emerald config
+    fn merge_configs(&self, home_dir: &Path) -> Result<()> {
+        for i in 0..self.nodes {
+            let config_dir = home_dir.join(i.to_string()).join("config");
+            let config_path = config_dir.join("config.toml");
+            if !config_path.exists() {
+                // Already merged
+                continue;
+            }
+
+            let config = toml::from_str::<Config>(&fs::read_to_string(&config_path)?)
+                .context(format!("Failed to parse the config of node {i}"))?;
+            save_consensus_config(&config_dir.join("emerald.toml"), &config)
+                .map_err(|e| eyre!("Failed to merge the configs of node {i}: {e}"))?;
+            fs::remove_file(&config_path)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn extract_public_keys(&self, home_dir: &Path) -> Result<()> {
         let mut public_keys = Vec::new();
 
         for i in 0..self.nodes {
@@ -341,7 +548,7 @@ fee_recipient = "{}"
         Ok(())
     }
 
-    fn generate_genesis(&self, home_dir: &Path) -> Result<()> {
+    pub(crate) fn generate_genesis(&self, home_dir: &Path) -> Result<()> {
         let pubkeys_file = home_dir.join("validator_public_keys.txt");
 
         // Create assets directory inside home_dir
@@ -365,7 +572,8 @@ fee_recipient = "{}"
             emerald_utils_bin.display()
         );
 
-        let output = Command::new(emerald_utils_bin.clone())
+        let mut command = Command::new(emerald_utils_bin.clone());
+        command
             .args(["genesis", "--public-keys-file"])
             .arg(&pubkeys_file)
             .args([
@@ -375,22 +583,27 @@ fee_recipient = "{}"
             ])
             .arg(&genesis_output)
             .args(["--emerald-genesis-output"])
-            .arg(&emerald_genesis_output)
-            .output()
-            .with_context(|| {
-                format!(
-                    "Failed to execute emerald-utils. Tried:\n  \
+            .arg(&emerald_genesis_output);
+
+        if !self.powers.is_empty() {
+            let powers: Vec<_> = self.powers.iter().map(u64::to_string).collect();
+            command.args(["--powers", &powers.join(",")]);
+        }
+
+        let output = command.output().with_context(|| {
+            format!(
+                "Failed to execute emerald-utils. Tried:\n  \
                      1. ./target/debug/emerald-utils ({})\n  \
                      2. emerald-utils in PATH\n\n\
                      Please ensure emerald-utils is built or available in PATH.\n\
                      Run: cargo build --bin emerald-utils",
-                    if emerald_utils_bin.exists() {
-                        "found"
-                    } else {
-                        "not found"
-                    }
-                )
-            })?;
+                if emerald_utils_bin.exists() {
+                    "found"
+                } else {
+                    "not found"
+                }
+            )
+        })?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -431,7 +644,7 @@ fee_recipient = "{}"
         Ok(())
     }
 
-    fn spawn_reth_nodes(&self, home_dir: &Path) -> Result<Vec<RethProcess>> {
+    pub(crate) fn spawn_reth_nodes(&self, home_dir: &Path) -> Result<Vec<RethProcess>> {
         let assets_dir = home_dir.join("assets");
         let mut processes = Vec::new();
 
@@ -442,6 +655,18 @@ fee_recipient = "{}"
                 assets_dir.clone(),
                 &self.reth_config_path,
             );
+            if let Some(pid) = self
+                .resume
+                .then(|| self.running_process(home_dir, i, "reth"))
+                .flatten()
+            {
+                println!("  Reth node {i} already running (PID: {pid})");
+                processes.push(RethProcess {
+                    pid,
+                    log_file: home_dir.join(i.to_string()).join("logs").join("reth.log"),
+                });
+                continue;
+            }
             print!("  Starting Reth node {i}... ");
             let process = reth_node.spawn(&self.custom_reth_bin)?;
             println!("✓ (PID: {})", process.pid);
@@ -454,7 +679,7 @@ fee_recipient = "{}"
         Ok(processes)
     }
 
-    fn wait_for_reth_nodes(&self, home_dir: &Path) -> Result<()> {
+    pub(crate) fn wait_for_reth_nodes(&self, home_dir: &Path) -> Result<()> {
         let assets_dir = home_dir.join("assets");
 
         for i in 0..self.nodes {
@@ -523,6 +748,21 @@ fee_recipient = "{}"
         let mut processes = Vec::new();
 
         for i in 0..self.nodes {
+            if let Some(pid) = self
+                .resume
+                .then(|| self.running_process(home_dir, i, "emerald"))
+                .flatten()
+            {
+                println!("  Emerald node {i} already running (PID: {pid})");
+                processes.push(EmeraldProcess {
+                    pid,
+                    log_file: home_dir
+                        .join(i.to_string())
+                        .join("logs")
+                        .join("emerald.log"),
+                });
+                continue;
+            }
             print!("  Starting Emerald node {i}... ");
             let process = self.spawn_emerald_node(i, home_dir)?;
             println!("✓ (PID: {})", process.pid);
@@ -546,15 +786,7 @@ fee_recipient = "{}"
         let log_file_path = log_dir.join("emerald.log");
         let pid_file = node_home.join("emerald.pid");
 
-        // Check for built binary first, then fallback to PATH
-        let emerald_bin = {
-            let p = PathBuf::from(self.emerald_bin.clone());
-            if p.exists() {
-                p
-            } else {
-                PathBuf::from("emerald")
-            }
-        };
+        let emerald_bin = self.emerald_bin();
         info!(
             "Using `{}` for Emerald binary to spawn node",
             emerald_bin.display()
@@ -594,6 +826,60 @@ fee_recipient = "{}"
             log_file: log_file_path,
         })
     }
+
+    /// The `emerald` executable: the built binary if present, or the one in $PATH
+    fn emerald_bin(&self) -> PathBuf {
+        let p = PathBuf::from(self.emerald_bin.clone());
+        if p.exists() {
+            p
+        } else {
+            PathBuf::from("emerald")
+        }
+    }
+
+    /// Spawns `emerald testnet metrics` in the background, unless it is already running
+    fn spawn_metrics_aggregator(&self, home_dir: &Path) -> Result<()> {
+        if let Some(pid) = metrics::running(home_dir) {
+            println!("  Metrics aggregator already running (PID: {pid})");
+            return Ok(());
+        }
+
+        let log_file_path = home_dir.join(metrics::LOG_FILE);
+        let pid_file = home_dir.join(metrics::PID_FILE);
+
+        let cmd = format!(
+            "{} testnet --home {} metrics --listen-addr {}",
+            self.emerald_bin().display(),
+            home_dir.display(),
+            self.metrics_addr
+        );
+
+        let shell_cmd = format!(
+            "nohup {} > {} 2>&1 & echo $! > {}",
+            cmd,
+            log_file_path.display(),
+            pid_file.display()
+        );
+
+        Command::new("sh")
+            .arg("-c")
+            .arg(&shell_cmd)
+            .spawn()
+            .context("Failed to spawn the metrics aggregator")?;
+
+        // Wait a moment for PID file to be written
+        std::thread::sleep(core::time::Duration::from_millis(100));
+
+        let pid = ProcessHandle::from_pid_file(&pid_file)
+            .context("Failed to read the PID file of the metrics aggregator")?
+            .pid;
+        println!(
+            "✓ Serving the metrics of all nodes on {} (PID: {pid})",
+            self.metrics_addr
+        );
+
+        Ok(())
+    }
 }
 
 #[allow(dead_code)]
@@ -1,5 +1,6 @@
 //! Testnet status command - Show status of all nodes
 
+use std::fs;
 use std::path::Path;
 
 use clap::Parser;
@@ -7,6 +8,8 @@ use color_eyre::Result;
 
 use super::rpc::RpcClient;
 use super::types::{ProcessHandle, RethPorts};
+use crate::cmd::status::{fetch_node_status, NodeStatus};
+use crate::config::EmeraldConfig;
 
 #[derive(Parser, Debug, Clone, PartialEq)]
 pub struct TestnetStatusCmd {
@@ -53,6 +56,18 @@ impl TestnetStatusCmd {
             };
             println!("  Emerald: {emerald_status}");
 
+            if let Some(status) = consensus_status(&node_dir) {
+                let sync = if status.catching_up {
+                    "catching up"
+                } else {
+                    "caught up"
+                };
+                println!(
+                    "  Consensus: height {}, round {}, {sync}",
+                    status.height, status.round
+                );
+            }
+
             // Check Reth status
             let reth_pid_file = node_dir.join("reth.pid");
             let reth_status = if reth_pid_file.exists() {
@@ -69,7 +84,7 @@ impl TestnetStatusCmd {
             println!("  Reth:    {reth_status}");
 
             // Get block height if Reth is running
-            let ports = RethPorts::for_node(i);
+            let ports = RethPorts::load(home_dir, i);
             let rpc = RpcClient::new(ports.http);
 
             if let Ok(height) = rpc.get_block_number() {
@@ -92,3 +107,18 @@ impl TestnetStatusCmd {
         Ok(())
     }
 }
+
+/// Status of the consensus of a node, when its admin API is enabled without authentication
+fn consensus_status(node_dir: &Path) -> Option<NodeStatus> {
+    let content = fs::read_to_string(node_dir.join("config").join("emerald.toml")).ok()?;
+    let config: EmeraldConfig = toml::from_str(&content).ok()?;
+    if config.admin_tls.is_some() || config.admin_auth_token.is_some() {
+        return None;
+    }
+
+    let admin_listen_addr = config.admin_listen_addr?;
+    let runtime = tokio::runtime::Runtime::new().ok()?;
+    runtime
+        .block_on(fetch_node_status(&admin_listen_addr.to_string(), None))
+        .ok()
+}
@@ -7,6 +7,8 @@ use std::process::Command;
 use clap::Parser;
 use color_eyre::Result;
 
+use super::metrics;
+
 #[derive(Parser, Debug, Clone, PartialEq)]
 pub struct TestnetStopCmd {}
 
@@ -86,6 +88,13 @@ impl TestnetStopCmd {
             }
         }
 
+        // Stop the metrics aggregator
+        if let Some(pid) = metrics::stop(home_dir) {
+            total_processes += 1;
+            stopped_count += 1;
+            println!("Stopped the metrics aggregator (PID: {pid})");
+        }
+
         println!();
         if total_processes == 0 {
             println!("⚠️  No running processes found");
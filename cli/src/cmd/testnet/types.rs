@@ -121,7 +121,7 @@ impl TestnetMetadata {
 }
 
 /// Reth port configuration for a node
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct RethPorts {
     pub http: u16,
     pub ws: u16,
@@ -132,7 +132,8 @@ pub struct RethPorts {
 }
 
 impl RethPorts {
-    /// Calculate ports for a given node index
+    /// Default ports for a given node index, see [`RethPorts::allocate`] for the ports
+    /// actually used by a node
     /// Each node gets 10 consecutive ports starting from base
     pub fn for_node(node_id: usize) -> Self {
         let base = 8645 + (node_id * 30);
@@ -170,7 +171,7 @@ impl RethNode {
         let data_dir = home_dir.join(node_id.to_string()).join("reth-data");
         let genesis_file = assets_dir.join("genesis.json");
         let jwt_secret = assets_dir.join("jwtsecret");
-        let ports = RethPorts::for_node(node_id);
+        let ports = RethPorts::load(&home_dir, node_id);
 
         let config = match config_path {
             None => {
@@ -0,0 +1,17 @@
+use clap::Args;
+
+/// Roll the node back to a given height
+///
+/// Removes the decided values, certificates and block data above the target height
+/// from the store, clears all undecided and pending proposals, and moves the head of
+/// the execution client back to the block at that height. The node must be stopped.
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct UnsafeResetCmd {
+    /// Height to roll back to, the last height kept in the store
+    #[clap(long, value_name = "HEIGHT")]
+    pub to_height: u64,
+
+    /// Only truncate the store, without updating the forkchoice of the execution client
+    #[clap(long)]
+    pub skip_el: bool,
+}
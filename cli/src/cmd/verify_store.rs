@@ -0,0 +1,77 @@
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+/// Check the integrity of the consensus store
+///
+/// Walks the decided heights, checking that the value, the certificate and the block
+/// header of each height are consistent with each other and with their checksums, and
+/// that each header links to the header of the previous height. Reports the first
+/// broken height, from which the node can be rolled back with `unsafe-reset`.
+/// The node must be stopped.
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct VerifyStoreCmd {
+    /// First height to check, the earliest height in the store by default
+    #[clap(long, value_name = "HEIGHT")]
+    pub from_height: Option<u64>,
+
+    /// Last height to check, the latest height in the store by default
+    #[clap(long, value_name = "HEIGHT")]
+    pub to_height: Option<u64>,
+
+    /// Print the outcome as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Outcome of a check of the integrity of the store
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreVerification {
+    /// Range of heights checked, none if the store has no decided value
+    pub from_height: Option<u64>,
+    pub to_height: Option<u64>,
+    /// Number of heights checked before the first broken one, if any
+    pub checked: u64,
+    /// First broken height
+    pub broken: Option<BrokenHeight>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrokenHeight {
+    pub height: u64,
+    pub reason: String,
+}
+
+impl StoreVerification {
+    /// Command rolling the node back to the last height before the broken one
+    pub fn suggestion(&self) -> Option<String> {
+        let broken = self.broken.as_ref()?;
+        Some(match broken.height.checked_sub(1).filter(|h| *h > 0) {
+            Some(height) => format!(
+                "Roll the node back with `emerald unsafe-reset --to-height {height}`, and let it sync the heights above again"
+            ),
+            None => "Sync the node again from an empty store".to_string(),
+        })
+    }
+
+    pub fn print(&self) {
+        let (Some(from), Some(to)) = (self.from_height, self.to_height) else {
+            println!("The store has no decided value");
+            return;
+        };
+
+        match &self.broken {
+            None => println!(
+                "Checked heights {from} to {to}: the store is consistent ({} heights)",
+                self.checked
+            ),
+            Some(broken) => {
+                println!("Checked heights {from} to {to}:");
+                println!("  First broken height: {}", broken.height);
+                println!("  Reason:              {}", broken.reason);
+                if let Some(suggestion) = self.suggestion() {
+                    println!("  {suggestion}");
+                }
+            }
+        }
+    }
+}
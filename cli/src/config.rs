@@ -1,13 +1,17 @@
-use std::path::Path;
+use core::net::{IpAddr, SocketAddr};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-use color_eyre::eyre;
+use alloy_primitives::B256;
+use color_eyre::eyre::{self, eyre, Context};
 use malachitebft_app::node::NodeConfig;
 pub use malachitebft_config::{
     BootstrapProtocol, ConsensusConfig, DiscoveryConfig, LoggingConfig, MempoolConfig,
     MempoolLoadConfig, MetricsConfig, P2pConfig, PubSubProtocol, RuntimeConfig, ScoringStrategy,
     Selector, TestConfig, TimeoutConfig, TransportProtocol, ValuePayload, ValueSyncConfig,
 };
-use malachitebft_eth_types::{Address, RetryConfig};
+use malachitebft_eth_engine::client_version::ClientVersionPolicy;
+use malachitebft_eth_types::{Address, EngineTimeouts, RetryConfig};
 use serde::{Deserialize, Serialize};
 use tokio::time::Duration;
 
@@ -36,10 +40,40 @@ pub struct EmeraldConfig {
     #[serde(default)]
     pub retry_config: RetryConfig,
 
+    /// Timeouts of the Engine API calls, each method defaults to its own timeout
+    #[serde(default)]
+    pub engine_timeouts: EngineTimeouts,
+
     /// Type of execution layer node (archive, full, or custom)
     #[serde(default)]
     pub el_node_type: ElNodeType,
 
+    /// Number of most recent blocks whose bodies are retained by the execution client
+    /// when it is not an archive node. The values pruned from the store are rebuilt from
+    /// these bodies to serve syncing peers, so older heights are not served.
+    /// Ignored for archive nodes.
+    /// Default: 10064, the distance kept by a Reth full node
+    #[serde(default = "default_el_retained_blocks")]
+    pub el_retained_blocks: u64,
+
+    /// Execution client versions this node accepts to run with,
+    /// as reported by `engine_getClientVersionV1` at startup
+    #[serde(default)]
+    pub el_version_policy: ClientVersionPolicy,
+
+    /// Interval at which the sync status and the head of the execution client are polled,
+    /// to tell whether it is ready, syncing or unreachable.
+    /// Default: 2s
+    #[serde(with = "humantime_serde", default = "default_el_health_interval")]
+    pub el_health_interval: Duration,
+
+    /// Time to wait for the execution client to reconnect when it restarts while the
+    /// decided blocks are replayed to it, after which the replay fails. 0 fails the replay
+    /// as soon as the connection is lost.
+    /// Default: 5m
+    #[serde(with = "humantime_serde", default = "default_el_reconnect_timeout")]
+    pub el_reconnect_timeout: Duration,
+
     /// Number of certificates to retain.
     /// Default is retain all (u64::MAX).
     /// Once the certificates are deleted those blocks
@@ -64,6 +98,60 @@ pub struct EmeraldConfig {
     #[serde(with = "humantime_serde", default = "default_min_block_time")]
     pub min_block_time: Duration,
 
+    /// Time window within which the payload built for a proposal is reused when the node
+    /// proposes again on the same parent block, e.g. in a later round of the same height,
+    /// instead of asking the execution client to build a new one.
+    /// Set to 0 to always build a new payload.
+    /// Default: 5s
+    #[serde(with = "humantime_serde", default = "default_payload_reuse_window")]
+    pub payload_reuse_window: Duration,
+
+    /// Enables lazy block production: the proposer waits for transactions to be
+    /// available in the txpool of the execution client before proposing, up to this
+    /// interval since the previous block, after which it proposes an empty block.
+    /// `timeout_propose` is extended by this interval, so that validators accept
+    /// the delayed proposals. All validators must use the same value.
+    /// Disabled when unset.
+    #[serde(with = "humantime_serde", default)]
+    pub max_idle_block_interval: Option<Duration>,
+
+    /// Number of attempts at building the payload to propose before giving up on the
+    /// round, letting it time out so that the next proposer takes over, e.g. while the
+    /// execution client restarts.
+    /// Default: 3
+    #[serde(default = "default_proposer_build_attempts")]
+    pub proposer_build_attempts: u32,
+
+    /// Enables optimistic payload building: right after deciding a block, a node which
+    /// proposes at the next height asks the execution client to start building on top of
+    /// it, and fetches that payload when consensus asks for a value, instead of only then
+    /// starting to build. The execution client keeps adding transactions to the payload
+    /// during `min_block_time`. Not used with an external builder.
+    /// Default: false
+    #[serde(default)]
+    pub optimistic_payload_building: bool,
+
+    /// Maximum rate, in bytes per second, at which this node uploads the parts of its
+    /// proposals, after a first burst. Pacing the chunks of large payloads avoids
+    /// saturating the uplink of the proposer, so that the first chunks propagate
+    /// through the gossip mesh while the next ones are uploaded.
+    /// Disabled when unset.
+    #[serde(default)]
+    pub proposal_upload_rate: Option<u64>,
+
+    /// Size of the chunks the payloads of the proposals of this node are split into,
+    /// fixed or adapted to the streams received from the peers.
+    #[serde(default)]
+    pub proposal_chunking: ProposalChunkingConfig,
+
+    /// Number of blocks by which the finalized block reported to the execution client
+    /// lags the last decided block. Decided blocks are final, so 0 reports them as
+    /// finalized immediately; a larger depth is only useful to downstream applications
+    /// expecting a lagging finalized tag.
+    /// Default: 0
+    #[serde(default)]
+    pub finalized_block_depth: u64,
+
     // Address used to receive fees
     pub fee_recipient: Address,
 
@@ -78,12 +166,805 @@ pub struct EmeraldConfig {
     /// Default: 10
     #[serde(default = "default_num_temp_blocks_retained")]
     pub num_temp_blocks_retained: u64,
+
+    /// Where to load the key used to encrypt the consensus store at rest.
+    /// When unset, the store is not encrypted.
+    /// A store created with encryption cannot be opened without its key, and vice versa.
+    #[serde(default)]
+    pub store_encryption_key: Option<SecretSource>,
+
+    /// Limits on the proposals stored before being decided, protecting the store from
+    /// peers streaming many proposals, e.g. for future heights.
+    #[serde(default)]
+    pub store_limits: StoreLimitsConfig,
+
+    /// Number of latest decided heights whose consistency is verified before the node
+    /// starts, as `emerald verify-store` does for the whole store. 0 disables the check.
+    /// Default: 64
+    #[serde(default = "default_store_startup_check_heights")]
+    pub store_startup_check_heights: u64,
+
+    /// Hash of the genesis this node is expected to run, as printed by `emerald init`.
+    /// When set, the node refuses to start if the genesis file does not match it.
+    #[serde(default)]
+    pub expected_genesis_hash: Option<B256>,
+
+    /// Address of the admin API, used to inspect and adjust the node at runtime.
+    /// Disabled when unset. Without `admin_auth_token`, it is served without
+    /// authentication and must listen on a loopback address.
+    #[serde(default)]
+    pub admin_listen_addr: Option<SocketAddr>,
+
+    /// Certificate and key the admin API is served with over TLS.
+    /// Served in plaintext when unset.
+    #[serde(default)]
+    pub admin_tls: Option<TlsConfig>,
+
+    /// Where to load the bearer token required by the requests to the admin API.
+    /// Not authenticated when unset.
+    #[serde(default)]
+    pub admin_auth_token: Option<SecretSource>,
+
+    /// Address of the JSON-RPC proxy to the execution client, which adds finality
+    /// information to the blocks it returns. Disabled when unset.
+    #[serde(default)]
+    pub rpc_proxy_listen_addr: Option<SocketAddr>,
+
+    /// Authenticated endpoint accepting raw transactions, submitted right away to the
+    /// execution client of this node when it is the next proposer, instead of reaching it
+    /// through the gossip of transactions. Disabled when unset.
+    #[serde(default)]
+    pub direct_tx: Option<DirectTxConfig>,
+
+    /// TOML file listing the addresses whose transactions this node must not propose,
+    /// relative paths are resolved against the home directory. Disabled when unset.
+    #[serde(default)]
+    pub tx_filter_file: Option<PathBuf>,
+
+    /// External block builder asked for the payloads this node proposes. The payloads
+    /// are built by the local execution client when the builder fails, returns a payload
+    /// which does not extend the parent block, or when unset.
+    #[serde(default)]
+    pub external_builder: Option<ExternalBuilderConfig>,
+
+    /// Allow and deny lists of the peers of this node on the consensus network,
+    /// applied to the proposals streamed by the peers and to the persistent peers dialed
+    /// at startup. Empty by default.
+    #[serde(default)]
+    pub peer_filter: PeerFilterConfig,
+
+    /// Rate limit of the decided values served to syncing peers, protecting the node
+    /// from peers requesting random heights. Disabled when unset.
+    #[serde(default)]
+    pub sync_rate_limit: Option<SyncRateLimitConfig>,
+
+    /// Throttle of the values synced from the peers while the node catches up, so that
+    /// applying them does not starve the RPC traffic of the execution client. Disabled
+    /// when unset, or with `emerald start --fast-sync`.
+    #[serde(default)]
+    pub catch_up_throttle: Option<CatchUpThrottleConfig>,
+
+    /// Size in bytes of the cache of the decided values rebuilt from the execution client
+    /// for syncing peers, so that the heights requested by several peers are only rebuilt
+    /// once. Disabled when set to 0.
+    /// Default: 64 MiB
+    #[serde(default = "default_sync_value_cache_bytes")]
+    pub sync_value_cache_bytes: u64,
+
+    /// Maximum number of decided values pruned from the store rebuilt from the execution
+    /// client for syncing peers at the same time, in the background. The heights requested
+    /// above it are answered without a value, and requested from other nodes.
+    /// Default: 4
+    #[serde(default = "default_sync_max_concurrent_rebuilds")]
+    pub sync_max_concurrent_rebuilds: usize,
+
+    /// Namespace and constant labels of the application metrics
+    #[serde(default)]
+    pub metrics: AppMetricsConfig,
+
+    /// Append-only log of consensus events, one JSON object per line, meant for
+    /// postmortem analysis and trace checking against the spec. Disabled when unset.
+    #[serde(default)]
+    pub event_log: Option<EventLogConfig>,
+
+    /// Webhooks notified of the decided blocks, the changes of the validator set and the
+    /// stalls of consensus. Disabled when unset.
+    #[serde(default)]
+    pub webhooks: Option<WebhooksConfig>,
+
+    /// Execution clients other than the one of this node, e.g. of its peers or of RPC
+    /// nodes, to which the decided blocks are announced, so that they import them before
+    /// being asked to by their own consensus node. Disabled when unset.
+    #[serde(default)]
+    pub el_announce: Option<ElAnnounceConfig>,
+
+    /// Failover between a primary and a standby node sharing the validator key, only the
+    /// holder of a lease shared by both nodes signing with it. Disabled when unset.
+    #[serde(default)]
+    pub failover: Option<FailoverConfig>,
+
+    /// Start from a snapshot of the execution client, e.g. restored from a Reth snapshot,
+    /// rather than replaying or syncing the chain from genesis. Disabled when unset.
+    #[serde(default)]
+    pub el_snapshot: Option<ElSnapshotConfig>,
+
+    /// Address at which the peers reach the consensus port of the node when it is behind a
+    /// NAT, and how the port is mapped on the gateway. Listen address only when unset.
+    #[serde(default)]
+    pub p2p_nat: Option<P2pNatConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppMetricsConfig {
+    /// Prefix of the names of the application metrics, to tell apart instances
+    /// scraped by the same Prometheus.
+    /// Default: "app_channel"
+    #[serde(default = "default_metrics_namespace")]
+    pub namespace: String,
+
+    /// Constant labels added to all application metrics, e.g. `chain_id`.
+    /// The `moniker` label is always added.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    /// Certificate and key the metrics are served with over TLS.
+    /// Served in plaintext when unset.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    /// Where to load the bearer token required to scrape the metrics.
+    /// Not authenticated when unset.
+    #[serde(default)]
+    pub auth_token: Option<SecretSource>,
+
+    /// Monikers of the validators, by address, labelling the per-proposer metrics.
+    /// The proposers not listed are labelled with their address, up to 64 of them,
+    /// then as `other`, bounding the number of series.
+    #[serde(default)]
+    pub validator_monikers: BTreeMap<Address, String>,
+}
+
+impl Default for AppMetricsConfig {
+    fn default() -> Self {
+        Self {
+            namespace: default_metrics_namespace(),
+            labels: BTreeMap::new(),
+            tls: None,
+            auth_token: None,
+            validator_monikers: BTreeMap::new(),
+        }
+    }
+}
+
+/// PEM files of the certificate chain and private key of an HTTP endpoint served over TLS,
+/// relative paths are resolved against the home directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeerFilterConfig {
+    /// When not empty, only the peers with these peer ids are accepted
+    #[serde(default)]
+    pub allowed_peers: Vec<String>,
+
+    /// Peer ids of the peers which are never accepted
+    #[serde(default)]
+    pub denied_peers: Vec<String>,
+
+    /// When not empty, only the peers with these IP addresses are dialed
+    #[serde(default)]
+    pub allowed_ips: Vec<IpAddr>,
+
+    /// IP addresses of the peers which are never dialed
+    #[serde(default)]
+    pub denied_ips: Vec<IpAddr>,
+}
+
+/// Limits on the proposals stored before being decided. A proposal above a hard limit is
+/// not stored. To stay below a soft limit, the least useful proposals are evicted: the
+/// pending proposals of the furthest heights, and the undecided proposals of the oldest
+/// heights. A proposal which would be evicted first is not stored.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoreLimitsConfig {
+    /// Hard limit on the pending proposals of each future height, stored without validation.
+    /// Default: 16 proposals, 256 MiB
+    #[serde(default = "default_pending_per_height")]
+    pub pending_per_height: TableLimit,
+
+    /// Soft limit on the pending proposals of all the future heights.
+    /// Default: 256 proposals, 1 GiB
+    #[serde(default = "default_pending_total")]
+    pub pending_total: TableLimit,
+
+    /// Hard limit on the validated proposals of each round. The limit is per round rather
+    /// than per height, as a height can last many rounds while the network is unhealthy.
+    /// Default: 4 proposals, 512 MiB
+    #[serde(default = "default_undecided_per_round")]
+    pub undecided_per_round: TableLimit,
+
+    /// Soft limit on the validated proposals of all the heights.
+    /// Default: 4096 proposals, 8 GiB
+    #[serde(default = "default_undecided_total")]
+    pub undecided_total: TableLimit,
+}
+
+impl Default for StoreLimitsConfig {
+    fn default() -> Self {
+        Self {
+            pending_per_height: default_pending_per_height(),
+            pending_total: default_pending_total(),
+            undecided_per_round: default_undecided_per_round(),
+            undecided_total: default_undecided_total(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableLimit {
+    /// Maximum number of proposals
+    pub entries: usize,
+    /// Maximum size of the proposals, in bytes
+    pub bytes: u64,
+}
+
+/// Size of the chunks the payloads of the proposals are split into. Each chunk is a message
+/// of the gossip network, so it must stay below its maximum message size.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProposalChunkingConfig {
+    /// Size of the chunks in bytes, the initial size when adaptive.
+    /// Default: 128 KiB
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+
+    /// Adapts the size of the chunks to the proposals streamed by the peers: larger chunks
+    /// when they are received at a high throughput, e.g. on a LAN, and smaller ones when
+    /// streams are left incomplete, which points to lost messages.
+    /// Default: false
+    #[serde(default)]
+    pub adaptive: bool,
+
+    /// Smallest size of the chunks when adaptive.
+    /// Default: 16 KiB
+    #[serde(default = "default_min_chunk_size")]
+    pub min_chunk_size: usize,
+
+    /// Largest size of the chunks when adaptive.
+    /// Default: 1 MiB
+    #[serde(default = "default_max_chunk_size")]
+    pub max_chunk_size: usize,
+}
+
+impl Default for ProposalChunkingConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: default_chunk_size(),
+            adaptive: false,
+            min_chunk_size: default_min_chunk_size(),
+            max_chunk_size: default_max_chunk_size(),
+        }
+    }
+}
+
+impl ProposalChunkingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chunk_size == 0 {
+            return Err("chunk_size must be greater than 0".to_string());
+        }
+        if self.adaptive {
+            if self.min_chunk_size == 0 {
+                return Err("min_chunk_size must be greater than 0".to_string());
+            }
+            if self.min_chunk_size > self.max_chunk_size {
+                return Err("min_chunk_size must not exceed max_chunk_size".to_string());
+            }
+            if !(self.min_chunk_size..=self.max_chunk_size).contains(&self.chunk_size) {
+                return Err(
+                    "chunk_size must be between min_chunk_size and max_chunk_size".to_string(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncRateLimitConfig {
+    /// Sustained rate of the heights served to syncing peers, in requests per second
+    pub requests_per_second: u32,
+
+    /// Number of heights served back to back before the rate applies, the lower half
+    /// being reserved to the lower half of the heights served by the node.
+    /// Default: twice `requests_per_second`
+    #[serde(default)]
+    pub burst: Option<u32>,
+
+    /// Maximum size in bytes of the decided values served over the last minute, as
+    /// recorded in the statistics served on `/sync_serving` by the admin API.
+    /// Unlimited when unset.
+    #[serde(default)]
+    pub max_bytes_per_minute: Option<u64>,
+}
+
+impl SyncRateLimitConfig {
+    pub fn burst(&self) -> u32 {
+        self.burst
+            .unwrap_or(self.requests_per_second.saturating_mul(2))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.requests_per_second == 0 {
+            return Err("requests_per_second must be greater than 0".to_string());
+        }
+        if self.burst() == 0 {
+            return Err("burst must be greater than 0".to_string());
+        }
+        if self.max_bytes_per_minute == Some(0) {
+            return Err("max_bytes_per_minute must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExternalBuilderConfig {
+    /// JSON-RPC endpoint of the builder, serving `builder_getPayload`
+    pub url: String,
+
+    /// Time to wait for the builder before building the payload locally.
+    /// Default: 1s
+    #[serde(with = "humantime_serde", default = "default_external_builder_timeout")]
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatchUpThrottleConfig {
+    /// Maximum rate at which the synced values are applied to the execution client, in
+    /// blocks per second. Unlimited when unset.
+    #[serde(default)]
+    pub blocks_per_second: Option<u32>,
+
+    /// File holding the pid of the execution client, whose CPU usage is read from
+    /// `/proc/<pid>/stat` on Linux. The CPU usage is not considered when unset.
+    #[serde(default)]
+    pub el_pid_file: Option<PathBuf>,
+
+    /// CPU usage of the execution client above which the synced values wait, in percent
+    /// of a core, e.g. 200 for two cores.
+    /// Default: 80
+    #[serde(default = "default_max_el_cpu_percent")]
+    pub max_el_cpu_percent: u32,
+
+    /// Maximum time a synced value waits for the CPU usage of the execution client to
+    /// drop, so that the catch-up keeps progressing.
+    /// Default: 1s
+    #[serde(with = "humantime_serde", default = "default_max_el_cpu_wait")]
+    pub max_el_cpu_wait: Duration,
+}
+
+impl CatchUpThrottleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.blocks_per_second.is_none() && self.el_pid_file.is_none() {
+            return Err("either blocks_per_second or el_pid_file must be set".to_string());
+        }
+        if self.blocks_per_second == Some(0) {
+            return Err("blocks_per_second must be greater than 0".to_string());
+        }
+        if self.max_el_cpu_percent == 0 {
+            return Err("max_el_cpu_percent must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElAnnounceConfig {
+    /// Execution clients the decided blocks are announced to
+    pub endpoints: Vec<ElAnnounceEndpoint>,
+}
+
+impl ElAnnounceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.endpoints.is_empty() {
+            return Err("endpoints must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElAnnounceEndpoint {
+    /// Engine API endpoint of the execution client
+    pub engine_url: String,
+
+    /// Path of the JWT secret of the Engine API of the execution client
+    pub jwt_token_path: String,
+}
+
+/// How the consensus port of a node behind a NAT is made reachable
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NatMapping {
+    /// The port is forwarded on the gateway by the operator, or the node is not behind a NAT
+    #[default]
+    Static,
+    /// The port is mapped on the gateway with UPnP IGD
+    Upnp,
+    /// The port is mapped on the gateway with NAT-PMP
+    NatPmp,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct P2pNatConfig {
+    /// How the consensus port is mapped on the gateway.
+    /// Default: static
+    #[serde(default)]
+    pub mapping: NatMapping,
+
+    /// Public IP or DNS name at which the peers reach the node, used by the config generator
+    /// in the persistent peers of the other nodes. Required with a static mapping, and
+    /// otherwise the external address reported by the gateway when unset.
+    #[serde(default)]
+    pub external_address: Option<String>,
+
+    /// Port at which the peers reach the node, which must be mapped on the gateway to the
+    /// consensus port. The consensus port when unset.
+    #[serde(default)]
+    pub external_port: Option<u16>,
+
+    /// Gateway the port is mapped on with NAT-PMP. The default route when unset.
+    #[serde(default)]
+    pub gateway: Option<IpAddr>,
+
+    /// Lifetime of the port mappings requested from the gateway, renewed at half of it.
+    /// Default: 1h
+    #[serde(with = "humantime_serde", default = "default_nat_lease_duration")]
+    pub lease_duration: Duration,
+}
+
+impl P2pNatConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mapping == NatMapping::Static && self.external_address.is_none() {
+            return Err("external_address is required with a static mapping".to_string());
+        }
+        if self.external_address.as_deref() == Some("") {
+            return Err("external_address must not be empty".to_string());
+        }
+        if self.external_port == Some(0) {
+            return Err("external_port must be greater than 0".to_string());
+        }
+        if self.lease_duration < Duration::from_secs(120) {
+            return Err("lease_duration must be at least 2m".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventLogConfig {
+    /// Path of the log file, relative paths are resolved against the home directory
+    pub path: PathBuf,
+
+    /// Size in bytes above which the log file is rotated.
+    /// Default: 64 MiB
+    #[serde(default = "default_event_log_max_file_size")]
+    pub max_file_size: u64,
+
+    /// Number of rotated files to keep, as `<path>.1` (most recent) to `<path>.<max_files>`.
+    /// Default: 4
+    #[serde(default = "default_event_log_max_files")]
+    pub max_files: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// Endpoints notified of the events
+    pub endpoints: Vec<WebhookEndpointConfig>,
+
+    /// Time without a decided block after which consensus is reported as stalled.
+    /// Default: 30s
+    #[serde(with = "humantime_serde", default = "default_stall_timeout")]
+    pub stall_timeout: Duration,
+
+    /// Number of attempts at delivering an event to an endpoint, after which it is
+    /// written to the dead-letter log.
+    /// Default: 5
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Log of the events which could not be delivered, one JSON object per line.
+    /// Relative paths are resolved against the home directory.
+    /// Default: webhooks/dead_letter.jsonl
+    #[serde(default = "default_dead_letter_path")]
+    pub dead_letter_path: PathBuf,
+}
+
+impl WebhooksConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stall_timeout.is_zero() {
+            return Err("stall_timeout must be greater than 0".to_string());
+        }
+        if self.max_attempts == 0 {
+            return Err("max_attempts must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookEndpointConfig {
+    /// URL the events are POSTed to
+    pub url: String,
+
+    /// Where to load the secret the events are signed with, in the `X-Emerald-Signature`
+    /// header. Not signed when unset.
+    #[serde(default)]
+    pub secret: Option<SecretSource>,
+
+    /// Events sent to the endpoint, all of them when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+}
+
+/// Kind of the events sent to the webhooks
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    /// A block was decided and committed to the execution client
+    DecidedBlock,
+    /// The validator set of the next height differs from the one of the decided height
+    ValidatorSetChanged,
+    /// No block was decided for `stall_timeout`
+    ConsensusStalled,
+}
+
+impl WebhookEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DecidedBlock => "decided_block",
+            Self::ValidatorSetChanged => "validator_set_changed",
+            Self::ConsensusStalled => "consensus_stalled",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElSnapshotConfig {
+    /// Height of the block the execution client was restored at, trusted as decided.
+    /// Consensus starts at the next height while the store holds no later height.
+    pub trusted_height: u64,
+
+    /// Hash of the block at `trusted_height`, which the execution client must hold
+    pub trusted_hash: B256,
+
+    /// Admin APIs of the nodes the certificates of the heights up to `trusted_height`
+    /// are backfilled from, within `num_certificates_to_retain`.
+    /// No certificate is backfilled when empty.
+    #[serde(default)]
+    pub backfill_peers: Vec<String>,
+
+    /// Where to load the bearer token required by the admin APIs of the peers, if any
+    #[serde(default)]
+    pub backfill_auth_token: Option<SecretSource>,
+}
+
+impl ElSnapshotConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.trusted_height == 0 {
+            return Err("trusted_height must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Role of a node in a failover pair
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailoverRole {
+    /// Takes the lease as soon as it expires
+    Primary,
+    /// Takes the lease once it has been expired for another lease duration, so that the
+    /// primary gets it first
+    Standby,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectTxConfig {
+    /// Address the endpoint listens on
+    pub listen_addr: SocketAddr,
+
+    /// Certificate and key the endpoint is served with over TLS.
+    /// Served in plaintext when unset.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    /// Where to load the bearer token required by the requests. The transactions skip the
+    /// gossip, so the endpoint is only open to the clients holding the token.
+    pub auth_token: SecretSource,
+
+    /// Direct transaction endpoints of the other validators, by validator address, which
+    /// the clients are redirected to when this node is not the next proposer
+    #[serde(default)]
+    pub validator_endpoints: BTreeMap<Address, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FailoverConfig {
+    pub role: FailoverRole,
+
+    /// Lease file shared by both nodes, e.g. on a network file system.
+    /// Relative paths are resolved against the home directory.
+    pub lease_file: PathBuf,
+
+    /// Key of the node while it does not hold the lease, which must not be in the validator
+    /// set, generated if missing. Relative paths are resolved against the home directory.
+    /// Default: config/standby_key.json
+    #[serde(default = "default_standby_key_file")]
+    pub standby_key_file: PathBuf,
+
+    /// Time for which the lease is taken or renewed.
+    /// Default: 15s
+    #[serde(with = "humantime_serde", default = "default_lease_duration")]
+    pub lease_duration: Duration,
+
+    /// Interval at which the holder renews the lease, and the other node checks it.
+    /// Must be lower than a third of the lease duration.
+    /// Default: 3s
+    #[serde(with = "humantime_serde", default = "default_lease_renew_interval")]
+    pub renew_interval: Duration,
+}
+
+impl FailoverConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.renew_interval.is_zero() {
+            return Err("renew_interval must be greater than 0".to_string());
+        }
+        if self.renew_interval * 3 >= self.lease_duration {
+            return Err("renew_interval must be lower than a third of lease_duration".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Source of a secret: the key used to encrypt the consensus store, the key signing the
+/// events sent to a webhook, or the bearer token required by an HTTP endpoint, or sent to one
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum SecretSource {
+    /// Read the secret from an environment variable
+    Env { var: String },
+    /// Read the secret from a file
+    File { path: PathBuf },
+    /// Run a command and read the secret from its standard output,
+    /// e.g. a KMS or secret manager client
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl SecretSource {
+    /// Reads the secret from its source, without the surrounding whitespace,
+    /// `name` being used in the error messages. Fails if the secret is empty.
+    pub fn read(&self, name: &str) -> eyre::Result<String> {
+        let secret = match self {
+            Self::Env { var } => read_env(name, var),
+            Self::File { path } => read_file(name, path),
+            Self::Command { program, args } => read_command(name, program, args),
+        }?;
+
+        let secret = secret.trim();
+        if secret.is_empty() {
+            return Err(eyre!("The {name} cannot be empty"));
+        }
+
+        Ok(secret.to_string())
+    }
+
+    /// Path of the file the secret is read from, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File { path } => Some(path),
+            Self::Env { .. } | Self::Command { .. } => None,
+        }
+    }
+}
+
+fn read_env(name: &str, var: &str) -> eyre::Result<String> {
+    std::env::var(var).wrap_err_with(|| format!("Failed to read {name} from env var `{var}`"))
+}
+
+fn read_file(name: &str, path: &Path) -> eyre::Result<String> {
+    std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {name} from {}", path.display()))
+}
+
+fn read_command(name: &str, program: &str, args: &[String]) -> eyre::Result<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .wrap_err_with(|| format!("Failed to run {name} command `{program}`"))?;
+
+    if !output.status.success() {
+        return Err(eyre!(
+            "The {name} command `{program}` exited with {}",
+            output.status
+        ));
+    }
+
+    String::from_utf8(output.stdout)
+        .wrap_err_with(|| format!("The {name} command returned non UTF-8 output"))
 }
 
 fn default_min_block_time() -> Duration {
     Duration::from_millis(500)
 }
 
+fn default_el_health_interval() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_el_reconnect_timeout() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_payload_reuse_window() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_proposer_build_attempts() -> u32 {
+    3
+}
+
+fn default_external_builder_timeout() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_el_retained_blocks() -> u64 {
+    10_064
+}
+
+fn default_chunk_size() -> usize {
+    128 * 1024
+}
+
+fn default_min_chunk_size() -> usize {
+    16 * 1024
+}
+
+fn default_max_chunk_size() -> usize {
+    1024 * 1024
+}
+
+fn default_sync_value_cache_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_sync_max_concurrent_rebuilds() -> usize {
+    4
+}
+
 fn default_num_certificates_to_retain() -> u64 {
     u64::MAX
 }
@@ -95,6 +976,86 @@ fn default_num_temp_blocks_retained() -> u64 {
     10
 }
 
+fn default_store_startup_check_heights() -> u64 {
+    64
+}
+
+fn default_metrics_namespace() -> String {
+    "app_channel".to_string()
+}
+
+fn default_pending_per_height() -> TableLimit {
+    TableLimit {
+        entries: 16,
+        bytes: 256 * 1024 * 1024,
+    }
+}
+
+fn default_pending_total() -> TableLimit {
+    TableLimit {
+        entries: 256,
+        bytes: 1024 * 1024 * 1024,
+    }
+}
+
+fn default_undecided_per_round() -> TableLimit {
+    TableLimit {
+        entries: 4,
+        bytes: 512 * 1024 * 1024,
+    }
+}
+
+fn default_undecided_total() -> TableLimit {
+    TableLimit {
+        entries: 4096,
+        bytes: 8 * 1024 * 1024 * 1024,
+    }
+}
+
+fn default_event_log_max_file_size() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_event_log_max_files() -> usize {
+    4
+}
+
+fn default_standby_key_file() -> PathBuf {
+    PathBuf::from("config/standby_key.json")
+}
+
+fn default_stall_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_dead_letter_path() -> PathBuf {
+    PathBuf::from("webhooks/dead_letter.jsonl")
+}
+
+fn default_lease_duration() -> Duration {
+    Duration::from_secs(15)
+}
+
+fn default_lease_renew_interval() -> Duration {
+    Duration::from_secs(3)
+}
+
+fn default_max_el_cpu_percent() -> u32 {
+    80
+}
+
+fn default_max_el_cpu_wait() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_nat_lease_duration() -> Duration {
+    Duration::from_secs(3600)
+}
+
 fn default_eth_gensesis_path() -> String {
     "./assets/genesis.json".to_string()
 }
@@ -164,8 +1125,65 @@ impl NodeConfig for Config {
 }
 
 pub fn load_config(path: impl AsRef<Path>, prefix: Option<&str>) -> eyre::Result<Config> {
+    load_config_from(::config::File::from(path.as_ref()), prefix)
+}
+
+/// Table of the emerald config file holding the malachite configuration, for the nodes
+/// configured with a single file
+pub const CONSENSUS_TABLE: &str = "consensus";
+
+/// Loads the malachite configuration of a node from the `[consensus]` table of the emerald
+/// config file if it has one, or from the separate malachite config file otherwise.
+pub fn load_node_config(config_file: &Path, emerald_config_file: &Path) -> eyre::Result<Config> {
+    match consensus_table(emerald_config_file)? {
+        Some(table) => load_config_from(
+            ::config::File::from_str(&table, ::config::FileFormat::Toml),
+            None,
+        ),
+        None => load_config(config_file, None),
+    }
+}
+
+/// Whether the emerald config file holds the malachite configuration
+pub fn has_consensus_table(emerald_config_file: &Path) -> bool {
+    consensus_table(emerald_config_file).is_ok_and(|table| table.is_some())
+}
+
+/// The `[consensus]` table of the emerald config file, if the file exists and has one
+fn consensus_table(emerald_config_file: &Path) -> eyre::Result<Option<String>> {
+    let content = match std::fs::read_to_string(emerald_config_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).wrap_err_with(|| {
+                format!(
+                    "Failed to read emerald config file `{}`",
+                    emerald_config_file.display()
+                )
+            })
+        }
+    };
+
+    let mut table = toml::from_str::<toml::Table>(&content).wrap_err_with(|| {
+        format!(
+            "Failed to parse emerald config file `{}`",
+            emerald_config_file.display()
+        )
+    })?;
+
+    table
+        .remove(CONSENSUS_TABLE)
+        .map(|consensus| toml::to_string(&consensus))
+        .transpose()
+        .map_err(Into::into)
+}
+
+fn load_config_from<S>(source: S, prefix: Option<&str>) -> eyre::Result<Config>
+where
+    S: ::config::Source + Send + Sync + 'static,
+{
     ::config::Config::builder()
-        .add_source(::config::File::from(path.as_ref()))
+        .add_source(source)
         .add_source(
             ::config::Environment::with_prefix(prefix.unwrap_or("MALACHITE")).separator("__"),
         )
@@ -5,7 +5,7 @@ use std::path::Path;
 
 use malachitebft_app::node::Node;
 
-use crate::config::Config;
+use crate::config::{Config, CONSENSUS_TABLE};
 use crate::error::Error;
 
 /// Save configuration to file
@@ -16,6 +16,35 @@ pub fn save_config(config_file: &Path, config: &Config) -> Result<(), Error> {
     )
 }
 
+/// Save configuration to the `[consensus]` table of the emerald config file, keeping the
+/// rest of the file as is when it has no such table yet
+pub fn save_consensus_config(emerald_config_file: &Path, config: &Config) -> Result<(), Error> {
+    let content = match fs::read_to_string(emerald_config_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::LoadFile(emerald_config_file.to_path_buf(), e)),
+    };
+
+    let mut table = toml::from_str::<toml::Table>(&content).map_err(Error::FromTOML)?;
+    let consensus = toml::Value::try_from(config).map_err(|e| Error::ToJSON(e.to_string()))?;
+
+    let content = if table.contains_key(CONSENSUS_TABLE) {
+        table.insert(CONSENSUS_TABLE.to_string(), consensus);
+        toml::to_string_pretty(&table).map_err(|e| Error::ToJSON(e.to_string()))?
+    } else {
+        let consensus = toml::Table::from_iter([(CONSENSUS_TABLE.to_string(), consensus)]);
+        let consensus =
+            toml::to_string_pretty(&consensus).map_err(|e| Error::ToJSON(e.to_string()))?;
+        if content.is_empty() {
+            consensus
+        } else {
+            format!("{}\n\n{consensus}", content.trim_end())
+        }
+    };
+
+    save(emerald_config_file, &content)
+}
+
 /// Save genesis to file
 pub fn save_genesis<N: Node>(
     _node: &N,
@@ -60,3 +89,93 @@ fn save(path: &Path, data: &str) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        has_consensus_table, load_node_config, BootstrapProtocol, LoggingConfig, RuntimeConfig,
+        Selector, TransportProtocol,
+    };
+    use crate::new::generate_config;
+
+    fn config(moniker: &str) -> Config {
+        generate_config(
+            0,
+            1,
+            RuntimeConfig::SingleThreaded,
+            false,
+            BootstrapProtocol::Full,
+            Selector::Random,
+            20,
+            20,
+            5000,
+            TransportProtocol::Tcp,
+            LoggingConfig::default(),
+            moniker.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_consensus_config_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("config.toml");
+        let emerald_config_file = dir.path().join("emerald.toml");
+        let emerald_config = "# Emerald\nmoniker = \"emerald\"\n\n[ethereum_config]\nexecution_authrpc_address = \"http://localhost:8545\"\n";
+        fs::write(&emerald_config_file, emerald_config).unwrap();
+        assert!(!has_consensus_table(&emerald_config_file));
+
+        // Appended to the emerald config, which is kept as is
+        save_consensus_config(&emerald_config_file, &config("node-0")).unwrap();
+        let content = fs::read_to_string(&emerald_config_file).unwrap();
+        assert!(content.starts_with(emerald_config.trim_end()));
+        assert!(has_consensus_table(&emerald_config_file));
+        assert_eq!(
+            load_node_config(&config_file, &emerald_config_file).unwrap(),
+            config("node-0")
+        );
+
+        // Replaced when saved again, the other tables being kept
+        save_consensus_config(&emerald_config_file, &config("node-1")).unwrap();
+        assert_eq!(
+            load_node_config(&config_file, &emerald_config_file).unwrap(),
+            config("node-1")
+        );
+        let table: toml::Table =
+            toml::from_str(&fs::read_to_string(&emerald_config_file).unwrap()).unwrap();
+        assert_eq!(table["moniker"].as_str(), Some("emerald"));
+        assert!(table.contains_key("ethereum_config"));
+    }
+
+    #[test]
+    fn test_consensus_config_of_missing_emerald_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let emerald_config_file = dir.path().join("config").join("emerald.toml");
+
+        save_consensus_config(&emerald_config_file, &config("node-0")).unwrap();
+        assert_eq!(
+            load_node_config(&dir.path().join("config.toml"), &emerald_config_file).unwrap(),
+            config("node-0")
+        );
+    }
+
+    #[test]
+    fn test_separate_config_without_consensus_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("config.toml");
+        let emerald_config_file = dir.path().join("emerald.toml");
+        fs::write(&emerald_config_file, "moniker = \"emerald\"\n").unwrap();
+
+        save_config(&config_file, &config("node-0")).unwrap();
+        assert_eq!(
+            load_node_config(&config_file, &emerald_config_file).unwrap(),
+            config("node-0")
+        );
+
+        // Without an emerald config either
+        assert_eq!(
+            load_node_config(&config_file, &dir.path().join("missing.toml")).unwrap(),
+            config("node-0")
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Serving of the HTTP endpoints of the node, i.e. the metrics and the admin API,
+//! optionally over TLS and behind a bearer token.
+
+use core::net::SocketAddr;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use color_eyre::eyre::{eyre, Context, Result};
+use rustls::ServerConfig;
+use tokio::net::TcpListener;
+
+use crate::config::{SecretSource, TlsConfig};
+
+/// TLS configuration and bearer token of an HTTP endpoint,
+/// which is served in plaintext and without authentication by default.
+#[derive(Clone, Default)]
+pub struct EndpointSecurity {
+    tls: Option<RustlsConfig>,
+    auth_token: Option<Arc<str>>,
+}
+
+impl EndpointSecurity {
+    /// Loads the certificate, key and token of an endpoint,
+    /// relative paths being resolved against `home_dir`.
+    pub fn load(
+        tls: Option<&TlsConfig>,
+        auth_token: Option<&SecretSource>,
+        home_dir: &Path,
+    ) -> Result<Self> {
+        let tls = tls
+            .map(|tls| load_tls(tls, home_dir))
+            .transpose()?
+            .map(|config| RustlsConfig::from_config(Arc::new(config)));
+
+        let auth_token = auth_token
+            .map(|source| source.read("auth token"))
+            .transpose()?;
+
+        Ok(Self {
+            tls,
+            auth_token: auth_token.map(Arc::from),
+        })
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.auth_token.is_some()
+    }
+}
+
+fn load_tls(tls: &TlsConfig, home_dir: &Path) -> Result<ServerConfig> {
+    let cert_path = home_dir.join(&tls.cert_path);
+    let key_path = home_dir.join(&tls.key_path);
+
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .wrap_err_with(|| format!("Failed to open {}", path.display()))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(&cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err_with(|| format!("Failed to parse certificates of {}", cert_path.display()))?;
+    if certs.is_empty() {
+        return Err(eyre!("No certificate found in {}", cert_path.display()));
+    }
+
+    let key = rustls_pemfile::private_key(&mut open(&key_path)?)
+        .wrap_err_with(|| format!("Failed to parse private key of {}", key_path.display()))?
+        .ok_or_else(|| eyre!("No private key found in {}", key_path.display()))?;
+
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .wrap_err("Invalid TLS certificate or key")
+}
+
+/// Serves `app` on `listen_addr` with the TLS configuration and bearer token of `security`.
+pub async fn serve(
+    listen_addr: SocketAddr,
+    app: Router,
+    security: EndpointSecurity,
+) -> io::Result<()> {
+    let app = match security.auth_token {
+        Some(token) => app.layer(middleware::from_fn_with_state(token, require_token)),
+        None => app,
+    };
+
+    match security.tls {
+        Some(tls) => {
+            axum_server::bind_rustls(listen_addr, tls)
+                .serve(app.into_make_service())
+                .await
+        }
+        None => {
+            let listener = TcpListener::bind(listen_addr).await?;
+            axum::serve(listener, app).await
+        }
+    }
+}
+
+/// Rejects the requests which do not carry the bearer token of the endpoint
+async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")]).into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Compares the token of a request without leaking the length of the matching prefix
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+
+    use super::*;
+    use crate::config::SecretSource;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    #[tokio::test]
+    async fn test_require_token() {
+        let app =
+            Router::new()
+                .route("/", get(|| async { "ok" }))
+                .layer(middleware::from_fn_with_state(
+                    Arc::<str>::from("secret"),
+                    require_token,
+                ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let status = |authorization: Option<&'static str>| {
+            let mut request = client.get(&url);
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            async move { request.send().await.unwrap().status() }
+        };
+
+        assert_eq!(status(None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("Bearer other")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("Bearer secre")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("Basic secret")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("secret")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("Bearer secret")).await, StatusCode::OK);
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[test]
+    fn test_load_auth_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        let source = SecretSource::File { path: path.clone() };
+
+        std::fs::write(&path, "secret\n").unwrap();
+        let security = EndpointSecurity::load(None, Some(&source), dir.path()).unwrap();
+        assert_eq!(security.auth_token.as_deref(), Some("secret"));
+        assert!(security.is_authenticated());
+        assert!(!security.is_tls());
+
+        std::fs::write(&path, " \n").unwrap();
+        assert!(EndpointSecurity::load(None, Some(&source), dir.path()).is_err());
+
+        let security = EndpointSecurity::load(None, None, dir.path()).unwrap();
+        assert!(!security.is_authenticated());
+    }
+}
@@ -1,10 +1,13 @@
 pub mod args;
+pub mod chains;
 pub mod cmd;
 pub mod config;
 pub mod error;
 pub mod file;
+pub mod http;
 pub mod logging;
 pub mod metrics;
 pub mod new;
 pub mod runtime;
 pub mod utils;
+pub mod validation;
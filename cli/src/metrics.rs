@@ -1,27 +1,30 @@
+use core::net::SocketAddr;
 use std::io;
 
 use axum::routing::get;
 use axum::Router;
 use malachitebft_app::metrics::export;
-use tokio::net::{TcpListener, ToSocketAddrs};
 use tracing::{error, info};
 
+use crate::http::{self, EndpointSecurity};
+
 #[tracing::instrument(name = "metrics", skip_all)]
-pub async fn serve(listen_addr: impl ToSocketAddrs) {
-    if let Err(e) = inner(listen_addr).await {
+pub async fn serve(listen_addr: SocketAddr, security: EndpointSecurity) {
+    if let Err(e) = inner(listen_addr, security).await {
         error!("Metrics server failed: {e}");
     }
 }
 
-async fn inner(listen_addr: impl ToSocketAddrs) -> io::Result<()> {
+async fn inner(listen_addr: SocketAddr, security: EndpointSecurity) -> io::Result<()> {
     let app = Router::new().route("/metrics", get(get_metrics));
-    let listener = TcpListener::bind(listen_addr).await?;
-    let local_addr = listener.local_addr()?;
-
-    info!(address = %local_addr, "Serving metrics");
-    axum::serve(listener, app).await?;
 
-    Ok(())
+    info!(
+        address = %listen_addr,
+        tls = security.is_tls(),
+        auth = security.is_authenticated(),
+        "Serving metrics"
+    );
+    http::serve(listen_addr, app, security).await
 }
 
 async fn get_metrics() -> String {
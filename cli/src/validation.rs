@@ -0,0 +1,664 @@
+//! Validation of the configuration of a node at startup, before any of its components
+//! starts, so that the mistakes of a configuration file are all reported at once, with the
+//! field they come from, rather than as failures deep in the stack.
+//!
+//! On top of the checks of each section, the URLs must use a supported scheme, the files
+//! they refer to must exist, the durations must be in order, and the ports the node listens
+//! on must be set and distinct.
+
+use core::fmt;
+use core::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use reqwest::Url;
+
+use crate::config::{Config, EmeraldConfig, SecretSource, TlsConfig};
+
+/// Schemes of the HTTP endpoints
+const HTTP_SCHEMES: &[&str] = &["http", "https"];
+
+/// Invalid field of a configuration file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    /// Path of the field, e.g. `ethereum_config.jwt_token_path`
+    pub field: String,
+    pub message: String,
+}
+
+/// Invalid fields of a configuration file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ConfigErrors {
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    fn push(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Records the error of the validation of a section, if any
+    fn section(&mut self, field: &str, result: Result<(), String>) {
+        if let Err(message) = result {
+            self.push(field, message);
+        }
+    }
+
+    fn url(&mut self, field: &str, url: &str, schemes: &[&str]) {
+        match Url::parse(url) {
+            Ok(parsed) if schemes.contains(&parsed.scheme()) => {}
+            Ok(parsed) => self.push(
+                field,
+                format!(
+                    "unsupported scheme `{}` in `{url}`, expected {}",
+                    parsed.scheme(),
+                    schemes.join(" or ")
+                ),
+            ),
+            Err(e) => self.push(field, format!("invalid URL `{url}`: {e}")),
+        }
+    }
+
+    fn file(&mut self, field: &str, path: &Path) {
+        if !path.is_file() {
+            self.push(field, format!("file `{}` not found", path.display()));
+        }
+    }
+
+    fn secret(&mut self, field: &str, source: &SecretSource) {
+        if let Some(path) = source.path() {
+            self.file(&format!("{field}.path"), path);
+        }
+    }
+
+    fn tls(&mut self, field: &str, tls: &TlsConfig, home_dir: &Path) {
+        self.file(
+            &format!("{field}.cert_path"),
+            &home_dir.join(&tls.cert_path),
+        );
+        self.file(&format!("{field}.key_path"), &home_dir.join(&tls.key_path));
+    }
+
+    fn into_result(self) -> Result<(), Self> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "  - {}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Returns the TCP or UDP port of a multiaddr, e.g. `/ip4/127.0.0.1/tcp/27000`
+pub fn multiaddr_port(addr: &str) -> Option<u16> {
+    multiaddr_socket(addr).map(|(_, _, port)| port)
+}
+
+/// IP address, transport and port of a multiaddr
+fn multiaddr_socket(addr: &str) -> Option<(Option<IpAddr>, &str, u16)> {
+    let components: Vec<&str> = addr.split('/').filter(|c| !c.is_empty()).collect();
+
+    let ip = components.windows(2).find_map(|pair| match pair {
+        ["ip4" | "ip6", ip] => ip.parse().ok(),
+        _ => None,
+    });
+    components.windows(2).find_map(|pair| match pair {
+        [transport @ ("tcp" | "udp"), port] => Some((ip, *transport, port.parse().ok()?)),
+        _ => None,
+    })
+}
+
+/// Validates the malachite configuration of a node
+pub fn validate_config(config: &Config) -> Result<(), ConfigErrors> {
+    let mut errors = ConfigErrors::default();
+
+    if config.moniker.is_empty() {
+        errors.push("moniker", "must not be empty");
+    }
+
+    let listen_addr = config.consensus.p2p.listen_addr.to_string();
+    match multiaddr_port(&listen_addr) {
+        None => errors.push(
+            "consensus.p2p.listen_addr",
+            format!("no TCP or UDP port in `{listen_addr}`"),
+        ),
+        Some(0) => errors.push("consensus.p2p.listen_addr", "port must not be 0"),
+        Some(_) => {}
+    }
+    for peer in &config.consensus.p2p.persistent_peers {
+        let peer = peer.to_string();
+        if !multiaddr_port(&peer).is_some_and(|port| port > 0) {
+            errors.push(
+                "consensus.p2p.persistent_peers",
+                format!("no TCP or UDP port in `{peer}`"),
+            );
+        }
+    }
+
+    let timeouts = &config.consensus.timeouts;
+    for (field, timeout) in [
+        (
+            "consensus.timeouts.timeout_propose",
+            timeouts.timeout_propose,
+        ),
+        (
+            "consensus.timeouts.timeout_prevote",
+            timeouts.timeout_prevote,
+        ),
+        (
+            "consensus.timeouts.timeout_precommit",
+            timeouts.timeout_precommit,
+        ),
+    ] {
+        if timeout.is_zero() {
+            errors.push(field, "must be greater than 0");
+        }
+    }
+
+    let value_sync = &config.value_sync;
+    if value_sync.enabled {
+        if value_sync.request_timeout.is_zero() {
+            errors.push("value_sync.request_timeout", "must be greater than 0");
+        }
+        if value_sync.batch_size == 0 {
+            errors.push("value_sync.batch_size", "must be greater than 0");
+        }
+        if value_sync.parallel_requests == 0 {
+            errors.push("value_sync.parallel_requests", "must be greater than 0");
+        }
+    }
+
+    if config.metrics.enabled && config.metrics.listen_addr.port() == 0 {
+        errors.push("metrics.listen_addr", "port must not be 0");
+    }
+
+    errors.into_result()
+}
+
+/// Validates the emerald config of a node, whose relative paths are resolved against
+/// `home_dir`. The addresses it listens on must not conflict with the ones of `config`.
+pub fn validate_emerald_config(
+    emerald_config: &EmeraldConfig,
+    config: &Config,
+    home_dir: &Path,
+) -> Result<(), ConfigErrors> {
+    let mut errors = ConfigErrors::default();
+
+    if emerald_config.moniker.is_empty() {
+        errors.push("moniker", "must not be empty");
+    }
+
+    let ethereum_config = &emerald_config.ethereum_config;
+    errors.url(
+        "ethereum_config.execution_authrpc_address",
+        &ethereum_config.execution_authrpc_address,
+        HTTP_SCHEMES,
+    );
+    errors.url(
+        "ethereum_config.engine_authrpc_address",
+        &ethereum_config.engine_authrpc_address,
+        HTTP_SCHEMES,
+    );
+    errors.file(
+        "ethereum_config.jwt_token_path",
+        Path::new(&ethereum_config.jwt_token_path),
+    );
+    errors.file(
+        "ethereum_config.eth_genesis_path",
+        Path::new(&ethereum_config.eth_genesis_path),
+    );
+
+    errors.section("engine_timeouts", emerald_config.engine_timeouts.validate());
+    errors.section("retry_config", emerald_config.retry_config.validate());
+    errors.section(
+        "proposal_chunking",
+        emerald_config.proposal_chunking.validate(),
+    );
+
+    if emerald_config.el_health_interval.is_zero() {
+        errors.push("el_health_interval", "must be greater than 0");
+    }
+    if emerald_config.num_certificates_to_retain < emerald_config.num_temp_blocks_retained {
+        errors.push(
+            "num_certificates_to_retain",
+            "must not be lower than num_temp_blocks_retained",
+        );
+    }
+    if emerald_config.prune_at_block_interval == 0 {
+        errors.push("prune_at_block_interval", "must be greater than 0");
+    }
+    if emerald_config
+        .max_idle_block_interval
+        .is_some_and(|interval| interval <= emerald_config.min_block_time)
+    {
+        errors.push(
+            "max_idle_block_interval",
+            "must be greater than min_block_time",
+        );
+    }
+    if emerald_config.proposer_build_attempts == 0 {
+        errors.push("proposer_build_attempts", "must be greater than 0");
+    }
+
+    if emerald_config.metrics.namespace.is_empty() {
+        errors.push("metrics.namespace", "must not be empty");
+    }
+    if let Some(tls) = &emerald_config.metrics.tls {
+        errors.tls("metrics.tls", tls, home_dir);
+    }
+    if let Some(auth_token) = &emerald_config.metrics.auth_token {
+        errors.secret("metrics.auth_token", auth_token);
+    }
+
+    if let Some(store_encryption_key) = &emerald_config.store_encryption_key {
+        errors.secret("store_encryption_key", store_encryption_key);
+    }
+    if let Some(tls) = &emerald_config.admin_tls {
+        errors.tls("admin_tls", tls, home_dir);
+    }
+    if let Some(auth_token) = &emerald_config.admin_auth_token {
+        errors.secret("admin_auth_token", auth_token);
+    }
+    // The admin API can stop and reconfigure the node, it is only served without
+    // authentication on localhost
+    if let Some(addr) = emerald_config.admin_listen_addr {
+        if emerald_config.admin_auth_token.is_none() && !addr.ip().is_loopback() {
+            errors.push(
+                "admin_listen_addr",
+                format!("`{addr}` is not a loopback address, which requires `admin_auth_token`"),
+            );
+        }
+    }
+
+    if let Some(direct_tx) = &emerald_config.direct_tx {
+        if let Some(tls) = &direct_tx.tls {
+            errors.tls("direct_tx.tls", tls, home_dir);
+        }
+        errors.secret("direct_tx.auth_token", &direct_tx.auth_token);
+        for (address, url) in &direct_tx.validator_endpoints {
+            errors.url(
+                &format!("direct_tx.validator_endpoints.{address}"),
+                url,
+                HTTP_SCHEMES,
+            );
+        }
+    }
+
+    if let Some(tx_filter_file) = &emerald_config.tx_filter_file {
+        errors.file("tx_filter_file", &home_dir.join(tx_filter_file));
+    }
+
+    if let Some(external_builder) = &emerald_config.external_builder {
+        errors.url("external_builder.url", &external_builder.url, HTTP_SCHEMES);
+        if external_builder.timeout.is_zero() {
+            errors.push("external_builder.timeout", "must be greater than 0");
+        }
+    }
+
+    if let Some(sync_rate_limit) = &emerald_config.sync_rate_limit {
+        errors.section("sync_rate_limit", sync_rate_limit.validate());
+    }
+    if emerald_config.sync_max_concurrent_rebuilds == 0 {
+        errors.push("sync_max_concurrent_rebuilds", "must be greater than 0");
+    }
+    if let Some(catch_up_throttle) = &emerald_config.catch_up_throttle {
+        errors.section("catch_up_throttle", catch_up_throttle.validate());
+    }
+
+    if let Some(webhooks) = &emerald_config.webhooks {
+        errors.section("webhooks", webhooks.validate());
+        for (i, endpoint) in webhooks.endpoints.iter().enumerate() {
+            errors.url(
+                &format!("webhooks.endpoints[{i}].url"),
+                &endpoint.url,
+                HTTP_SCHEMES,
+            );
+            if let Some(secret) = &endpoint.secret {
+                errors.secret(&format!("webhooks.endpoints[{i}].secret"), secret);
+            }
+        }
+    }
+
+    if let Some(el_announce) = &emerald_config.el_announce {
+        errors.section("el_announce", el_announce.validate());
+        for (i, endpoint) in el_announce.endpoints.iter().enumerate() {
+            errors.url(
+                &format!("el_announce.endpoints[{i}].engine_url"),
+                &endpoint.engine_url,
+                HTTP_SCHEMES,
+            );
+            errors.file(
+                &format!("el_announce.endpoints[{i}].jwt_token_path"),
+                Path::new(&endpoint.jwt_token_path),
+            );
+        }
+    }
+
+    if let Some(failover) = &emerald_config.failover {
+        errors.section("failover", failover.validate());
+    }
+
+    if let Some(el_snapshot) = &emerald_config.el_snapshot {
+        errors.section("el_snapshot", el_snapshot.validate());
+        for (i, peer) in el_snapshot.backfill_peers.iter().enumerate() {
+            errors.url(
+                &format!("el_snapshot.backfill_peers[{i}]"),
+                peer,
+                HTTP_SCHEMES,
+            );
+        }
+        if let Some(auth_token) = &el_snapshot.backfill_auth_token {
+            errors.secret("el_snapshot.backfill_auth_token", auth_token);
+        }
+    }
+
+    if let Some(p2p_nat) = &emerald_config.p2p_nat {
+        errors.section("p2p_nat", p2p_nat.validate());
+    }
+
+    validate_listen_addrs(&mut errors, emerald_config, config);
+
+    errors.into_result()
+}
+
+/// Checks that the addresses the node listens on have a port, and that no two of them
+/// conflict
+fn validate_listen_addrs(
+    errors: &mut ConfigErrors,
+    emerald_config: &EmeraldConfig,
+    config: &Config,
+) {
+    let mut listen_addrs: Vec<(&str, SocketAddr)> = Vec::new();
+    if let Some(addr) = emerald_config.admin_listen_addr {
+        listen_addrs.push(("admin_listen_addr", addr));
+    }
+    if let Some(addr) = emerald_config.rpc_proxy_listen_addr {
+        listen_addrs.push(("rpc_proxy_listen_addr", addr));
+    }
+    if let Some(direct_tx) = &emerald_config.direct_tx {
+        listen_addrs.push(("direct_tx.listen_addr", direct_tx.listen_addr));
+    }
+
+    for (field, addr) in &listen_addrs {
+        if addr.port() == 0 {
+            errors.push(*field, "port must not be 0");
+        }
+    }
+
+    // The HTTP endpoints of the node config listen on TCP
+    let mut taken: Vec<(&str, Option<IpAddr>, u16)> = Vec::new();
+    if let Some((ip, "tcp", port)) = multiaddr_socket(&config.consensus.p2p.listen_addr.to_string())
+    {
+        taken.push(("consensus.p2p.listen_addr of the node config", ip, port));
+    }
+    if config.metrics.enabled {
+        let addr = config.metrics.listen_addr;
+        taken.push((
+            "metrics.listen_addr of the node config",
+            Some(addr.ip()),
+            addr.port(),
+        ));
+    }
+
+    for (field, addr) in listen_addrs {
+        if addr.port() == 0 {
+            continue;
+        }
+        let conflict = taken.iter().find(|(_, ip, port)| {
+            *port == addr.port() && ip.is_none_or(|ip| overlaps(ip, addr.ip()))
+        });
+        match conflict {
+            Some((other, _, _)) => errors.push(
+                field,
+                format!("port {} is also used by {other}", addr.port()),
+            ),
+            None => taken.push((field, Some(addr.ip()), addr.port())),
+        }
+    }
+}
+
+/// Whether two listen IPs can conflict, either one listening on all the interfaces
+fn overlaps(a: IpAddr, b: IpAddr) -> bool {
+    a == b || a.is_unspecified() || b.is_unspecified()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv4Addr;
+    use core::time::Duration;
+
+    use super::*;
+    use crate::config::{
+        BootstrapProtocol, LoggingConfig, RuntimeConfig, Selector, TransportProtocol,
+    };
+    use crate::new::generate_config;
+
+    /// Node config listening on port 27000 for consensus and 29000 for the metrics
+    fn config() -> Config {
+        generate_config(
+            0,
+            1,
+            RuntimeConfig::SingleThreaded,
+            false,
+            BootstrapProtocol::Full,
+            Selector::Random,
+            20,
+            20,
+            5000,
+            TransportProtocol::Tcp,
+            LoggingConfig::default(),
+            "node-0".to_string(),
+        )
+    }
+
+    /// Valid emerald config, whose files are written to `dir`
+    fn emerald_config(dir: &Path) -> EmeraldConfig {
+        let jwt_path = dir.join("jwt.hex");
+        std::fs::write(&jwt_path, "00").unwrap();
+        let genesis_path = dir.join("genesis.json");
+        std::fs::write(&genesis_path, "{}").unwrap();
+
+        toml::from_str(&format!(
+            r#"
+            moniker = "node-0"
+            fee_recipient = "0x0000000000000000000000000000000000000000"
+
+            [ethereum_config]
+            execution_authrpc_address = "http://127.0.0.1:8545"
+            engine_authrpc_address = "http://127.0.0.1:8551"
+            jwt_token_path = "{}"
+            eth_genesis_path = "{}"
+            "#,
+            jwt_path.display(),
+            genesis_path.display(),
+        ))
+        .unwrap()
+    }
+
+    fn fields(errors: &ConfigErrors) -> Vec<&str> {
+        errors
+            .errors()
+            .iter()
+            .map(|error| error.field.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_multiaddr_port() {
+        assert_eq!(multiaddr_port("/ip4/127.0.0.1/tcp/27000"), Some(27000));
+        assert_eq!(multiaddr_port("/ip6/::1/udp/27000/quic-v1"), Some(27000));
+        assert_eq!(multiaddr_port("/dns/example.com/tcp/80"), Some(80));
+        assert_eq!(multiaddr_port("/ip4/127.0.0.1"), None);
+        assert_eq!(multiaddr_port("/ip4/127.0.0.1/tcp/port"), None);
+        assert_eq!(multiaddr_port("/ip4/127.0.0.1/tcp/70000"), None);
+        assert_eq!(multiaddr_port(""), None);
+    }
+
+    #[test]
+    fn test_multiaddr_socket() {
+        assert_eq!(
+            multiaddr_socket("/ip4/127.0.0.1/tcp/27000"),
+            Some((Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), "tcp", 27000))
+        );
+        assert_eq!(
+            multiaddr_socket("/ip6/::/udp/1/quic-v1"),
+            Some((Some("::".parse().unwrap()), "udp", 1))
+        );
+        // The IP of a DNS name is unknown
+        assert_eq!(
+            multiaddr_socket("/dns/example.com/tcp/80"),
+            Some((None, "tcp", 80))
+        );
+        assert_eq!(multiaddr_socket("/ip4/127.0.0.1"), None);
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(validate_config(&config()), Ok(()));
+        assert_eq!(
+            validate_emerald_config(&emerald_config(dir.path()), &config(), dir.path()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_url_schemes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut emerald_config = emerald_config(dir.path());
+        emerald_config.ethereum_config.execution_authrpc_address = "ws://127.0.0.1:8545".into();
+        emerald_config.ethereum_config.engine_authrpc_address = "127.0.0.1:8551".into();
+
+        let errors = validate_emerald_config(&emerald_config, &config(), dir.path()).unwrap_err();
+        assert_eq!(
+            fields(&errors),
+            [
+                "ethereum_config.execution_authrpc_address",
+                "ethereum_config.engine_authrpc_address",
+            ]
+        );
+        assert_eq!(
+            errors.errors()[0].message,
+            "unsupported scheme `ws` in `ws://127.0.0.1:8545`, expected http or https"
+        );
+        assert!(
+            errors.errors()[1]
+                .message
+                .starts_with("invalid URL `127.0.0.1:8551`"),
+            "{errors}"
+        );
+    }
+
+    #[test]
+    fn test_unset_ports() {
+        let mut config = config();
+        config.consensus.p2p.listen_addr = "/ip4/127.0.0.1".parse().unwrap();
+        config.consensus.p2p.persistent_peers = vec!["/ip4/127.0.0.1/tcp/0".parse().unwrap()];
+        config.metrics.listen_addr = "127.0.0.1:0".parse().unwrap();
+
+        let errors = validate_config(&config).unwrap_err();
+        assert_eq!(
+            fields(&errors),
+            [
+                "consensus.p2p.listen_addr",
+                "consensus.p2p.persistent_peers",
+                "metrics.listen_addr",
+            ]
+        );
+        assert_eq!(
+            errors.errors()[0].message,
+            "no TCP or UDP port in `/ip4/127.0.0.1`"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut emerald_config = emerald_config(dir.path());
+        emerald_config.admin_listen_addr = Some("127.0.0.1:0".parse().unwrap());
+
+        let errors = validate_emerald_config(&emerald_config, &config, dir.path()).unwrap_err();
+        assert_eq!(fields(&errors), ["admin_listen_addr"]);
+        assert_eq!(errors.errors()[0].message, "port must not be 0");
+    }
+
+    #[test]
+    fn test_duplicate_ports() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut emerald_config = emerald_config(dir.path());
+
+        // Listening on all the interfaces conflicts with any other address
+        emerald_config.admin_listen_addr = Some("127.0.0.1:8000".parse().unwrap());
+        emerald_config.rpc_proxy_listen_addr = Some("0.0.0.0:8000".parse().unwrap());
+        let errors = validate_emerald_config(&emerald_config, &config(), dir.path()).unwrap_err();
+        assert_eq!(fields(&errors), ["rpc_proxy_listen_addr"]);
+        assert_eq!(
+            errors.errors()[0].message,
+            "port 8000 is also used by admin_listen_addr"
+        );
+
+        // The ports of the node config are taken as well
+        emerald_config.rpc_proxy_listen_addr = Some("127.0.0.1:27000".parse().unwrap());
+        let errors = validate_emerald_config(&emerald_config, &config(), dir.path()).unwrap_err();
+        assert_eq!(
+            errors.errors()[0].message,
+            "port 27000 is also used by consensus.p2p.listen_addr of the node config"
+        );
+
+        // Distinct IPs do not conflict
+        emerald_config.rpc_proxy_listen_addr = Some("127.0.0.2:8000".parse().unwrap());
+        assert_eq!(
+            validate_emerald_config(&emerald_config, &config(), dir.path()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_errors_reported_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut emerald_config = emerald_config(dir.path());
+        emerald_config.moniker = String::new();
+        emerald_config.ethereum_config.execution_authrpc_address = "ws://127.0.0.1:8545".into();
+        emerald_config.ethereum_config.jwt_token_path =
+            dir.path().join("missing.hex").display().to_string();
+        emerald_config.el_health_interval = Duration::ZERO;
+        emerald_config.admin_listen_addr = Some("0.0.0.0:29000".parse().unwrap());
+
+        let errors = validate_emerald_config(&emerald_config, &config(), dir.path()).unwrap_err();
+        assert_eq!(
+            fields(&errors),
+            [
+                "moniker",
+                "ethereum_config.execution_authrpc_address",
+                "ethereum_config.jwt_token_path",
+                "el_health_interval",
+                "admin_listen_addr",
+                "admin_listen_addr",
+            ]
+        );
+
+        // Listed one per line, with their field
+        let report = errors.to_string();
+        assert_eq!(report.lines().count(), 6);
+        assert!(report.starts_with("  - moniker: must not be empty\n"));
+        assert!(report.ends_with(
+            "  - admin_listen_addr: port 29000 is also used by metrics.listen_addr of the node config"
+        ));
+    }
+}
@@ -8,6 +8,7 @@ use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header
 const DEFAULT_ALGORITHM: Algorithm = Algorithm::HS256;
 
 /// Contains the JWT secret and claims parameters.
+#[derive(Clone)]
 pub struct Auth {
     key: EncodingKey,
 }
@@ -26,15 +27,18 @@ impl Auth {
     }
 
     /// Generate a JWT token with `claims.iat` set to current time.
-    pub fn generate_token(&self) -> eyre::Result<String> {
+    pub fn generate_token(&self) -> Result<String, jsonwebtoken::errors::Error> {
         let claims = self.generate_claims_at_timestamp();
         self.generate_token_with_claims(&claims)
     }
 
     /// Generate a JWT token with the given claims.
-    fn generate_token_with_claims(&self, claims: &Claims) -> eyre::Result<String> {
+    fn generate_token_with_claims(
+        &self,
+        claims: &Claims,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
         let header = Header::new(DEFAULT_ALGORITHM);
-        Ok(encode(&header, claims, &self.key)?)
+        encode(&header, claims, &self.key)
     }
 
     /// Generate a `Claims` struct with `iat` set to current time
@@ -0,0 +1,118 @@
+//! Gating of the execution client versions this node accepts to run with.
+//!
+//! Rules are written as `NAME` or `NAME/VERSION`, where the name is matched
+//! case-insensitively against the client name or code (e.g. `reth` or `RH`) and the
+//! version, if any, is matched as a prefix of the client version, ignoring a leading `v`.
+//! For instance, `reth/1.9` matches Reth `v1.9.0` and `v1.9.3` but not `v1.10.0`.
+
+use color_eyre::eyre::{self, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::json_structures::ClientVersionV1;
+
+/// Version of this node, sent to the execution client
+pub fn emerald_client_version() -> ClientVersionV1 {
+    ClientVersionV1 {
+        code: "EM".to_string(),
+        name: "Emerald".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        commit: "0x00000000".to_string(),
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientVersionPolicy {
+    /// When not empty, only execution clients matching one of these rules are accepted
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// Execution clients matching one of these rules are refused
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ClientVersionPolicy {
+    /// Checks the version of the execution client, or its absence when the client
+    /// does not support `engine_getClientVersionV1`.
+    pub fn check(&self, client_version: Option<&ClientVersionV1>) -> eyre::Result<()> {
+        let Some(client_version) = client_version else {
+            if !self.allow.is_empty() {
+                return Err(eyre!(
+                    "Execution client does not report its version, which is required by the allow list"
+                ));
+            }
+            return Ok(());
+        };
+
+        if let Some(rule) = self.deny.iter().find(|rule| matches(rule, client_version)) {
+            return Err(eyre!(
+                "Execution client {} {} is denied by rule `{rule}`",
+                client_version.name,
+                client_version.version
+            ));
+        }
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|rule| matches(rule, client_version)) {
+            return Err(eyre!(
+                "Execution client {} {} is not in the allow list",
+                client_version.name,
+                client_version.version
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn matches(rule: &str, client_version: &ClientVersionV1) -> bool {
+    let (name, version) = match rule.split_once('/') {
+        Some((name, version)) => (name, Some(version)),
+        None => (rule, None),
+    };
+
+    let name_matches = name.eq_ignore_ascii_case(&client_version.name)
+        || name.eq_ignore_ascii_case(&client_version.code);
+
+    let version_matches = version.is_none_or(|version| {
+        client_version
+            .version
+            .trim_start_matches('v')
+            .starts_with(version.trim_start_matches('v'))
+    });
+
+    name_matches && version_matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reth(version: &str) -> ClientVersionV1 {
+        ClientVersionV1 {
+            code: "RH".to_string(),
+            name: "reth".to_string(),
+            version: version.to_string(),
+            commit: "0x12345678".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_client_version_policy() {
+        let policy = ClientVersionPolicy {
+            allow: vec!["reth".to_string()],
+            deny: vec!["RH/v1.8".to_string()],
+        };
+
+        assert!(policy.check(Some(&reth("v1.9.3"))).is_ok());
+        assert!(policy.check(Some(&reth("v1.8.2"))).is_err());
+        assert!(policy.check(None).is_err());
+
+        let mut geth = reth("1.14.0");
+        geth.code = "GE".to_string();
+        geth.name = "Geth".to_string();
+        assert!(policy.check(Some(&geth)).is_err());
+
+        assert!(ClientVersionPolicy::default().check(None).is_ok());
+        assert!(ClientVersionPolicy::default().check(Some(&geth)).is_ok());
+    }
+}
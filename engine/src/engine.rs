@@ -2,110 +2,127 @@ use core::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use alloy_rpc_types_engine::{
-    ExecutionPayloadV3, ForkchoiceUpdated, PayloadAttributes, PayloadStatus, PayloadStatusEnum,
+    ExecutionPayloadV3, ForkchoiceState, ForkchoiceUpdated, PayloadAttributes, PayloadId,
+    PayloadStatus, PayloadStatusEnum,
 };
-use color_eyre::eyre;
-use malachitebft_eth_types::{Address, BlockHash, RetryConfig, B256};
+use emerald_retry::{retry_with_clock, RetryError, SharedClock, SystemClock};
+use malachitebft_eth_types::{Address, BlockHash, RetryConfig, RetryOperation, B256};
 use tracing::{debug, warn};
 
-use crate::engine_rpc::{EngineRPC, Fork};
+use crate::client_version;
+use crate::engine_rpc::{EngineCapabilities, EngineRPC, Fork};
+use crate::error::EngineError;
 use crate::ethereum_rpc::EthereumRPC;
-use crate::json_structures::{ExecutionBlock, SyncStatus};
+use crate::json_structures::{ClientVersionV1, ExecutionBlock, SyncStatus};
 /// RPC client for Engine API.
 /// Spec: https://github.com/ethereum/execution-apis/tree/main/src/engine
+#[derive(Clone)]
 pub struct Engine {
     pub api: EngineRPC,
     pub eth: EthereumRPC,
+    /// Clock measuring the delays and timeouts of the retries
+    clock: SharedClock,
 }
 
 impl Engine {
     pub fn new(api: EngineRPC, eth: EthereumRPC) -> Self {
-        Self { api, eth }
+        Self {
+            api,
+            eth,
+            clock: SystemClock::shared(),
+        }
+    }
+
+    pub fn with_clock(self, clock: SharedClock) -> Self {
+        Self { clock, ..self }
     }
 
-    pub async fn check_capabilities(&self) -> eyre::Result<()> {
-        let cap: crate::engine_rpc::EngineCapabilities = self.api.exchange_capabilities().await?;
+    pub async fn check_capabilities(&self) -> Result<EngineCapabilities, EngineError> {
+        let cap: EngineCapabilities = self.api.exchange_capabilities().await?;
         if !cap.forkchoice_updated_v3
             || !cap.get_payload_v3
             || !cap.new_payload_v3
             || !cap.get_payload_bodies_by_hash_v1
             || !cap.get_payload_bodies_by_range_v1
         {
-            return Err(eyre::eyre!("Engine does not support required methods"));
+            return Err(EngineError::MissingCapabilities);
         }
 
-        Ok(())
+        Ok(cap)
+    }
+
+    /// Returns the version of the execution client, or `None` if it does not
+    /// support `engine_getClientVersionV1`.
+    pub async fn get_client_version(
+        &self,
+        capabilities: &EngineCapabilities,
+    ) -> Result<Option<ClientVersionV1>, EngineError> {
+        if !capabilities.get_client_version_v1 {
+            return Ok(None);
+        }
+
+        let versions = self
+            .api
+            .get_client_version(&client_version::emerald_client_version())
+            .await?;
+
+        Ok(versions.into_iter().next())
     }
 
     async fn forkchoice_updated_with_retry(
         &self,
-        head_block_hash: BlockHash,
+        forkchoice_state: ForkchoiceState,
         payload_attributes: Option<PayloadAttributes>,
         retry_config: &RetryConfig,
-    ) -> eyre::Result<ForkchoiceUpdated> {
-        let fcu_future = async {
-            let mut retry_delay = retry_config.initial_delay;
-
-            loop {
-                let result = self
-                    .api
-                    .forkchoice_updated(head_block_hash, payload_attributes.clone())
-                    .await;
-
-                match result {
-                    Ok(forkchoice_updated) => {
-                        if forkchoice_updated.payload_status.status.is_syncing() {
-                            warn!(
-                                "⚠️  Execution client SYNCING, retrying in {:?}",
-                                retry_delay
-                            );
-
-                            tokio::time::sleep(retry_delay).await;
-                            retry_delay = retry_config.next_delay(retry_delay);
-                            continue;
-                        }
-
-                        return Ok(forkchoice_updated);
-                    }
-                    Err(e) => return Err(e),
+    ) -> Result<ForkchoiceUpdated, EngineError> {
+        let retry_config = &retry_config.for_operation(RetryOperation::ForkchoiceUpdated);
+
+        retry_with_clock(
+            self.clock.as_ref(),
+            &retry_config.backoff(),
+            retry_config.max_elapsed_time,
+            |result: &Result<ForkchoiceUpdated, _>, delay| {
+                let syncing = result
+                    .as_ref()
+                    .is_ok_and(|fcu| fcu.payload_status.status.is_syncing());
+                if syncing {
+                    warn!("⚠️  Execution client SYNCING, retrying in {delay:?}");
                 }
-            }
-        };
-
-        tokio::time::timeout(retry_config.max_elapsed_time, fcu_future)
-            .await
-            .map_err(|_| {
-                eyre::eyre!(
-                    "Timeout after {:?} waiting for execution client to sync",
-                    retry_config.max_elapsed_time
-                )
-            })?
+                syncing
+            },
+            || {
+                self.api
+                    .forkchoice_updated(forkchoice_state, payload_attributes.clone())
+            },
+        )
+        .await
+        .map_err(sync_error)
     }
 
     pub async fn send_forkchoice_updated(
         &self,
-        head_block_hash: BlockHash,
+        forkchoice_state: ForkchoiceState,
         retry_config: &RetryConfig,
-    ) -> eyre::Result<PayloadStatus> {
-        debug!("🟠 send_forkchoice_updated: {:?}", head_block_hash);
+    ) -> Result<PayloadStatus, EngineError> {
+        debug!("🟠 send_forkchoice_updated: {:?}", forkchoice_state);
 
-        self.forkchoice_updated_with_retry(head_block_hash, None, retry_config)
+        self.forkchoice_updated_with_retry(forkchoice_state, None, retry_config)
             .await
             .map(|ForkchoiceUpdated { payload_status, .. }| payload_status)
     }
 
     pub async fn set_latest_forkchoice_state(
         &self,
-        head_block_hash: BlockHash,
+        forkchoice_state: ForkchoiceState,
         retry_config: &RetryConfig,
-    ) -> eyre::Result<BlockHash> {
-        debug!("🟠 set_latest_forkchoice_state: {:?}", head_block_hash);
+    ) -> Result<BlockHash, EngineError> {
+        debug!("🟠 set_latest_forkchoice_state: {:?}", forkchoice_state);
 
         let ForkchoiceUpdated {
             payload_status,
             payload_id,
         } = self
-            .forkchoice_updated_with_retry(head_block_hash, None, retry_config)
+            .forkchoice_updated_with_retry(forkchoice_state, None, retry_config)
             .await?;
 
         assert!(payload_id.is_none(), "Payload ID should be None!");
@@ -116,60 +133,133 @@ impl Engine {
             .status
             .is_valid()
             .then(|| payload_status.latest_valid_hash.unwrap())
-            .ok_or_else(|| eyre::eyre!("Invalid payload status: {}", payload_status.status))
+            .ok_or(EngineError::InvalidPayloadStatus(payload_status.status))
     }
 
+    /// Builds a block on top of `latest_block`, which must be the head of `forkchoice_state`.
     pub async fn generate_block(
         &self,
         latest_block: &Option<ExecutionBlock>,
+        forkchoice_state: ForkchoiceState,
         retry_config: &RetryConfig,
         fee_recipient: &Address,
         fork: Fork,
-    ) -> eyre::Result<ExecutionPayloadV3> {
+    ) -> Result<ExecutionPayloadV3, EngineError> {
         debug!("🟠 current fork is {:?}", fork);
 
         debug!("🟠 generate_block on top of {:?}", latest_block);
-        let payload_attributes: PayloadAttributes;
-        let block_hash: BlockHash;
-        match latest_block {
-            Some(lb) => {
-                block_hash = lb.block_hash;
-
-                payload_attributes = PayloadAttributes {
-                    // Use current time to enable sub-second block production.
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-
-                    // prev_randao comes from the previous beacon block and influences the proposer selection mechanism.
-                    // prev_randao is derived from the RANDAO mix (randomness accumulator) of the parent beacon block.
-                    // The beacon chain generates this value using aggregated validator signatures over time.
-                    // The mix_hash field in the generated block will be equal to prev_randao.
-                    // TODO: generate value according to spec.
-                    prev_randao: lb.prev_randao,
-
-                    // TODO: provide proper address.
-                    suggested_fee_recipient: fee_recipient.to_alloy_address(),
-
-                    // Cannot be None in V3.
-                    withdrawals: Some(vec![]),
-
-                    // Cannot be None in V3.
-                    parent_beacon_block_root: Some(block_hash),
-                };
-            }
-            None => {
-                // TODO once validated that this is never happening
-                panic!("lb should never be none")
-            }
-        }
+        let Some(lb) = latest_block else {
+            // TODO once validated that this is never happening
+            panic!("lb should never be none")
+        };
+
+        // Use current time to enable sub-second block production.
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.build_block(
+            lb,
+            forkchoice_state,
+            retry_config,
+            fee_recipient,
+            timestamp,
+            fork,
+        )
+        .await
+    }
+
+    /// Builds a block with the given `timestamp` on top of `parent`, which must be the head
+    /// of `forkchoice_state`.
+    pub async fn build_block(
+        &self,
+        parent: &ExecutionBlock,
+        forkchoice_state: ForkchoiceState,
+        retry_config: &RetryConfig,
+        fee_recipient: &Address,
+        timestamp: u64,
+        fork: Fork,
+    ) -> Result<ExecutionPayloadV3, EngineError> {
+        let payload_id = self
+            .start_payload(
+                parent,
+                forkchoice_state,
+                retry_config,
+                fee_recipient,
+                timestamp,
+            )
+            .await?;
+
+        // See how payload is constructed: https://github.com/ethereum/consensus-specs/blob/v1.1.5/specs/merge/validator.md#block-proposal
+        self.get_payload_with_retry(payload_id, fork, retry_config)
+            .await
+    }
+
+    /// Builds a block with the given `timestamp` on top of `parent` as [`Engine::build_block`]
+    /// does, but retrieves it right away, without retries. The execution client then returns
+    /// the block it builds without the transactions of its pool, unless it already built a
+    /// better one.
+    pub async fn build_empty_block(
+        &self,
+        parent: &ExecutionBlock,
+        forkchoice_state: ForkchoiceState,
+        retry_config: &RetryConfig,
+        fee_recipient: &Address,
+        timestamp: u64,
+        fork: Fork,
+    ) -> Result<ExecutionPayloadV3, EngineError> {
+        let payload_id = self
+            .start_payload(
+                parent,
+                forkchoice_state,
+                retry_config,
+                fee_recipient,
+                timestamp,
+            )
+            .await?;
+
+        self.api.get_payload(payload_id, fork).await
+    }
+
+    /// Asks the execution client to start building a block with the given `timestamp` on top
+    /// of `parent`, which must be the head of `forkchoice_state`, and returns the id of the
+    /// payload, to be retrieved with `engine_getPayload`.
+    pub async fn start_payload(
+        &self,
+        parent: &ExecutionBlock,
+        forkchoice_state: ForkchoiceState,
+        retry_config: &RetryConfig,
+        fee_recipient: &Address,
+        timestamp: u64,
+    ) -> Result<PayloadId, EngineError> {
+        let block_hash = parent.block_hash;
+
+        let payload_attributes = PayloadAttributes {
+            timestamp,
+
+            // prev_randao comes from the previous beacon block and influences the proposer selection mechanism.
+            // prev_randao is derived from the RANDAO mix (randomness accumulator) of the parent beacon block.
+            // The beacon chain generates this value using aggregated validator signatures over time.
+            // The mix_hash field in the generated block will be equal to prev_randao.
+            // TODO: generate value according to spec.
+            prev_randao: parent.prev_randao,
+
+            // TODO: provide proper address.
+            suggested_fee_recipient: fee_recipient.to_alloy_address(),
+
+            // Cannot be None in V3.
+            withdrawals: Some(vec![]),
+
+            // Cannot be None in V3.
+            parent_beacon_block_root: Some(block_hash),
+        };
 
         let ForkchoiceUpdated {
             payload_status,
             payload_id,
         } = self
-            .forkchoice_updated_with_retry(block_hash, Some(payload_attributes), retry_config)
+            .forkchoice_updated_with_retry(forkchoice_state, Some(payload_attributes), retry_config)
             .await?;
 
         assert_eq!(payload_status.latest_valid_hash, Some(block_hash));
@@ -177,19 +267,52 @@ impl Engine {
         match payload_status.status {
             PayloadStatusEnum::Valid => {
                 assert!(payload_id.is_some(), "Payload ID should be Some!");
-                let payload_id = payload_id.unwrap();
-                // See how payload is constructed: https://github.com/ethereum/consensus-specs/blob/v1.1.5/specs/merge/validator.md#block-proposal
-                Ok(self.api.get_payload(payload_id, fork).await?)
+                Ok(payload_id.unwrap())
             }
-            status => Err(eyre::eyre!("Invalid payload status: {}", status)),
+            status => Err(EngineError::InvalidPayloadStatus(status)),
         }
     }
 
+    /// Retrieves a payload being built, retrying on transient errors (e.g. while the
+    /// execution client restarts) until the `get_payload` retry budget is exhausted.
+    /// Other errors, e.g. an unknown payload, are returned right away.
+    async fn get_payload_with_retry(
+        &self,
+        payload_id: PayloadId,
+        fork: Fork,
+        retry_config: &RetryConfig,
+    ) -> Result<ExecutionPayloadV3, EngineError> {
+        if let Fork::Unsupported = fork {
+            return Err(EngineError::UnsupportedFork);
+        }
+
+        let retry_config = &retry_config.for_operation(RetryOperation::GetPayload);
+
+        retry_with_clock(
+            self.clock.as_ref(),
+            &retry_config.backoff(),
+            retry_config.max_elapsed_time,
+            |result: &Result<_, EngineError>, delay| match result {
+                Err(e) if e.is_transient() => {
+                    warn!("⚠️  engine_getPayload failed: {e}, retrying in {delay:?}");
+                    true
+                }
+                _ => false,
+            },
+            || self.api.get_payload(payload_id, fork),
+        )
+        .await
+        .map_err(|e| match e {
+            RetryError::Failed(e) => e,
+            RetryError::TimedOut { timeout, .. } => EngineError::GetPayloadTimeout(timeout),
+        })
+    }
+
     pub async fn notify_new_block(
         &self,
         execution_payload: ExecutionPayloadV3,
         versioned_hashes: Vec<B256>,
-    ) -> eyre::Result<PayloadStatus> {
+    ) -> Result<PayloadStatus, EngineError> {
         let parent_block_hash = execution_payload.payload_inner.payload_inner.parent_hash;
         let execution_requests = vec![]; // TODO: Implement execution requests
         self.api
@@ -206,7 +329,7 @@ impl Engine {
     pub async fn get_payload_bodies_by_hash(
         &self,
         block_hashes: Vec<BlockHash>,
-    ) -> eyre::Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>> {
+    ) -> Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>, EngineError> {
         debug!("🟠 get_payload_bodies_by_hash: {:?}", block_hashes);
         self.api.get_payload_bodies_by_hash(block_hashes).await
     }
@@ -216,7 +339,7 @@ impl Engine {
         &self,
         start_block: u64,
         count: u64,
-    ) -> eyre::Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>> {
+    ) -> Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>, EngineError> {
         debug!(
             "🟠 get_payload_bodies_by_range: start={}, count={}",
             start_block, count
@@ -233,45 +356,26 @@ impl Engine {
         execution_payload: ExecutionPayloadV3,
         versioned_hashes: Vec<BlockHash>,
         retry_config: &RetryConfig,
-    ) -> eyre::Result<PayloadStatus> {
-        let validation_future = async {
-            let mut retry_delay = retry_config.initial_delay;
-
-            loop {
-                let result = self
-                    .notify_new_block(execution_payload.clone(), versioned_hashes.clone())
-                    .await;
-
-                match result {
-                    Ok(payload_status) => {
-                        if payload_status.status.is_syncing() {
-                            warn!(
-                                "⚠️  Execution client SYNCING, retrying in {:?}",
-                                retry_delay
-                            );
-
-                            tokio::time::sleep(retry_delay).await;
-                            retry_delay = retry_config.next_delay(retry_delay);
-                            continue;
-                        }
-
-                        return Ok(payload_status);
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
+    ) -> Result<PayloadStatus, EngineError> {
+        let retry_config = &retry_config.for_operation(RetryOperation::NewPayload);
+
+        retry_with_clock(
+            self.clock.as_ref(),
+            &retry_config.backoff(),
+            retry_config.max_elapsed_time,
+            |result: &Result<PayloadStatus, _>, delay| {
+                let syncing = result
+                    .as_ref()
+                    .is_ok_and(|payload_status| payload_status.status.is_syncing());
+                if syncing {
+                    warn!("⚠️  Execution client SYNCING, retrying in {delay:?}");
                 }
-            }
-        };
-
-        tokio::time::timeout(retry_config.max_elapsed_time, validation_future)
-            .await
-            .map_err(|_| {
-                eyre::eyre!(
-                    "Timeout after {:?} waiting for execution client to sync",
-                    retry_config.max_elapsed_time
-                )
-            })?
+                syncing
+            },
+            || self.notify_new_block(execution_payload.clone(), versioned_hashes.clone()),
+        )
+        .await
+        .map_err(sync_error)
     }
 
     /// Check if the execution client is syncing.
@@ -280,7 +384,7 @@ impl Engine {
     /// Returns a tuple of (is_syncing, current_block_height).
     /// - is_syncing: true if the node is currently syncing, false otherwise
     /// - heights_block_height: the heights block height of the chain from Reth's perspective
-    pub async fn is_syncing(&self) -> eyre::Result<(bool, u64)> {
+    pub async fn is_syncing(&self) -> Result<(bool, u64), EngineError> {
         let sync_status: SyncStatus = self
             .api
             .rpc_request("eth_syncing", serde_json::json!([]), Duration::from_secs(2))
@@ -296,7 +400,7 @@ impl Engine {
 
     /// Get the latest block number from the execution client.
     /// Returns None if the client has no blocks (genesis case).
-    pub async fn get_latest_block_number(&self) -> eyre::Result<Option<u64>> {
+    pub async fn get_latest_block_number(&self) -> Result<Option<u64>, EngineError> {
         debug!("🟠 get_latest_block_number");
 
         let block = self.eth.get_block_by_number("latest").await?;
@@ -311,3 +415,11 @@ impl Engine {
             .as_secs()
     }
 }
+
+/// Error of a call retried while the execution client is syncing
+fn sync_error(e: RetryError<EngineError>) -> EngineError {
+    match e {
+        RetryError::Failed(e) => e,
+        RetryError::TimedOut { timeout, .. } => EngineError::SyncTimeout(timeout),
+    }
+}
@@ -2,53 +2,47 @@ use core::time::Duration;
 use std::collections::HashSet;
 use std::fmt;
 use std::path::Path;
+use std::sync::Arc;
 
 use alloy_rpc_types_engine::{
     ExecutionPayloadEnvelopeV4, ExecutionPayloadEnvelopeV5, ExecutionPayloadV3, ForkchoiceState,
     ForkchoiceUpdated, PayloadAttributes, PayloadId as AlloyPayloadId, PayloadStatus,
 };
 use color_eyre::eyre;
-use eyre::eyre;
-use malachitebft_eth_types::{BlockHash, B256};
+use malachitebft_eth_types::{BlockHash, EngineTimeouts, B256};
 use reqwest::header::CONTENT_TYPE;
 use reqwest::{Client, Url};
 use serde::de::DeserializeOwned;
 use serde_json::json;
 
 use crate::auth::Auth;
+use crate::error::EngineError;
 use crate::json_structures::*;
 
 pub const ENGINE_NEW_PAYLOAD_V1: &str = "engine_newPayloadV1";
 pub const ENGINE_NEW_PAYLOAD_V2: &str = "engine_newPayloadV2";
 pub const ENGINE_NEW_PAYLOAD_V3: &str = "engine_newPayloadV3";
 pub const ENGINE_NEW_PAYLOAD_V4: &str = "engine_newPayloadV4";
-pub const ENGINE_NEW_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(8);
 
 pub const ENGINE_GET_PAYLOAD_V1: &str = "engine_getPayloadV1";
 pub const ENGINE_GET_PAYLOAD_V2: &str = "engine_getPayloadV2";
 pub const ENGINE_GET_PAYLOAD_V3: &str = "engine_getPayloadV3";
 pub const ENGINE_GET_PAYLOAD_V4: &str = "engine_getPayloadV4";
 pub const ENGINE_GET_PAYLOAD_V5: &str = "engine_getPayloadV5";
-pub const ENGINE_GET_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub const ENGINE_FORKCHOICE_UPDATED_V1: &str = "engine_forkchoiceUpdatedV1";
 pub const ENGINE_FORKCHOICE_UPDATED_V2: &str = "engine_forkchoiceUpdatedV2";
 pub const ENGINE_FORKCHOICE_UPDATED_V3: &str = "engine_forkchoiceUpdatedV3";
-pub const ENGINE_FORKCHOICE_UPDATED_TIMEOUT: Duration = Duration::from_secs(8);
 
 pub const ENGINE_GET_PAYLOAD_BODIES_BY_HASH_V1: &str = "engine_getPayloadBodiesByHashV1";
 pub const ENGINE_GET_PAYLOAD_BODIES_BY_RANGE_V1: &str = "engine_getPayloadBodiesByRangeV1";
-pub const ENGINE_GET_PAYLOAD_BODIES_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub const ENGINE_EXCHANGE_CAPABILITIES: &str = "engine_exchangeCapabilities";
-pub const ENGINE_EXCHANGE_CAPABILITIES_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub const ENGINE_GET_CLIENT_VERSION_V1: &str = "engine_getClientVersionV1";
-pub const ENGINE_GET_CLIENT_VERSION_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub const ENGINE_GET_BLOBS_V1: &str = "engine_getBlobsV1";
 pub const ENGINE_GET_BLOBS_V2: &str = "engine_getBlobsV2";
-pub const ENGINE_GET_BLOBS_TIMEOUT: Duration = Duration::from_secs(1);
 
 // Engine API methods supported by this implementation
 pub static NODE_CAPABILITIES: &[&str] = &[
@@ -66,7 +60,7 @@ pub static NODE_CAPABILITIES: &[&str] = &[
     ENGINE_FORKCHOICE_UPDATED_V3,
     ENGINE_GET_PAYLOAD_BODIES_BY_HASH_V1,
     ENGINE_GET_PAYLOAD_BODIES_BY_RANGE_V1,
-    // ENGINE_GET_CLIENT_VERSION_V1,
+    ENGINE_GET_CLIENT_VERSION_V1,
     // ENGINE_GET_BLOBS_V1,
     // ENGINE_GET_BLOBS_V2,
 ];
@@ -92,7 +86,7 @@ pub struct EngineCapabilities {
     pub get_blobs_v2: bool,
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 pub enum Fork {
     Osaka,
     Prague,
@@ -109,11 +103,17 @@ impl fmt::Display for Fork {
     }
 }
 
+/// Called with the name of the method of each call which timed out
+pub type TimeoutHook = Arc<dyn Fn(&str) + Send + Sync>;
+
 // RPC client for connecting to Engine RPC endpoint with JWT authentication.
+#[derive(Clone)]
 pub struct EngineRPC {
     client: Client,
     url: Url,
     auth: Auth,
+    timeouts: EngineTimeouts,
+    on_timeout: Option<TimeoutHook>,
 }
 
 impl core::fmt::Display for EngineRPC {
@@ -123,28 +123,36 @@ impl core::fmt::Display for EngineRPC {
 }
 
 impl EngineRPC {
-    pub fn new(url: Url, jwt_path: &Path) -> eyre::Result<Self> {
+    pub fn new(url: Url, jwt_path: &Path, timeouts: EngineTimeouts) -> eyre::Result<Self> {
         Ok(Self {
             client: Client::builder().build()?,
             url,
             auth: Auth::new_from_path(jwt_path)
                 .map_err(|error| eyre::eyre!("Failed to load configuration file: {error}"))?,
+            timeouts,
+            on_timeout: None,
         })
     }
 
+    /// Calls `hook` each time a call exceeds its timeout, e.g. to count them in metrics
+    pub fn with_timeout_hook(mut self, hook: TimeoutHook) -> Self {
+        self.on_timeout = Some(hook);
+        self
+    }
+
     pub async fn rpc_request<D: DeserializeOwned>(
         &self,
         method: &str,
         params: serde_json::Value,
         timeout: Duration,
-    ) -> eyre::Result<D> {
+    ) -> Result<D, EngineError> {
         let body = JsonRequestBody {
             jsonrpc: "2.0",
             method,
             params,
             id: json!(1),
         };
-        let token = self.auth.generate_token()?;
+        let token = self.auth.generate_token().map_err(EngineError::Auth)?;
         let request = self
             .client
             .post(self.url.clone())
@@ -152,25 +160,47 @@ impl EngineRPC {
             .header(CONTENT_TYPE, "application/json")
             .bearer_auth(token)
             .json(&body);
-        let body: JsonResponseBody = request.send().await?.error_for_status()?.json().await?;
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let body: JsonResponseBody = match response {
+            Ok(response) => response
+                .json()
+                .await
+                .map_err(|e| EngineError::transport(method, e))?,
+            Err(e) if e.is_timeout() => {
+                if let Some(on_timeout) = &self.on_timeout {
+                    on_timeout(method);
+                }
+                return Err(EngineError::Timeout {
+                    method: method.to_string(),
+                    timeout,
+                });
+            }
+            Err(e) => return Err(EngineError::transport(method, e)),
+        };
 
         if let Some(error) = body.error {
-            Err(eyre::eyre!(
-                "Server Message: code: {}, message: {}",
-                error.code,
-                error.message,
-            ))
+            Err(EngineError::Rpc {
+                method: method.to_string(),
+                code: error.code,
+                message: error.message,
+            })
         } else {
-            serde_json::from_value(body.result).map_err(Into::into)
+            serde_json::from_value(body.result).map_err(|source| EngineError::Decode {
+                method: method.to_string(),
+                source,
+            })
         }
     }
 
-    pub async fn exchange_capabilities(&self) -> eyre::Result<EngineCapabilities> {
+    pub async fn exchange_capabilities(&self) -> Result<EngineCapabilities, EngineError> {
         let capabilities: HashSet<String> = self
             .rpc_request(
                 ENGINE_EXCHANGE_CAPABILITIES,
                 json!([NODE_CAPABILITIES]),
-                ENGINE_EXCHANGE_CAPABILITIES_TIMEOUT,
+                self.timeouts.exchange_capabilities,
             )
             .await?;
 
@@ -197,24 +227,33 @@ impl EngineRPC {
         })
     }
 
+    /// Exchanges client versions with the execution client, which may return
+    /// several versions when it is a multiplexer of clients.
+    pub async fn get_client_version(
+        &self,
+        client_version: &ClientVersionV1,
+    ) -> Result<Vec<ClientVersionV1>, EngineError> {
+        self.rpc_request(
+            ENGINE_GET_CLIENT_VERSION_V1,
+            json!([client_version]),
+            self.timeouts.get_client_version,
+        )
+        .await
+    }
+
     /// Notify that a fork choice has been updated, to set the head of the chain
     /// - head_block_hash: The block hash of the head of the chain
     /// - safe_block_hash: The block hash of the most recent "safe" block (can be same as head)
     /// - finalized_block_hash: The block hash of the highest finalized block (can be 0x0 for genesis)
     pub async fn forkchoice_updated(
         &self,
-        head_block_hash: BlockHash,
+        forkchoice_state: ForkchoiceState,
         maybe_payload_attributes: Option<PayloadAttributes>,
-    ) -> eyre::Result<ForkchoiceUpdated> {
-        let forkchoice_state = ForkchoiceState {
-            head_block_hash,
-            safe_block_hash: head_block_hash,
-            finalized_block_hash: head_block_hash,
-        };
+    ) -> Result<ForkchoiceUpdated, EngineError> {
         self.rpc_request(
             ENGINE_FORKCHOICE_UPDATED_V3,
             json!([forkchoice_state, maybe_payload_attributes]),
-            ENGINE_FORKCHOICE_UPDATED_TIMEOUT,
+            self.timeouts.forkchoice_updated,
         )
         .await
     }
@@ -227,14 +266,14 @@ impl EngineRPC {
         &self,
         payload_id: AlloyPayloadId,
         fork: Fork,
-    ) -> eyre::Result<ExecutionPayloadV3> {
+    ) -> Result<ExecutionPayloadV3, EngineError> {
         match fork {
             Fork::Osaka => {
                 let response: ExecutionPayloadEnvelopeV5 = self
                     .rpc_request(
                         ENGINE_GET_PAYLOAD_V5,
                         json!([payload_id]),
-                        ENGINE_GET_PAYLOAD_TIMEOUT,
+                        self.timeouts.get_payload,
                     )
                     .await?;
                 Ok(response.execution_payload)
@@ -244,12 +283,12 @@ impl EngineRPC {
                     .rpc_request(
                         ENGINE_GET_PAYLOAD_V4,
                         json!([payload_id]),
-                        ENGINE_GET_PAYLOAD_TIMEOUT,
+                        self.timeouts.get_payload,
                     )
                     .await?;
                 Ok(response.envelope_inner.execution_payload)
             }
-            Fork::Unsupported => Err(eyre!("Unsupported fork")),
+            Fork::Unsupported => Err(EngineError::UnsupportedFork),
         }
     }
 
@@ -259,7 +298,7 @@ impl EngineRPC {
         versioned_hashes: Vec<B256>,
         parent_block_hash: BlockHash,
         execution_requests: Vec<Vec<u8>>,
-    ) -> eyre::Result<PayloadStatus> {
+    ) -> Result<PayloadStatus, EngineError> {
         let payload = JsonExecutionPayloadV3::from(execution_payload);
         let params = json!([
             payload,
@@ -267,19 +306,19 @@ impl EngineRPC {
             parent_block_hash,
             execution_requests
         ]);
-        self.rpc_request(ENGINE_NEW_PAYLOAD_V4, params, ENGINE_NEW_PAYLOAD_TIMEOUT)
+        self.rpc_request(ENGINE_NEW_PAYLOAD_V4, params, self.timeouts.new_payload)
             .await
     }
 
     pub async fn get_payload_bodies_by_hash(
         &self,
         block_hashes: Vec<BlockHash>,
-    ) -> eyre::Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>> {
+    ) -> Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>, EngineError> {
         let params = json!([block_hashes]);
         self.rpc_request(
             ENGINE_GET_PAYLOAD_BODIES_BY_HASH_V1,
             params,
-            ENGINE_GET_PAYLOAD_BODIES_TIMEOUT,
+            self.timeouts.get_payload_bodies,
         )
         .await
     }
@@ -288,14 +327,14 @@ impl EngineRPC {
         &self,
         start: u64,
         count: u64,
-    ) -> eyre::Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>> {
+    ) -> Result<Vec<Option<crate::json_structures::ExecutionPayloadBodyV1>>, EngineError> {
         let start_hex = format!("0x{start:x}");
         let count_hex = format!("0x{count:x}");
         let params = json!([start_hex, count_hex]);
         self.rpc_request(
             ENGINE_GET_PAYLOAD_BODIES_BY_RANGE_V1,
             params,
-            ENGINE_GET_PAYLOAD_BODIES_TIMEOUT,
+            self.timeouts.get_payload_bodies,
         )
         .await
     }
@@ -0,0 +1,102 @@
+//! Errors returned by the clients of the execution client.
+
+use core::time::Duration;
+
+use alloy_rpc_types_engine::PayloadStatusEnum;
+
+/// Error returned by a call to the execution client
+#[derive(Debug, thiserror::Error)]
+pub enum EngineError {
+    /// The request could not be sent, or the server answered with an HTTP error status
+    #[error("{method} failed: {source}")]
+    Transport {
+        method: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// The execution client did not answer in time
+    #[error("{method} timed out after {timeout:?}")]
+    Timeout { method: String, timeout: Duration },
+
+    /// The execution client answered with a JSON-RPC error
+    #[error("Server Message: code: {code}, message: {message}")]
+    Rpc {
+        method: String,
+        code: i64,
+        message: String,
+    },
+
+    /// The result of the call could not be decoded
+    #[error("Failed to decode the result of {method}: {source}")]
+    Decode {
+        method: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// The JWT token authenticating the call could not be generated
+    #[error("Failed to generate the JWT token: {0}")]
+    Auth(#[source] jsonwebtoken::errors::Error),
+
+    /// The execution client does not support the methods required by the node
+    #[error("Engine does not support required methods")]
+    MissingCapabilities,
+
+    #[error("Unsupported fork")]
+    UnsupportedFork,
+
+    /// The execution client answered with an unexpected payload status
+    #[error("Invalid payload status: {0}")]
+    InvalidPayloadStatus(PayloadStatusEnum),
+
+    /// The execution client was still syncing when the retry budget was exhausted
+    #[error("Timeout after {0:?} waiting for execution client to sync")]
+    SyncTimeout(Duration),
+
+    /// The payload could not be retrieved before the retry budget was exhausted
+    #[error("Timeout after {0:?} waiting for execution client to return the payload")]
+    GetPayloadTimeout(Duration),
+}
+
+impl EngineError {
+    pub(crate) fn transport(method: &str, source: reqwest::Error) -> Self {
+        Self::Transport {
+            method: method.to_string(),
+            source,
+        }
+    }
+
+    /// Returns whether the execution client did not answer in time, including when
+    /// it kept syncing until the retry budget was exhausted
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout { .. } | Self::SyncTimeout(_) | Self::GetPayloadTimeout(_)
+        )
+    }
+
+    /// Returns whether the execution client could not be reached or did not answer, e.g.
+    /// while it restarts, as opposed to rejecting the call
+    pub fn is_connection_loss(&self) -> bool {
+        match self {
+            Self::Transport { source, .. } => source.status().is_none(),
+            Self::Timeout { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the call may succeed if retried: the execution client could not be
+    /// reached, or failed with a server error, e.g. while it restarts
+    pub fn is_transient(&self) -> bool {
+        self.is_connection_loss() || self.http_status().is_some_and(|status| status >= 500)
+    }
+
+    /// Returns the HTTP status of the answer of the server, if it was an error status
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            Self::Transport { source, .. } => source.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+}
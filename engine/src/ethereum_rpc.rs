@@ -2,15 +2,18 @@ use core::time::Duration;
 
 use alloy_rpc_types_txpool::{TxpoolInspect, TxpoolStatus};
 use color_eyre::eyre;
+use malachitebft_eth_types::{Bytes, B256};
 use reqwest::header::CONTENT_TYPE;
 use reqwest::{Client, Url};
 use serde::de::DeserializeOwned;
 use serde_json::json;
 use tracing::debug;
 
+use crate::error::EngineError;
 use crate::json_structures::*;
 
 /// RPC client for Ethereum server.
+#[derive(Clone)]
 pub struct EthereumRPC {
     client: Client,
     url: Url,
@@ -33,7 +36,7 @@ impl EthereumRPC {
         method: &str,
         params: serde_json::Value,
         timeout: Duration,
-    ) -> eyre::Result<D> {
+    ) -> Result<D, EngineError> {
         let body = JsonRequestBody {
             jsonrpc: "2.0",
             method,
@@ -46,22 +49,58 @@ impl EthereumRPC {
             .timeout(timeout)
             .header(CONTENT_TYPE, "application/json")
             .json(&body);
-        let body: JsonResponseBody = request.send().await?.error_for_status()?.json().await?;
+        let body: JsonResponseBody = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| EngineError::transport(method, e))?
+            .json()
+            .await
+            .map_err(|e| EngineError::transport(method, e))?;
 
         debug!("response body: {:?}", body);
 
         match (body.result, body.error) {
-            (result, None) => serde_json::from_value(result).map_err(Into::into),
-            (_, Some(error)) => Err(eyre::eyre!(
-                "Server Message: code: {}, message: {}",
-                error.code,
-                error.message,
-            )),
+            (result, None) => {
+                serde_json::from_value(result).map_err(|source| EngineError::Decode {
+                    method: method.to_string(),
+                    source,
+                })
+            }
+            (_, Some(error)) => Err(EngineError::Rpc {
+                method: method.to_string(),
+                code: error.code,
+                message: error.message,
+            }),
         }
     }
 
+    /// Forwards a raw JSON-RPC request, or batch of requests, and returns the raw response.
+    pub async fn forward(
+        &self,
+        request: &serde_json::Value,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, EngineError> {
+        let forward = |e| EngineError::transport("forward", e);
+        let response = self
+            .client
+            .post(self.url.clone())
+            .timeout(timeout)
+            .header(CONTENT_TYPE, "application/json")
+            .json(request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(forward)?
+            .json()
+            .await
+            .map_err(forward)?;
+
+        Ok(response)
+    }
+
     /// Get the eth1 chain id of the given endpoint.
-    pub async fn get_chain_id(&self) -> eyre::Result<String> {
+    pub async fn get_chain_id(&self) -> Result<String, EngineError> {
         self.rpc_request("eth_chainId", json!([]), Duration::from_secs(1))
             .await
     }
@@ -69,19 +108,29 @@ impl EthereumRPC {
     pub async fn get_block_by_number(
         &self,
         block_number: &str,
-    ) -> eyre::Result<Option<ExecutionBlock>> {
+    ) -> Result<Option<ExecutionBlock>, EngineError> {
         let return_full_transaction_objects = false;
         let params = json!([block_number, return_full_transaction_objects]);
         self.rpc_request("eth_getBlockByNumber", params, Duration::from_secs(1))
             .await
     }
 
-    pub async fn txpool_status(&self) -> eyre::Result<TxpoolStatus> {
+    /// Submits a signed transaction, EIP-2718 encoded, to the pool and returns its hash.
+    pub async fn send_raw_transaction(&self, tx: &Bytes) -> Result<B256, EngineError> {
+        self.rpc_request(
+            "eth_sendRawTransaction",
+            json!([tx]),
+            Duration::from_secs(1),
+        )
+        .await
+    }
+
+    pub async fn txpool_status(&self) -> Result<TxpoolStatus, EngineError> {
         self.rpc_request("txpool_status", json!([]), Duration::from_secs(1))
             .await
     }
 
-    pub async fn txpool_inspect(&self) -> eyre::Result<TxpoolInspect> {
+    pub async fn txpool_inspect(&self) -> Result<TxpoolInspect, EngineError> {
         self.rpc_request("txpool_inspect", json!([]), Duration::from_secs(1))
             .await
     }
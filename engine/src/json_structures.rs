@@ -133,6 +133,18 @@ pub struct ExecutionPayloadBodyV1 {
     pub withdrawals: Option<Vec<Withdrawal>>,
 }
 
+/// Identification of a client, as exchanged with `engine_getClientVersionV1`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientVersionV1 {
+    /// Two-letter client code, e.g. `RH` for Reth
+    pub code: String,
+    pub name: String,
+    pub version: String,
+    /// First four bytes of the commit hash, hex-encoded
+    pub commit: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncStatusData {
@@ -1,5 +1,8 @@
 pub mod auth;
+pub mod client_version;
 pub mod engine;
 pub mod engine_rpc;
+pub mod error;
 pub mod ethereum_rpc;
 pub mod json_structures;
+pub mod payload_builder;
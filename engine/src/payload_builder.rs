@@ -0,0 +1,122 @@
+//! Builders of the execution payloads proposed by this node.
+//!
+//! Payloads are built either by the local execution client through the Engine API, or by
+//! an external block builder. The external builder is asked with the `builder_getPayload`
+//! JSON-RPC method, whose single parameter describes the block to build:
+//!
+//! ```json
+//! {"parentHash":"0x...","blockNumber":"0x1","timestamp":"0x...","feeRecipient":"0x...","prevRandao":"0x..."}
+//! ```
+//!
+//! and which returns the payload as an `ExecutionPayloadV3`.
+
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_rpc_types_engine::{ExecutionPayloadV3, ForkchoiceState};
+use async_trait::async_trait;
+use color_eyre::eyre;
+use malachitebft_eth_types::{Address, RetryConfig};
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::engine::Engine;
+use crate::engine_rpc::Fork;
+use crate::ethereum_rpc::EthereumRPC;
+use crate::json_structures::ExecutionBlock;
+
+/// Block to build on top of `parent`
+pub struct PayloadRequest<'a> {
+    pub parent: &'a ExecutionBlock,
+    /// Forkchoice state whose head is `parent`
+    pub forkchoice_state: ForkchoiceState,
+    pub fee_recipient: Address,
+    pub fork: Fork,
+    pub retry_config: &'a RetryConfig,
+}
+
+#[async_trait]
+pub trait PayloadBuilder: Send + Sync {
+    /// Name of the builder, for the logs and metrics
+    fn name(&self) -> &'static str;
+
+    async fn build_payload(&self, request: &PayloadRequest<'_>)
+        -> eyre::Result<ExecutionPayloadV3>;
+}
+
+/// Builds the payloads with the local execution client
+#[async_trait]
+impl PayloadBuilder for Engine {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn build_payload(
+        &self,
+        request: &PayloadRequest<'_>,
+    ) -> eyre::Result<ExecutionPayloadV3> {
+        Ok(self
+            .generate_block(
+                &Some(*request.parent),
+                request.forkchoice_state,
+                request.retry_config,
+                &request.fee_recipient,
+                request.fork,
+            )
+            .await?)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuilderRequest {
+    parent_hash: String,
+    block_number: String,
+    timestamp: String,
+    fee_recipient: String,
+    prev_randao: String,
+}
+
+/// Builds the payloads with an external block builder
+pub struct ExternalBuilder {
+    rpc: EthereumRPC,
+    timeout: Duration,
+}
+
+impl ExternalBuilder {
+    pub fn new(url: Url, timeout: Duration) -> eyre::Result<Self> {
+        Ok(Self {
+            rpc: EthereumRPC::new(url)?,
+            timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl PayloadBuilder for ExternalBuilder {
+    fn name(&self) -> &'static str {
+        "external"
+    }
+
+    async fn build_payload(
+        &self,
+        request: &PayloadRequest<'_>,
+    ) -> eyre::Result<ExecutionPayloadV3> {
+        let parent = request.parent;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let params = BuilderRequest {
+            parent_hash: parent.block_hash.to_string(),
+            block_number: format!("0x{:x}", parent.block_number + 1),
+            timestamp: format!("0x{timestamp:x}"),
+            fee_recipient: request.fee_recipient.to_alloy_address().to_string(),
+            prev_randao: parent.prev_randao.to_string(),
+        };
+
+        Ok(self
+            .rpc
+            .rpc_request("builder_getPayload", json!([params]), self.timeout)
+            .await?)
+    }
+}
@@ -0,0 +1,161 @@
+//! Source of the time, so that the timing behaviors can be tested deterministically.
+//!
+//! The node, the retries of the Engine API calls and the spammer read the time and sleep
+//! through a [`SharedClock`]: the [`SystemClock`] in production, and a [`MockClock`]
+//! advanced manually in tests, which do not have to wait for the delays to elapse.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::time::Duration;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+/// Future completing at the end of a sleep
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Clock shared by the components of a node
+pub type SharedClock = Arc<dyn Clock>;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Time elapsed since `earlier`, zero if it is in the future
+    fn elapsed(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+}
+
+/// Time of the system, as seen by the tokio runtime
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn shared() -> SharedClock {
+        Arc::new(Self)
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Clock for tests, which only moves forward when [`MockClock::advance`] is called.
+///
+/// The sleeps complete once the clock is advanced to their end.
+#[derive(Clone)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+struct MockState {
+    now: Instant,
+    /// End of the pending sleeps, with the sender waking them up
+    sleepers: Vec<(Instant, oneshot::Sender<()>)>,
+}
+
+impl MockClock {
+    /// Clock starting at the current time of the system
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                now: Instant::now(),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+
+    /// Moves the clock forward by `duration`, completing the sleeps ending by then
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().expect("mock clock lock poisoned");
+        state.now += duration;
+
+        let now = state.now;
+        let (done, pending) = state
+            .sleepers
+            .drain(..)
+            .partition::<Vec<_>, _>(|(end, _)| *end <= now);
+        state.sleepers = pending;
+
+        for (_, waker) in done {
+            let _ = waker.send(());
+        }
+    }
+
+    /// Number of sleeps in progress, e.g. to wait until the code under test sleeps
+    pub fn sleepers(&self) -> usize {
+        let mut state = self.state.lock().expect("mock clock lock poisoned");
+        // Sleeps whose future was dropped are not in progress anymore
+        state.sleepers.retain(|(_, waker)| !waker.is_closed());
+        state.sleepers.len()
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.state.lock().expect("mock clock lock poisoned").now
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        if duration.is_zero() {
+            return Box::pin(core::future::ready(()));
+        }
+
+        let (waker, sleep) = oneshot::channel();
+        let mut state = self.state.lock().expect("mock clock lock poisoned");
+        let end = state.now + duration;
+        state.sleepers.push((end, waker));
+
+        Box::pin(async move {
+            // Also completes if the clock is dropped, rather than hanging forever
+            let _ = sleep.await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let clock = MockClock::new();
+        let start = clock.now();
+
+        let short = tokio::spawn(clock.sleep(Duration::from_secs(1)));
+        let long = tokio::spawn(clock.sleep(Duration::from_secs(10)));
+        assert_eq!(clock.sleepers(), 2);
+
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(clock.sleepers(), 2);
+
+        clock.advance(Duration::from_millis(1));
+        short.await.unwrap();
+        assert_eq!(clock.sleepers(), 1);
+        assert!(!long.is_finished());
+
+        clock.advance(Duration::from_secs(9));
+        long.await.unwrap();
+        assert_eq!(clock.elapsed(start), Duration::from_secs(10));
+        assert_eq!(clock.sleepers(), 0);
+    }
+}
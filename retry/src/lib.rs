@@ -0,0 +1,385 @@
+//! Retries of the operations failing transiently, e.g. the calls to an execution client
+//! while it restarts or syncs, or to a node being started.
+//!
+//! A [`Backoff`] gives the delays between the attempts, growing exponentially up to a
+//! maximum, or constant, and randomized by a jitter. [`retry`] and [`retry_blocking`]
+//! repeat an operation with these delays for as long as a predicate holds for its outcome,
+//! and until a timeout.
+//!
+//! The delays of [`retry`] are measured by the system clock, and those of
+//! [`retry_with_clock`] by any [`Clock`], e.g. a [`MockClock`] in tests.
+
+use core::fmt;
+use core::future::Future;
+use core::pin::pin;
+use core::task::Poll;
+use core::time::Duration;
+use std::time::Instant;
+
+use rand::Rng;
+
+pub mod clock;
+
+pub use clock::{Clock, MockClock, SharedClock, SystemClock};
+
+/// Delays between the attempts of an operation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Backoff {
+    /// Delay before the second attempt
+    pub initial_delay: Duration,
+    /// Maximum delay between two attempts
+    pub max_delay: Duration,
+    /// Factor applied to the delay after each attempt, 1.0 for a constant delay
+    pub multiplier: f64,
+    /// Random jitter applied to each delay, as a fraction of the delay (e.g., 0.2 for ±20%)
+    pub jitter: f64,
+}
+
+impl Backoff {
+    /// The same delay between all the attempts
+    pub fn constant(delay: Duration) -> Self {
+        Self::exponential(delay, delay, 1.0)
+    }
+
+    /// A delay multiplied by `multiplier` after each attempt, up to `max_delay`
+    pub fn exponential(initial_delay: Duration, max_delay: Duration, multiplier: f64) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            multiplier,
+            jitter: 0.0,
+        }
+    }
+
+    pub fn with_jitter(self, jitter: f64) -> Self {
+        Self { jitter, ..self }
+    }
+
+    /// Delay following `current_delay`
+    pub fn next_delay(&self, current_delay: Duration) -> Duration {
+        let next = current_delay.mul_f64(self.multiplier);
+        core::cmp::min(next, self.max_delay)
+    }
+
+    /// Randomize a delay by up to `jitter` of its value in either direction
+    pub fn jittered(&self, delay: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+
+        let factor = rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter);
+        delay.mul_f64(factor)
+    }
+}
+
+/// Error of an operation which is not retried any more
+#[derive(Debug, PartialEq, Eq)]
+pub enum RetryError<E> {
+    /// The operation failed with an error which is not retried
+    Failed(E),
+    /// The operation did not succeed before the timeout, `last_error` being the error of
+    /// the last completed attempt if it failed
+    TimedOut {
+        timeout: Duration,
+        last_error: Option<E>,
+    },
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed(e) => write!(f, "{e}"),
+            Self::TimedOut {
+                timeout,
+                last_error: Some(e),
+            } => write!(f, "timed out after {timeout:?}, last error: {e}"),
+            Self::TimedOut {
+                timeout,
+                last_error: None,
+            } => write!(f, "timed out after {timeout:?}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for RetryError<E> {}
+
+/// Predicate retrying all the errors, and none of the values
+pub fn on_error<T, E>(outcome: &Result<T, E>, _next_delay: Duration) -> bool {
+    outcome.is_err()
+}
+
+/// Runs `op` until `retry_on` does not hold for its outcome, waiting for the delays of
+/// `backoff` between the attempts, and returns the outcome of the last attempt.
+///
+/// `retry_on` is given the delay before the next attempt, e.g. to log it. The attempts
+/// are abandoned after `timeout`, including the attempt in flight.
+pub async fn retry<T, E, F, Fut, R>(
+    backoff: &Backoff,
+    timeout: Duration,
+    retry_on: R,
+    op: F,
+) -> Result<T, RetryError<E>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    R: FnMut(&Result<T, E>, Duration) -> bool,
+{
+    retry_with_clock(&SystemClock, backoff, timeout, retry_on, op).await
+}
+
+/// [`retry`] with the delays and the timeout measured by `clock`
+pub async fn retry_with_clock<T, E, F, Fut, R>(
+    clock: &dyn Clock,
+    backoff: &Backoff,
+    timeout: Duration,
+    mut retry_on: R,
+    mut op: F,
+) -> Result<T, RetryError<E>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    R: FnMut(&Result<T, E>, Duration) -> bool,
+{
+    let mut last_error = None;
+
+    let outcome = {
+        let mut attempts = pin!(async {
+            let mut delay = backoff.initial_delay;
+
+            loop {
+                let outcome = op().await;
+                if !retry_on(&outcome, delay) {
+                    return outcome.map_err(RetryError::Failed);
+                }
+
+                last_error = outcome.err();
+                clock.sleep(backoff.jittered(delay)).await;
+                delay = backoff.next_delay(delay);
+            }
+        });
+        let mut deadline = clock.sleep(timeout);
+
+        core::future::poll_fn(|cx| {
+            if let Poll::Ready(outcome) = attempts.as_mut().poll(cx) {
+                return Poll::Ready(Some(outcome));
+            }
+            deadline.as_mut().poll(cx).map(|()| None)
+        })
+        .await
+    };
+
+    outcome.unwrap_or(Err(RetryError::TimedOut {
+        timeout,
+        last_error,
+    }))
+}
+
+/// Blocking version of [`retry`], which does not interrupt an attempt in flight
+/// at the timeout.
+pub fn retry_blocking<T, E, F, R>(
+    backoff: &Backoff,
+    timeout: Duration,
+    mut retry_on: R,
+    mut op: F,
+) -> Result<T, RetryError<E>>
+where
+    F: FnMut() -> Result<T, E>,
+    R: FnMut(&Result<T, E>, Duration) -> bool,
+{
+    let start = Instant::now();
+    let mut delay = backoff.initial_delay;
+
+    loop {
+        let outcome = op();
+        if !retry_on(&outcome, delay) {
+            return outcome.map_err(RetryError::Failed);
+        }
+
+        let Some(remaining) = timeout.checked_sub(start.elapsed()) else {
+            return Err(RetryError::TimedOut {
+                timeout,
+                last_error: outcome.err(),
+            });
+        };
+
+        std::thread::sleep(backoff.jittered(delay).min(remaining));
+        delay = backoff.next_delay(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = Backoff::exponential(100 * MS, 500 * MS, 2.0);
+        let delays = core::iter::successors(Some(backoff.initial_delay), |delay| {
+            Some(backoff.next_delay(*delay))
+        });
+        assert_eq!(
+            delays.take(5).collect::<Vec<_>>(),
+            [100 * MS, 200 * MS, 400 * MS, 500 * MS, 500 * MS]
+        );
+
+        let backoff = Backoff::constant(100 * MS);
+        assert_eq!(backoff.next_delay(100 * MS), 100 * MS);
+        assert_eq!(backoff.jittered(100 * MS), 100 * MS);
+
+        let backoff = backoff.with_jitter(0.2);
+        for _ in 0..100 {
+            let delay = backoff.jittered(Duration::from_secs(1));
+            assert!(delay >= 800 * MS && delay <= 1200 * MS);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_not_retried() {
+        let backoff = Backoff::constant(MS);
+        let timeout = Duration::from_secs(10);
+
+        // Values can be retried too, e.g. while a server is syncing
+        let mut attempts = 0;
+        let result = retry::<_, (), _, _, _>(
+            &backoff,
+            timeout,
+            |outcome, _| *outcome == Ok("syncing"),
+            || {
+                attempts += 1;
+                let outcome = if attempts < 3 { "syncing" } else { "valid" };
+                async move { Ok(outcome) }
+            },
+        )
+        .await;
+        assert_eq!(result, Ok("valid"));
+        assert_eq!(attempts, 3);
+
+        // Errors which are not retried are returned as is
+        let mut attempts = 0;
+        let result = retry::<(), _, _, _, _>(
+            &backoff,
+            timeout,
+            |outcome, _| *outcome == Err("transient"),
+            || {
+                attempts += 1;
+                let error = if attempts < 3 { "transient" } else { "fatal" };
+                async move { Err(error) }
+            },
+        )
+        .await;
+        assert_eq!(result, Err(RetryError::Failed("fatal")));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_timeout() {
+        let result = retry::<(), _, _, _, _>(&Backoff::constant(MS), 20 * MS, on_error, || async {
+            Err("unreachable")
+        })
+        .await;
+        assert_eq!(
+            result,
+            Err(RetryError::TimedOut {
+                timeout: 20 * MS,
+                last_error: Some("unreachable"),
+            })
+        );
+
+        // The attempt in flight is abandoned
+        let result = retry::<(), (), _, _, _>(&Backoff::constant(MS), 20 * MS, on_error, || {
+            core::future::pending()
+        })
+        .await;
+        assert_eq!(
+            result,
+            Err(RetryError::TimedOut {
+                timeout: 20 * MS,
+                last_error: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_mock_clock() {
+        let clock = MockClock::new();
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let retried = tokio::spawn({
+            let clock = clock.clone();
+            let attempts = attempts.clone();
+            let backoff = Backoff::exponential(Duration::from_secs(1), Duration::from_secs(4), 2.0);
+            async move {
+                retry_with_clock::<(), _, _, _, _>(
+                    &clock,
+                    &backoff,
+                    Duration::from_secs(10),
+                    on_error,
+                    || {
+                        attempts.fetch_add(1, Ordering::SeqCst);
+                        async { Err("unreachable") }
+                    },
+                )
+                .await
+            }
+        });
+
+        // Attempts at 0s, 1s, 3s and 7s, then the timeout at 10s, without waiting for them
+        for _ in 0..10 {
+            // The timeout and the delay before the next attempt
+            while clock.sleepers() < 2 {
+                tokio::task::yield_now().await;
+            }
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(
+            retried.await.unwrap(),
+            Err(RetryError::TimedOut {
+                timeout: Duration::from_secs(10),
+                last_error: Some("unreachable"),
+            })
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_retry_blocking() {
+        let mut attempts = 0;
+        let result = retry_blocking(
+            &Backoff::constant(MS),
+            Duration::from_secs(10),
+            on_error,
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    Err("not ready")
+                } else {
+                    Ok(attempts)
+                }
+            },
+        );
+        assert_eq!(result, Ok(3));
+
+        let start = Instant::now();
+        let result = retry_blocking::<(), _, _, _>(
+            &Backoff::constant(Duration::from_secs(1)),
+            20 * MS,
+            on_error,
+            || Err("not ready"),
+        );
+        assert_eq!(
+            result,
+            Err(RetryError::TimedOut {
+                timeout: 20 * MS,
+                last_error: Some("not ready"),
+            })
+        );
+        // The last delay is cut short by the timeout
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: Apache 2.0
+pragma solidity ^0.8.28;
+
+import {Ownable} from "@openzeppelin/contracts/access/Ownable.sol";
+
+/**
+ * @title ConsensusParams
+ * @dev Consensus-level parameters governed on-chain, read by the nodes at each epoch boundary
+ * @dev A zero value leaves the parameter to the local configuration of each node
+ */
+contract ConsensusParams is Ownable {
+    struct Params {
+        /// @dev Minimum time between two blocks, in milliseconds
+        uint64 minBlockTimeMs;
+        /// @dev Maximum size of the SSZ-encoded execution payload of a block, in bytes
+        uint64 maxPayloadBytes;
+        /// @dev Number of blocks between two reads of the parameters
+        uint64 epochLength;
+    }
+
+    // State variables
+    Params private _params;
+
+    constructor() Ownable(_msgSender()) {}
+
+    // Events
+    event ParamsUpdated(Params oldParams, Params newParams);
+
+    /**
+     * @dev Replace the consensus parameters, which take effect at the next epoch boundary
+     * @param newParams The new parameters
+     */
+    function setParams(Params calldata newParams) external onlyOwner {
+        Params memory oldParams = _params;
+        _params = newParams;
+        emit ParamsUpdated(oldParams, newParams);
+    }
+
+    /**
+     * @dev Get the consensus parameters
+     * @return params The current parameters
+     */
+    function getParams() external view returns (Params memory params) {
+        return _params;
+    }
+}
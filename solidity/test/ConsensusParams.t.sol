@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: Apache 2.0
+pragma solidity ^0.8.28;
+
+import {Test} from "forge-std/Test.sol";
+import {ConsensusParams} from "../src/ConsensusParams.sol";
+import {Ownable} from "@openzeppelin/contracts/access/Ownable.sol";
+
+contract ConsensusParamsTest is Test {
+    ConsensusParams internal consensusParams;
+
+    address internal constant NON_OWNER = address(0xBEEF);
+
+    event ParamsUpdated(ConsensusParams.Params oldParams, ConsensusParams.Params newParams);
+
+    function setUp() public {
+        consensusParams = new ConsensusParams();
+    }
+
+    function testParamsAreUnsetByDefault() public view {
+        ConsensusParams.Params memory params = consensusParams.getParams();
+        assertEq(params.minBlockTimeMs, 0);
+        assertEq(params.maxPayloadBytes, 0);
+        assertEq(params.epochLength, 0);
+    }
+
+    function testOwnerCanSetParams() public {
+        ConsensusParams.Params memory oldParams = consensusParams.getParams();
+        ConsensusParams.Params memory newParams =
+            ConsensusParams.Params({minBlockTimeMs: 500, maxPayloadBytes: 2_000_000, epochLength: 100});
+
+        vm.expectEmit(false, false, false, true);
+        emit ParamsUpdated(oldParams, newParams);
+        consensusParams.setParams(newParams);
+
+        ConsensusParams.Params memory params = consensusParams.getParams();
+        assertEq(params.minBlockTimeMs, 500);
+        assertEq(params.maxPayloadBytes, 2_000_000);
+        assertEq(params.epochLength, 100);
+    }
+
+    function testNonOwnerCannotSetParams() public {
+        ConsensusParams.Params memory newParams =
+            ConsensusParams.Params({minBlockTimeMs: 500, maxPayloadBytes: 0, epochLength: 0});
+
+        vm.expectRevert(abi.encodeWithSelector(Ownable.OwnableUnauthorizedAccount.selector, NON_OWNER));
+        vm.prank(NON_OWNER);
+        consensusParams.setParams(newParams);
+    }
+}
@@ -0,0 +1,95 @@
+//! Events of the node event log.
+
+use std::fs;
+use std::path::PathBuf;
+
+use color_eyre::eyre::{Context, Result};
+use serde::Deserialize;
+
+/// Line of the event log
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Entry {
+    pub timestamp_ms: u64,
+    pub node: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Events relevant to the specification. Values are identified by their id,
+/// and proposers by their address.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ConsensusReady {
+        height: u64,
+    },
+    StartedRound {
+        height: u64,
+        round: i64,
+        proposer: String,
+    },
+    GetValue {
+        height: u64,
+        round: i64,
+        value_id: String,
+    },
+    ProposalReceived {
+        height: u64,
+        round: i64,
+        proposer: String,
+        value_id: String,
+        valid: bool,
+    },
+    Decided {
+        height: u64,
+        round: i64,
+        value_id: String,
+    },
+    ProcessSyncedValue {
+        height: u64,
+        round: i64,
+        proposer: String,
+        value_id: String,
+        valid: bool,
+    },
+    GetDecidedValue {
+        height: u64,
+        found: bool,
+    },
+    /// Events which are not modelled by the specification, e.g. forkchoice updates
+    #[serde(other)]
+    Other,
+}
+
+/// Reads and merges the given event logs, ordering the events by timestamp.
+///
+/// The last line of a log is ignored if it is incomplete, as happens when a node
+/// crashes while writing an event.
+pub fn read_event_logs(paths: &[PathBuf]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+
+    for path in paths {
+        let content = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read event log {}", path.display()))?;
+
+        let mut lines: Vec<_> = content.lines().collect();
+        if !content.is_empty() && !content.ends_with('\n') {
+            lines.pop();
+        }
+
+        for (index, line) in lines.into_iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: Entry = serde_json::from_str(line)
+                .wrap_err_with(|| format!("Invalid event at {}:{}", path.display(), index + 1))?;
+            entries.push(entry);
+        }
+    }
+
+    // Stable sort, so that events of a node with the same timestamp keep their order
+    entries.sort_by_key(|entry| entry.timestamp_ms);
+
+    Ok(entries)
+}
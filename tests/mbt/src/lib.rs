@@ -0,0 +1,19 @@
+//! Model-based testing support for the Emerald Quint specification in `specs/`.
+//!
+//! Converts the event logs written by Emerald nodes (see the `event_log` option of the
+//! Emerald config) into [ITF] traces of the `emerald` Quint module, so that executions of
+//! real networks can be checked against the specification.
+//!
+//! The logs of all the nodes of a network are merged by timestamp and replayed through
+//! the transitions of the specification. Each consensus event yields one state of the
+//! trace, whose `choreo::s` variable holds the local state of every node and whose
+//! `extensions.action_taken` field records the action that led to it. Process restarts
+//! and round timeouts, which are not logged as such, are inferred from the events.
+//!
+//! [ITF]: https://apalache-mc.org/docs/adr/015adr-trace.html
+
+pub mod event;
+pub mod trace;
+
+pub use event::{read_event_logs, Entry, Event};
+pub use trace::{to_itf_trace, Replay};
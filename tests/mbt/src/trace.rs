@@ -0,0 +1,653 @@
+//! Replay of the event logs through the transitions of the specification.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use color_eyre::eyre::{eyre, Result};
+use serde_json::{json, Value};
+
+use crate::event::{Entry, Event};
+
+type Node = String;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Uninitialized,
+    Ready,
+    Working,
+    Syncing,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Proposal {
+    height: u64,
+    round: i64,
+    proposer: Node,
+    payload: u64,
+}
+
+#[derive(Clone, Debug)]
+struct LocalState {
+    phase: Phase,
+    consensus_height: u64,
+    consensus_round: i64,
+    last_decided_height: u64,
+    last_decided_payload: Option<u64>,
+    proposals: BTreeSet<Proposal>,
+}
+
+impl LocalState {
+    fn initial() -> Self {
+        Self {
+            phase: Phase::Uninitialized,
+            consensus_height: 0,
+            consensus_round: 0,
+            last_decided_height: 0,
+            last_decided_payload: None,
+            proposals: BTreeSet::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum FailureMode {
+    ProcessRestart,
+    ConsensusTimeout,
+}
+
+#[derive(Clone, Debug)]
+enum Action {
+    Init,
+    ConsensusReady {
+        node: Node,
+    },
+    StartedRound {
+        node: Node,
+        height: u64,
+        round: i64,
+        proposer: Node,
+    },
+    GetValue {
+        node: Node,
+        proposal: Proposal,
+    },
+    ReceivedProposal {
+        node: Node,
+        proposal: Proposal,
+    },
+    Decided {
+        node: Node,
+        proposal: Proposal,
+    },
+    ProcessSyncedValue {
+        node: Node,
+        proposal: Proposal,
+    },
+    GetDecidedValue {
+        node: Node,
+        height: u64,
+        proposal: Option<Proposal>,
+    },
+    Failure {
+        node: Node,
+        mode: FailureMode,
+        height: u64,
+    },
+}
+
+/// Global state of the specification, rebuilt from the events of all nodes
+pub struct Replay {
+    system: BTreeMap<Node, LocalState>,
+    /// Round timeouts of each node, as `(height, round)`
+    timeouts: BTreeMap<Node, BTreeSet<(u64, i64)>>,
+    all_proposals: BTreeSet<Proposal>,
+    decided_proposals: BTreeSet<Proposal>,
+    last_decided_height: BTreeMap<Node, u64>,
+    failures: BTreeSet<(Node, u64, FailureMode)>,
+    action_taken: Action,
+
+    /// Node names of the proposers, by address
+    proposers: HashMap<String, Node>,
+    /// Payloads standing for the value ids, numbered in order of appearance
+    payloads: HashMap<String, u64>,
+    states: Vec<Value>,
+}
+
+impl Replay {
+    /// Starts a replay of the given nodes, in their initial state.
+    ///
+    /// `proposers` maps the addresses of the validators to the names of their nodes,
+    /// unknown proposers are named after their address.
+    pub fn new(nodes: impl IntoIterator<Item = Node>, proposers: HashMap<String, Node>) -> Self {
+        let system: BTreeMap<_, _> = nodes
+            .into_iter()
+            .map(|node| (node, LocalState::initial()))
+            .collect();
+
+        let mut replay = Self {
+            timeouts: system
+                .keys()
+                .map(|n| (n.clone(), BTreeSet::new()))
+                .collect(),
+            last_decided_height: system.keys().map(|n| (n.clone(), 0)).collect(),
+            system,
+            all_proposals: BTreeSet::new(),
+            decided_proposals: BTreeSet::new(),
+            failures: BTreeSet::new(),
+            action_taken: Action::Init,
+            proposers: proposers
+                .into_iter()
+                .map(|(address, node)| (normalize_address(&address), node))
+                .collect(),
+            payloads: HashMap::new(),
+            states: Vec::new(),
+        };
+
+        replay.record(Action::Init);
+        replay
+    }
+
+    /// Applies an event, recording one state per action of the specification it stands for.
+    pub fn apply(&mut self, entry: &Entry) -> Result<()> {
+        let node = &entry.node;
+        let mut s = self
+            .system
+            .get(node)
+            .cloned()
+            .ok_or_else(|| eyre!("Unknown node `{node}`"))?;
+
+        match &entry.event {
+            Event::ConsensusReady { height } => {
+                // The node was already running, so its process must have restarted
+                if s.phase != Phase::Uninitialized {
+                    s = LocalState {
+                        proposals: s.proposals,
+                        ..LocalState::initial()
+                    };
+                    self.timeouts.insert(node.clone(), BTreeSet::new());
+                    self.fail(node, s.clone(), FailureMode::ProcessRestart)?;
+                }
+
+                let last_decided_height = height.saturating_sub(1);
+                s.consensus_height = *height;
+                s.consensus_round = 0;
+                s.last_decided_height = last_decided_height;
+                s.last_decided_payload = self.decision(last_decided_height).map(|p| p.payload);
+                s.phase = Phase::Ready;
+
+                self.transition(node, s, Action::ConsensusReady { node: node.clone() });
+            }
+
+            Event::StartedRound {
+                height,
+                round,
+                proposer,
+            } => {
+                // A new round of the same height follows a timeout of the previous one
+                if s.phase == Phase::Working
+                    && s.consensus_height == *height
+                    && s.consensus_round < *round
+                {
+                    self.timeouts
+                        .entry(node.clone())
+                        .or_default()
+                        .insert((s.consensus_height, s.consensus_round));
+                    self.fail(node, s.clone(), FailureMode::ConsensusTimeout)?;
+                }
+
+                s.phase = Phase::Working;
+                s.consensus_height = *height;
+                s.consensus_round = *round;
+
+                let action = Action::StartedRound {
+                    node: node.clone(),
+                    height: *height,
+                    round: *round,
+                    proposer: self.proposer(proposer),
+                };
+                self.transition(node, s, action);
+            }
+
+            Event::GetValue {
+                height,
+                round,
+                value_id,
+            } => {
+                let proposal = Proposal {
+                    height: *height,
+                    round: *round,
+                    proposer: node.clone(),
+                    payload: self.payload(value_id),
+                };
+
+                s.proposals.insert(proposal.clone());
+                self.all_proposals.insert(proposal.clone());
+
+                let action = Action::GetValue {
+                    node: node.clone(),
+                    proposal,
+                };
+                self.transition(node, s, action);
+            }
+
+            Event::ProposalReceived {
+                height,
+                round,
+                proposer,
+                value_id,
+                valid,
+            } => {
+                // Invalid proposals are not modelled by the specification
+                if !valid {
+                    return Ok(());
+                }
+
+                let proposal = Proposal {
+                    height: *height,
+                    round: *round,
+                    proposer: self.proposer(proposer),
+                    payload: self.payload(value_id),
+                };
+                s.proposals.insert(proposal.clone());
+
+                let action = Action::ReceivedProposal {
+                    node: node.clone(),
+                    proposal,
+                };
+                self.transition(node, s, action);
+            }
+
+            Event::Decided {
+                height,
+                round,
+                value_id,
+            } => {
+                let payload = self.payload(value_id);
+                let proposal = s
+                    .proposals
+                    .iter()
+                    .chain(&self.all_proposals)
+                    .find(|p| p.height == *height && p.round == *round && p.payload == payload)
+                    .cloned()
+                    .ok_or_else(|| {
+                        eyre!("Node `{node}` decided value {value_id} at height {height} round {round}, which was never proposed")
+                    })?;
+
+                s.phase = Phase::Ready;
+                s.consensus_height = height + 1;
+                s.consensus_round = 0;
+                s.last_decided_height = *height;
+                s.last_decided_payload = Some(payload);
+
+                self.decided_proposals.insert(proposal.clone());
+                self.last_decided_height.insert(node.clone(), *height);
+
+                let action = Action::Decided {
+                    node: node.clone(),
+                    proposal,
+                };
+                self.transition(node, s, action);
+            }
+
+            Event::ProcessSyncedValue {
+                height,
+                round,
+                proposer,
+                value_id,
+                valid,
+            } => {
+                if !valid {
+                    return Ok(());
+                }
+
+                let proposal = Proposal {
+                    height: *height,
+                    round: *round,
+                    proposer: self.proposer(proposer),
+                    payload: self.payload(value_id),
+                };
+                s.phase = Phase::Syncing;
+                s.proposals.insert(proposal.clone());
+
+                let action = Action::ProcessSyncedValue {
+                    node: node.clone(),
+                    proposal,
+                };
+                self.transition(node, s, action);
+            }
+
+            Event::GetDecidedValue { height, found } => {
+                let proposal = self.decision(*height).filter(|_| *found).cloned();
+
+                let action = Action::GetDecidedValue {
+                    node: node.clone(),
+                    height: *height,
+                    proposal,
+                };
+                self.transition(node, s, action);
+            }
+
+            Event::Other => {}
+        }
+
+        Ok(())
+    }
+
+    /// The states of the specification recorded so far, in ITF format
+    pub fn states(&self) -> &[Value] {
+        &self.states
+    }
+
+    fn transition(&mut self, node: &Node, state: LocalState, action: Action) {
+        self.system.insert(node.clone(), state);
+        self.record(action);
+    }
+
+    fn fail(&mut self, node: &Node, state: LocalState, mode: FailureMode) -> Result<()> {
+        let height = self
+            .system
+            .get(node)
+            .map(|s| s.consensus_height)
+            .ok_or_else(|| eyre!("Unknown node `{node}`"))?;
+
+        self.failures.insert((node.clone(), height, mode));
+        self.transition(
+            node,
+            state,
+            Action::Failure {
+                node: node.clone(),
+                mode,
+                height,
+            },
+        );
+        Ok(())
+    }
+
+    fn record(&mut self, action: Action) {
+        self.action_taken = action;
+        let state = json!({
+            "#meta": { "index": self.states.len() },
+            "choreo::s": self.global_context(),
+        });
+        self.states.push(state);
+    }
+
+    fn decision(&self, height: u64) -> Option<&Proposal> {
+        self.decided_proposals.iter().find(|p| p.height == height)
+    }
+
+    fn proposer(&self, address: &str) -> Node {
+        let address = normalize_address(address);
+        self.proposers.get(&address).cloned().unwrap_or(address)
+    }
+
+    fn payload(&mut self, value_id: &str) -> u64 {
+        let next = self.payloads.len() as u64;
+        *self.payloads.entry(value_id.to_string()).or_insert(next)
+    }
+
+    fn global_context(&self) -> Value {
+        json!({
+            "system": map(self.system.iter().map(|(node, s)| (json!(node), local_state(node, s)))),
+            "messages": map(self.system.keys().map(|node| (json!(node), set([])))),
+            "events": map(self.timeouts.iter().map(|(node, timeouts)| {
+                (
+                    json!(node),
+                    set(timeouts.iter().map(|(height, round)| {
+                        variant("Timeout", json!({ "height": int(*height), "round": int(*round) }))
+                    })),
+                )
+            })),
+            "extensions": {
+                "action_taken": action(&self.action_taken),
+                "all_proposals": set(self.all_proposals.iter().map(proposal)),
+                "decided_proposals": set(self.decided_proposals.iter().map(proposal)),
+                "last_decided_height": map(
+                    self.last_decided_height.iter().map(|(node, height)| (json!(node), int(*height)))
+                ),
+                "failures": set(self.failures.iter().map(|(node, height, mode)| {
+                    json!({ "node": node, "height": int(*height), "mode": failure_mode(*mode) })
+                })),
+            },
+        })
+    }
+}
+
+/// Converts the merged event logs of a network into an ITF trace.
+pub fn to_itf_trace(entries: &[Entry], proposers: HashMap<String, Node>) -> Result<Value> {
+    let nodes: BTreeSet<_> = entries.iter().map(|entry| entry.node.clone()).collect();
+
+    let mut replay = Replay::new(nodes, proposers);
+    for entry in entries {
+        replay.apply(entry)?;
+    }
+
+    Ok(json!({
+        "#meta": {
+            "format": "ITF",
+            "format-description": "https://apalache-mc.org/docs/adr/015adr-trace.html",
+            "source": "emerald.qnt",
+            "description": "Converted from Emerald node event logs",
+        },
+        "vars": ["choreo::s"],
+        "states": replay.states(),
+    }))
+}
+
+/// Addresses are logged in upper-case hex, without prefix
+fn normalize_address(address: &str) -> String {
+    address.trim_start_matches("0x").to_uppercase()
+}
+
+fn local_state(node: &Node, s: &LocalState) -> Value {
+    let phase = match s.phase {
+        Phase::Uninitialized => "Uninitialized",
+        Phase::Ready => "Ready",
+        Phase::Working => "Working",
+        Phase::Syncing => "Syncing",
+    };
+
+    json!({
+        "process_id": node,
+        "phase": variant(phase, unit()),
+        "consensus_height": int(s.consensus_height),
+        "consensus_round": int(s.consensus_round),
+        "last_decided_height": int(s.last_decided_height),
+        "last_decided_payload": option(s.last_decided_payload.map(int)),
+        "proposals": set(s.proposals.iter().map(proposal)),
+    })
+}
+
+fn action(action: &Action) -> Value {
+    match action {
+        Action::Init => variant("InitAction", unit()),
+        Action::ConsensusReady { node } => variant("ConsensusReadyAction", json!({ "node": node })),
+        Action::StartedRound {
+            node,
+            height,
+            round,
+            proposer,
+        } => variant(
+            "StartedRoundAction",
+            json!({ "node": node, "height": int(*height), "round": int(*round), "proposer": proposer }),
+        ),
+        Action::GetValue { node, proposal: p } => variant(
+            "GetValueAction",
+            json!({ "node": node, "height": int(p.height), "round": int(p.round), "proposal": proposal(p) }),
+        ),
+        Action::ReceivedProposal { node, proposal: p } => variant(
+            "ReceivedProposalAction",
+            json!({ "node": node, "proposal": proposal(p) }),
+        ),
+        Action::Decided { node, proposal: p } => variant(
+            "DecidedAction",
+            json!({ "node": node, "proposal": proposal(p) }),
+        ),
+        Action::ProcessSyncedValue { node, proposal: p } => variant(
+            "ProcessSyncedValueAction",
+            json!({ "node": node, "proposal": proposal(p) }),
+        ),
+        Action::GetDecidedValue {
+            node,
+            height,
+            proposal: p,
+        } => variant(
+            "GetDecidedValueAction",
+            json!({ "node": node, "height": int(*height), "proposal": option(p.as_ref().map(proposal)) }),
+        ),
+        Action::Failure { node, mode, height } => variant(
+            "Failure",
+            json!({ "node": node, "mode": failure_mode(*mode), "height": int(*height) }),
+        ),
+    }
+}
+
+fn failure_mode(mode: FailureMode) -> Value {
+    match mode {
+        FailureMode::ProcessRestart => variant("ProcessRestart", unit()),
+        FailureMode::ConsensusTimeout => variant("ConsensusTimeout", unit()),
+    }
+}
+
+fn proposal(p: &Proposal) -> Value {
+    json!({
+        "height": int(p.height),
+        "round": int(p.round),
+        "proposer": p.proposer,
+        "payload": int(p.payload),
+    })
+}
+
+fn int(n: impl ToString) -> Value {
+    json!({ "#bigint": n.to_string() })
+}
+
+fn unit() -> Value {
+    json!({ "#tup": [] })
+}
+
+fn variant(tag: &str, value: Value) -> Value {
+    json!({ "tag": tag, "value": value })
+}
+
+fn option(value: Option<Value>) -> Value {
+    match value {
+        Some(value) => variant("Some", value),
+        None => variant("None", unit()),
+    }
+}
+
+fn set(values: impl IntoIterator<Item = Value>) -> Value {
+    json!({ "#set": values.into_iter().collect::<Vec<_>>() })
+}
+
+fn map(entries: impl IntoIterator<Item = (Value, Value)>) -> Value {
+    json!({ "#map": entries.into_iter().map(|(k, v)| json!([k, v])).collect::<Vec<_>>() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp_ms: u64, node: &str, event: Value) -> Entry {
+        let mut line = event;
+        line["timestamp_ms"] = json!(timestamp_ms);
+        line["node"] = json!(node);
+        serde_json::from_value(line).unwrap()
+    }
+
+    #[test]
+    fn test_replay_two_nodes() {
+        let proposers = HashMap::from([("0xaa".to_string(), "node1".to_string())]);
+        let entries = [
+            entry(
+                1,
+                "node1",
+                json!({ "event": "consensus_ready", "height": 1 }),
+            ),
+            entry(
+                2,
+                "node2",
+                json!({ "event": "consensus_ready", "height": 1 }),
+            ),
+            entry(
+                3,
+                "node1",
+                json!({ "event": "started_round", "height": 1, "round": 0, "proposer": "AA" }),
+            ),
+            entry(
+                4,
+                "node2",
+                json!({ "event": "started_round", "height": 1, "round": 0, "proposer": "AA" }),
+            ),
+            entry(
+                5,
+                "node1",
+                json!({ "event": "get_value", "height": 1, "round": 0, "value_id": "v1" }),
+            ),
+            entry(
+                6,
+                "node2",
+                json!({ "event": "proposal_received", "height": 1, "round": 0, "proposer": "AA", "value_id": "v1", "valid": true }),
+            ),
+            entry(
+                7,
+                "node1",
+                json!({ "event": "forkchoice_updated", "height": 1, "head_block_hash": "0x01", "latest_valid_hash": "0x01" }),
+            ),
+            entry(
+                8,
+                "node1",
+                json!({ "event": "decided", "height": 1, "round": 0, "value_id": "v1", "block_hash": "0x01" }),
+            ),
+            entry(
+                9,
+                "node2",
+                json!({ "event": "decided", "height": 1, "round": 0, "value_id": "v1", "block_hash": "0x01" }),
+            ),
+            entry(
+                10,
+                "node2",
+                json!({ "event": "consensus_ready", "height": 2 }),
+            ),
+        ];
+
+        let trace = to_itf_trace(&entries, proposers).unwrap();
+        let states = trace["states"].as_array().unwrap();
+
+        // Init, 9 modelled events and the process restart inferred for node2
+        assert_eq!(states.len(), 11);
+
+        let restart = &states[9]["choreo::s"]["extensions"]["action_taken"];
+        assert_eq!(restart["tag"], "Failure");
+        assert_eq!(restart["value"]["mode"]["tag"], "ProcessRestart");
+
+        let last = &states[10]["choreo::s"];
+        let decided = &last["extensions"]["decided_proposals"]["#set"];
+        assert_eq!(decided.as_array().unwrap().len(), 1);
+        assert_eq!(decided[0]["proposer"], "node1");
+        assert_eq!(decided[0]["payload"]["#bigint"], "0");
+
+        let node2 = &last["system"]["#map"][1][1];
+        assert_eq!(node2["phase"]["tag"], "Ready");
+        assert_eq!(node2["consensus_height"]["#bigint"], "2");
+        assert_eq!(node2["last_decided_payload"]["value"]["#bigint"], "0");
+    }
+
+    #[test]
+    fn test_decided_value_must_be_proposed() {
+        let entries = [
+            entry(
+                1,
+                "node1",
+                json!({ "event": "consensus_ready", "height": 1 }),
+            ),
+            entry(
+                2,
+                "node1",
+                json!({ "event": "decided", "height": 1, "round": 0, "value_id": "v1" }),
+            ),
+        ];
+
+        assert!(to_itf_trace(&entries, HashMap::new()).is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::io::Result;
 fn main() -> Result<()> {
     let protos = &[
         "proto/consensus.proto",
+        "proto/envelope.proto",
         "proto/liveness.proto",
         "proto/sync.proto",
     ];
@@ -17,8 +17,30 @@ use crate::{
     ProposalPart, Value, ValueId, Vote,
 };
 
-#[derive(Copy, Clone, Debug)]
-pub struct ProtobufCodec;
+pub mod version;
+#[cfg(test)]
+mod wire_format;
+
+use version::{decode_envelope, encode_envelope, envelope_encoded_len, SharedPeerVersions};
+
+/// Codec of the messages of a node, sent in the version negotiated with its own peers
+#[derive(Clone, Debug, Default)]
+pub struct ProtobufCodec {
+    peer_versions: SharedPeerVersions,
+}
+
+impl ProtobufCodec {
+    /// Codec recording the versions of the peers in `peer_versions`, shared by all the
+    /// codecs of the node
+    pub fn new(peer_versions: SharedPeerVersions) -> Self {
+        Self { peer_versions }
+    }
+
+    /// Wraps an encoded message in an envelope of the version negotiated with the peers
+    fn envelope(&self, payload: Vec<u8>) -> Bytes {
+        encode_envelope(self.peer_versions.negotiated(), payload)
+    }
+}
 
 impl Codec<Value> for ProtobufCodec {
     type Error = ProtoError;
@@ -66,7 +88,7 @@ impl Codec<SignedConsensusMsg<EmeraldContext>> for ProtobufCodec {
     type Error = ProtoError;
 
     fn decode(&self, bytes: Bytes) -> Result<SignedConsensusMsg<EmeraldContext>, Self::Error> {
-        let proto = proto::SignedMessage::decode(bytes.as_ref())?;
+        let proto = proto::SignedMessage::decode(decode_envelope(bytes)?)?;
 
         let signature = proto
             .signature
@@ -100,7 +122,7 @@ impl Codec<SignedConsensusMsg<EmeraldContext>> for ProtobufCodec {
                     )),
                     signature: Some(encode_signature(&vote.signature)),
                 };
-                Ok(Bytes::from(proto.encode_to_vec()))
+                Ok(self.envelope(proto.encode_to_vec()))
             }
             SignedConsensusMsg::Proposal(proposal) => {
                 let proto = proto::SignedMessage {
@@ -109,7 +131,7 @@ impl Codec<SignedConsensusMsg<EmeraldContext>> for ProtobufCodec {
                     )),
                     signature: Some(encode_signature(&proposal.signature)),
                 };
-                Ok(Bytes::from(proto.encode_to_vec()))
+                Ok(self.envelope(proto.encode_to_vec()))
             }
         }
     }
@@ -119,7 +141,7 @@ impl Codec<StreamMessage<ProposalPart>> for ProtobufCodec {
     type Error = ProtoError;
 
     fn decode(&self, bytes: Bytes) -> Result<StreamMessage<ProposalPart>, Self::Error> {
-        let proto = proto::StreamMessage::decode(bytes.as_ref())?;
+        let proto = proto::StreamMessage::decode(decode_envelope(bytes)?)?;
 
         let proto_content = proto
             .content
@@ -151,7 +173,7 @@ impl Codec<StreamMessage<ProposalPart>> for ProtobufCodec {
             },
         };
 
-        Ok(Bytes::from(proto.encode_to_vec()))
+        Ok(self.envelope(proto.encode_to_vec()))
     }
 }
 
@@ -197,14 +219,19 @@ impl Codec<sync::Status<EmeraldContext>> for ProtobufCodec {
     type Error = ProtoError;
 
     fn decode(&self, bytes: Bytes) -> Result<sync::Status<EmeraldContext>, Self::Error> {
-        let proto = proto::Status::decode(bytes.as_ref())?;
+        let proto = proto::Status::decode(decode_envelope(bytes)?)?;
 
         let proto_peer_id = proto
             .peer_id
             .ok_or_else(|| ProtoError::missing_field::<proto::Status>("peer_id"))?;
+        let peer_id = PeerId::from_bytes(proto_peer_id.id.as_ref())
+            .map_err(|e| ProtoError::Other(format!("Invalid peer id: {e}")))?;
+
+        // The status is the handshake metadata from which the wire version is negotiated
+        self.peer_versions.record(peer_id, proto.wire_version);
 
         Ok(sync::Status {
-            peer_id: PeerId::from_bytes(proto_peer_id.id.as_ref()).unwrap(),
+            peer_id,
             tip_height: Height::new(proto.height),
             history_min_height: Height::new(proto.earliest_height),
         })
@@ -217,8 +244,10 @@ impl Codec<sync::Status<EmeraldContext>> for ProtobufCodec {
             }),
             height: msg.tip_height.as_u64(),
             earliest_height: msg.history_min_height.as_u64(),
+            wire_version: version::CURRENT_VERSION,
         };
 
+        // Always sent bare, so that peers of all versions learn the versions supported here
         Ok(Bytes::from(proto.encode_to_vec()))
     }
 }
@@ -227,7 +256,7 @@ impl Codec<sync::Request<EmeraldContext>> for ProtobufCodec {
     type Error = ProtoError;
 
     fn decode(&self, bytes: Bytes) -> Result<sync::Request<EmeraldContext>, Self::Error> {
-        let proto = proto::SyncRequest::decode(bytes.as_ref())?;
+        let proto = proto::SyncRequest::decode(decode_envelope(bytes)?)?;
         let request = proto
             .request
             .ok_or_else(|| ProtoError::missing_field::<proto::SyncRequest>("request"))?;
@@ -255,7 +284,7 @@ impl Codec<sync::Request<EmeraldContext>> for ProtobufCodec {
             },
         };
 
-        Ok(Bytes::from(proto.encode_to_vec()))
+        Ok(self.envelope(proto.encode_to_vec()))
     }
 }
 
@@ -263,18 +292,18 @@ impl Codec<sync::Response<EmeraldContext>> for ProtobufCodec {
     type Error = ProtoError;
 
     fn decode(&self, bytes: Bytes) -> Result<sync::Response<EmeraldContext>, Self::Error> {
-        decode_sync_response(proto::SyncResponse::decode(bytes)?)
+        decode_sync_response(proto::SyncResponse::decode(decode_envelope(bytes)?)?)
     }
 
     fn encode(&self, response: &sync::Response<EmeraldContext>) -> Result<Bytes, Self::Error> {
-        encode_sync_response(response).map(|proto| proto.encode_to_vec().into())
+        encode_sync_response(response).map(|proto| self.envelope(proto.encode_to_vec()))
     }
 }
 
 impl HasEncodedLen<sync::Response<EmeraldContext>> for ProtobufCodec {
     fn encoded_len(&self, response: &sync::Response<EmeraldContext>) -> Result<usize, ProtoError> {
         let proto = encode_sync_response(response)?;
-        Ok(proto.encoded_len())
+        Ok(envelope_encoded_len(proto.encoded_len()))
     }
 }
 
@@ -592,7 +621,7 @@ impl Codec<LivenessMsg<EmeraldContext>> for ProtobufCodec {
     type Error = ProtoError;
 
     fn decode(&self, bytes: Bytes) -> Result<LivenessMsg<EmeraldContext>, Self::Error> {
-        let msg = proto::LivenessMessage::decode(bytes.as_ref())?;
+        let msg = proto::LivenessMessage::decode(decode_envelope(bytes)?)?;
         match msg.message {
             Some(proto::liveness_message::Message::Vote(vote)) => {
                 Ok(LivenessMsg::Vote(decode_vote(vote)?))
@@ -613,7 +642,7 @@ impl Codec<LivenessMsg<EmeraldContext>> for ProtobufCodec {
         match msg {
             LivenessMsg::Vote(vote) => {
                 let message = encode_vote(vote)?;
-                Ok(Bytes::from(
+                Ok(self.envelope(
                     proto::LivenessMessage {
                         message: Some(proto::liveness_message::Message::Vote(message)),
                     }
@@ -622,7 +651,7 @@ impl Codec<LivenessMsg<EmeraldContext>> for ProtobufCodec {
             }
             LivenessMsg::PolkaCertificate(cert) => {
                 let message = encode_polka_certificate(cert)?;
-                Ok(Bytes::from(
+                Ok(self.envelope(
                     proto::LivenessMessage {
                         message: Some(proto::liveness_message::Message::PolkaCertificate(message)),
                     }
@@ -631,7 +660,7 @@ impl Codec<LivenessMsg<EmeraldContext>> for ProtobufCodec {
             }
             LivenessMsg::SkipRoundCertificate(cert) => {
                 let message = encode_round_certificate(cert)?;
-                Ok(Bytes::from(
+                Ok(self.envelope(
                     proto::LivenessMessage {
                         message: Some(proto::liveness_message::Message::RoundCertificate(message)),
                     }
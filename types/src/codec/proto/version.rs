@@ -0,0 +1,241 @@
+//! Versions of the wire format of the messages exchanged with peers.
+//!
+//! Up to [`LEGACY_VERSION`], messages were sent as bare protobuf messages. Since version 1,
+//! each message is wrapped in a [`proto::Envelope`] carrying its version, so that the
+//! encoding of a message can change without breaking the nodes running an older release.
+//!
+//! Nodes advertise the latest version they support in their sync status, which is always
+//! sent as a bare message so that all releases understand it. Each node records the versions
+//! of its own peers in the [`SharedPeerVersions`] of its codec. Messages are gossiped to all
+//! the peers at once, so they are sent in the latest version supported by all the peers of
+//! the node which advertised one, and as bare messages until a peer did. Decoding accepts
+//! any version from [`MIN_SUPPORTED_VERSION`]: a version is kept for at least one release
+//! cycle after the next one is introduced.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use bytes::Bytes;
+use malachitebft_proto::Error as ProtoError;
+use malachitebft_sync::PeerId;
+use prost::Message;
+
+use crate::proto;
+
+/// Version of the bare messages, sent without envelope
+pub const LEGACY_VERSION: u32 = 0;
+
+/// Latest version supported by this release
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Version from which the peers decode the requests for the missing parts of proposal
+/// streams, and the parts sent again in reply, which older releases reject
+pub const PARTS_RECOVERY_VERSION: u32 = 2;
+
+/// Oldest version still decoded by this release
+pub const MIN_SUPPORTED_VERSION: u32 = LEGACY_VERSION;
+
+/// Time after which the version advertised by a peer is forgotten, unless advertised again
+const PEER_VERSION_TTL: Duration = Duration::from_secs(60);
+
+/// Latest versions advertised by the peers
+#[derive(Debug, Default)]
+pub struct PeerVersions {
+    peers: BTreeMap<PeerId, (u32, Instant)>,
+}
+
+impl PeerVersions {
+    pub const fn new() -> Self {
+        Self {
+            peers: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, peer_id: PeerId, version: u32, now: Instant) {
+        self.peers.insert(peer_id, (version, now));
+        self.peers
+            .retain(|_, (_, seen)| now.saturating_duration_since(*seen) < PEER_VERSION_TTL);
+    }
+
+    /// Latest version supported by this node and the given peer, if it advertised
+    /// its version recently
+    pub fn version_of(&self, peer_id: &PeerId, now: Instant) -> Option<u32> {
+        self.peers
+            .get(peer_id)
+            .filter(|(_, seen)| now.saturating_duration_since(*seen) < PEER_VERSION_TTL)
+            .map(|(version, _)| (*version).min(CURRENT_VERSION))
+    }
+
+    /// Latest version supported by this node and all the peers seen recently,
+    /// or [`LEGACY_VERSION`] if no peer advertised its version.
+    pub fn negotiated(&self, now: Instant) -> u32 {
+        self.peers
+            .values()
+            .filter(|(_, seen)| now.saturating_duration_since(*seen) < PEER_VERSION_TTL)
+            .map(|(version, _)| (*version).min(CURRENT_VERSION))
+            .min()
+            .unwrap_or(LEGACY_VERSION)
+    }
+}
+
+/// Versions advertised by the peers of a node, shared by its codecs and its application
+#[derive(Clone, Debug, Default)]
+pub struct SharedPeerVersions(Arc<Mutex<PeerVersions>>);
+
+impl SharedPeerVersions {
+    /// Records the latest version supported by a peer, as advertised in its status
+    pub fn record(&self, peer_id: PeerId, version: u32) {
+        self.0.lock().expect("peer versions lock poisoned").record(
+            peer_id,
+            version,
+            Instant::now(),
+        );
+    }
+
+    /// Version in which the messages gossiped to all the peers are currently sent
+    pub fn negotiated(&self) -> u32 {
+        self.0
+            .lock()
+            .expect("peer versions lock poisoned")
+            .negotiated(Instant::now())
+    }
+
+    /// Latest version supported by this node and the given peer, if it advertised one
+    pub fn version_of(&self, peer_id: &PeerId) -> Option<u32> {
+        self.0
+            .lock()
+            .expect("peer versions lock poisoned")
+            .version_of(peer_id, Instant::now())
+    }
+}
+
+/// Wraps an encoded message in an envelope of the given version
+pub fn encode_envelope(wire_version: u32, payload: Vec<u8>) -> Bytes {
+    wrap(wire_version, payload)
+}
+
+/// Maximum length of a message of `payload_len` bytes once wrapped by [`encode_envelope`].
+///
+/// This is the length in the current version, whose envelope is the largest, so that the
+/// bound holds whatever the version negotiated when the message is eventually encoded.
+pub fn envelope_encoded_len(payload_len: usize) -> usize {
+    wrapped_len(CURRENT_VERSION, payload_len)
+}
+
+fn wrap(wire_version: u32, payload: Vec<u8>) -> Bytes {
+    if wire_version == LEGACY_VERSION {
+        return Bytes::from(payload);
+    }
+
+    Bytes::from(
+        proto::Envelope {
+            wire_version,
+            payload: Bytes::from(payload),
+        }
+        .encode_to_vec(),
+    )
+}
+
+fn wrapped_len(wire_version: u32, payload_len: usize) -> usize {
+    use prost::encoding::{encoded_len_varint, key_len, uint32};
+
+    if wire_version == LEGACY_VERSION {
+        return payload_len;
+    }
+
+    uint32::encoded_len(100, &wire_version)
+        + key_len(101)
+        + encoded_len_varint(payload_len as u64)
+        + payload_len
+}
+
+/// Returns the encoded message wrapped in an envelope, or the message itself if bare.
+///
+/// All the supported versions share the same message encoding so far. Once a version
+/// changes it, the decoders dispatch on the version of the envelope.
+pub fn decode_envelope(bytes: Bytes) -> Result<Bytes, ProtoError> {
+    let envelope = proto::Envelope::decode(bytes.as_ref())?;
+
+    match envelope.wire_version {
+        LEGACY_VERSION => Ok(bytes),
+        version if (MIN_SUPPORTED_VERSION..=CURRENT_VERSION).contains(&version) => {
+            Ok(envelope.payload)
+        }
+        version => Err(ProtoError::Other(format!(
+            "Unsupported wire version {version}, supported versions are \
+             {MIN_SUPPORTED_VERSION} to {CURRENT_VERSION}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(byte: u8) -> PeerId {
+        // Identity multihash of a single byte
+        PeerId::from_bytes(&[0, 1, byte]).unwrap()
+    }
+
+    #[test]
+    fn test_negotiated_version() {
+        let mut versions = PeerVersions::new();
+        let now = Instant::now();
+        assert_eq!(versions.negotiated(now), LEGACY_VERSION);
+        assert_eq!(versions.version_of(&peer(1), now), None);
+
+        versions.record(peer(1), CURRENT_VERSION + 1, now);
+        assert_eq!(versions.version_of(&peer(1), now), Some(CURRENT_VERSION));
+        assert_eq!(versions.negotiated(now), CURRENT_VERSION);
+
+        versions.record(peer(2), LEGACY_VERSION, now);
+        assert_eq!(versions.negotiated(now), LEGACY_VERSION);
+
+        // The legacy peer is forgotten once it stops advertising its version
+        let later = now + PEER_VERSION_TTL;
+        versions.record(peer(3), CURRENT_VERSION, later);
+        assert_eq!(versions.negotiated(later), CURRENT_VERSION);
+        assert_eq!(versions.version_of(&peer(2), later), None);
+    }
+
+    #[test]
+    fn test_peer_versions_are_per_node() {
+        let node1 = SharedPeerVersions::default();
+        let node2 = SharedPeerVersions::default();
+
+        node1.record(peer(1), CURRENT_VERSION);
+        node2.record(peer(2), LEGACY_VERSION);
+
+        assert_eq!(node1.negotiated(), CURRENT_VERSION);
+        assert_eq!(node2.negotiated(), LEGACY_VERSION);
+        assert_eq!(node1.clone().version_of(&peer(1)), Some(CURRENT_VERSION));
+        assert_eq!(node2.version_of(&peer(1)), None);
+    }
+
+    #[test]
+    fn test_decode_envelope() {
+        let payload = Bytes::from(
+            proto::Status {
+                peer_id: None,
+                height: 1,
+                earliest_height: 1,
+                wire_version: CURRENT_VERSION,
+            }
+            .encode_to_vec(),
+        );
+
+        // Bare messages are decoded as is
+        assert_eq!(decode_envelope(payload.clone()).unwrap(), payload);
+
+        for version in [LEGACY_VERSION, CURRENT_VERSION] {
+            let wrapped = wrap(version, payload.to_vec());
+            assert_eq!(wrapped.len(), wrapped_len(version, payload.len()));
+            assert!(wrapped.len() <= envelope_encoded_len(payload.len()));
+            assert_eq!(decode_envelope(wrapped).unwrap(), payload);
+        }
+
+        assert!(decode_envelope(wrap(CURRENT_VERSION + 1, payload.to_vec())).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+//! Round-trip properties of the protobuf codecs, and decoding of the vectors of
+//! `types/tests/vectors/wire_format.json`.
+//!
+//! Each vector is the encoding of a message as sent by a previous release, bare and in an
+//! envelope of version 1 for the messages exchanged with peers, or bare only for the
+//! messages sent without envelope and the values kept in the store. Both encodings must
+//! decode to a message encoded again to the same bare bytes, so that a change of the wire
+//! format, e.g. a renumbered field, breaks these tests rather than the upgrade of a network.
+
+use bytes::Bytes;
+use malachitebft_app::streaming::{StreamContent, StreamId, StreamMessage};
+use malachitebft_codec::Codec;
+use malachitebft_core_consensus::{ProposedValue, SignedConsensusMsg};
+use malachitebft_core_types::{
+    CommitCertificate, CommitSignature, NilOrVal, Round, SignedProposal, SignedVote, Validity,
+    VoteType,
+};
+use malachitebft_proto::Error as ProtoError;
+use malachitebft_sync::{self as sync, PeerId};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use prost::Message;
+use serde::Deserialize;
+
+use super::version::decode_envelope;
+use super::{decode_certificate, encode_certificate, ProtobufCodec};
+use crate::secp256k1::{PrivateKey, Signature};
+use crate::{
+    proto, Address, BlockHash, EmeraldContext, Height, PartsRequest, PayloadSummary, Proposal,
+    ProposalData, ProposalFin, ProposalInit, ProposalPart, RecoveredPart, Value, ValueId, Vote,
+};
+
+const VECTORS: &str = include_str!("../../../tests/vectors/wire_format.json");
+
+fn height() -> impl Strategy<Value = Height> {
+    any::<u64>().prop_map(Height::new)
+}
+
+fn round() -> impl Strategy<Value = Round> {
+    (0..1000u32).prop_map(Round::new)
+}
+
+fn pol_round() -> impl Strategy<Value = Round> {
+    prop_oneof![Just(Round::Nil), round()]
+}
+
+fn address() -> impl Strategy<Value = Address> {
+    any::<[u8; 20]>().prop_map(Address::new)
+}
+
+fn value_id() -> impl Strategy<Value = ValueId> {
+    any::<u64>().prop_map(ValueId::new)
+}
+
+fn value() -> impl Strategy<Value = Value> {
+    (any::<u64>(), vec(any::<u8>(), 0..64)).prop_map(|(value, extensions)| Value {
+        value,
+        extensions: Bytes::from(extensions),
+    })
+}
+
+fn signature() -> impl Strategy<Value = Signature> {
+    // Keys of bytes below 0x80 are lower than the order of the curve
+    (1..0x80u8, any::<[u8; 32]>()).prop_map(|(key, message)| {
+        PrivateKey::from_slice(&[key; 32])
+            .expect("valid private key")
+            .sign(&message)
+    })
+}
+
+fn vote() -> impl Strategy<Value = Vote> {
+    (
+        any::<bool>(),
+        height(),
+        round(),
+        proptest::option::of(value_id()),
+        address(),
+    )
+        .prop_map(
+            |(precommit, height, round, value, validator_address)| Vote {
+                typ: if precommit {
+                    VoteType::Precommit
+                } else {
+                    VoteType::Prevote
+                },
+                height,
+                round,
+                value: value.map_or(NilOrVal::Nil, NilOrVal::Val),
+                validator_address,
+                // Not part of the encoding of the vote
+                extension: None,
+            },
+        )
+}
+
+fn proposal() -> impl Strategy<Value = Proposal> {
+    (height(), round(), value(), pol_round(), address()).prop_map(
+        |(height, round, value, pol_round, validator_address)| {
+            Proposal::new(height, round, value, pol_round, validator_address)
+        },
+    )
+}
+
+fn proposal_part() -> impl Strategy<Value = ProposalPart> {
+    let payload =
+        (any::<[u8; 32]>(), any::<u64>(), signature()).prop_map(|(block_hash, len, signature)| {
+            PayloadSummary {
+                block_hash: BlockHash::from(block_hash),
+                len,
+                signature,
+            }
+        });
+    let init = (
+        height(),
+        round(),
+        pol_round(),
+        address(),
+        proptest::option::of(payload),
+    )
+        .prop_map(|(height, round, pol_round, proposer, payload)| {
+            ProposalPart::Init(ProposalInit {
+                height,
+                round,
+                pol_round,
+                proposer,
+                payload,
+            })
+        });
+    let data = vec(any::<u8>(), 0..256)
+        .prop_map(|bytes| ProposalPart::Data(ProposalData::new(Bytes::from(bytes))));
+    let fin = signature().prop_map(|signature| ProposalPart::Fin(ProposalFin::new(signature)));
+    let request = (
+        height(),
+        round(),
+        address(),
+        vec(any::<u8>(), 0..16),
+        vec(any::<u64>(), 0..8),
+        proptest::option::of(any::<u64>()),
+    )
+        .prop_map(
+            |(height, round, proposer, stream_id, sequences, from_sequence)| {
+                ProposalPart::Request(PartsRequest {
+                    height,
+                    round,
+                    proposer,
+                    stream_id: Bytes::from(stream_id),
+                    sequences,
+                    from_sequence,
+                })
+            },
+        );
+    let leaf = prop_oneof![init, data, fin, request];
+    let recovered = (
+        vec(any::<u8>(), 0..16),
+        any::<u64>(),
+        proptest::option::of(leaf.clone()),
+    )
+        .prop_map(|(stream_id, sequence, part)| {
+            ProposalPart::Recovered(RecoveredPart {
+                stream_id: Bytes::from(stream_id),
+                sequence,
+                part: part.map(Box::new),
+            })
+        });
+
+    prop_oneof![leaf, recovered]
+}
+
+fn certificate() -> impl Strategy<Value = CommitCertificate<EmeraldContext>> {
+    (
+        height(),
+        round(),
+        value_id(),
+        vec((address(), signature()), 0..4),
+    )
+        .prop_map(|(height, round, value_id, signatures)| CommitCertificate {
+            height,
+            round,
+            value_id,
+            commit_signatures: signatures
+                .into_iter()
+                .map(|(address, signature)| CommitSignature::new(address, signature))
+                .collect(),
+        })
+}
+
+fn roundtrip<T>(msg: &T) -> T
+where
+    ProtobufCodec: Codec<T, Error = ProtoError>,
+{
+    let bytes = ProtobufCodec::default()
+        .encode(msg)
+        .expect("encodable message");
+    ProtobufCodec::default()
+        .decode(bytes)
+        .expect("decodable message")
+}
+
+proptest! {
+    #[test]
+    fn test_value_roundtrip(value in value()) {
+        prop_assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_vote_roundtrip(vote in vote(), signature in signature()) {
+        let msg = SignedConsensusMsg::Vote(SignedVote::new(vote, signature));
+        prop_assert_eq!(roundtrip(&msg), msg);
+    }
+
+    #[test]
+    fn test_proposal_roundtrip(proposal in proposal(), signature in signature()) {
+        let msg = SignedConsensusMsg::Proposal(SignedProposal::new(proposal, signature));
+        prop_assert_eq!(roundtrip(&msg), msg);
+    }
+
+    #[test]
+    fn test_proposal_part_roundtrip(part in proposal_part()) {
+        prop_assert_eq!(roundtrip(&part), part);
+    }
+
+    #[test]
+    fn test_stream_message_roundtrip(
+        stream_id in vec(any::<u8>(), 0..16),
+        sequence in any::<u64>(),
+        part in proptest::option::of(proposal_part()),
+    ) {
+        let content = part.map_or(StreamContent::Fin, StreamContent::Data);
+        let msg = StreamMessage::new(StreamId::new(Bytes::from(stream_id)), sequence, content);
+        prop_assert_eq!(roundtrip(&msg), msg);
+    }
+
+    #[test]
+    fn test_proposed_value_roundtrip(
+        height in height(),
+        round in round(),
+        valid_round in pol_round(),
+        proposer in address(),
+        value in value(),
+        valid in any::<bool>(),
+    ) {
+        let msg = ProposedValue::<EmeraldContext> {
+            height,
+            round,
+            valid_round,
+            proposer,
+            value,
+            validity: Validity::from_bool(valid),
+        };
+        prop_assert_eq!(roundtrip(&msg), msg);
+    }
+
+    #[test]
+    fn test_certificate_roundtrip(certificate in certificate()) {
+        let proto = encode_certificate(&certificate).unwrap();
+        let decoded = proto::CommitCertificate::decode(proto.encode_to_vec().as_slice()).unwrap();
+        prop_assert_eq!(decode_certificate(decoded).unwrap(), certificate);
+    }
+
+    #[test]
+    fn test_sync_request_roundtrip(start in any::<u64>(), len in 0..100u64) {
+        let range = Height::new(start)..=Height::new(start.saturating_add(len));
+        let msg = sync::Request::<EmeraldContext>::ValueRequest(sync::ValueRequest::new(range.clone()));
+        let sync::Request::ValueRequest(decoded) = roundtrip(&msg);
+        prop_assert_eq!(decoded.range, range);
+    }
+
+    #[test]
+    fn test_sync_response_roundtrip(
+        start_height in height(),
+        values in vec((vec(any::<u8>(), 0..64), certificate()), 0..4),
+    ) {
+        let values = values
+            .into_iter()
+            .map(|(value_bytes, certificate)| sync::RawDecidedValue {
+                value_bytes: Bytes::from(value_bytes),
+                certificate,
+            })
+            .collect::<Vec<_>>();
+        let msg = sync::Response::<EmeraldContext>::ValueResponse(sync::ValueResponse::new(
+            start_height,
+            values.clone(),
+        ));
+
+        let sync::Response::ValueResponse(decoded) = roundtrip(&msg);
+        prop_assert_eq!(decoded.start_height, start_height);
+        prop_assert_eq!(decoded.values.len(), values.len());
+        for (decoded, value) in decoded.values.iter().zip(&values) {
+            prop_assert_eq!(&decoded.value_bytes, &value.value_bytes);
+            prop_assert_eq!(&decoded.certificate, &value.certificate);
+        }
+    }
+
+    #[test]
+    fn test_sync_status_roundtrip(
+        peer in any::<u8>(),
+        tip_height in height(),
+        history_min_height in height(),
+    ) {
+        let peer_id = PeerId::from_bytes(&[0, 1, peer]).unwrap();
+        let msg = sync::Status::<EmeraldContext> {
+            peer_id,
+            tip_height,
+            history_min_height,
+        };
+
+        let decoded = roundtrip(&msg);
+        prop_assert_eq!(decoded.peer_id, peer_id);
+        prop_assert_eq!(decoded.tip_height, tip_height);
+        prop_assert_eq!(decoded.history_min_height, history_min_height);
+    }
+}
+
+#[derive(Deserialize)]
+struct Vectors {
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    kind: Kind,
+    bare: String,
+    v1: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Value,
+    SignedMessage,
+    StreamMessage,
+    SyncStatus,
+    SyncRequest,
+    SyncResponse,
+    CommitCertificate,
+    ProposedValue,
+}
+
+fn reencode_as<T>(bytes: Bytes) -> Result<Bytes, ProtoError>
+where
+    ProtobufCodec: Codec<T, Error = ProtoError>,
+{
+    let msg: T = ProtobufCodec::default().decode(bytes)?;
+    ProtobufCodec::default().encode(&msg)
+}
+
+/// Decodes a message of the given kind and encodes it again, without envelope
+fn reencode(kind: Kind, bytes: Bytes) -> Result<Bytes, ProtoError> {
+    let bytes = match kind {
+        Kind::Value => reencode_as::<Value>(bytes)?,
+        Kind::SignedMessage => reencode_as::<SignedConsensusMsg<EmeraldContext>>(bytes)?,
+        Kind::StreamMessage => reencode_as::<StreamMessage<ProposalPart>>(bytes)?,
+        Kind::SyncStatus => {
+            // The status advertises the version of the release which encodes it
+            let wire_version = proto::Status::decode(bytes.clone())?.wire_version;
+            let reencoded = reencode_as::<sync::Status<EmeraldContext>>(bytes)?;
+            let status = proto::Status {
+                wire_version,
+                ..proto::Status::decode(reencoded)?
+            };
+            Bytes::from(status.encode_to_vec())
+        }
+        Kind::SyncRequest => reencode_as::<sync::Request<EmeraldContext>>(bytes)?,
+        Kind::SyncResponse => reencode_as::<sync::Response<EmeraldContext>>(bytes)?,
+        Kind::ProposedValue => reencode_as::<ProposedValue<EmeraldContext>>(bytes)?,
+        Kind::CommitCertificate => {
+            let certificate = decode_certificate(proto::CommitCertificate::decode(bytes)?)?;
+            Bytes::from(encode_certificate(&certificate)?.encode_to_vec())
+        }
+    };
+
+    // The envelope depends on the versions of the peers seen by the other tests
+    decode_envelope(bytes)
+}
+
+#[test]
+fn test_wire_format_vectors() {
+    let vectors: Vectors = serde_json::from_str(VECTORS).expect("valid vectors");
+
+    for vector in vectors.vectors {
+        let bare = Bytes::from(hex::decode(&vector.bare).unwrap());
+
+        let reencoded = reencode(vector.kind, bare.clone())
+            .unwrap_or_else(|e| panic!("{}: failed to decode the bare encoding: {e}", vector.name));
+        assert_eq!(reencoded, bare, "{}: bare encoding changed", vector.name);
+
+        if let Some(v1) = &vector.v1 {
+            let v1 = Bytes::from(hex::decode(v1).unwrap());
+            let reencoded = reencode(vector.kind, v1).unwrap_or_else(|e| {
+                panic!(
+                    "{}: failed to decode the version 1 encoding: {e}",
+                    vector.name
+                )
+            });
+            assert_eq!(
+                reencoded, bare,
+                "{}: version 1 encoding changed",
+                vector.name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_parts_request_of_nil_round_is_not_encoded() {
+    let request = ProposalPart::Request(PartsRequest {
+        height: Height::new(1),
+        round: Round::Nil,
+        proposer: Address::new([0; 20]),
+        stream_id: Bytes::from_static(&[1]),
+        sequences: vec![0],
+        from_sequence: None,
+    });
+
+    assert!(ProtobufCodec::default().encode(&request).is_err());
+}
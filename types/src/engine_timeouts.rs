@@ -0,0 +1,99 @@
+use core::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Timeouts of the individual Engine API calls made to the execution client.
+///
+/// A call exceeding its timeout fails, and is retried or not according to the
+/// [`RetryConfig`](crate::RetryConfig) of the operation.
+/// Large blocks on slow disks may need longer timeouts for `engine_newPayload`
+/// and `engine_forkchoiceUpdated`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineTimeouts {
+    /// Timeout of `engine_newPayload` calls.
+    /// Default: 8s
+    #[serde(with = "humantime_serde")]
+    pub new_payload: Duration,
+
+    /// Timeout of `engine_getPayload` calls.
+    /// Default: 2s
+    #[serde(with = "humantime_serde")]
+    pub get_payload: Duration,
+
+    /// Timeout of `engine_forkchoiceUpdated` calls.
+    /// Default: 8s
+    #[serde(with = "humantime_serde")]
+    pub forkchoice_updated: Duration,
+
+    /// Timeout of `engine_getPayloadBodiesByHash` and `engine_getPayloadBodiesByRange` calls.
+    /// Default: 10s
+    #[serde(with = "humantime_serde")]
+    pub get_payload_bodies: Duration,
+
+    /// Timeout of `engine_exchangeCapabilities` calls.
+    /// Default: 1s
+    #[serde(with = "humantime_serde")]
+    pub exchange_capabilities: Duration,
+
+    /// Timeout of `engine_getClientVersion` calls.
+    /// Default: 1s
+    #[serde(with = "humantime_serde")]
+    pub get_client_version: Duration,
+}
+
+impl Default for EngineTimeouts {
+    fn default() -> Self {
+        Self {
+            new_payload: Duration::from_secs(8),
+            get_payload: Duration::from_secs(2),
+            forkchoice_updated: Duration::from_secs(8),
+            get_payload_bodies: Duration::from_secs(10),
+            exchange_capabilities: Duration::from_secs(1),
+            get_client_version: Duration::from_secs(1),
+        }
+    }
+}
+
+impl EngineTimeouts {
+    /// Check that none of the timeouts is zero, which would fail every call
+    pub fn validate(&self) -> Result<(), String> {
+        for (method, timeout) in [
+            ("new_payload", self.new_payload),
+            ("get_payload", self.get_payload),
+            ("forkchoice_updated", self.forkchoice_updated),
+            ("get_payload_bodies", self.get_payload_bodies),
+            ("exchange_capabilities", self.exchange_capabilities),
+            ("get_client_version", self.get_client_version),
+        ] {
+            if timeout.is_zero() {
+                return Err(format!("{method}: timeout must be greater than 0"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_overrides() {
+        let timeouts: EngineTimeouts =
+            serde_json::from_str(r#"{ "new_payload": "30s", "forkchoice_updated": "1m" }"#)
+                .unwrap();
+
+        assert_eq!(timeouts.new_payload, Duration::from_secs(30));
+        assert_eq!(timeouts.forkchoice_updated, Duration::from_secs(60));
+        assert_eq!(timeouts.get_payload, EngineTimeouts::default().get_payload);
+        assert!(timeouts.validate().is_ok());
+
+        let timeouts = EngineTimeouts {
+            get_payload: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(timeouts.validate().is_err());
+    }
+}
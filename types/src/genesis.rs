@@ -1,8 +1,270 @@
+use alloy_primitives::{keccak256, B256};
 use serde::{Deserialize, Serialize};
 
-use crate::ValidatorSet;
+use crate::{Address, Hashable, ValidatorSet};
+
+/// Domain separator of the genesis hash, bumped whenever its encoding changes
+const GENESIS_HASH_DOMAIN: &[u8] = b"emerald/genesis/v1";
+
+/// Address of the validator manager contract when the genesis does not set one
+pub const DEFAULT_VALIDATOR_MANAGER_ADDRESS: Address = Address::new(
+    alloy_primitives::address!("0x0000000000000000000000000000000000002000")
+        .0
+         .0,
+);
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Genesis {
     pub validator_set: ValidatorSet,
+
+    /// Minimum base fee of the proposed blocks, not enforced when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_fee_floor: Option<BaseFeeFloor>,
+
+    /// Fee recipients accepted in the proposed blocks, any when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_recipient_policy: Option<FeeRecipientPolicy>,
+
+    /// Maximum size in bytes of the encoded payload of a proposal, not enforced when unset.
+    /// The `maxPayloadBytes` of the `ConsensusParams` contract can only lower it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_proposal_bytes: Option<u64>,
+
+    /// Maximum change of the voting power of the validator set from one height to the next,
+    /// not enforced when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_change_limit: Option<PowerChangeLimit>,
+
+    /// Address at which the validator manager contract is deployed in the EVM genesis,
+    /// [`DEFAULT_VALIDATOR_MANAGER_ADDRESS`] when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator_manager_address: Option<Address>,
+}
+
+impl Genesis {
+    /// Address from which the validator sets are read
+    pub fn validator_manager_address(&self) -> Address {
+        self.validator_manager_address
+            .unwrap_or(DEFAULT_VALIDATOR_MANAGER_ADDRESS)
+    }
+}
+
+/// Minimum base fee per gas that validators require from the blocks proposed to them.
+///
+/// Proposals whose base fee is below the floor are rejected if they contain transactions
+/// whose maximum fee per gas is below it too. Values which are already decided, e.g. when
+/// syncing, are not checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaseFeeFloor {
+    /// Floor in wei
+    pub min_base_fee_per_gas: u64,
+
+    /// Contract whose storage slot 0, when non-zero, overrides the floor.
+    /// It is read at each decided block, so that the floor can be changed on-chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub override_contract: Option<Address>,
+}
+
+/// Fee recipients that validators accept in the blocks proposed to them, so that a
+/// proposer cannot direct the fees of its blocks to an arbitrary address.
+///
+/// Proposals with another fee recipient are rejected. As for the base fee floor, values
+/// which are already decided are not checked.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRecipientPolicy {
+    /// The fee recipient must be the address of the proposer
+    Proposer,
+    /// The fee recipient must be one of these addresses
+    Allowed(Vec<Address>),
+}
+
+/// Maximum change of the voting power of the validator set per height, so that an update
+/// of the validator manager contract cannot move the set far enough in one height to break
+/// the assumption that less than a third of the voting power is faulty across heights.
+///
+/// The change is the sum of the differences of voting power of all the validators, added,
+/// removed or updated, as a percentage of the total voting power of the current set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerChangeLimit {
+    /// Maximum change in percent of the total voting power, e.g. 33
+    pub max_percent: u8,
+
+    /// What happens to the validator sets changing more than the limit
+    #[serde(default)]
+    pub policy: PowerChangePolicy,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerChangePolicy {
+    /// The change is applied over several heights, up to the limit at each height
+    #[default]
+    PhaseIn,
+    /// The change is ignored and the current set is kept, until the contract is updated
+    /// to a set within the limit
+    Reject,
+}
+
+impl Hashable for Genesis {
+    type Output = B256;
+
+    /// Canonical hash of the genesis, independent of how the genesis file is formatted.
+    ///
+    /// Keccak256 of the domain separator followed by, for each validator in order,
+    /// its address, compressed public key and big-endian voting power, and then by
+    /// the base fee floor, the fee recipient policy, the maximum proposal size and the
+    /// power change limit, if any.
+    fn hash(&self) -> B256 {
+        let mut bytes = GENESIS_HASH_DOMAIN.to_vec();
+        bytes.extend_from_slice(&(self.validator_set.validators.len() as u64).to_be_bytes());
+
+        for validator in self.validator_set.validators.iter() {
+            bytes.extend_from_slice(&validator.address.into_inner());
+            bytes.extend_from_slice(&validator.public_key.to_vec());
+            bytes.extend_from_slice(&validator.voting_power.to_be_bytes());
+        }
+
+        // Appended only when set, so that the hash of existing genesis files is unchanged
+        if let Some(floor) = &self.base_fee_floor {
+            bytes.extend_from_slice(b"base_fee_floor");
+            bytes.extend_from_slice(&floor.min_base_fee_per_gas.to_be_bytes());
+            if let Some(contract) = floor.override_contract {
+                bytes.extend_from_slice(&contract.into_inner());
+            }
+        }
+
+        if let Some(policy) = &self.fee_recipient_policy {
+            bytes.extend_from_slice(b"fee_recipient_policy");
+            match policy {
+                FeeRecipientPolicy::Proposer => bytes.push(0),
+                FeeRecipientPolicy::Allowed(addresses) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&(addresses.len() as u64).to_be_bytes());
+                    for address in addresses {
+                        bytes.extend_from_slice(&address.into_inner());
+                    }
+                }
+            }
+        }
+
+        if let Some(max_proposal_bytes) = self.max_proposal_bytes {
+            bytes.extend_from_slice(b"max_proposal_bytes");
+            bytes.extend_from_slice(&max_proposal_bytes.to_be_bytes());
+        }
+
+        if let Some(limit) = &self.power_change_limit {
+            bytes.extend_from_slice(b"power_change_limit");
+            bytes.push(limit.max_percent);
+            bytes.push(match limit.policy {
+                PowerChangePolicy::PhaseIn => 0,
+                PowerChangePolicy::Reject => 1,
+            });
+        }
+
+        if let Some(address) = self.validator_manager_address {
+            bytes.extend_from_slice(b"validator_manager_address");
+            bytes.extend_from_slice(&address.into_inner());
+        }
+
+        keccak256(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp256k1::PrivateKey;
+    use crate::Validator;
+
+    fn genesis(powers: &[u64]) -> Genesis {
+        let validators = powers.iter().enumerate().map(|(i, power)| {
+            let private_key = PrivateKey::from_slice(&[i as u8 + 1; 32]).unwrap();
+            Validator::new(private_key.public_key(), *power)
+        });
+        Genesis {
+            validator_set: ValidatorSet::new(validators),
+            base_fee_floor: None,
+            fee_recipient_policy: None,
+            max_proposal_bytes: None,
+            power_change_limit: None,
+            validator_manager_address: None,
+        }
+    }
+
+    #[test]
+    fn test_genesis_hash_is_canonical() {
+        let genesis = genesis(&[10, 20]);
+
+        // Formatting of the genesis file does not change the hash
+        let compact: Genesis =
+            serde_json::from_str(&serde_json::to_string(&genesis).unwrap()).unwrap();
+        let pretty: Genesis =
+            serde_json::from_str(&serde_json::to_string_pretty(&genesis).unwrap()).unwrap();
+        assert_eq!(compact.hash(), genesis.hash());
+        assert_eq!(pretty.hash(), genesis.hash());
+
+        assert_ne!(self::genesis(&[10, 21]).hash(), genesis.hash());
+
+        let mut with_floor = genesis.clone();
+        with_floor.base_fee_floor = Some(BaseFeeFloor {
+            min_base_fee_per_gas: 1_000_000_000,
+            override_contract: None,
+        });
+        assert_ne!(with_floor.hash(), genesis.hash());
+
+        let mut with_policy = genesis.clone();
+        with_policy.fee_recipient_policy = Some(FeeRecipientPolicy::Proposer);
+        assert_ne!(with_policy.hash(), genesis.hash());
+
+        let mut with_allowed = genesis.clone();
+        with_allowed.fee_recipient_policy =
+            Some(FeeRecipientPolicy::Allowed(vec![Address::repeat_byte(1)]));
+        assert_ne!(with_allowed.hash(), with_policy.hash());
+        assert_ne!(with_allowed.hash(), genesis.hash());
+
+        let mut with_max_proposal = genesis.clone();
+        with_max_proposal.max_proposal_bytes = Some(4 * 1024 * 1024);
+        assert_ne!(with_max_proposal.hash(), genesis.hash());
+
+        let mut with_phase_in = genesis.clone();
+        with_phase_in.power_change_limit = Some(PowerChangeLimit {
+            max_percent: 33,
+            policy: PowerChangePolicy::PhaseIn,
+        });
+        let mut with_reject = genesis.clone();
+        with_reject.power_change_limit = Some(PowerChangeLimit {
+            max_percent: 33,
+            policy: PowerChangePolicy::Reject,
+        });
+        assert_ne!(with_phase_in.hash(), genesis.hash());
+        assert_ne!(with_reject.hash(), with_phase_in.hash());
+
+        let mut with_validator_manager = genesis.clone();
+        with_validator_manager.validator_manager_address = Some(Address::repeat_byte(2));
+        assert_ne!(with_validator_manager.hash(), genesis.hash());
+    }
+
+    #[test]
+    fn test_default_validator_manager_address() {
+        let genesis = genesis(&[10]);
+        let json = serde_json::to_string(&genesis).unwrap();
+        assert!(!json.contains("validator_manager_address"));
+
+        let decoded: Genesis = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            decoded.validator_manager_address(),
+            DEFAULT_VALIDATOR_MANAGER_ADDRESS
+        );
+        assert_eq!(
+            DEFAULT_VALIDATOR_MANAGER_ADDRESS.to_alloy_address(),
+            alloy_primitives::address!("0x0000000000000000000000000000000000002000")
+        );
+
+        let mut custom = genesis.clone();
+        custom.validator_manager_address = Some(Address::repeat_byte(2));
+        let decoded: Genesis =
+            serde_json::from_str(&serde_json::to_string(&custom).unwrap()).unwrap();
+        assert_eq!(decoded.validator_manager_address(), Address::repeat_byte(2));
+    }
 }
@@ -5,6 +5,7 @@
 mod address;
 mod aliases;
 mod context;
+mod engine_timeouts;
 mod genesis;
 mod height;
 mod proposal;
@@ -23,6 +24,7 @@ pub mod utils;
 pub use crate::address::*;
 pub use crate::aliases::*;
 pub use crate::context::*;
+pub use crate::engine_timeouts::*;
 pub use crate::genesis::*;
 pub use crate::height::*;
 pub use crate::proposal::*;
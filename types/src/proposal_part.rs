@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::codec::proto::{decode_signature, encode_signature};
 use crate::secp256k1::Signature;
-use crate::{Address, EmeraldContext, Height};
+use crate::{Address, BlockHash, EmeraldContext, Height};
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProposalData {
@@ -45,6 +45,10 @@ pub enum ProposalPart {
     Init(ProposalInit),
     Data(ProposalData),
     Fin(ProposalFin),
+    /// Request for the parts of a stream missed by the sender, which is not part of a proposal
+    Request(PartsRequest),
+    /// Part of a stream sent again in reply to a [`PartsRequest`]
+    Recovered(RecoveredPart),
 }
 
 impl ProposalPart {
@@ -53,6 +57,8 @@ impl ProposalPart {
             Self::Init(_) => "init",
             Self::Data(_) => "data",
             Self::Fin(_) => "fin",
+            Self::Request(_) => "request",
+            Self::Recovered(_) => "recovered",
         }
     }
 
@@ -95,6 +101,8 @@ pub struct ProposalInit {
     #[serde(with = "RoundDef")]
     pub pol_round: Round,
     pub proposer: Address,
+    /// Payload carried by the stream, absent in proposals from older nodes
+    pub payload: Option<PayloadSummary>,
 }
 
 impl ProposalInit {
@@ -104,8 +112,43 @@ impl ProposalInit {
             round,
             pol_round,
             proposer,
+            payload: None,
         }
     }
+
+    pub fn with_payload(self, payload: PayloadSummary) -> Self {
+        Self {
+            payload: Some(payload),
+            ..self
+        }
+    }
+
+    /// Bytes signed by the proposer in the [`PayloadSummary`], binding the payload to
+    /// this height and round.
+    pub fn payload_sign_bytes(&self, block_hash: &BlockHash, len: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.height.as_u64().to_be_bytes());
+        bytes.extend_from_slice(&self.round.as_i64().to_be_bytes());
+        bytes.extend_from_slice(&self.pol_round.as_i64().to_be_bytes());
+        bytes.extend_from_slice(self.proposer.into_inner().as_slice());
+        bytes.extend_from_slice(block_hash.as_slice());
+        bytes.extend_from_slice(&len.to_be_bytes());
+        bytes
+    }
+}
+
+/// Execution payload carried by a proposal stream, announced in its [`ProposalInit`].
+///
+/// Receivers which already have the payload, e.g. when it is proposed again in a later
+/// round, can reply to consensus without waiting for the rest of the stream. Since this
+/// happens before the [`ProposalFin`], the summary carries its own signature of
+/// [`ProposalInit::payload_sign_bytes`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayloadSummary {
+    pub block_hash: BlockHash,
+    /// Length of the payload in bytes
+    pub len: u64,
+    pub signature: Signature,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -119,6 +162,54 @@ impl ProposalFin {
     }
 }
 
+/// Request for the parts of a proposal stream which a node missed.
+///
+/// The proposer of the stream replies with the requested parts, each in a [`RecoveredPart`],
+/// so that a proposal whose parts were lost on the way can be completed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartsRequest {
+    pub height: Height,
+    #[serde(with = "RoundDef")]
+    pub round: Round,
+    pub proposer: Address,
+    /// Stream of the proposal, as received by the requesting node
+    pub stream_id: Bytes,
+    /// Sequences missing before the last part received
+    pub sequences: Vec<u64>,
+    /// Sequence from which all the parts are missing, if the end of the stream was not received
+    pub from_sequence: Option<u64>,
+}
+
+/// Part of a proposal stream sent again in reply to a [`PartsRequest`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveredPart {
+    pub stream_id: Bytes,
+    pub sequence: u64,
+    /// The part at this sequence, none for the end of the stream
+    pub part: Option<Box<ProposalPart>>,
+}
+
+fn decode_payload_summary(
+    payload: crate::proto::PayloadSummary,
+) -> Result<PayloadSummary, ProtoError> {
+    let len = payload.block_hash.len();
+    let block_hash = BlockHash::try_from(payload.block_hash.as_ref()).map_err(|_| {
+        ProtoError::Other(format!(
+            "Invalid block hash length, got {len} bytes expected 32"
+        ))
+    })?;
+    let signature = payload
+        .signature
+        .ok_or_else(|| ProtoError::missing_field::<crate::proto::PayloadSummary>("signature"))
+        .and_then(decode_signature)?;
+
+    Ok(PayloadSummary {
+        block_hash,
+        len: payload.len,
+        signature,
+    })
+}
+
 impl malachitebft_core_types::ProposalPart<EmeraldContext> for ProposalPart {
     fn is_first(&self) -> bool {
         matches!(self, Self::Init(_))
@@ -149,6 +240,7 @@ impl Protobuf for ProposalPart {
                     .proposer
                     .ok_or_else(|| ProtoError::missing_field::<Self::Proto>("proposer"))
                     .and_then(Address::from_proto)?,
+                payload: init.payload.map(decode_payload_summary).transpose()?,
             })),
             Part::Data(data) => Ok(Self::Data(ProposalData::new(data.bytes))),
             Part::Fin(fin) => Ok(Self::Fin(ProposalFin {
@@ -157,6 +249,33 @@ impl Protobuf for ProposalPart {
                     .ok_or_else(|| ProtoError::missing_field::<Self::Proto>("signature"))
                     .and_then(decode_signature)?,
             })),
+            Part::Request(request) => Ok(Self::Request(PartsRequest {
+                height: Height::new(request.height),
+                round: Round::new(request.round),
+                proposer: request
+                    .proposer
+                    .ok_or_else(|| ProtoError::missing_field::<Self::Proto>("proposer"))
+                    .and_then(Address::from_proto)?,
+                stream_id: request.stream_id,
+                sequences: request.sequences,
+                from_sequence: request.from_sequence,
+            })),
+            Part::Recovered(recovered) => {
+                use crate::proto::recovered_part::Content;
+
+                let part = match recovered.content.ok_or_else(|| {
+                    ProtoError::missing_field::<crate::proto::RecoveredPart>("content")
+                })? {
+                    Content::Data(data) => Some(Box::new(Self::from_bytes(&data)?)),
+                    Content::Fin(_) => None,
+                };
+
+                Ok(Self::Recovered(RecoveredPart {
+                    stream_id: recovered.stream_id,
+                    sequence: recovered.sequence,
+                    part,
+                }))
+            }
         }
     }
 
@@ -172,6 +291,11 @@ impl Protobuf for ProposalPart {
                     round: init.round.as_u32().unwrap(),
                     pol_round: init.pol_round.as_u32(),
                     proposer: Some(init.proposer.to_proto()?),
+                    payload: init.payload.as_ref().map(|payload| proto::PayloadSummary {
+                        block_hash: Bytes::copy_from_slice(payload.block_hash.as_slice()),
+                        len: payload.len,
+                        signature: Some(encode_signature(&payload.signature)),
+                    }),
                 })),
             }),
             Self::Data(data) => Ok(Self::Proto {
@@ -184,6 +308,34 @@ impl Protobuf for ProposalPart {
                     signature: Some(encode_signature(&fin.signature)),
                 })),
             }),
+            Self::Request(request) => Ok(Self::Proto {
+                part: Some(Part::Request(proto::PartsRequest {
+                    height: request.height.as_u64(),
+                    round: request.round.as_u32().ok_or_else(|| {
+                        ProtoError::Other("Parts request round should not be nil".into())
+                    })?,
+                    proposer: Some(request.proposer.to_proto()?),
+                    stream_id: request.stream_id.clone(),
+                    sequences: request.sequences.clone(),
+                    from_sequence: request.from_sequence,
+                })),
+            }),
+            Self::Recovered(recovered) => {
+                use crate::proto::recovered_part::Content;
+
+                let content = match &recovered.part {
+                    Some(part) => Content::Data(part.to_bytes()?),
+                    None => Content::Fin(true),
+                };
+
+                Ok(Self::Proto {
+                    part: Some(Part::Recovered(proto::RecoveredPart {
+                        stream_id: recovered.stream_id.clone(),
+                        sequence: recovered.sequence,
+                        content: Some(content),
+                    })),
+                })
+            }
         }
     }
 }
@@ -1,5 +1,7 @@
 use core::time::Duration;
+use std::sync::{Arc, RwLock};
 
+use emerald_retry::Backoff;
 use serde::{Deserialize, Serialize};
 
 /// Exponential backoff retry configuration
@@ -23,12 +25,60 @@ pub struct RetryConfig {
     /// Exponential backoff multiplier (e.g., 2.0 for doubling)
     #[serde(default = "default_multiplier")]
     pub multiplier: f64,
+
+    /// Random jitter applied to each delay, as a fraction of the delay (e.g., 0.2 for ±20%).
+    /// Spreads the retries of nodes sharing an execution client after it restarts.
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+
+    /// Overrides for `engine_forkchoiceUpdated` calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forkchoice_updated: Option<RetryOverride>,
+
+    /// Overrides for `engine_newPayload` calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_payload: Option<RetryOverride>,
+
+    /// Overrides for `engine_getPayload` calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub get_payload: Option<RetryOverride>,
+}
+
+/// Per-operation overrides of the [`RetryConfig`], unset fields keep the base value
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetryOverride {
+    #[serde(default, with = "humantime_serde")]
+    pub initial_delay: Option<Duration>,
+
+    #[serde(default, with = "humantime_serde")]
+    pub max_delay: Option<Duration>,
+
+    #[serde(default, with = "humantime_serde")]
+    pub max_elapsed_time: Option<Duration>,
+
+    #[serde(default)]
+    pub multiplier: Option<f64>,
+
+    #[serde(default)]
+    pub jitter: Option<f64>,
+}
+
+/// Engine API operation a [`RetryConfig`] applies to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RetryOperation {
+    ForkchoiceUpdated,
+    NewPayload,
+    GetPayload,
 }
 
 fn default_multiplier() -> f64 {
     2.0
 }
 
+fn default_jitter() -> f64 {
+    0.2
+}
+
 impl Default for RetryConfig {
     fn default() -> Self {
         Self {
@@ -36,14 +86,144 @@ impl Default for RetryConfig {
             max_delay: Duration::from_secs(2),
             max_elapsed_time: Duration::from_secs(10),
             multiplier: 2.0,
+            jitter: default_jitter(),
+            forkchoice_updated: None,
+            new_payload: None,
+            get_payload: None,
         }
     }
 }
 
 impl RetryConfig {
-    /// Calculate the next retry delay using exponential backoff
-    pub fn next_delay(&self, current_delay: Duration) -> Duration {
-        let next = current_delay.mul_f64(self.multiplier);
-        core::cmp::min(next, self.max_delay)
+    /// Delays between the retries of the operations using this configuration
+    pub fn backoff(&self) -> Backoff {
+        Backoff::exponential(self.initial_delay, self.max_delay, self.multiplier)
+            .with_jitter(self.jitter)
+    }
+
+    /// Returns the configuration to use for the given operation, with its overrides applied
+    pub fn for_operation(&self, operation: RetryOperation) -> Self {
+        let overrides = match operation {
+            RetryOperation::ForkchoiceUpdated => &self.forkchoice_updated,
+            RetryOperation::NewPayload => &self.new_payload,
+            RetryOperation::GetPayload => &self.get_payload,
+        };
+
+        let overrides = overrides.clone().unwrap_or_default();
+
+        Self {
+            initial_delay: overrides.initial_delay.unwrap_or(self.initial_delay),
+            max_delay: overrides.max_delay.unwrap_or(self.max_delay),
+            max_elapsed_time: overrides.max_elapsed_time.unwrap_or(self.max_elapsed_time),
+            multiplier: overrides.multiplier.unwrap_or(self.multiplier),
+            jitter: overrides.jitter.unwrap_or(self.jitter),
+            forkchoice_updated: None,
+            new_payload: None,
+            get_payload: None,
+        }
+    }
+
+    /// Check that the configuration, including its overrides, is usable
+    pub fn validate(&self) -> Result<(), String> {
+        for operation in [
+            RetryOperation::ForkchoiceUpdated,
+            RetryOperation::NewPayload,
+            RetryOperation::GetPayload,
+        ] {
+            let config = self.for_operation(operation);
+
+            if config.initial_delay > config.max_delay {
+                return Err(format!(
+                    "{operation:?}: initial_delay ({:?}) exceeds max_delay ({:?})",
+                    config.initial_delay, config.max_delay
+                ));
+            }
+            if config.multiplier.is_nan() || config.multiplier < 1.0 {
+                return Err(format!(
+                    "{operation:?}: multiplier must be >= 1.0, got {}",
+                    config.multiplier
+                ));
+            }
+            if !(0.0..=1.0).contains(&config.jitter) {
+                return Err(format!(
+                    "{operation:?}: jitter must be between 0.0 and 1.0, got {}",
+                    config.jitter
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Retry configuration shared between the application and the admin API,
+/// so that it can be adjusted while the node is running
+#[derive(Clone, Debug, Default)]
+pub struct SharedRetryConfig(Arc<RwLock<RetryConfig>>);
+
+impl SharedRetryConfig {
+    pub fn new(config: RetryConfig) -> Self {
+        Self(Arc::new(RwLock::new(config)))
+    }
+
+    pub fn get(&self) -> RetryConfig {
+        self.0.read().expect("retry config lock poisoned").clone()
+    }
+
+    pub fn set(&self, config: RetryConfig) -> Result<(), String> {
+        config.validate()?;
+        *self.0.write().expect("retry config lock poisoned") = config;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_overrides() {
+        let config: RetryConfig = serde_json::from_str(
+            r#"{
+                "initial_delay": "100ms",
+                "max_delay": "2s",
+                "max_elapsed_time": "10s",
+                "new_payload": { "max_elapsed_time": "1m", "jitter": 0.0 }
+            }"#,
+        )
+        .unwrap();
+
+        let new_payload = config.for_operation(RetryOperation::NewPayload);
+        assert_eq!(new_payload.max_elapsed_time, Duration::from_secs(60));
+        assert_eq!(new_payload.initial_delay, Duration::from_millis(100));
+        assert_eq!(
+            new_payload.backoff().jittered(Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+
+        let fcu = config.for_operation(RetryOperation::ForkchoiceUpdated);
+        assert_eq!(fcu.max_elapsed_time, Duration::from_secs(10));
+        for _ in 0..100 {
+            let delay = fcu.backoff().jittered(Duration::from_secs(1));
+            assert!(delay >= Duration::from_millis(800) && delay <= Duration::from_millis(1200));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RetryConfig::default().validate().is_ok());
+
+        let config = RetryConfig {
+            get_payload: Some(RetryOverride {
+                initial_delay: Some(Duration::from_secs(5)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let shared = SharedRetryConfig::new(RetryConfig::default());
+        assert!(shared.set(config).is_err());
+        assert_eq!(shared.get(), RetryConfig::default());
     }
 }
@@ -107,12 +107,15 @@ impl Protobuf for Value {
             .value
             .ok_or_else(|| ProtoError::missing_field::<Self::Proto>("value"))?;
 
-        let value = bytes[0..8].try_into().map_err(|_| {
-            ProtoError::Other(format!(
-                "Too few bytes, expected at least {}",
-                u64::BITS / 8
-            ))
-        })?;
+        let value = bytes
+            .get(0..8)
+            .and_then(|value| value.try_into().ok())
+            .ok_or_else(|| {
+                ProtoError::Other(format!(
+                    "Too few bytes, expected at least {}",
+                    u64::BITS / 8
+                ))
+            })?;
 
         let extensions = bytes.slice(8..);
 
@@ -0,0 +1,23 @@
+//! Predeployed `ConsensusParams` contract, see `solidity/src/ConsensusParams.sol`.
+
+use std::collections::BTreeMap;
+
+use alloy_primitives::{address, Address, B256};
+
+pub const GENESIS_CONSENSUS_PARAMS_ACCOUNT: Address =
+    address!("0x0000000000000000000000000000000000002001");
+
+alloy_sol_types::sol!(
+    #[derive(Debug)]
+    #[sol(rpc)]
+    ConsensusParams,
+    "../solidity/out/ConsensusParams.sol/ConsensusParams.json",
+);
+
+/// Generate the genesis storage of the contract. All parameters are left unset,
+/// so that nodes use their local configuration until the owner sets them.
+pub fn generate_storage_data(owner: Address) -> BTreeMap<B256, B256> {
+    // Slot 0: Ownable._owner
+    // Slot 1: _params, whose fields are packed in a single slot
+    BTreeMap::from([(B256::ZERO, owner.into_word())])
+}
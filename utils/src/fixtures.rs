@@ -0,0 +1,364 @@
+//! Generation of canned chains, used as fixtures by the integration tests and benchmarks.
+//!
+//! A single-validator dev chain is started with `emerald dev`, which produces blocks lazily,
+//! only once transactions are available. Each block is then filled with the same batch of
+//! transactions, signed by the genesis owner account with consecutive nonces, so that two
+//! runs with the same parameters produce the same transactions in the same blocks. Once the
+//! chain is long enough, the node is stopped and the fixture written to the output directory:
+//!
+//! ```text
+//! <output>/emerald-store.archive   store of the Emerald node, see `emerald store import`
+//! <output>/reth-data/              datadir of the Reth node
+//! <output>/genesis.json            EVM genesis
+//! <output>/emerald-genesis.json    consensus genesis
+//! <output>/manifest.json           blocks of the chain and their transactions
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use alloy_network::eip2718::Encodable2718;
+use alloy_primitives::{address, Address, B256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_signer_local::PrivateKeySigner;
+use color_eyre::eyre::{bail, eyre, Context, Result};
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::json;
+use tokio::process::Command;
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::make_signers;
+use crate::spammer::RpcClient;
+use crate::tx::{make_signed_contract_call_tx, make_signed_eip1559_tx};
+
+/// HTTP RPC port of the Reth node of the dev chain
+const DEV_RPC_PORT: u16 = 8645;
+
+const VALIDATOR_MANAGER_ADDRESS: Address = address!("0x0000000000000000000000000000000000002000");
+
+/// Time to wait for the transactions of a block to be included
+const INCLUSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Kind of transaction of the mix filling the blocks
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TxKind {
+    /// Transfer of 10 wei
+    Transfer,
+    /// Call of `getValidators()` on the ValidatorManager contract
+    ContractCall,
+}
+
+impl fmt::Display for TxKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transfer => write!(f, "transfer"),
+            Self::ContractCall => write!(f, "contract-call"),
+        }
+    }
+}
+
+/// Relative weights of the kinds of transactions, e.g. `transfer=3,contract-call=1`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxMix(Vec<(TxKind, usize)>);
+
+impl FromStr for TxMix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mix = s
+            .split(',')
+            .map(|entry| {
+                let (kind, weight) = entry.split_once('=').unwrap_or((entry, "1"));
+                let kind = match kind.trim() {
+                    "transfer" => TxKind::Transfer,
+                    "contract-call" => TxKind::ContractCall,
+                    other => return Err(format!("unknown transaction kind `{other}`")),
+                };
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid weight of `{kind}`: {e}"))?;
+                Ok((kind, weight))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if mix.iter().all(|(_, weight)| *weight == 0) {
+            return Err("the transaction mix is empty".to_string());
+        }
+
+        Ok(Self(mix))
+    }
+}
+
+impl TxMix {
+    /// Kinds of the `count` transactions of a block, cycling through the mix
+    pub fn kinds(&self, count: usize) -> Vec<TxKind> {
+        self.0
+            .iter()
+            .flat_map(|(kind, weight)| core::iter::repeat_n(*kind, *weight))
+            .cycle()
+            .take(count)
+            .collect()
+    }
+}
+
+pub struct FixturesConfig {
+    /// Number of blocks with transactions
+    pub blocks: u64,
+    pub txs_per_block: usize,
+    pub tx_mix: TxMix,
+    pub output: PathBuf,
+    /// Path to the `emerald` executable, which runs the dev chain
+    pub emerald_bin: String,
+    pub emerald_utils_bin: String,
+    pub custom_reth_bin: String,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    chain_id: u64,
+    tx_mix: Vec<TxKind>,
+    blocks: Vec<BlockEntry>,
+}
+
+#[derive(Serialize)]
+struct BlockEntry {
+    number: u64,
+    hash: B256,
+    transactions: Vec<B256>,
+}
+
+/// Generates the fixture in `config.output`, which must not exist.
+pub async fn generate(config: &FixturesConfig) -> Result<()> {
+    if config.txs_per_block == 0 {
+        bail!("Blocks must contain at least one transaction");
+    }
+    if config.output.exists() {
+        bail!(
+            "Output directory {} already exists",
+            config.output.display()
+        );
+    }
+
+    let work_dir = config.output.join("work");
+    fs::create_dir_all(&work_dir)?;
+    let node_home = work_dir.join("dev").join("0");
+
+    println!("💎 Starting the dev chain in {}", work_dir.display());
+    let mut emerald = Command::new(&config.emerald_bin)
+        .arg("--home")
+        .arg(&work_dir)
+        .args(["dev", "--reset"])
+        .args(["--emerald-utils-bin", &config.emerald_utils_bin])
+        .args(["--custom-reth-bin", &config.custom_reth_bin])
+        .stdout(fs::File::create(work_dir.join("emerald.log"))?)
+        .stderr(fs::File::create(work_dir.join("emerald.err.log"))?)
+        .spawn()
+        .context("Failed to start `emerald dev`")?;
+
+    let result = fill_chain(config).await;
+
+    // `emerald dev` stops its Reth node on SIGINT
+    if let Some(pid) = emerald.id() {
+        Command::new("kill")
+            .args(["-INT", &pid.to_string()])
+            .status()
+            .await?;
+    }
+    emerald.wait().await?;
+
+    let manifest = result?;
+
+    println!("📦 Writing the fixture to {}", config.output.display());
+    let status = Command::new(&config.emerald_bin)
+        .arg("--home")
+        .arg(&node_home)
+        .arg("--config")
+        .arg(node_home.join("config").join("emerald.toml"))
+        .args(["store", "export"])
+        .arg(config.output.join("emerald-store.archive"))
+        .status()
+        .await
+        .context("Failed to run `emerald store export`")?;
+    if !status.success() {
+        bail!("`emerald store export` failed");
+    }
+
+    copy_dir(
+        &node_home.join("reth-data"),
+        &config.output.join("reth-data"),
+    )?;
+    fs::copy(
+        work_dir.join("dev").join("assets").join("genesis.json"),
+        config.output.join("genesis.json"),
+    )?;
+    fs::copy(
+        node_home.join("config").join("genesis.json"),
+        config.output.join("emerald-genesis.json"),
+    )?;
+    fs::write(
+        config.output.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    fs::remove_dir_all(&work_dir)?;
+
+    println!("✅ Generated {} blocks", manifest.blocks.len());
+    Ok(())
+}
+
+/// Fills `config.blocks` blocks with the transaction mix, one batch per block.
+async fn fill_chain(config: &FixturesConfig) -> Result<Manifest> {
+    let url = Url::parse(&format!("http://127.0.0.1:{DEV_RPC_PORT}"))?;
+    let provider = ProviderBuilder::new().connect_http(url.clone());
+
+    // The node spawns Reth once the chain is generated
+    let deadline = Instant::now() + INCLUSION_TIMEOUT;
+    let chain_id = loop {
+        match provider.get_chain_id().await {
+            Ok(chain_id) => break chain_id,
+            Err(_) if Instant::now() < deadline => sleep(Duration::from_millis(500)).await,
+            Err(e) => return Err(eyre!("Reth node of the dev chain not ready: {e}")),
+        }
+    };
+
+    // The genesis owner, the only funded account of the dev chain
+    let signer = &make_signers()[0];
+    let kinds = config.tx_mix.kinds(config.txs_per_block);
+    let client = RpcClient::new(url)?;
+    let mut nonce = 0;
+    let mut blocks = Vec::new();
+
+    for i in 0..config.blocks {
+        let mut txs = Vec::with_capacity(kinds.len());
+        for kind in &kinds {
+            txs.push(make_tx(signer, *kind, nonce, chain_id).await?);
+            nonce += 1;
+        }
+
+        // Sent as a single batch, so that the proposer sees all of them at once
+        let batch = txs
+            .iter()
+            .map(|tx| vec![json!(hex::encode(tx.encoded_2718()))])
+            .collect();
+        for response in client
+            .rpc_batch_request("eth_sendRawTransaction", batch)
+            .await?
+        {
+            response.context("Transaction rejected by the Reth node")?;
+        }
+
+        let hashes: Vec<B256> = txs.iter().map(|tx| *tx.tx_hash()).collect();
+        let (number, hash) = wait_for_receipt(&provider, hashes[0]).await?;
+        for tx_hash in &hashes[1..] {
+            // Otherwise the blocks would depend on the timing of the run
+            if wait_for_receipt(&provider, *tx_hash).await?.0 != number {
+                bail!("Transactions of block {number} split across several blocks");
+            }
+        }
+
+        println!(
+            "  Block {number} ({}/{}): {} txs",
+            i + 1,
+            config.blocks,
+            hashes.len()
+        );
+        blocks.push(BlockEntry {
+            number,
+            hash,
+            transactions: hashes,
+        });
+    }
+
+    Ok(Manifest {
+        chain_id,
+        tx_mix: kinds,
+        blocks,
+    })
+}
+
+async fn make_tx(
+    signer: &PrivateKeySigner,
+    kind: TxKind,
+    nonce: u64,
+    chain_id: u64,
+) -> Result<reth_primitives::TransactionSigned> {
+    match kind {
+        TxKind::Transfer => make_signed_eip1559_tx(signer, nonce, chain_id).await,
+        TxKind::ContractCall => {
+            make_signed_contract_call_tx(
+                signer,
+                nonce,
+                VALIDATOR_MANAGER_ADDRESS,
+                "getValidators()",
+                &[],
+                chain_id,
+            )
+            .await
+        }
+    }
+}
+
+/// Returns the number and hash of the block including the transaction `hash`.
+async fn wait_for_receipt(provider: &impl Provider, hash: B256) -> Result<(u64, B256)> {
+    let deadline = Instant::now() + INCLUSION_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(receipt) = provider.get_transaction_receipt(hash).await? {
+            let number = receipt
+                .block_number
+                .ok_or_else(|| eyre!("Receipt of {hash} without block number"))?;
+            let block_hash = receipt
+                .block_hash
+                .ok_or_else(|| eyre!("Receipt of {hash} without block hash"))?;
+            return Ok((number, block_hash));
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    Err(eyre!(
+        "Transaction {hash} not included after {INCLUSION_TIMEOUT:?}"
+    ))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tx_mix() {
+        let mix: TxMix = "transfer=2,contract-call=1".parse().unwrap();
+        assert_eq!(
+            mix.kinds(4),
+            vec![
+                TxKind::Transfer,
+                TxKind::Transfer,
+                TxKind::ContractCall,
+                TxKind::Transfer
+            ]
+        );
+
+        let mix: TxMix = "contract-call".parse().unwrap();
+        assert_eq!(mix.kinds(2), vec![TxKind::ContractCall; 2]);
+
+        assert!("transfer=0".parse::<TxMix>().is_err());
+        assert!("burn=1".parse::<TxMix>().is_err());
+    }
+}
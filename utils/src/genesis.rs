@@ -3,6 +3,8 @@ use std::collections::BTreeMap;
 
 use alloy_genesis::{ChainConfig, Genesis, GenesisAccount};
 use alloy_primitives::{address, hex, Address, B256, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::BlockNumberOrTag;
 use alloy_signer_local::coins_bip39::English;
 use alloy_signer_local::{MnemonicBuilder, PrivateKeySigner};
 use chrono::NaiveDate;
@@ -12,10 +14,13 @@ use k256::ecdsa::VerifyingKey;
 // Malachite types for Emerald genesis
 use malachitebft_eth_types::secp256k1::PublicKey as EmeraldPublicKey;
 use malachitebft_eth_types::{
-    Genesis as EmeraldGenesis, Validator as EmeraldValidator, ValidatorSet as EmeraldValidatorSet,
+    Address as EmeraldAddress, BaseFeeFloor, FeeRecipientPolicy, Genesis as EmeraldGenesis,
+    Hashable, PowerChangeLimit, Validator as EmeraldValidator, ValidatorSet as EmeraldValidatorSet,
 };
+use reqwest::Url;
 use tracing::debug;
 
+use crate::consensus_params::{self, ConsensusParams, GENESIS_CONSENSUS_PARAMS_ACCOUNT};
 use crate::validator_manager::contract::{ValidatorManager, GENESIS_VALIDATOR_MANAGER_ACCOUNT};
 use crate::validator_manager::{generate_storage_data, Validator};
 
@@ -43,6 +48,78 @@ pub(crate) fn make_signers() -> Vec<PrivateKeySigner> {
     (0..10).map(make_signer).collect()
 }
 
+/// Parse a hex-encoded uncompressed secp256k1 public key (64 bytes, without the 0x04 prefix)
+pub(crate) fn parse_validator_public_key(key: &str) -> Result<[u8; 64]> {
+    let hex_str = key.strip_prefix("0x").unwrap_or(key);
+    let bytes = decode(hex_str).map_err(|e| eyre!("invalid hex-encoded validator key: {e}"))?;
+
+    let key: [u8; 64] = bytes.as_slice().try_into().map_err(|_| {
+        eyre!(
+            "expected 64-byte uncompressed secp256k1 payload (sans 0x04 prefix), got {} bytes",
+            bytes.len()
+        )
+    })?;
+
+    VerifyingKey::from_sec1_bytes(&uncompressed_sec1(&key))
+        .map_err(|_| eyre!("invalid secp256k1 public key material"))?;
+
+    Ok(key)
+}
+
+/// Read the validator public keys from a file containing one key per line
+pub(crate) fn read_public_keys_file(public_keys_file: &str) -> Result<Vec<[u8; 64]>> {
+    let mut keys = Vec::new();
+    for (idx, raw_line) in std::fs::read_to_string(public_keys_file)?
+        .lines()
+        .enumerate()
+    {
+        let line = raw_line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let key = parse_validator_public_key(line)
+            .map_err(|e| eyre!("{e} at line {} in {public_keys_file}", idx + 1))?;
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Pair the validator public keys with the given voting powers, in order,
+/// or with `default_power` when no powers are given
+pub(crate) fn with_powers(
+    keys: Vec<[u8; 64]>,
+    powers: &[u64],
+    default_power: u64,
+) -> Result<Vec<([u8; 64], u64)>> {
+    if powers.is_empty() {
+        return Ok(keys.into_iter().map(|key| (key, default_power)).collect());
+    }
+
+    if powers.len() != keys.len() {
+        return Err(eyre!(
+            "got {} voting powers for {} validators",
+            powers.len(),
+            keys.len()
+        ));
+    }
+
+    if powers.contains(&0) {
+        return Err(eyre!("voting powers must be positive"));
+    }
+
+    Ok(keys.into_iter().zip(powers.iter().copied()).collect())
+}
+
+/// Convert to uncompressed SEC1 format (65 bytes with 0x04 prefix)
+pub(crate) fn uncompressed_sec1(key: &[u8; 64]) -> [u8; 65] {
+    let mut uncompressed = [0u8; 65];
+    uncompressed[0] = 0x04;
+    uncompressed[1..].copy_from_slice(key);
+    uncompressed
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn generate_genesis(
     public_keys_file: &str,
     poa_address_owner: &Option<String>,
@@ -51,6 +128,12 @@ pub(crate) fn generate_genesis(
     chain_id: &u64,
     evm_genesis_output_file: &str,
     emerald_genesis_output_file: &str,
+    base_fee_floor: Option<BaseFeeFloor>,
+    fee_recipient_policy: Option<FeeRecipientPolicy>,
+    max_proposal_bytes: Option<u64>,
+    power_change_limit: Option<PowerChangeLimit>,
+    validator_manager_address: Option<Address>,
+    powers: &[u64],
 ) -> Result<()> {
     generate_evm_genesis(
         public_keys_file,
@@ -59,13 +142,25 @@ pub(crate) fn generate_genesis(
         testnet_balance,
         chain_id,
         evm_genesis_output_file,
+        validator_manager_address.unwrap_or(GENESIS_VALIDATOR_MANAGER_ACCOUNT),
+        powers,
     )?;
 
-    generate_emerald_genesis(public_keys_file, emerald_genesis_output_file)?;
+    generate_emerald_genesis(
+        public_keys_file,
+        emerald_genesis_output_file,
+        base_fee_floor,
+        fee_recipient_policy,
+        max_proposal_bytes,
+        power_change_limit,
+        validator_manager_address.map(EmeraldAddress::from),
+        powers,
+    )?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn generate_evm_genesis(
     public_keys_file: &str,
     poa_address_owner: &Option<String>,
@@ -73,7 +168,39 @@ pub(crate) fn generate_evm_genesis(
     testnet_balance: &u64,
     chain_id: &u64,
     genesis_output_file: &str,
+    validator_manager_address: Address,
+    powers: &[u64],
+) -> Result<()> {
+    let validators = with_powers(read_public_keys_file(public_keys_file)?, powers, 100)?;
+
+    write_evm_genesis(
+        &validators,
+        poa_address_owner,
+        testnet,
+        testnet_balance,
+        chain_id,
+        genesis_output_file,
+        validator_manager_address,
+    )
+}
+
+/// Generate the EVM genesis file from validator public keys and their voting power
+pub(crate) fn write_evm_genesis(
+    validators: &[([u8; 64], u64)],
+    poa_address_owner: &Option<String>,
+    testnet: &bool,
+    testnet_balance: &u64,
+    chain_id: &u64,
+    genesis_output_file: &str,
+    validator_manager_address: Address,
 ) -> Result<()> {
+    if [GENESIS_CONSENSUS_PARAMS_ACCOUNT, BEACON_ROOTS_ADDRESS].contains(&validator_manager_address)
+    {
+        return Err(eyre!(
+            "the ValidatorManager cannot be deployed at {validator_manager_address}, which holds another system contract"
+        ));
+    }
+
     let mut alloc = BTreeMap::new();
     let signers = make_signers();
     // If test addresses are requested, create them and pre-fund them
@@ -102,54 +229,17 @@ pub(crate) fn generate_evm_genesis(
         }
     }
 
-    let mut initial_validators = Vec::new();
-    for (idx, raw_line) in std::fs::read_to_string(public_keys_file)?
-        .lines()
-        .enumerate()
-    {
-        let line = raw_line.trim();
-        if line.is_empty() {
-            continue;
-        }
-
-        let hex_str = line.strip_prefix("0x").unwrap_or(line);
-        let bytes = decode(hex_str).map_err(|e| {
-            eyre!(
-                "invalid hex-encoded validator key at line {} in {}: {}",
-                idx + 1,
-                public_keys_file,
-                e
-            )
-        })?;
-
-        if bytes.len() != 64 {
-            return Err(eyre!(
-                "expected 64-byte uncompressed secp256k1 payload (sans 0x04 prefix) at line {} in {}, got {} bytes",
-                idx + 1,
-                public_keys_file,
-                bytes.len()
-            ));
-        }
-
-        let mut uncompressed = [0u8; 65];
-        uncompressed[0] = 0x04;
-        uncompressed[1..].copy_from_slice(&bytes);
-
-        VerifyingKey::from_sec1_bytes(&uncompressed).map_err(|_| {
-            eyre!(
-                "invalid secp256k1 public key material at line {} in {}",
-                idx + 1,
-                public_keys_file
-            )
-        })?;
-
-        let mut x_bytes = [0u8; 32];
-        x_bytes.copy_from_slice(&bytes[..32]);
-        let mut y_bytes = [0u8; 32];
-        y_bytes.copy_from_slice(&bytes[32..]);
-        let key = (U256::from_be_bytes(x_bytes), U256::from_be_bytes(y_bytes));
-        initial_validators.push(Validator::from_public_key(key, 100));
-    }
+    let initial_validators = validators
+        .iter()
+        .map(|(key, power)| {
+            let mut x_bytes = [0u8; 32];
+            x_bytes.copy_from_slice(&key[..32]);
+            let mut y_bytes = [0u8; 32];
+            y_bytes.copy_from_slice(&key[32..]);
+            let key = (U256::from_be_bytes(x_bytes), U256::from_be_bytes(y_bytes));
+            Validator::from_public_key(key, *power)
+        })
+        .collect();
 
     // Parse PoA owner address or override with first test address
     let poa_address_owner = if let Some(addr_str) = poa_address_owner {
@@ -163,7 +253,7 @@ pub(crate) fn generate_evm_genesis(
 
     let storage = generate_storage_data(initial_validators, poa_address_owner)?;
     alloc.insert(
-        GENESIS_VALIDATOR_MANAGER_ACCOUNT,
+        validator_manager_address,
         GenesisAccount {
             code: Some(ValidatorManager::DEPLOYED_BYTECODE.clone()),
             storage: Some(storage),
@@ -171,6 +261,15 @@ pub(crate) fn generate_evm_genesis(
         },
     );
 
+    alloc.insert(
+        GENESIS_CONSENSUS_PARAMS_ACCOUNT,
+        GenesisAccount {
+            code: Some(ConsensusParams::DEPLOYED_BYTECODE.clone()),
+            storage: Some(consensus_params::generate_storage_data(poa_address_owner)),
+            ..Default::default()
+        },
+    );
+
     // Deploy EIP-4788 Beacon Roots Contract
     // Required for Engine API V3 compliance when parent_beacon_block_root is set
     // reth deploys this contract at genesis but only for chain-id 1 so we add it here manually in
@@ -232,73 +331,119 @@ pub(crate) fn generate_evm_genesis(
 }
 
 /// Generate Malachite/Emerald genesis file from validator public keys
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn generate_emerald_genesis(
     public_keys_file: &str,
     emerald_genesis_output_file: &str,
+    base_fee_floor: Option<BaseFeeFloor>,
+    fee_recipient_policy: Option<FeeRecipientPolicy>,
+    max_proposal_bytes: Option<u64>,
+    power_change_limit: Option<PowerChangeLimit>,
+    validator_manager_address: Option<EmeraldAddress>,
+    powers: &[u64],
 ) -> Result<()> {
     debug!("Generating Emerald genesis file from {public_keys_file}");
 
-    let mut validators = Vec::new();
-
-    for (idx, raw_line) in std::fs::read_to_string(public_keys_file)?
-        .lines()
-        .enumerate()
-    {
-        let line = raw_line.trim();
-        if line.is_empty() {
-            continue;
-        }
-
-        // Parse hex-encoded public key (64 bytes without 0x04 prefix)
-        let hex_str = line.strip_prefix("0x").unwrap_or(line);
-        let bytes = decode(hex_str).map_err(|e| {
-            eyre!(
-                "invalid hex-encoded validator key at line {} in {}: {}",
-                idx + 1,
-                public_keys_file,
-                e
-            )
-        })?;
-
-        if bytes.len() != 64 {
-            return Err(eyre!(
-                "expected 64-byte uncompressed secp256k1 payload (sans 0x04 prefix) at line {} in {}, got {} bytes",
-                idx + 1,
-                public_keys_file,
-                bytes.len()
-            ));
-        }
-
-        // Convert to uncompressed SEC1 format (65 bytes with 0x04 prefix)
-        let mut uncompressed = [0u8; 65];
-        uncompressed[0] = 0x04;
-        uncompressed[1..].copy_from_slice(&bytes);
-
-        // Validate and create public key
-        let pub_key = EmeraldPublicKey::from_sec1_bytes(&uncompressed).map_err(|_| {
-            eyre!(
-                "invalid secp256k1 public key material at line {} in {}",
-                idx + 1,
-                public_keys_file
-            )
-        })?;
-
-        // Create validator with voting power of 1
-        validators.push(EmeraldValidator::new(pub_key, 1));
-    }
+    // Create validators with voting power of 1, unless given
+    let validators = with_powers(read_public_keys_file(public_keys_file)?, powers, 1)?;
 
     if validators.is_empty() {
         return Err(eyre!("no valid validators found in {}", public_keys_file));
     }
 
+    write_emerald_genesis(
+        &validators,
+        base_fee_floor,
+        fee_recipient_policy,
+        max_proposal_bytes,
+        power_change_limit,
+        validator_manager_address,
+        emerald_genesis_output_file,
+    )?;
+
+    Ok(())
+}
+
+/// Generate the Malachite/Emerald genesis file from validator public keys and their voting power.
+/// Returns the canonical hash of the genesis.
+pub(crate) fn write_emerald_genesis(
+    validators: &[([u8; 64], u64)],
+    base_fee_floor: Option<BaseFeeFloor>,
+    fee_recipient_policy: Option<FeeRecipientPolicy>,
+    max_proposal_bytes: Option<u64>,
+    power_change_limit: Option<PowerChangeLimit>,
+    validator_manager_address: Option<EmeraldAddress>,
+    emerald_genesis_output_file: &str,
+) -> Result<B256> {
+    let validators = validators
+        .iter()
+        .map(|(key, power)| {
+            let pub_key = EmeraldPublicKey::from_sec1_bytes(&uncompressed_sec1(key))
+                .map_err(|_| eyre!("invalid secp256k1 public key material"))?;
+            Ok(EmeraldValidator::new(pub_key, *power))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
     // Create validator set and genesis
     let validator_set = EmeraldValidatorSet::new(validators);
-    let genesis = EmeraldGenesis { validator_set };
+    let genesis = EmeraldGenesis {
+        validator_set,
+        base_fee_floor,
+        fee_recipient_policy,
+        max_proposal_bytes,
+        power_change_limit,
+        validator_manager_address,
+    };
 
     // Write emerald genesis to file
     let genesis_json = serde_json::to_string_pretty(&genesis)?;
     std::fs::write(emerald_genesis_output_file, genesis_json)?;
     debug!("Emerald genesis configuration written to {emerald_genesis_output_file}");
 
+    let genesis_hash = genesis.hash();
+    debug!("Emerald genesis hash: {genesis_hash}");
+
+    Ok(genesis_hash)
+}
+
+/// Compare the storage of the accounts of a genesis file with the storage of a live node,
+/// read with `eth_getStorageAt` at `block`, and report every mismatching slot.
+pub(crate) async fn verify_genesis_storage(
+    genesis_file: &str,
+    rpc_url: &Url,
+    block: BlockNumberOrTag,
+) -> Result<()> {
+    let genesis: Genesis = serde_json::from_str(&std::fs::read_to_string(genesis_file)?)?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.clone());
+
+    let mut checked = 0;
+    let mut mismatches = 0;
+    for (address, account) in &genesis.alloc {
+        let Some(storage) = &account.storage else {
+            continue;
+        };
+
+        for (slot, expected) in storage {
+            let actual = provider
+                .get_storage_at(*address, (*slot).into())
+                .block_id(block.into())
+                .await?;
+            let actual = B256::from(actual);
+
+            if actual != *expected {
+                println!("Mismatch in {address} at slot {slot}: expected {expected}, got {actual}");
+                mismatches += 1;
+            }
+            checked += 1;
+        }
+    }
+
+    if mismatches > 0 {
+        return Err(eyre!(
+            "{mismatches} of the {checked} storage slots of {genesis_file} differ at block {block}"
+        ));
+    }
+
+    println!("All the {checked} storage slots of {genesis_file} match at block {block}");
     Ok(())
 }
@@ -0,0 +1,243 @@
+//! Genesis ceremony for networks run by several operators.
+//!
+//! Each operator submits a JSON file describing their validator:
+//!
+//! ```json
+//! {
+//!   "public_key": "0xd8620dd478f043bd27fc9389ec6873410265cf8640cb636decd2f0a2ddad7aa5...",
+//!   "power": 100,
+//!   "metadata": { "operator": "Acme", "contact": "ops@acme.example" }
+//! }
+//! ```
+//!
+//! The coordinator collects all submissions, which are validated and ordered
+//! deterministically (by power descending, then by address ascending), so that
+//! every party running the ceremony on the same submissions gets the same
+//! public keys file and genesis files.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context, Result};
+use malachitebft_eth_types::secp256k1::PublicKey as EmeraldPublicKey;
+use malachitebft_eth_types::Address as EmeraldAddress;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::genesis::{
+    parse_validator_public_key, uncompressed_sec1, write_emerald_genesis, write_evm_genesis,
+};
+use crate::validator_manager::contract::GENESIS_VALIDATOR_MANAGER_ACCOUNT;
+
+/// Validator submitted by an operator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatorSubmission {
+    /// Public key as printed by `emerald show-pubkey`
+    pub public_key: String,
+    /// Voting power of the validator
+    pub power: u64,
+    /// Free-form information about the operator, not included in the genesis
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Validated submission
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectedValidator {
+    pub public_key: [u8; 64],
+    pub address: EmeraldAddress,
+    pub power: u64,
+    pub source: PathBuf,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Read the submissions from the given files, or from the `*.json` files of the given directories.
+pub fn read_submissions(paths: &[PathBuf]) -> Result<Vec<(PathBuf, ValidatorSubmission)>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .wrap_err_with(|| format!("failed to read directory {}", path.display()))?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.retain(|p| p.extension().is_some_and(|ext| ext == "json"));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|file| {
+            let content = std::fs::read_to_string(&file)
+                .wrap_err_with(|| format!("failed to read submission {}", file.display()))?;
+            let submission = serde_json::from_str(&content)
+                .wrap_err_with(|| format!("invalid submission {}", file.display()))?;
+            Ok((file, submission))
+        })
+        .collect()
+}
+
+/// Validate the submissions and order them deterministically.
+pub fn collect_validators(
+    submissions: Vec<(PathBuf, ValidatorSubmission)>,
+) -> Result<Vec<CollectedValidator>> {
+    if submissions.is_empty() {
+        return Err(eyre!("no validator submissions found"));
+    }
+
+    let mut addresses = HashSet::new();
+    let mut validators = Vec::with_capacity(submissions.len());
+
+    for (source, submission) in submissions {
+        let public_key = parse_validator_public_key(submission.public_key.trim())
+            .map_err(|e| eyre!("{e} in {}", source.display()))?;
+
+        if submission.power == 0 {
+            return Err(eyre!(
+                "validator power must be positive in {}",
+                source.display()
+            ));
+        }
+
+        let address = EmeraldAddress::from_public_key(
+            &EmeraldPublicKey::from_sec1_bytes(&uncompressed_sec1(&public_key))
+                .map_err(|_| eyre!("invalid secp256k1 public key in {}", source.display()))?,
+        );
+
+        if !addresses.insert(address) {
+            return Err(eyre!(
+                "validator {address} is submitted more than once (again in {})",
+                source.display()
+            ));
+        }
+
+        validators.push(CollectedValidator {
+            public_key,
+            address,
+            power: submission.power,
+            source,
+            metadata: submission.metadata,
+        });
+    }
+
+    validators
+        .iter()
+        .try_fold(0u64, |total, v| total.checked_add(v.power))
+        .ok_or_else(|| eyre!("total voting power overflows"))?;
+
+    validators.sort_by(|a, b| b.power.cmp(&a.power).then(a.address.cmp(&b.address)));
+
+    Ok(validators)
+}
+
+/// Write the public keys file, one key per line, in the order of the validators.
+pub fn write_public_keys_file(validators: &[CollectedValidator], path: &Path) -> Result<()> {
+    let content: String = validators
+        .iter()
+        .map(|v| format!("0x{}\n", hex::encode(v.public_key)))
+        .collect();
+    std::fs::write(path, content)?;
+    debug!("Public keys written to {}", path.display());
+    Ok(())
+}
+
+pub(crate) fn collect(
+    submissions: &[PathBuf],
+    poa_address_owner: &str,
+    chain_id: &u64,
+    public_keys_output_file: &Path,
+    evm_genesis_output_file: &str,
+    emerald_genesis_output_file: &str,
+) -> Result<()> {
+    let validators = collect_validators(read_submissions(submissions)?)?;
+
+    write_public_keys_file(&validators, public_keys_output_file)?;
+
+    let keys: Vec<_> = validators.iter().map(|v| (v.public_key, v.power)).collect();
+    write_evm_genesis(
+        &keys,
+        &Some(poa_address_owner.to_string()),
+        &false,
+        &0,
+        chain_id,
+        evm_genesis_output_file,
+        GENESIS_VALIDATOR_MANAGER_ACCOUNT,
+    )?;
+    let genesis_hash = write_emerald_genesis(
+        &keys,
+        None,
+        None,
+        None,
+        None,
+        None,
+        emerald_genesis_output_file,
+    )?;
+
+    let total_power: u64 = validators.iter().map(|v| v.power).sum();
+    println!("Collected {} validators:", validators.len());
+    for v in &validators {
+        println!(
+            "  0x{}  power {:>6}  {}",
+            v.address,
+            v.power,
+            v.source.display()
+        );
+    }
+    println!("Total voting power: {total_power}");
+    println!();
+    println!("Public keys file: {}", public_keys_output_file.display());
+    println!("EVM genesis:      {evm_genesis_output_file}");
+    println!("Emerald genesis:  {emerald_genesis_output_file}");
+    println!("Genesis hash:     {genesis_hash}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+
+    use super::*;
+
+    fn submission(seed: u8, power: u64) -> (PathBuf, ValidatorSubmission) {
+        let signing_key = SigningKey::from_slice(&[seed; 32]).unwrap();
+        let point = signing_key.verifying_key().to_encoded_point(false);
+        (
+            PathBuf::from(format!("{seed}.json")),
+            ValidatorSubmission {
+                public_key: format!("0x{}", hex::encode(&point.as_bytes()[1..])),
+                power,
+                metadata: BTreeMap::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_collect_orders_deterministically() {
+        let submissions = vec![submission(1, 10), submission(2, 30), submission(3, 10)];
+
+        let mut reversed = submissions.clone();
+        reversed.reverse();
+
+        let validators = collect_validators(submissions).unwrap();
+        assert_eq!(validators, collect_validators(reversed).unwrap());
+
+        assert_eq!(validators[0].power, 30);
+        assert!(validators[1].address < validators[2].address);
+    }
+
+    #[test]
+    fn test_collect_rejects_invalid_submissions() {
+        assert!(collect_validators(vec![]).is_err());
+        assert!(collect_validators(vec![submission(1, 0)]).is_err());
+        assert!(collect_validators(vec![submission(1, 10), submission(1, 20)]).is_err());
+
+        let (path, mut bad_key) = submission(1, 10);
+        bad_key.public_key.truncate(10);
+        assert!(collect_validators(vec![(path, bad_key)]).is_err());
+    }
+}
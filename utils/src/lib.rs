@@ -1,11 +1,21 @@
 use alloy_primitives::Address;
+use alloy_rpc_types::{BlockId, BlockNumberOrTag};
 use clap::{Parser, Subcommand, ValueHint};
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use genesis::{generate_genesis, make_signers};
+use malachitebft_eth_types::{
+    Address as EmeraldAddress, BaseFeeFloor, FeeRecipientPolicy, Genesis as EmeraldGenesis,
+    PowerChangeLimit, PowerChangePolicy,
+};
 use reqwest::Url;
 use spammer::Spammer;
+use validator_manager::contract::GENESIS_VALIDATOR_MANAGER_ACCOUNT;
 
+pub mod consensus_params;
+pub mod fixtures;
 pub mod genesis;
+pub mod genesis_ceremony;
+pub mod load_control;
 pub mod modify_config;
 pub mod poa;
 pub mod spammer;
@@ -22,27 +32,13 @@ pub struct Cli {
 impl Cli {
     pub async fn run(&self) -> Result<()> {
         match &self.command {
-            Commands::Genesis {
-                public_keys_file,
-                poa_owner_address,
-                devnet,
-                devnet_balance,
-                chain_id,
-                evm_genesis_output,
-                emerald_genesis_output,
-            } => generate_genesis(
-                public_keys_file,
-                poa_owner_address,
-                devnet,
-                devnet_balance,
-                chain_id,
-                evm_genesis_output,
-                emerald_genesis_output,
-            ),
+            Commands::Genesis(genesis_cmd) => genesis_cmd.run().await,
             Commands::Spam(spam_cmd) => spam_cmd.run().await,
             Commands::Poa(poa_cmd) => poa_cmd.run().await,
             Commands::SpamContract(spam_contract_cmd) => spam_contract_cmd.run().await,
             Commands::ModifyConfig(modify_config_cmd) => modify_config_cmd.run(),
+            Commands::Itf(itf_cmd) => itf_cmd.run(),
+            Commands::Fixtures(fixtures_cmd) => fixtures_cmd.run().await,
         }
     }
 }
@@ -50,64 +46,7 @@ impl Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Generate genesis file
-    Genesis {
-        #[clap(
-            short,
-            long,
-            value_hint = ValueHint::FilePath,
-            help = "File containing validator public keys (one per line)"
-        )]
-        public_keys_file: String,
-
-        #[clap(
-            long,
-            short = 'a',
-            required_unless_present = "devnet",
-            help = "Address of the Proof-of-Authority owner"
-        )]
-        poa_owner_address: Option<String>,
-
-        #[clap(
-            long,
-            short = 'c',
-            help = "Chain ID for the genesis file (default: 12345)",
-            default_value_t = 12345
-        )]
-        chain_id: u64,
-
-        #[clap(
-            short,
-            long,
-            default_value_t = false,
-            help = "Generate test addresses in genesis using mnemonic: 'test test test test test test test test test test test junk'"
-        )]
-        devnet: bool,
-
-        #[clap(
-            long,
-            short = 'b',
-            default_value_t = 15_000_u64,
-            help = "Balance for each testnet wallet (default: 15000)"
-        )]
-        devnet_balance: u64,
-
-        #[clap(
-            long,
-            short = 'g',
-            value_hint = ValueHint::FilePath,
-            default_value = "./assets/genesis.json",
-            help = "Output path for the generated genesis file"
-        )]
-        evm_genesis_output: String,
-
-        #[clap(
-            long,
-            short = 'e',
-            default_value = "./assets/emerald_genesis.json",
-            help = "Output path for the generated Emerald genesis file"
-        )]
-        emerald_genesis_output: String,
-    },
+    Genesis(GenesisCmd),
 
     /// Spam transactions
     #[command(arg_required_else_help = true)]
@@ -123,6 +62,262 @@ pub enum Commands {
     /// Apply custom node configurations from a TOML file
     #[command(arg_required_else_help = true)]
     ModifyConfig(ModifyConfigCmd),
+
+    /// Convert node event logs into an ITF trace of the Quint specification
+    #[command(arg_required_else_help = true)]
+    Itf(ItfCmd),
+
+    /// Generate a chain of blocks with a local dev chain, exported as a test fixture
+    #[command(arg_required_else_help = true)]
+    Fixtures(FixturesCmd),
+}
+
+#[derive(Parser, Debug, Clone, PartialEq)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct GenesisCmd {
+    #[command(subcommand)]
+    command: Option<GenesisCommands>,
+
+    #[clap(
+        short,
+        long,
+        value_hint = ValueHint::FilePath,
+        help = "File containing validator public keys (one per line)",
+        required = true
+    )]
+    public_keys_file: Option<String>,
+
+    #[clap(
+        long,
+        short = 'a',
+        required_unless_present = "devnet",
+        help = "Address of the Proof-of-Authority owner"
+    )]
+    poa_owner_address: Option<String>,
+
+    #[clap(
+        long,
+        short = 'c',
+        help = "Chain ID for the genesis file (default: 12345)",
+        default_value_t = 12345
+    )]
+    chain_id: u64,
+
+    #[clap(
+        short,
+        long,
+        default_value_t = false,
+        help = "Generate test addresses in genesis using mnemonic: 'test test test test test test test test test test test junk'"
+    )]
+    devnet: bool,
+
+    #[clap(
+        long,
+        short = 'b',
+        default_value_t = 15_000_u64,
+        help = "Balance for each testnet wallet (default: 15000)"
+    )]
+    devnet_balance: u64,
+
+    #[clap(
+        long,
+        short = 'g',
+        value_hint = ValueHint::FilePath,
+        default_value = "./assets/genesis.json",
+        help = "Output path for the generated genesis file"
+    )]
+    evm_genesis_output: String,
+
+    #[clap(
+        long,
+        short = 'e',
+        default_value = "./assets/emerald_genesis.json",
+        help = "Output path for the generated Emerald genesis file"
+    )]
+    emerald_genesis_output: String,
+
+    #[clap(
+        long,
+        help = "Minimum base fee per gas (wei) of the blocks validators accept to vote for"
+    )]
+    min_base_fee_per_gas: Option<u64>,
+
+    #[clap(
+        long,
+        requires = "min_base_fee_per_gas",
+        help = "Contract whose storage slot 0, when non-zero, overrides the minimum base fee"
+    )]
+    base_fee_floor_contract: Option<Address>,
+
+    #[clap(
+        long,
+        conflicts_with = "allowed_fee_recipients",
+        help = "Only accept blocks whose fee recipient is the address of their proposer"
+    )]
+    fee_recipient_proposer: bool,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Only accept blocks whose fee recipient is one of these addresses"
+    )]
+    allowed_fee_recipients: Vec<Address>,
+
+    #[clap(
+        long,
+        help = "Maximum size in bytes of the encoded payload of the blocks validators accept to vote for"
+    )]
+    max_proposal_bytes: Option<u64>,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        help = "Maximum change of the voting power of the validator set per height, in percent of its total voting power"
+    )]
+    max_power_change_percent: Option<u8>,
+
+    #[clap(
+        long,
+        requires = "max_power_change_percent",
+        help = "Ignore the validator sets changing more than the maximum, instead of phasing them in over several heights"
+    )]
+    reject_power_changes: bool,
+
+    #[clap(
+        long,
+        help = "Address at which the ValidatorManager contract is deployed (default: 0x0000000000000000000000000000000000002000)"
+    )]
+    validator_manager_address: Option<Address>,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Voting powers of the validators, in the order of the public keys file, e.g. 100,50,10 (default: equal powers)"
+    )]
+    powers: Vec<u64>,
+}
+
+impl GenesisCmd {
+    fn power_change_limit(&self) -> Option<PowerChangeLimit> {
+        self.max_power_change_percent
+            .map(|max_percent| PowerChangeLimit {
+                max_percent,
+                policy: if self.reject_power_changes {
+                    PowerChangePolicy::Reject
+                } else {
+                    PowerChangePolicy::PhaseIn
+                },
+            })
+    }
+
+    fn fee_recipient_policy(&self) -> Option<FeeRecipientPolicy> {
+        if self.fee_recipient_proposer {
+            Some(FeeRecipientPolicy::Proposer)
+        } else if !self.allowed_fee_recipients.is_empty() {
+            Some(FeeRecipientPolicy::Allowed(
+                self.allowed_fee_recipients
+                    .iter()
+                    .copied()
+                    .map(EmeraldAddress::from)
+                    .collect(),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        match &self.command {
+            Some(GenesisCommands::Collect {
+                submissions,
+                poa_owner_address,
+                chain_id,
+                public_keys_output,
+                evm_genesis_output,
+                emerald_genesis_output,
+            }) => genesis_ceremony::collect(
+                submissions,
+                poa_owner_address,
+                chain_id,
+                public_keys_output,
+                evm_genesis_output,
+                emerald_genesis_output,
+            ),
+            Some(GenesisCommands::Verify {
+                genesis,
+                rpc_url,
+                block,
+            }) => genesis::verify_genesis_storage(genesis, rpc_url, *block).await,
+            None => generate_genesis(
+                self.public_keys_file
+                    .as_deref()
+                    .expect("public keys file is a required argument"),
+                &self.poa_owner_address,
+                &self.devnet,
+                &self.devnet_balance,
+                &self.chain_id,
+                &self.evm_genesis_output,
+                &self.emerald_genesis_output,
+                self.min_base_fee_per_gas
+                    .map(|min_base_fee_per_gas| BaseFeeFloor {
+                        min_base_fee_per_gas,
+                        override_contract: self.base_fee_floor_contract.map(EmeraldAddress::from),
+                    }),
+                self.fee_recipient_policy(),
+                self.max_proposal_bytes,
+                self.power_change_limit(),
+                self.validator_manager_address,
+                &self.powers,
+            ),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum GenesisCommands {
+    /// Collect the validator submissions of the operators of a new network and generate
+    /// the public keys file and genesis files from them
+    Collect {
+        /// Submission files, or directories containing `*.json` submission files
+        #[clap(required = true, value_hint = ValueHint::AnyPath)]
+        submissions: Vec<std::path::PathBuf>,
+
+        /// Address of the Proof-of-Authority owner
+        #[clap(long, short = 'a')]
+        poa_owner_address: String,
+
+        /// Chain ID for the genesis file
+        #[clap(long, short = 'c', default_value_t = 12345)]
+        chain_id: u64,
+
+        /// Output path for the canonical public keys file
+        #[clap(long, short = 'p', value_hint = ValueHint::FilePath, default_value = "./validator_public_keys.txt")]
+        public_keys_output: std::path::PathBuf,
+
+        /// Output path for the generated genesis file
+        #[clap(long, short = 'g', value_hint = ValueHint::FilePath, default_value = "./assets/genesis.json")]
+        evm_genesis_output: String,
+
+        /// Output path for the generated Emerald genesis file
+        #[clap(long, short = 'e', default_value = "./assets/emerald_genesis.json")]
+        emerald_genesis_output: String,
+    },
+
+    /// Check that the storage of the accounts of a genesis file, e.g. the ValidatorManager
+    /// slots, matches the storage of a live node
+    Verify {
+        /// Genesis file to verify
+        #[clap(value_hint = ValueHint::FilePath, default_value = "./assets/genesis.json")]
+        genesis: String,
+
+        /// URL of the execution client's RPC endpoint
+        #[clap(long, short, default_value = "http://127.0.0.1:8545")]
+        rpc_url: Url,
+
+        /// Block at which the storage is read (a number, or a tag such as `earliest` or `latest`)
+        #[clap(long, short, default_value = "earliest")]
+        block: BlockNumberOrTag,
+    },
 }
 
 #[derive(Parser, Debug, Clone, Default, PartialEq)]
@@ -130,6 +325,14 @@ pub struct SpamCmd {
     /// URL of the execution client's RPC endpoint (e.g., http://127.0.0.1:8545, https://eth.example.com)
     #[clap(long, default_value = "http://127.0.0.1:8545")]
     rpc_url: String,
+    /// RPC endpoints of several execution clients the transactions are distributed across,
+    /// instead of `--rpc-url` (e.g., http://127.0.0.1:8545,http://127.0.0.1:18545)
+    #[clap(long, value_delimiter = ',')]
+    rpc_urls: Vec<String>,
+    /// Relative share of the transactions sent to each of the `--rpc-urls`, in the same order
+    /// (e.g., 2,1). The endpoints take turns in round-robin by default
+    #[clap(long, value_delimiter = ',', requires = "rpc_urls")]
+    rpc_weights: Vec<u32>,
     /// Number of transactions to send
     #[clap(short, long, default_value = "0")]
     num_txs: u64,
@@ -145,6 +348,18 @@ pub struct SpamCmd {
     /// Spam EIP-4844 (blob) transactions instead of EIP-1559
     #[clap(long, default_value = "false")]
     blobs: bool,
+    /// Re-submit the missing nonces with bumped fees once the pending nonce has been stuck
+    /// at the latest nonce for this many seconds (0 to disable)
+    #[clap(long, default_value = "0")]
+    repair_after: u64,
+    /// Prometheus endpoint of an Emerald node (e.g., http://127.0.0.1:29000/metrics).
+    /// When set, the rate is reduced while rounds fail or blocks are slow, and increased
+    /// back up to `rate` while consensus keeps up
+    #[clap(long)]
+    metrics_url: Option<String>,
+    /// Block time in ms above which the rate is reduced, when `--metrics-url` is set
+    #[clap(long, default_value = "2000")]
+    max_block_time: u64,
 
     #[clap(long, default_value = "0")]
     signer_index: usize,
@@ -157,16 +372,28 @@ impl SpamCmd {
     pub(crate) async fn run(&self) -> Result<()> {
         let Self {
             rpc_url,
+            rpc_urls,
+            rpc_weights,
             num_txs,
             rate,
             interval,
             time,
             blobs,
+            repair_after,
+            metrics_url,
+            max_block_time,
             signer_index,
             chain_id,
         } = self;
 
-        let url: Url = rpc_url.parse()?;
+        let urls = if rpc_urls.is_empty() {
+            vec![rpc_url.parse()?]
+        } else {
+            rpc_urls
+                .iter()
+                .map(|url| url.parse())
+                .collect::<Result<Vec<Url>, _>>()?
+        };
         let config = spammer::SpammerConfig {
             max_num_txs: *num_txs,
             max_time: *time,
@@ -174,9 +401,47 @@ impl SpamCmd {
             batch_interval: *interval,
             blobs: *blobs,
             chain_id: *chain_id,
+            repair_after: *repair_after,
+            load_control: load_control_config(metrics_url.as_deref(), *max_block_time)?,
         };
-        Spammer::new(url, *signer_index, config)?.run().await
+        Spammer::new(&rpc_targets(urls, rpc_weights)?, *signer_index, config)?
+            .run()
+            .await
+    }
+}
+
+/// Endpoints the spammer sends its transactions to, with their `weights` if any
+fn rpc_targets(urls: Vec<Url>, weights: &[u32]) -> Result<Vec<spammer::RpcTarget>> {
+    if !weights.is_empty() && weights.len() != urls.len() {
+        return Err(eyre!(
+            "Expected one weight per RPC endpoint, got {} weights for {} endpoints",
+            weights.len(),
+            urls.len()
+        ));
     }
+
+    Ok(urls
+        .into_iter()
+        .enumerate()
+        .map(|(i, url)| spammer::RpcTarget {
+            url,
+            weight: weights.get(i).copied().unwrap_or(1),
+        })
+        .collect())
+}
+
+fn load_control_config(
+    metrics_url: Option<&str>,
+    max_block_time: u64,
+) -> Result<Option<load_control::LoadControlConfig>> {
+    metrics_url
+        .map(|url| -> Result<_> {
+            Ok(load_control::LoadControlConfig {
+                metrics_url: url.parse()?,
+                max_block_time: core::time::Duration::from_millis(max_block_time),
+            })
+        })
+        .transpose()
 }
 
 #[derive(Parser, Debug, Clone, PartialEq)]
@@ -185,19 +450,34 @@ pub struct PoaCmd {
     #[clap(long, short, default_value = "http://127.0.0.1:8545")]
     rpc_url: Url,
 
-    /// ValidatorManager contract address
-    #[clap(
-        long,
-        short,
-        default_value_t = alloy_primitives::address!("0x0000000000000000000000000000000000002000")
-    )]
-    contract_address: alloy_primitives::Address,
+    /// ValidatorManager contract address, the one of the Emerald genesis if given, and
+    /// 0x0000000000000000000000000000000000002000 otherwise
+    #[clap(long, short)]
+    contract_address: Option<Address>,
+
+    /// Emerald genesis file of the network, to read the ValidatorManager contract address from
+    #[clap(long, value_hint = ValueHint::FilePath, conflicts_with = "contract_address")]
+    emerald_genesis: Option<String>,
 
     #[command(subcommand)]
     command: PoaCommands,
 }
 
 impl PoaCmd {
+    fn contract_address(&self) -> Result<Address> {
+        if let Some(address) = self.contract_address {
+            return Ok(address);
+        }
+        match &self.emerald_genesis {
+            Some(path) => {
+                let genesis: EmeraldGenesis = serde_json::from_str(&std::fs::read_to_string(path)?)
+                    .map_err(|e| eyre!("invalid Emerald genesis file '{path}': {e}"))?;
+                Ok(genesis.validator_manager_address().to_alloy_address())
+            }
+            None => Ok(GENESIS_VALIDATOR_MANAGER_ACCOUNT),
+        }
+    }
+
     pub async fn run(&self) -> Result<()> {
         match &self.command {
             PoaCommands::AddValidator {
@@ -206,7 +486,7 @@ impl PoaCmd {
                 owner_private_key,
             } => {
                 let url = &self.rpc_url;
-                let address = &self.contract_address;
+                let address = &self.contract_address()?;
                 poa::add_validator(url, address, validator_pubkey, *power, owner_private_key).await
             }
             PoaCommands::RemoveValidator {
@@ -214,13 +494,17 @@ impl PoaCmd {
                 owner_private_key,
             } => {
                 let url = &self.rpc_url;
-                let address = &self.contract_address;
+                let address = &self.contract_address()?;
                 poa::remove_validator(url, address, validator_identifier, owner_private_key).await
             }
-            PoaCommands::List {} => {
+            PoaCommands::List { block, watch } => {
                 let url = &self.rpc_url;
-                let address = &self.contract_address;
-                poa::list_validators(url, address).await
+                let address = &self.contract_address()?;
+                if *watch {
+                    poa::watch_validators(url, address).await
+                } else {
+                    poa::list_validators(url, address, *block).await
+                }
             }
             PoaCommands::UpdateValidator {
                 validator_identifier,
@@ -228,7 +512,7 @@ impl PoaCmd {
                 owner_private_key,
             } => {
                 let url = &self.rpc_url;
-                let address = &self.contract_address;
+                let address = &self.contract_address()?;
                 poa::update_validator_power(
                     url,
                     address,
@@ -281,7 +565,17 @@ pub enum PoaCommands {
         #[clap(long, short)]
         owner_private_key: String,
     },
-    List {},
+    /// List the validators
+    List {
+        /// Block at which the validators are listed: a number, a hash, or a tag such as
+        /// `latest`. Blocks which are not recent require an archive execution client.
+        #[clap(long, short, default_value = "latest", value_parser = poa::parse_block_id)]
+        block: BlockId,
+
+        /// Print the changes of the validator set at each new block, until interrupted
+        #[clap(long, short, conflicts_with = "block")]
+        watch: bool,
+    },
 }
 
 #[derive(Parser, Debug, Clone, Default, PartialEq)]
@@ -298,6 +592,14 @@ pub struct SpamContractCmd {
     /// URL of the execution client's RPC endpoint
     #[clap(long, default_value = "127.0.0.1:8645")]
     rpc_url: String,
+    /// RPC endpoints of several execution clients the transactions are distributed across,
+    /// instead of `--rpc-url` (e.g., 127.0.0.1:8645,127.0.0.1:18645)
+    #[clap(long, value_delimiter = ',')]
+    rpc_urls: Vec<String>,
+    /// Relative share of the transactions sent to each of the `--rpc-urls`, in the same order
+    /// (e.g., 2,1). The endpoints take turns in round-robin by default
+    #[clap(long, value_delimiter = ',', requires = "rpc_urls")]
+    rpc_weights: Vec<u32>,
     /// Number of transactions to send
     #[clap(short, long, default_value_t = 0)]
     num_txs: u64,
@@ -310,6 +612,18 @@ pub struct SpamContractCmd {
     /// Time to run the spammer for in seconds
     #[clap(short, long, default_value_t = 0)]
     time: u64,
+    /// Re-submit the missing nonces with bumped fees once the pending nonce has been stuck
+    /// at the latest nonce for this many seconds (0 to disable)
+    #[clap(long, default_value_t = 0)]
+    repair_after: u64,
+    /// Prometheus endpoint of an Emerald node (e.g., http://127.0.0.1:29000/metrics).
+    /// When set, the rate is reduced while rounds fail or blocks are slow, and increased
+    /// back up to `rate` while consensus keeps up
+    #[clap(long)]
+    metrics_url: Option<String>,
+    /// Block time in ms above which the rate is reduced, when `--metrics-url` is set
+    #[clap(long, default_value = "2000")]
+    max_block_time: u64,
 
     #[clap(long, default_value_t = 0)]
     signer_index: usize,
@@ -324,14 +638,26 @@ impl SpamContractCmd {
             function,
             args,
             rpc_url,
+            rpc_urls,
+            rpc_weights,
             num_txs,
             rate,
             interval,
             time,
+            repair_after,
+            metrics_url,
+            max_block_time,
             signer_index,
             chain_id,
         } = self;
-        let url = format!("http://{rpc_url}").parse()?;
+        let urls = if rpc_urls.is_empty() {
+            vec![format!("http://{rpc_url}").parse()?]
+        } else {
+            rpc_urls
+                .iter()
+                .map(|url| format!("http://{url}").parse())
+                .collect::<Result<Vec<Url>, _>>()?
+        };
         let config = spammer::SpammerConfig {
             max_num_txs: *num_txs,
             max_time: *time,
@@ -339,10 +665,19 @@ impl SpamContractCmd {
             batch_interval: *interval,
             blobs: false,
             chain_id: *chain_id,
+            repair_after: *repair_after,
+            load_control: load_control_config(metrics_url.as_deref(), *max_block_time)?,
         };
-        Spammer::new_contract(url, *signer_index, config, contract, function, args)?
-            .run()
-            .await
+        Spammer::new_contract(
+            &rpc_targets(urls, rpc_weights)?,
+            *signer_index,
+            config,
+            contract,
+            function,
+            args,
+        )?
+        .run()
+        .await
     }
 }
 
@@ -362,3 +697,91 @@ impl ModifyConfigCmd {
         modify_config::apply_custom_config(&self.node_config_home, &self.custom_config_file_path)
     }
 }
+
+#[derive(Parser, Debug, Clone, PartialEq)]
+pub struct ItfCmd {
+    /// Event logs of the nodes, including rotated files
+    #[clap(required = true, value_hint = ValueHint::FilePath)]
+    event_logs: Vec<std::path::PathBuf>,
+
+    /// Name of the node of a proposer in the trace, as `ADDRESS=NODE` (can be repeated).
+    /// Proposers without a name are identified by their address.
+    #[clap(long = "proposer", value_name = "ADDRESS=NODE")]
+    proposers: Vec<String>,
+
+    /// Output path of the ITF trace
+    #[clap(long, short = 'o', value_hint = ValueHint::FilePath, default_value = "./trace.itf.json")]
+    output: std::path::PathBuf,
+}
+
+impl ItfCmd {
+    pub fn run(&self) -> Result<()> {
+        let proposers = self
+            .proposers
+            .iter()
+            .map(|p| {
+                p.split_once('=')
+                    .map(|(address, node)| (address.to_string(), node.to_string()))
+                    .ok_or_else(|| eyre!("Invalid proposer `{p}`, expected ADDRESS=NODE"))
+            })
+            .collect::<Result<_>>()?;
+
+        let entries = emerald_mbt::read_event_logs(&self.event_logs)?;
+        let trace = emerald_mbt::to_itf_trace(&entries, proposers)?;
+
+        std::fs::write(&self.output, serde_json::to_string_pretty(&trace)?)?;
+        println!(
+            "Wrote {} states to {}",
+            trace["states"].as_array().map_or(0, Vec::len),
+            self.output.display()
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone, PartialEq)]
+pub struct FixturesCmd {
+    /// Number of blocks to generate
+    #[clap(long)]
+    blocks: u64,
+
+    /// Number of transactions in each block
+    #[clap(long, default_value_t = 10)]
+    txs_per_block: usize,
+
+    /// Relative weights of the kinds of transactions in each block (`transfer`, `contract-call`)
+    #[clap(long, default_value = "transfer=1")]
+    tx_mix: fixtures::TxMix,
+
+    /// Output directory of the fixture, which must not exist
+    #[clap(long, short = 'o', value_hint = ValueHint::DirPath)]
+    output: std::path::PathBuf,
+
+    /// Path to the `emerald` executable
+    #[clap(long, default_value = "./target/debug/emerald")]
+    emerald_bin: String,
+
+    /// Path to the `emerald-utils` executable
+    #[clap(long, default_value = "./target/debug/emerald-utils")]
+    emerald_utils_bin: String,
+
+    /// Path to the `custom-reth` executable
+    #[clap(long, default_value = "./custom-reth/target/debug/custom-reth")]
+    custom_reth_bin: String,
+}
+
+impl FixturesCmd {
+    pub async fn run(&self) -> Result<()> {
+        fixtures::generate(&fixtures::FixturesConfig {
+            blocks: self.blocks,
+            txs_per_block: self.txs_per_block,
+            tx_mix: self.tx_mix.clone(),
+            output: self.output.clone(),
+            emerald_bin: self.emerald_bin.clone(),
+            emerald_utils_bin: self.emerald_utils_bin.clone(),
+            custom_reth_bin: self.custom_reth_bin.clone(),
+        })
+        .await
+    }
+}
@@ -0,0 +1,185 @@
+//! Closed-loop control of the load sent by the spammer, based on the health of consensus.
+//!
+//! The controller scrapes the metrics endpoint of an Emerald node and scales the rate of
+//! the spammer down when rounds fail or blocks take too long to be decided, and back up
+//! while consensus keeps up, so that the network stays at its sustainable maximum.
+
+use reqwest::Url;
+use tokio::time::{Duration, Instant};
+use tracing::debug;
+
+/// Metric holding the current consensus height
+const HEIGHT_METRIC: &str = "malachitebft_core_consensus_height";
+
+/// Metric holding the current consensus round, above 0 once a round failed
+const ROUND_METRIC: &str = "malachitebft_core_consensus_round";
+
+/// Minimum time between two scrapes of the metrics endpoint
+const SCRAPE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fraction of the maximum rate below which the controller does not back off
+const MIN_FACTOR: f64 = 0.01;
+
+/// Fraction of the maximum rate recovered after each healthy scrape
+const RECOVERY_STEP: f64 = 0.1;
+
+/// Configuration of the load controller.
+#[derive(Clone, Debug)]
+pub struct LoadControlConfig {
+    /// Prometheus endpoint of an Emerald node, e.g. http://127.0.0.1:29000/metrics
+    pub metrics_url: Url,
+    /// Block time above which the load is reduced
+    pub max_block_time: Duration,
+}
+
+/// Scales the rate of the spammer, halving it when consensus is unhealthy and
+/// increasing it linearly while healthy.
+pub struct LoadController {
+    config: LoadControlConfig,
+    client: reqwest::Client,
+    /// Fraction of the maximum rate to send at
+    factor: f64,
+    last_scrape: Option<Instant>,
+    /// Latest height seen, and when it was first seen
+    last_height: Option<(u64, Instant)>,
+}
+
+impl LoadController {
+    pub fn new(config: LoadControlConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            factor: 1.0,
+            last_scrape: None,
+            last_height: None,
+        }
+    }
+
+    /// Returns the fraction of the maximum rate to send at, scraping the metrics
+    /// endpoint if the last scrape is old enough. Scrape failures keep the current rate.
+    pub async fn factor(&mut self) -> f64 {
+        if self
+            .last_scrape
+            .is_some_and(|last| last.elapsed() < SCRAPE_INTERVAL)
+        {
+            return self.factor;
+        }
+        self.last_scrape = Some(Instant::now());
+
+        match self.scrape().await {
+            Ok(Some((height, round))) => self.update(height, round, Instant::now()),
+            Ok(None) => debug!("Consensus metrics not found at {}", self.config.metrics_url),
+            Err(e) => debug!("Failed to scrape {}: {e}", self.config.metrics_url),
+        }
+
+        self.factor
+    }
+
+    async fn scrape(&self) -> reqwest::Result<Option<(u64, u64)>> {
+        let body = self
+            .client
+            .get(self.config.metrics_url.clone())
+            .timeout(SCRAPE_INTERVAL)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let height = gauge_value(&body, HEIGHT_METRIC);
+        let round = gauge_value(&body, ROUND_METRIC);
+
+        Ok(height
+            .zip(round)
+            .map(|(h, r)| (h as u64, r.max(0.0) as u64)))
+    }
+
+    fn update(&mut self, height: u64, round: u64, now: Instant) {
+        // Time per block since the last new height, or since the last block was decided
+        let block_time = match self.last_height {
+            Some((last, since)) if height > last => {
+                self.last_height = Some((height, now));
+                now.saturating_duration_since(since) / (height - last) as u32
+            }
+            Some((_, since)) => now.saturating_duration_since(since),
+            None => {
+                self.last_height = Some((height, now));
+                Duration::ZERO
+            }
+        };
+
+        let previous = self.factor;
+        if round > 0 || block_time > self.config.max_block_time {
+            self.factor = (self.factor / 2.0).max(MIN_FACTOR);
+        } else {
+            self.factor = (self.factor + RECOVERY_STEP).min(1.0);
+        }
+
+        if self.factor != previous {
+            debug!(
+                "Consensus at height {height}, round {round}, block time {block_time:?}: \
+                 sending at {:.0}% of the maximum rate",
+                self.factor * 100.0
+            );
+        }
+    }
+}
+
+/// Returns the highest value of the samples of a metric in the Prometheus text format.
+fn gauge_value(body: &str, name: &str) -> Option<f64> {
+    body.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let rest = line.strip_prefix(name)?;
+            let value = match rest.chars().next()? {
+                '{' => &rest[rest.rfind('}')? + 1..],
+                ' ' => rest,
+                _ => return None,
+            };
+            value.split_whitespace().next()?.parse::<f64>().ok()
+        })
+        .reduce(f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gauge_value() {
+        let body = "\
+# HELP malachitebft_core_consensus_height Consensus height
+# TYPE malachitebft_core_consensus_height gauge
+malachitebft_core_consensus_height{moniker=\"node 0\"} 41
+malachitebft_core_consensus_height{moniker=\"node 1\"} 42 1700000000000
+malachitebft_core_consensus_height_total 7
+malachitebft_core_consensus_round 0
+";
+        assert_eq!(gauge_value(body, HEIGHT_METRIC), Some(42.0));
+        assert_eq!(gauge_value(body, ROUND_METRIC), Some(0.0));
+        assert_eq!(gauge_value(body, "missing"), None);
+    }
+
+    #[test]
+    fn test_back_off_and_recover() {
+        let mut controller = LoadController::new(LoadControlConfig {
+            metrics_url: "http://127.0.0.1:29000/metrics".parse().unwrap(),
+            max_block_time: Duration::from_secs(2),
+        });
+        let now = Instant::now();
+
+        controller.update(10, 0, now);
+        assert_eq!(controller.factor, 1.0);
+
+        // A failed round halves the rate
+        controller.update(11, 1, now + Duration::from_secs(1));
+        assert_eq!(controller.factor, 0.5);
+
+        controller.update(12, 0, now + Duration::from_secs(2));
+        assert!((controller.factor - 0.6).abs() < 1e-9);
+
+        // No block for longer than the maximum block time halves it again
+        controller.update(12, 0, now + Duration::from_secs(5));
+        assert!((controller.factor - 0.3).abs() < 1e-9);
+    }
+}
@@ -1,6 +1,12 @@
+use core::fmt;
+use core::str::FromStr;
+use core::time::Duration;
+use std::collections::{BTreeMap, BTreeSet};
+
 use alloy_network::EthereumWallet;
 use alloy_primitives::{Address, U256};
-use alloy_provider::ProviderBuilder;
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::BlockId;
 use alloy_signer::utils::raw_public_key_to_address;
 use alloy_signer_local::PrivateKeySigner;
 use color_eyre::eyre;
@@ -100,45 +106,188 @@ pub fn parse_validator_identifier(identifier: &str) -> Result<Address> {
     }
 }
 
-// list validators
-pub async fn list_validators(rpc_url: &Url, contract_address: &Address) -> Result<()> {
+/// Interval between the polls of the latest block when watching the validator set
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Parses a block given by its number, in decimal or in hex with a `0x` prefix, its hash,
+/// or a tag such as `latest`
+pub fn parse_block_id(block: &str) -> Result<BlockId> {
+    if let Ok(number) = block.parse::<u64>() {
+        return Ok(BlockId::number(number));
+    }
+
+    BlockId::from_str(block).map_err(|e| eyre::eyre!("Invalid block `{block}`: {e}"))
+}
+
+/// Validator registered in the ValidatorManager contract
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ListedValidator {
+    address: Address,
+    /// Uncompressed SEC1 public key
+    public_key: Vec<u8>,
+    power: u64,
+}
+
+/// Change of a validator between the validator sets of two blocks
+#[derive(Debug, PartialEq, Eq)]
+enum ValidatorChange {
+    Added {
+        address: Address,
+        power: u64,
+    },
+    Removed {
+        address: Address,
+        power: u64,
+    },
+    PowerUpdated {
+        address: Address,
+        from: u64,
+        to: u64,
+    },
+}
+
+impl fmt::Display for ValidatorChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { address, power } => write!(f, "added 0x{address:x} with power {power}"),
+            Self::Removed { address, power } => {
+                write!(f, "removed 0x{address:x} with power {power}")
+            }
+            Self::PowerUpdated { address, from, to } => {
+                write!(f, "updated the power of 0x{address:x} from {from} to {to}")
+            }
+        }
+    }
+}
+
+/// Changes from the validators `old` to the validators `new`, in the order of their addresses
+fn validator_set_changes(old: &[ListedValidator], new: &[ListedValidator]) -> Vec<ValidatorChange> {
+    let old = old
+        .iter()
+        .map(|v| (v.address, v.power))
+        .collect::<BTreeMap<_, _>>();
+    let new = new
+        .iter()
+        .map(|v| (v.address, v.power))
+        .collect::<BTreeMap<_, _>>();
+
+    let addresses = old
+        .keys()
+        .chain(new.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+    addresses
+        .into_iter()
+        .filter_map(|address| match (old.get(&address), new.get(&address)) {
+            (None, Some(&power)) => Some(ValidatorChange::Added { address, power }),
+            (Some(&power), None) => Some(ValidatorChange::Removed { address, power }),
+            (Some(&from), Some(&to)) if from != to => {
+                Some(ValidatorChange::PowerUpdated { address, from, to })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Validators of the contract at `block`, by descending power
+async fn validators_at(
+    provider: &impl Provider,
+    contract_address: &Address,
+    block: BlockId,
+) -> Result<Vec<ListedValidator>> {
+    let contract = ValidatorManager::new(*contract_address, provider);
+    let validators = contract
+        .getValidators()
+        .block(block)
+        .call()
+        .await
+        .with_context(|| format!("Failed to read the validators at block {block}"))?;
+
+    let mut validators = validators
+        .into_iter()
+        .map(|validator| {
+            let mut public_key = Vec::with_capacity(65);
+            public_key.push(0x04); // uncompressed prefix
+            public_key.extend_from_slice(&validator.validatorKey.x.to_be_bytes::<32>());
+            public_key.extend_from_slice(&validator.validatorKey.y.to_be_bytes::<32>());
+
+            let pubkey = PublicKey::from_sec1_bytes(&public_key)
+                .map_err(|e| eyre::eyre!("Invalid public key bytes: {}", e))?;
+            let address =
+                raw_public_key_to_address(&pubkey.to_encoded_point(false).as_bytes()[1..]);
+
+            Ok(ListedValidator {
+                address: Address::from_slice(address.as_slice()),
+                public_key,
+                power: validator.power,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // sort validators by power descending
+    validators.sort_by_key(|v| core::cmp::Reverse(v.power));
+    Ok(validators)
+}
+
+/// List the validators at `block`, which must be recent unless the execution client
+/// is an archive node
+pub async fn list_validators(
+    rpc_url: &Url,
+    contract_address: &Address,
+    block: BlockId,
+) -> Result<()> {
     let provider = ProviderBuilder::new().connect_http(rpc_url.clone());
 
     let contract = ValidatorManager::new(*contract_address, &provider);
 
-    let poa_owner_address = contract.owner().call().await?.0;
+    let poa_owner_address = contract.owner().block(block).call().await?.0;
     println!("POA Owner Address: 0x{poa_owner_address:x}");
     println!();
 
-    let validators = contract.getValidators().call().await?;
+    let validators = validators_at(&provider, contract_address, block).await?;
 
     println!("Total validators: {}", validators.len());
     println!();
 
-    // sort validators by power descending
-    let mut validators = validators;
-    validators.sort_by_key(|b| core::cmp::Reverse(b.power));
-
     for (i, validator) in validators.iter().enumerate() {
         println!("Validator #{}:", i + 1);
         println!("  Power: {}", validator.power);
-        // validator pubkey in hex
-        let mut pubkey_bytes = Vec::with_capacity(65);
-        pubkey_bytes.push(0x04); // uncompressed prefix
-        pubkey_bytes.extend_from_slice(&validator.validatorKey.x.to_be_bytes::<32>());
-        pubkey_bytes.extend_from_slice(&validator.validatorKey.y.to_be_bytes::<32>());
-        println!("  Pubkey: {}", hex::encode(&pubkey_bytes));
-        // print validator address 0x
-        let pubkey = PublicKey::from_sec1_bytes(&pubkey_bytes)
-            .map_err(|e| color_eyre::eyre::eyre!("Invalid public key bytes: {}", e))?;
-        let address = raw_public_key_to_address(&pubkey.to_encoded_point(false).as_bytes()[1..]);
-        println!("Validator address: 0x{address:x}");
+        println!("  Pubkey: {}", hex::encode(&validator.public_key));
+        println!("Validator address: 0x{:x}", validator.address);
         println!();
     }
 
     Ok(())
 }
 
+/// Print the changes of the validator set at each new block, until interrupted
+pub async fn watch_validators(rpc_url: &Url, contract_address: &Address) -> Result<()> {
+    let provider = ProviderBuilder::new().connect_http(rpc_url.clone());
+
+    let mut number = provider.get_block_number().await?;
+    let mut validators = validators_at(&provider, contract_address, number.into()).await?;
+    println!(
+        "Watching the validator set from block {number}: {} validators, total power {}",
+        validators.len(),
+        validators.iter().map(|v| v.power).sum::<u64>()
+    );
+
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+
+        let latest = provider.get_block_number().await?;
+        while number < latest {
+            number += 1;
+            let next = validators_at(&provider, contract_address, number.into()).await?;
+
+            for change in validator_set_changes(&validators, &next) {
+                println!("Block {number}: {change}");
+            }
+            validators = next;
+        }
+    }
+}
+
 /// Add a validator to the PoA validator set
 pub async fn add_validator(
     rpc_url: &Url,
@@ -304,3 +453,59 @@ pub async fn update_validator_power(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+
+    use super::*;
+
+    fn validator(byte: u8, power: u64) -> ListedValidator {
+        ListedValidator {
+            address: Address::repeat_byte(byte),
+            public_key: Vec::new(),
+            power,
+        }
+    }
+
+    #[test]
+    fn test_parse_block_id() {
+        assert_eq!(parse_block_id("42").unwrap(), BlockId::number(42));
+        assert_eq!(parse_block_id("0x2a").unwrap(), BlockId::number(42));
+        assert_eq!(parse_block_id("latest").unwrap(), BlockId::latest());
+
+        let hash = B256::repeat_byte(0xab);
+        assert_eq!(
+            parse_block_id(&hash.to_string()).unwrap(),
+            BlockId::from(hash)
+        );
+
+        assert!(parse_block_id("block").is_err());
+    }
+
+    #[test]
+    fn test_validator_set_changes() {
+        let old = [validator(1, 100), validator(2, 100), validator(3, 50)];
+        let new = [validator(1, 100), validator(3, 80), validator(4, 10)];
+
+        assert_eq!(
+            validator_set_changes(&old, &new),
+            [
+                ValidatorChange::Removed {
+                    address: Address::repeat_byte(2),
+                    power: 100
+                },
+                ValidatorChange::PowerUpdated {
+                    address: Address::repeat_byte(3),
+                    from: 50,
+                    to: 80
+                },
+                ValidatorChange::Added {
+                    address: Address::repeat_byte(4),
+                    power: 10
+                },
+            ]
+        );
+        assert!(validator_set_changes(&new, &new).is_empty());
+    }
+}
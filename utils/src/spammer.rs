@@ -1,5 +1,6 @@
+use core::cmp::Reverse;
 use core::fmt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use alloy_network::eip2718::Encodable2718;
@@ -7,22 +8,93 @@ use alloy_primitives::Address;
 use alloy_rpc_types_txpool::TxpoolStatus;
 use alloy_signer_local::PrivateKeySigner;
 use color_eyre::eyre::{self, Result};
+use emerald_retry::{retry_with_clock, Backoff, SharedClock, SystemClock};
 use jsonrpsee_core::client::ClientT;
 use jsonrpsee_core::params::{ArrayParams, BatchRequestBuilder};
 use jsonrpsee_http_client::{HttpClient, HttpClientBuilder};
 use reqwest::Url;
+use reth_primitives::Transaction;
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use serde_json::json;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::time::{self, sleep, Duration, Instant};
-use tracing::debug;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
 
+use crate::load_control::{LoadControlConfig, LoadController};
 use crate::make_signers;
-use crate::tx::{make_signed_contract_call_tx, make_signed_eip1559_tx, make_signed_eip4844_tx};
+use crate::tx::{
+    bump_fees, make_contract_call_tx, make_eip1559_tx, make_eip4844_tx, sign_transaction,
+};
 
 /// Target pool size to maintain (in number of transactions).
 const TARGET_POOL_SIZE: u64 = 30_000;
 
+/// Maximum number of nonces re-submitted by a single repair of the nonce gaps.
+const MAX_REPAIRED_NONCES: u64 = 1_000;
+
+/// Maximum sum of the weights of the RPC endpoints, bounding the round-robin cycle.
+pub const MAX_TOTAL_WEIGHT: u64 = 10_000;
+
+/// Maximum time to wait for the node to be reachable again, e.g. while it restarts.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Delays between the attempts to reach the node again.
+fn reconnect_backoff() -> Backoff {
+    Backoff::exponential(Duration::from_millis(100), Duration::from_secs(5), 2.0).with_jitter(0.2)
+}
+
+/// Whether an RPC error is caused by the connection to the node rather than by the request.
+fn is_connection_error(err: &eyre::Report) -> bool {
+    matches!(
+        err.downcast_ref::<jsonrpsee_core::client::Error>(),
+        Some(
+            jsonrpsee_core::client::Error::Transport(_)
+                | jsonrpsee_core::client::Error::RestartNeeded(_)
+        )
+    )
+}
+
+/// RPC endpoint of an execution client the transactions are sent to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcTarget {
+    pub url: Url,
+    /// Share of the transactions sent to this endpoint, relative to the other endpoints.
+    pub weight: u32,
+}
+
+/// Order in which the endpoints of `weights` receive the transactions, by smooth weighted
+/// round-robin: each endpoint appears as many times as its weight in the cycle, spread
+/// over it rather than in a row.
+fn round_robin_cycle(weights: &[u32]) -> Vec<usize> {
+    let total = weights.iter().map(|w| i64::from(*w)).sum::<i64>();
+    let mut current = vec![0i64; weights.len()];
+
+    (0..total)
+        .map(|_| {
+            for (current, weight) in current.iter_mut().zip(weights) {
+                *current += i64::from(*weight);
+            }
+            let (next, _) = current
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, current)| (**current, Reverse(*i)))
+                .expect("at least one endpoint");
+            current[next] -= total;
+            next
+        })
+        .collect()
+}
+
+/// Signed transaction of a batch, as the params of `eth_sendRawTransaction`.
+struct BatchEntry {
+    nonce: u64,
+    params: Vec<serde_json::Value>,
+    /// Size of the encoded transaction, in bytes.
+    len: u64,
+}
+
 struct ContractPayload {
     /// Contract address for contract call spamming.
     address: Address,
@@ -46,15 +118,28 @@ pub struct SpammerConfig {
     pub blobs: bool,
     /// Chain ID for the transactions.
     pub chain_id: u64,
+    /// Number of seconds the pending nonce may stay equal to the latest nonce, while
+    /// transactions are in flight, before the missing nonces are re-submitted (0 to disable).
+    pub repair_after: u64,
+    /// Optional control of the rate by the health of consensus.
+    pub load_control: Option<LoadControlConfig>,
 }
 
 /// A transaction spammer that sends Ethereum transactions at a controlled rate.
 /// Tracks and reports statistics on sent transactions.
+///
+/// With several RPC endpoints, consecutive nonces are sent to different endpoints, so that
+/// each execution client receives from the others, through the gossip of transactions, the
+/// nonces it is missing. The nonces and the pool of the account are read from the first one.
 pub struct Spammer {
     /// Spammer identifier.
     id: String,
-    /// Client for Ethereum RPC node server.
+    /// Client for the first RPC endpoint, from which the nonces and the pool are read.
     client: RpcClient,
+    /// Clients for the RPC endpoints the transactions are sent to.
+    targets: Vec<(Url, RpcClient)>,
+    /// Index in `targets` of the endpoint of each nonce, modulo the length of the cycle.
+    cycle: Vec<usize>,
     /// Ethereum transaction signer.
     signer: PrivateKeySigner,
     /// Maximum number of transactions to send (0 for no limit).
@@ -69,16 +154,25 @@ pub struct Spammer {
     blobs: bool,
     /// Chain ID for the transactions.
     chain_id: u64,
+    /// Number of seconds a nonce may be stuck before it is repaired (0 to disable).
+    repair_after: u64,
+    /// Optional control of the rate by the health of consensus.
+    load_control: Option<LoadControlConfig>,
     /// Optional payload describing contract call spam parameters.
     contract_payload: Option<ContractPayload>,
+    /// Clock pacing the batches and measuring the statistics.
+    clock: SharedClock,
 }
 
 impl Spammer {
-    pub fn new(url: Url, signer_index: usize, config: SpammerConfig) -> Result<Self> {
+    pub fn new(targets: &[RpcTarget], signer_index: usize, config: SpammerConfig) -> Result<Self> {
         let signers = make_signers();
+        let (client, clients, cycle) = rpc_clients(targets)?;
         Ok(Self {
             id: signer_index.to_string(),
-            client: RpcClient::new(url)?,
+            client,
+            targets: clients,
+            cycle,
             signer: signers[signer_index].clone(),
             max_num_txs: config.max_num_txs,
             max_time: config.max_time,
@@ -86,12 +180,15 @@ impl Spammer {
             batch_interval: config.batch_interval,
             blobs: config.blobs,
             chain_id: config.chain_id,
+            repair_after: config.repair_after,
+            load_control: config.load_control,
             contract_payload: None,
+            clock: SystemClock::shared(),
         })
     }
 
     pub fn new_contract(
-        url: Url,
+        targets: &[RpcTarget],
         signer_index: usize,
         config: SpammerConfig,
         contract: &Address,
@@ -104,9 +201,12 @@ impl Spammer {
             function_sig: function.to_string(),
             args: args.to_vec(),
         };
+        let (client, clients, cycle) = rpc_clients(targets)?;
         Ok(Self {
             id: signer_index.to_string(),
-            client: RpcClient::new(url)?,
+            client,
+            targets: clients,
+            cycle,
             signer: signers[signer_index].clone(),
             max_num_txs: config.max_num_txs,
             max_time: config.max_time,
@@ -115,9 +215,16 @@ impl Spammer {
             blobs: false, // Contract calls don't use blobs
             contract_payload: Some(contract_payload),
             chain_id: config.chain_id,
+            repair_after: config.repair_after,
+            load_control: config.load_control,
+            clock: SystemClock::shared(),
         })
     }
 
+    pub fn with_clock(self, clock: SharedClock) -> Self {
+        Self { clock, ..self }
+    }
+
     pub async fn run(self) -> Result<()> {
         // Create channels for communication between spammer and tracker.
         let (result_sender, result_receiver) = mpsc::channel::<Result<u64>>(10000);
@@ -154,13 +261,36 @@ impl Spammer {
 
     // Fetch from an Ethereum node the latest used nonce for the given address.
     async fn get_latest_nonce(&self, address: Address) -> Result<u64> {
-        let response: String = self
-            .client
-            .rpc_request(
-                "eth_getTransactionCount",
-                vec![json!(address), json!("latest")],
-            )
-            .await?;
+        self.get_nonce(address, "latest").await
+    }
+
+    // Fetch from an Ethereum node the next nonce of the given address, including the
+    // executable transactions of the pool.
+    async fn get_pending_nonce(&self, address: Address) -> Result<u64> {
+        self.get_nonce(address, "pending").await
+    }
+
+    async fn get_nonce(&self, address: Address, block: &str) -> Result<u64> {
+        // Wait for the node to be reachable again if it restarts
+        let response: String = retry_with_clock(
+            self.clock.as_ref(),
+            &reconnect_backoff(),
+            RECONNECT_TIMEOUT,
+            |result: &Result<String>, delay| {
+                let reconnect = result.as_ref().is_err_and(is_connection_error);
+                if reconnect {
+                    warn!("Node unreachable, reconnecting in {delay:?}");
+                }
+                reconnect
+            },
+            || {
+                self.client.rpc_request(
+                    "eth_getTransactionCount",
+                    vec![json!(address), json!(block)],
+                )
+            },
+        )
+        .await?;
         // Convert hex string to integer.
         let hex_str = response.as_str().strip_prefix("0x").unwrap();
         Ok(u64::from_str_radix(hex_str, 16)?)
@@ -200,14 +330,20 @@ impl Spammer {
 
         // Initialize nonce and counters.
         let mut nonce = latest_nonce;
-        let start_time = Instant::now();
+        let start_time = self.clock.now();
         let mut txs_sent_total = 0u64;
-        let mut interval = time::interval(Duration::from_millis(self.batch_interval));
+        let batch_interval = Duration::from_millis(self.batch_interval);
+        let mut next_tick = start_time;
+        let mut stuck_nonce = StuckNonce::default();
+        let mut load_controller = self.load_control.clone().map(LoadController::new);
 
         loop {
-            // Wait for next one-second tick.
-            let _ = interval.tick().await;
-            let interval_start = Instant::now();
+            // Wait for the next tick, right away if it is late
+            self.clock
+                .sleep(next_tick.saturating_duration_since(self.clock.now()))
+                .await;
+            next_tick += batch_interval;
+            let interval_start = self.clock.now();
 
             // Verify the nonce for gaps
             // TODO: probably this should run as a separate task
@@ -229,9 +365,8 @@ impl Spammer {
                     }
 
                     // Report individual results.
-                    for ((_, tx_bytes_len), result) in batch_entries.into_iter().zip(results) {
-                        let mapped_result = result.map(|_| tx_bytes_len);
-                        result_sender.send(mapped_result).await?;
+                    for (entry, result) in batch_entries.into_iter().zip(results) {
+                        result_sender.send(result.map(|_| entry.len)).await?;
                     }
                 } else {
                     debug!("Batch eth_sendRawTransaction timed out; skipping this tick");
@@ -240,9 +375,31 @@ impl Spammer {
                 }
             }
 
+            // Re-submit the missing nonces once the account has been stuck for too long
+            if self.repair_after > 0 && nonce > on_chain_nonce {
+                let pending_nonce = self.get_pending_nonce(address).await?;
+                let stuck_for = Duration::from_secs(self.repair_after);
+
+                if let Some(round) =
+                    stuck_nonce.observe(on_chain_nonce, pending_nonce, stuck_for, self.clock.now())
+                {
+                    self.repair_nonce_gaps(address, on_chain_nonce, nonce, round, &result_sender)
+                        .await?;
+                }
+            }
+
             // Get current pool size and calculate dynamic send rate
             let current_pool_size = self.get_mempool_count().await.unwrap_or(0);
             let space_available = TARGET_POOL_SIZE.saturating_sub(current_pool_size);
+
+            // Scale the batch down while consensus does not keep up
+            let txs_per_batch = match &mut load_controller {
+                Some(controller) => {
+                    (txs_per_batch as f64 * controller.factor().await).ceil() as u64
+                }
+                None => txs_per_batch,
+            };
+
             let txs_to_send = if space_available < txs_per_batch {
                 space_available
             } else {
@@ -284,9 +441,8 @@ impl Spammer {
                     }
 
                     // Report individual results.
-                    for ((_, tx_bytes_len), result) in batch_entries.into_iter().zip(results) {
-                        let mapped_result = result.map(|_| tx_bytes_len);
-                        result_sender.send(mapped_result).await?;
+                    for (entry, result) in batch_entries.into_iter().zip(results) {
+                        result_sender.send(result.map(|_| entry.len)).await?;
                     }
 
                     txs_sent_total += batch_size;
@@ -297,14 +453,14 @@ impl Spammer {
             }
 
             // Give time to the in-flight results to be received.
-            sleep(Duration::from_millis(20)).await;
+            self.clock.sleep(Duration::from_millis(20)).await;
 
             // Signal tracker to report stats after this batch.
             let _ = report_sender.send(interval_start).await;
 
             // Check exit conditions after each tick.
             if (self.max_num_txs > 0 && txs_sent_total >= self.max_num_txs)
-                || (self.max_time > 0 && start_time.elapsed().as_secs() >= self.max_time)
+                || (self.max_time > 0 && self.clock.elapsed(start_time).as_secs() >= self.max_time)
             {
                 break;
             }
@@ -313,66 +469,169 @@ impl Spammer {
         Ok(())
     }
 
-    async fn build_batch_entries(
-        &self,
-        tx_count: u64,
-        nonce: u64,
-    ) -> Result<Vec<(Vec<serde_json::Value>, u64)>> {
+    async fn build_batch_entries(&self, tx_count: u64, nonce: u64) -> Result<Vec<BatchEntry>> {
         let mut batch_entries = Vec::with_capacity(tx_count as usize);
-        let mut next_nonce = nonce;
-
-        for _ in 0..tx_count {
-            let signed_tx = if let Some(ref payload) = self.contract_payload {
-                make_signed_contract_call_tx(
-                    &self.signer,
-                    next_nonce,
-                    payload.address,
-                    &payload.function_sig,
-                    payload.args.as_slice(),
-                    self.chain_id,
-                )
-                .await?
-            } else if self.blobs {
-                make_signed_eip4844_tx(&self.signer, next_nonce, self.chain_id).await?
-            } else {
-                make_signed_eip1559_tx(&self.signer, next_nonce, self.chain_id).await?
-            };
 
-            let tx_bytes = signed_tx.encoded_2718();
-            let tx_bytes_len = tx_bytes.len() as u64;
-            let payload = hex::encode(tx_bytes);
-            batch_entries.push((vec![json!(payload)], tx_bytes_len));
-            next_nonce += 1;
+        for next_nonce in nonce..nonce + tx_count {
+            let tx = self.build_tx(next_nonce).await?;
+            batch_entries.push(self.encode_batch_entry(next_nonce, tx).await?);
         }
 
         Ok(batch_entries)
     }
 
+    async fn build_tx(&self, nonce: u64) -> Result<Transaction> {
+        if let Some(ref payload) = self.contract_payload {
+            make_contract_call_tx(
+                nonce,
+                payload.address,
+                &payload.function_sig,
+                payload.args.as_slice(),
+                self.chain_id,
+            )
+            .await
+        } else if self.blobs {
+            Ok(make_eip4844_tx(nonce, self.chain_id))
+        } else {
+            Ok(make_eip1559_tx(nonce, self.chain_id))
+        }
+    }
+
+    async fn encode_batch_entry(&self, nonce: u64, tx: Transaction) -> Result<BatchEntry> {
+        let signed_tx = sign_transaction(&self.signer, tx).await?;
+        let tx_bytes = signed_tx.encoded_2718();
+        let len = tx_bytes.len() as u64;
+        let payload = hex::encode(tx_bytes);
+        Ok(BatchEntry {
+            nonce,
+            params: vec![json!(payload)],
+            len,
+        })
+    }
+
+    /// Re-submits with bumped fees the nonces between `from` and `to` which are missing
+    /// from the pool, along with the nonce `from` itself, which is in the pool but not
+    /// executable, e.g. because its fees are below the base fee. The fees are doubled on
+    /// each `round` for which the account stays stuck, so that the re-submitted
+    /// transactions replace the previous ones.
+    async fn repair_nonce_gaps(
+        &self,
+        address: Address,
+        from: u64,
+        to: u64,
+        round: u32,
+        result_sender: &Sender<Result<u64>>,
+    ) -> Result<()> {
+        let content: TxpoolContentFrom = self
+            .client
+            .rpc_request("txpool_contentFrom", vec![json!(address)])
+            .await?;
+
+        let factor = 2u128.saturating_pow(round);
+        let nonces = (from..to)
+            .filter(|nonce| *nonce == from || !content.contains(*nonce))
+            .take(MAX_REPAIRED_NONCES as usize)
+            .collect::<Vec<_>>();
+
+        debug!(
+            "Nonce {from} stuck, re-submitting {} nonces up to {to} with fees bumped {factor}x",
+            nonces.len()
+        );
+
+        let mut batch_entries = Vec::with_capacity(nonces.len());
+        for nonce in nonces {
+            let tx = bump_fees(self.build_tx(nonce).await?, factor);
+            batch_entries.push(self.encode_batch_entry(nonce, tx).await?);
+        }
+
+        match self.send_raw_batch(&batch_entries).await? {
+            Some(results) => {
+                for (entry, result) in batch_entries.into_iter().zip(results) {
+                    result_sender.send(result.map(|_| entry.len)).await?;
+                }
+            }
+            None => debug!("Batch eth_sendRawTransaction timed out; repairing on next tick"),
+        }
+
+        Ok(())
+    }
+
+    /// Sends each transaction of the batch to the endpoint of its nonce, returning the
+    /// result of each transaction, or `None` if no endpoint could be reached in time.
     async fn send_raw_batch(
         &self,
-        batch_entries: &[(Vec<serde_json::Value>, u64)],
+        batch_entries: &[BatchEntry],
     ) -> Result<Option<Vec<Result<String>>>> {
-        let params: Vec<_> = batch_entries
-            .iter()
-            .map(|(params, _)| params.clone())
-            .collect();
+        // Positions in the batch of the transactions of each endpoint
+        let mut positions = vec![Vec::new(); self.targets.len()];
+        for (position, entry) in batch_entries.iter().enumerate() {
+            positions[self.target_of(entry.nonce)].push(position);
+        }
 
-        match self
-            .client
-            .rpc_batch_request("eth_sendRawTransaction", params)
-            .await
-        {
-            Ok(responses) => Ok(Some(responses)),
-            Err(err) => {
-                if let Some(jsonrpsee_core::client::Error::RequestTimeout) =
-                    err.downcast_ref::<jsonrpsee_core::client::Error>()
+        let mut requests = JoinSet::new();
+        for (target, positions) in positions.into_iter().enumerate() {
+            if positions.is_empty() {
+                continue;
+            }
+            let (url, client) = self.targets[target].clone();
+            let params = positions
+                .iter()
+                .map(|position| batch_entries[*position].params.clone())
+                .collect();
+            requests.spawn(async move {
+                let responses = client
+                    .rpc_batch_request("eth_sendRawTransaction", params)
+                    .await;
+                (url, positions, responses)
+            });
+        }
+
+        let mut results = batch_entries.iter().map(|_| None).collect::<Vec<_>>();
+        let mut reached = false;
+        while let Some(response) = requests.join_next().await {
+            let (url, positions, responses) = response?;
+            match responses {
+                Ok(responses) => {
+                    if responses.len() != positions.len() {
+                        return Err(eyre::eyre!(
+                            "Batch response count {} of {url} does not match request count {}",
+                            responses.len(),
+                            positions.len()
+                        ));
+                    }
+                    reached = true;
+                    for (position, response) in positions.into_iter().zip(responses) {
+                        results[position] = Some(response);
+                    }
+                }
+                // The node is reconnected to by the next nonce query
+                Err(err)
+                    if is_connection_error(&err)
+                        || matches!(
+                            err.downcast_ref::<jsonrpsee_core::client::Error>(),
+                            Some(jsonrpsee_core::client::Error::RequestTimeout)
+                        ) =>
                 {
-                    Ok(None)
-                } else {
-                    Err(err)
+                    debug!("Batch eth_sendRawTransaction to {url} failed: {err}");
+                    for position in positions {
+                        results[position] = Some(Err(eyre::eyre!("{url} unreachable")));
+                    }
                 }
+                Err(err) => return Err(err),
             }
         }
+
+        Ok(reached.then(|| {
+            results
+                .into_iter()
+                .map(|result| result.expect("every transaction is sent to an endpoint"))
+                .collect()
+        }))
+    }
+
+    /// Index in `targets` of the endpoint the transaction of `nonce` is sent to.
+    fn target_of(&self, nonce: u64) -> usize {
+        self.cycle[(nonce % self.cycle.len() as u64) as usize]
     }
 
     // Track and report statistics on sent transactions.
@@ -383,9 +642,8 @@ impl Spammer {
         mut finish_receiver: Receiver<()>,
     ) -> Result<()> {
         // Initialize counters
-        let start_time = Instant::now();
-        let mut stats_total = Stats::new(self.id.as_str(), start_time);
-        let mut stats_last_second = Stats::new(self.id.as_str(), start_time);
+        let mut stats_total = Stats::new(self.id.as_str(), self.clock.clone());
+        let mut stats_last_second = Stats::new(self.id.as_str(), self.clock.clone());
         loop {
             tokio::select! {
                 // Update counters
@@ -398,9 +656,9 @@ impl Spammer {
                 // Report stats
                 Some(interval_start) = report_receiver.recv() => {
                     // Wait what's missing to complete one second.
-                    let elapsed = interval_start.elapsed();
+                    let elapsed = self.clock.elapsed(interval_start);
                     if elapsed < Duration::from_secs(1) {
-                        sleep(Duration::from_secs(1) - elapsed).await;
+                        self.clock.sleep(Duration::from_secs(1) - elapsed).await;
                     }
 
                     let pool_status = self.get_txpool_status().await?;
@@ -421,9 +679,70 @@ impl Spammer {
     }
 }
 
+/// Transactions of an account in the pool, as returned by `txpool_contentFrom`,
+/// keyed by nonce.
+#[derive(Default, Deserialize)]
+struct TxpoolContentFrom {
+    #[serde(default)]
+    pending: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    queued: BTreeMap<String, serde_json::Value>,
+}
+
+impl TxpoolContentFrom {
+    fn contains(&self, nonce: u64) -> bool {
+        let nonce = nonce.to_string();
+        self.pending.contains_key(&nonce) || self.queued.contains_key(&nonce)
+    }
+}
+
+/// Detects an account whose pending nonce is stuck at its latest nonce, i.e. whose next
+/// transaction is missing from the pool or cannot be executed.
+#[derive(Default)]
+struct StuckNonce {
+    /// Latest nonce of the account, and since when the pending nonce is equal to it
+    since: Option<(u64, Instant)>,
+    /// Number of repairs since the latest nonce last advanced
+    rounds: u32,
+}
+
+impl StuckNonce {
+    /// Returns the round of the repair to make, if the pending nonce has been equal to
+    /// the latest nonce for at least `stuck_for` at `now`.
+    fn observe(
+        &mut self,
+        latest: u64,
+        pending: u64,
+        stuck_for: Duration,
+        now: Instant,
+    ) -> Option<u32> {
+        if pending > latest {
+            self.since = None;
+            return None;
+        }
+
+        match self.since {
+            Some((nonce, since)) if nonce == latest => {
+                if now.saturating_duration_since(since) < stuck_for {
+                    return None;
+                }
+                self.since = Some((latest, now));
+                self.rounds = self.rounds.saturating_add(1);
+                Some(self.rounds)
+            }
+            _ => {
+                self.since = Some((latest, now));
+                self.rounds = 0;
+                None
+            }
+        }
+    }
+}
+
 /// Statistics on sent transactions.
 struct Stats {
     id: String,
+    clock: SharedClock,
     start_time: Instant,
     succeed: u64,
     bytes: u64,
@@ -431,10 +750,11 @@ struct Stats {
 }
 
 impl Stats {
-    fn new(id: &str, start_time: Instant) -> Self {
+    fn new(id: &str, clock: SharedClock) -> Self {
         Self {
             id: id.to_string(),
-            start_time,
+            start_time: clock.now(),
+            clock,
             succeed: 0,
             bytes: 0,
             errors_counter: HashMap::new(),
@@ -473,7 +793,7 @@ impl Stats {
 
 impl fmt::Display for Stats {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let elapsed = self.start_time.elapsed().as_millis();
+        let elapsed = self.clock.elapsed(self.start_time).as_millis();
         let stats = format!(
             "[{}] elapsed {:.3}s: Sent {} txs ({} bytes)",
             self.id,
@@ -491,8 +811,34 @@ impl fmt::Display for Stats {
     }
 }
 
+/// Clients for the first endpoint of `targets`, for each endpoint, and the round-robin
+/// cycle of the endpoints.
+fn rpc_clients(targets: &[RpcTarget]) -> Result<(RpcClient, Vec<(Url, RpcClient)>, Vec<usize>)> {
+    let first = targets
+        .first()
+        .ok_or_else(|| eyre::eyre!("At least one RPC endpoint is required"))?;
+    let total_weight = targets.iter().map(|t| u64::from(t.weight)).sum::<u64>();
+    if targets.iter().any(|t| t.weight == 0) || total_weight > MAX_TOTAL_WEIGHT {
+        return Err(eyre::eyre!(
+            "The weights of the RPC endpoints must be positive, and sum to at most {MAX_TOTAL_WEIGHT}"
+        ));
+    }
+
+    let clients = targets
+        .iter()
+        .map(|t| Ok((t.url.clone(), RpcClient::new(t.url.clone())?)))
+        .collect::<Result<Vec<_>>>()?;
+    let weights = targets.iter().map(|t| t.weight).collect::<Vec<_>>();
+
+    Ok((
+        RpcClient::new(first.url.clone())?,
+        clients,
+        round_robin_cycle(&weights),
+    ))
+}
+
 #[derive(Clone)]
-struct RpcClient {
+pub(crate) struct RpcClient {
     client: HttpClient,
 }
 
@@ -540,3 +886,43 @@ impl RpcClient {
             .collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use emerald_retry::{Clock, MockClock};
+
+    use super::*;
+
+    #[test]
+    fn test_stuck_nonce() {
+        let clock = MockClock::new();
+        let stuck_for = Duration::from_secs(10);
+        let mut stuck_nonce = StuckNonce::default();
+
+        // The pending nonce is stuck at the latest nonce from now on
+        assert_eq!(stuck_nonce.observe(5, 5, stuck_for, clock.now()), None);
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(stuck_nonce.observe(5, 5, stuck_for, clock.now()), None);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(stuck_nonce.observe(5, 5, stuck_for, clock.now()), Some(1));
+
+        // Repaired again once stuck for as long
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(stuck_nonce.observe(5, 5, stuck_for, clock.now()), Some(2));
+
+        // Unstuck once the pool holds the next transaction, then stuck at a new nonce
+        assert_eq!(stuck_nonce.observe(5, 6, stuck_for, clock.now()), None);
+        assert_eq!(stuck_nonce.observe(6, 6, stuck_for, clock.now()), None);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(stuck_nonce.observe(6, 6, stuck_for, clock.now()), Some(1));
+    }
+
+    #[test]
+    fn test_round_robin_cycle() {
+        assert_eq!(round_robin_cycle(&[1]), vec![0]);
+        assert_eq!(round_robin_cycle(&[1, 1, 1]), vec![0, 1, 2]);
+        // The transactions of the heavier endpoint are spread over the cycle
+        assert_eq!(round_robin_cycle(&[3, 1]), vec![0, 0, 1, 0]);
+        assert_eq!(round_robin_cycle(&[2, 1, 1]), vec![0, 1, 2, 0]);
+    }
+}
@@ -23,19 +23,32 @@ pub(crate) fn make_eip4844_tx(nonce: u64, chain_id: u64) -> Transaction {
     })
 }
 
-async fn sign_transaction(signer: &PrivateKeySigner, tx: Transaction) -> Result<TransactionSigned> {
+pub(crate) async fn sign_transaction(
+    signer: &PrivateKeySigner,
+    tx: Transaction,
+) -> Result<TransactionSigned> {
     let tx_sign_hash = tx.signature_hash();
     let signature = signer.sign_hash(&tx_sign_hash).await?;
     Ok(TransactionSigned::new_unhashed(tx, signature))
 }
 
-pub(crate) async fn make_signed_eip4844_tx(
-    signer: &PrivateKeySigner,
-    nonce: u64,
-    chain_id: u64,
-) -> Result<TransactionSigned> {
-    let tx = make_eip4844_tx(nonce, chain_id);
-    sign_transaction(signer, tx).await
+/// Multiplies the fees of a transaction, so that it replaces a pending transaction with the
+/// same nonce. A factor of at least 2 satisfies the price bump required for blob transactions.
+pub(crate) fn bump_fees(tx: Transaction, factor: u128) -> Transaction {
+    match tx {
+        Transaction::Eip1559(tx) => Transaction::Eip1559(TxEip1559 {
+            max_fee_per_gas: tx.max_fee_per_gas.saturating_mul(factor),
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas.saturating_mul(factor),
+            ..tx
+        }),
+        Transaction::Eip4844(tx) => Transaction::Eip4844(TxEip4844 {
+            max_fee_per_gas: tx.max_fee_per_gas.saturating_mul(factor),
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas.saturating_mul(factor),
+            max_fee_per_blob_gas: tx.max_fee_per_blob_gas.saturating_mul(factor),
+            ..tx
+        }),
+        tx => tx,
+    }
 }
 
 pub(crate) fn make_eip1559_tx(nonce: u64, chain_id: u64) -> Transaction {
@@ -133,6 +146,16 @@ mod tests {
         assert_eq!(decoded_signed_tx, signed_tx);
     }
 
+    #[test]
+    fn test_bump_fees() {
+        let Transaction::Eip1559(tx) = bump_fees(make_eip1559_tx(0, 1), 2) else {
+            panic!("expected an EIP-1559 transaction");
+        };
+        assert_eq!(tx.max_fee_per_gas, 4_000_000_000);
+        assert_eq!(tx.max_priority_fee_per_gas, 2_000_000_000);
+        assert_eq!(tx.nonce, 0);
+    }
+
     #[tokio::test]
     async fn test_encode_decode_signed_eip1559_tx() {
         let tx = make_eip1559_tx(0, 1);
@@ -1,5 +1,6 @@
 use alloy_primitives::{address, Address};
 
+/// Address of the ValidatorManager contract, unless the genesis deploys it at another one
 pub const GENESIS_VALIDATOR_MANAGER_ACCOUNT: Address =
     address!("0x0000000000000000000000000000000000002000");
 
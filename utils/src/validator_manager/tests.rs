@@ -1,18 +1,50 @@
 use core::str::FromStr;
+use std::collections::BTreeMap;
 
 use alloy_network::EthereumWallet;
 use alloy_node_bindings::anvil::Anvil;
-use alloy_primitives::{address, Address, U256};
+use alloy_primitives::{address, Address, B256, U256};
 use alloy_provider::{Provider, ProviderBuilder};
 use alloy_signer_local::coins_bip39::English;
 use alloy_signer_local::{MnemonicBuilder, PrivateKeySigner};
 use color_eyre::eyre;
 use reqwest::Url;
+use serde::Deserialize;
 use tracing::debug;
 
 use super::{generate_storage_data, Validator};
 use crate::validator_manager::contract::ValidatorManager;
 
+/// Storage of a ValidatorManager deployed by `owner`, after registering `validators` in order
+const STORAGE_VECTORS: &str = include_str!("../../tests/vectors/validator_manager_storage.json");
+
+#[derive(Deserialize)]
+struct StorageVectors {
+    owner: Address,
+    validators: Vec<VectorValidator>,
+    storage: BTreeMap<B256, B256>,
+}
+
+#[derive(Deserialize)]
+struct VectorValidator {
+    x: U256,
+    y: U256,
+    power: u64,
+}
+
+impl StorageVectors {
+    fn load() -> Self {
+        serde_json::from_str(STORAGE_VECTORS).expect("valid storage vectors")
+    }
+
+    fn validators(&self) -> Vec<Validator> {
+        self.validators
+            .iter()
+            .map(|v| Validator::from_public_key((v.x, v.y), v.power))
+            .collect()
+    }
+}
+
 /// Generate validators from "test test ... junk" mnemonic using sequential derivation paths.
 ///
 /// Each validator is derived from path `m/44'/60'/0'/0/{index}` and includes both
@@ -55,6 +87,44 @@ fn generate_validators_from_mnemonic(count: usize) -> eyre::Result<Vec<Validator
     Ok(derived)
 }
 
+#[test]
+fn test_storage_vectors() -> eyre::Result<()> {
+    let vectors = StorageVectors::load();
+
+    let storage = generate_storage_data(vectors.validators(), vectors.owner)?;
+
+    // Exactly the same slots, as any extra slot would be set in the genesis too
+    assert_eq!(storage, vectors.storage);
+    Ok(())
+}
+
+/// Register the validators of the vectors on Anvil and check the vectors against the
+/// storage of the deployed contract
+#[tokio::test]
+async fn test_anvil_storage_vectors() -> eyre::Result<()> {
+    let anvil = Anvil::new().spawn();
+    let rpc_url: Url = anvil.endpoint().parse()?;
+
+    let vectors = StorageVectors::load();
+    assert_eq!(vectors.owner, TEST_OWNER_ADDRESS);
+
+    let contract_address =
+        deploy_and_register_validators(&vectors.validators(), vectors.owner, &rpc_url).await?;
+
+    let provider = ProviderBuilder::new().connect_http(rpc_url);
+    for (slot, expected_value) in &vectors.storage {
+        let actual_value = provider
+            .get_storage_at(contract_address, (*slot).into())
+            .await?;
+        assert_eq!(
+            B256::from(actual_value),
+            *expected_value,
+            "Storage mismatch at slot {slot}",
+        );
+    }
+    Ok(())
+}
+
 /// Deploy ValidatorManager contract on Anvil and compare storage values
 ///
 /// This test attempts to deploy a ValidatorManager contract on a local Anvil node